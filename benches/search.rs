@@ -0,0 +1,79 @@
+//! Search and usage-recording latency over a large synthetic history.
+//!
+//! Run with `cargo bench --bench search`.
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use mux::HistorySearcher;
+use rusqlite::{Connection, params};
+use std::hint::black_box;
+
+const CORPUS_SIZE: usize = 100_000;
+
+const TOOLS: &[&str] = &["cargo", "git", "kubectl", "docker", "ssh", "rg", "make", "npm"];
+const VERBS: &[&str] = &["build", "test", "run", "status", "logs", "exec", "push", "apply"];
+const FLAGS: &[&str] = &["--release", "-v", "--all", "-n prod", "--target wasm32", "-f"];
+
+/// Build a searcher backed by a temp database holding `CORPUS_SIZE` distinct commands
+fn build_searcher(db: &tempfile::NamedTempFile) -> HistorySearcher {
+    let mut searcher = HistorySearcher::new(db.path().to_path_buf()).unwrap();
+
+    let mut conn = Connection::open(db.path()).unwrap();
+    let tx = conn.transaction().unwrap();
+    for i in 0..CORPUS_SIZE {
+        let command = format!(
+            "{} {} {} host-{:05} shard={}",
+            TOOLS[i % TOOLS.len()],
+            VERBS[(i / TOOLS.len()) % VERBS.len()],
+            FLAGS[(i / 7) % FLAGS.len()],
+            i,
+            i % 64,
+        );
+        tx.execute(
+            "INSERT INTO commands (command, timestamp, shell_source, frequency, last_used)
+             VALUES (?, ?, 'Bench', ?, ?)",
+            params![command, i as i64, (i % 50) as u32 + 1, i as i64],
+        )
+        .unwrap();
+    }
+    tx.commit().unwrap();
+
+    searcher.reload_from_db().unwrap();
+    searcher
+}
+
+fn bench_search(c: &mut Criterion) {
+    let db = tempfile::NamedTempFile::new().unwrap();
+    let mut searcher = build_searcher(&db);
+
+    let mut group = c.benchmark_group("search_100k");
+    for query in ["c", "cargo", "kubectl logs", "gt psh prod", "host-4242", "zzzzzz"] {
+        group.bench_function(query, |b| {
+            b.iter(|| black_box(searcher.search(black_box(query), 8)))
+        });
+    }
+    group.finish();
+}
+
+fn bench_record_usage(c: &mut Criterion) {
+    let db = tempfile::NamedTempFile::new().unwrap();
+    let mut searcher = build_searcher(&db);
+
+    // Rarely used commands sit near the end of the frequency-sorted entries
+    let rare: Vec<String> = searcher
+        .commands()
+        .rev()
+        .take(1_000)
+        .map(|e| e.command.clone())
+        .collect();
+
+    let mut i = 0;
+    c.bench_function("record_usage_100k", |b| {
+        b.iter(|| {
+            searcher.record_usage(&rare[i % rare.len()]).unwrap();
+            i += 1;
+        })
+    });
+}
+
+criterion_group!(benches, bench_search, bench_record_usage);
+criterion_main!(benches);
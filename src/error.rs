@@ -0,0 +1,87 @@
+//! Errors from opening, syncing and persisting the history database, and
+//! from resolving mux's config and state paths.
+
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::history::HistoryError;
+
+/// Why a history database or path operation failed
+#[derive(Debug)]
+pub enum Error {
+    /// No home directory to put config files under
+    NoHomeDir,
+    /// A config or state directory couldn't be created
+    CreateDir { path: PathBuf, source: io::Error },
+    /// The SQLite database couldn't be opened, read or written
+    Database(rusqlite::Error),
+    /// A history source couldn't be read while syncing
+    History(HistoryError),
+}
+
+impl Error {
+    pub(crate) fn create_dir(path: &Path, source: io::Error) -> Self {
+        Self::CreateDir {
+            path: path.to_path_buf(),
+            source,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoHomeDir => write!(f, "could not determine home directory"),
+            Self::CreateDir { path, source } => {
+                write!(f, "could not create {}: {}", path.display(), source)
+            }
+            Self::Database(e) => write!(f, "history database error: {}", e),
+            Self::History(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::NoHomeDir => None,
+            Self::CreateDir { source, .. } => Some(source),
+            Self::Database(e) => Some(e),
+            Self::History(e) => Some(e),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for Error {
+    fn from(e: rusqlite::Error) -> Self {
+        Self::Database(e)
+    }
+}
+
+impl From<HistoryError> for Error {
+    fn from(e: HistoryError) -> Self {
+        Self::History(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error as _;
+
+    #[test]
+    fn test_display_and_source() {
+        let e = Error::create_dir(Path::new("/ro/mux"), io::Error::from(io::ErrorKind::PermissionDenied));
+        assert!(e.to_string().starts_with("could not create /ro/mux: "));
+        assert!(e.source().is_some());
+
+        let e: Error = HistoryError::NoDefaultPath.into();
+        assert_eq!(e.to_string(), "plain history has no default file");
+        assert!(matches!(e, Error::History(HistoryError::NoDefaultPath)));
+
+        let e: Error = rusqlite::Error::InvalidQuery.into();
+        assert!(e.to_string().starts_with("history database error: "));
+        assert!(Error::NoHomeDir.source().is_none());
+    }
+}
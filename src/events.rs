@@ -0,0 +1,274 @@
+//! Structured task events for external tooling.
+//!
+//! With `[runner] event_file` or `[runner] event_socket` set, every task
+//! reports `started`, `line` and `completed` events as JSON lines, e.g.
+//! `{"event":"started","id":3,"label":"[n=1]","command":"make","time_ms":...}`.
+//! Writing happens on a background thread behind a bounded queue; when the
+//! reader falls behind, events are dropped rather than slowing down the TUI.
+
+use serde::Serialize;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::config::RunnerConfig;
+use crate::paths;
+use crate::runner::{TaskId, TaskStats};
+
+/// Events queued for the writer thread before new ones are dropped
+const QUEUE_CAPACITY: usize = 4096;
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum TaskEvent<'a> {
+    Started {
+        id: TaskId,
+        label: &'a str,
+        command: &'a str,
+        time_ms: u128,
+    },
+    Line {
+        id: TaskId,
+        label: &'a str,
+        content: &'a str,
+    },
+    Completed {
+        id: TaskId,
+        label: &'a str,
+        command: &'a str,
+        /// Final status text, e.g. "completed" or "exited with code 1"
+        status: &'a str,
+        exit_code: Option<u32>,
+        lines: usize,
+        bytes: u64,
+        elapsed_ms: u128,
+        /// Runs of the command, more than 1 after retries
+        attempts: u32,
+        time_ms: u128,
+    },
+}
+
+impl<'a> TaskEvent<'a> {
+    pub fn started(id: TaskId, label: &'a str, command: &'a str) -> Self {
+        Self::Started { id, label, command, time_ms: now_ms() }
+    }
+
+    pub fn completed(
+        id: TaskId,
+        label: &'a str,
+        command: &'a str,
+        status: &'a str,
+        stats: &TaskStats,
+    ) -> Self {
+        Self::Completed {
+            id,
+            label,
+            command,
+            status,
+            exit_code: stats.exit_code,
+            lines: stats.lines,
+            bytes: stats.bytes,
+            elapsed_ms: stats.elapsed.as_millis(),
+            attempts: stats.attempts,
+            time_ms: now_ms(),
+        }
+    }
+}
+
+fn now_ms() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or(Duration::ZERO)
+        .as_millis()
+}
+
+/// Connect to the Unix domain socket at `path`
+#[cfg(unix)]
+fn connect_socket(path: &Path) -> std::io::Result<Box<dyn Write + Send>> {
+    let stream = std::os::unix::net::UnixStream::connect(path)
+        .map_err(|e| std::io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+    Ok(Box::new(stream))
+}
+
+#[cfg(not(unix))]
+fn connect_socket(path: &Path) -> std::io::Result<Box<dyn Write + Send>> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        format!("{}: event_socket needs Unix domain sockets, which this platform lacks", path.display()),
+    ))
+}
+
+/// Best-effort sender of task events. Cheap to clone; the writer thread
+/// exits once every clone is dropped.
+#[derive(Clone)]
+pub struct EventSink {
+    tx: SyncSender<String>,
+    dropped: Arc<AtomicUsize>,
+}
+
+impl EventSink {
+    /// Open the sinks configured in `[runner]`, or None if neither is set.
+    /// The event file is appended to; the socket must already be listening.
+    pub fn open(config: &RunnerConfig) -> std::io::Result<Option<Self>> {
+        let mut writers: Vec<Box<dyn Write + Send>> = Vec::new();
+        if let Some(path) = &config.event_file {
+            writers.push(Box::new(open_event_file(&paths::expand_path(path))?));
+        }
+        if let Some(path) = &config.event_socket {
+            writers.push(connect_socket(&paths::expand_path(path))?);
+        }
+        if writers.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(Self::spawn(writers, QUEUE_CAPACITY)))
+    }
+
+    fn spawn(mut writers: Vec<Box<dyn Write + Send>>, capacity: usize) -> Self {
+        let (tx, rx) = mpsc::sync_channel::<String>(capacity);
+        std::thread::spawn(move || {
+            for line in rx {
+                // A writer that fails (e.g. the socket closed) is dropped
+                writers.retain_mut(|w| match writeln!(w, "{}", line).and_then(|_| w.flush()) {
+                    Ok(()) => true,
+                    Err(e) => {
+                        log::warn!("Event sink write failed, closing it: {}", e);
+                        false
+                    }
+                });
+                if writers.is_empty() {
+                    break;
+                }
+            }
+        });
+        Self {
+            tx,
+            dropped: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Queue an event without blocking; it's dropped if the queue is full
+    pub fn emit(&self, event: &TaskEvent) {
+        let line = match serde_json::to_string(event) {
+            Ok(line) => line,
+            Err(e) => {
+                log::warn!("Failed to serialize task event: {}", e);
+                return;
+            }
+        };
+        match self.tx.try_send(line) {
+            Ok(()) | Err(TrySendError::Disconnected(_)) => {}
+            Err(TrySendError::Full(_)) => {
+                if self.dropped.fetch_add(1, Ordering::Relaxed) == 0 {
+                    log::warn!("Event sink is falling behind; dropping events");
+                }
+            }
+        }
+    }
+}
+
+fn open_event_file(path: &Path) -> std::io::Result<std::fs::File> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| std::io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Poll `path` until it holds `lines` lines or a second has passed
+    fn read_lines(path: &Path, lines: usize) -> Vec<serde_json::Value> {
+        let deadline = std::time::Instant::now() + Duration::from_secs(1);
+        loop {
+            let content = std::fs::read_to_string(path).unwrap_or_default();
+            if content.lines().count() >= lines || std::time::Instant::now() > deadline {
+                return content
+                    .lines()
+                    .map(|l| serde_json::from_str(l).unwrap())
+                    .collect();
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn test_disabled_by_default() {
+        assert!(EventSink::open(&RunnerConfig::default()).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_file_sink_writes_json_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("events").join("mux.jsonl");
+        let config = RunnerConfig {
+            event_file: Some(path.clone()),
+            ..RunnerConfig::default()
+        };
+        let sink = EventSink::open(&config).unwrap().unwrap();
+
+        let stats = TaskStats {
+            exit_code: Some(2),
+            lines: 1,
+            bytes: 6,
+            elapsed: Duration::from_millis(1500),
+            attempts: 1,
+        };
+        sink.emit(&TaskEvent::started(1, "[n=1]", "make"));
+        sink.emit(&TaskEvent::Line { id: 1, label: "[n=1]", content: "error" });
+        sink.emit(&TaskEvent::completed(1, "[n=1]", "make", "exited with code 2", &stats));
+
+        let events = read_lines(&path, 3);
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[0]["event"], "started");
+        assert_eq!(events[0]["command"], "make");
+        assert_eq!(events[1]["event"], "line");
+        assert_eq!(events[1]["content"], "error");
+        assert_eq!(events[2]["event"], "completed");
+        assert_eq!(events[2]["exit_code"], 2);
+        assert_eq!(events[2]["elapsed_ms"], 1500);
+    }
+
+    #[test]
+    fn test_missing_socket_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = RunnerConfig {
+            event_socket: Some(dir.path().join("nobody.sock")),
+            ..RunnerConfig::default()
+        };
+        let err = EventSink::open(&config).err().unwrap();
+        assert!(err.to_string().contains("nobody.sock"));
+    }
+
+    /// A writer that blocks until the test finishes
+    struct Stalled(mpsc::Receiver<()>);
+
+    impl Write for Stalled {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            let _ = self.0.recv();
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_full_queue_drops_instead_of_blocking() {
+        let (_release, stalled) = mpsc::channel();
+        let sink = EventSink::spawn(vec![Box::new(Stalled(stalled))], 2);
+
+        for i in 0..50 {
+            sink.emit(&TaskEvent::started(i, "", "true"));
+        }
+        assert!(sink.dropped.load(Ordering::Relaxed) > 0);
+    }
+}
@@ -1,18 +1,156 @@
+use serde::Deserialize;
+use std::ffi::OsString;
+use std::fmt;
 use std::fs;
-use std::io::{BufRead, BufReader};
-use std::path::PathBuf;
+use std::io;
+use std::path::{Path, PathBuf};
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Shell {
     Bash,
     Zsh,
     Fish,
+    Ksh,
+    /// Windows cmd.exe, which keeps no history of its own: commands saved one
+    /// per line, e.g. by `doskey /history > %USERPROFILE%\cmd_history.txt`
+    Cmd,
+    /// Any other shell with newline-separated history, read from a
+    /// configured file (see [`HistoryReader::with_path`])
+    #[serde(skip_deserializing)]
+    Plain,
+}
+
+impl Shell {
+    /// The shell an executable name runs, e.g. `zsh` or `mksh`
+    pub fn from_binary_name(name: &str) -> Option<Self> {
+        match name {
+            "bash" => Some(Self::Bash),
+            "zsh" => Some(Self::Zsh),
+            "fish" => Some(Self::Fish),
+            "ksh" | "ksh93" | "mksh" => Some(Self::Ksh),
+            "cmd" | "cmd.exe" => Some(Self::Cmd),
+            _ => None,
+        }
+    }
+
+    /// The user's login shell according to `$SHELL`, if it's a known one
+    pub fn login_shell() -> Option<Self> {
+        let shell = PathBuf::from(std::env::var_os("SHELL")?);
+        Self::from_binary_name(shell.file_name()?.to_str()?)
+    }
+
+    /// Format of an extra history file, guessed from its name so that copies
+    /// and archives such as `.zsh_history.1.gz` are parsed as the shell wrote
+    /// them. Anything unrecognized is `Plain`.
+    pub fn for_history_file(path: &Path) -> Self {
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        if name.contains("zsh_history") || name.contains("zhistory") {
+            Self::Zsh
+        } else if name.contains("bash_history") {
+            Self::Bash
+        } else if name.contains("fish_history") {
+            Self::Fish
+        } else if name.contains("cmd_history") || name.contains("doskey") {
+            Self::Cmd
+        } else {
+            Self::Plain
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct HistoryEntry {
     pub command: String,
     pub timestamp: Option<i64>,
+    /// How long the command ran, if the shell recorded it
+    pub duration_ms: Option<u64>,
+}
+
+impl HistoryEntry {
+    /// An entry without a recorded runtime, as most history formats have
+    pub fn new(command: String, timestamp: Option<i64>) -> Self {
+        Self { command, timestamp, duration_ms: None }
+    }
+}
+
+/// Why a history source couldn't be read. A missing history file is not an
+/// error: the shell may simply not be in use.
+#[derive(Debug)]
+pub enum HistoryError {
+    /// No home directory to look for default history files in
+    NoHomeDir,
+    /// The shell has no default history file (`Plain`)
+    NoDefaultPath,
+    /// The file exists but this user can't read it
+    PermissionDenied(PathBuf),
+    /// The file doesn't match the shell's history format
+    Parse { path: PathBuf, line: usize, message: String },
+    /// Any other I/O failure
+    Io { path: PathBuf, source: io::Error },
+    /// A gzip-compressed file is corrupt or truncated
+    Decompress { path: PathBuf, message: String },
+    /// A history command couldn't be started or exited non-zero
+    Command { command: String, message: String },
+}
+
+impl HistoryError {
+    fn io(path: &Path, source: io::Error) -> Self {
+        match source.kind() {
+            io::ErrorKind::PermissionDenied => Self::PermissionDenied(path.to_path_buf()),
+            _ => Self::Io {
+                path: path.to_path_buf(),
+                source,
+            },
+        }
+    }
+
+    /// Invalid UTF-8 in `bytes`, reported at the line where it starts
+    fn invalid_utf8(path: &Path, bytes: &[u8], error: std::str::Utf8Error) -> Self {
+        let valid = &bytes[..error.valid_up_to()];
+        Self::Parse {
+            path: path.to_path_buf(),
+            line: valid.iter().filter(|&&b| b == b'\n').count() + 1,
+            message: "invalid UTF-8".to_string(),
+        }
+    }
+}
+
+impl fmt::Display for HistoryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoHomeDir if cfg!(windows) => {
+                write!(f, "could not determine home directory (is %USERPROFILE% set?)")
+            }
+            Self::NoHomeDir => write!(f, "could not determine home directory (is $HOME set?)"),
+            Self::NoDefaultPath => write!(f, "plain history has no default file"),
+            Self::PermissionDenied(path) => write!(
+                f,
+                "permission denied reading {} (check its owner and mode)",
+                path.display()
+            ),
+            Self::Parse { path, line, message } => {
+                write!(f, "parse error in {} at line {}: {}", path.display(), line, message)
+            }
+            Self::Io { path, source } => write!(f, "could not read {}: {}", path.display(), source),
+            Self::Decompress { path, message } => {
+                write!(f, "could not decompress {}: {}", path.display(), message)
+            }
+            Self::Command { command, message } => write!(f, "`{}` {}", command, message),
+        }
+    }
+}
+
+impl std::error::Error for HistoryError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -23,7 +161,7 @@ pub struct HistoryReader {
 
 impl HistoryReader {
     /// Create a new HistoryReader for a specific shell
-    pub fn new(shell: Shell) -> Result<Self, Box<dyn std::error::Error>> {
+    pub fn new(shell: Shell) -> Result<Self, HistoryError> {
         let history_path = Self::get_default_history_path(&shell)?;
         Ok(Self {
             shell,
@@ -31,66 +169,142 @@ impl HistoryReader {
         })
     }
 
+    /// Create a HistoryReader for a specific history file
+    pub fn with_path(shell: Shell, path: PathBuf) -> Self {
+        Self {
+            shell,
+            history_path: path,
+        }
+    }
+
     /// Get the default history file path for a shell
-    fn get_default_history_path(shell: &Shell) -> Result<PathBuf, Box<dyn std::error::Error>> {
-        let home = std::env::var("HOME").map_err(|_| "HOME environment variable not set")?;
+    fn get_default_history_path(shell: &Shell) -> Result<PathBuf, HistoryError> {
+        let home = crate::paths::home_dir().ok_or(HistoryError::NoHomeDir)?;
+        Self::history_path_from_env(shell, &home, |name| std::env::var_os(name))
+            .ok_or(HistoryError::NoDefaultPath)
+    }
 
-        let path = match shell {
-            Shell::Bash => PathBuf::from(home).join(".bash_history"),
-            Shell::Zsh => PathBuf::from(home).join(".zsh_history"),
-            Shell::Fish => PathBuf::from(home).join(".local/share/fish/fish_history"),
-        };
+    /// History file path for a shell, honoring the variables the shell itself
+    /// uses to relocate it:
+    /// - Bash, Zsh, Ksh: `$HISTFILE`, but only for the login shell named by
+    ///   `$SHELL`, since each shell exports it for its own format
+    /// - Fish: `$XDG_DATA_HOME/fish/<$fish_history>_history`
+    /// - Cmd: `%USERPROFILE%\cmd_history.txt`, since `$HOME` is often unset on
+    ///   Windows or points at an MSYS home
+    ///
+    /// Falls back to [`Self::history_path_in`]. Empty variables count as unset.
+    fn history_path_from_env(
+        shell: &Shell,
+        home: &Path,
+        env: impl Fn(&str) -> Option<OsString>,
+    ) -> Option<PathBuf> {
+        let var = |name: &str| env(name).filter(|value| !value.is_empty());
+
+        match shell {
+            Shell::Bash | Shell::Zsh | Shell::Ksh => {
+                let login_shell = var("SHELL").map(PathBuf::from);
+                let is_login_shell = login_shell
+                    .as_deref()
+                    .and_then(Path::file_name)
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| Shell::from_binary_name(name) == Some(*shell));
+                match var("HISTFILE") {
+                    Some(histfile) if is_login_shell => Some(PathBuf::from(histfile)),
+                    _ => Self::history_path_in(shell, home),
+                }
+            }
+            Shell::Fish => {
+                let data_dir = var("XDG_DATA_HOME")
+                    .map(PathBuf::from)
+                    .unwrap_or_else(|| home.join(".local/share"));
+                let session = var("fish_history").unwrap_or_else(|| "fish".into());
+                let mut file_name = session;
+                file_name.push("_history");
+                Some(data_dir.join("fish").join(file_name))
+            }
+            Shell::Cmd => match var("USERPROFILE") {
+                Some(profile) => Self::history_path_in(shell, Path::new(&profile)),
+                None => Self::history_path_in(shell, home),
+            },
+            Shell::Plain => None,
+        }
+    }
 
-        Ok(path)
+    /// History file path for a shell relative to the given home directory.
+    /// None for `Plain`, whose files are configured explicitly.
+    fn history_path_in(shell: &Shell, home: &Path) -> Option<PathBuf> {
+        match shell {
+            Shell::Bash => Some(home.join(".bash_history")),
+            Shell::Zsh => Some(home.join(".zsh_history")),
+            Shell::Fish => Some(home.join(".local/share/fish/fish_history")),
+            Shell::Ksh => Some(home.join(".sh_history")),
+            Shell::Cmd => Some(home.join("cmd_history.txt")),
+            Shell::Plain => None,
+        }
     }
 
     /// Read all history entries from the history file.
     /// Returns an empty vec if the history file doesn't exist (the shell may not be in use).
-    pub fn read_history(&self) -> Result<Vec<HistoryEntry>, Box<dyn std::error::Error>> {
-        if !self.history_path.exists() {
-            return Ok(Vec::new());
-        }
-
-        match self.shell {
+    pub fn read_history(&self) -> Result<Vec<HistoryEntry>, HistoryError> {
+        let result = match self.shell {
             Shell::Bash => self.read_bash_history(),
             Shell::Zsh => self.read_zsh_history(),
             Shell::Fish => self.read_fish_history(),
+            Shell::Ksh | Shell::Plain => self.read_plain_history(),
+            Shell::Cmd => self.read_cmd_history(),
+        };
+        match result {
+            Err(HistoryError::Io { source, .. }) if source.kind() == io::ErrorKind::NotFound => {
+                Ok(Vec::new())
+            }
+            result => result,
         }
     }
 
+    /// Contents of the history file, decompressed if it's gzip (a `.gz` name
+    /// or the gzip magic bytes), e.g. a rotated `~/.zsh_history.1.gz`
+    fn read_file(&self) -> Result<Vec<u8>, HistoryError> {
+        let bytes = fs::read(&self.history_path).map_err(|e| HistoryError::io(&self.history_path, e))?;
+        let gzip_name = self.history_path.extension().is_some_and(|ext| ext == "gz");
+        if !gzip_name && !bytes.starts_with(&GZIP_MAGIC) {
+            return Ok(bytes);
+        }
+        gunzip(&bytes).map_err(|message| HistoryError::Decompress {
+            path: self.history_path.clone(),
+            message,
+        })
+    }
+
     /// Read bash history file
     /// Format: Simple newline-separated commands, optionally with timestamps if HISTTIMEFORMAT is set
-    fn read_bash_history(&self) -> Result<Vec<HistoryEntry>, Box<dyn std::error::Error>> {
-        let file = fs::File::open(&self.history_path)?;
-        let reader = BufReader::new(file);
+    /// Invalid UTF-8 is dropped from the line it's on, so one bad line
+    /// doesn't keep the rest of the file from syncing.
+    fn read_bash_history(&self) -> Result<Vec<HistoryEntry>, HistoryError> {
+        let bytes = self.read_file()?;
+        let content = String::from_utf8_lossy(&bytes);
         let mut entries = Vec::new();
-        let mut lines = reader.lines();
+        // `\n` is never part of a multi-byte sequence, so decoding the whole
+        // file lossily is the same as decoding each line
+        let mut lines = content
+            .lines()
+            .map(|line| line.chars().filter(|&c| c != char::REPLACEMENT_CHARACTER).collect::<String>());
 
-        while let Some(Ok(line)) = lines.next() {
+        while let Some(line) = lines.next() {
             // Check if line starts with # (timestamp marker)
-            if line.starts_with('#') {
+            if let Some(marker) = line.strip_prefix('#') {
                 // Try to parse timestamp
-                if let Ok(timestamp) = line[1..].trim().parse::<i64>() {
+                if let Ok(timestamp) = marker.trim().parse::<i64>() {
                     // Next line should be the command
-                    if let Some(Ok(command)) = lines.next() {
-                        entries.push(HistoryEntry {
-                            command,
-                            timestamp: Some(timestamp),
-                        });
+                    if let Some(command) = lines.next() {
+                        entries.push(HistoryEntry::new(command, Some(timestamp)));
                     }
                 } else {
                     // It's a comment, treat as command
-                    entries.push(HistoryEntry {
-                        command: line,
-                        timestamp: None,
-                    });
+                    entries.push(HistoryEntry::new(line, None));
                 }
             } else {
                 // Regular command without timestamp
-                entries.push(HistoryEntry {
-                    command: line,
-                    timestamp: None,
-                });
+                entries.push(HistoryEntry::new(line, None));
             }
         }
 
@@ -107,22 +321,22 @@ impl HistoryReader {
     /// joined with the next line (the backslash is replaced with a newline).
     ///
     /// Uses lossy UTF-8 conversion since zsh can write metafied (non-UTF-8) bytes.
-    fn read_zsh_history(&self) -> Result<Vec<HistoryEntry>, Box<dyn std::error::Error>> {
-        let bytes = fs::read(&self.history_path)?;
+    fn read_zsh_history(&self) -> Result<Vec<HistoryEntry>, HistoryError> {
+        let bytes = self.read_file()?;
         let content = String::from_utf8_lossy(&bytes);
         let mut entries = Vec::new();
 
         // First pass: join continuation lines (lines ending with '\')
         let mut joined_lines: Vec<String> = Vec::new();
         for line in content.lines() {
-            if let Some(current) = joined_lines.last_mut() {
-                if current.ends_with('\\') {
-                    // Previous line had a continuation — append this line
-                    current.pop(); // remove trailing '\'
-                    current.push('\n');
-                    current.push_str(line);
-                    continue;
-                }
+            if let Some(current) = joined_lines.last_mut()
+                && current.ends_with('\\')
+            {
+                // Previous line had a continuation — append this line
+                current.pop(); // remove trailing '\'
+                current.push('\n');
+                current.push_str(line);
+                continue;
             }
             joined_lines.push(line.to_string());
         }
@@ -137,10 +351,7 @@ impl HistoryReader {
                 entries.push(entry);
             } else {
                 // Non-extended format: plain command
-                entries.push(HistoryEntry {
-                    command: line.to_string(),
-                    timestamp: None,
-                });
+                entries.push(HistoryEntry::new(line.to_string(), None));
             }
         }
 
@@ -160,24 +371,34 @@ impl HistoryReader {
         // Validate metadata looks like "timestamp:duration" (both numeric)
         let mut parts = metadata.split(':');
         let timestamp_str = parts.next()?;
-        let _duration_str = parts.next()?;
+        let duration_str = parts.next()?;
 
         // If there are extra colons or the timestamp isn't numeric, this isn't extended format
         if parts.next().is_some() {
             return None;
         }
         let timestamp = timestamp_str.parse::<i64>().ok()?;
+        // Whole seconds. zsh writes 0 when the line is saved before the
+        // command finishes (INC_APPEND_HISTORY), so 0 means unknown.
+        let duration_ms = duration_str
+            .parse::<u64>()
+            .ok()
+            .filter(|&secs| secs > 0)
+            .map(|secs| secs.saturating_mul(1000));
 
         Some(HistoryEntry {
             command: command.to_string(),
             timestamp: Some(timestamp),
+            duration_ms,
         })
     }
 
     /// Read fish history file
     /// Format: YAML-like with `- cmd:` and `  when:` fields
-    fn read_fish_history(&self) -> Result<Vec<HistoryEntry>, Box<dyn std::error::Error>> {
-        let content = fs::read_to_string(&self.history_path)?;
+    fn read_fish_history(&self) -> Result<Vec<HistoryEntry>, HistoryError> {
+        let bytes = self.read_file()?;
+        let content = std::str::from_utf8(&bytes)
+            .map_err(|e| HistoryError::invalid_utf8(&self.history_path, &bytes, e))?;
         let mut entries = Vec::new();
         let mut current_command: Option<String> = None;
         let mut current_timestamp: Option<i64> = None;
@@ -185,45 +406,122 @@ impl HistoryReader {
         for line in content.lines() {
             let trimmed = line.trim();
 
-            if trimmed.starts_with("- cmd:") {
+            if let Some(cmd) = trimmed.strip_prefix("- cmd:") {
                 // Save previous entry if exists
                 if let Some(cmd) = current_command.take() {
-                    entries.push(HistoryEntry {
-                        command: cmd,
-                        timestamp: current_timestamp.take(),
-                    });
+                    entries.push(HistoryEntry::new(cmd, current_timestamp.take()));
                 }
 
                 // Extract command
-                current_command = Some(trimmed[6..].trim().to_string());
-            } else if trimmed.starts_with("when:") {
+                current_command = Some(cmd.trim().to_string());
+            } else if let Some(when) = trimmed.strip_prefix("when:") {
                 // Extract timestamp
-                current_timestamp = trimmed[5..].trim().parse::<i64>().ok();
+                current_timestamp = when.trim().parse::<i64>().ok();
             }
         }
 
         // Don't forget the last entry
         if let Some(cmd) = current_command {
-            entries.push(HistoryEntry {
-                command: cmd,
-                timestamp: current_timestamp,
-            });
+            entries.push(HistoryEntry::new(cmd, current_timestamp));
         }
 
         Ok(entries)
     }
 
+    /// Read a plain history file: one command per line, no timestamps.
+    /// Control bytes (ksh writes a binary header and may leave NULs) and
+    /// invalid UTF-8 are dropped; empty lines are skipped.
+    fn read_plain_history(&self) -> Result<Vec<HistoryEntry>, HistoryError> {
+        let bytes = self.read_file()?;
+        Ok(parse_plain_history(&bytes))
+    }
+
+    /// Read a saved cmd.exe history: one command per line, with CRLF line
+    /// endings and in UTF-16 when written by `cmd /u`. Text without a byte
+    /// order mark is read as UTF-8, as for plain history.
+    fn read_cmd_history(&self) -> Result<Vec<HistoryEntry>, HistoryError> {
+        let bytes = self.read_file()?;
+        let text = match bytes.as_slice() {
+            [0xff, 0xfe, rest @ ..] => decode_utf16(rest, u16::from_le_bytes),
+            [0xfe, 0xff, rest @ ..] => decode_utf16(rest, u16::from_be_bytes),
+            [0xef, 0xbb, 0xbf, rest @ ..] => return Ok(parse_plain_history(rest)),
+            _ => return Ok(parse_plain_history(&bytes)),
+        };
+        Ok(parse_plain_history(text.as_bytes()))
+    }
 }
 
-#[cfg(test)]
-impl HistoryReader {
-    /// Create a HistoryReader with a custom history file path (test only)
-    pub fn with_path(shell: Shell, path: PathBuf) -> Self {
-        Self {
-            shell,
-            history_path: path,
-        }
+/// UTF-16 text in the given byte order; an odd trailing byte and unpaired
+/// surrogates become replacement characters, which the parser drops
+fn decode_utf16(bytes: &[u8], unit: fn([u8; 2]) -> u16) -> String {
+    let units = bytes.chunks_exact(2).map(|pair| unit([pair[0], pair[1]]));
+    char::decode_utf16(units)
+        .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+        .collect()
+}
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Refuse to inflate a history archive beyond this size (a corrupt or
+/// malicious file could otherwise exhaust memory)
+const MAX_DECOMPRESSED_SIZE: usize = 1 << 30;
+
+/// Decompress a gzip file (RFC 1952), including files made of several
+/// concatenated members such as `cat a.gz b.gz`. Each member's CRC and
+/// length are checked.
+fn gunzip(bytes: &[u8]) -> Result<Vec<u8>, String> {
+    use std::io::Read;
+
+    if !bytes.starts_with(&GZIP_MAGIC) {
+        return Err("not a gzip file".to_string());
+    }
+    let mut data = Vec::new();
+    flate2::read::MultiGzDecoder::new(bytes)
+        .take(MAX_DECOMPRESSED_SIZE as u64 + 1)
+        .read_to_end(&mut data)
+        .map_err(|e| format!("corrupt data ({})", e))?;
+    if data.len() > MAX_DECOMPRESSED_SIZE {
+        return Err(format!("more than {} bytes uncompressed", MAX_DECOMPRESSED_SIZE));
+    }
+    Ok(data)
+}
+
+/// Run `command` with `sh -c` and read its stdout as plain history (one
+/// command per line). Fails if the command can't be started or exits non-zero.
+pub fn read_command_output(command: &str) -> Result<Vec<HistoryEntry>, HistoryError> {
+    let output = std::process::Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(std::process::Stdio::null())
+        .output()
+        .map_err(|e| HistoryError::Command {
+            command: command.to_string(),
+            message: format!("could not be started: {}", e),
+        })?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(HistoryError::Command {
+            command: command.to_string(),
+            message: format!("failed ({}): {}", output.status, stderr.trim()),
+        });
     }
+    Ok(parse_plain_history(&output.stdout))
+}
+
+/// Parse newline-separated commands, dropping control bytes, invalid UTF-8
+/// and empty lines
+fn parse_plain_history(bytes: &[u8]) -> Vec<HistoryEntry> {
+    String::from_utf8_lossy(bytes)
+        .lines()
+        .map(|line| {
+            line.chars()
+                .filter(|&c| (!c.is_control() || c == '\t') && c != char::REPLACEMENT_CHARACTER)
+                .collect::<String>()
+        })
+        .filter(|command| !command.trim().is_empty())
+        .map(|command| HistoryEntry::new(command, None))
+        .collect()
 }
 
 #[cfg(test)]
@@ -232,6 +530,106 @@ mod tests {
     use std::io::Write;
     use tempfile::NamedTempFile;
 
+    #[test]
+    fn test_history_path_in_home() {
+        let home = Path::new("/home/u");
+        assert_eq!(
+            HistoryReader::history_path_in(&Shell::Bash, home),
+            Some(PathBuf::from("/home/u/.bash_history"))
+        );
+        assert_eq!(
+            HistoryReader::history_path_in(&Shell::Fish, home),
+            Some(PathBuf::from("/home/u/.local/share/fish/fish_history"))
+        );
+        assert_eq!(
+            HistoryReader::history_path_in(&Shell::Ksh, home),
+            Some(PathBuf::from("/home/u/.sh_history"))
+        );
+        assert_eq!(HistoryReader::history_path_in(&Shell::Plain, home), None);
+    }
+
+    #[test]
+    fn test_cmd_history_location_from_env() {
+        let home = Path::new("/home/u");
+        assert_eq!(
+            HistoryReader::history_path_from_env(&Shell::Cmd, home, env_with(&[])),
+            Some(PathBuf::from("/home/u/cmd_history.txt"))
+        );
+        let env = env_with(&[("USERPROFILE", "/users/u"), ("HOME", "/home/u")]);
+        assert_eq!(
+            HistoryReader::history_path_from_env(&Shell::Cmd, home, env),
+            Some(PathBuf::from("/users/u/cmd_history.txt"))
+        );
+    }
+
+    fn env_with(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<OsString> {
+        let vars: Vec<(String, String)> = vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        move |name| {
+            vars.iter()
+                .find(|(k, _)| k == name)
+                .map(|(_, v)| OsString::from(v))
+        }
+    }
+
+    #[test]
+    fn test_histfile_for_login_shell() {
+        let home = Path::new("/home/u");
+        let env = env_with(&[("SHELL", "/usr/bin/zsh"), ("HISTFILE", "/data/zhist")]);
+        assert_eq!(
+            HistoryReader::history_path_from_env(&Shell::Zsh, home, &env),
+            Some(PathBuf::from("/data/zhist"))
+        );
+        // Another shell's HISTFILE is never applied to bash
+        assert_eq!(
+            HistoryReader::history_path_from_env(&Shell::Bash, home, &env),
+            Some(PathBuf::from("/home/u/.bash_history"))
+        );
+
+        let env = env_with(&[("SHELL", "/bin/bash"), ("HISTFILE", "/data/bhist")]);
+        assert_eq!(
+            HistoryReader::history_path_from_env(&Shell::Bash, home, &env),
+            Some(PathBuf::from("/data/bhist"))
+        );
+    }
+
+    #[test]
+    fn test_shell_from_binary_name() {
+        assert_eq!(Shell::from_binary_name("fish"), Some(Shell::Fish));
+        assert_eq!(Shell::from_binary_name("mksh"), Some(Shell::Ksh));
+        assert_eq!(Shell::from_binary_name("nu"), None);
+    }
+
+    #[test]
+    fn test_histfile_unset_or_empty_uses_default() {
+        let home = Path::new("/home/u");
+        for env in [
+            env_with(&[("SHELL", "/bin/bash")]),
+            env_with(&[("SHELL", "/bin/bash"), ("HISTFILE", "")]),
+        ] {
+            assert_eq!(
+                HistoryReader::history_path_from_env(&Shell::Bash, home, env),
+                Some(PathBuf::from("/home/u/.bash_history"))
+            );
+        }
+    }
+
+    #[test]
+    fn test_fish_history_location_from_env() {
+        let home = Path::new("/home/u");
+        assert_eq!(
+            HistoryReader::history_path_from_env(&Shell::Fish, home, env_with(&[])),
+            Some(PathBuf::from("/home/u/.local/share/fish/fish_history"))
+        );
+        let env = env_with(&[("XDG_DATA_HOME", "/data"), ("fish_history", "work")]);
+        assert_eq!(
+            HistoryReader::history_path_from_env(&Shell::Fish, home, env),
+            Some(PathBuf::from("/data/fish/work_history"))
+        );
+    }
+
     #[test]
     fn test_bash_history_simple() {
         let mut temp_file = NamedTempFile::new().unwrap();
@@ -282,6 +680,25 @@ mod tests {
         assert_eq!(entries[1].timestamp, Some(1234567900));
     }
 
+    #[test]
+    fn test_zsh_history_duration() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        writeln!(temp_file, ": 1234567890:0;ls -la").unwrap();
+        writeln!(temp_file, ": 1234567900:75;cargo build --release").unwrap();
+        writeln!(temp_file, ": 1234567990:x;make").unwrap();
+        writeln!(temp_file, "echo plain").unwrap();
+
+        let reader = HistoryReader::with_path(Shell::Zsh, temp_file.path().to_path_buf());
+        let entries = reader.read_history().unwrap();
+
+        let durations: Vec<_> = entries.iter().map(|e| (e.command.as_str(), e.duration_ms)).collect();
+        assert_eq!(
+            durations,
+            [("ls -la", None), ("cargo build --release", Some(75_000)), ("make", None), ("echo plain", None)]
+        );
+        assert_eq!(entries[2].timestamp, Some(1234567990));
+    }
+
     #[test]
     fn test_zsh_history_non_extended() {
         let mut temp_file = NamedTempFile::new().unwrap();
@@ -391,4 +808,157 @@ mod tests {
         assert_eq!(entries[1].command, "cd /tmp");
         assert_eq!(entries[1].timestamp, Some(1234567900));
     }
+
+    #[test]
+    fn test_plain_history_strips_control_bytes() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        // ksh-style magic header, then commands with stray NULs and blank lines
+        temp_file.write_all(b"\x81\x01ls -la\n\0\ncd /tmp\0\n\n  \ngit status\n").unwrap();
+
+        let reader = HistoryReader::with_path(Shell::Ksh, temp_file.path().to_path_buf());
+        let entries = reader.read_history().unwrap();
+
+        let commands: Vec<_> = entries.iter().map(|e| e.command.as_str()).collect();
+        assert_eq!(commands, vec!["ls -la", "cd /tmp", "git status"]);
+        assert!(entries.iter().all(|e| e.timestamp.is_none()));
+    }
+
+    #[test]
+    fn test_cmd_history() {
+        let read = |bytes: &[u8]| {
+            let mut temp_file = NamedTempFile::new().unwrap();
+            temp_file.write_all(bytes).unwrap();
+            let entries = HistoryReader::with_path(Shell::Cmd, temp_file.path().to_path_buf())
+                .read_history()
+                .unwrap();
+            entries.into_iter().map(|e| e.command).collect::<Vec<_>>()
+        };
+        let expected = ["dir /b", "cd \\projects\\é", "git status"];
+
+        assert_eq!(read("dir /b\r\ncd \\projects\\é\r\n\r\ngit status\r\n".as_bytes()), expected);
+        assert_eq!(read("\u{feff}dir /b\r\ncd \\projects\\é\r\ngit status".as_bytes()), expected);
+
+        let utf16 = |bom: [u8; 2], unit: fn(u16) -> [u8; 2]| {
+            let text = "dir /b\r\ncd \\projects\\é\r\ngit status\r\n";
+            let mut bytes = bom.to_vec();
+            text.encode_utf16().for_each(|u| bytes.extend(unit(u)));
+            bytes
+        };
+        assert_eq!(read(&utf16([0xff, 0xfe], u16::to_le_bytes)), expected);
+        assert_eq!(read(&utf16([0xfe, 0xff], u16::to_be_bytes)), expected);
+    }
+
+    #[test]
+    fn test_read_command_output() {
+        let entries = read_command_output("printf 'make deploy\\n\\nkubectl get pods\\n'").unwrap();
+        let commands: Vec<_> = entries.iter().map(|e| e.command.as_str()).collect();
+        assert_eq!(commands, vec!["make deploy", "kubectl get pods"]);
+    }
+
+    #[test]
+    fn test_read_command_output_failure() {
+        let err = read_command_output("echo oops >&2; exit 3").unwrap_err();
+        assert!(err.to_string().contains("oops"));
+    }
+
+    #[test]
+    fn test_missing_file_is_empty() {
+        for shell in [Shell::Bash, Shell::Zsh, Shell::Fish, Shell::Plain] {
+            let reader = HistoryReader::with_path(shell, PathBuf::from("/nonexistent/history"));
+            assert!(reader.read_history().unwrap().is_empty());
+        }
+    }
+
+    #[test]
+    fn test_invalid_utf8_reports_line() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        temp_file.write_all(b"- cmd: ls\n  when: 1\n- cmd: caf\xe9\n").unwrap();
+        let reader = HistoryReader::with_path(Shell::Fish, temp_file.path().to_path_buf());
+        let err = reader.read_history().unwrap_err();
+        assert!(matches!(err, HistoryError::Parse { line: 3, .. }), "{:?}", err);
+        assert!(err.to_string().contains("at line 3: invalid UTF-8"));
+
+        // Bash history drops the bad bytes and keeps going
+        let reader = HistoryReader::with_path(Shell::Bash, temp_file.path().to_path_buf());
+        let commands: Vec<String> = reader.read_history().unwrap().into_iter().map(|e| e.command).collect();
+        assert_eq!(commands, ["- cmd: ls", "  when: 1", "- cmd: caf"]);
+    }
+
+    /// `data` as a gzip file with the FNAME header field set
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = flate2::GzBuilder::new()
+            .filename("history")
+            .write(Vec::new(), flate2::Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn test_gzip_history() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(".zsh_history.1.gz");
+        fs::write(&path, gzip(b": 1700000000:0;git status\nls -la\n")).unwrap();
+
+        let entries = HistoryReader::with_path(Shell::Zsh, path).read_history().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].command, "git status");
+        assert_eq!(entries[0].timestamp, Some(1700000000));
+
+        // Detected by the magic bytes too, whatever the name
+        let path = dir.path().join("bash_history.old");
+        fs::write(&path, gzip(b"#1700000000\nmake\n")).unwrap();
+        let entries = HistoryReader::with_path(Shell::Bash, path).read_history().unwrap();
+        assert_eq!(entries[0].command, "make");
+
+        // Concatenated archives are read to the end
+        let path = dir.path().join("history.gz");
+        fs::write(&path, [gzip(b"ls\n"), gzip(b"make\n")].concat()).unwrap();
+        let entries = HistoryReader::with_path(Shell::Plain, path).read_history().unwrap();
+        let commands: Vec<&str> = entries.iter().map(|e| e.command.as_str()).collect();
+        assert_eq!(commands, ["ls", "make"]);
+    }
+
+    #[test]
+    fn test_shell_for_history_file() {
+        assert_eq!(Shell::for_history_file(Path::new("/h/.zsh_history.1.gz")), Shell::Zsh);
+        assert_eq!(Shell::for_history_file(Path::new("/h/.zhistory")), Shell::Zsh);
+        assert_eq!(Shell::for_history_file(Path::new("old.bash_history")), Shell::Bash);
+        assert_eq!(Shell::for_history_file(Path::new("fish_history.gz")), Shell::Fish);
+        assert_eq!(Shell::for_history_file(Path::new("C:/Users/u/cmd_history.txt")), Shell::Cmd);
+        assert_eq!(Shell::for_history_file(Path::new("doskey.log")), Shell::Cmd);
+        assert_eq!(Shell::for_history_file(Path::new("~/.mksh_history")), Shell::Plain);
+    }
+
+    #[test]
+    fn test_corrupt_gzip_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.gz");
+        let mut file = gzip(b"ls\n");
+        file.truncate(file.len() - 6);
+        fs::write(&path, file).unwrap();
+
+        let err = HistoryReader::with_path(Shell::Plain, path).read_history().unwrap_err();
+        assert!(matches!(err, HistoryError::Decompress { .. }), "{:?}", err);
+        assert!(err.to_string().starts_with("could not decompress"));
+
+        assert_eq!(gunzip(b"plain text, not gzip at all"), Err("not a gzip file".to_string()));
+
+        // A damaged CRC is caught
+        let mut file = gzip(b"ls\n");
+        let crc = file.len() - 8;
+        file[crc] ^= 0xff;
+        assert!(gunzip(&file).is_err());
+    }
+
+    #[test]
+    fn test_io_error_kinds() {
+        let path = Path::new("/root/.zsh_history");
+        let err = HistoryError::io(path, io::Error::from(io::ErrorKind::PermissionDenied));
+        assert_eq!(
+            err.to_string(),
+            "permission denied reading /root/.zsh_history (check its owner and mode)"
+        );
+        let err = HistoryError::io(path, io::Error::from(io::ErrorKind::IsADirectory));
+        assert!(matches!(err, HistoryError::Io { .. }));
+    }
 }
@@ -0,0 +1,245 @@
+//! OSC 8 hyperlinks in task output (`gh`, `cargo`, `ls --hyperlink`).
+//!
+//! A link is `ESC ] 8 ; params ; URI ST text ESC ] 8 ; ; ST`, with ST either
+//! `ESC \` or BEL. The ANSI parser only knows BEL-terminated OSC sequences
+//! and swallows the rest of the line after an `ESC \` one, so OSC sequences
+//! are rewritten before parsing: links become zero-width markers that ride
+//! along in the styled spans through truncation, wrapping and box drawing,
+//! and every other OSC sequence (window titles and such) is dropped.
+//!
+//! `take_links` then removes the markers from the rows about to be drawn and
+//! says where each link ended up, and `apply` wraps those cells of the drawn
+//! buffer in OSC 8 again. Terminals without OSC 8 support ignore the
+//! sequence and show the link text.
+
+use std::borrow::Cow;
+use std::ops::Range;
+
+use ratatui::buffer::Buffer;
+use ratatui::layout::Rect;
+use ratatui::text::{Line, Span};
+use unicode_width::UnicodeWidthStr;
+
+/// Starts a link marker; the URL follows as tag characters, and a marker
+/// without one ends the link. Tag characters are zero-width.
+const MARKER: char = '\u{E0001}';
+/// Tag characters mirror ASCII from U+E0020 to U+E007E
+const TAG_BASE: u32 = 0xE0000;
+
+/// A link's place in the rows passed to `take_links`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Link {
+    pub row: usize,
+    pub columns: Range<usize>,
+    pub url: String,
+}
+
+/// `content` with OSC 8 links turned into markers and other OSC sequences
+/// removed; borrowed when there are none
+pub fn mark_links(content: &str) -> Cow<'_, str> {
+    rewrite_osc(content, true)
+}
+
+/// `content` with OSC sequences removed, keeping the text of links
+pub fn strip_links(content: &str) -> Cow<'_, str> {
+    rewrite_osc(content, false)
+}
+
+fn rewrite_osc(content: &str, markers: bool) -> Cow<'_, str> {
+    if !content.contains("\x1b]") {
+        return Cow::Borrowed(content);
+    }
+    let mut out = String::with_capacity(content.len());
+    let mut rest = content;
+    while let Some(start) = rest.find("\x1b]") {
+        out.push_str(&rest[..start]);
+        let body_start = start + 2;
+        // Unterminated, e.g. cut by `max_line_length`: drop the remainder
+        let (body, next) = match rest[body_start..].find(['\x07', '\x1b']) {
+            Some(end) => {
+                let end = body_start + end;
+                let terminator = if rest[end..].starts_with("\x1b\\") { 2 } else { 1 };
+                (&rest[body_start..end], end + terminator)
+            }
+            None => (&rest[body_start..], rest.len()),
+        };
+        if markers && let Some((_params, url)) = body.strip_prefix("8;").and_then(|link| link.split_once(';')) {
+            out.push(MARKER);
+            for byte in url.bytes() {
+                if byte.is_ascii_graphic() && byte != b'%' {
+                    out.push(tag(byte));
+                } else {
+                    format!("%{:02X}", byte).bytes().for_each(|b| out.push(tag(b)));
+                }
+            }
+        }
+        rest = &rest[next..];
+    }
+    out.push_str(rest);
+    Cow::Owned(out)
+}
+
+fn tag(byte: u8) -> char {
+    char::from_u32(TAG_BASE + byte as u32).expect("tag characters are valid")
+}
+
+fn untag(c: char) -> Option<char> {
+    let byte = (c as u32).checked_sub(TAG_BASE).filter(|b| (0x21..0x7f).contains(b))?;
+    char::from_u32(byte)
+}
+
+/// Remove the link markers from `rows` and return where the links are, by
+/// row and column. A link still open at the end of a row ends there.
+pub fn take_links(rows: &mut [Line<'static>]) -> Vec<Link> {
+    let mut links = Vec::new();
+    for (row, line) in rows.iter_mut().enumerate() {
+        if !line.spans.iter().any(|s| s.content.contains(MARKER)) {
+            continue;
+        }
+        let mut column = 0;
+        let mut open: Option<(usize, String)> = None;
+        let mut close = |open: &mut Option<(usize, String)>, column: usize| {
+            if let Some((start, url)) = open.take()
+                && column > start
+            {
+                links.push(Link {
+                    row,
+                    columns: start..column,
+                    url,
+                });
+            }
+        };
+        for span in line.spans.iter_mut() {
+            if !span.content.contains(MARKER) {
+                column += span.content.width();
+                continue;
+            }
+            let mut text = String::with_capacity(span.content.len());
+            let mut chars = span.content.chars().peekable();
+            while let Some(c) = chars.next() {
+                if c != MARKER {
+                    column += unicode_width::UnicodeWidthChar::width(c).unwrap_or(0);
+                    text.push(c);
+                    continue;
+                }
+                let mut url = String::new();
+                while let Some(c) = chars.next_if(|&c| untag(c).is_some()) {
+                    url.extend(untag(c));
+                }
+                close(&mut open, column);
+                if !url.is_empty() {
+                    open = Some((column, url));
+                }
+            }
+            *span = Span::styled(text, span.style);
+        }
+        close(&mut open, column);
+    }
+    links
+}
+
+/// Wrap the cells of `links`, as laid out in `area`, in OSC 8 sequences.
+///
+/// A cell holding escape sequences counts as wider than it is, so the cell
+/// after it isn't drawn separately: cells are linked in pairs, the second
+/// one's character moving into the first. An odd cell out at the end of a
+/// link stays unlinked rather than hiding the cell after the link.
+pub fn apply(buf: &mut Buffer, area: Rect, links: &[Link]) {
+    for link in links {
+        let Ok(row) = u16::try_from(link.row) else { continue };
+        if row >= area.height {
+            continue;
+        }
+        let y = area.y + row;
+        let end = link.columns.end.min(area.width as usize);
+        let mut column = link.columns.start;
+        while column + 1 < end {
+            let x = area.x + column as u16;
+            let first = buf[(x, y)].symbol().to_string();
+            let second = buf[(x + 1, y)].symbol();
+            let text = if first.width() > 1 {
+                // A wide character covers the next cell itself
+                first
+            } else if second.width() > 1 {
+                break;
+            } else {
+                first + second
+            };
+            let symbol = format!("\x1b]8;;{}\x1b\\{}\x1b]8;;\x1b\\", link.url, text);
+            buf[(x, y)].set_symbol(&symbol);
+            column += 2;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn link(url: &str, text: &str) -> String {
+        format!("\x1b]8;id=1;{}\x1b\\{}\x1b]8;;\x1b\\", url, text)
+    }
+
+    fn rows(content: &str) -> Vec<Line<'static>> {
+        use ansi_to_tui::IntoText;
+        mark_links(content).as_bytes().into_text().unwrap().lines
+    }
+
+    #[test]
+    fn test_strip_links() {
+        let content = format!("see {} and \x1b]0;title\x07{}", link("https://a.io", "docs"), "\x1b[1mbold\x1b[0m");
+        assert_eq!(strip_links(&content), "see docs and \x1b[1mbold\x1b[0m");
+        // BEL-terminated, and cut off mid-sequence
+        assert_eq!(strip_links("\x1b]8;;https://a.io\x07x\x1b]8;;\x07 y"), "x y");
+        assert_eq!(strip_links("x \x1b]8;;https://a."), "x ");
+        assert!(matches!(strip_links("plain \x1b[1m"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn test_take_links() {
+        let content = format!("\x1b[32mok\x1b[0m {} · {}", link("https://a.io/x y", "a.io"), link("file:///tmp/é", "tmp"));
+        let mut lines = rows(&content);
+        assert_eq!(lines[0].to_string().width(), "ok a.io · tmp".width());
+
+        let links = take_links(&mut lines);
+        assert_eq!(lines[0].to_string(), "ok a.io · tmp");
+        assert_eq!(
+            links,
+            [
+                Link {
+                    row: 0,
+                    columns: 3..7,
+                    url: "https://a.io/x%20y".to_string(),
+                },
+                Link {
+                    row: 0,
+                    columns: 10..13,
+                    url: "file:///tmp/%C3%A9".to_string(),
+                },
+            ]
+        );
+
+        // Cut before the link ends, as by truncation
+        let mut lines = rows("\x1b]8;;https://a.io\x1b\\abc");
+        assert_eq!(take_links(&mut lines)[0].columns, 0..3);
+    }
+
+    #[test]
+    fn test_apply_pairs_cells() {
+        let area = Rect::new(1, 1, 10, 2);
+        let mut buf = Buffer::empty(Rect::new(0, 0, 12, 4));
+        buf.set_string(1, 2, "abcde", ratatui::style::Style::default());
+        let links = [Link {
+            row: 1,
+            columns: 0..5,
+            url: "https://a.io".to_string(),
+        }];
+        apply(&mut buf, area, &links);
+
+        assert_eq!(buf[(1, 2)].symbol(), "\x1b]8;;https://a.io\x1b\\ab\x1b]8;;\x1b\\");
+        assert_eq!(buf[(3, 2)].symbol(), "\x1b]8;;https://a.io\x1b\\cd\x1b]8;;\x1b\\");
+        // The odd cell out stays as it was
+        assert_eq!(buf[(5, 2)].symbol(), "e");
+        assert_eq!(buf[(6, 2)].symbol(), " ");
+    }
+}
@@ -0,0 +1,157 @@
+//! Shell integration printed by `mux --init <shell>`.
+//!
+//! Each snippet binds Ctrl+G to launch mux and, once it exits, puts a
+//! command from mux on the shell's command line for editing or re-running.
+//!
+//! The handoff: the snippet creates an empty temporary file and starts
+//! `mux --handoff-file <path> --handoff <last|draft>`. On exit mux writes one
+//! command to that file, without a trailing newline, or leaves it empty:
+//!   last   the last command run in mux, as typed (the default)
+//!   draft  the text left in the input line, so mux works as a picker
+//! The snippet reads the file, removes it, and fills the command line if
+//! there's something in it. `$MUX_HANDOFF` picks the mode. Stdout stays free
+//! for the TUI.
+
+use std::path::Path;
+
+use crate::tui::ExitCommands;
+
+/// Shells `--init` has a snippet for
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum InitShell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+/// Which command `--handoff-file` receives on exit
+#[derive(Debug, Clone, Copy, Default, PartialEq, clap::ValueEnum)]
+pub enum Handoff {
+    /// The last command run in mux
+    #[default]
+    Last,
+    /// The text left in the input line, e.g. a suggestion picked but not run
+    Draft,
+}
+
+const BASH: &str = r#"# mux shell integration for bash: eval "$(mux --init bash)" in ~/.bashrc
+__mux_run() {
+  local handoff cmd
+  handoff=$(mktemp "${TMPDIR:-/tmp}/mux-handoff.XXXXXX") || return
+  mux --handoff-file "$handoff" --handoff "${MUX_HANDOFF:-last}" </dev/tty
+  cmd=$(<"$handoff")
+  command rm -f -- "$handoff"
+  if [[ -n $cmd ]]; then
+    READLINE_LINE=$cmd
+    READLINE_POINT=${#READLINE_LINE}
+  fi
+}
+bind -m emacs-standard -x '"\C-g": __mux_run'
+bind -m vi-insert -x '"\C-g": __mux_run'
+"#;
+
+const ZSH: &str = r#"# mux shell integration for zsh: eval "$(mux --init zsh)" in ~/.zshrc
+__mux_widget() {
+  local handoff cmd
+  handoff=$(mktemp "${TMPDIR:-/tmp}/mux-handoff.XXXXXX") || return
+  mux --handoff-file "$handoff" --handoff "${MUX_HANDOFF:-last}" </dev/tty
+  cmd=$(<"$handoff")
+  command rm -f -- "$handoff"
+  if [[ -n $cmd ]]; then
+    BUFFER=$cmd
+    CURSOR=${#BUFFER}
+  fi
+  zle reset-prompt
+}
+zle -N __mux_widget
+bindkey '^G' __mux_widget
+"#;
+
+const FISH: &str = r#"# mux shell integration for fish: mux --init fish | source in config.fish
+function __mux_run
+    set -l tmpdir /tmp
+    set -q TMPDIR; and set tmpdir $TMPDIR
+    set -l handoff (mktemp "$tmpdir/mux-handoff.XXXXXX"); or return
+    set -l what last
+    set -q MUX_HANDOFF; and set what $MUX_HANDOFF
+    mux --handoff-file $handoff --handoff $what </dev/tty
+    set -l cmd (string collect <$handoff)
+    command rm -f -- $handoff
+    if test -n "$cmd"
+        commandline -r -- $cmd
+        commandline -f end-of-line
+    end
+    commandline -f repaint
+end
+bind \cg __mux_run
+bind -M insert \cg __mux_run
+"#;
+
+/// The integration snippet for `shell`, ready to be eval'd or sourced
+pub fn script(shell: InitShell) -> &'static str {
+    match shell {
+        InitShell::Bash => BASH,
+        InitShell::Zsh => ZSH,
+        InitShell::Fish => FISH,
+    }
+}
+
+/// The command to hand to the shell, if any
+pub fn handoff_command(mode: Handoff, exit: &ExitCommands) -> Option<&str> {
+    match mode {
+        Handoff::Last => exit.last_run.as_deref(),
+        Handoff::Draft => Some(exit.draft.trim()).filter(|draft| !draft.is_empty()),
+    }
+}
+
+/// Write the handoff file for the shell snippet: the command, or nothing
+pub fn write_handoff(path: &Path, command: Option<&str>) -> std::io::Result<()> {
+    std::fs::write(path, command.unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scripts_use_the_handoff_file() {
+        for shell in [InitShell::Bash, InitShell::Zsh, InitShell::Fish] {
+            let script = script(shell);
+            assert!(script.contains("mux --handoff-file"), "{:?}", shell);
+            assert!(script.contains("--handoff "), "{:?}", shell);
+            assert!(script.contains("rm -f -- "), "{:?}", shell);
+        }
+        assert!(script(InitShell::Bash).contains("READLINE_LINE"));
+        assert!(script(InitShell::Zsh).contains("bindkey '^G'"));
+        assert!(script(InitShell::Fish).contains("commandline -r"));
+    }
+
+    #[test]
+    fn test_handoff_command() {
+        let exit = ExitCommands {
+            last_run: Some("make test".to_string()),
+            draft: "  git push  ".to_string(),
+        };
+        assert_eq!(handoff_command(Handoff::Last, &exit), Some("make test"));
+        assert_eq!(handoff_command(Handoff::Draft, &exit), Some("git push"));
+
+        let blank = ExitCommands {
+            draft: " ".to_string(),
+            ..ExitCommands::default()
+        };
+        assert_eq!(handoff_command(Handoff::Last, &blank), None);
+        assert_eq!(handoff_command(Handoff::Draft, &blank), None);
+    }
+
+    #[test]
+    fn test_write_handoff() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("handoff");
+        write_handoff(&path, Some("git status")).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "git status");
+
+        // Nothing ran: the file is emptied, so the command line is left alone
+        write_handoff(&path, None).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "");
+    }
+}
@@ -1,13 +1,88 @@
+use std::time::{Duration, Instant};
+
 use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use crate::pager::Pager;
 use crate::runner::TaskRunner;
 use crate::tui::App;
 
+/// How long the first key of a chord waits for the second
+pub const CHORD_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// First key, second key, and the action they run together
+type Chord = (KeyCode, KeyCode, fn(&mut App));
+
+/// Two-key sequences in the output pane. A key that starts one waits up to
+/// `CHORD_TIMEOUT` for the second; on a timeout, or a second key that makes
+/// no chord, it does what it does alone.
+const OUTPUT_CHORDS: &[Chord] = &[
+    (KeyCode::Char('g'), KeyCode::Char('g'), App::jump_to_top),
+];
+
+fn output_chord(first: KeyEvent, second: KeyEvent) -> Option<fn(&mut App)> {
+    if !second.modifiers.difference(KeyModifiers::SHIFT).is_empty() {
+        return None;
+    }
+    OUTPUT_CHORDS
+        .iter()
+        .find(|(a, b, _)| *a == first.code && *b == second.code)
+        .map(|&(_, _, action)| action)
+}
+
+fn starts_output_chord(key: KeyEvent) -> bool {
+    key.modifiers.difference(KeyModifiers::SHIFT).is_empty()
+        && OUTPUT_CHORDS.iter().any(|(a, _, _)| *a == key.code)
+}
+
+/// Give up on a chord whose second key didn't come in time, running its
+/// first key alone. Called by the event loop when the chord's deadline passes.
+pub fn expire_chord(app: &mut App, now: Instant) {
+    if app.chord_deadline().is_some_and(|deadline| now >= deadline)
+        && let Some(first) = app.take_pending_chord()
+    {
+        output_focus_key(app, first);
+    }
+}
+
 /// Handle keyboard input for the application
 /// Returns true if the application should quit
 pub fn handle_key_event(app: &mut App, key: KeyEvent, runner: &mut TaskRunner) -> bool {
     let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
     let alt = key.modifiers.contains(KeyModifiers::ALT);
 
+    if let Some(pager) = app.pager_mut() {
+        if !handle_pager_key(pager, key) {
+            app.close_pager();
+        }
+        return false;
+    }
+
+    if app.settings_mut().is_some() {
+        handle_settings_key(app, key);
+        return false;
+    }
+
+    if app.arg_picker_mut().is_some() {
+        handle_arg_picker_key(app, key);
+        return false;
+    }
+
+    if app.is_parameterizing() {
+        handle_parameterize_key(app, key);
+        return false;
+    }
+
+    if app.is_tagging() && handle_tagging_key(app, key) {
+        return false;
+    }
+
+    if app.run_pipe(key) {
+        return false;
+    }
+
+    if app.is_output_focused() {
+        return handle_output_focus_key(app, key, runner);
+    }
+
     match key.code {
         // Quit (double-press Ctrl+C or Ctrl+D within 1s)
         KeyCode::Char('c') if ctrl => return app.try_quit(),
@@ -18,14 +93,26 @@ pub fn handle_key_event(app: &mut App, key: KeyEvent, runner: &mut TaskRunner) -
                 app.delete_char_forward();
             }
         }
-        KeyCode::Esc => return app.try_quit(),
+        KeyCode::Esc => return app.escape(),
 
         // Suggestions
         KeyCode::Tab => app.next_suggestion(),
         KeyCode::BackTab => app.prev_suggestion(),
         KeyCode::Char('y') if ctrl => app.accept_suggestion(),
+        KeyCode::Char('y') if alt => app.yank_suggestion(),
+        KeyCode::Char('s') if alt => app.toggle_suggestions(),
+        KeyCode::Char('a') if alt => app.toggle_rare_commands(),
+        KeyCode::Char('v') if alt => app.cycle_suggestion_display(),
+        KeyCode::Char(',') if alt => app.open_settings(),
         KeyCode::Char('p') if ctrl => app.prev_suggestion(),
         KeyCode::Char('n') if ctrl => app.next_suggestion(),
+        KeyCode::Char('p') if alt => app.start_parameterize(),
+        KeyCode::Char('r') if alt => app.open_arg_picker(),
+        KeyCode::Char('g') if alt => app.start_tagging(),
+        KeyCode::Char('t') if alt => app.insert_timestamp(false),
+        KeyCode::Char('T') if alt => app.insert_timestamp(true),
+        KeyCode::Up if alt => app.cycle_query(true),
+        KeyCode::Down if alt => app.cycle_query(false),
         KeyCode::Up => {
             if app.input().is_empty() {
                 app.recall_last_command();
@@ -36,6 +123,7 @@ pub fn handle_key_event(app: &mut App, key: KeyEvent, runner: &mut TaskRunner) -
         KeyCode::Down => app.next_suggestion(),
 
         // Text input
+        KeyCode::Char(' ') if !ctrl && !alt => app.insert_space(),
         KeyCode::Char(c) if !ctrl && !alt => app.insert_char(c),
 
         // Line editing (emacs-style)
@@ -45,6 +133,10 @@ pub fn handle_key_event(app: &mut App, key: KeyEvent, runner: &mut TaskRunner) -
         KeyCode::Char('u') if ctrl => app.delete_to_line_start(),
         KeyCode::Char('k') if ctrl => app.delete_to_line_end(),
         KeyCode::Char('l') if ctrl => app.clear_output(),
+        KeyCode::Char('z') if ctrl => app.undo_clear_or_suspend(),
+        KeyCode::Char('l') if alt => app.toggle_label_filter(),
+        KeyCode::Char('k') if alt => app.dismiss_last_box(),
+        KeyCode::Char('z') if alt => app.toggle_fold_in_view(),
 
         // Delete operations
         KeyCode::Backspace if alt => app.delete_word_backward(),
@@ -55,7 +147,13 @@ pub fn handle_key_event(app: &mut App, key: KeyEvent, runner: &mut TaskRunner) -
 
         // Cursor movement
         KeyCode::Char('b') if ctrl => app.move_cursor_left(),
-        KeyCode::Char('f') if ctrl => app.move_cursor_right(),
+        KeyCode::Char('f') if ctrl => {
+            if app.cursor_position() == app.input().len() {
+                app.accept_full_preview();
+            } else {
+                app.move_cursor_right();
+            }
+        }
         KeyCode::Char('b') if alt => app.move_cursor_word_left(),
         KeyCode::Char('f') if alt => app.move_cursor_word_right(),
         KeyCode::Left if alt | ctrl => app.move_cursor_word_left(),
@@ -69,16 +167,221 @@ pub fn handle_key_event(app: &mut App, key: KeyEvent, runner: &mut TaskRunner) -
             }
         }
         KeyCode::Home => app.move_cursor_home(),
-        KeyCode::End => app.move_cursor_end(),
+        KeyCode::End => {
+            if app.cursor_position() == app.input().len() {
+                app.accept_full_preview();
+            } else {
+                app.move_cursor_end();
+            }
+        }
 
         // Output scrolling
         KeyCode::PageUp => app.scroll_up(10),
         KeyCode::PageDown => app.scroll_down(10),
+        KeyCode::Char('o') if alt => app.toggle_output_focus(),
+        KeyCode::Char('o') if ctrl => app.open_pager(),
+
+        // Submit. Ctrl/Shift+Enter need a terminal that reports modifiers on
+        // Enter; Alt+Enter works everywhere.
+        KeyCode::Enter if ctrl || alt || key.modifiers.contains(KeyModifiers::SHIFT) => {
+            return app.accept_and_submit(runner);
+        }
+        KeyCode::Enter => return app.submit_or_accept(runner),
+        KeyCode::Char('r') if ctrl => return app.rerun_last_command(runner),
+
+        // Task control
+        KeyCode::Char('g') if ctrl => app.cancel_parallel_batch(runner),
+
+        _ => {}
+    }
+    false
+}
+
+/// Handle a key while the output pane has focus. Input editing is suspended
+/// so plain letters can scroll; Ctrl+C still quits.
+/// Returns true if the application should quit.
+fn handle_output_focus_key(app: &mut App, key: KeyEvent, runner: &mut TaskRunner) -> bool {
+    if let Some(first) = app.take_pending_chord() {
+        if let Some(action) = output_chord(first, key) {
+            action(app);
+            return false;
+        }
+        output_focus_key(app, first);
+    }
+    if let KeyCode::Char(c) = key.code
+        && key.modifiers.difference(KeyModifiers::SHIFT).is_empty()
+        && let Some(digit) = c.to_digit(10)
+    {
+        app.push_count_digit(digit);
+        return false;
+    }
+    // Running needs the runner, which single keys don't get
+    if key.code == KeyCode::Char('r') && key.modifiers.is_empty() {
+        app.take_count();
+        app.rerun_selected_command(runner);
+        return false;
+    }
+    if starts_output_chord(key) {
+        app.start_chord(key);
+        return false;
+    }
+    output_focus_key(app, key)
+}
 
-        // Submit
-        KeyCode::Enter => return app.submit_command(runner),
+/// A single key's action with the output focused. Scroll keys are
+/// repeated by a count typed before them; any key ends the count.
+fn output_focus_key(app: &mut App, key: KeyEvent) -> bool {
+    let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+    let alt = key.modifiers.contains(KeyModifiers::ALT);
+    let count = app.take_count();
+    let repeat = |app: &mut App, action: fn(&mut App)| (0..count).for_each(|_| action(app));
 
+    match key.code {
+        KeyCode::Char('c') if ctrl => return app.try_quit(),
+        KeyCode::Char('z') if ctrl => app.undo_clear_or_suspend(),
+        KeyCode::Char('o') if alt => app.toggle_output_focus(),
+        KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('i') => app.toggle_output_focus(),
+        KeyCode::Char('e') if ctrl => app.scroll_down(count),
+        KeyCode::Char('y') if ctrl => app.scroll_up(count),
+        KeyCode::Char('d') if ctrl => repeat(app, App::scroll_half_page_down),
+        KeyCode::Char('u') if ctrl => repeat(app, App::scroll_half_page_up),
+        KeyCode::Char('f') if ctrl => repeat(app, App::scroll_page_down),
+        KeyCode::Char('b') if ctrl => repeat(app, App::scroll_page_up),
+        KeyCode::Char('j') | KeyCode::Down => app.scroll_down(count),
+        KeyCode::Char('k') | KeyCode::Up => app.scroll_up(count),
+        KeyCode::Char('d') => repeat(app, App::scroll_half_page_down),
+        KeyCode::Char('u') => repeat(app, App::scroll_half_page_up),
+        KeyCode::Char(' ') | KeyCode::PageDown => repeat(app, App::scroll_page_down),
+        KeyCode::Char('b') | KeyCode::PageUp => repeat(app, App::scroll_page_up),
+        KeyCode::Char('g') | KeyCode::Home => app.jump_to_top(),
+        KeyCode::Char('G') | KeyCode::End => app.jump_to_bottom(),
+        KeyCode::Char('z') | KeyCode::Enter => app.toggle_fold_in_view(),
+        KeyCode::Char(']') | KeyCode::Tab => app.select_command(true),
+        KeyCode::Char('[') | KeyCode::BackTab => app.select_command(false),
+        KeyCode::Char('e') if !ctrl => app.edit_selected_command(),
+        KeyCode::Char('M') => app.fold_all(true),
+        KeyCode::Char('R') => app.fold_all(false),
+        KeyCode::Char('o') if ctrl => app.open_pager(),
         _ => {}
     }
     false
 }
+
+/// Handle a key while the settings overlay is open
+fn handle_settings_key(app: &mut App, key: KeyEvent) {
+    let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+
+    match key.code {
+        KeyCode::Char('q') | KeyCode::Esc => app.close_settings(),
+        KeyCode::Char('c') if ctrl => app.close_settings(),
+        KeyCode::Char(',') if key.modifiers.contains(KeyModifiers::ALT) => app.close_settings(),
+        KeyCode::Char('j') | KeyCode::Down | KeyCode::Tab => {
+            if let Some(view) = app.settings_mut() {
+                view.select_next();
+            }
+        }
+        KeyCode::Char('k') | KeyCode::Up | KeyCode::BackTab => {
+            if let Some(view) = app.settings_mut() {
+                view.select_prev();
+            }
+        }
+        KeyCode::Char('l') | KeyCode::Char('+') | KeyCode::Right | KeyCode::Enter => app.adjust_setting(true),
+        KeyCode::Char('h') | KeyCode::Char('-') | KeyCode::Left => app.adjust_setting(false),
+        KeyCode::Char('w') => app.save_settings(),
+        _ => {}
+    }
+}
+
+/// Handle a key while the argument picker is open. Typing filters the list.
+fn handle_arg_picker_key(app: &mut App, key: KeyEvent) {
+    let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+    let alt = key.modifiers.contains(KeyModifiers::ALT);
+
+    match key.code {
+        KeyCode::Esc => app.close_arg_picker(),
+        KeyCode::Char('c') if ctrl => app.close_arg_picker(),
+        KeyCode::Char('r') if alt => app.close_arg_picker(),
+        KeyCode::Enter => app.apply_arg_picker(),
+        _ => {
+            let Some(picker) = app.arg_picker_mut() else {
+                return;
+            };
+            match key.code {
+                KeyCode::Down | KeyCode::Tab => picker.select_next(),
+                KeyCode::Char('n') if ctrl => picker.select_next(),
+                KeyCode::Up | KeyCode::BackTab => picker.select_prev(),
+                KeyCode::Char('p') if ctrl => picker.select_prev(),
+                KeyCode::Backspace => picker.pop_char(),
+                KeyCode::Char(c) if !ctrl && !alt => picker.push_char(c),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Handle a key while picking a word to parameterize. Editing is suspended
+/// until the word is wrapped (Enter) or the pick is cancelled.
+fn handle_parameterize_key(app: &mut App, key: KeyEvent) {
+    let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+
+    match key.code {
+        KeyCode::Esc => app.cancel_parameterize(),
+        KeyCode::Char('c') if ctrl => app.cancel_parameterize(),
+        KeyCode::Char('p') if key.modifiers.contains(KeyModifiers::ALT) => app.cancel_parameterize(),
+        KeyCode::Right | KeyCode::Tab | KeyCode::Char('l') => app.move_parameterize_selection(true),
+        KeyCode::Left | KeyCode::BackTab | KeyCode::Char('h') => app.move_parameterize_selection(false),
+        KeyCode::Enter => app.apply_parameterize(),
+        _ => {}
+    }
+}
+
+/// Handle Enter and cancelling while typing tags (Alt+G). Returns false
+/// for other keys, which edit the tags like any input.
+fn handle_tagging_key(app: &mut App, key: KeyEvent) -> bool {
+    let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+
+    match key.code {
+        KeyCode::Enter => app.apply_tagging(),
+        KeyCode::Esc => app.cancel_tagging(),
+        KeyCode::Char('c') if ctrl => app.cancel_tagging(),
+        KeyCode::Char('g') if key.modifiers.contains(KeyModifiers::ALT) => app.cancel_tagging(),
+        _ => return false,
+    }
+    true
+}
+
+/// Handle a key while the pager is open.
+/// Returns false if the pager should close.
+pub fn handle_pager_key(pager: &mut Pager, key: KeyEvent) -> bool {
+    let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+
+    // Typing a search after `/`
+    if pager.search_input().is_some() {
+        match key.code {
+            KeyCode::Enter => pager.commit_search(),
+            KeyCode::Esc => pager.cancel_search(),
+            KeyCode::Backspace => pager.pop_search_char(),
+            KeyCode::Char(c) if !ctrl => pager.push_search_char(c),
+            _ => {}
+        }
+        return true;
+    }
+
+    match key.code {
+        KeyCode::Char('q') | KeyCode::Esc => return false,
+        KeyCode::Char('c') if ctrl => return false,
+        KeyCode::Char('d') if ctrl => pager.page_down(),
+        KeyCode::Char('u') if ctrl => pager.page_up(),
+        KeyCode::Char('j') | KeyCode::Down | KeyCode::Enter => pager.scroll_down(1),
+        KeyCode::Char('k') | KeyCode::Up => pager.scroll_up(1),
+        KeyCode::Char(' ') | KeyCode::PageDown => pager.page_down(),
+        KeyCode::Char('b') | KeyCode::PageUp => pager.page_up(),
+        KeyCode::Char('g') | KeyCode::Home => pager.jump_to_top(),
+        KeyCode::Char('G') | KeyCode::End => pager.jump_to_bottom(),
+        KeyCode::Char('/') => pager.start_search(),
+        KeyCode::Char('n') => pager.next_match(),
+        KeyCode::Char('N') => pager.prev_match(),
+        _ => {}
+    }
+    true
+}
@@ -0,0 +1,37 @@
+//! mux's history-search, suggestion and parallel-expansion engines, without
+//! the TUI.
+//!
+//! - [`HistorySearcher`]: commands imported from shell history (see
+//!   [`history`]) in a SQLite database, fuzzy-searched and ranked by use
+//! - [`SuggestionEngine`]: completions for a partly typed command, from that
+//!   history's [`IndexedCommand`]s plus any registered [`providers`]
+//! - [`parallel`]: `[name=range] command {name}` parsing and expansion
+//!
+//! ```
+//! use mux::{HistorySearcher, SuggestionEngine};
+//!
+//! let mut searcher = HistorySearcher::new_in_memory()?;
+//! searcher.record_usage("cargo build --release")?;
+//! let engine = SuggestionEngine::new(searcher.commands());
+//! let suggestions = engine.suggest("cargo b", &mut searcher, 5);
+//! assert_eq!(suggestions[0].text, "cargo build --release");
+//!
+//! let parsed = mux::parallel::parse_parallel("[n=1-3] ssh web-{n} uptime").unwrap().unwrap();
+//! let commands: Vec<_> = mux::parallel::expand(&parsed).into_iter().map(|c| c.command).collect();
+//! assert_eq!(commands, ["ssh web-1 uptime", "ssh web-2 uptime", "ssh web-3 uptime"]);
+//! # Ok::<(), mux::Error>(())
+//! ```
+//!
+//! The `mux` binary is built on these and adds the TUI, task runner and config.
+
+pub mod error;
+pub mod history;
+pub mod parallel;
+pub mod paths;
+pub mod providers;
+pub mod searcher;
+pub mod suggest;
+
+pub use error::Error;
+pub use searcher::{HistorySearcher, IndexedCommand};
+pub use suggest::{Suggestion, SuggestionEngine, SuggestionType};
@@ -8,20 +8,39 @@ use log4rs::{
         },
         RollingFileAppender,
     },
-    config::{Appender, Config, Root},
+    config::{Appender, Config, Logger, Root},
     encode::pattern::PatternEncoder,
 };
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
 use crate::config::LoggingConfig;
 use crate::paths;
 
+/// Name of the current log file; archives are `mux.1.log`, `mux.2.log`, ...
+pub const LOG_FILE_NAME: &str = "mux.log";
+
+/// How much of the end of the log `last_lines` reads
+const TAIL_BYTES: u64 = 1024 * 1024;
+
 /// Initialize the logging system
 ///
-/// Logs to $XDG_STATE_HOME/mux/logs/mux.log in glog format.
+/// Logs to mux.log in `[logging] dir` (default $XDG_STATE_HOME/mux/logs/) in glog format.
 /// Rotation size and archive count are controlled by config.
-/// Log level is read from RUST_LOG env var, defaults to INFO if unset or invalid.
+/// Log levels are read from the RUST_LOG env var (see [`parse_log_directives`]),
+/// defaulting to INFO if unset or invalid.
+/// With `[logging] enabled = false` a logger without appenders is installed
+/// at level Off, so log calls return immediately and nothing is written.
 pub fn init_logger(config: &LoggingConfig) -> Result<(), Box<dyn std::error::Error>> {
-    let log_dir = paths::get_log_dir()?;
-    let log_file = log_dir.join("mux.log");
+    if !config.enabled {
+        let config = Config::builder().build(Root::builder().build(LevelFilter::Off))?;
+        log4rs::init_config(config)?;
+        return Ok(());
+    }
+
+    let log_dir = log_dir(config);
+    std::fs::create_dir_all(&log_dir)?;
+    let log_file = log_dir.join(LOG_FILE_NAME);
 
     // glog format: Lmmdd hh:mm:ss.uuuuuu threadid file:line] msg
     let pattern = "{l:.1}{d(%m%d %H:%M:%S%.6f)} {T} {f}:{L}] {m}{n}";
@@ -42,12 +61,21 @@ pub fn init_logger(config: &LoggingConfig) -> Result<(), Box<dyn std::error::Err
         .encoder(Box::new(PatternEncoder::new(pattern)))
         .build(log_file, Box::new(compound_policy))?;
 
+    let directives = parse_log_directives(&std::env::var("RUST_LOG").unwrap_or_default());
+
+    // Per-target loggers inherit the root's file appender (they're additive)
     let config = Config::builder()
         .appender(Appender::builder().build("file", Box::new(file_appender)))
+        .loggers(
+            directives
+                .targets
+                .into_iter()
+                .map(|(target, level)| Logger::builder().build(target, level)),
+        )
         .build(
             Root::builder()
                 .appender("file")
-                .build(log_level_from_env()),
+                .build(directives.default_level),
         )?;
 
     log4rs::init_config(config)?;
@@ -55,18 +83,148 @@ pub fn init_logger(config: &LoggingConfig) -> Result<(), Box<dyn std::error::Err
     Ok(())
 }
 
-/// Read log level from RUST_LOG env var. Defaults to INFO if unset or invalid.
-fn log_level_from_env() -> LevelFilter {
-    std::env::var("RUST_LOG")
-        .ok()
-        .and_then(|s| match s.to_lowercase().as_str() {
-            "trace" => Some(LevelFilter::Trace),
-            "debug" => Some(LevelFilter::Debug),
-            "info" => Some(LevelFilter::Info),
-            "warn" => Some(LevelFilter::Warn),
-            "error" => Some(LevelFilter::Error),
-            "off" => Some(LevelFilter::Off),
-            _ => None,
-        })
-        .unwrap_or(LevelFilter::Info)
+/// The log directory: `[logging] dir` or the default. It may not exist
+/// yet; `init_logger` creates it.
+pub fn log_dir(config: &LoggingConfig) -> PathBuf {
+    match &config.dir {
+        Some(dir) => paths::expand_path(dir),
+        None => paths::get_log_dir(),
+    }
+}
+
+/// The last `n` lines of the file at `path`. Only the end of a big file is
+/// read, so the first line that was cut short there is skipped.
+pub fn last_lines(path: &Path, n: usize) -> io::Result<Vec<String>> {
+    let mut file = std::fs::File::open(path)?;
+    let start = file.metadata()?.len().saturating_sub(TAIL_BYTES);
+    file.seek(SeekFrom::Start(start))?;
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes)?;
+
+    let text = String::from_utf8_lossy(&bytes);
+    let mut lines: Vec<&str> = text.lines().collect();
+    if start > 0 && !lines.is_empty() {
+        lines.remove(0);
+    }
+    let skip = lines.len().saturating_sub(n);
+    Ok(lines[skip..].iter().map(|l| l.to_string()).collect())
+}
+
+/// Log levels parsed from a RUST_LOG value
+#[derive(Debug, PartialEq)]
+struct LogDirectives {
+    /// Level for targets without their own directive
+    default_level: LevelFilter,
+    /// (target, level) pairs, e.g. ("mux::suggest", Debug)
+    targets: Vec<(String, LevelFilter)>,
+}
+
+/// Parse RUST_LOG in env_logger's syntax: comma-separated directives, each
+/// either a bare level (`debug`) or `target=level` (`mux::suggest=debug`).
+/// A target covers its submodules. Invalid directives are ignored; without a
+/// bare level the default is INFO.
+fn parse_log_directives(spec: &str) -> LogDirectives {
+    let mut directives = LogDirectives {
+        default_level: LevelFilter::Info,
+        targets: Vec::new(),
+    };
+
+    for directive in spec.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+        match directive.split_once('=') {
+            Some((target, level)) => {
+                if let Some(level) = parse_level(level) {
+                    directives.targets.push((target.trim().to_string(), level));
+                }
+            }
+            None => {
+                if let Some(level) = parse_level(directive) {
+                    directives.default_level = level;
+                }
+            }
+        }
+    }
+
+    directives
+}
+
+fn parse_level(s: &str) -> Option<LevelFilter> {
+    match s.trim().to_lowercase().as_str() {
+        "trace" => Some(LevelFilter::Trace),
+        "debug" => Some(LevelFilter::Debug),
+        "info" => Some(LevelFilter::Info),
+        "warn" => Some(LevelFilter::Warn),
+        "error" => Some(LevelFilter::Error),
+        "off" => Some(LevelFilter::Off),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_single_level() {
+        assert_eq!(
+            parse_log_directives("debug"),
+            LogDirectives {
+                default_level: LevelFilter::Debug,
+                targets: Vec::new(),
+            }
+        );
+        // Unset or invalid falls back to INFO
+        assert_eq!(parse_log_directives("").default_level, LevelFilter::Info);
+        assert_eq!(parse_log_directives("loud").default_level, LevelFilter::Info);
+    }
+
+    #[test]
+    fn test_parse_target_directives() {
+        assert_eq!(
+            parse_log_directives("warn, mux::suggest=debug,mux::runner=off,bad=loud"),
+            LogDirectives {
+                default_level: LevelFilter::Warn,
+                targets: vec![
+                    ("mux::suggest".to_string(), LevelFilter::Debug),
+                    ("mux::runner".to_string(), LevelFilter::Off),
+                ],
+            }
+        );
+        assert_eq!(
+            parse_log_directives("mux::suggest=trace").default_level,
+            LevelFilter::Info
+        );
+    }
+
+    #[test]
+    fn test_last_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(LOG_FILE_NAME);
+        std::fs::write(&path, "one\ntwo\nthree\n").unwrap();
+        assert_eq!(last_lines(&path, 2).unwrap(), ["two", "three"]);
+        assert_eq!(last_lines(&path, 10).unwrap(), ["one", "two", "three"]);
+
+        // Past TAIL_BYTES, the partial line where reading starts is dropped
+        let long = format!("{}\nend\n", "x".repeat(TAIL_BYTES as usize + 10));
+        std::fs::write(&path, long).unwrap();
+        assert_eq!(last_lines(&path, 5).unwrap(), ["end"]);
+
+        assert!(last_lines(&dir.path().join("missing.log"), 5).is_err());
+    }
+
+    #[test]
+    fn test_disabled_logger_writes_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let log_dir = dir.path().join("logs");
+        let config = LoggingConfig {
+            enabled: false,
+            dir: Some(log_dir.clone()),
+            ..LoggingConfig::default()
+        };
+
+        init_logger(&config).unwrap();
+        log::info!("not written");
+
+        assert_eq!(log::max_level(), LevelFilter::Off);
+        assert!(!log_dir.exists());
+    }
 }
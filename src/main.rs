@@ -1,55 +1,198 @@
+mod arg_picker;
 mod args;
+mod command_policy;
 mod config;
-mod history;
+mod cwd_policy;
+mod dotenv;
+mod events;
+mod hyperlink;
+mod init;
 mod keymap;
 mod logger;
-mod parallel;
-mod paths;
+mod output_pipe;
+mod pager;
+mod pipe;
 mod runner;
-mod searcher;
-mod suggest;
+mod session;
+mod settings;
+mod state;
 mod sync;
+mod tail;
+mod theme;
+mod time;
 mod tui;
+mod vars;
+
+// The engines are in the library; these let `crate::searcher` etc. keep working
+use mux::{error, history, parallel, paths, providers, searcher, suggest};
 
 use args::Args;
 use config::Config;
-use log::{error, info};
+use log::{debug, error, info, warn};
 use searcher::HistorySearcher;
 use suggest::SuggestionEngine;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let config = match paths::get_config_path() {
-        Ok(path) => Config::load(&path),
-        Err(_) => Config::default(),
+    let args = Args::parse_args();
+    if let Some(shell) = args.init {
+        print!("{}", init::script(shell));
+        return Ok(());
+    }
+
+    // Config layers, lowest precedence first: global, profile, then project-local
+    let profile_config = match &args.profile {
+        Some(name) => {
+            let path = paths::get_profile_config_path(name)?;
+            if !path.is_file() {
+                return Err(format!(
+                    "profile '{}' not found: create {} (it may be empty)",
+                    name,
+                    path.display()
+                )
+                .into());
+            }
+            Some(path)
+        }
+        None => None,
     };
+    let project_config = std::env::current_dir()
+        .ok()
+        .and_then(|dir| paths::find_project_config(&dir));
+    let config_layers: Vec<_> = paths::get_config_path()
+        .ok()
+        .into_iter()
+        .chain(paths::get_config_fragments().unwrap_or_default())
+        .chain(profile_config)
+        .collect();
+    let (mut config, config_warnings) = Config::load_layered(&config_layers, project_config.as_deref());
 
     if let Err(e) = logger::init_logger(&config.logging) {
         eprintln!("Failed to initialize logger: {}", e);
     }
+    // Logged here: the layers are read before the logger exists
+    if let Some(path) = &project_config {
+        info!("Using project config {}", path.display());
+    }
+    for warning in &config_warnings {
+        warn!("{}", warning);
+    }
 
     info!("Config loaded: {:?}", config);
 
-    let args = Args::parse_args();
-    let db_path = paths::get_db_path()?;
+    // --tail needs neither the history database nor suggestions
+    let tail_source = match (args.tail, args.tail_cmd) {
+        (Some(path), _) => Some(tail::TailSource::File(path)),
+        (None, Some(command)) => Some(tail::TailSource::Command(command)),
+        (None, None) => None,
+    };
+    if let Some(source) = tail_source {
+        return tail::run_tail(source, &config).await;
+    }
+
+    if let Some(name) = &args.profile {
+        info!("Using profile {}", name);
+    }
+    let db_path = paths::get_db_path(args.profile.as_deref())?;
+    if args.doctor {
+        return print_doctor_report(&db_path);
+    }
 
-    // Handle --rebuild: delete existing database to force a full re-sync
-    if args.rebuild {
-        if db_path.exists() {
-            std::fs::remove_file(&db_path)?;
-            info!("Rebuilding index: removed existing database");
+    // Handle --rebuild: move the existing database aside to force a full re-sync
+    if args.rebuild && db_path.exists() {
+        if !args.yes && !confirm_rebuild(&db_path)? {
+            eprintln!("Rebuild cancelled; the database was left as it was");
+            return Ok(());
         }
+        let backup = HistorySearcher::move_to_backup(&db_path)?;
+        info!("Rebuilding index: moved existing database to {}", backup.display());
+        eprintln!("Rebuilding the index; the old database is at {}", backup.display());
+    }
+
+    let mut searcher = if args.no_persist {
+        HistorySearcher::new_in_memory()?
+    } else if args.read_only {
+        HistorySearcher::open_read_only(db_path)?
+    } else {
+        HistorySearcher::new(db_path)?
+    };
+    if args.stats {
+        print_flaky_commands(&searcher)?;
+        print_slowest_commands(&searcher)?;
+        return print_usage_by_shell(&searcher);
     }
 
-    let mut searcher = HistorySearcher::new(db_path)?;
-    let sync_result = sync::sync_shell_history(&mut searcher);
+    searcher.set_match_all_terms(config.search.match_all_terms);
+    searcher.set_rank_by(config.search.rank_by);
+    searcher.set_algorithm(config.search.algorithm);
+    searcher.set_path_aware(config.search.path_aware);
+    searcher.set_min_fuzzy_length(config.search.min_fuzzy_length);
+    if let Err(e) = searcher.set_time_aware(config.search.time_aware) {
+        warn!("Time-aware ranking disabled: {}", e);
+    }
+    if let Err(e) = searcher.set_failure_penalty(config.search.penalize_failures) {
+        warn!("Failure penalty disabled: {}", e);
+    }
+    searcher.set_rank_weights(config.search.fuzzy_weight, config.search.frequency_weight);
+    searcher.set_strip_ansi(config.history.strip_ansi);
+    searcher.set_strip_comments(config.history.strip_comments);
+    searcher.set_strip_prompt_prefixes(config.history.strip_prompt_prefixes.clone());
+    searcher.set_sync_batch_size(config.sync.batch_size);
+    let sync_result = sync::sync_shell_history(&mut searcher, &config.history, &config.sync);
+    debug!("Startup sync indexed {} new commands", sync_result.total_synced);
+
+    if let Some(query) = &args.query {
+        return print_query_results(&mut searcher, query, args.limit, args.json);
+    }
+    if let Some(args::Command::History { action }) = &args.command {
+        return run_history_command(&mut searcher, action, args.read_only || args.no_persist);
+    }
+
+    // UI choices from earlier sessions; not saved in sessions that persist nothing
+    let ui_state_path = if args.no_persist || args.read_only {
+        None
+    } else {
+        paths::get_ui_state_path().ok()
+    };
+    if let Some(display) = ui_state_path
+        .as_deref()
+        .and_then(|path| state::UiState::load(path).suggestion_display)
+    {
+        config.suggest.display = display;
+    }
 
-    let suggestion_engine = SuggestionEngine::new(searcher.get_all_commands());
-    let result = tui::run_tui(searcher, suggestion_engine, sync_result.warnings, config).await;
+    let vacuum_on_exit = config.db.vacuum_on_exit;
+    let mut suggestion_engine = SuggestionEngine::new(searcher.commands());
+    suggestion_engine.register_provider(Box::new(providers::EnvVarProvider));
+    suggestion_engine.register_provider(Box::new(providers::PathProvider::default()));
+    suggestion_engine.set_argument_pairs(config.suggest.argument_pairs);
+    suggestion_engine.set_type_limits(suggest::TypeLimits {
+        commands: config.suggest.max_commands,
+        args: config.suggest.max_args,
+        values: config.suggest.max_values,
+    });
+    suggestion_engine.set_min_frequency(config.suggest.min_frequency);
+    suggestion_engine.set_anchor_start(config.search.anchor_start);
+    suggestion_engine.set_exact_first_token(config.search.exact_first_token);
+    let result = tui::run_tui(
+        searcher,
+        suggestion_engine,
+        config_warnings.into_iter().chain(sync_result.warnings).collect(),
+        config,
+        ui_state_path,
+    )
+    .await;
 
     match result {
-        Ok(mut searcher) => {
+        Ok((mut searcher, exit)) => {
             searcher.flush()?;
+            // Housekeeping only: a failure here loses nothing
+            if let Err(e) = searcher.optimize(vacuum_on_exit) {
+                warn!("Database optimize failed: {}", e);
+            }
+            if let Some(path) = &args.handoff_file {
+                init::write_handoff(path, init::handoff_command(args.handoff, &exit))?;
+            }
             Ok(())
         }
         Err(e) => {
@@ -58,3 +201,174 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
     }
 }
+
+/// Ask on the terminal before `--rebuild` replaces the database. Without a
+/// terminal to ask on, refuse rather than delete unasked.
+fn confirm_rebuild(db_path: &std::path::Path) -> Result<bool, Box<dyn std::error::Error>> {
+    use std::io::{IsTerminal, Write};
+
+    if !std::io::stdin().is_terminal() {
+        return Err(format!(
+            "--rebuild would replace {}; pass --yes to confirm when not running in a terminal",
+            db_path.display()
+        )
+        .into());
+    }
+    eprint!(
+        "Replace {} with a fresh index from shell history? Commands only mux recorded \
+         won't be in it; the old database is kept as a .bak file. [y/N] ",
+        db_path.display()
+    );
+    std::io::stderr().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+/// `--doctor`: report on the database's health; an error exit when
+/// something is wrong with it
+fn print_doctor_report(db_path: &std::path::Path) -> Result<(), Box<dyn std::error::Error>> {
+    println!("database:       {}", db_path.display());
+    if !db_path.exists() {
+        println!("not created yet; mux creates it on the first run");
+        return Ok(());
+    }
+    let diagnosis = HistorySearcher::diagnose(db_path)
+        .map_err(|e| format!("{} (mux --rebuild recreates it from shell history)", e))?;
+
+    let kib = |bytes: u64| bytes.div_ceil(1024);
+    println!("schema version: {}", diagnosis.schema_version);
+    println!("journal mode:   {}", diagnosis.journal_mode);
+    println!("size:           {} KiB ({} KiB free)", kib(diagnosis.size), kib(diagnosis.free));
+    println!("rows:");
+    for (table, count) in &diagnosis.row_counts {
+        println!("  {:<22}{}", table, count);
+    }
+
+    if diagnosis.problems.is_empty() {
+        println!("no problems found");
+        return Ok(());
+    }
+    println!();
+    for problem in &diagnosis.problems {
+        println!("problem: {}", problem);
+    }
+    Err(format!(
+        "{} problems found; mux --rebuild recreates the database from shell history",
+        diagnosis.problems.len()
+    )
+    .into())
+}
+
+/// `--query`: print the top matches from the history database, for scripts
+fn print_query_results(
+    searcher: &mut HistorySearcher,
+    query: &str,
+    limit: usize,
+    json: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let results = searcher.search(query, limit);
+    if json {
+        println!("{}", serde_json::to_string(&results)?);
+    } else {
+        for result in results {
+            println!("{}", result.command);
+        }
+    }
+    Ok(())
+}
+
+/// `mux history list|grep|delete`: commands go to stdout, one per line, and
+/// delete's count to stderr
+fn run_history_command(
+    searcher: &mut HistorySearcher,
+    action: &args::HistoryAction,
+    read_only: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let print = |commands: &[String]| commands.iter().for_each(|command| println!("{}", command));
+    let matching = |searcher: &HistorySearcher, pattern: &str| -> Vec<String> {
+        searcher
+            .commands()
+            .filter(|c| c.command.contains(pattern))
+            .map(|c| c.command.clone())
+            .collect()
+    };
+
+    match action {
+        args::HistoryAction::List => print(&matching(searcher, "")),
+        args::HistoryAction::Grep { pattern } => print(&matching(searcher, pattern)),
+        args::HistoryAction::Delete { pattern, dry_run: true } => {
+            let doomed = matching(searcher, pattern);
+            print(&doomed);
+            eprintln!("Would delete {} commands", doomed.len());
+        }
+        args::HistoryAction::Delete { pattern, dry_run: false } => {
+            if read_only {
+                return Err("history delete can't be used with --read-only or --no-persist".into());
+            }
+            let deleted = searcher.delete_matching(|command| command.contains(pattern.as_str()))?;
+            print(&deleted);
+            eprintln!("Deleted {} commands", deleted.len());
+        }
+    }
+    Ok(())
+}
+
+/// Commands need at least this many recorded runs to show up in `--stats`
+const STATS_MIN_RUNS: u32 = 3;
+const STATS_LIMIT: usize = 20;
+
+/// `--stats`: list the commands with the highest failure rates
+fn print_flaky_commands(searcher: &HistorySearcher) -> Result<(), Box<dyn std::error::Error>> {
+    let flaky = searcher.flaky_commands(STATS_MIN_RUNS, STATS_LIMIT)?;
+    if flaky.is_empty() {
+        println!("No failing commands with at least {} recorded runs", STATS_MIN_RUNS);
+        return Ok(());
+    }
+
+    println!("{:>6}  {:>5}  command", "failed", "runs");
+    for outcomes in flaky {
+        println!(
+            "{:>5.0}%  {:>5}  {}",
+            outcomes.failure_rate() * 100.0,
+            outcomes.success_count + outcomes.fail_count,
+            outcomes.command
+        );
+    }
+    Ok(())
+}
+
+/// `--stats`: the commands that take longest on average
+fn print_slowest_commands(searcher: &HistorySearcher) -> Result<(), Box<dyn std::error::Error>> {
+    let slowest = searcher.slowest_commands(STATS_LIMIT)?;
+    if slowest.is_empty() {
+        return Ok(());
+    }
+
+    println!();
+    println!("{:>6}  {:>5}  command", "mean", "runs");
+    for timing in slowest {
+        println!("{:>6}  {:>5}  {}", time::format_duration(timing.mean), timing.runs, timing.command);
+    }
+    Ok(())
+}
+
+/// `--stats`: the most used commands, with their runs per shell
+fn print_usage_by_shell(searcher: &HistorySearcher) -> Result<(), Box<dyn std::error::Error>> {
+    let usage = searcher.usage_by_shell(STATS_LIMIT)?;
+    if usage.is_empty() {
+        return Ok(());
+    }
+
+    println!();
+    println!("{:>6}  {:<40}  by shell", "runs", "command");
+    for command in usage {
+        let shells: Vec<String> = command
+            .shells
+            .iter()
+            .map(|(shell, count)| format!("{} {}", shell, count))
+            .collect();
+        println!("{:>6}  {:<40}  {}", command.frequency, command.command, shells.join(", "));
+    }
+    Ok(())
+}
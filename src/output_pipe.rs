@@ -0,0 +1,240 @@
+//! `[logging] output_pipe`: a long-lived program started with mux that
+//! receives every line of task output on stdin, e.g. `logger -t mux` to
+//! send the session to syslog.
+//!
+//! Lines are plain text, prefixed with the task's label for parallel runs
+//! (`[n=1] up 3 days`); a task's retry notices and final status line are
+//! sent too, but not the `started` status. Writing
+//! happens on a background thread behind a bounded queue, so a slow reader
+//! loses lines instead of slowing down the TUI. If the program exits or stops
+//! reading, piping stops and the TUI carries on.
+
+use std::io::{self, Write};
+use std::process::{Child, ChildStdin, Command, ExitStatus, Stdio};
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::runner::{OutputMessage, StreamType};
+
+/// Lines queued for the writer thread before new ones are dropped
+const QUEUE_CAPACITY: usize = 4096;
+
+/// How long the program gets to exit after its stdin closes, before it's killed
+const EXIT_GRACE: Duration = Duration::from_secs(1);
+
+/// How long the writer thread waits for a program it can't write to to be
+/// reaped, to tell an exit from a closed stdin
+const REAP_WAIT: Duration = Duration::from_millis(100);
+
+/// Why the writer thread stopped
+struct Stopped {
+    error: io::Error,
+    /// The program's exit status, if it had exited
+    status: Option<ExitStatus>,
+}
+
+/// The running program and the queue feeding its stdin
+pub struct OutputPipe {
+    command: String,
+    child: Arc<Mutex<Child>>,
+    tx: Option<SyncSender<String>>,
+    /// Set by the writer thread before it stops, if a write failed
+    stopped: Arc<Mutex<Option<Stopped>>>,
+    dropped: usize,
+}
+
+impl OutputPipe {
+    /// Start `command`, split into words as a shell would (without running
+    /// one). Its stdout and stderr are discarded so it can't draw over the TUI.
+    pub fn spawn(command: &str) -> io::Result<Self> {
+        let argv = shell_words::split(command)
+            .ok()
+            .filter(|argv| !argv.is_empty())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("can't parse `{}`", command)))?;
+        let mut child = Command::new(&argv[0])
+            .args(&argv[1..])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| io::Error::new(e.kind(), format!("could not start `{}`: {}", command, e)))?;
+        let stdin = child.stdin.take().expect("stdin is piped");
+
+        let child = Arc::new(Mutex::new(child));
+        let stopped = Arc::new(Mutex::new(None));
+        let tx = Self::spawn_writer(stdin, Arc::clone(&child), Arc::clone(&stopped), QUEUE_CAPACITY);
+        Ok(Self {
+            command: command.to_string(),
+            child,
+            tx: Some(tx),
+            stopped,
+            dropped: 0,
+        })
+    }
+
+    fn spawn_writer(
+        mut stdin: ChildStdin,
+        child: Arc<Mutex<Child>>,
+        stopped: Arc<Mutex<Option<Stopped>>>,
+        capacity: usize,
+    ) -> SyncSender<String> {
+        let (tx, rx) = mpsc::sync_channel::<String>(capacity);
+        std::thread::spawn(move || {
+            for line in rx {
+                if let Err(error) = writeln!(stdin, "{}", line).and_then(|_| stdin.flush()) {
+                    // A dead program is noticed here before it's reaped;
+                    // wait for that on this thread rather than the UI's
+                    let status = wait_for(&mut lock(&child), REAP_WAIT);
+                    *lock(&stopped) = Some(Stopped { error, status });
+                    break;
+                }
+            }
+            // Dropping stdin tells the program the session is over
+        });
+        tx
+    }
+
+    /// Queue the lines of `msg` without blocking. Returns why piping has
+    /// stopped once the program is gone; nothing more is sent after that.
+    pub fn send(&mut self, msg: &OutputMessage) -> Result<(), String> {
+        let Some(tx) = &self.tx else {
+            return Ok(());
+        };
+        if msg.stream == StreamType::Status && msg.content == "started" {
+            return Ok(());
+        }
+        for line in message_lines(msg) {
+            match tx.try_send(line) {
+                Ok(()) => {}
+                Err(TrySendError::Full(_)) => {
+                    if self.dropped == 0 {
+                        log::warn!("Output pipe `{}` is falling behind; dropping lines", self.command);
+                    }
+                    self.dropped += 1;
+                }
+                Err(TrySendError::Disconnected(_)) => {
+                    self.tx = None;
+                    return Err(self.failure());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Describe why the writer thread stopped. It has recorded that by the
+    /// time its queue disconnects, so this doesn't wait.
+    fn failure(&mut self) -> String {
+        match lock(&self.stopped).take() {
+            Some(Stopped { status: Some(status), .. }) => format!("`{}` {}", self.command, status),
+            Some(Stopped { error, status: None }) => format!("writing to `{}` failed: {}", self.command, error),
+            None => format!("`{}` stopped reading", self.command),
+        }
+    }
+
+    /// Close the program's stdin once the queued lines are written, and give
+    /// it `EXIT_GRACE` to finish before killing it
+    pub fn close(mut self) {
+        drop(self.tx.take());
+        let mut child = lock(&self.child);
+        if wait_for(&mut child, EXIT_GRACE).is_none() {
+            log::warn!("Output pipe `{}` didn't exit; killing it", self.command);
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+        if self.dropped > 0 {
+            log::info!("Output pipe dropped {} lines", self.dropped);
+        }
+    }
+}
+
+/// Lock `mutex`, even if a thread panicked holding it
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(std::sync::PoisonError::into_inner)
+}
+
+/// The child's exit status, if it exits within `timeout`
+fn wait_for(child: &mut Child, timeout: Duration) -> Option<ExitStatus> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) => return Some(status),
+            Ok(None) if Instant::now() < deadline => std::thread::sleep(Duration::from_millis(10)),
+            _ => return None,
+        }
+    }
+}
+
+/// The lines of `msg` as sent down the pipe: without ANSI codes, after the
+/// task's label if it has one
+fn message_lines(msg: &OutputMessage) -> Vec<String> {
+    msg.content
+        .lines()
+        .map(|line| {
+            let line = crate::pager::plain_text(line);
+            if msg.runner_label.is_empty() {
+                line
+            } else {
+                format!("{} {}", msg.runner_label, line)
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    /// Poll `path` until it holds `lines` lines or a second has passed
+    fn read_lines(path: &Path, lines: usize) -> Vec<String> {
+        let deadline = Instant::now() + Duration::from_secs(1);
+        loop {
+            let content = std::fs::read_to_string(path).unwrap_or_default();
+            if content.lines().count() >= lines || Instant::now() > deadline {
+                return content.lines().map(str::to_string).collect();
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn test_pipes_lines_to_the_program() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("session.log");
+        let command = format!("sh -c 'cat > \"$0\"' {}", path.display());
+        let mut pipe = OutputPipe::spawn(&command).unwrap();
+
+        pipe.send(&OutputMessage::status(1, "", "started")).unwrap();
+        pipe.send(&OutputMessage::output(1, "", "\x1b[31mred\x1b[0m".to_string())).unwrap();
+        pipe.send(&OutputMessage::output(2, "[n=1]", "up 3 days".to_string())).unwrap();
+        pipe.send(&OutputMessage::status(2, "[n=1]", "completed")).unwrap();
+        pipe.close();
+        assert_eq!(read_lines(&path, 3), ["red", "[n=1] up 3 days", "[n=1] completed"]);
+    }
+
+    #[test]
+    fn test_program_exiting_stops_piping() {
+        let mut pipe = OutputPipe::spawn("sh -c 'exit 3'").unwrap();
+        let line = OutputMessage::output(1, "", "x".to_string());
+        let deadline = Instant::now() + Duration::from_secs(2);
+        let reason = loop {
+            match pipe.send(&line) {
+                Err(reason) => break reason,
+                Ok(()) if Instant::now() < deadline => std::thread::sleep(Duration::from_millis(10)),
+                Ok(()) => panic!("piping didn't stop"),
+            }
+        };
+        assert!(reason.contains("exit status: 3"), "{}", reason);
+        // Nothing more is sent, and that's not an error
+        assert!(pipe.send(&line).is_ok());
+        pipe.close();
+    }
+
+    #[test]
+    fn test_bad_command() {
+        assert!(OutputPipe::spawn("'unclosed").is_err());
+        let err = OutputPipe::spawn("mux-no-such-program").err().unwrap();
+        assert!(err.to_string().contains("could not start `mux-no-such-program`"));
+    }
+}
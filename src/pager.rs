@@ -0,0 +1,287 @@
+//! Full-screen pager over a snapshot of the session output.
+//!
+//! Navigation follows `less`/vim: j/k scroll a line, g/G jump to the ends,
+//! `/` searches (case-insensitive substring), n/N go to the next/previous match.
+//! The snapshot is taken when the pager opens, so new output doesn't move it.
+//! `mux --tail` instead feeds lines in as they arrive (see `push_line`).
+
+use ansi_to_tui::IntoText;
+
+pub struct Pager {
+    /// Shown in the title, e.g. "Output" or the file being tailed
+    title: String,
+    /// Plain-text lines (ANSI styling removed)
+    lines: Vec<String>,
+    /// Index of the first visible line
+    top: usize,
+    /// Visible rows, updated by the renderer
+    height: usize,
+    /// Last committed search, lowercased
+    query: Option<String>,
+    /// Search being typed after `/`
+    search_input: Option<String>,
+    /// One-shot message for the status line, e.g. "Pattern not found"
+    message: Option<String>,
+}
+
+impl Pager {
+    /// Open a pager positioned at the end of `lines`
+    pub fn new(lines: Vec<String>) -> Self {
+        Self {
+            title: "Output".to_string(),
+            lines,
+            top: usize::MAX,
+            height: 1,
+            query: None,
+            search_input: None,
+            message: None,
+        }
+    }
+
+    /// An empty pager titled `title`, for lines added with `push_line`
+    pub fn following(title: String) -> Self {
+        Self {
+            title,
+            ..Self::new(Vec::new())
+        }
+    }
+
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    /// Append a line. A view at the bottom stays there, like `tail -f`;
+    /// one scrolled up is left where it is.
+    pub fn push_line(&mut self, line: String) {
+        let following = self.top >= self.max_top();
+        self.lines.push(line);
+        if following {
+            self.top = usize::MAX;
+        }
+    }
+
+    pub fn set_height(&mut self, height: usize) {
+        self.height = height.max(1);
+        self.top = self.top.min(self.max_top());
+    }
+
+    fn max_top(&self) -> usize {
+        self.lines.len().saturating_sub(self.height)
+    }
+
+    /// Lines currently on screen
+    pub fn visible(&self) -> &[String] {
+        let start = self.top.min(self.max_top());
+        let end = (start + self.height).min(self.lines.len());
+        &self.lines[start..end]
+    }
+
+    /// (first visible line, last visible line, total), 1-based for display
+    pub fn position(&self) -> (usize, usize, usize) {
+        let start = self.top.min(self.max_top());
+        let shown = self.visible().len();
+        (start + 1, start + shown, self.lines.len())
+    }
+
+    /// Whether `line` contains the committed search
+    pub fn is_match(&self, line: &str) -> bool {
+        self.query
+            .as_deref()
+            .is_some_and(|q| line.to_lowercase().contains(q))
+    }
+
+    pub fn scroll_down(&mut self, n: usize) {
+        self.top = self.top.min(self.max_top()).saturating_add(n).min(self.max_top());
+    }
+
+    pub fn scroll_up(&mut self, n: usize) {
+        self.top = self.top.min(self.max_top()).saturating_sub(n);
+    }
+
+    pub fn page_down(&mut self) {
+        self.scroll_down(self.height);
+    }
+
+    pub fn page_up(&mut self) {
+        self.scroll_up(self.height);
+    }
+
+    pub fn jump_to_top(&mut self) {
+        self.top = 0;
+    }
+
+    pub fn jump_to_bottom(&mut self) {
+        self.top = self.max_top();
+    }
+
+    // --- Search ---
+
+    /// The search being typed, if the `/` prompt is open
+    pub fn search_input(&self) -> Option<&str> {
+        self.search_input.as_deref()
+    }
+
+    pub fn message(&self) -> Option<&str> {
+        self.message.as_deref()
+    }
+
+    pub fn start_search(&mut self) {
+        self.search_input = Some(String::new());
+        self.message = None;
+    }
+
+    pub fn push_search_char(&mut self, c: char) {
+        if let Some(input) = &mut self.search_input {
+            input.push(c);
+        }
+    }
+
+    pub fn pop_search_char(&mut self) {
+        if let Some(input) = &mut self.search_input {
+            input.pop();
+        }
+    }
+
+    pub fn cancel_search(&mut self) {
+        self.search_input = None;
+    }
+
+    /// Close the prompt and jump to the first match after the top line.
+    /// An empty search repeats the previous one, as in `less`.
+    pub fn commit_search(&mut self) {
+        let Some(input) = self.search_input.take() else {
+            return;
+        };
+        if !input.is_empty() {
+            self.query = Some(input.to_lowercase());
+        }
+        self.next_match();
+    }
+
+    /// Scroll the next matching line (below the top line) to the top
+    pub fn next_match(&mut self) {
+        let start = self.top.min(self.max_top()) + 1;
+        let found = (start..self.lines.len()).find(|&i| self.is_match(&self.lines[i]));
+        self.jump_to(found);
+    }
+
+    /// Scroll the previous matching line (above the top line) to the top
+    pub fn prev_match(&mut self) {
+        let end = self.top.min(self.max_top());
+        let found = (0..end).rev().find(|&i| self.is_match(&self.lines[i]));
+        self.jump_to(found);
+    }
+
+    fn jump_to(&mut self, line: Option<usize>) {
+        self.message = None;
+        match (line, &self.query) {
+            (Some(line), _) => self.top = line,
+            (None, Some(_)) => self.message = Some("Pattern not found".to_string()),
+            (None, None) => {}
+        }
+    }
+}
+
+/// Text of a line with ANSI escape sequences removed
+pub fn plain_text(content: &str) -> String {
+    match crate::hyperlink::strip_links(content).as_bytes().into_text() {
+        Ok(text) => text
+            .lines
+            .iter()
+            .map(|line| line.spans.iter().map(|s| s.content.as_ref()).collect::<String>())
+            .collect::<Vec<_>>()
+            .join(" "),
+        Err(_) => content.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn numbered(n: usize, height: usize) -> Pager {
+        let mut pager = Pager::new((0..n).map(|i| format!("line {}", i)).collect());
+        pager.set_height(height);
+        pager
+    }
+
+    #[test]
+    fn test_opens_at_bottom() {
+        let pager = numbered(10, 4);
+        assert_eq!(pager.position(), (7, 10, 10));
+        assert_eq!(pager.visible().last().unwrap(), "line 9");
+    }
+
+    #[test]
+    fn test_navigation_is_clamped() {
+        let mut pager = numbered(10, 4);
+        pager.scroll_down(5);
+        assert_eq!(pager.position().0, 7);
+        pager.jump_to_top();
+        pager.scroll_up(1);
+        assert_eq!(pager.position().0, 1);
+        pager.page_down();
+        assert_eq!(pager.position().0, 5);
+        pager.jump_to_bottom();
+        assert_eq!(pager.position().0, 7);
+
+        // Fewer lines than rows
+        let pager = numbered(2, 4);
+        assert_eq!(pager.position(), (1, 2, 2));
+    }
+
+    #[test]
+    fn test_push_line_follows_the_bottom() {
+        let mut pager = Pager::following("app.log".to_string());
+        pager.set_height(2);
+        for i in 0..4 {
+            pager.push_line(format!("line {}", i));
+        }
+        assert_eq!(pager.position(), (3, 4, 4));
+        assert_eq!(pager.title(), "app.log");
+
+        // Scrolled up: new lines don't move the view
+        pager.scroll_up(2);
+        pager.push_line("line 4".to_string());
+        assert_eq!(pager.position(), (1, 2, 5));
+
+        pager.jump_to_bottom();
+        pager.push_line("line 5".to_string());
+        assert_eq!(pager.visible().last().unwrap(), "line 5");
+    }
+
+    #[test]
+    fn test_search_next_and_prev() {
+        let mut pager = Pager::new(
+            ["start", "ERROR one", "ok", "ok", "error two", "ok", "ok", "end"]
+                .map(String::from)
+                .to_vec(),
+        );
+        pager.set_height(2);
+        pager.jump_to_top();
+
+        pager.start_search();
+        for c in "error".chars() {
+            pager.push_search_char(c);
+        }
+        pager.commit_search();
+        assert_eq!(pager.visible()[0], "ERROR one");
+
+        pager.next_match();
+        assert_eq!(pager.visible()[0], "error two");
+
+        pager.next_match();
+        assert_eq!(pager.message(), Some("Pattern not found"));
+        assert_eq!(pager.visible()[0], "error two");
+
+        pager.prev_match();
+        assert_eq!(pager.visible()[0], "ERROR one");
+        assert!(pager.is_match("an Error"));
+    }
+
+    #[test]
+    fn test_plain_text_strips_ansi() {
+        assert_eq!(plain_text("\x1b[31mred\x1b[0m text"), "red text");
+        assert_eq!(plain_text("plain"), "plain");
+    }
+}
@@ -1,19 +1,45 @@
-/// Parallel command expansion.
-///
-/// Syntax:
-///   [name=range] ...more prefixes... command with {name} placeholders
-///
-/// Range types:
-///   [shard=1-64]         → numeric: "1", "2", ..., "64"
-///   [shard=01-64]        → zero-padded: "01", "02", ..., "64"
-///   [region=east,west]   → list: "east", "west"
-///
-/// Combination modes:
-///   Separate [...] blocks → cross product
-///     [shard=1-3] [region=a,b] cmd     → 6 commands (1,a), (1,b), (2,a), ...
-///
-///   Space-separated names in one [...] → zip (must be same length)
-///     [shard=1-3 region=a,b,c] cmd     → 3 commands (1,a), (2,b), (3,c)
+//! Parallel command expansion.
+//!
+//! Syntax:
+//!   [name=range] ...more prefixes... command with {name} placeholders
+//!
+//! Range types:
+//!   [shard=1-64]         → numeric: "1", "2", ..., "64"
+//!   [shard=01-64]        → zero-padded: "01", "02", ..., "64"
+//!   [region=east,west]   → list: "east", "west"
+//!   [n=1,3,5-8]          → list items may be ranges: "1", "3", "5", ..., "8"
+//!   [port=8000-8000+7]   → endpoints may use `+ - * /` on integers
+//!
+//! Combination modes:
+//!   Separate [...] blocks → cross product
+//!     [shard=1-3] [region=a,b] cmd     → 6 commands (1,a), (1,b), (2,a), ...
+//!
+//!   Space-separated names in one [...] → zip (must be same length)
+//!     [shard=1-3 region=a,b,c] cmd     → 3 commands (1,a), (2,b), (3,c)
+//!
+//! Placement:
+//!   Blocks may appear anywhere in the input, e.g. after env assignments:
+//!     ENV=prod [shard=1-3] deploy {shard}
+//!   A block must be space-delimited: `[` starts a token and the closing `]`
+//!   ends one, outside quotes and escapes. Anything else (`[ -f x ]`,
+//!   `arr[0]`, `git commit -m "fix [n=1-3] it"`, `\[n=1]`) is left in the
+//!   template as-is.
+//!
+//! Directives:
+//!   [retry=3] cmd        → re-run cmd up to 3 more times while it fails
+//!   A `retry` block holding a single plain integer configures the run rather
+//!   than expanding anything; it can be combined with parameter blocks
+//!   (`[host=a,b] [retry=2] ssh {host} uptime` retries each host on its own).
+//!   `[retry=1-3]` is still an ordinary parameter.
+//!   [fail-fast] cmd      → once a task of the batch fails, the tasks still
+//!   queued are cancelled; tasks already running finish.
+//!
+//! Errors:
+//!   A block starting with `name=` is meant as parallel syntax; if it doesn't
+//!   parse (bad range, zip length mismatch, ...) `parse_parallel` returns a
+//!   [`ParallelError`] rather than running the input verbatim.
+
+use std::fmt;
 
 /// A single named parameter with its expanded values
 #[derive(Debug, Clone)]
@@ -36,6 +62,10 @@ pub struct ParsedParallel {
     pub groups: Vec<ParamGroup>,
     /// The command template with {name} placeholders
     pub template: String,
+    /// How often a failing command is re-run, from a `[retry=N]` block
+    pub retries: u32,
+    /// Cancel the batch's queued tasks once one fails, from a `[fail-fast]` block
+    pub fail_fast: bool,
 }
 
 /// A single expanded command with its parameter assignments
@@ -47,61 +77,194 @@ pub struct ExpandedCommand {
     pub label: String,
 }
 
+/// Why input that looks like parallel syntax couldn't be parsed
+#[derive(Debug, PartialEq)]
+pub enum ParallelError {
+    /// A block entry that isn't `name=range`, e.g. `oops` in `[n=1-3 oops]`
+    BadParam(String),
+    /// A range that doesn't parse, e.g. `5-1` or `1-x`
+    BadRange { name: String, range: String },
+    /// Zipped params in one block have different lengths: (name, length) pairs
+    ZipLengthMismatch(Vec<(String, usize)>),
+    /// Parameter blocks but no command to run
+    NoCommand,
+}
+
+impl fmt::Display for ParallelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BadParam(param) => write!(f, "expected name=range, got {:?}", param),
+            Self::BadRange { name, range } => write!(f, "invalid range for {}: {:?}", name, range),
+            Self::ZipLengthMismatch(lengths) => {
+                let lengths: Vec<String> = lengths
+                    .iter()
+                    .map(|(name, len)| format!("{}={}", name, len))
+                    .collect();
+                write!(f, "zip lengths differ: {}", lengths.join(", "))
+            }
+            Self::NoCommand => write!(f, "no command after the parallel block"),
+        }
+    }
+}
+
+impl std::error::Error for ParallelError {}
+
+/// Whether `s` can name a parameter: letters, digits and `_`, not starting with a digit
+fn is_param_name(s: &str) -> bool {
+    s.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
 /// Parse a range string into a list of values.
 /// "1-64" → ["1", "2", ..., "64"]
 /// "01-64" → ["01", "02", ..., "64"] (zero-padded)
 /// "east,west" → ["east", "west"]
+/// "1,3,5-7" → ["1", "3", "5", "6", "7"] (list items may be ranges)
+/// "8000-8000+3" → ["8000", ..., "8003"] (endpoints are evaluated, see `eval_arithmetic`)
 fn parse_range(range: &str) -> Option<Vec<String>> {
-    // Check for comma-separated list first
+    // Comma-separated list: items that read as a numeric range are expanded,
+    // anything else ("web-1", "east") is kept as written
     if range.contains(',') {
-        return Some(range.split(',').map(|s| s.trim().to_string()).collect());
+        return Some(
+            range
+                .split(',')
+                .map(str::trim)
+                .flat_map(|item| expand_numeric_range(item).unwrap_or_else(|| vec![item.to_string()]))
+                .collect(),
+        );
+    }
+
+    // A single value: a numeric range, or kept as written ("web-1",
+    // "2024-01-02"). Arithmetic that isn't a valid range ("5-1", "1-10/0")
+    // is an error rather than a literal.
+    if let Some(values) = expand_numeric_range(range) {
+        return Some(values);
+    }
+    let arithmetic = range.bytes().all(|b| b.is_ascii_digit() || b"+-*/".contains(&b));
+    let dashes = range.matches('-').count();
+    let dashed = range.bytes().all(|b| b.is_ascii_digit() || b == b'-') && dashes > 1;
+    if arithmetic && dashes > 0 && !dashed {
+        return None;
     }
+    Some(vec![range.to_string()])
+}
 
-    // Check for numeric range: n-m
-    if let Some((start_str, end_str)) = range.split_once('-') {
-        let start: i64 = start_str.parse().ok()?;
-        let end: i64 = end_str.parse().ok()?;
+/// Expand a numeric range "n-m", zero-padded if `n` has leading zeros.
+/// None if it isn't one.
+fn expand_numeric_range(range: &str) -> Option<Vec<String>> {
+    let (start_str, end_str) = range.split_once('-')?;
+    let start = eval_arithmetic(start_str)?;
+    let end = eval_arithmetic(end_str)?;
 
-        if start > end {
-            return None;
-        }
+    if start > end {
+        return None;
+    }
 
-        // Detect zero-padding: if the start string has leading zeros
-        let pad_width = if start_str.len() > 1 && start_str.starts_with('0') {
-            // Pad to the width of the longer of start/end
-            start_str.len().max(end_str.len())
+    // Detect zero-padding: if the start string has leading zeros
+    let pad_width = if start_str.len() > 1 && start_str.starts_with('0') {
+        // Pad to the width of the longer of start/end
+        let end_width = if end_str.bytes().all(|b| b.is_ascii_digit()) {
+            end_str.len()
         } else {
-            0
+            end.to_string().len()
         };
+        start_str.len().max(end_width)
+    } else {
+        0
+    };
 
-        let values: Vec<String> = (start..=end)
-            .map(|n| {
-                if pad_width > 0 {
-                    format!("{:0>width$}", n, width = pad_width)
-                } else {
-                    n.to_string()
-                }
-            })
-            .collect();
+    let values = (start..=end)
+        .map(|n| {
+            if pad_width > 0 {
+                format!("{:0>width$}", n, width = pad_width)
+            } else {
+                n.to_string()
+            }
+        })
+        .collect();
 
-        return Some(values);
+    Some(values)
+}
+
+/// Evaluate integer literals joined by `+ - * /` with the usual precedence,
+/// e.g. "8000+7" or "10*2". No parentheses, signs or spaces. Returns None if
+/// the expression is malformed, divides by zero or overflows.
+fn eval_arithmetic(expr: &str) -> Option<i64> {
+    fn take_number(rest: &mut &str) -> Option<i64> {
+        let end = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+        if end == 0 {
+            return None;
+        }
+        let (digits, tail) = rest.split_at(end);
+        *rest = tail;
+        digits.parse().ok()
     }
 
-    // Single value
-    Some(vec![range.to_string()])
+    let mut rest = expr;
+    // Sum of the finished terms, and the product being built
+    let mut sum: i64 = 0;
+    let mut negative = false;
+    let mut product = take_number(&mut rest)?;
+
+    while let Some(op) = rest.chars().next() {
+        rest = &rest[op.len_utf8()..];
+        let n = take_number(&mut rest)?;
+        match op {
+            '*' => product = product.checked_mul(n)?,
+            '/' => product = product.checked_div(n)?,
+            '+' | '-' => {
+                sum = if negative { sum.checked_sub(product)? } else { sum.checked_add(product)? };
+                negative = op == '-';
+                product = n;
+            }
+            _ => return None,
+        }
+    }
+    if negative { sum.checked_sub(product) } else { sum.checked_add(product) }
+}
+
+/// Directive block that stops a batch from starting more tasks after a failure
+const FAIL_FAST_BLOCK: &str = "[fail-fast]";
+
+/// The count of a `[retry=N]` directive block, or None for any other block
+fn parse_retry_block(block: &str) -> Option<u32> {
+    let count = block.strip_prefix("[retry=")?.strip_suffix(']')?;
+    if count.is_empty() || !count.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    count.parse().ok()
 }
 
 /// Parse a single [...] block into a ParamGroup.
 /// "[shard=1-3]" → ParamGroup with one param
 /// "[shard=1-3 region=a,b,c]" → ParamGroup with two zipped params
-fn parse_bracket_block(block: &str) -> Option<ParamGroup> {
-    let inner = block.strip_prefix('[')?.strip_suffix(']')?;
-    let mut params = Vec::new();
+/// Returns Ok(None) if the block isn't parameter syntax at all (`[ -f x ]`).
+fn parse_bracket_block(block: &str) -> Result<Option<ParamGroup>, ParallelError> {
+    let Some(inner) = block.strip_prefix('[').and_then(|b| b.strip_suffix(']')) else {
+        return Ok(None);
+    };
+
+    // Only a block opening with `name=` is meant as parameters
+    let is_param_block = inner
+        .split_whitespace()
+        .next()
+        .and_then(|part| part.split_once('='))
+        .is_some_and(|(name, _)| is_param_name(name));
+    if !is_param_block {
+        return Ok(None);
+    }
 
     // Split on whitespace for multiple params (zip mode)
+    let mut params = Vec::new();
     for part in inner.split_whitespace() {
-        let (name, range) = part.split_once('=')?;
-        let values = parse_range(range)?;
+        let (name, range) = part
+            .split_once('=')
+            .filter(|(name, _)| is_param_name(name))
+            .ok_or_else(|| ParallelError::BadParam(part.to_string()))?;
+        let values = parse_range(range).ok_or_else(|| ParallelError::BadRange {
+            name: name.to_string(),
+            range: range.to_string(),
+        })?;
         params.push(ParamDef {
             name: name.to_string(),
             values,
@@ -109,53 +272,125 @@ fn parse_bracket_block(block: &str) -> Option<ParamGroup> {
     }
 
     // Validate zip: all params in the same group must have the same length
-    if params.len() > 1 {
-        let len = params[0].values.len();
-        if params.iter().any(|p| p.values.len() != len) {
-            return None; // mismatched lengths
-        }
+    let len = params[0].values.len();
+    if params.iter().any(|p| p.values.len() != len) {
+        return Err(ParallelError::ZipLengthMismatch(
+            params.iter().map(|p| (p.name.clone(), p.values.len())).collect(),
+        ));
     }
 
-    if params.is_empty() {
-        return None;
-    }
-
-    Some(ParamGroup { params })
+    Ok(Some(ParamGroup { params }))
 }
 
 /// Parse a full input string for parallel expansion.
-/// Returns None if the input has no [...] prefixes (normal command).
-pub fn parse_parallel(input: &str) -> Option<ParsedParallel> {
+/// Bracket blocks are collected wherever they appear as space-delimited
+/// tokens outside quotes; everything else, in order, becomes the template.
+/// Returns Ok(None) if the input has no bracket blocks (normal command), and
+/// an error if a block is parameter syntax but malformed. Directive blocks
+/// alone give a result without groups: a single command, e.g. to retry.
+pub fn parse_parallel(input: &str) -> Result<Option<ParsedParallel>, ParallelError> {
     let trimmed = input.trim();
 
-    // Quick check: must start with '['
-    if !trimmed.starts_with('[') {
-        return None;
+    // Quick check: no '[' means no blocks
+    if !trimmed.contains('[') {
+        return Ok(None);
     }
 
-    let mut remaining = trimmed;
     let mut groups = Vec::new();
+    let mut retries = None;
+    let mut fail_fast = false;
+    let mut segments: Vec<&str> = Vec::new();
+    let mut segment_start = 0;
+    let mut quote = None;
+    let mut chars = trimmed.char_indices();
 
-    // Parse consecutive [...] blocks from the start
-    while remaining.starts_with('[') {
-        // Find the matching ']'
-        let close = remaining.find(']')?;
-        let block = &remaining[..=close];
+    while let Some((open, c)) = chars.next() {
+        // Only an unquoted, unescaped '[' past the last block can open one
+        let opens = match (quote, c) {
+            (Some('\''), '\'') | (Some('"'), '"') => {
+                quote = None;
+                false
+            }
+            (Some('\''), _) => false,
+            (_, '\\') => {
+                chars.next();
+                false
+            }
+            (Some(_), _) => false,
+            (None, '\'' | '"') => {
+                quote = Some(c);
+                false
+            }
+            (None, '[') => open >= segment_start,
+            _ => false,
+        };
+        if !opens {
+            continue;
+        }
+
+        // '[' must start a token
+        let at_token_start = trimmed[..open]
+            .chars()
+            .next_back()
+            .is_none_or(char::is_whitespace);
+        if !at_token_start {
+            continue;
+        }
+
+        // ... and the first ']' after it must end one
+        let Some(close) = trimmed[open..].find(']').map(|i| open + i) else {
+            break;
+        };
+        let at_token_end = trimmed[close + 1..]
+            .chars()
+            .next()
+            .is_none_or(char::is_whitespace);
+        if !at_token_end {
+            continue;
+        }
 
-        let group = parse_bracket_block(block)?;
-        groups.push(group);
+        let block = &trimmed[open..=close];
+        // A quote or escape inside makes it something else, e.g. `["a b"]`
+        if block.contains(['\'', '"', '\\']) {
+            continue;
+        }
+        if let Some(count) = parse_retry_block(block) {
+            retries = Some(count);
+        } else if block == FAIL_FAST_BLOCK {
+            fail_fast = true;
+        } else {
+            // Not a parameter block (e.g. `[ -f x ]`): leave it in the template
+            let Some(group) = parse_bracket_block(block)? else {
+                continue;
+            };
+            groups.push(group);
+        }
 
-        remaining = remaining[close + 1..].trim_start();
+        segments.push(&trimmed[segment_start..open]);
+        segment_start = close + 1;
     }
+    segments.push(&trimmed[segment_start..]);
 
-    if groups.is_empty() || remaining.is_empty() {
-        return None;
+    let template = segments
+        .iter()
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    if groups.is_empty() && retries.is_none() && !fail_fast {
+        return Ok(None);
+    }
+    if template.is_empty() {
+        return Err(ParallelError::NoCommand);
     }
 
-    Some(ParsedParallel {
+    Ok(Some(ParsedParallel {
         groups,
-        template: remaining.to_string(),
-    })
+        template,
+        retries: retries.unwrap_or(0),
+        fail_fast,
+    }))
 }
 
 /// Expand a ParsedParallel into a list of concrete commands.
@@ -196,25 +431,367 @@ pub fn expand(parsed: &ParsedParallel) -> Vec<ExpandedCommand> {
 
     // Substitute into template
     combinations
-        .into_iter()
-        .map(|assignments| {
-            let mut command = parsed.template.clone();
-            let mut label = String::new();
-
-            for (name, value) in &assignments {
-                command = command.replace(&format!("{{{}}}", name), value);
-                if parsed.groups.len() == 1 && parsed.groups[0].params.len() == 1 {
-                    command = command.replace("{}", value);
-                }
-                label.push_str(&format!("[{}={}]", name, value));
-            }
+        .iter()
+        .map(|assignments| substitute(parsed, assignments))
+        .collect()
+}
 
-            ExpandedCommand {
-                command,
-                label,
-            }
+/// How many commands `expand` produces, without expanding them
+pub fn count(parsed: &ParsedParallel) -> usize {
+    parsed
+        .groups
+        .iter()
+        .map(|g| g.params[0].values.len())
+        .fold(1, usize::saturating_mul)
+}
+
+/// The first command `expand` produces, without expanding the rest
+pub fn first(parsed: &ParsedParallel) -> Option<ExpandedCommand> {
+    let assignments = parsed
+        .groups
+        .iter()
+        .flat_map(|g| &g.params)
+        .map(|p| Some((p.name.clone(), p.values.first()?.clone())))
+        .collect::<Option<Vec<_>>>()?;
+    Some(substitute(parsed, &assignments))
+}
+
+/// Fill the template's placeholders with one value per parameter
+fn substitute(parsed: &ParsedParallel, assignments: &[(String, String)]) -> ExpandedCommand {
+    let mut command = parsed.template.clone();
+    let mut label = String::new();
+
+    for (name, value) in assignments {
+        command = command.replace(&format!("{{{}}}", name), value);
+        if parsed.groups.len() == 1 && parsed.groups[0].params.len() == 1 {
+            command = command.replace("{}", value);
+        }
+        label.push_str(&format!("[{}={}]", name, value));
+    }
+
+    ExpandedCommand { command, label }
+}
+
+// --- Explaining an expansion ---
+
+/// Expanded commands listed by `explain` from each end of the list
+const EXPLAIN_HEAD: usize = 3;
+const EXPLAIN_TAIL: usize = 2;
+/// Values listed per parameter before eliding the middle
+const EXPLAIN_VALUES: usize = 5;
+
+/// `a, b, c` or, for long lists, `1, 2, 3, …, 64`
+fn summarize_values(values: &[String]) -> String {
+    if values.len() <= EXPLAIN_VALUES {
+        return values.join(", ");
+    }
+    format!("{}, …, {}", values[..3].join(", "), values[values.len() - 1])
+}
+
+/// Describe how `parsed` expands, one line per entry: the template, what each
+/// group contributes, how groups combine, and a sample of the commands.
+pub fn explain(parsed: &ParsedParallel) -> Vec<String> {
+    let mut lines = vec![format!("template: {}", parsed.template)];
+
+    for (i, group) in parsed.groups.iter().enumerate() {
+        let rows = group.params[0].values.len();
+        let params: Vec<String> = group
+            .params
+            .iter()
+            .map(|p| format!("{} = {}", p.name, summarize_values(&p.values)))
+            .collect();
+        if params.len() == 1 {
+            lines.push(format!("group {}: {} ({} values)", i + 1, params[0], rows));
+        } else {
+            lines.push(format!("group {}: zips {} params, {} rows", i + 1, params.len(), rows));
+            lines.extend(params.iter().map(|p| format!("  {}", p)));
+        }
+    }
+
+    let sizes: Vec<usize> = parsed.groups.iter().map(|g| g.params[0].values.len()).collect();
+    let total = count(parsed);
+    lines.push(match sizes.len() {
+        0 => "no parameters: 1 command".to_string(),
+        1 => format!("1 group: {} commands", total),
+        n => {
+            let factors: Vec<String> = sizes.iter().map(usize::to_string).collect();
+            format!("cross product of {} groups: {} = {} commands", n, factors.join(" × "), total)
+        }
+    });
+
+    // Parameters the template never mentions only multiply the command count
+    let shorthand = parsed.groups.len() == 1 && parsed.groups[0].params.len() == 1;
+    for param in parsed.groups.iter().flat_map(|g| &g.params) {
+        let used = parsed.template.contains(&format!("{{{}}}", param.name))
+            || (shorthand && parsed.template.contains("{}"));
+        if !used {
+            lines.push(format!("note: {{{}}} isn't used in the template", param.name));
+        }
+    }
+    if parsed.retries > 0 {
+        lines.push(format!("each failing command is retried up to {} times", parsed.retries));
+    }
+    if parsed.fail_fast {
+        lines.push("after a failure, tasks that haven't started are cancelled".to_string());
+    }
+
+    let expanded = expand(parsed);
+    let describe = |cmd: &ExpandedCommand| {
+        if cmd.label.is_empty() {
+            format!("  {}", cmd.command)
+        } else {
+            format!("  {} {}", cmd.label, cmd.command)
+        }
+    };
+    lines.push("commands:".to_string());
+    if expanded.len() <= EXPLAIN_HEAD + EXPLAIN_TAIL {
+        lines.extend(expanded.iter().map(describe));
+    } else {
+        lines.extend(expanded[..EXPLAIN_HEAD].iter().map(describe));
+        lines.push(format!("  … {} more", expanded.len() - EXPLAIN_HEAD - EXPLAIN_TAIL));
+        lines.extend(expanded[expanded.len() - EXPLAIN_TAIL..].iter().map(describe));
+    }
+    lines
+}
+
+// --- Inferring bracket syntax from similar commands ---
+
+/// Parameter names used for inferred templates, in order of appearance
+const INFERRED_NAMES: [&str; 4] = ["x", "y", "z", "w"];
+
+/// Order numeric strings numerically, everything else lexically
+fn natural_cmp(a: &str, b: &str) -> std::cmp::Ordering {
+    match (a.parse::<i64>(), b.parse::<i64>()) {
+        (Ok(x), Ok(y)) => x.cmp(&y).then_with(|| a.cmp(b)),
+        _ => a.cmp(b),
+    }
+}
+
+/// Render a parameter's values as range syntax: "1-8" / "01-12" when they form
+/// a contiguous run that `parse_range` reproduces exactly, otherwise "a,b,c".
+/// Returns None if a value can't be written inside a bracket block.
+fn render_values(values: &[&str]) -> Option<String> {
+    if values
+        .iter()
+        .any(|v| v.is_empty() || v.contains([',', ']', '[', '=']) || v.contains(char::is_whitespace))
+    {
+        return None;
+    }
+    // A value like "1-3" would expand when read back as a list item
+    if values.iter().any(|v| expand_numeric_range(v).is_some()) {
+        return None;
+    }
+
+    if values.len() > 2 {
+        let range = format!("{}-{}", values[0], values[values.len() - 1]);
+        if parse_range(&range).is_some_and(|expanded| expanded == values) {
+            return Some(range);
+        }
+    }
+    Some(values.join(","))
+}
+
+/// The part of a token that varies across commands, with its shared prefix and
+/// suffix. The prefix/suffix only split at punctuation, so "host-1"/"host-12"
+/// varies in "1"/"12" and "east"/"west" varies as a whole.
+fn split_varying<'a>(tokens: &[&'a str]) -> (&'a str, Vec<&'a str>, &'a str) {
+    let first = tokens[0];
+    let shortest = tokens.iter().map(|t| t.len()).min().unwrap_or(0);
+
+    let mut prefix = first
+        .char_indices()
+        .find(|&(i, c)| tokens.iter().any(|t| !t[i..].starts_with(c)))
+        .map_or(shortest, |(i, _)| i)
+        .min(shortest);
+    while !first.is_char_boundary(prefix) {
+        prefix -= 1;
+    }
+    while let Some(c) = first[..prefix].chars().next_back()
+        && c.is_alphanumeric()
+    {
+        prefix -= c.len_utf8();
+    }
+
+    let max_suffix = shortest - prefix;
+    let mut suffix = 0;
+    while suffix < max_suffix {
+        let c = first.as_bytes()[first.len() - suffix - 1];
+        if tokens.iter().any(|t| t.as_bytes()[t.len() - suffix - 1] != c) {
+            break;
+        }
+        suffix += 1;
+    }
+    while suffix > 0 && !first.is_char_boundary(first.len() - suffix) {
+        suffix -= 1;
+    }
+    while let Some(c) = first[first.len() - suffix..].chars().next()
+        && c.is_alphanumeric()
+    {
+        suffix -= c.len_utf8();
+    }
+
+    let middles: Vec<&str> = tokens.iter().map(|t| &t[prefix..t.len() - suffix]).collect();
+    if middles.iter().any(|m| m.is_empty()) {
+        // e.g. "run" vs "run2": vary the whole token instead
+        return ("", tokens.to_vec(), "");
+    }
+    (&first[..prefix], middles, &first[first.len() - suffix..])
+}
+
+/// Propose a parallel command reproducing a set of similar commands.
+///
+/// Commands are split on whitespace; the largest group sharing a token count is
+/// used. Tokens that differ become `{x}`, `{y}`, ... placeholders. Independent
+/// parameters whose combinations cover the full cross product get separate
+/// blocks; otherwise parameters are zipped in one block.
+///
+/// e.g. "ssh host-1 uptime", "ssh host-2 uptime", "ssh host-3 uptime"
+///   → "[x=1-3] ssh host-{x} uptime"
+pub fn infer_parallel(commands: &[&str]) -> Option<String> {
+    let mut unique: Vec<Vec<&str>> = Vec::new();
+    for cmd in commands {
+        let tokens: Vec<&str> = cmd.split_whitespace().collect();
+        if !tokens.is_empty() && !unique.contains(&tokens) {
+            unique.push(tokens);
+        }
+    }
+
+    // Keep the most common shape
+    let mut by_len: std::collections::HashMap<usize, Vec<Vec<&str>>> = std::collections::HashMap::new();
+    for tokens in unique {
+        by_len.entry(tokens.len()).or_default().push(tokens);
+    }
+    let rows = by_len
+        .into_values()
+        .max_by_key(|group| (group.len(), std::cmp::Reverse(group[0].len())))?;
+    if rows.len() < 2 {
+        return None;
+    }
+
+    let width = rows[0].len();
+    let varying: Vec<usize> = (0..width)
+        .filter(|&i| rows.iter().any(|r| r[i] != rows[0][i]))
+        .collect();
+    if varying.is_empty() || varying.len() > INFERRED_NAMES.len() {
+        return None;
+    }
+
+    // Per varying position: (prefix, per-row values, suffix)
+    let splits: Vec<(&str, Vec<&str>, &str)> = varying
+        .iter()
+        .map(|&i| {
+            let column: Vec<&str> = rows.iter().map(|r| r[i]).collect();
+            split_varying(&column)
         })
-        .collect()
+        .collect();
+
+    // One tuple of values per command, sorted naturally
+    let mut tuples: Vec<Vec<&str>> = (0..rows.len())
+        .map(|row| splits.iter().map(|(_, values, _)| values[row]).collect())
+        .collect();
+    tuples.sort_by(|a, b| {
+        a.iter()
+            .zip(b)
+            .map(|(x, y)| natural_cmp(x, y))
+            .find(|o| o.is_ne())
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    tuples.dedup();
+
+    let distinct: Vec<Vec<&str>> = (0..splits.len())
+        .map(|p| {
+            let mut values: Vec<&str> = tuples.iter().map(|t| t[p]).collect();
+            values.sort_by(|a, b| natural_cmp(a, b));
+            values.dedup();
+            values
+        })
+        .collect();
+    let cross_product = splits.len() > 1
+        && distinct.iter().map(Vec::len).product::<usize>() == tuples.len();
+
+    let blocks = if cross_product {
+        distinct
+            .iter()
+            .zip(INFERRED_NAMES)
+            .map(|(values, name)| Some(format!("[{}={}]", name, render_values(values)?)))
+            .collect::<Option<Vec<_>>>()?
+            .join(" ")
+    } else {
+        let params = (0..splits.len())
+            .zip(INFERRED_NAMES)
+            .map(|(p, name)| {
+                let values: Vec<&str> = tuples.iter().map(|t| t[p]).collect();
+                Some(format!("{}={}", name, render_values(&values)?))
+            })
+            .collect::<Option<Vec<_>>>()?;
+        format!("[{}]", params.join(" "))
+    };
+
+    let mut template: Vec<String> = rows[0].iter().map(|t| t.to_string()).collect();
+    for ((&pos, (prefix, _, suffix)), name) in varying.iter().zip(&splits).zip(INFERRED_NAMES) {
+        template[pos] = format!("{}{{{}}}{}", prefix, name, suffix);
+    }
+
+    Some(format!("{} {}", blocks, template.join(" ")))
+}
+
+// --- Parameterizing a single command ---
+
+/// Byte ranges of the words of `command` that can become a parameter.
+/// Bracket blocks (which may contain spaces), words already holding a
+/// `{placeholder}`, and words that wouldn't read back as a single value
+/// from a block (with a comma, quote or backslash) are skipped.
+pub fn parameterizable_words(command: &str) -> Vec<std::ops::Range<usize>> {
+    let mut words = Vec::new();
+    let mut start = None;
+    let mut depth = 0usize;
+    let mut skip = false;
+    for (i, c) in command.char_indices() {
+        match c {
+            '[' => depth += 1,
+            ']' => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+        if c.is_whitespace() && depth == 0 {
+            if let Some(s) = start.take()
+                && !skip
+            {
+                words.push(s..i);
+            }
+            skip = false;
+            continue;
+        }
+        start.get_or_insert(i);
+        skip |= matches!(c, '[' | ']' | '{' | '}' | ',' | '\'' | '"' | '\\');
+    }
+    if let Some(s) = start
+        && !skip
+    {
+        words.push(s..command.len());
+    }
+    words
+}
+
+/// Turn the word at `word` into a placeholder, with its current value as
+/// the only one in a new block, ready to be edited into a range:
+/// "ssh web-1 uptime" with "web-1" picked → "[x=web-1] ssh {x} uptime".
+/// Takes the first of x, y, z, w not already used as a placeholder.
+/// Returns the command and the byte range of the value in the new block.
+pub fn parameterize(command: &str, word: std::ops::Range<usize>) -> Option<(String, std::ops::Range<usize>)> {
+    let name = INFERRED_NAMES
+        .into_iter()
+        .find(|name| !command.contains(&format!("{{{}}}", name)))?;
+    let value = command.get(word.clone())?;
+    let block = format!("[{}={}] ", name, value);
+    let value_start = name.len() + 2;
+    let rewritten = format!(
+        "{}{}{{{}}}{}",
+        block,
+        &command[..word.start],
+        name,
+        &command[word.end..]
+    );
+    Some((rewritten, value_start..value_start + value.len()))
 }
 
 #[cfg(test)]
@@ -239,9 +816,54 @@ mod tests {
         assert_eq!(vals, vec!["east", "west", "staging"]);
     }
 
+    #[test]
+    fn test_parse_range_mixed_list() {
+        assert_eq!(parse_range("1,3,5-8,10").unwrap(), vec!["1", "3", "5", "6", "7", "8", "10"]);
+        // Padding follows each item
+        assert_eq!(parse_range("01,05-07").unwrap(), vec!["01", "05", "06", "07"]);
+        assert_eq!(parse_range("1, 3-4").unwrap(), vec!["1", "3", "4"]);
+        // Items that aren't numeric ranges are kept as written
+        assert_eq!(parse_range("web-1,db-2,9-7").unwrap(), vec!["web-1", "db-2", "9-7"]);
+    }
+
+    #[test]
+    fn test_parse_range_single_value() {
+        assert_eq!(parse_range("east").unwrap(), vec!["east"]);
+        assert_eq!(parse_range("42").unwrap(), vec!["42"]);
+        assert_eq!(parse_range("web-1").unwrap(), vec!["web-1"]);
+        assert_eq!(parse_range("2024-01-02").unwrap(), vec!["2024-01-02"]);
+        assert!(parse_range("5-1").is_none());
+    }
+
+    #[test]
+    fn test_eval_arithmetic() {
+        assert_eq!(eval_arithmetic("42"), Some(42));
+        assert_eq!(eval_arithmetic("8000+7"), Some(8007));
+        assert_eq!(eval_arithmetic("10*2"), Some(20));
+        assert_eq!(eval_arithmetic("2+3*4-10/5"), Some(12));
+        assert_eq!(eval_arithmetic("10-2-3"), Some(5));
+
+        assert_eq!(eval_arithmetic(""), None);
+        assert_eq!(eval_arithmetic("1+"), None);
+        assert_eq!(eval_arithmetic("1/0"), None);
+        assert_eq!(eval_arithmetic("2^3"), None);
+        assert_eq!(eval_arithmetic("x+1"), None);
+        assert_eq!(eval_arithmetic("9223372036854775807+1"), None);
+    }
+
+    #[test]
+    fn test_parse_range_arithmetic() {
+        assert_eq!(parse_range("8000-8000+3").unwrap(), vec!["8000", "8001", "8002", "8003"]);
+        assert_eq!(parse_range("1-2*3").unwrap().len(), 6);
+        assert_eq!(parse_range("01-2*5").unwrap().last().unwrap(), "10");
+        assert_eq!(parse_range("01-4+4").unwrap()[0], "01");
+        assert!(parse_range("1-2**3").is_none());
+        assert!(parse_range("1-10/0").is_none());
+    }
+
     #[test]
     fn test_parse_parallel_single_param() {
-        let parsed = parse_parallel("[shard=1-3] mysql -h shard-{shard}").unwrap();
+        let parsed = parse_parallel("[shard=1-3] mysql -h shard-{shard}").unwrap().unwrap();
         assert_eq!(parsed.groups.len(), 1);
         assert_eq!(parsed.groups[0].params[0].name, "shard");
         assert_eq!(parsed.groups[0].params[0].values, vec!["1", "2", "3"]);
@@ -251,7 +873,7 @@ mod tests {
     #[test]
     fn test_parse_parallel_cross_product() {
         let parsed =
-            parse_parallel("[shard=1-2] [region=east,west] cmd -s {shard} -r {region}").unwrap();
+            parse_parallel("[shard=1-2] [region=east,west] cmd -s {shard} -r {region}").unwrap().unwrap();
         assert_eq!(parsed.groups.len(), 2);
         assert_eq!(parsed.groups[0].params[0].values, vec!["1", "2"]);
         assert_eq!(parsed.groups[1].params[0].values, vec!["east", "west"]);
@@ -260,7 +882,7 @@ mod tests {
     #[test]
     fn test_parse_parallel_zip() {
         let parsed =
-            parse_parallel("[shard=1-3 region=a,b,c] cmd {shard} {region}").unwrap();
+            parse_parallel("[shard=1-3 region=a,b,c] cmd {shard} {region}").unwrap().unwrap();
         assert_eq!(parsed.groups.len(), 1);
         assert_eq!(parsed.groups[0].params.len(), 2);
         assert_eq!(parsed.groups[0].params[0].name, "shard");
@@ -270,19 +892,172 @@ mod tests {
     #[test]
     fn test_parse_parallel_zip_mismatched_length() {
         // Zip with different lengths should fail
-        let result = parse_parallel("[shard=1-3 region=a,b] cmd {shard} {region}");
-        assert!(result.is_none());
+        let err = parse_parallel("[shard=1-3 region=a,b] cmd {shard} {region}").unwrap_err();
+        assert_eq!(
+            err,
+            ParallelError::ZipLengthMismatch(vec![("shard".to_string(), 3), ("region".to_string(), 2)])
+        );
+        assert_eq!(err.to_string(), "zip lengths differ: shard=3, region=2");
+    }
+
+    #[test]
+    fn test_parse_parallel_malformed_blocks() {
+        assert_eq!(
+            parse_parallel("[n=5-1] echo {n}").unwrap_err(),
+            ParallelError::BadRange { name: "n".to_string(), range: "5-1".to_string() }
+        );
+        assert_eq!(
+            parse_parallel("[n=1-3 oops] echo {n}").unwrap_err(),
+            ParallelError::BadParam("oops".to_string())
+        );
+        assert_eq!(parse_parallel("[n=1-3]").unwrap_err(), ParallelError::NoCommand);
+    }
+
+    #[test]
+    fn test_parse_parallel_retry() {
+        let parsed = parse_parallel("[retry=3] curl -f example.com").unwrap().unwrap();
+        assert!(parsed.groups.is_empty());
+        assert_eq!(parsed.retries, 3);
+        assert_eq!(parsed.template, "curl -f example.com");
+        let expanded = expand(&parsed);
+        assert_eq!(expanded.len(), 1);
+        assert_eq!(expanded[0].command, "curl -f example.com");
+        assert_eq!(expanded[0].label, "");
+
+        let parsed = parse_parallel("[host=a,b] [retry=2] ssh {host} uptime").unwrap().unwrap();
+        assert_eq!(parsed.retries, 2);
+        assert_eq!(parsed.template, "ssh {host} uptime");
+        assert_eq!(expand(&parsed).len(), 2);
+
+        // Without a plain count, `retry` is an ordinary parameter
+        let parsed = parse_parallel("[retry=1-2] echo {retry}").unwrap().unwrap();
+        assert_eq!(parsed.retries, 0);
+        assert_eq!(parsed.groups[0].params[0].name, "retry");
+
+        assert_eq!(parse_parallel("[retry=3]").unwrap_err(), ParallelError::NoCommand);
+    }
+
+    #[test]
+    fn test_parse_parallel_fail_fast() {
+        let parsed = parse_parallel("[host=a,b,c] [fail-fast] ssh {host} migrate").unwrap().unwrap();
+        assert!(parsed.fail_fast);
+        assert_eq!(parsed.template, "ssh {host} migrate");
+        assert_eq!(expand(&parsed).len(), 3);
+        assert!(explain(&parsed).contains(&"after a failure, tasks that haven't started are cancelled".to_string()));
+
+        assert!(!parse_parallel("[n=1-2] echo {n}").unwrap().unwrap().fail_fast);
+        let parsed = parse_parallel("[fail-fast] make test").unwrap().unwrap();
+        assert!(parsed.groups.is_empty());
+        assert_eq!(parsed.template, "make test");
+        assert_eq!(parse_parallel("[fail-fast]").unwrap_err(), ParallelError::NoCommand);
+        // Only the exact block is the directive
+        assert!(parse_parallel("echo [fail-fast=1]").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_explain_cross_product() {
+        let parsed = parse_parallel("[shard=1-10] [region=east,west] deploy {shard}").unwrap().unwrap();
+        assert_eq!(
+            explain(&parsed),
+            vec![
+                "template: deploy {shard}",
+                "group 1: shard = 1, 2, 3, …, 10 (10 values)",
+                "group 2: region = east, west (2 values)",
+                "cross product of 2 groups: 10 × 2 = 20 commands",
+                "note: {region} isn't used in the template",
+                "commands:",
+                "  [shard=1][region=east] deploy 1",
+                "  [shard=1][region=west] deploy 1",
+                "  [shard=2][region=east] deploy 2",
+                "  … 15 more",
+                "  [shard=10][region=east] deploy 10",
+                "  [shard=10][region=west] deploy 10",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_explain_zip() {
+        let parsed = parse_parallel("[retry=2] [n=1-2 host=a,b] ssh {host} {n}").unwrap().unwrap();
+        assert_eq!(
+            explain(&parsed),
+            vec![
+                "template: ssh {host} {n}",
+                "group 1: zips 2 params, 2 rows",
+                "  n = 1, 2",
+                "  host = a, b",
+                "1 group: 2 commands",
+                "each failing command is retried up to 2 times",
+                "commands:",
+                "  [n=1][host=a] ssh a 1",
+                "  [n=2][host=b] ssh b 2",
+            ]
+        );
     }
 
     #[test]
     fn test_parse_parallel_not_parallel() {
-        assert!(parse_parallel("echo hello").is_none());
-        assert!(parse_parallel("ls -la").is_none());
+        assert!(parse_parallel("echo hello").unwrap().is_none());
+        assert!(parse_parallel("ls -la").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_parse_parallel_env_prefix() {
+        let parsed = parse_parallel("ENV=prod [shard=1-3] deploy {shard}").unwrap().unwrap();
+        assert_eq!(parsed.groups.len(), 1);
+        assert_eq!(parsed.groups[0].params[0].name, "shard");
+        assert_eq!(parsed.template, "ENV=prod deploy {shard}");
+
+        let expanded = expand(&parsed);
+        assert_eq!(expanded[0].command, "ENV=prod deploy 1");
+        assert_eq!(expanded[2].command, "ENV=prod deploy 3");
+    }
+
+    #[test]
+    fn test_parse_parallel_blocks_anywhere() {
+        let parsed = parse_parallel("ssh [h=a,b] host-{h} uptime [n=1-2]").unwrap().unwrap();
+        assert_eq!(parsed.groups.len(), 2);
+        assert_eq!(parsed.template, "ssh host-{h} uptime");
+    }
+
+    #[test]
+    fn test_parse_parallel_non_block_brackets_kept() {
+        // Test expressions and indexing are not parameter blocks
+        assert!(parse_parallel("[ -f Cargo.toml ] && echo yes").unwrap().is_none());
+        assert!(parse_parallel("echo arr[0]").unwrap().is_none());
+        assert!(parse_parallel("echo \"[n=1-3]\"").unwrap().is_none());
+
+        let parsed = parse_parallel("[ -f x{n} ] && [n=1-2] echo {n}").unwrap().unwrap();
+        assert_eq!(parsed.template, "[ -f x{n} ] && echo {n}");
+    }
+
+    #[test]
+    fn test_parse_parallel_quoted_blocks_kept() {
+        // Blocks and directives inside quoted arguments are part of them
+        for input in [
+            "git commit -m \"fix [n=1-3] thing\"",
+            "echo 'see [a=1-x] here'",
+            "echo 'a [fail-fast] b'",
+            "echo \"[retry=2] times\"",
+            "echo \\[n=1-3] x",
+        ] {
+            assert!(parse_parallel(input).unwrap().is_none(), "{}", input);
+        }
+
+        let parsed = parse_parallel("[n=1-2] git commit -m \"it's [a=1] {n}\" [retry=1]").unwrap().unwrap();
+        assert_eq!(parsed.groups.len(), 1);
+        assert_eq!(parsed.retries, 1);
+        assert_eq!(parsed.template, "git commit -m \"it's [a=1] {n}\"");
+    }
+
+    #[test]
+    fn test_parse_parallel_requires_space_after_block() {
+        assert!(parse_parallel("[n=1-3]echo {n}").unwrap().is_none());
     }
 
     #[test]
     fn test_expand_single_param() {
-        let parsed = parse_parallel("[n=1-3] echo {n}").unwrap();
+        let parsed = parse_parallel("[n=1-3] echo {n}").unwrap().unwrap();
         let expanded = expand(&parsed);
         assert_eq!(expanded.len(), 3);
         assert_eq!(expanded[0].command, "echo 1");
@@ -293,18 +1068,23 @@ mod tests {
 
     #[test]
     fn test_expand_cross_product() {
-        let parsed = parse_parallel("[a=1-2] [b=x,y] cmd {a} {b}").unwrap();
+        let parsed = parse_parallel("[a=1-2] [b=x,y] cmd {a} {b}").unwrap().unwrap();
         let expanded = expand(&parsed);
         assert_eq!(expanded.len(), 4); // 2 x 2
         assert_eq!(expanded[0].command, "cmd 1 x");
         assert_eq!(expanded[1].command, "cmd 1 y");
         assert_eq!(expanded[2].command, "cmd 2 x");
         assert_eq!(expanded[3].command, "cmd 2 y");
+
+        // Counted and sampled without expanding
+        assert_eq!(count(&parsed), 4);
+        let first = first(&parsed).unwrap();
+        assert_eq!((first.command.as_str(), first.label.as_str()), ("cmd 1 x", "[a=1][b=x]"));
     }
 
     #[test]
     fn test_expand_zip() {
-        let parsed = parse_parallel("[a=1-3 b=x,y,z] cmd {a} {b}").unwrap();
+        let parsed = parse_parallel("[a=1-3 b=x,y,z] cmd {a} {b}").unwrap().unwrap();
         let expanded = expand(&parsed);
         assert_eq!(expanded.len(), 3); // zipped, not cross product
         assert_eq!(expanded[0].command, "cmd 1 x");
@@ -312,9 +1092,114 @@ mod tests {
         assert_eq!(expanded[2].command, "cmd 3 z");
     }
 
+    #[test]
+    fn test_infer_numeric_range() {
+        let inferred = infer_parallel(&[
+            "ssh host-3 uptime",
+            "ssh host-1 uptime",
+            "ssh host-2 uptime",
+        ]);
+        assert_eq!(inferred.as_deref(), Some("[x=1-3] ssh host-{x} uptime"));
+    }
+
+    #[test]
+    fn test_infer_digits_not_shared() {
+        let inferred = infer_parallel(&["ping db-9", "ping db-10", "ping db-11"]);
+        assert_eq!(inferred.as_deref(), Some("[x=9-11] ping db-{x}"));
+    }
+
+    #[test]
+    fn test_infer_list_and_zero_padding() {
+        let inferred = infer_parallel(&["deploy --region east", "deploy --region west"]);
+        assert_eq!(inferred.as_deref(), Some("[x=east,west] deploy --region {x}"));
+
+        let inferred = infer_parallel(&["mysql -h shard-01", "mysql -h shard-02", "mysql -h shard-03"]);
+        assert_eq!(inferred.as_deref(), Some("[x=01-03] mysql -h shard-{x}"));
+    }
+
+    #[test]
+    fn test_infer_zip_vs_cross_product() {
+        // Values move together: zipped
+        let inferred = infer_parallel(&["cmd 1 a", "cmd 2 b", "cmd 3 c"]);
+        assert_eq!(inferred.as_deref(), Some("[x=1-3 y=a,b,c] cmd {x} {y}"));
+
+        // Every combination seen: separate blocks
+        let inferred = infer_parallel(&["cmd 1 a", "cmd 1 b", "cmd 2 a", "cmd 2 b"]);
+        assert_eq!(inferred.as_deref(), Some("[x=1,2] [y=a,b] cmd {x} {y}"));
+    }
+
+    #[test]
+    fn test_infer_roundtrips_through_expand() {
+        let commands = ["kubectl -n team-a get pods", "kubectl -n team-b get pods"];
+        let inferred = infer_parallel(&commands).unwrap();
+        let expanded = expand(&parse_parallel(&inferred).unwrap().unwrap());
+        let mut got: Vec<_> = expanded.iter().map(|e| e.command.as_str()).collect();
+        got.sort();
+        assert_eq!(got, commands);
+    }
+
+    #[test]
+    fn test_infer_nothing_to_parallelize() {
+        assert!(infer_parallel(&["ls -la"]).is_none());
+        assert!(infer_parallel(&["ls -la", "ls -la"]).is_none());
+        assert!(infer_parallel(&["echo a,b", "echo c"]).is_none());
+        // "1-2,3-4" would read back as 1,2,3,4
+        assert!(infer_parallel(&["pages 1-2", "pages 3-4"]).is_none());
+    }
+
+    #[test]
+    fn test_parameterizable_words() {
+        fn words(cmd: &str) -> Vec<&str> {
+            parameterizable_words(cmd).into_iter().map(|r| &cmd[r]).collect()
+        }
+        assert_eq!(words("ssh web-1  uptime"), vec!["ssh", "web-1", "uptime"]);
+        // Blocks, even with spaces inside, and existing placeholders are skipped
+        assert_eq!(
+            words("[x=1-3 y=a,b,c] ssh host-{x} -p 22"),
+            vec!["ssh", "-p", "22"]
+        );
+        assert!(words("   ").is_empty());
+    }
+
+    #[test]
+    fn test_parameterize() {
+        let cmd = "ssh web1 uptime";
+        let (rewritten, value) = parameterize(cmd, 4..8).unwrap();
+        assert_eq!(rewritten, "[x=web1] ssh {x} uptime");
+        assert_eq!(&rewritten[value], "web1");
+
+        // The next free name is used, so a command can be parameterized again
+        let (again, value) = parameterize(&rewritten, 9..12).unwrap();
+        assert_eq!(again, "[y=ssh] [x=web1] {y} {x} uptime");
+        assert_eq!(&again[value], "ssh");
+        let parsed = parse_parallel(&again).unwrap().unwrap();
+        assert_eq!(expand(&parsed)[0].command, "ssh web1 uptime");
+
+        assert!(parameterize("echo {x} {y} {z} {w} a", 21..22).is_none());
+    }
+
+    #[test]
+    fn test_parameterize_round_trips() {
+        // A hyphenated value is kept as written rather than read as a range
+        let (rewritten, _) = parameterize("ssh web-1 uptime", 4..9).unwrap();
+        assert_eq!(rewritten, "[x=web-1] ssh {x} uptime");
+        let parsed = parse_parallel(&rewritten).unwrap().unwrap();
+        assert_eq!(expand(&parsed)[0].command, "ssh web-1 uptime");
+        assert_eq!(expand(&parsed).len(), 1);
+
+        let (rewritten, _) = parameterize("touch 2024-01-02.log", 6..20).unwrap();
+        let parsed = parse_parallel(&rewritten).unwrap().unwrap();
+        assert_eq!(expand(&parsed)[0].command, "touch 2024-01-02.log");
+
+        // A comma would turn the value into a list, so such words aren't offered
+        let cmd = "cut -d, -f1,3 data.csv";
+        let words: Vec<_> = parameterizable_words(cmd).into_iter().map(|w| &cmd[w]).collect();
+        assert_eq!(words, ["cut", "data.csv"]);
+    }
+
     #[test]
     fn test_expand_zero_padded() {
-        let parsed = parse_parallel("[n=01-03] echo {n}").unwrap();
+        let parsed = parse_parallel("[n=01-03] echo {n}").unwrap().unwrap();
         let expanded = expand(&parsed);
         assert_eq!(expanded[0].command, "echo 01");
         assert_eq!(expanded[1].command, "echo 02");
@@ -1,51 +1,410 @@
-use std::path::PathBuf;
+use crate::error::Error;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
 
-fn get_home() -> Result<String, Box<dyn std::error::Error>> {
-    std::env::var("HOME")
-        .map_err(|_| "HOME environment variable not set".into())
+/// Get the user's home directory.
+/// Uses $HOME if set and non-empty, otherwise asks the OS (the passwd entry on
+/// Unix, %USERPROFILE% on Windows), which still works in containers and CI jobs that don't export HOME.
+pub fn home_dir() -> Option<PathBuf> {
+    resolve_home(std::env::var_os("HOME"), std::env::home_dir)
+}
+
+fn resolve_home(
+    env_home: Option<OsString>,
+    os_lookup: impl FnOnce() -> Option<PathBuf>,
+) -> Option<PathBuf> {
+    match env_home {
+        Some(home) if !home.is_empty() => Some(PathBuf::from(home)),
+        _ => os_lookup().filter(|p| !p.as_os_str().is_empty()),
+    }
+}
+
+/// Expand a leading `~/` to the home directory. Other paths are returned as-is.
+pub fn expand_tilde(path: &Path) -> PathBuf {
+    expand_tilde_in(path, home_dir())
+}
+
+fn expand_tilde_in(path: &Path, home: Option<PathBuf>) -> PathBuf {
+    match (path.strip_prefix("~"), home) {
+        (Ok(rest), Some(home)) => home.join(rest),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// Expand a path from the config: a leading `~` or `~user`, then `$VAR` and
+/// `${VAR}` anywhere in it. A variable that isn't set (or isn't valid UTF-8),
+/// or a user without a home directory, is left as written, so the mistake
+/// shows up in the path mux then fails to open.
+pub fn expand(path: &str) -> PathBuf {
+    let home = |user: &str| match user {
+        "" => home_dir(),
+        user => user_home(user),
+    };
+    expand_with(path, home, |name| std::env::var(name).ok())
+}
+
+/// [`expand`] for a configured `PathBuf`; paths that aren't UTF-8 are
+/// returned unchanged
+pub fn expand_path(path: &Path) -> PathBuf {
+    match path.to_str() {
+        Some(path) => expand(path),
+        None => path.to_path_buf(),
+    }
+}
+
+fn expand_with(
+    path: &str,
+    home: impl Fn(&str) -> Option<PathBuf>,
+    var: impl Fn(&str) -> Option<String>,
+) -> PathBuf {
+    let (head, rest) = match path.strip_prefix('~') {
+        Some(after) => {
+            let (user, rest) = after.split_at(after.find('/').unwrap_or(after.len()));
+            match home(user) {
+                Some(dir) => (Some(dir), rest.trim_start_matches('/')),
+                None => (None, path),
+            }
+        }
+        None => (None, path),
+    };
+    let rest = PathBuf::from(expand_vars(rest, var));
+    match head {
+        Some(dir) if rest.as_os_str().is_empty() => dir,
+        Some(dir) => dir.join(rest),
+        None => rest,
+    }
+}
+
+/// Replace `$NAME` and `${NAME}` with the variables' values
+fn expand_vars(text: &str, var: impl Fn(&str) -> Option<String>) -> String {
+    let is_name_char = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(dollar) = rest.find('$') {
+        out.push_str(&rest[..dollar]);
+        let after = &rest[dollar + 1..];
+        let (name, len) = match after.strip_prefix('{') {
+            Some(braced) => match braced.find('}') {
+                Some(end) => (&braced[..end], end + 2),
+                None => ("", 0),
+            },
+            None => {
+                let end = after.find(|c: char| !is_name_char(c)).unwrap_or(after.len());
+                (&after[..end], end)
+            }
+        };
+        let valid = name.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && name.chars().all(is_name_char);
+        match var(name).filter(|_| valid) {
+            Some(value) => out.push_str(&value),
+            None => out.push_str(&rest[dollar..dollar + 1 + len]),
+        }
+        rest = &after[len..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Home directory of another user, from the passwd database
+#[cfg(unix)]
+fn user_home(user: &str) -> Option<PathBuf> {
+    use std::ffi::{CStr, CString, OsStr};
+    use std::os::unix::ffi::OsStrExt;
+
+    let name = CString::new(user).ok()?;
+    // SAFETY: an all-zero passwd is a valid value for getpwnam_r to fill in
+    let mut entry: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut buf = vec![0 as libc::c_char; 16 * 1024];
+    let mut found = std::ptr::null_mut();
+    // SAFETY: every pointer is valid for the call and `buf.len()` is its size
+    let rc = unsafe { libc::getpwnam_r(name.as_ptr(), &mut entry, buf.as_mut_ptr(), buf.len(), &mut found) };
+    if rc != 0 || found.is_null() || entry.pw_dir.is_null() {
+        return None;
+    }
+    // SAFETY: getpwnam_r succeeded, so pw_dir points to a C string in `buf`
+    let dir = unsafe { CStr::from_ptr(entry.pw_dir) };
+    Some(PathBuf::from(OsStr::from_bytes(dir.to_bytes()))).filter(|dir| !dir.as_os_str().is_empty())
+}
+
+#[cfg(not(unix))]
+fn user_home(_user: &str) -> Option<PathBuf> {
+    None
+}
+
+fn get_home() -> Result<PathBuf, Error> {
+    home_dir().ok_or(Error::NoHomeDir)
 }
 
 /// Get the XDG state home directory.
-/// Uses $XDG_STATE_HOME if set, otherwise falls back to $HOME/.local/state.
-fn get_xdg_state_home() -> Result<PathBuf, Box<dyn std::error::Error>> {
-    if let Ok(state_home) = std::env::var("XDG_STATE_HOME") {
-        return Ok(PathBuf::from(state_home));
+/// Uses $XDG_STATE_HOME if set, otherwise falls back to $HOME/.local/state,
+/// and to the system temp directory if there's no home directory either.
+fn get_xdg_state_home() -> PathBuf {
+    resolve_state_home(std::env::var_os("XDG_STATE_HOME"), home_dir())
+}
+
+fn resolve_state_home(xdg_state_home: Option<OsString>, home: Option<PathBuf>) -> PathBuf {
+    if let Some(state_home) = xdg_state_home.filter(|s| !s.is_empty()) {
+        return PathBuf::from(state_home);
+    }
+    match home {
+        Some(home) => home.join(".local").join("state"),
+        None => {
+            let fallback = std::env::temp_dir();
+            log::warn!(
+                "No home directory found; storing state under {}",
+                fallback.display()
+            );
+            fallback
+        }
     }
-    Ok(PathBuf::from(get_home()?).join(".local").join("state"))
 }
 
 /// Get the XDG config home directory.
 /// Uses $XDG_CONFIG_HOME if set, otherwise falls back to $HOME/.config.
-fn get_xdg_config_home() -> Result<PathBuf, Box<dyn std::error::Error>> {
+fn get_xdg_config_home() -> Result<PathBuf, Error> {
     if let Ok(config_home) = std::env::var("XDG_CONFIG_HOME") {
         return Ok(PathBuf::from(config_home));
     }
-    Ok(PathBuf::from(get_home()?).join(".config"))
+    Ok(get_home()?.join(".config"))
 }
 
 /// Get the config file path: $XDG_CONFIG_HOME/mux/config.toml
-pub fn get_config_path() -> Result<PathBuf, Box<dyn std::error::Error>> {
+pub fn get_config_path() -> Result<PathBuf, Error> {
     let config_dir = get_xdg_config_home()?.join("mux");
-    std::fs::create_dir_all(&config_dir)?;
+    std::fs::create_dir_all(&config_dir).map_err(|e| Error::create_dir(&config_dir, e))?;
     Ok(config_dir.join("config.toml"))
 }
 
+/// Get the config fragments: the `*.toml` files in $XDG_CONFIG_HOME/mux/conf.d/,
+/// in file name order. Empty if the directory doesn't exist.
+pub fn get_config_fragments() -> Result<Vec<PathBuf>, Error> {
+    Ok(list_config_fragments(&get_xdg_config_home()?.join("mux").join("conf.d")))
+}
+
+fn list_config_fragments(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut fragments: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "toml") && path.is_file())
+        .collect();
+    fragments.sort();
+    fragments
+}
+
+/// Get the config file of a profile: $XDG_CONFIG_HOME/mux/profiles/<name>.toml
+pub fn get_profile_config_path(name: &str) -> Result<PathBuf, Error> {
+    Ok(get_xdg_config_home()?.join("mux").join("profiles").join(format!("{}.toml", name)))
+}
+
+/// Check a `--profile` name: it becomes part of file names, so it must be
+/// non-empty and made of letters, digits, `-` and `_`
+pub fn parse_profile_name(name: &str) -> Result<String, String> {
+    if !name.is_empty()
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        Ok(name.to_string())
+    } else {
+        Err("profile names may only contain letters, digits, '-' and '_'".to_string())
+    }
+}
+
+/// Name of the per-project config file
+pub const PROJECT_CONFIG_FILE: &str = ".mux.toml";
+
+/// Find a project-local `.mux.toml`, searching from `start` upwards.
+/// The search stops at the repo root (the first directory containing `.git`)
+/// or the home directory, whichever comes first. Outside both, only `start`
+/// itself is checked, so a stray file in `/tmp` or `/` is never picked up.
+pub fn find_project_config(start: &Path) -> Option<PathBuf> {
+    find_project_config_in(start, home_dir().as_deref())
+}
+
+fn find_project_config_in(start: &Path, home: Option<&Path>) -> Option<PathBuf> {
+    let is_boundary = |dir: &Path| dir.join(".git").exists() || Some(dir) == home;
+    let depth = start.ancestors().position(is_boundary).unwrap_or(0);
+    start
+        .ancestors()
+        .take(depth + 1)
+        .map(|dir| dir.join(PROJECT_CONFIG_FILE))
+        .find(|candidate| candidate.is_file())
+}
+
 /// Get the mux state directory: $XDG_STATE_HOME/mux
 /// Creates the directory if it doesn't exist.
-pub fn get_state_dir() -> Result<PathBuf, Box<dyn std::error::Error>> {
-    let mux_dir = get_xdg_state_home()?.join("mux");
-    std::fs::create_dir_all(&mux_dir)?;
+pub fn get_state_dir() -> Result<PathBuf, Error> {
+    let mux_dir = get_xdg_state_home().join("mux");
+    std::fs::create_dir_all(&mux_dir).map_err(|e| Error::create_dir(&mux_dir, e))?;
     Ok(mux_dir)
 }
 
-/// Get the database path: $XDG_STATE_HOME/mux/history.db
-pub fn get_db_path() -> Result<PathBuf, Box<dyn std::error::Error>> {
-    Ok(get_state_dir()?.join("history.db"))
+/// Get the database path: $XDG_STATE_HOME/mux/history.db, or
+/// history-<profile>.db when a profile is selected
+pub fn get_db_path(profile: Option<&str>) -> Result<PathBuf, Error> {
+    Ok(get_state_dir()?.join(db_file_name(profile)))
+}
+
+fn db_file_name(profile: Option<&str>) -> String {
+    match profile {
+        Some(name) => format!("history-{}.db", name),
+        None => "history.db".to_string(),
+    }
+}
+
+/// Get the path of the remembered UI state: $XDG_STATE_HOME/mux/state.toml
+pub fn get_ui_state_path() -> Result<PathBuf, Error> {
+    Ok(get_state_dir()?.join("state.toml"))
+}
+
+/// Get the log directory path: $XDG_STATE_HOME/mux/logs/. Not created
+/// here; the logger does that before writing to it.
+pub fn get_log_dir() -> PathBuf {
+    get_xdg_state_home().join("mux").join("logs")
 }
 
-/// Get the log directory path: $XDG_STATE_HOME/mux/logs/
-pub fn get_log_dir() -> Result<PathBuf, Box<dyn std::error::Error>> {
-    let log_dir = get_state_dir()?.join("logs");
-    std::fs::create_dir_all(&log_dir)?;
-    Ok(log_dir)
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_home_prefers_env() {
+        let home = resolve_home(Some("/home/env".into()), || Some(PathBuf::from("/home/os")));
+        assert_eq!(home, Some(PathBuf::from("/home/env")));
+    }
+
+    #[test]
+    fn test_resolve_home_unset_falls_back_to_os() {
+        let home = resolve_home(None, || Some(PathBuf::from("/home/os")));
+        assert_eq!(home, Some(PathBuf::from("/home/os")));
+
+        // An empty HOME is treated as unset
+        let home = resolve_home(Some("".into()), || Some(PathBuf::from("/home/os")));
+        assert_eq!(home, Some(PathBuf::from("/home/os")));
+    }
+
+    #[test]
+    fn test_resolve_home_unresolvable() {
+        assert_eq!(resolve_home(None, || None), None);
+    }
+
+    #[test]
+    fn test_expand_tilde() {
+        let home = Some(PathBuf::from("/home/u"));
+        assert_eq!(
+            expand_tilde_in(Path::new("~/.sh_history"), home.clone()),
+            PathBuf::from("/home/u/.sh_history")
+        );
+        assert_eq!(
+            expand_tilde_in(Path::new("/var/hist"), home.clone()),
+            PathBuf::from("/var/hist")
+        );
+        assert_eq!(expand_tilde_in(Path::new("~user/h"), home), PathBuf::from("~user/h"));
+        assert_eq!(expand_tilde_in(Path::new("~/h"), None), PathBuf::from("~/h"));
+    }
+
+    #[test]
+    fn test_expand() {
+        let home = |user: &str| match user {
+            "" => Some(PathBuf::from("/home/u")),
+            "ops" => Some(PathBuf::from("/srv/ops")),
+            _ => None,
+        };
+        let var = |name: &str| match name {
+            "HOME" => Some("/home/u".to_string()),
+            "APP" => Some("mux".to_string()),
+            _ => None,
+        };
+        let expand = |path: &str| expand_with(path, home, var);
+
+        assert_eq!(expand("~"), PathBuf::from("/home/u"));
+        assert_eq!(expand("~/logs"), PathBuf::from("/home/u/logs"));
+        assert_eq!(expand("~ops/h"), PathBuf::from("/srv/ops/h"));
+        assert_eq!(expand("$HOME/x"), PathBuf::from("/home/u/x"));
+        assert_eq!(expand("/var/${APP}_logs/$APP"), PathBuf::from("/var/mux_logs/mux"));
+        assert_eq!(expand("~/$APP.env"), PathBuf::from("/home/u/mux.env"));
+
+        // Unknown users and unset variables are left as written
+        assert_eq!(expand("~nobody/h"), PathBuf::from("~nobody/h"));
+        assert_eq!(expand("$NOPE/x/${NOPE}"), PathBuf::from("$NOPE/x/${NOPE}"));
+        // as is anything that isn't a variable reference
+        assert_eq!(expand("/a$/b$1/${APP"), PathBuf::from("/a$/b$1/${APP"));
+        assert_eq!(expand("/tmp/a~b"), PathBuf::from("/tmp/a~b"));
+    }
+
+    #[test]
+    fn test_state_home_fallbacks() {
+        assert_eq!(
+            resolve_state_home(Some("/xdg/state".into()), None),
+            PathBuf::from("/xdg/state")
+        );
+        assert_eq!(
+            resolve_state_home(None, Some(PathBuf::from("/home/u"))),
+            PathBuf::from("/home/u/.local/state")
+        );
+        // Neither XDG_STATE_HOME nor a home directory: temp dir
+        assert_eq!(resolve_state_home(None, None), std::env::temp_dir());
+    }
+
+    #[test]
+    fn test_profile_names() {
+        assert_eq!(db_file_name(None), "history.db");
+        assert_eq!(db_file_name(Some("work")), "history-work.db");
+
+        assert_eq!(parse_profile_name("work_2-b"), Ok("work_2-b".to_string()));
+        assert!(parse_profile_name("").is_err());
+        assert!(parse_profile_name("../etc").is_err());
+        assert!(parse_profile_name("a b").is_err());
+    }
+
+    #[test]
+    fn test_config_fragments_in_name_order() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(list_config_fragments(&dir.path().join("conf.d")).is_empty());
+
+        for name in ["20-theme.toml", "10-runner.toml", "notes.txt", "99-local.toml.bak"] {
+            std::fs::write(dir.path().join(name), "").unwrap();
+        }
+        std::fs::create_dir(dir.path().join("30-dir.toml")).unwrap();
+
+        assert_eq!(
+            list_config_fragments(dir.path()),
+            [dir.path().join("10-runner.toml"), dir.path().join("20-theme.toml")]
+        );
+    }
+
+    #[test]
+    fn test_find_project_config_walks_up() {
+        let dir = tempfile::tempdir().unwrap();
+        let home = dir.path().join("home");
+        let nested = home.join("a").join("b");
+        std::fs::create_dir_all(&nested).unwrap();
+        std::fs::write(home.join(PROJECT_CONFIG_FILE), "").unwrap();
+        assert_eq!(find_project_config_in(&nested, Some(&home)), Some(home.join(PROJECT_CONFIG_FILE)));
+
+        // Never above the home directory
+        std::fs::remove_file(home.join(PROJECT_CONFIG_FILE)).unwrap();
+        std::fs::write(dir.path().join(PROJECT_CONFIG_FILE), "").unwrap();
+        assert_eq!(find_project_config_in(&nested, Some(&home)), None);
+
+        // Outside home and any repo, only the directory itself counts
+        assert_eq!(find_project_config_in(&nested, None), None);
+        assert_eq!(find_project_config_in(dir.path(), None), Some(dir.path().join(PROJECT_CONFIG_FILE)));
+    }
+
+    #[test]
+    fn test_find_project_config_stops_at_repo_root() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = dir.path().join("repo");
+        let nested = repo.join("src");
+        std::fs::create_dir_all(&nested).unwrap();
+        std::fs::create_dir(repo.join(".git")).unwrap();
+        // Above the repo root: must not be picked up
+        std::fs::write(dir.path().join(PROJECT_CONFIG_FILE), "").unwrap();
+
+        assert_eq!(find_project_config_in(&nested, Some(dir.path())), None);
+
+        std::fs::write(repo.join(PROJECT_CONFIG_FILE), "").unwrap();
+        assert_eq!(find_project_config_in(&nested, None), Some(repo.join(PROJECT_CONFIG_FILE)));
+    }
 }
@@ -0,0 +1,235 @@
+//! User-defined pipes: `[[pipes]]` entries in the config bind a key to a
+//! program that receives the highlighted suggestion, or the output box in
+//! view, on stdin. Copying to the clipboard is `command = "wl-copy"` (or
+//! `pbcopy`, `xclip -selection clipboard`, ...).
+
+use std::fmt;
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+use crate::config::PipeConfig;
+
+/// Why a `[[pipes]]` entry can't be used
+#[derive(Debug)]
+pub enum PipeError {
+    /// The key isn't of the form `ctrl+alt+x`
+    BadKey(String),
+    /// The key is one mux can't do without, and what it's for
+    ReservedKey(String, &'static str),
+    /// The command is empty or has unbalanced quotes
+    BadCommand(String),
+    /// The program couldn't be started
+    Spawn { program: String, source: io::Error },
+}
+
+impl fmt::Display for PipeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BadKey(key) => write!(f, "unknown key '{}' in [[pipes]]", key),
+            Self::ReservedKey(key, what) => write!(f, "key '{}' in [[pipes]] is reserved for {}", key, what),
+            Self::BadCommand(command) => write!(f, "can't parse [[pipes]] command '{}'", command),
+            Self::Spawn { program, source } => write!(f, "could not start {}: {}", program, source),
+        }
+    }
+}
+
+impl std::error::Error for PipeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Spawn { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// A key and the program it sends text to
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pipe {
+    modifiers: KeyModifiers,
+    code: KeyCode,
+    argv: Vec<String>,
+}
+
+impl Pipe {
+    pub fn from_config(config: &PipeConfig) -> Result<Self, PipeError> {
+        let (modifiers, code) = parse_key(&config.key).ok_or_else(|| PipeError::BadKey(config.key.clone()))?;
+        if let Some(what) = reserved(modifiers, code) {
+            return Err(PipeError::ReservedKey(config.key.clone(), what));
+        }
+        let argv = shell_words::split(&config.command)
+            .ok()
+            .filter(|argv| !argv.is_empty())
+            .ok_or_else(|| PipeError::BadCommand(config.command.clone()))?;
+        Ok(Self { modifiers, code, argv })
+    }
+
+    /// Whether `key` is this pipe's key. Shift is part of the character, so
+    /// it's ignored for characters: terminals differ in reporting it.
+    pub fn matches(&self, key: KeyEvent) -> bool {
+        let modifiers = match key.code {
+            KeyCode::Char(_) => key.modifiers - KeyModifiers::SHIFT,
+            _ => key.modifiers,
+        };
+        key.code == self.code && modifiers == self.modifiers
+    }
+
+    /// Start the program and write `text` to its stdin in the background.
+    /// Its output is discarded.
+    pub fn send(&self, text: String) -> Result<(), PipeError> {
+        let (program, args) = self.argv.split_first().expect("argv is never empty");
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|source| PipeError::Spawn {
+                program: program.clone(),
+                source,
+            })?;
+
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        std::thread::spawn(move || {
+            // A program that exits without reading just misses the text
+            let _ = stdin.write_all(text.as_bytes());
+            drop(stdin);
+            let _ = child.wait();
+        });
+        Ok(())
+    }
+}
+
+/// What mux uses `code` with `modifiers` for, if a pipe can't take it over:
+/// a pipe's key is checked before the built-in ones, so binding these would
+/// leave no way to quit, cancel or type
+fn reserved(modifiers: KeyModifiers, code: KeyCode) -> Option<&'static str> {
+    match code {
+        KeyCode::Char('c') if modifiers == KeyModifiers::CONTROL => Some("quitting and cancelling"),
+        KeyCode::Char('d') if modifiers == KeyModifiers::CONTROL => Some("quitting"),
+        KeyCode::Char('z') if modifiers == KeyModifiers::CONTROL => Some("suspending"),
+        KeyCode::F(_) => None,
+        _ if !modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) => Some("typing"),
+        _ => None,
+    }
+}
+
+/// Parse a key such as `alt+c`, `ctrl+alt+y`, `shift+f5` or `alt+enter`
+fn parse_key(spec: &str) -> Option<(KeyModifiers, KeyCode)> {
+    let mut parts: Vec<&str> = spec.split('+').map(str::trim).collect();
+    // "alt++" binds the plus key
+    if spec.ends_with("++") {
+        parts.truncate(parts.len() - 2);
+        parts.push("+");
+    }
+    let (key, modifier_names) = parts.split_last()?;
+
+    let mut modifiers = KeyModifiers::NONE;
+    for name in modifier_names {
+        modifiers |= match name.to_ascii_lowercase().as_str() {
+            "ctrl" | "control" => KeyModifiers::CONTROL,
+            "alt" | "meta" => KeyModifiers::ALT,
+            "shift" => KeyModifiers::SHIFT,
+            _ => return None,
+        };
+    }
+
+    let mut chars = key.chars();
+    let code = match (chars.next(), chars.next()) {
+        (Some(c), None) => KeyCode::Char(c),
+        _ => match key.to_ascii_lowercase().as_str() {
+            "enter" => KeyCode::Enter,
+            "tab" => KeyCode::Tab,
+            "space" => KeyCode::Char(' '),
+            "backspace" => KeyCode::Backspace,
+            "delete" => KeyCode::Delete,
+            f => KeyCode::F(f.strip_prefix('f')?.parse().ok().filter(|n| (1..=12).contains(n))?),
+        },
+    };
+
+    // Shifted characters are matched by the character itself
+    if let KeyCode::Char(c) = code
+        && modifiers.contains(KeyModifiers::SHIFT)
+    {
+        let upper = c.to_uppercase().next().unwrap_or(c);
+        return Some((modifiers - KeyModifiers::SHIFT, KeyCode::Char(upper)));
+    }
+    Some((modifiers, code))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pipe(key: &str, command: &str) -> Result<Pipe, PipeError> {
+        Pipe::from_config(&PipeConfig {
+            key: key.to_string(),
+            command: command.to_string(),
+        })
+    }
+
+    #[test]
+    fn test_parse_key() {
+        assert_eq!(parse_key("alt+c"), Some((KeyModifiers::ALT, KeyCode::Char('c'))));
+        assert_eq!(
+            parse_key("Ctrl+Alt+y"),
+            Some((KeyModifiers::CONTROL | KeyModifiers::ALT, KeyCode::Char('y')))
+        );
+        assert_eq!(parse_key("alt+shift+c"), Some((KeyModifiers::ALT, KeyCode::Char('C'))));
+        assert_eq!(parse_key("shift+F5"), Some((KeyModifiers::SHIFT, KeyCode::F(5))));
+        assert_eq!(parse_key("alt++"), Some((KeyModifiers::ALT, KeyCode::Char('+'))));
+        assert_eq!(parse_key("f13"), None);
+        assert_eq!(parse_key("hyper+c"), None);
+        assert_eq!(parse_key("alt+"), None);
+    }
+
+    #[test]
+    fn test_matches_ignores_shift_on_characters() {
+        let pipe = pipe("alt+shift+c", "wl-copy").unwrap();
+        assert!(pipe.matches(KeyEvent::new(KeyCode::Char('C'), KeyModifiers::ALT | KeyModifiers::SHIFT)));
+        assert!(pipe.matches(KeyEvent::new(KeyCode::Char('C'), KeyModifiers::ALT)));
+        assert!(!pipe.matches(KeyEvent::new(KeyCode::Char('c'), KeyModifiers::ALT)));
+        assert!(!pipe.matches(KeyEvent::new(KeyCode::Char('C'), KeyModifiers::NONE)));
+    }
+
+    #[test]
+    fn test_bad_config() {
+        assert!(matches!(pipe("alt+c", "  "), Err(PipeError::BadCommand(_))));
+        assert!(matches!(pipe("alt+c", "tee 'notes"), Err(PipeError::BadCommand(_))));
+        assert!(matches!(pipe("super+c", "wl-copy"), Err(PipeError::BadKey(_))));
+    }
+
+    #[test]
+    fn test_reserved_keys() {
+        for key in ["ctrl+c", "Ctrl+d", "ctrl+z", "x", "shift+x", "enter", "tab", "backspace", "space"] {
+            assert!(matches!(pipe(key, "wl-copy"), Err(PipeError::ReservedKey(..))), "{}", key);
+        }
+        for key in ["alt+c", "ctrl+alt+c", "ctrl+shift+c", "alt+enter", "f5", "shift+f5"] {
+            assert!(pipe(key, "wl-copy").is_ok(), "{}", key);
+        }
+        assert_eq!(
+            pipe("ctrl+c", "wl-copy").unwrap_err().to_string(),
+            "key 'ctrl+c' in [[pipes]] is reserved for quitting and cancelling"
+        );
+    }
+
+    #[test]
+    fn test_send_writes_stdin() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("sent.txt");
+        let pipe = pipe("alt+c", &format!("tee '{}'", path.display())).unwrap();
+        pipe.send("cargo build --release\n".to_string()).unwrap();
+
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+        while std::fs::read_to_string(&path).unwrap_or_default().is_empty() {
+            assert!(std::time::Instant::now() < deadline, "nothing written");
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "cargo build --release\n");
+
+        let missing = self::pipe("alt+c", "mux-no-such-program").unwrap();
+        let err = missing.send(String::new()).unwrap_err();
+        assert!(err.to_string().starts_with("could not start mux-no-such-program: "));
+    }
+}
@@ -0,0 +1,317 @@
+//! Pluggable suggestion sources.
+//!
+//! A [`SuggestionProvider`] looks at the input being typed and returns extra
+//! suggestions, which `SuggestionEngine::suggest` merges with its built-in
+//! history/argument results by score. Register one with
+//! `SuggestionEngine::register_provider`.
+//!
+//! `suggest` runs on every keystroke, so providers must be cheap: return
+//! early when the input isn't relevant to them, and cache anything that
+//! needs I/O or a subprocess rather than doing it per call.
+
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use crate::suggest::{Suggestion, SuggestionType};
+
+/// What the user is typing, as seen by providers
+#[derive(Debug)]
+pub struct ProviderContext<'a> {
+    /// Completed tokens of the sub-command being typed (after any `&&`, `|`, ...)
+    pub completed: &'a [String],
+    /// The token being typed; empty after a trailing space
+    pub partial: &'a str,
+}
+
+/// A source of suggestions.
+///
+/// Returned suggestions are used as-is: `FullCommand` replaces the whole
+/// input, the argument types replace `partial`. Scores compete directly with
+/// the built-in ones — history matches score in the hundreds (fuzzy score plus
+/// a frequency bonus), argument suggestions score by how often they were used.
+pub trait SuggestionProvider: Send {
+    /// Short name for logging
+    fn name(&self) -> &str;
+
+    /// Suggestions for the current input, at most `limit` of them.
+    /// Return an empty vec when the provider doesn't apply.
+    fn suggest(&self, ctx: &ProviderContext, limit: usize) -> Vec<Suggestion>;
+}
+
+/// Score for environment variable names: the user typed `$`, so they win
+const ENV_VAR_SCORE: f32 = 1000.0;
+
+/// Completes `$NAME` / `${NAME}` from the process environment
+pub struct EnvVarProvider;
+
+impl SuggestionProvider for EnvVarProvider {
+    fn name(&self) -> &str {
+        "env"
+    }
+
+    fn suggest(&self, ctx: &ProviderContext, limit: usize) -> Vec<Suggestion> {
+        // A variable in command position is rare; leave that to history
+        if ctx.completed.is_empty() {
+            return Vec::new();
+        }
+        let Some(rest) = ctx.partial.strip_prefix('$') else {
+            return Vec::new();
+        };
+        let (braced, prefix) = match rest.strip_prefix('{') {
+            Some(name) => (true, name),
+            None => (false, rest),
+        };
+
+        let mut names: Vec<String> = std::env::vars_os()
+            .filter_map(|(k, _)| k.into_string().ok())
+            .filter(|k| k.starts_with(prefix))
+            .collect();
+        names.sort();
+        names.truncate(limit);
+
+        names
+            .into_iter()
+            .map(|name| Suggestion {
+                text: if braced {
+                    format!("${{{}}}", name)
+                } else {
+                    format!("${}", name)
+                },
+                score: ENV_VAR_SCORE,
+                suggestion_type: SuggestionType::ArgumentValue,
+            })
+            .collect()
+    }
+}
+
+/// Score for path entries: only offered for path-like tokens, so they win
+const PATH_SCORE: f32 = 1000.0;
+
+/// Completes filesystem paths for tokens that look like one: containing `/`,
+/// or starting with `.` or `~`
+#[derive(Default)]
+pub struct PathProvider {
+    /// The last directory listed, reused while its mtime is unchanged so
+    /// typing a file name doesn't re-read the directory on every key
+    cache: Mutex<Option<DirListing>>,
+}
+
+/// Every entry of a directory, directories with a trailing `/`
+struct DirListing {
+    dir: PathBuf,
+    modified: SystemTime,
+    entries: Vec<String>,
+}
+
+/// Whether a token should be completed as a path
+fn is_path_like(partial: &str) -> bool {
+    partial.contains('/') || partial.starts_with('.') || partial.starts_with('~')
+}
+
+/// Directory to list for the directory part of a token ("" is the current
+/// directory, a leading `~/` is the home directory)
+fn resolve_dir(dir_part: &str, home: Option<PathBuf>) -> Option<PathBuf> {
+    if dir_part.is_empty() {
+        return Some(PathBuf::from("."));
+    }
+    match dir_part.strip_prefix("~/") {
+        Some(rest) => Some(home?.join(rest)),
+        None => Some(PathBuf::from(dir_part)),
+    }
+}
+
+impl SuggestionProvider for PathProvider {
+    fn name(&self) -> &str {
+        "path"
+    }
+
+    fn suggest(&self, ctx: &ProviderContext, limit: usize) -> Vec<Suggestion> {
+        let partial = ctx.partial;
+        if !is_path_like(partial) {
+            return Vec::new();
+        }
+        if partial == "~" {
+            return vec![path_suggestion("~/".to_string())];
+        }
+
+        // Keep what the user typed (including `~`) and complete the last component
+        let (dir_part, name_prefix) = match partial.rfind('/') {
+            Some(i) => partial.split_at(i + 1),
+            None => ("", partial),
+        };
+        let Some(dir) = resolve_dir(dir_part, crate::paths::home_dir()) else {
+            return Vec::new();
+        };
+
+        let mut entries = self.list_dir(&dir, name_prefix);
+        entries.sort();
+        entries.truncate(limit);
+        entries
+            .into_iter()
+            .map(|name| path_suggestion(format!("{}{}", dir_part, name)))
+            .collect()
+    }
+}
+
+impl PathProvider {
+    /// Entries of `dir` starting with `prefix`, directories with a trailing `/`.
+    /// Hidden entries are only listed when the prefix starts with `.`.
+    fn list_dir(&self, dir: &Path, prefix: &str) -> Vec<String> {
+        let modified = match std::fs::metadata(dir).and_then(|m| m.modified()) {
+            Ok(modified) => modified,
+            Err(e) => {
+                log::debug!("Cannot list {} for path completion: {}", dir.display(), e);
+                return Vec::new();
+            }
+        };
+        let Ok(mut cache) = self.cache.lock() else {
+            return Vec::new();
+        };
+        if !cache.as_ref().is_some_and(|c| c.dir == dir && c.modified == modified) {
+            *cache = Some(DirListing {
+                dir: dir.to_path_buf(),
+                modified,
+                entries: read_dir_entries(dir),
+            });
+        }
+        let Some(listing) = cache.as_ref() else {
+            return Vec::new();
+        };
+
+        listing
+            .entries
+            .iter()
+            .filter(|name| name.starts_with(prefix) && (!name.starts_with('.') || prefix.starts_with('.')))
+            .cloned()
+            .collect()
+    }
+}
+
+/// All entries of `dir`, directories with a trailing `/`
+fn read_dir_entries(dir: &Path) -> Vec<String> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            log::debug!("Cannot list {} for path completion: {}", dir.display(), e);
+            return Vec::new();
+        }
+    };
+
+    entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            // Follow symlinks so links to directories complete like directories
+            let is_dir = std::fs::metadata(entry.path()).is_ok_and(|m| m.is_dir());
+            Some(if is_dir { format!("{}/", name) } else { name })
+        })
+        .collect()
+}
+
+fn path_suggestion(text: String) -> Suggestion {
+    Suggestion {
+        text,
+        score: PATH_SCORE,
+        suggestion_type: SuggestionType::ArgumentValue,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ctx<'a>(completed: &'a [String], partial: &'a str) -> ProviderContext<'a> {
+        ProviderContext { completed, partial }
+    }
+
+    #[test]
+    fn test_env_var_provider() {
+        let completed = vec!["echo".to_string()];
+        let suggestions = EnvVarProvider.suggest(&ctx(&completed, "$PAT"), 10);
+        assert!(suggestions.iter().any(|s| s.text == "$PATH"));
+
+        let suggestions = EnvVarProvider.suggest(&ctx(&completed, "${PAT"), 10);
+        assert!(suggestions.iter().any(|s| s.text == "${PATH}"));
+    }
+
+    #[test]
+    fn test_env_var_provider_inactive_without_dollar() {
+        let completed = vec!["echo".to_string()];
+        assert!(EnvVarProvider.suggest(&ctx(&completed, "PAT"), 10).is_empty());
+        assert!(EnvVarProvider.suggest(&ctx(&completed, ""), 10).is_empty());
+        assert!(EnvVarProvider.suggest(&ctx(&[], "$PAT"), 10).is_empty());
+    }
+
+    fn path_texts(partial: &str) -> Vec<String> {
+        let completed = vec!["cat".to_string()];
+        PathProvider::default()
+            .suggest(&ctx(&completed, partial), 10)
+            .into_iter()
+            .map(|s| s.text)
+            .collect()
+    }
+
+    #[test]
+    fn test_path_provider_lists_matching_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("src")).unwrap();
+        std::fs::write(dir.path().join("main.rs"), "").unwrap();
+        std::fs::write(dir.path().join("map.txt"), "").unwrap();
+        std::fs::write(dir.path().join(".hidden"), "").unwrap();
+        let base = format!("{}/", dir.path().display());
+
+        assert_eq!(
+            path_texts(&format!("{}ma", base)),
+            vec![format!("{}main.rs", base), format!("{}map.txt", base)]
+        );
+        // Directories get a trailing slash
+        assert_eq!(path_texts(&format!("{}s", base)), vec![format!("{}src/", base)]);
+        // Hidden entries only when asked for
+        assert_eq!(path_texts(&base).len(), 3);
+        assert_eq!(path_texts(&format!("{}.", base)), vec![format!("{}.hidden", base)]);
+    }
+
+    #[test]
+    fn test_path_provider_reuses_listing_until_dir_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a.txt"), "").unwrap();
+        let base = format!("{}/", dir.path().display());
+        let provider = PathProvider::default();
+        let texts = |partial: &str| -> Vec<String> {
+            let completed = vec!["cat".to_string()];
+            provider.suggest(&ctx(&completed, partial), 10).into_iter().map(|s| s.text).collect()
+        };
+
+        assert_eq!(texts(&base), vec![format!("{}a.txt", base)]);
+        assert_eq!(texts(&format!("{}a", base)), vec![format!("{}a.txt", base)]);
+        let cached = provider.cache.lock().unwrap().as_ref().map(|c| c.dir.clone());
+        assert_eq!(cached, Some(dir.path().to_path_buf()));
+
+        // A new entry changes the directory's mtime: listed again
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        std::fs::write(dir.path().join("b.txt"), "").unwrap();
+        assert_eq!(texts(&base), vec![format!("{}a.txt", base), format!("{}b.txt", base)]);
+    }
+
+    #[test]
+    fn test_path_provider_only_for_path_like_tokens() {
+        assert!(path_texts("main").is_empty());
+        assert!(path_texts("--release").is_empty());
+        assert_eq!(path_texts("~"), vec!["~/"]);
+    }
+
+    #[test]
+    fn test_path_provider_unreadable_dir() {
+        assert!(path_texts("/nonexistent/dir/x").is_empty());
+    }
+
+    #[test]
+    fn test_resolve_dir() {
+        let home = Some(PathBuf::from("/home/u"));
+        assert_eq!(resolve_dir("", home.clone()), Some(PathBuf::from(".")));
+        assert_eq!(resolve_dir("~/src/", home.clone()), Some(PathBuf::from("/home/u/src/")));
+        assert_eq!(resolve_dir("./src/", home), Some(PathBuf::from("./src/")));
+        assert_eq!(resolve_dir("~/", None), None);
+    }
+}
@@ -1,15 +1,65 @@
 use std::collections::HashMap;
 use std::io::Read;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc::error::TrySendError;
 use tokio::sync::{Semaphore, mpsc};
 use tokio::task::JoinHandle;
 
+use crate::events::{EventSink, TaskEvent};
+
 pub type TaskId = u64;
 
+/// Groups the tasks of one parallel submission so they can be cancelled together
+pub type BatchId = u64;
+
+/// Completion status of a task cancelled before it started
+pub const CANCELLED_STATUS: &str = "cancelled";
+
+/// Start of the status sent before a failed task is run again: `retry 1/3: ...`
+pub const RETRY_STATUS: &str = "retry";
+
+/// Wait before the first retry of a failed command; doubles with each retry
+const RETRY_BACKOFF: std::time::Duration = std::time::Duration::from_millis(500);
+/// Longest wait between retries
+const MAX_RETRY_BACKOFF: std::time::Duration = std::time::Duration::from_secs(8);
+
+/// Default `[runner] kill_grace_ms`: how long a cancelled task gets to exit
+/// after SIGTERM before it's sent SIGKILL
+pub const DEFAULT_KILL_GRACE_MS: u64 = 2000;
+/// How often a cancelled task is checked for having exited during its grace period
+const KILL_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(10);
+/// How much longer than the grace period `cancel_all` waits for tasks to stop
+const CANCEL_ALL_SLACK: std::time::Duration = std::time::Duration::from_millis(500);
+
+/// PTY sizes for tasks, as (cols, rows)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PtySizes {
+    /// A standalone task's
+    pub single: (u16, u16),
+    /// A task of a batch, which may share the width with the batch's other tasks
+    pub batch: (u16, u16),
+}
+
+impl PtySizes {
+    /// The same size for every task
+    pub fn uniform(cols: u16, rows: u16) -> Self {
+        Self { single: (cols, rows), batch: (cols, rows) }
+    }
+
+    fn for_task(&self, in_batch: bool) -> (u16, u16) {
+        if in_batch { self.batch } else { self.single }
+    }
+}
+
+/// A task's process, once it has been spawned
+type ChildHandle = Arc<Mutex<Option<Box<dyn portable_pty::Child + Send>>>>;
+
 /// Type of output stream
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, Default, PartialEq)]
 pub enum StreamType {
     /// Command output (stdout + stderr merged via PTY)
+    #[default]
     Output,
     /// Lifecycle events: "completed", "exited with code 1", etc.
     Status,
@@ -23,6 +73,21 @@ pub struct OutputMessage {
     pub runner_label: String,
     pub stream: StreamType,
     pub content: String,
+    /// Set on the final status message of a task
+    pub stats: Option<TaskStats>,
+}
+
+/// Exit code and output statistics for a finished task
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TaskStats {
+    /// Process exit code; None if the task failed before producing one
+    pub exit_code: Option<u32>,
+    pub lines: usize,
+    /// Raw bytes read from the PTY
+    pub bytes: u64,
+    pub elapsed: std::time::Duration,
+    /// How many times the command ran: 1, or more after `[retry=N]`
+    pub attempts: u32,
 }
 
 impl OutputMessage {
@@ -32,6 +97,7 @@ impl OutputMessage {
             runner_label: runner_label.to_string(),
             stream: StreamType::Output,
             content,
+            stats: None,
         }
     }
 
@@ -41,6 +107,15 @@ impl OutputMessage {
             runner_label: runner_label.to_string(),
             stream: StreamType::Status,
             content: content.to_string(),
+            stats: None,
+        }
+    }
+
+    /// Final status message of a task, carrying its output statistics
+    pub fn completed(task_id: TaskId, runner_label: &str, content: &str, stats: TaskStats) -> Self {
+        Self {
+            stats: Some(stats),
+            ..Self::status(task_id, runner_label, content)
         }
     }
 }
@@ -50,31 +125,105 @@ struct TaskHandle {
     join: JoinHandle<()>,
     child: Arc<Mutex<Option<Box<dyn portable_pty::Child + Send>>>>,
     master: Arc<Mutex<Option<Box<dyn portable_pty::MasterPty + Send>>>>,
+    batch: Option<BatchId>,
+    /// Set once the task holds a semaphore permit; until then it's queued
+    started: Arc<AtomicBool>,
+    /// Set by `cancel_batch`; checked when the task gets its slot and again
+    /// once its process exists, so a cancelled task never keeps running
+    cancelled: Arc<AtomicBool>,
 }
 
+/// Flag shared by the tasks of a fail-fast batch, set once one of them fails
+type BatchFailed = Arc<AtomicBool>;
+
 /// Manages spawning and tracking of concurrent command tasks.
 /// Uses a semaphore to limit the number of concurrently running processes.
 pub struct TaskRunner {
     output_tx: mpsc::Sender<OutputMessage>,
     next_id: TaskId,
+    next_batch_id: BatchId,
     active: HashMap<TaskId, TaskHandle>,
+    /// Fail-fast batches with unfinished tasks
+    fail_fast: HashMap<BatchId, BatchFailed>,
     semaphore: Arc<Semaphore>,
+    /// Where task lifecycle events go, if `[runner] event_file`/`event_socket` is set
+    events: Option<EventSink>,
+    /// Output lines that found the channel full, since the last `take_output_stalls`
+    stalls: Arc<AtomicUsize>,
+    /// Extra environment variables for every task, e.g. from a `.env` file
+    env: Arc<[(String, String)]>,
+    /// PTY sizes for new tasks, from the last `resize_all`; the terminal size until then
+    pty_size: Arc<Mutex<Option<PtySizes>>>,
+    /// Time between SIGTERM and SIGKILL for cancelled tasks
+    kill_grace: std::time::Duration,
 }
 
 impl TaskRunner {
+    /// `max_concurrent` of 0 means no limit.
     pub fn new(output_tx: mpsc::Sender<OutputMessage>, max_concurrent: usize) -> Self {
+        let permits = match max_concurrent {
+            0 => Semaphore::MAX_PERMITS,
+            n => n.min(Semaphore::MAX_PERMITS),
+        };
         Self {
             output_tx,
             next_id: 1,
+            next_batch_id: 1,
             active: HashMap::new(),
-            semaphore: Arc::new(Semaphore::new(max_concurrent)),
+            fail_fast: HashMap::new(),
+            semaphore: Arc::new(Semaphore::new(permits)),
+            events: None,
+            stalls: Arc::new(AtomicUsize::new(0)),
+            pty_size: Arc::new(Mutex::new(None)),
+            env: Arc::from([]),
+            kill_grace: std::time::Duration::from_millis(DEFAULT_KILL_GRACE_MS),
         }
     }
 
+    /// How long cancelled tasks get to exit after SIGTERM before they're
+    /// killed; zero kills them straight away
+    pub fn set_kill_grace(&mut self, grace: std::time::Duration) {
+        self.kill_grace = grace;
+    }
+
+    /// Set `vars` in the environment of tasks spawned from now on
+    pub fn set_env(&mut self, vars: Vec<(String, String)>) {
+        self.env = Arc::from(vars);
+    }
+
+    /// Report the lifecycle of tasks spawned from now on to `sink`
+    pub fn set_event_sink(&mut self, sink: EventSink) {
+        self.events = Some(sink);
+    }
+
     /// Spawn a command as an async task. Label is shown in the output box header
     /// (empty for single commands, e.g., "[n=3]" for parallel).
     /// If the pool is full, the task is queued and will start once a slot frees up.
     pub fn spawn_labeled(&mut self, command: &str, label: &str) -> TaskId {
+        self.spawn_in_batch(command, label, None, 0)
+    }
+
+    /// Start a new batch; pass its id to `spawn_in_batch` for each of its
+    /// tasks. In a `fail_fast` batch, a task that fails (after its retries)
+    /// cancels the tasks still queued; running ones finish.
+    pub fn new_batch(&mut self, fail_fast: bool) -> BatchId {
+        let id = self.next_batch_id;
+        self.next_batch_id += 1;
+        if fail_fast {
+            self.fail_fast.insert(id, Arc::new(AtomicBool::new(false)));
+        }
+        id
+    }
+
+    /// Like `spawn_labeled`, but the task belongs to `batch` (see `cancel_batch`)
+    /// and a failing command is re-run up to `retries` times, with a backoff
+    pub fn spawn_in_batch(
+        &mut self,
+        command: &str,
+        label: &str,
+        batch: Option<BatchId>,
+        retries: u32,
+    ) -> TaskId {
         let id = self.next_id;
         self.next_id += 1;
 
@@ -88,63 +237,175 @@ impl TaskRunner {
         let child_for_task = child_handle.clone();
         let master_for_task = master_handle.clone();
         let semaphore = self.semaphore.clone();
+        let events = self.events.clone();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let started = Arc::new(AtomicBool::new(false));
+        let batch_failed = batch.and_then(|batch| self.fail_fast.get(&batch)).cloned();
 
-        let join = tokio::spawn(run_task(id, lbl, cmd, tx, child_for_task, master_for_task, semaphore));
-        self.active.insert(id, TaskHandle { join, child: child_handle, master: master_handle });
+        let join = tokio::spawn(run_task(
+            id,
+            lbl,
+            cmd,
+            tx,
+            TaskControl {
+                child: child_for_task,
+                master: master_for_task,
+                cancelled: cancelled.clone(),
+                started: started.clone(),
+                batch_failed,
+                stalls: self.stalls.clone(),
+                pty_size: self.pty_size.clone(),
+                in_batch: batch.is_some(),
+                env: self.env.clone(),
+                kill_grace: self.kill_grace,
+            },
+            semaphore,
+            events,
+            retries,
+        ));
+        self.active.insert(
+            id,
+            TaskHandle {
+                join,
+                child: child_handle,
+                master: master_handle,
+                batch,
+                started,
+                cancelled,
+            },
+        );
 
-        // Clean up finished tasks
+        // Clean up finished tasks, and batches without any left
         self.active.retain(|_, h| !h.join.is_finished());
+        let active = &self.active;
+        self.fail_fast
+            .retain(|batch, _| active.values().any(|h| h.batch == Some(*batch)));
 
         id
     }
 
-    /// Resize the PTY of all active tasks to the new terminal dimensions
-    pub fn resize_all(&mut self, cols: u16, rows: u16) {
+    /// How many output lines had to wait for room in the output channel since
+    /// the last call. Non-zero means the display isn't keeping up and tasks
+    /// are being slowed down.
+    pub fn take_output_stalls(&self) -> usize {
+        self.stalls.swap(0, Ordering::Relaxed)
+    }
+
+    /// Whether any spawned task (running or queued) hasn't finished yet
+    pub fn has_active_tasks(&mut self) -> bool {
+        self.active.retain(|_, h| !h.join.is_finished());
+        !self.active.is_empty()
+    }
+
+    /// Unfinished tasks as (running, queued): running ones hold a slot,
+    /// queued ones are waiting for `max_concurrent` to let them start
+    pub fn task_counts(&mut self) -> (usize, usize) {
+        self.active.retain(|_, h| !h.join.is_finished());
+        let running = self
+            .active
+            .values()
+            .filter(|h| h.started.load(Ordering::Relaxed))
+            .count();
+        (running, self.active.len() - running)
+    }
+
+    /// Resize the PTY of all active tasks to the new dimensions, which tasks
+    /// started later (including queued ones) get too
+    pub fn resize_all(&mut self, sizes: PtySizes) {
+        if let Ok(mut size) = self.pty_size.lock() {
+            *size = Some(sizes);
+        }
         // Clean up finished tasks first
         self.active.retain(|_, h| !h.join.is_finished());
 
         for (id, handle) in &self.active {
-            if let Ok(guard) = handle.master.lock() {
-                if let Some(ref master) = *guard {
-                    let size = portable_pty::PtySize {
-                        rows,
-                        cols,
-                        pixel_width: 0,
-                        pixel_height: 0,
-                    };
-                    if let Err(e) = master.resize(size) {
-                        log::warn!("Failed to resize PTY for task #{}: {}", id, e);
-                    }
+            if let Ok(guard) = handle.master.lock()
+                && let Some(ref master) = *guard
+            {
+                let (cols, rows) = sizes.for_task(handle.batch.is_some());
+                let size = portable_pty::PtySize {
+                    rows,
+                    cols,
+                    pixel_width: 0,
+                    pixel_height: 0,
+                };
+                if let Err(e) = master.resize(size) {
+                    log::warn!("Failed to resize PTY for task #{}: {}", id, e);
                 }
             }
         }
     }
 
-    /// Cancel all active tasks
-    pub fn cancel_all(&mut self) {
-        for (_, handle) in self.active.drain() {
-            if let Ok(mut guard) = handle.child.lock() {
-                if let Some(ref mut child) = *guard {
-                    let _ = child.kill();
-                }
-            }
+    /// Cancel the unfinished tasks of a batch, leaving other tasks alone.
+    /// Running tasks are stopped in the background (SIGTERM, then SIGKILL
+    /// after the grace period) and report their exit as usual; queued ones
+    /// complete with `CANCELLED_STATUS` without starting. Returns how many
+    /// tasks were cancelled.
+    pub fn cancel_batch(&mut self, batch: BatchId) -> usize {
+        self.active.retain(|_, h| !h.join.is_finished());
+
+        let mut children = Vec::new();
+        for handle in self.active.values().filter(|h| h.batch == Some(batch)) {
+            handle.cancelled.store(true, Ordering::SeqCst);
+            children.push(handle.child.clone());
+        }
+        let count = children.len();
+        let grace = self.kill_grace;
+        std::thread::spawn(move || terminate(children, grace));
+        log::info!("Cancelled {} tasks of batch #{}", count, batch);
+        count
+    }
+
+    /// Cancel all active tasks. Waits up to the grace period for running
+    /// ones to exit after SIGTERM, so none outlive mux; the waiting happens
+    /// on a blocking thread, and gives up `CANCEL_ALL_SLACK` after that.
+    pub async fn cancel_all(&mut self) {
+        let handles: Vec<TaskHandle> = self.active.drain().map(|(_, handle)| handle).collect();
+        let children = handles.iter().map(|h| h.child.clone()).collect();
+        let grace = self.kill_grace;
+        let stopping = tokio::task::spawn_blocking(move || terminate(children, grace));
+        if tokio::time::timeout(grace + CANCEL_ALL_SLACK, stopping).await.is_err() {
+            log::warn!("Cancelled tasks haven't stopped after {:?}; not waiting any longer", grace + CANCEL_ALL_SLACK);
+        }
+        for handle in handles {
             handle.join.abort();
         }
     }
 
 }
 
+/// The task's side of its `TaskHandle`
+#[derive(Clone)]
+struct TaskControl {
+    child: Arc<Mutex<Option<Box<dyn portable_pty::Child + Send>>>>,
+    master: Arc<Mutex<Option<Box<dyn portable_pty::MasterPty + Send>>>>,
+    cancelled: Arc<AtomicBool>,
+    started: Arc<AtomicBool>,
+    /// The batch's flag, if it's fail-fast
+    batch_failed: Option<BatchFailed>,
+    kill_grace: std::time::Duration,
+    stalls: Arc<AtomicUsize>,
+    env: Arc<[(String, String)]>,
+    pty_size: Arc<Mutex<Option<PtySizes>>>,
+    /// Whether the task belongs to a batch, and takes its PTY size
+    in_batch: bool,
+}
+
 /// Run a single command in a PTY, streaming output as OutputMessages.
 /// The PTY ensures child processes see a real terminal and emit colors.
 /// Acquires a semaphore permit before starting — queues if the pool is full.
+/// A failed run is repeated up to `retries` times, each announced with a
+/// `retry 1/3: ...` status; the task keeps its slot while it backs off.
+#[allow(clippy::too_many_arguments)]
 async fn run_task(
     id: TaskId,
     runner_label: String,
     command: String,
     tx: mpsc::Sender<OutputMessage>,
-    child_handle: Arc<Mutex<Option<Box<dyn portable_pty::Child + Send>>>>,
-    master_handle: Arc<Mutex<Option<Box<dyn portable_pty::MasterPty + Send>>>>,
+    control: TaskControl,
     semaphore: Arc<Semaphore>,
+    events: Option<EventSink>,
+    retries: u32,
 ) {
     // Acquire a permit — blocks if max concurrent tasks are already running.
     // The permit is held (via _permit) until this function returns.
@@ -156,53 +417,150 @@ async fn run_task(
             return;
         }
     };
+    control.started.store(true, Ordering::Relaxed);
+
+    let batch_failed = control
+        .batch_failed
+        .as_ref()
+        .is_some_and(|failed| failed.load(Ordering::SeqCst));
+    if control.cancelled.load(Ordering::SeqCst) || batch_failed {
+        if batch_failed {
+            log::info!("Task #{} aborted: another task of its batch failed: {}", id, command);
+        } else {
+            log::info!("Task #{} cancelled before starting: {}", id, command);
+        }
+        let stats = TaskStats {
+            exit_code: None,
+            lines: 0,
+            bytes: 0,
+            elapsed: std::time::Duration::ZERO,
+            attempts: 0,
+        };
+        if let Some(events) = &events {
+            events.emit(&TaskEvent::completed(id, &runner_label, &command, CANCELLED_STATUS, &stats));
+        }
+        let _ = tx
+            .send(OutputMessage::completed(id, &runner_label, CANCELLED_STATUS, stats))
+            .await;
+        return;
+    }
 
     log::info!("Task #{} started: {}", id, command);
     let start = std::time::Instant::now();
+    if let Some(events) = &events {
+        events.emit(&TaskEvent::started(id, &runner_label, &command));
+    }
 
     let _ = tx
         .send(OutputMessage::status(id, &runner_label, "started"))
         .await;
 
-    let cmd = command.clone();
-    let lbl = runner_label.clone();
-    let tx_clone = tx.clone();
+    let mut attempts = 0;
+    let mut line_count = 0;
+    let mut byte_count = 0;
+    let (exit_msg, exit_code) = loop {
+        attempts += 1;
+        let cmd = command.clone();
+        let lbl = runner_label.clone();
+        let tx_clone = tx.clone();
+        let line_events = events.clone();
+        let attempt_control = control.clone();
 
-    let result = tokio::task::spawn_blocking(move || {
-        run_task_blocking(id, &lbl, &cmd, tx_clone, child_handle, master_handle)
-    })
-    .await;
+        let result = tokio::task::spawn_blocking(move || {
+            run_task_blocking(id, &lbl, &cmd, tx_clone, attempt_control, line_events)
+        })
+        .await;
+
+        let (exit_msg, exit_code, lines, bytes) = match result {
+            Ok(Ok((msg, code, lines, bytes))) => (msg, Some(code), lines, bytes),
+            Ok(Err(e)) => (format!("error: {}", e), None, 0, 0),
+            Err(e) => (format!("task panicked: {}", e), None, 0, 0),
+        };
+        line_count += lines;
+        byte_count += bytes;
 
-    let (exit_msg, line_count) = match result {
-        Ok(Ok((msg, lines))) => (msg, lines),
-        Ok(Err(e)) => (format!("error: {}", e), 0),
-        Err(e) => (format!("task panicked: {}", e), 0),
+        // A killed task exits non-zero too; that's no reason to run it again
+        let retry = attempts;
+        if exit_code == Some(0) || retry > retries || control.cancelled.load(Ordering::SeqCst) {
+            break (exit_msg, exit_code);
+        }
+        log::info!("Task #{} {}, retry {}/{}", id, exit_msg, retry, retries);
+        let _ = tx
+            .send(OutputMessage::status(
+                id,
+                &runner_label,
+                &format!("{} {}/{}: {}", RETRY_STATUS, retry, retries, exit_msg),
+            ))
+            .await;
+        tokio::time::sleep(retry_backoff(retry)).await;
+        if control.cancelled.load(Ordering::SeqCst) {
+            break (exit_msg, exit_code);
+        }
     };
 
+    // Set while this task still holds its slot, so no queued task of the
+    // batch can take the slot and start first
+    if exit_code != Some(0)
+        && !control.cancelled.load(Ordering::SeqCst)
+        && let Some(failed) = &control.batch_failed
+    {
+        failed.store(true, Ordering::SeqCst);
+    }
+
     let elapsed = start.elapsed();
     log::info!(
-        "Task #{} finished: {} ({}, {} lines, {:.2?})",
-        id, command, exit_msg, line_count, elapsed
+        "Task #{} finished: {} ({}, {} attempts, {} lines, {} bytes, {:.2?})",
+        id, command, exit_msg, attempts, line_count, byte_count, elapsed
     );
 
+    let stats = TaskStats {
+        exit_code,
+        lines: line_count,
+        bytes: byte_count,
+        elapsed,
+        attempts,
+    };
+    if let Some(events) = &events {
+        events.emit(&TaskEvent::completed(id, &runner_label, &command, &exit_msg, &stats));
+    }
     let _ = tx
-        .send(OutputMessage::status(id, &runner_label, &exit_msg))
+        .send(OutputMessage::completed(id, &runner_label, &exit_msg, stats))
         .await;
 }
 
+/// Wait before retry number `retry` (1-based)
+fn retry_backoff(retry: u32) -> std::time::Duration {
+    RETRY_BACKOFF
+        .saturating_mul(1 << (retry - 1).min(16))
+        .min(MAX_RETRY_BACKOFF)
+}
+
 /// Synchronous PTY execution (runs inside spawn_blocking)
 fn run_task_blocking(
     id: TaskId,
     runner_label: &str,
     command: &str,
     tx: mpsc::Sender<OutputMessage>,
-    child_handle: Arc<Mutex<Option<Box<dyn portable_pty::Child + Send>>>>,
-    master_handle: Arc<Mutex<Option<Box<dyn portable_pty::MasterPty + Send>>>>,
-) -> Result<(String, usize), Box<dyn std::error::Error + Send + Sync>> {
+    control: TaskControl,
+    events: Option<EventSink>,
+) -> Result<(String, u32, usize, u64), Box<dyn std::error::Error + Send + Sync>> {
     use portable_pty::{CommandBuilder, PtySize, native_pty_system};
 
-    // Get actual terminal size, fall back to 80x24
-    let (cols, rows) = crossterm::terminal::size().unwrap_or((80, 24));
+    let TaskControl {
+        child: child_handle,
+        master: master_handle,
+        cancelled,
+        stalls,
+        env,
+        pty_size,
+        in_batch,
+        kill_grace,
+        ..
+    } = control;
+
+    // The size from the last resize, else the actual terminal size, else 80x24
+    let size = pty_size.lock().ok().and_then(|size| *size).map(|sizes| sizes.for_task(in_batch));
+    let (cols, rows) = size.or_else(|| crossterm::terminal::size().ok()).unwrap_or((80, 24));
 
     let pty_system = native_pty_system();
 
@@ -216,6 +574,9 @@ fn run_task_blocking(
     let mut cmd = CommandBuilder::new("sh");
     cmd.arg("-c");
     cmd.arg(command);
+    for (key, value) in env.iter() {
+        cmd.env(key, value);
+    }
 
     let child = pty_pair.slave.spawn_command(cmd)?;
 
@@ -225,6 +586,13 @@ fn run_task_blocking(
         *guard = Some(child);
     }
 
+    // Cancelled while the process was being spawned: `cancel_batch` may have
+    // found no child to stop, so do it here
+    if cancelled.load(Ordering::SeqCst) {
+        let child = child_handle.clone();
+        std::thread::spawn(move || terminate(vec![child], kill_grace));
+    }
+
     // Drop the slave side so we get EOF when the child exits
     drop(pty_pair.slave);
 
@@ -237,60 +605,173 @@ fn run_task_blocking(
         *guard = Some(pty_pair.master);
     }
     let mut buf = [0u8; 4096];
-    let mut partial = String::new();
+    // Bytes after the last newline. Kept undecoded, so a character split
+    // between two reads isn't mangled.
+    let mut partial: Vec<u8> = Vec::new();
     let mut line_count: usize = 0;
+    let mut byte_count: u64 = 0;
+    let emit_line = |content: &str| {
+        if let Some(events) = &events {
+            events.emit(&TaskEvent::Line { id, label: runner_label, content });
+        }
+    };
 
-    loop {
+    'read: loop {
         match reader.read(&mut buf) {
             Ok(0) => break, // EOF
             Ok(n) => {
-                let chunk = String::from_utf8_lossy(&buf[..n]);
-                partial.push_str(&chunk);
+                byte_count += n as u64;
+                partial.extend_from_slice(&buf[..n]);
 
                 // Split on newlines and send complete lines
-                while let Some(newline_pos) = partial.find('\n') {
-                    let line = partial[..newline_pos].to_string();
-                    // Strip trailing \r (PTY uses \r\n)
-                    let line = line.trim_end_matches('\r').to_string();
-                    partial = partial[newline_pos + 1..].to_string();
+                let mut start = 0;
+                while let Some(newline_pos) = partial[start..].iter().position(|&b| b == b'\n') {
+                    let line = decode_line(&partial[start..start + newline_pos]);
+                    start += newline_pos + 1;
 
                     line_count += 1;
-                    if tx.blocking_send(OutputMessage::output(id, runner_label, line)).is_err() {
-                        break;
+                    emit_line(&line);
+                    if !send_line(&tx, OutputMessage::output(id, runner_label, line), &stalls) {
+                        // Nobody is reading any more (shutdown): stop the
+                        // process rather than leave it blocked on a full PTY
+                        if let Ok(mut guard) = child_handle.lock()
+                            && let Some(ref mut child) = *guard
+                        {
+                            let _ = child.kill();
+                        }
+                        break 'read;
                     }
                 }
 
                 // Any remaining content in `partial` is an incomplete line;
                 // keep it in the buffer so the next read can complete it.
                 // Final flush after EOF handles any leftover.
+                partial.drain(..start);
             }
             Err(_) => break,
         }
     }
 
-    // Flush any remaining partial content
-    if !partial.is_empty() {
-        let line = partial.trim_end_matches('\r').to_string();
+    // A last line without a trailing newline is sent like any other, so it
+    // lands in the box the same way. Unless nothing in it would show: the
+    // cursor or color resets many programs print on exit would otherwise
+    // leave a blank row at the bottom of the box.
+    let line = decode_line(&partial);
+    if !crate::pager::plain_text(&line).is_empty() {
         line_count += 1;
-        let _ = tx.blocking_send(OutputMessage::output(id, runner_label, line));
+        emit_line(&line);
+        send_line(&tx, OutputMessage::output(id, runner_label, line), &stalls);
     }
 
     // Wait for the child to finish
-    let exit_msg = {
+    let (exit_msg, exit_code) = {
         let mut guard = child_handle.lock().map_err(|e| format!("lock error: {}", e))?;
         if let Some(ref mut child) = *guard {
             let status = child.wait()?;
             if status.success() {
-                "completed".to_string()
+                ("completed".to_string(), 0)
             } else {
-                format!("exited with code {}", status.exit_code())
+                (exit_message(status.exit_code()), status.exit_code())
             }
         } else {
-            "completed".to_string()
+            ("completed".to_string(), 0)
         }
     };
 
-    Ok((exit_msg, line_count))
+    Ok((exit_msg, exit_code, line_count, byte_count))
+}
+
+/// Stop the processes of cancelled tasks: SIGTERM to each one's process
+/// group, so the commands its shell started get it too, then SIGKILL to
+/// the groups still running after `grace`. Blocks until they've stopped.
+#[cfg(unix)]
+fn terminate(children: Vec<ChildHandle>, grace: std::time::Duration) {
+    let mut running: Vec<(ChildHandle, i32)> = children
+        .into_iter()
+        .filter_map(|child| {
+            let pid = child.lock().ok()?.as_ref()?.process_id()?;
+            let pid = i32::try_from(pid).ok()?;
+            signal_group(pid, libc::SIGTERM);
+            Some((child, pid))
+        })
+        .collect();
+
+    let deadline = std::time::Instant::now() + grace;
+    while !running.is_empty() && std::time::Instant::now() < deadline {
+        std::thread::sleep(KILL_POLL_INTERVAL);
+        running.retain(|(child, pid)| !group_stopped(child, *pid));
+    }
+    for (_, pid) in running {
+        log::info!("Process {} still running {:?} after SIGTERM; killing it", pid, grace);
+        signal_group(pid, libc::SIGKILL);
+    }
+}
+
+/// Without process groups to signal, tasks are stopped as portable-pty does it
+#[cfg(not(unix))]
+fn terminate(children: Vec<ChildHandle>, _grace: std::time::Duration) {
+    for child in children {
+        if let Ok(mut guard) = child.lock()
+            && let Some(ref mut child) = *guard
+        {
+            let _ = child.kill();
+        }
+    }
+}
+
+/// Send `signal` to the process group `pid` leads (the PTY makes a task's
+/// shell a session leader), or to `pid` alone if it has none
+#[cfg(unix)]
+fn signal_group(pid: i32, signal: libc::c_int) {
+    // SAFETY: kill has no memory effects; a stale pid only fails with ESRCH
+    if unsafe { libc::kill(-pid, signal) } != 0 {
+        unsafe { libc::kill(pid, signal) };
+    }
+}
+
+/// Whether every process of the group `pid` leads has exited. The shell is
+/// reaped here if it's done, since a zombie still counts as a group member;
+/// while the task itself is waiting on it, that's left to the task.
+#[cfg(unix)]
+fn group_stopped(child: &ChildHandle, pid: i32) -> bool {
+    if let Ok(mut guard) = child.try_lock()
+        && let Some(ref mut child) = *guard
+    {
+        let _ = child.try_wait();
+    }
+    // SAFETY: signal 0 only checks that the group exists
+    unsafe { libc::kill(-pid, 0) != 0 }
+}
+
+/// A line of PTY output as text, without the `\r` of the PTY's `\r\n`
+fn decode_line(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).trim_end_matches('\r').to_string()
+}
+
+/// Status text for a failed exit. Codes the shell gives a meaning to are
+/// spelled out, keeping the number: "command not found (127)".
+fn exit_message(code: u32) -> String {
+    let meaning = match code {
+        126 => "not executable",
+        127 => "command not found",
+        130 => "interrupted",
+        137 => "killed, possibly out of memory",
+        _ => return format!("exited with code {}", code),
+    };
+    format!("{} ({})", meaning, code)
+}
+
+/// Send a line from the reader thread, waiting if the channel is full (and
+/// counting the stall). Returns false once the receiver is gone, e.g. at shutdown.
+fn send_line(tx: &mpsc::Sender<OutputMessage>, msg: OutputMessage, stalls: &AtomicUsize) -> bool {
+    match tx.try_send(msg) {
+        Ok(()) => true,
+        Err(TrySendError::Full(msg)) => {
+            stalls.fetch_add(1, Ordering::Relaxed);
+            tx.blocking_send(msg).is_ok()
+        }
+        Err(TrySendError::Closed(_)) => false,
+    }
 }
 
 #[cfg(test)]
@@ -310,6 +791,7 @@ mod tests {
         assert_eq!(msg.stream, StreamType::Status);
         assert_eq!(msg.content, "started");
         assert_eq!(msg.runner_label, "");
+        assert!(msg.stats.is_none());
     }
 
     #[tokio::test]
@@ -328,6 +810,10 @@ mod tests {
             match msg.stream {
                 StreamType::Status if msg.content == "started" => got_started = true,
                 StreamType::Status if msg.content == "completed" => {
+                    let stats = msg.stats.expect("completion should carry stats");
+                    assert_eq!(stats.exit_code, Some(0));
+                    assert_eq!(stats.lines, 1);
+                    assert!(stats.bytes >= "hello".len() as u64);
                     got_completed = true;
                     break;
                 }
@@ -361,6 +847,66 @@ mod tests {
         panic!("should have received an exit status message");
     }
 
+    #[test]
+    fn test_exit_message() {
+        assert_eq!(exit_message(1), "exited with code 1");
+        assert_eq!(exit_message(126), "not executable (126)");
+        assert_eq!(exit_message(127), "command not found (127)");
+        assert_eq!(exit_message(130), "interrupted (130)");
+        assert_eq!(exit_message(137), "killed, possibly out of memory (137)");
+    }
+
+    /// The output lines of `command` and the line count its completion reports
+    async fn output_of(command: &str) -> (Vec<String>, usize) {
+        let (tx, mut rx) = mpsc::channel::<OutputMessage>(64);
+        let mut runner = TaskRunner::new(tx, 64);
+        runner.spawn_labeled(command, "");
+
+        let mut lines = Vec::new();
+        while let Some(msg) = rx.recv().await {
+            if let Some(stats) = msg.stats {
+                return (lines, stats.lines);
+            }
+            if msg.stream == StreamType::Output {
+                lines.push(msg.content);
+            }
+        }
+        panic!("should have received a final status message");
+    }
+
+    #[tokio::test]
+    async fn test_last_line_with_or_without_newline() {
+        assert_eq!(output_of("printf 'no newline'").await, (vec!["no newline".to_string()], 1));
+        assert_eq!(output_of("printf 'newline\\n'").await, (vec!["newline".to_string()], 1));
+        let both = vec!["first".to_string(), "no newline".to_string()];
+        assert_eq!(output_of("printf 'first\\nno newline'").await, (both, 2));
+
+        // Escape codes printed after the last line don't make a blank one
+        assert_eq!(output_of("printf 'done\\n\\033[?25h\\033[0m'").await, (vec!["done".to_string()], 1));
+        assert_eq!(output_of("printf 'done\\r\\n\\r'").await, (vec!["done".to_string()], 1));
+
+        // A character split between reads comes through whole
+        let (lines, _) = output_of("printf 'a%.0s' $(seq 4095); printf 'é\\n'").await;
+        assert!(lines[0].ends_with("aé"), "{:?}", &lines[0][4090..]);
+    }
+
+    #[tokio::test]
+    async fn test_missing_binary_is_reported() {
+        let (tx, mut rx) = mpsc::channel::<OutputMessage>(64);
+        let mut runner = TaskRunner::new(tx, 64);
+
+        runner.spawn_labeled("mux-no-such-command-xyz", "");
+
+        while let Some(msg) = rx.recv().await {
+            if let Some(stats) = msg.stats {
+                assert_eq!(stats.exit_code, Some(127));
+                assert_eq!(msg.content, "command not found (127)");
+                return;
+            }
+        }
+        panic!("should have received a final status message");
+    }
+
     #[tokio::test]
     async fn test_task_ids_increment() {
         let (tx, _rx) = mpsc::channel::<OutputMessage>(64);
@@ -371,6 +917,173 @@ mod tests {
         assert_eq!(runner.spawn_labeled("true", ""), 3);
     }
 
+    #[tokio::test]
+    async fn test_event_sink_sees_task_lifecycle() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("events.jsonl");
+        let config = crate::config::RunnerConfig {
+            event_file: Some(path.clone()),
+            ..crate::config::RunnerConfig::default()
+        };
+        let (tx, mut rx) = mpsc::channel::<OutputMessage>(64);
+        let mut runner = TaskRunner::new(tx, 64);
+        runner.set_event_sink(EventSink::open(&config).unwrap().unwrap());
+
+        runner.spawn_labeled("echo hello; exit 3", "[n=1]");
+        while let Some(msg) = rx.recv().await {
+            if msg.stats.is_some() {
+                break;
+            }
+        }
+
+        // The sink writes on its own thread; give it a moment
+        let mut content = String::new();
+        for _ in 0..100 {
+            content = std::fs::read_to_string(&path).unwrap_or_default();
+            if content.contains("\"completed\"") {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        let events: Vec<serde_json::Value> =
+            content.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        let kinds: Vec<&str> = events.iter().map(|e| e["event"].as_str().unwrap()).collect();
+        assert_eq!(kinds, ["started", "line", "completed"]);
+        assert_eq!(events[0]["command"], "echo hello; exit 3");
+        assert_eq!(events[1]["content"], "hello");
+        assert_eq!(events[2]["label"], "[n=1]");
+        assert_eq!(events[2]["exit_code"], 3);
+    }
+
+    #[tokio::test]
+    async fn test_full_channel_counts_stalls() {
+        let (tx, mut rx) = mpsc::channel::<OutputMessage>(1);
+        let mut runner = TaskRunner::new(tx, 64);
+        assert_eq!(runner.take_output_stalls(), 0);
+
+        runner.spawn_labeled("seq 1 50", "");
+        tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+        assert!(runner.take_output_stalls() > 0);
+
+        while let Some(msg) = rx.recv().await {
+            if msg.stats.is_some() {
+                break;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_closed_receiver_unblocks_tasks() {
+        let (tx, mut rx) = mpsc::channel::<OutputMessage>(1);
+        let mut runner = TaskRunner::new(tx, 64);
+
+        runner.spawn_labeled("yes", "");
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        rx.close();
+
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+        while runner.has_active_tasks() {
+            assert!(std::time::Instant::now() < deadline, "task stayed blocked on a closed channel");
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+    }
+
+    #[test]
+    fn test_retry_backoff() {
+        assert_eq!(retry_backoff(1), RETRY_BACKOFF);
+        assert_eq!(retry_backoff(2), RETRY_BACKOFF * 2);
+        assert_eq!(retry_backoff(100), MAX_RETRY_BACKOFF);
+    }
+
+    #[tokio::test]
+    async fn test_failing_command_is_retried() {
+        let dir = tempfile::tempdir().unwrap();
+        let marker = dir.path().join("attempts");
+        let (tx, mut rx) = mpsc::channel::<OutputMessage>(64);
+        let mut runner = TaskRunner::new(tx, 64);
+
+        // Fails on the first run, succeeds on the second
+        let command = format!(
+            "echo run >> {0}; test $(wc -l < {0}) -ge 2",
+            marker.display()
+        );
+        runner.spawn_in_batch(&command, "", None, 3);
+
+        let mut statuses = Vec::new();
+        let stats = loop {
+            let msg = rx.recv().await.unwrap();
+            if let Some(stats) = msg.stats {
+                statuses.push(msg.content);
+                break stats;
+            }
+            if msg.stream == StreamType::Status {
+                statuses.push(msg.content);
+            }
+        };
+        assert_eq!(statuses, ["started", "retry 1/3: exited with code 1", "completed"]);
+        assert_eq!(stats.exit_code, Some(0));
+        assert_eq!(stats.attempts, 2);
+
+        // Out of retries: the last failure is the result
+        runner.spawn_in_batch("exit 4", "", None, 1);
+        let stats = loop {
+            if let Some(stats) = rx.recv().await.unwrap().stats {
+                break stats;
+            }
+        };
+        assert_eq!(stats.exit_code, Some(4));
+        assert_eq!(stats.attempts, 2);
+    }
+
+    #[tokio::test]
+    async fn test_env_is_passed_to_tasks() {
+        let (tx, mut rx) = mpsc::channel::<OutputMessage>(64);
+        let mut runner = TaskRunner::new(tx, 64);
+        runner.set_env(vec![("MUX_TEST_STAGE".to_string(), "prod eu".to_string())]);
+
+        runner.spawn_labeled("echo \"stage=$MUX_TEST_STAGE\"", "");
+        let mut output = Vec::new();
+        while let Some(msg) = rx.recv().await {
+            if msg.stats.is_some() {
+                break;
+            }
+            if msg.stream == StreamType::Output {
+                output.push(msg.content);
+            }
+        }
+        assert_eq!(output, ["stage=prod eu"]);
+    }
+
+    #[tokio::test]
+    async fn test_zero_max_concurrent_is_unbounded() {
+        let (tx, _rx) = mpsc::channel::<OutputMessage>(64);
+        let runner = TaskRunner::new(tx.clone(), 0);
+        assert_eq!(runner.semaphore.available_permits(), Semaphore::MAX_PERMITS);
+
+        let runner = TaskRunner::new(tx, 3);
+        assert_eq!(runner.semaphore.available_permits(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_task_counts_running_and_queued() {
+        let (tx, mut rx) = mpsc::channel::<OutputMessage>(64);
+        let mut runner = TaskRunner::new(tx, 1);
+        assert_eq!(runner.task_counts(), (0, 0));
+
+        runner.spawn_labeled("echo running && sleep 10", "[n=1]");
+        runner.spawn_labeled("echo queued", "[n=2]");
+        runner.spawn_labeled("echo queued", "[n=3]");
+        while let Some(msg) = rx.recv().await {
+            if msg.stream == StreamType::Output && msg.content.contains("running") {
+                break;
+            }
+        }
+        assert_eq!(runner.task_counts(), (1, 2));
+
+        runner.cancel_all().await;
+        assert_eq!(runner.task_counts(), (0, 0));
+    }
+
     #[tokio::test]
     async fn test_cancel_all() {
         let (tx, mut rx) = mpsc::channel::<OutputMessage>(64);
@@ -387,11 +1100,176 @@ mod tests {
         }
 
         // Kill all tasks
-        runner.cancel_all();
+        runner.cancel_all().await;
 
         // Give the PTY reader time to notice the child died
         tokio::time::sleep(std::time::Duration::from_secs(1)).await;
         // All tasks should be drained from the active map
         assert!(runner.active.is_empty());
     }
+
+    #[tokio::test]
+    async fn test_cancel_batch_leaves_other_tasks() {
+        let (tx, mut rx) = mpsc::channel::<OutputMessage>(64);
+        let mut runner = TaskRunner::new(tx, 2);
+
+        let solo = runner.spawn_labeled("echo solo && sleep 10", "");
+        let batch = runner.new_batch(false);
+        let running = runner.spawn_in_batch("echo running && sleep 10", "[n=1]", Some(batch), 0);
+        // No free slot: stays queued until the running batch task dies
+        let queued = runner.spawn_in_batch("echo never", "[n=2]", Some(batch), 0);
+
+        let mut seen = 0;
+        while let Some(msg) = rx.recv().await {
+            if msg.stream == StreamType::Output && (msg.content.contains("solo") || msg.content.contains("running")) {
+                seen += 1;
+                if seen == 2 {
+                    break;
+                }
+            }
+        }
+
+        assert_eq!(runner.cancel_batch(batch), 2);
+
+        let mut finished = HashMap::new();
+        let wait = async {
+            while let Some(msg) = rx.recv().await {
+                if let Some(stats) = msg.stats {
+                    finished.insert(msg.task_id, (msg.content, stats.exit_code));
+                    if finished.len() == 2 {
+                        break;
+                    }
+                }
+            }
+        };
+        tokio::time::timeout(std::time::Duration::from_secs(5), wait)
+            .await
+            .expect("batch tasks should finish after cancel");
+
+        assert_ne!(finished[&running].1, Some(0));
+        assert_eq!(finished[&queued], (CANCELLED_STATUS.to_string(), None));
+        assert!(!finished.contains_key(&solo));
+        assert!(runner.has_active_tasks());
+
+        runner.cancel_all().await;
+    }
+
+    #[tokio::test]
+    async fn test_fail_fast_batch_cancels_queued_tasks() {
+        let (tx, mut rx) = mpsc::channel::<OutputMessage>(64);
+        let mut runner = TaskRunner::new(tx, 2);
+        let batch = runner.new_batch(true);
+
+        let running = runner.spawn_in_batch("echo slow && sleep 0.5", "[n=1]", Some(batch), 0);
+        let failing = runner.spawn_in_batch("sleep 0.2; exit 1", "[n=2]", Some(batch), 0);
+        let mut started = 0;
+        while let Some(msg) = rx.recv().await {
+            if msg.content == "started" {
+                started += 1;
+                if started == 2 {
+                    break;
+                }
+            }
+        }
+        // Both slots are taken: these wait for one
+        let queued = [
+            runner.spawn_in_batch("echo never", "[n=3]", Some(batch), 0),
+            runner.spawn_in_batch("echo never", "[n=4]", Some(batch), 0),
+        ];
+
+        let mut finished = HashMap::new();
+        let wait = async {
+            while let Some(msg) = rx.recv().await {
+                assert_ne!(msg.content, "never");
+                if let Some(stats) = msg.stats {
+                    finished.insert(msg.task_id, (msg.content, stats.exit_code));
+                    if finished.len() == 4 {
+                        break;
+                    }
+                }
+            }
+        };
+        tokio::time::timeout(std::time::Duration::from_secs(5), wait)
+            .await
+            .expect("the batch should finish");
+
+        // The running task finishes, the queued ones never start
+        assert_eq!(finished[&running].1, Some(0));
+        assert_eq!(finished[&failing].1, Some(1));
+        for id in queued {
+            assert_eq!(finished[&id], (CANCELLED_STATUS.to_string(), None));
+        }
+
+        // Other batches carry on after a failure
+        let batch = runner.new_batch(false);
+        runner.spawn_in_batch("exit 1", "[n=1]", Some(batch), 0);
+        runner.spawn_in_batch("exit 1", "[n=2]", Some(batch), 0);
+        let after = runner.spawn_in_batch("echo ran", "[n=3]", Some(batch), 0);
+        let ran = async {
+            while let Some(msg) = rx.recv().await {
+                if msg.task_id == after
+                    && let Some(stats) = msg.stats
+                {
+                    return stats.exit_code;
+                }
+            }
+            None
+        };
+        let exit = tokio::time::timeout(std::time::Duration::from_secs(5), ran).await.unwrap();
+        assert_eq!(exit, Some(0));
+    }
+
+    /// Cancel `command`'s batch once it prints "ready"; its output lines and
+    /// exit code, and how long it took to stop
+    async fn cancel_when_ready(
+        command: &str,
+        grace: std::time::Duration,
+    ) -> (Vec<String>, Option<u32>, std::time::Duration) {
+        let (tx, mut rx) = mpsc::channel::<OutputMessage>(64);
+        let mut runner = TaskRunner::new(tx, 4);
+        runner.set_kill_grace(grace);
+        let batch = runner.new_batch(false);
+        runner.spawn_in_batch(command, "[n=1]", Some(batch), 0);
+
+        let mut lines = Vec::new();
+        while let Some(msg) = rx.recv().await {
+            if msg.stream == StreamType::Output && msg.content.contains("ready") {
+                break;
+            }
+        }
+        let cancelled = std::time::Instant::now();
+        assert_eq!(runner.cancel_batch(batch), 1);
+
+        let wait = async {
+            while let Some(msg) = rx.recv().await {
+                if let Some(stats) = msg.stats {
+                    return stats.exit_code;
+                }
+                if msg.stream == StreamType::Output {
+                    lines.push(msg.content);
+                }
+            }
+            None
+        };
+        let exit = tokio::time::timeout(std::time::Duration::from_secs(10), wait)
+            .await
+            .expect("the task should stop after cancel");
+        (lines, exit, cancelled.elapsed())
+    }
+
+    #[tokio::test]
+    async fn test_cancel_lets_task_handle_sigterm() {
+        let command = "trap 'echo cleaning up; exit 3' TERM; echo ready; while :; do sleep 0.1; done";
+        let (lines, exit, _) = cancel_when_ready(command, std::time::Duration::from_secs(5)).await;
+        assert!(lines.iter().any(|line| line.contains("cleaning up")), "{:?}", lines);
+        assert_eq!(exit, Some(3));
+    }
+
+    #[tokio::test]
+    async fn test_cancel_kills_task_ignoring_sigterm() {
+        let command = "trap '' TERM; echo ready; sleep 30";
+        let (_, exit, took) = cancel_when_ready(command, std::time::Duration::from_millis(200)).await;
+        assert_ne!(exit, Some(0));
+        assert!(took < std::time::Duration::from_secs(5), "took {:?}", took);
+    }
 }
@@ -1,23 +1,218 @@
-use log::{debug, info};
-use nucleo_matcher::{Config, Matcher, Utf32String};
-use rusqlite::{params, Connection, Result as SqlResult};
-use std::path::PathBuf;
-
-use crate::history::{HistoryEntry, HistoryReader, Shell};
+use log::{debug, info, warn};
+use nucleo_matcher::{Config, Matcher, Utf32Str, Utf32String};
+use rusqlite::{params, Connection, ErrorCode, OpenFlags, Result as SqlResult};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::error::Error;
+use crate::history::{read_command_output, HistoryEntry, HistoryReader, Shell};
 
 /// In-memory command history searcher with persistent SQLite backing
 pub struct HistorySearcher {
-    /// All indexed commands (sorted by frequency DESC)
-    entries: Vec<IndexedCommand>,
+    /// All indexed commands with their match haystacks (sorted by frequency DESC)
+    entries: Vec<SearchEntry>,
 
-    /// Pre-computed Utf32String representations for fuzzy matching (parallel to entries)
-    haystacks: Vec<Utf32String>,
+    /// Command text -> index into entries, kept in sync on every reorder
+    positions: HashMap<String, usize>,
 
     /// Nucleo fuzzy matcher
     matcher: Matcher,
 
+    /// Matcher tuned for file paths (bonuses after `/`), for queries containing one
+    path_matcher: Matcher,
+
+    /// Use `path_matcher` for path-like queries
+    path_aware: bool,
+
+    /// Shorter queries skip the fuzzy scan (see `set_min_fuzzy_length`)
+    min_fuzzy_length: usize,
+
+    /// Scratch buffer reused for non-ASCII queries
+    query_buf: Vec<char>,
+
     /// SQLite database connection
     db: Connection,
+
+    /// When set, nothing is ever written to the database
+    read_only: bool,
+
+    /// Split queries on whitespace and require every term to match
+    match_all_terms: bool,
+
+    /// How matches are ordered
+    rank_by: RankBy,
+
+    /// Which nucleo matcher configuration scores matches
+    algorithm: MatchAlgorithm,
+
+    /// Multipliers for the fuzzy score and the frequency bonus
+    fuzzy_weight: u32,
+    frequency_weight: u32,
+
+    /// Remove escape sequences and control characters from commands before storing them
+    strip_ansi: bool,
+
+    /// Drop trailing `# comments` so annotated variants share one entry
+    strip_comments: bool,
+    /// Prompts (`$ `) removed from the start of imported commands
+    strip_prompt_prefixes: Vec<String>,
+
+    /// History entries committed per transaction when syncing; 0 commits
+    /// each sync in one transaction
+    sync_batch_size: usize,
+
+    /// Favour commands usually run around the current hour and weekday
+    time_aware: bool,
+
+    /// Command id -> when it's been run; only loaded while `time_aware` is on
+    time_profiles: HashMap<i64, TimeProfile>,
+
+    /// Percent of a command's score taken away at a failure rate of 1
+    failure_penalty: u32,
+
+    /// Command id -> (success_count, fail_count); only loaded while
+    /// `failure_penalty` is set
+    outcomes: HashMap<i64, (u32, u32)>,
+
+    /// Command id -> its tags, sorted
+    tags: HashMap<i64, Vec<String>>,
+
+    /// Command id -> (mean runtime in ms, timed runs), for commands whose
+    /// runtime was measured
+    durations: HashMap<i64, (u64, u32)>,
+}
+
+/// How search results are ordered
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RankBy {
+    /// Fuzzy score plus a bonus for often-used commands
+    #[default]
+    Frequency,
+    /// Most recently used first; the fuzzy score only breaks ties
+    Recency,
+    /// Fuzzy score plus bonuses for both frequency and recency
+    Balanced,
+}
+
+/// How a query is fuzzy matched against commands: presets of nucleo's
+/// matcher configuration
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MatchAlgorithm {
+    /// Case-insensitive, accented Latin letters match their plain form, with
+    /// bonuses at word starts
+    #[default]
+    Default,
+    /// Like `Default`, but with the bonuses placed after `/` for every query,
+    /// as `path_aware` does only for queries containing one
+    Paths,
+    /// Like `Default`, plus a small bonus for matches near the start of the
+    /// command, for typing commands from their first word
+    Prefix,
+    /// Case-sensitive when the query contains an uppercase letter
+    Smartcase,
+    /// Case-sensitive, and accented letters only match themselves
+    Strict,
+}
+
+impl MatchAlgorithm {
+    fn config(self) -> Config {
+        let mut config = match self {
+            Self::Paths => Config::DEFAULT.match_paths(),
+            _ => Config::DEFAULT,
+        };
+        config.prefer_prefix = self == Self::Prefix;
+        config.normalize = self != Self::Strict;
+        config
+    }
+
+    /// Whether `query` is matched with its case
+    fn case_sensitive(self, query: &str) -> bool {
+        match self {
+            Self::Strict => true,
+            Self::Smartcase => query.chars().any(char::is_uppercase),
+            _ => false,
+        }
+    }
+}
+
+/// How long a statement waits for another connection's lock; rusqlite's default
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Default multiplier for the fuzzy score
+pub const DEFAULT_FUZZY_WEIGHT: u32 = 1;
+/// Default bonus per use of a command, in fuzzy score points
+pub const DEFAULT_FREQUENCY_WEIGHT: u32 = 10;
+
+/// A database file and the journals SQLite may keep next to it
+const DB_FILE_SUFFIXES: [&str; 4] = ["", "-journal", "-wal", "-shm"];
+
+/// Default number of history entries committed per transaction by a sync
+pub const DEFAULT_SYNC_BATCH_SIZE: usize = 5000;
+
+/// Bonus for a command used just now in `Balanced` mode; it halves after a day
+const RECENCY_BONUS: u64 = 1000;
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Bonus for a command always run around this hour on this weekday, with
+/// `[search] time_aware`
+const TIME_AWARE_BONUS: f64 = 1000.0;
+
+/// Runs needed before a command's time-of-day pattern earns the full bonus
+const TIME_AWARE_MIN_RUNS: u32 = 5;
+
+/// Successful runs assumed for every command when `[search]
+/// penalize_failures` computes its failure rate, so a command that failed
+/// once isn't judged on that run alone
+const FAILURE_RATE_PRIOR_RUNS: u32 = 3;
+
+/// Failure rate of a command, smoothed towards 0 by `FAILURE_RATE_PRIOR_RUNS`
+fn smoothed_failure_rate(success_count: u32, fail_count: u32) -> f64 {
+    let runs = success_count + fail_count + FAILURE_RATE_PRIOR_RUNS;
+    fail_count as f64 / runs as f64
+}
+
+/// When a command has been run, local time: runs per hour of the day and
+/// per weekday (Monday first)
+#[derive(Debug, Clone, Default, PartialEq)]
+struct TimeProfile {
+    hours: [u32; 24],
+    weekdays: [u32; 7],
+}
+
+impl TimeProfile {
+    fn add(&mut self, (weekday, hour): (usize, usize), count: u32) {
+        self.hours[hour] += count;
+        self.weekdays[weekday] += count;
+    }
+
+    /// Up to `TIME_AWARE_BONUS`, for the share of runs within an hour of
+    /// `hour` and, weighing less, on `weekday`
+    fn bonus(&self, (weekday, hour): (usize, usize)) -> u32 {
+        let runs: u32 = self.hours.iter().sum();
+        if runs == 0 {
+            return 0;
+        }
+        let near: u32 = [23, 0, 1].iter().map(|offset| self.hours[(hour + offset) % 24]).sum();
+        let hour_share = near as f64 / runs as f64;
+        let weekday_share = self.weekdays[weekday] as f64 / runs as f64;
+        // A couple of runs at the same time may be chance
+        let confidence = runs.min(TIME_AWARE_MIN_RUNS) as f64 / TIME_AWARE_MIN_RUNS as f64;
+        (TIME_AWARE_BONUS * confidence * (0.75 * hour_share + 0.25 * weekday_share)) as u32
+    }
+}
+
+/// (weekday, hour) of a unix timestamp in local time
+fn time_slot(timestamp: i64) -> Option<(usize, usize)> {
+    use chrono::{Datelike, TimeZone, Timelike};
+
+    let time = chrono::Local.timestamp_opt(timestamp, 0).single()?;
+    Some((time.weekday().num_days_from_monday() as usize, time.hour() as usize))
 }
 
 /// A command entry with metadata
@@ -29,8 +224,316 @@ pub struct IndexedCommand {
     pub last_used: Option<i64>,
 }
 
+/// Recorded run outcomes of a command
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommandOutcomes {
+    pub command: String,
+    pub success_count: u32,
+    pub fail_count: u32,
+}
+
+impl CommandOutcomes {
+    /// Fraction of recorded runs that failed
+    pub fn failure_rate(&self) -> f64 {
+        let runs = self.success_count + self.fail_count;
+        if runs == 0 {
+            0.0
+        } else {
+            self.fail_count as f64 / runs as f64
+        }
+    }
+}
+
+/// Measured runtime of a command
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommandDuration {
+    pub command: String,
+    /// Mean over the measured runs
+    pub mean: Duration,
+    pub runs: u32,
+}
+
+/// How often a command was run, in total and per history source
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShellUsage {
+    pub command: String,
+    pub frequency: u32,
+    /// (source, count), most used first. Sources are the shells synced from
+    /// ("Zsh", "Bash", ...), `mux` for commands run in mux, and `Plain:<path>`
+    /// or `Command:<command>` for extra history sources.
+    pub shells: Vec<(String, u32)>,
+}
+
+/// Health report of a history database, from [`HistorySearcher::diagnose`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnosis {
+    /// `PRAGMA user_version`; 0 until the schema carries a version
+    pub schema_version: i64,
+    /// `PRAGMA journal_mode`, e.g. `delete` or `wal`
+    pub journal_mode: String,
+    /// Size of the database in bytes, and how much of it is free pages
+    pub size: u64,
+    pub free: u64,
+    /// Rows per table, by table name
+    pub row_counts: Vec<(String, i64)>,
+    /// What's wrong with it; empty for a healthy database
+    pub problems: Vec<String>,
+}
+
+/// An indexed command paired with its pre-processed haystack, so the two
+/// can never drift apart when entries are added, reordered or removed
+struct SearchEntry {
+    meta: IndexedCommand,
+    haystack: Haystack,
+}
+
+impl SearchEntry {
+    fn new(meta: IndexedCommand) -> Self {
+        let haystack = Haystack::new(&meta.command);
+        Self { meta, haystack }
+    }
+}
+
+/// A command pre-processed for fuzzy matching
+struct Haystack {
+    text: Utf32String,
+    /// Lowercased ASCII characters present in the command (see `char_mask`)
+    mask: u128,
+}
+
+impl Haystack {
+    fn new(command: &str) -> Self {
+        // Non-ASCII haystacks may normalize to ASCII during matching,
+        // so they can never be ruled out by the mask.
+        let mask = if command.is_ascii() { char_mask(command) } else { u128::MAX };
+        Self {
+            text: Utf32String::from(command),
+            mask,
+        }
+    }
+}
+
+/// Bitset of the lowercased ASCII characters in `s`. Non-ASCII characters are ignored.
+/// A haystack can only match a needle if it contains every needle character,
+/// so `needle_mask & !haystack_mask != 0` rules it out without running the matcher.
+fn char_mask(s: &str) -> u128 {
+    s.bytes()
+        .filter(u8::is_ascii)
+        .fold(0, |mask, b| mask | 1u128 << b.to_ascii_lowercase())
+}
+
+/// A `@since:<N><unit>` prefix restricting search to recently used commands
+#[derive(Debug, PartialEq)]
+pub struct TimeFilter<'a> {
+    /// Earliest `last_used` timestamp (unix seconds) to include
+    pub since: i64,
+    /// The rest of the input, used as the fuzzy query
+    pub query: &'a str,
+}
+
+/// `command` without its first word and the whitespace after it
+pub fn after_first_word(command: &str) -> &str {
+    command
+        .trim_start()
+        .split_once(char::is_whitespace)
+        .map_or("", |(_, rest)| rest.trim_start())
+}
+
+/// A `@tag:<name>` prefix restricting search to commands with that tag
+#[derive(Debug, PartialEq)]
+pub struct TagFilter<'a> {
+    /// The tag, normalized
+    pub tag: String,
+    /// The rest of the input, used as the fuzzy query
+    pub query: &'a str,
+}
+
+/// Parse a leading `@tag:<name>` filter, e.g. `@tag:deploy prod`.
+/// Returns None if the input doesn't start with a valid one.
+pub fn parse_tag_filter(input: &str) -> Option<TagFilter<'_>> {
+    let rest = input.trim_start().strip_prefix("@tag:")?;
+    let (tag, query) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+    Some(TagFilter {
+        tag: normalize_tag(tag)?,
+        query: query.trim_start(),
+    })
+}
+
+/// The stored form of a tag: lowercase, without a leading `#`. None if
+/// nothing is left, or it has whitespace or `:` in it.
+pub fn normalize_tag(tag: &str) -> Option<String> {
+    let tag = tag.strip_prefix('#').unwrap_or(tag);
+    if tag.is_empty() || tag.contains(|c: char| c.is_whitespace() || c == ':') {
+        return None;
+    }
+    Some(tag.to_lowercase())
+}
+
+/// Parse a leading `@since:<N><unit>` filter, where unit is one of
+/// `m` (minutes), `h` (hours), `d` (days) or `w` (weeks), e.g. `@since:2d cargo`.
+/// Returns None if the input doesn't start with a valid filter.
+pub fn parse_time_filter(input: &str, now: i64) -> Option<TimeFilter<'_>> {
+    let rest = input.trim_start().strip_prefix("@since:")?;
+    let (spec, query) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+
+    let unit_pos = spec.len().checked_sub(1)?;
+    let amount: i64 = spec.get(..unit_pos)?.parse().ok()?;
+    let unit_secs = match spec.get(unit_pos..)? {
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return None,
+    };
+
+    Some(TimeFilter {
+        since: now.saturating_sub(amount.saturating_mul(unit_secs)),
+        query: query.trim_start(),
+    })
+}
+
+/// `command` without ANSI escape sequences (CSI such as colors, OSC such as
+/// window titles, and two-byte escapes) and without control characters other
+/// than tab and newline, which multi-line commands keep
+fn strip_ansi(command: &str) -> Cow<'_, str> {
+    let is_unwanted = |c: char| c.is_control() && c != '\t' && c != '\n';
+    if !command.contains(is_unwanted) {
+        return Cow::Borrowed(command);
+    }
+
+    let mut clean = String::with_capacity(command.len());
+    let mut chars = command.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            if !is_unwanted(c) {
+                clean.push(c);
+            }
+            continue;
+        }
+        match chars.next() {
+            // CSI: parameters and intermediates up to a final byte in @..~
+            Some('[') => {
+                for c in chars.by_ref() {
+                    if ('@'..='~').contains(&c) {
+                        break;
+                    }
+                }
+            }
+            // OSC: up to BEL or ST (ESC \)
+            Some(']') => {
+                while let Some(c) = chars.next() {
+                    if c == '\x07' {
+                        break;
+                    }
+                    if c == '\x1b' {
+                        chars.next_if_eq(&'\\');
+                        break;
+                    }
+                }
+            }
+            // Any other escape is two characters long
+            _ => {}
+        }
+    }
+    Cow::Owned(clean)
+}
+
+/// `command` without the first of `prompts` it starts with, ignoring
+/// leading whitespace: `$ ls -la` gives `ls -la`. Only one prompt is removed,
+/// so `$ > out.txt` stays a redirection.
+fn strip_prompt_prefix<'a>(command: &'a str, prompts: &[String]) -> &'a str {
+    let trimmed = command.trim_start();
+    prompts
+        .iter()
+        .find_map(|prompt| trimmed.strip_prefix(prompt.as_str()))
+        .map_or(command, str::trim_start)
+}
+
+/// `command` without a trailing shell comment: `make deploy # prod` gives
+/// `make deploy`. A `#` only starts a comment at the beginning of a word and
+/// outside quotes, so `echo '#1'`, `echo a#b` and `${#args}` are kept. A
+/// comment followed by more lines (inside a multi-line command) stays too.
+fn strip_trailing_comment(command: &str) -> &str {
+    let mut quote = None;
+    let mut escaped = false;
+    let mut word_start = true;
+    for (i, c) in command.char_indices() {
+        if escaped {
+            escaped = false;
+            word_start = false;
+            continue;
+        }
+        match (quote, c) {
+            (Some('\''), '\'') | (Some('"'), '"') => quote = None,
+            (Some('"'), '\\') => escaped = true,
+            (Some(_), _) => {}
+            (None, '\\') => escaped = true,
+            (None, '\'' | '"') => quote = Some(c),
+            (None, '#') if word_start => {
+                if command[i..].contains('\n') {
+                    // Not trailing; the shell skips to the end of the line
+                    continue;
+                }
+                return command[..i].trim_end();
+            }
+            _ => {}
+        }
+        word_start = quote.is_none() && (c.is_whitespace() || matches!(c, ';' | '&' | '|' | '(' | ')'));
+    }
+    command
+}
+
+/// Edit distance between `a` and `b` where swapping two adjacent characters
+/// counts as one edit (optimal string alignment), so `gti` is 1 away from `git`
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    // rows[i][j]: distance between a[..i] and b[..j]
+    let mut rows = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    rows[0] = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut d = (rows[i - 1][j] + 1)
+                .min(rows[i][j - 1] + 1)
+                .min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d = d.min(rows[i - 2][j - 2] + 1);
+            }
+            rows[i][j] = d;
+        }
+    }
+    rows[a.len()][b.len()]
+}
+
+/// Ranking bonus added to a command's fuzzy score. `Recency` adds nothing:
+/// it orders by `last_used` directly; `Balanced` gives frequency half the
+/// weight to leave room for the recency bonus.
+fn rank_bonus(rank_by: RankBy, frequency_weight: u32, meta: &IndexedCommand, now: i64) -> u32 {
+    match rank_by {
+        RankBy::Frequency => meta.frequency.saturating_mul(frequency_weight),
+        RankBy::Recency => 0,
+        RankBy::Balanced => meta
+            .frequency
+            .saturating_mul(frequency_weight / 2)
+            .saturating_add(recency_bonus(meta.last_used, now)),
+    }
+}
+
+/// Up to `RECENCY_BONUS`, decaying with the time since the command was last used
+fn recency_bonus(last_used: Option<i64>, now: i64) -> u32 {
+    let Some(last_used) = last_used else {
+        return 0;
+    };
+    let age = now.saturating_sub(last_used).max(0) as u64;
+    (RECENCY_BONUS * SECONDS_PER_DAY / (SECONDS_PER_DAY + age)) as u32
+}
+
 /// Search result with relevance score
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct SearchResult {
     pub command: String,
     pub score: u32,
@@ -38,7 +541,7 @@ pub struct SearchResult {
 
 impl HistorySearcher {
     /// Create a new HistorySearcher with the given database path
-    pub fn new(db_path: PathBuf) -> Result<Self, Box<dyn std::error::Error>> {
+    pub fn new(db_path: PathBuf) -> Result<Self, Error> {
         debug!("Opening database at: {}", db_path.display());
         let db = Connection::open(&db_path)?;
 
@@ -46,24 +549,203 @@ impl HistorySearcher {
         debug!("Initializing database schema");
         Self::init_schema(&db)?;
 
+        Self::from_connection(db, false)
+    }
+
+    /// Create a HistorySearcher backed by an in-memory database. Behaves like
+    /// `new`, but nothing outlives the searcher: for tests and `--no-persist`.
+    pub fn new_in_memory() -> Result<Self, Error> {
+        debug!("Opening in-memory database");
+        let db = Connection::open_in_memory()?;
+        Self::init_schema(&db)?;
+
+        Self::from_connection(db, false)
+    }
+
+    /// Open the database read-only. Usage recording, flushing and syncing become
+    /// no-ops, and the file is never modified (works on read-only filesystems).
+    /// A missing database is treated as an empty history.
+    pub fn open_read_only(db_path: PathBuf) -> Result<Self, Error> {
+        let db = if db_path.exists() {
+            debug!("Opening database read-only at: {}", db_path.display());
+            Connection::open_with_flags(
+                &db_path,
+                OpenFlags::SQLITE_OPEN_READ_ONLY
+                    | OpenFlags::SQLITE_OPEN_URI
+                    | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+            )?
+        } else {
+            info!("No database at {}, starting read-only with empty history", db_path.display());
+            let db = Connection::open_in_memory()?;
+            Self::init_schema(&db)?;
+            db
+        };
+
+        Self::from_connection(db, true)
+    }
+
+    /// Load in-memory state from an already initialized connection
+    fn from_connection(db: Connection, read_only: bool) -> Result<Self, Error> {
         // Load data from database
         debug!("Loading commands from database");
         let entries = Self::load_from_db(&db)?;
         info!("Loaded {} commands from database", entries.len());
 
-        let haystacks = entries
-            .iter()
-            .map(|e| Utf32String::from(e.command.as_str()))
-            .collect();
+        let positions = Self::index_positions(&entries);
+        let tags = Self::load_tags(&db)?;
+        let durations = Self::load_durations(&db)?;
 
         Ok(Self {
             entries,
-            haystacks,
+            positions,
             matcher: Matcher::new(Config::DEFAULT),
+            path_matcher: Matcher::new(Config::DEFAULT.match_paths()),
+            path_aware: true,
+            min_fuzzy_length: 1,
+            query_buf: Vec::new(),
             db,
+            read_only,
+            match_all_terms: false,
+            rank_by: RankBy::Frequency,
+            algorithm: MatchAlgorithm::Default,
+            fuzzy_weight: DEFAULT_FUZZY_WEIGHT,
+            frequency_weight: DEFAULT_FREQUENCY_WEIGHT,
+            strip_ansi: true,
+            strip_comments: false,
+            strip_prompt_prefixes: Vec::new(),
+            sync_batch_size: DEFAULT_SYNC_BATCH_SIZE,
+            time_aware: false,
+            time_profiles: HashMap::new(),
+            failure_penalty: 0,
+            outcomes: HashMap::new(),
+            tags,
+            durations,
         })
     }
 
+    /// Whether this searcher was opened read-only
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Treat a query with several whitespace-separated terms as an AND of
+    /// independent fuzzy matches, in any order, instead of one pattern
+    pub fn set_match_all_terms(&mut self, enabled: bool) {
+        self.match_all_terms = enabled;
+    }
+
+    /// Match queries containing `/` with bonuses for path segment starts, so
+    /// `dep/prod` prefers `./scripts/deploy/prod.sh`
+    pub fn set_path_aware(&mut self, enabled: bool) {
+        self.path_aware = enabled;
+    }
+
+    /// Queries shorter than `length` characters aren't fuzzy matched: they
+    /// list the commands starting with the query in ranking order, like an
+    /// empty query does, which is much cheaper on a large history
+    pub fn set_min_fuzzy_length(&mut self, length: usize) {
+        self.min_fuzzy_length = length;
+    }
+
+    pub fn set_rank_by(&mut self, rank_by: RankBy) {
+        self.rank_by = rank_by;
+    }
+
+    /// Match queries with `algorithm`'s matcher configuration. Path-like
+    /// queries under `path_aware` keep its other settings.
+    pub fn set_algorithm(&mut self, algorithm: MatchAlgorithm) {
+        self.algorithm = algorithm;
+        self.matcher = Matcher::new(algorithm.config());
+        self.path_matcher = Matcher::new(algorithm.config().match_paths());
+    }
+
+    /// Set how a match is scored: `fuzzy_weight * fuzzy score +
+    /// frequency_weight * uses`. A higher frequency weight favours habits,
+    /// a higher fuzzy weight favours close matches.
+    pub fn set_rank_weights(&mut self, fuzzy_weight: u32, frequency_weight: u32) {
+        self.fuzzy_weight = fuzzy_weight;
+        self.frequency_weight = frequency_weight;
+    }
+
+    /// Boost commands whose past runs cluster around the current hour and
+    /// weekday. Loads the recorded run times when turned on.
+    pub fn set_time_aware(&mut self, enabled: bool) -> Result<(), Error> {
+        self.time_profiles = if enabled {
+            Self::load_time_profiles(&self.db)?
+        } else {
+            HashMap::new()
+        };
+        self.time_aware = enabled;
+        Ok(())
+    }
+
+    /// Rank commands that often fail lower: their score loses up to
+    /// `percent` percent, in proportion to their smoothed failure rate.
+    /// 0 turns it off. Loads the recorded outcomes when turned on.
+    pub fn set_failure_penalty(&mut self, percent: u32) -> Result<(), Error> {
+        self.failure_penalty = percent.min(100);
+        self.outcomes = if self.failure_penalty > 0 {
+            Self::load_outcomes(&self.db)?
+        } else {
+            HashMap::new()
+        };
+        Ok(())
+    }
+
+    pub fn set_strip_ansi(&mut self, enabled: bool) {
+        self.strip_ansi = enabled;
+    }
+
+    pub fn set_strip_comments(&mut self, enabled: bool) {
+        self.strip_comments = enabled;
+    }
+
+    /// Prompts to remove from the start of commands read from history
+    /// files, for lines pasted along with their `$ `
+    pub fn set_strip_prompt_prefixes(&mut self, prefixes: Vec<String>) {
+        self.strip_prompt_prefixes = prefixes.into_iter().filter(|p| !p.is_empty()).collect();
+    }
+
+    /// Commit syncs `size` entries at a time, so a huge first sync keeps
+    /// the journal small and can resume where it stopped; 0 for one
+    /// transaction per sync
+    pub fn set_sync_batch_size(&mut self, size: usize) {
+        self.sync_batch_size = size;
+    }
+
+    /// The form of `command` that gets stored and matched
+    fn sanitize<'a>(&self, command: &'a str) -> Cow<'a, str> {
+        self.clean(command, &[])
+    }
+
+    /// `sanitize` for commands read from history, which may also start
+    /// with a prompt
+    fn sanitize_imported<'a>(&self, command: &'a str) -> Cow<'a, str> {
+        self.clean(command, &self.strip_prompt_prefixes)
+    }
+
+    fn clean<'a>(&self, command: &'a str, prompts: &[String]) -> Cow<'a, str> {
+        // Escapes go first: a colored prompt is only a prompt without them
+        let mut command = if self.strip_ansi {
+            strip_ansi(command)
+        } else {
+            Cow::Borrowed(command)
+        };
+        let mut apply = |f: &dyn Fn(&str) -> &str| {
+            command = match std::mem::take(&mut command) {
+                Cow::Borrowed(command) => Cow::Borrowed(f(command)),
+                Cow::Owned(command) => Cow::Owned(f(&command).to_string()),
+            };
+        };
+        if !prompts.is_empty() {
+            apply(&|command| strip_prompt_prefix(command, prompts));
+        }
+        if self.strip_comments {
+            apply(&strip_trailing_comment);
+        }
+        command
+    }
+
     /// Initialize SQLite schema
     fn init_schema(db: &Connection) -> SqlResult<()> {
         db.execute(
@@ -74,11 +756,22 @@ impl HistorySearcher {
                 shell_source TEXT NOT NULL,
                 frequency INTEGER NOT NULL DEFAULT 1,
                 last_used INTEGER,
-                created_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now'))
+                created_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
+                success_count INTEGER NOT NULL DEFAULT 0,
+                fail_count INTEGER NOT NULL DEFAULT 0,
+                duration_ms INTEGER,
+                timed_runs INTEGER NOT NULL DEFAULT 0
             )",
             [],
         )?;
 
+        // Outcome counters and runtimes were added later; upgrade older
+        // databases in place
+        for column in ["success_count", "fail_count", "timed_runs"] {
+            Self::add_column_if_missing(db, "commands", column, "INTEGER NOT NULL DEFAULT 0")?;
+        }
+        Self::add_column_if_missing(db, "commands", "duration_ms", "INTEGER")?;
+
         // Index for fast lookups
         db.execute(
             "CREATE INDEX IF NOT EXISTS idx_commands_command ON commands(command)",
@@ -90,21 +783,94 @@ impl HistorySearcher {
             [],
         )?;
 
+        // Per-source run counts. The first time the table is created, each
+        // command's runs so far are credited to the source that added it.
+        if !Self::table_exists(db, "command_shell_counts")? {
+            db.execute(
+                "CREATE TABLE command_shell_counts (
+                    command_id INTEGER NOT NULL,
+                    shell_source TEXT NOT NULL,
+                    count INTEGER NOT NULL DEFAULT 0,
+                    PRIMARY KEY (command_id, shell_source)
+                )",
+                [],
+            )?;
+            db.execute(
+                "INSERT INTO command_shell_counts (command_id, shell_source, count)
+                 SELECT id, shell_source, frequency FROM commands",
+                [],
+            )?;
+        }
+
+        // Runs per (weekday, hour) in local time, for `[search] time_aware`
+        db.execute(
+            "CREATE TABLE IF NOT EXISTS command_times (
+                command_id INTEGER NOT NULL,
+                weekday INTEGER NOT NULL,
+                hour INTEGER NOT NULL,
+                count INTEGER NOT NULL DEFAULT 0,
+                PRIMARY KEY (command_id, weekday, hour)
+            )",
+            [],
+        )?;
+
+        // Labels such as `deploy` put on commands, for `@tag:deploy`
+        db.execute(
+            "CREATE TABLE IF NOT EXISTS command_tags (
+                command_id INTEGER NOT NULL,
+                tag TEXT NOT NULL,
+                PRIMARY KEY (command_id, tag)
+            )",
+            [],
+        )?;
+
         // Track last sync state per shell
         db.execute(
             "CREATE TABLE IF NOT EXISTS sync_state (
                 shell_source TEXT PRIMARY KEY,
                 last_sync_timestamp INTEGER NOT NULL,
-                last_line_count INTEGER NOT NULL DEFAULT 0
+                last_line_count INTEGER NOT NULL DEFAULT 0,
+                resume_line INTEGER NOT NULL DEFAULT 0
             )",
             [],
         )?;
+        Self::add_column_if_missing(db, "sync_state", "resume_line", "INTEGER NOT NULL DEFAULT 0")?;
+
+        Ok(())
+    }
+
+    /// Whether the database has a table called `name`, e.g. in an older
+    /// database opened read-only
+    fn table_exists(db: &Connection, name: &str) -> SqlResult<bool> {
+        db.query_row(
+            "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?)",
+            [name],
+            |row| row.get(0),
+        )
+    }
+
+    /// Whether `table` has a column called `column`, e.g. one added after
+    /// an older database opened read-only was created
+    fn column_exists(db: &Connection, table: &str, column: &str) -> SqlResult<bool> {
+        let mut stmt = db.prepare(&format!("PRAGMA table_info({})", table))?;
+        let exists = stmt
+            .query_map([], |row| row.get::<_, String>(1))?
+            .filter_map(Result::ok)
+            .any(|name| name == column);
+        Ok(exists)
+    }
 
+    /// `ALTER TABLE ... ADD COLUMN` unless the column already exists
+    fn add_column_if_missing(db: &Connection, table: &str, column: &str, decl: &str) -> SqlResult<()> {
+        if !Self::column_exists(db, table, column)? {
+            info!("Adding column {}.{}", table, column);
+            db.execute(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, decl), [])?;
+        }
         Ok(())
     }
 
     /// Load all commands from database into memory
-    fn load_from_db(db: &Connection) -> Result<Vec<IndexedCommand>, Box<dyn std::error::Error>> {
+    fn load_from_db(db: &Connection) -> Result<Vec<SearchEntry>, Error> {
         let mut stmt = db.prepare(
             "SELECT id, command, frequency, last_used
              FROM commands
@@ -122,94 +888,259 @@ impl HistorySearcher {
 
         let mut entries = Vec::new();
         for row in rows {
-            entries.push(row?);
+            entries.push(SearchEntry::new(row?));
         }
 
         Ok(entries)
     }
 
+    /// Recorded run times of every command, by command id
+    fn load_time_profiles(db: &Connection) -> SqlResult<HashMap<i64, TimeProfile>> {
+        let mut profiles: HashMap<i64, TimeProfile> = HashMap::new();
+        if !Self::table_exists(db, "command_times")? {
+            return Ok(profiles);
+        }
+        let mut stmt = db.prepare("SELECT command_id, weekday, hour, count FROM command_times")?;
+        let rows = stmt.query_map([], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, usize>(1)?, row.get::<_, usize>(2)?, row.get::<_, u32>(3)?))
+        })?;
+        for row in rows {
+            let (id, weekday, hour, count) = row?;
+            if weekday < 7 && hour < 24 {
+                profiles.entry(id).or_default().add((weekday, hour), count);
+            }
+        }
+        Ok(profiles)
+    }
+
+    /// Mean runtimes of the commands that have one. A database opened
+    /// read-only from before runtimes were kept has none.
+    fn load_durations(db: &Connection) -> SqlResult<HashMap<i64, (u64, u32)>> {
+        if !Self::column_exists(db, "commands", "duration_ms")? {
+            return Ok(HashMap::new());
+        }
+        let mut stmt = db.prepare("SELECT id, duration_ms, timed_runs FROM commands WHERE timed_runs > 0")?;
+        let rows = stmt.query_map([], |row| Ok((row.get::<_, i64>(0)?, (row.get(1)?, row.get(2)?))))?;
+        rows.collect()
+    }
+
+    /// Fold one measured run of command `id` into its mean runtime
+    fn record_duration_on(conn: &Connection, id: i64, duration_ms: u64) -> SqlResult<(u64, u32)> {
+        conn.query_row(
+            "UPDATE commands
+             SET duration_ms = (COALESCE(duration_ms, 0) * timed_runs + ?) / (timed_runs + 1),
+                 timed_runs = timed_runs + 1
+             WHERE id = ?
+             RETURNING duration_ms, timed_runs",
+            params![duration_ms as i64, id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+    }
+
+    /// Every command's tags, by command id
+    fn load_tags(db: &Connection) -> SqlResult<HashMap<i64, Vec<String>>> {
+        let mut tags: HashMap<i64, Vec<String>> = HashMap::new();
+        if !Self::table_exists(db, "command_tags")? {
+            return Ok(tags);
+        }
+        let mut stmt = db.prepare("SELECT command_id, tag FROM command_tags ORDER BY tag")?;
+        let rows = stmt.query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)))?;
+        for row in rows {
+            let (id, tag) = row?;
+            tags.entry(id).or_default().push(tag);
+        }
+        Ok(tags)
+    }
+
+    /// Recorded run outcomes of the commands that have any, by command id
+    fn load_outcomes(db: &Connection) -> SqlResult<HashMap<i64, (u32, u32)>> {
+        let mut stmt = db.prepare(
+            "SELECT id, success_count, fail_count FROM commands WHERE success_count + fail_count > 0",
+        )?;
+        let rows = stmt.query_map([], |row| Ok((row.get::<_, i64>(0)?, (row.get(1)?, row.get(2)?))))?;
+        rows.collect()
+    }
+
+    /// Build the command -> index lookup for a freshly loaded entry list
+    fn index_positions(entries: &[SearchEntry]) -> HashMap<String, usize> {
+        entries
+            .iter()
+            .enumerate()
+            .map(|(i, e)| (e.meta.command.clone(), i))
+            .collect()
+    }
+
     /// Sync new commands from shell history to database
-    pub fn sync_from_shell_history(&mut self, shell: Shell) -> Result<usize, Box<dyn std::error::Error>> {
+    pub fn sync_from_shell_history(&mut self, shell: Shell) -> Result<usize, Error> {
+        if self.read_only {
+            debug!("Read-only mode: skipping sync from {:?}", shell);
+            return Ok(0);
+        }
+
         debug!("Starting sync from {:?} shell", shell);
-        let reader = HistoryReader::new(shell)?;
-        let shell_name = format!("{:?}", shell);
+        let reader = match HistoryReader::new(shell) {
+            Ok(reader) => reader,
+            Err(e) => {
+                // No resolvable history path: skip this shell, don't fail the sync
+                warn!("Skipping {:?} history: {}", shell, e);
+                return Ok(0);
+            }
+        };
+        self.sync_from_reader(&reader, &format!("{:?}", shell))
+    }
+
+    /// Sync new commands from an extra history file: plain (one command per
+    /// line) unless its name identifies a shell's format, see
+    /// [`Shell::for_history_file`]. Each file keeps its own sync state, keyed by its path.
+    pub fn sync_from_plain_file(&mut self, path: &Path) -> Result<usize, Error> {
+        if self.read_only {
+            debug!("Read-only mode: skipping sync from {}", path.display());
+            return Ok(0);
+        }
+
+        let reader = HistoryReader::with_path(Shell::for_history_file(path), path.to_path_buf());
+        self.sync_from_reader(&reader, &format!("Plain:{}", path.display()))
+    }
+
+    /// Sync new commands from the stdout of a shell command (one per line).
+    /// The output is treated like an append-only history file: only lines
+    /// beyond those seen at the previous sync are indexed.
+    pub fn sync_from_command(&mut self, command: &str) -> Result<usize, Error> {
+        if self.read_only {
+            debug!("Read-only mode: skipping sync from `{}`", command);
+            return Ok(0);
+        }
+
+        let history = read_command_output(command)?;
+        self.sync_entries(history, &format!("Command:{}", command))
+    }
+
+    /// Sync new commands from a history reader, tracking progress under `source`
+    fn sync_from_reader(&mut self, reader: &HistoryReader, source: &str) -> Result<usize, Error> {
+        let history = reader.read_history()?;
+        self.sync_entries(history, source)
+    }
 
+    /// Index the entries of `history` not seen at the last sync of `source`
+    fn sync_entries(&mut self, history: Vec<HistoryEntry>, source: &str) -> Result<usize, Error> {
         // Get last sync state
-        let (last_sync_ts, last_line_count) = self.get_sync_state(&shell_name)?;
+        let (last_sync_ts, last_line_count, resume_line) = self.get_sync_state(source)?;
         debug!(
-            "Last sync for {:?}: timestamp={}, lines={}",
-            shell, last_sync_ts, last_line_count
+            "Last sync for {}: timestamp={}, lines={}, resuming at line {}",
+            source, last_sync_ts, last_line_count, resume_line
         );
 
-        // Read shell history
-        let history = reader.read_history()?;
         let total_lines = history.len();
-        debug!("Read {} total commands from {:?} history", total_lines, shell);
+        debug!("Read {} total commands from {} history", total_lines, source);
 
         // Filter for new commands:
+        // - Lines before where an interrupted sync stopped are already in
         // - Entries with timestamps: use timestamp comparison
         // - Entries without timestamps: only process lines beyond the last synced count
-        let new_commands: Vec<_> = history
+        // Each kept with its line number, for resuming an interrupted sync
+        let mut new_commands: Vec<(usize, HistoryEntry)> = history
             .into_iter()
             .enumerate()
             .filter(|(i, entry)| {
-                if let Some(ts) = entry.timestamp {
+                if *i < resume_line {
+                    false
+                } else if let Some(ts) = entry.timestamp {
                     ts > last_sync_ts
                 } else {
                     // No timestamp: only process entries beyond previously synced line count
                     *i >= last_line_count
                 }
             })
-            .map(|(_, entry)| entry)
             .collect();
+        if self.strip_ansi || self.strip_comments || !self.strip_prompt_prefixes.is_empty() {
+            for (_, entry) in &mut new_commands {
+                let clean = self.sanitize_imported(&entry.command);
+                if clean != entry.command.as_str() {
+                    entry.command = clean.into_owned();
+                }
+            }
+            new_commands.retain(|(_, entry)| !entry.command.trim().is_empty());
+        }
 
         let count = new_commands.len();
-        debug!("Found {} new commands from {:?}", count, shell);
-
-        // Insert new commands in a single transaction for performance
-        {
+        debug!("Found {} new commands from {}", count, source);
+
+        // Insert in batches of transactions: one per batch keeps the journal
+        // small. The sync state goes in with each batch, pointing at the
+        // next one's first line, so a sync that fails partway resumes there
+        // next time. Only a finished sync moves the timestamp and line count
+        // on: timestamps needn't be in file order.
+        let started = std::time::Instant::now();
+        let batch_size = if self.sync_batch_size == 0 { count.max(1) } else { self.sync_batch_size };
+        let batches = count.div_ceil(batch_size).max(1);
+        for batch in 0..batches {
+            let start = batch * batch_size;
+            let end = (start + batch_size).min(count);
+            let (timestamp, line_count, resume_line) = match new_commands.get(end) {
+                None => {
+                    let now = std::time::SystemTime::now()
+                        .duration_since(std::time::UNIX_EPOCH)
+                        .unwrap_or_default()
+                        .as_secs() as i64;
+                    (now, total_lines, 0)
+                }
+                Some((next_line, _)) => (last_sync_ts, last_line_count, *next_line),
+            };
             let tx = self.db.transaction()?;
-            for entry in &new_commands {
-                Self::insert_or_update_command_on(&tx, entry, &shell_name)?;
+            for (_, entry) in &new_commands[start..end] {
+                Self::insert_or_update_command_on(&tx, entry, source)?;
             }
-            Self::update_sync_state_on(&tx, &shell_name, total_lines)?;
+            Self::update_sync_state_on(&tx, source, timestamp, line_count, resume_line)?;
             tx.commit()?;
+            if batches > 1 {
+                debug!("Synced batch {}/{} ({} commands) from {}", batch + 1, batches, end - start, source);
+            }
         }
 
         // Reload in-memory data
         self.reload_from_db()?;
 
-        info!("Synced {} new commands from {:?}", count, shell);
+        let elapsed = started.elapsed();
+        info!(
+            "Synced {} new commands from {} in {:.1?} ({:.0} commands/s)",
+            count,
+            source,
+            elapsed,
+            count as f64 / elapsed.as_secs_f64().max(1e-6)
+        );
 
         Ok(count)
     }
 
-    /// Get last sync state for a shell: (last_timestamp, last_line_count)
-    fn get_sync_state(&self, shell_source: &str) -> SqlResult<(i64, usize)> {
+    /// Get last sync state for a shell: (last_timestamp, last_line_count,
+    /// resume_line), where resume_line is the first line an interrupted sync
+    /// didn't get to, or 0
+    fn get_sync_state(&self, shell_source: &str) -> SqlResult<(i64, usize, usize)> {
         let mut stmt = self.db.prepare(
-            "SELECT last_sync_timestamp, last_line_count FROM sync_state WHERE shell_source = ?"
+            "SELECT last_sync_timestamp, last_line_count, resume_line FROM sync_state WHERE shell_source = ?"
         )?;
 
         match stmt.query_row([shell_source], |row| {
-            Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)? as usize))
+            Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)? as usize, row.get::<_, i64>(2)? as usize))
         }) {
             Ok(state) => Ok(state),
-            Err(rusqlite::Error::QueryReturnedNoRows) => Ok((0, 0)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok((0, 0, 0)),
             Err(e) => Err(e),
         }
     }
 
     /// Update sync state for a shell
-    fn update_sync_state_on(conn: &Connection, shell_source: &str, line_count: usize) -> SqlResult<()> {
-        let now = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_secs() as i64;
-
+    fn update_sync_state_on(
+        conn: &Connection,
+        shell_source: &str,
+        timestamp: i64,
+        line_count: usize,
+        resume_line: usize,
+    ) -> SqlResult<()> {
         conn.execute(
-            "INSERT OR REPLACE INTO sync_state (shell_source, last_sync_timestamp, last_line_count)
-             VALUES (?, ?, ?)",
-            params![shell_source, now, line_count as i64],
+            "INSERT OR REPLACE INTO sync_state (shell_source, last_sync_timestamp, last_line_count, resume_line)
+             VALUES (?, ?, ?, ?)",
+            params![shell_source, timestamp, line_count as i64, resume_line as i64],
         )?;
 
         Ok(())
@@ -233,6 +1164,11 @@ impl HistorySearcher {
                     "UPDATE commands SET frequency = ?, last_used = ? WHERE id = ?",
                     params![freq + 1, entry.timestamp, id],
                 )?;
+                Self::count_shell_use_on(conn, id, shell_source)?;
+                Self::count_time_of_use_on(conn, id, entry.timestamp)?;
+                if let Some(duration_ms) = entry.duration_ms {
+                    Self::record_duration_on(conn, id, duration_ms)?;
+                }
             }
             Err(rusqlite::Error::QueryReturnedNoRows) => {
                 conn.execute(
@@ -240,6 +1176,12 @@ impl HistorySearcher {
                      VALUES (?, ?, ?, 1, ?)",
                     params![&entry.command, entry.timestamp, shell_source, entry.timestamp],
                 )?;
+                let id = conn.last_insert_rowid();
+                Self::count_shell_use_on(conn, id, shell_source)?;
+                Self::count_time_of_use_on(conn, id, entry.timestamp)?;
+                if let Some(duration_ms) = entry.duration_ms {
+                    Self::record_duration_on(conn, id, duration_ms)?;
+                }
             }
             Err(e) => return Err(e),
         }
@@ -247,51 +1189,271 @@ impl HistorySearcher {
         Ok(())
     }
 
+    /// Count one run of command `id` from `shell_source`
+    fn count_shell_use_on(conn: &Connection, id: i64, shell_source: &str) -> SqlResult<()> {
+        conn.execute(
+            "INSERT INTO command_shell_counts (command_id, shell_source, count) VALUES (?, ?, 1)
+             ON CONFLICT (command_id, shell_source) DO UPDATE SET count = count + 1",
+            params![id, shell_source],
+        )?;
+        Ok(())
+    }
+
+    /// Count a run of command `id` at `timestamp`, if the run has one
+    fn count_time_of_use_on(conn: &Connection, id: i64, timestamp: Option<i64>) -> SqlResult<()> {
+        let Some((weekday, hour)) = timestamp.and_then(time_slot) else {
+            return Ok(());
+        };
+        conn.execute(
+            "INSERT INTO command_times (command_id, weekday, hour, count) VALUES (?, ?, ?, 1)
+             ON CONFLICT (command_id, weekday, hour) DO UPDATE SET count = count + 1",
+            params![id, weekday as i64, hour as i64],
+        )?;
+        Ok(())
+    }
+
     /// Fuzzy search for commands
     pub fn search(&mut self, query: &str, limit: usize) -> Vec<SearchResult> {
-        if query.is_empty() {
+        self.search_filtered(query, limit, |_| true)
+    }
+
+    /// Fuzzy search restricted to commands last used within `[from_ts, to_ts]`
+    /// (unix seconds, inclusive). Commands with no `last_used` are excluded.
+    pub fn search_in_range(
+        &mut self,
+        query: &str,
+        from_ts: i64,
+        to_ts: i64,
+        limit: usize,
+    ) -> Vec<SearchResult> {
+        self.search_filtered(query, limit, |meta| {
+            meta.last_used.is_some_and(|ts| (from_ts..=to_ts).contains(&ts))
+        })
+    }
+
+    /// Fuzzy search over the entries accepted by `filter`, which runs before scoring
+    pub fn search_filtered(
+        &mut self,
+        query: &str,
+        limit: usize,
+        filter: impl Fn(&IndexedCommand) -> bool,
+    ) -> Vec<SearchResult> {
+        self.search_scoped(query, limit, filter, false)
+    }
+
+    /// Like [`search_filtered`](Self::search_filtered), but `query` is matched
+    /// against each command without its first word, for `exact_first_token`
+    pub fn search_after_first_word(
+        &mut self,
+        query: &str,
+        limit: usize,
+        filter: impl Fn(&IndexedCommand) -> bool,
+    ) -> Vec<SearchResult> {
+        self.search_scoped(query, limit, filter, true)
+    }
+
+    fn search_scoped(
+        &mut self,
+        query: &str,
+        limit: usize,
+        filter: impl Fn(&IndexedCommand) -> bool,
+        skip_first_word: bool,
+    ) -> Vec<SearchResult> {
+        let scope = |command: &str| -> usize {
+            if skip_first_word {
+                command.len() - after_first_word(command).len()
+            } else {
+                0
+            }
+        };
+        // Short queries take the empty-query path, narrowed to a prefix match
+        let prefix = (query.chars().count() < self.min_fuzzy_length).then_some(query);
+        let query = if prefix.is_some() { "" } else { query };
+        let filter = |meta: &IndexedCommand| {
+            filter(meta)
+                && prefix.is_none_or(|prefix| {
+                    meta.command[scope(&meta.command)..]
+                        .get(..prefix.len())
+                        .is_some_and(|head| head.eq_ignore_ascii_case(prefix))
+                })
+        };
+
+        if query.is_empty() && self.rank_by == RankBy::Frequency && !self.time_aware && self.failure_penalty == 0 {
             // Return most frequent commands
             return self.entries
                 .iter()
+                .filter(|e| filter(&e.meta))
                 .take(limit)
                 .map(|e| SearchResult {
-                    command: e.command.clone(),
-                    score: e.frequency,
+                    command: e.meta.command.clone(),
+                    score: e.meta.frequency,
                 })
                 .collect();
         }
 
-        // Convert query to Utf32String for nucleo matcher
-        let query_utf32 = Utf32String::from(query);
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs() as i64;
+        let Self {
+            entries,
+            matcher,
+            path_matcher,
+            path_aware,
+            query_buf,
+            match_all_terms,
+            rank_by,
+            algorithm,
+            fuzzy_weight,
+            frequency_weight,
+            time_aware,
+            time_profiles,
+            failure_penalty,
+            outcomes,
+            ..
+        } = self;
+        let (rank_by, fuzzy_weight, frequency_weight) = (*rank_by, *fuzzy_weight, *frequency_weight);
+        let slot = time_slot(now).filter(|_| *time_aware);
+        let usage_bonus = |meta: &IndexedCommand| {
+            let time_bonus = slot
+                .and_then(|slot| Some(time_profiles.get(&meta.id)?.bonus(slot)))
+                .unwrap_or(0);
+            rank_bonus(rank_by, frequency_weight, meta, now).saturating_add(time_bonus)
+        };
+        // The share of its score a command keeps with `penalize_failures`
+        let penalize = |meta: &IndexedCommand, score: u32| match outcomes.get(&meta.id) {
+            Some(&(success_count, fail_count)) => {
+                let rate = smoothed_failure_rate(success_count, fail_count);
+                (score as f64 * (1.0 - rate * *failure_penalty as f64 / 100.0)) as u32
+            }
+            None => score,
+        };
+
+        // Rank by combined score (descending), ties broken by position in `entries`.
+        // In recency mode the last use comes first.
+        let by_rank = |a: &(u32, usize), b: &(u32, usize)| {
+            let recency = if rank_by == RankBy::Recency {
+                entries[b.1].meta.last_used.cmp(&entries[a.1].meta.last_used)
+            } else {
+                std::cmp::Ordering::Equal
+            };
+            recency.then(b.0.cmp(&a.0)).then(a.1.cmp(&b.1))
+        };
+
+        if query.is_empty() {
+            let mut results: Vec<(u32, usize)> = entries
+                .iter()
+                .enumerate()
+                .filter(|(_, e)| filter(&e.meta))
+                .map(|(idx, e)| (penalize(&e.meta, usage_bonus(&e.meta)), idx))
+                .collect();
+            return Self::top_results(entries, &mut results, limit, by_rank);
+        }
+
+        // Ignoring case, the matcher lowercases each haystack character, so
+        // the needle must be lowercase too
+        let case_sensitive = algorithm.case_sensitive(query);
+        let query: Cow<str> = if !case_sensitive && query.chars().any(char::is_uppercase) {
+            Cow::Owned(query.to_lowercase())
+        } else {
+            Cow::Borrowed(query)
+        };
+
+        let matcher = if *path_aware && query.contains('/') {
+            path_matcher
+        } else {
+            matcher
+        };
+        matcher.config.ignore_case = !case_sensitive;
+
+        // Borrow the query as UTF-32 without allocating (ASCII) or via the scratch buffer
+        let needle = Utf32Str::new(&query, query_buf);
+        let mut needle_len = needle.len();
+        let mut needle_mask = char_mask(&query);
+
+        // AND mode: each term is matched on its own and the scores are summed
+        let terms: Vec<Utf32String> = if *match_all_terms {
+            query.split_whitespace().map(Utf32String::from).collect()
+        } else {
+            Vec::new()
+        };
+        if terms.len() > 1 {
+            needle_len = terms.iter().map(|t| t.len()).max().unwrap_or(0);
+            needle_mask = char_mask(&query.replace(char::is_whitespace, ""));
+        }
 
-        let mut results: Vec<_> = self.entries
+        let mut results: Vec<(u32, usize)> = entries
             .iter()
-            .zip(self.haystacks.iter())
-            .filter_map(|(entry, haystack)| {
-                let score = self.matcher.fuzzy_match(haystack.slice(..), query_utf32.slice(..))?;
+            .enumerate()
+            .filter_map(|(idx, SearchEntry { meta, haystack })| {
+                // Cheap pre-filters: too short, or missing a required character
+                if haystack.text.len() < needle_len || needle_mask & !haystack.mask != 0 {
+                    return None;
+                }
+                if !filter(meta) {
+                    return None;
+                }
+
+                let start = match scope(&meta.command) {
+                    0 => 0,
+                    bytes => meta.command[..bytes].chars().count(),
+                };
+                let text = haystack.text.slice(start..);
+                let score = if terms.len() > 1 {
+                    terms.iter().try_fold(0u32, |sum, term| {
+                        let score = matcher.fuzzy_match(text, term.slice(..))?;
+                        Some(sum + score as u32)
+                    })?
+                } else {
+                    matcher.fuzzy_match(text, needle)? as u32
+                };
 
-                // Combine fuzzy score with frequency for ranking
-                let combined_score = score as u32 + (entry.frequency * 10);
+                // Combine fuzzy score with usage for ranking
+                let combined_score = score
+                    .saturating_mul(fuzzy_weight)
+                    .saturating_add(usage_bonus(meta));
 
-                Some((combined_score, entry))
+                Some((penalize(meta, combined_score), idx))
             })
             .collect();
 
-        // Sort by combined score (descending)
-        results.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+        Self::top_results(entries, &mut results, limit, by_rank)
+    }
+
+    /// The best `limit` of the (score, entry index) pairs, in rank order.
+    /// Only the top `limit` results are fully sorted.
+    fn top_results(
+        entries: &[SearchEntry],
+        results: &mut Vec<(u32, usize)>,
+        limit: usize,
+        by_rank: impl Fn(&(u32, usize), &(u32, usize)) -> std::cmp::Ordering,
+    ) -> Vec<SearchResult> {
+        if limit > 0 && results.len() > limit {
+            results.select_nth_unstable_by(limit - 1, &by_rank);
+        }
+        results.truncate(limit);
+        results.sort_unstable_by(&by_rank);
 
         results
-            .into_iter()
-            .take(limit)
-            .map(|(score, entry)| SearchResult {
-                command: entry.command.clone(),
+            .iter()
+            .map(|&(score, idx)| SearchResult {
+                command: entries[idx].meta.command.clone(),
                 score,
             })
             .collect()
     }
 
     /// Record command usage (increment frequency, insert if new)
-    pub fn record_usage(&mut self, command: &str) -> Result<(), Box<dyn std::error::Error>> {
+    pub fn record_usage(&mut self, command: &str) -> Result<(), Error> {
+        if self.read_only {
+            return Ok(());
+        }
+        let sanitized = self.sanitize(command);
+        let command: &str = &sanitized;
+        if command.trim().is_empty() {
+            return Ok(());
+        }
+
         let now = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
             .unwrap_or_default()
@@ -313,40 +1475,232 @@ impl HistorySearcher {
 
             // Add to in-memory entries
             let id = self.db.last_insert_rowid();
+            Self::count_shell_use_on(&self.db, id, "mux")?;
+            self.count_time_of_use(id, now)?;
             let entry = IndexedCommand {
                 id,
                 command: command.to_string(),
                 frequency: 1,
                 last_used: Some(now),
             };
-            self.haystacks.push(Utf32String::from(command));
-            self.entries.push(entry);
+            self.positions.insert(entry.command.clone(), self.entries.len());
+            self.entries.push(SearchEntry::new(entry));
+        } else if let Some(&idx) = self.positions.get(command) {
+            Self::count_shell_use_on(&self.db, self.entries[idx].meta.id, "mux")?;
+            self.count_time_of_use(self.entries[idx].meta.id, now)?;
+
+            // Update in-memory entry and move it up to maintain sort order.
+            // Entries are sorted by frequency DESC, so the only ones it now outranks
+            // are the run sharing its old frequency directly above it; swapping with
+            // the head of that run keeps the order intact in O(log n).
+            let meta = &mut self.entries[idx].meta;
+            meta.frequency += 1;
+            meta.last_used = Some(now);
+            let new_frequency = meta.frequency;
+
+            let target = self.entries[..idx].partition_point(|e| e.meta.frequency >= new_frequency);
+            if target < idx {
+                self.entries.swap(target, idx);
+                self.positions.insert(self.entries[target].meta.command.clone(), target);
+                self.positions.insert(self.entries[idx].meta.command.clone(), idx);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Count a run of command `id` now, in the database and, if time-aware
+    /// ranking is on, in memory
+    fn count_time_of_use(&mut self, id: i64, now: i64) -> SqlResult<()> {
+        Self::count_time_of_use_on(&self.db, id, Some(now))?;
+        if self.time_aware
+            && let Some(slot) = time_slot(now)
+        {
+            self.time_profiles.entry(id).or_default().add(slot, 1);
+        }
+        Ok(())
+    }
+
+    /// Count a finished run of `command` as a success or failure.
+    /// Commands that aren't in the history are ignored.
+    pub fn record_outcome(&mut self, command: &str, success: bool) -> Result<(), Error> {
+        if self.read_only {
+            return Ok(());
+        }
+        let command = self.sanitize(command);
+
+        let sql = if success {
+            "UPDATE commands SET success_count = success_count + 1 WHERE command = ?"
         } else {
-            // Update in-memory entry and bubble up to maintain sort order
-            if let Some(mut idx) = self.entries.iter().position(|e| e.command == command) {
-                self.entries[idx].frequency += 1;
-                self.entries[idx].last_used = Some(now);
-
-                while idx > 0 && self.entries[idx].frequency > self.entries[idx - 1].frequency {
-                    self.entries.swap(idx, idx - 1);
-                    self.haystacks.swap(idx, idx - 1);
-                    idx -= 1;
-                }
+            "UPDATE commands SET fail_count = fail_count + 1 WHERE command = ?"
+        };
+        self.db.execute(sql, [command.as_ref()])?;
+        if self.failure_penalty > 0
+            && let Some(&i) = self.positions.get(command.as_ref())
+        {
+            let (success_count, fail_count) = self.outcomes.entry(self.entries[i].meta.id).or_default();
+            if success {
+                *success_count += 1;
+            } else {
+                *fail_count += 1;
             }
         }
+        Ok(())
+    }
 
+    /// Count a measured run of `command` towards its mean runtime. Commands
+    /// that aren't in the history are ignored.
+    pub fn record_duration(&mut self, command: &str, duration: Duration) -> Result<(), Error> {
+        if self.read_only {
+            return Ok(());
+        }
+        let command = self.sanitize(command);
+        let Some(&i) = self.positions.get(command.as_ref()) else {
+            return Ok(());
+        };
+        let id = self.entries[i].meta.id;
+        let duration_ms = u64::try_from(duration.as_millis()).unwrap_or(u64::MAX);
+        let mean = Self::record_duration_on(&self.db, id, duration_ms)?;
+        self.durations.insert(id, mean);
         Ok(())
     }
 
+    /// How long `command` typically runs: the mean of its measured runs
+    pub fn typical_duration(&self, command: &str) -> Option<Duration> {
+        let command = self.sanitize(command);
+        let &index = self.positions.get(command.as_ref())?;
+        let &(mean_ms, _) = self.durations.get(&self.entries[index].meta.id)?;
+        Some(Duration::from_millis(mean_ms))
+    }
+
+    /// The `limit` commands with the longest mean runtime
+    pub fn slowest_commands(&self, limit: usize) -> SqlResult<Vec<CommandDuration>> {
+        if !Self::column_exists(&self.db, "commands", "duration_ms")? {
+            return Ok(Vec::new());
+        }
+        let mut stmt = self.db.prepare(
+            "SELECT command, duration_ms, timed_runs
+             FROM commands
+             WHERE timed_runs > 0
+             ORDER BY duration_ms DESC, timed_runs DESC
+             LIMIT ?",
+        )?;
+        let rows = stmt.query_map([limit as i64], |row| {
+            Ok(CommandDuration {
+                command: row.get(0)?,
+                mean: Duration::from_millis(row.get(1)?),
+                runs: row.get(2)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    /// Delete the commands `matches` accepts from the database and the index,
+    /// with everything recorded about them: per-shell and time-of-day counts,
+    /// outcomes, runtimes and tags. Returns the deleted commands, most used
+    /// first; none from a read-only database, where nothing is deleted.
+    pub fn delete_matching(&mut self, matches: impl Fn(&str) -> bool) -> Result<Vec<String>, Error> {
+        if self.read_only {
+            return Ok(Vec::new());
+        }
+        let doomed: Vec<(i64, String)> = self
+            .commands()
+            .filter(|c| matches(&c.command))
+            .map(|c| (c.id, c.command.clone()))
+            .collect();
+        if doomed.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let tx = self.db.transaction()?;
+        for (id, _) in &doomed {
+            tx.execute("DELETE FROM command_shell_counts WHERE command_id = ?", [id])?;
+            tx.execute("DELETE FROM command_times WHERE command_id = ?", [id])?;
+            tx.execute("DELETE FROM command_tags WHERE command_id = ?", [id])?;
+            tx.execute("DELETE FROM commands WHERE id = ?", [id])?;
+        }
+        tx.commit()?;
+
+        self.entries.retain(|e| !matches(&e.meta.command));
+        self.positions = Self::index_positions(&self.entries);
+        for (id, _) in &doomed {
+            self.time_profiles.remove(id);
+            self.outcomes.remove(id);
+            self.tags.remove(id);
+            self.durations.remove(id);
+        }
+        Ok(doomed.into_iter().map(|(_, command)| command).collect())
+    }
+
+    /// Commands that failed at least once in `min_runs` or more recorded runs,
+    /// highest failure rate first
+    pub fn flaky_commands(&self, min_runs: u32, limit: usize) -> SqlResult<Vec<CommandOutcomes>> {
+        let mut stmt = self.db.prepare(
+            "SELECT command, success_count, fail_count
+             FROM commands
+             WHERE fail_count > 0 AND success_count + fail_count >= ?
+             ORDER BY CAST(fail_count AS REAL) / (success_count + fail_count) DESC, fail_count DESC
+             LIMIT ?",
+        )?;
+
+        let rows = stmt.query_map(params![min_runs, limit as i64], |row| {
+            Ok(CommandOutcomes {
+                command: row.get(0)?,
+                success_count: row.get(1)?,
+                fail_count: row.get(2)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    /// The `limit` most used commands, with their runs broken down by the
+    /// history source they came from. Databases opened read-only from before
+    /// per-source counts were kept have no breakdown.
+    pub fn usage_by_shell(&self, limit: usize) -> SqlResult<Vec<ShellUsage>> {
+        let mut stmt = self.db.prepare(
+            "SELECT id, command, frequency FROM commands ORDER BY frequency DESC, last_used DESC LIMIT ?",
+        )?;
+        let top = stmt
+            .query_map([limit as i64], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?, row.get::<_, u32>(2)?))
+            })?
+            .collect::<SqlResult<Vec<_>>>()?;
+
+        let mut counts = if Self::table_exists(&self.db, "command_shell_counts")? {
+            Some(self.db.prepare(
+                "SELECT shell_source, count FROM command_shell_counts
+                 WHERE command_id = ? ORDER BY count DESC, shell_source",
+            )?)
+        } else {
+            None
+        };
+
+        top.into_iter()
+            .map(|(id, command, frequency)| {
+                let shells = match counts.as_mut() {
+                    Some(stmt) => stmt
+                        .query_map([id], |row| Ok((row.get(0)?, row.get(1)?)))?
+                        .collect::<SqlResult<Vec<_>>>()?,
+                    None => Vec::new(),
+                };
+                Ok(ShellUsage { command, frequency, shells })
+            })
+            .collect()
+    }
+
     /// Persist all pending changes to database (called on shutdown)
-    pub fn flush(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+    pub fn flush(&mut self) -> Result<(), Error> {
+        if self.read_only {
+            return Ok(());
+        }
+
         // Transaction to ensure atomicity
         let tx = self.db.transaction()?;
 
-        for entry in &self.entries {
+        for SearchEntry { meta, .. } in &self.entries {
             tx.execute(
                 "UPDATE commands SET frequency = ?, last_used = ? WHERE id = ?",
-                params![entry.frequency, entry.last_used, entry.id],
+                params![meta.frequency, meta.last_used, meta.id],
             )?;
         }
 
@@ -354,34 +1708,349 @@ impl HistorySearcher {
         Ok(())
     }
 
+    /// Let SQLite refresh its query planner statistics, and with `vacuum`
+    /// rebuild the database file to drop free pages. Called on clean
+    /// shutdown, after `flush`. Returns the bytes reclaimed by `VACUUM`; None
+    /// if it didn't run, including when another connection (a second mux)
+    /// holds a lock, since rebuilding needs the database to itself.
+    pub fn optimize(&mut self, vacuum: bool) -> Result<Option<u64>, Error> {
+        if self.read_only {
+            return Ok(None);
+        }
+        let before = self.database_size()?;
+        // Don't hold up exit waiting for the lock; `PRAGMA optimize` may
+        // write statistics too
+        self.db.busy_timeout(Duration::ZERO)?;
+        let mut statements = vec!["PRAGMA optimize"];
+        if vacuum {
+            statements.push("VACUUM");
+        }
+        let ran = statements.iter().try_for_each(|sql| self.db.execute_batch(sql));
+        self.db.busy_timeout(BUSY_TIMEOUT)?;
+        match ran {
+            Ok(()) => {}
+            Err(rusqlite::Error::SqliteFailure(e, _))
+                if matches!(e.code, ErrorCode::DatabaseBusy | ErrorCode::DatabaseLocked) =>
+            {
+                info!("Skipped optimizing: the database is in use");
+                return Ok(None);
+            }
+            Err(e) => return Err(e.into()),
+        }
+        if !vacuum {
+            return Ok(None);
+        }
+        let reclaimed = before.saturating_sub(self.database_size()?);
+        info!("VACUUM reclaimed {} bytes", reclaimed);
+        Ok(Some(reclaimed))
+    }
+
+    /// Size of the database in bytes, from its page count
+    fn database_size(&self) -> SqlResult<u64> {
+        let pages: u64 = self.db.query_row("PRAGMA page_count", [], |row| row.get(0))?;
+        let page_size: u64 = self.db.query_row("PRAGMA page_size", [], |row| row.get(0))?;
+        Ok(pages * page_size)
+    }
+
+    /// Move the database at `db_path` aside to `<name>.bak` (for `--rebuild`),
+    /// with its journal files, replacing an earlier backup. Returns the
+    /// backup's path.
+    pub fn move_to_backup(db_path: &Path) -> std::io::Result<PathBuf> {
+        let with_suffix = |path: &Path, suffix: &str| {
+            let mut name = path.as_os_str().to_owned();
+            name.push(suffix);
+            PathBuf::from(name)
+        };
+        let backup = with_suffix(db_path, ".bak");
+        // Journals left from an earlier backup would be applied to this one
+        for suffix in DB_FILE_SUFFIXES {
+            let old = with_suffix(&backup, suffix);
+            if old.exists() {
+                std::fs::remove_file(old)?;
+            }
+        }
+        for suffix in DB_FILE_SUFFIXES {
+            let file = with_suffix(db_path, suffix);
+            if file.exists() {
+                std::fs::rename(file, with_suffix(&backup, suffix))?;
+            }
+        }
+        Ok(backup)
+    }
+
+    /// Check the database at `db_path` without changing it: SQLite's
+    /// integrity check, then the invariants the in-memory index relies on
+    /// (one row per command, counts that point at existing commands)
+    pub fn diagnose(db_path: &Path) -> Result<Diagnosis, Error> {
+        let db = Connection::open_with_flags(
+            db_path,
+            OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        )?;
+        let pragma_i64 = |name: &str| db.query_row(&format!("PRAGMA {}", name), [], |row| row.get::<_, i64>(0));
+
+        let mut problems: Vec<String> = {
+            let mut stmt = db.prepare("PRAGMA integrity_check")?;
+            stmt.query_map([], |row| row.get::<_, String>(0))?
+                .filter(|line| !matches!(line.as_deref(), Ok("ok")))
+                .collect::<SqlResult<_>>()?
+        };
+
+        let page_size = pragma_i64("page_size")? as u64;
+        let mut row_counts = Vec::new();
+        let tables: Vec<String> = db
+            .prepare("SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%' ORDER BY name")?
+            .query_map([], |row| row.get(0))?
+            .collect::<SqlResult<_>>()?;
+        for table in tables {
+            let count = db.query_row(&format!("SELECT COUNT(*) FROM \"{}\"", table), [], |row| row.get(0))?;
+            row_counts.push((table, count));
+        }
+
+        if Self::table_exists(&db, "commands")? {
+            let duplicates: i64 = db.query_row(
+                "SELECT COUNT(*) FROM (SELECT 1 FROM commands GROUP BY command HAVING COUNT(*) > 1)",
+                [],
+                |row| row.get(0),
+            )?;
+            if duplicates > 0 {
+                problems.push(format!("{} commands are stored more than once", duplicates));
+            }
+            let negative: i64 = db.query_row(
+                "SELECT COUNT(*) FROM commands WHERE frequency < 0 OR success_count < 0 OR fail_count < 0",
+                [],
+                |row| row.get(0),
+            )?;
+            if negative > 0 {
+                problems.push(format!("{} commands have negative counts", negative));
+            }
+            for table in ["command_shell_counts", "command_times", "command_tags"] {
+                if !Self::table_exists(&db, table)? {
+                    continue;
+                }
+                let orphans: i64 = db.query_row(
+                    &format!(
+                        "SELECT COUNT(*) FROM {} WHERE command_id NOT IN (SELECT id FROM commands)",
+                        table
+                    ),
+                    [],
+                    |row| row.get(0),
+                )?;
+                if orphans > 0 {
+                    problems.push(format!("{} rows of {} belong to no command", orphans, table));
+                }
+            }
+            // What startup does with the rows; a failure here is what the user sees
+            if let Err(e) = Self::load_from_db(&db) {
+                problems.push(format!("commands can't be loaded: {}", e));
+            }
+        } else {
+            problems.push("no commands table".to_string());
+        }
+
+        Ok(Diagnosis {
+            schema_version: pragma_i64("user_version")?,
+            journal_mode: db.query_row("PRAGMA journal_mode", [], |row| row.get(0))?,
+            size: pragma_i64("page_count")? as u64 * page_size,
+            free: pragma_i64("freelist_count")? as u64 * page_size,
+            row_counts,
+            problems,
+        })
+    }
+
     /// Reload all in-memory data from the database
-    pub fn reload_from_db(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+    pub fn reload_from_db(&mut self) -> Result<(), Error> {
         let entries = Self::load_from_db(&self.db)?;
-        self.haystacks = entries
-            .iter()
-            .map(|e| Utf32String::from(e.command.as_str()))
-            .collect();
+        self.positions = Self::index_positions(&entries);
         self.entries = entries;
+        if self.time_aware {
+            self.time_profiles = Self::load_time_profiles(&self.db)?;
+        }
+        if self.failure_penalty > 0 {
+            self.outcomes = Self::load_outcomes(&self.db)?;
+        }
+        self.tags = Self::load_tags(&self.db)?;
+        self.durations = Self::load_durations(&self.db)?;
         Ok(())
     }
 
+    /// Another connection to this searcher's database, with the same
+    /// settings, for syncing on another thread. Nothing is loaded: call
+    /// `reload_from_db` there first. None for in-memory and read-only
+    /// searchers, whose database another connection can't write.
+    pub fn reopen(&self) -> Result<Option<Self>, Error> {
+        let path = match self.db.path() {
+            Some(path) if !self.read_only && !path.is_empty() => PathBuf::from(path),
+            _ => return Ok(None),
+        };
+        debug!("Opening a second connection to {}", path.display());
+        Ok(Some(Self {
+            entries: Vec::new(),
+            positions: HashMap::new(),
+            matcher: Matcher::new(self.algorithm.config()),
+            path_matcher: Matcher::new(self.algorithm.config().match_paths()),
+            path_aware: self.path_aware,
+            min_fuzzy_length: self.min_fuzzy_length,
+            query_buf: Vec::new(),
+            db: Connection::open(&path)?,
+            read_only: false,
+            match_all_terms: self.match_all_terms,
+            rank_by: self.rank_by,
+            algorithm: self.algorithm,
+            fuzzy_weight: self.fuzzy_weight,
+            frequency_weight: self.frequency_weight,
+            strip_ansi: self.strip_ansi,
+            strip_comments: self.strip_comments,
+            strip_prompt_prefixes: self.strip_prompt_prefixes.clone(),
+            sync_batch_size: self.sync_batch_size,
+            time_aware: self.time_aware,
+            time_profiles: HashMap::new(),
+            failure_penalty: self.failure_penalty,
+            outcomes: HashMap::new(),
+            tags: HashMap::new(),
+            durations: HashMap::new(),
+        }))
+    }
+
+    /// Take the commands and statistics `other`, a `reopen`ed connection,
+    /// loaded, keeping this searcher's connection and settings. Statistics
+    /// this searcher doesn't rank by, or `other` didn't load, stay as they are.
+    pub fn adopt(&mut self, other: Self) {
+        self.entries = other.entries;
+        self.positions = other.positions;
+        if self.time_aware && other.time_aware {
+            self.time_profiles = other.time_profiles;
+        }
+        if self.failure_penalty > 0 && other.failure_penalty > 0 {
+            self.outcomes = other.outcomes;
+        }
+        self.tags = other.tags;
+        self.durations = other.durations;
+    }
+
+    /// SQLite's `PRAGMA data_version`: changes when another connection
+    /// commits to the database, so a loaded searcher can tell it's stale
+    pub fn data_version(&self) -> Result<i64, Error> {
+        Ok(self.db.query_row("PRAGMA data_version", [], |row| row.get(0))?)
+    }
+
     /// Get the most recently used command (by last_used timestamp)
     pub fn most_recent_command(&self) -> Option<&IndexedCommand> {
-        self.entries
-            .iter()
+        self.commands()
             .filter(|e| e.last_used.is_some())
             .max_by_key(|e| e.last_used)
     }
 
-    /// Get all commands (for displaying in TUI)
-    pub fn get_all_commands(&self) -> &[IndexedCommand] {
-        &self.entries
+    /// When `command` was last used (unix seconds), if it's indexed and has a timestamp
+    pub fn last_used(&self, command: &str) -> Option<i64> {
+        let &index = self.positions.get(command)?;
+        self.entries[index].meta.last_used
+    }
+
+    /// Tags of `command`, sorted; empty if it has none or isn't in the history
+    pub fn tags(&self, command: &str) -> &[String] {
+        self.positions
+            .get(command)
+            .and_then(|&index| self.tags.get(&self.entries[index].meta.id))
+            .map_or(&[], Vec::as_slice)
+    }
+
+    /// Tag `command` with `tag` (see [`normalize_tag`]). Returns false if
+    /// it's already tagged so, or isn't in the history to tag. Read-only
+    /// history can't be tagged.
+    pub fn add_tag(&mut self, command: &str, tag: &str) -> Result<bool, Error> {
+        let Some(id) = self.taggable_id(command) else {
+            return Ok(false);
+        };
+        let tags = self.tags.entry(id).or_default();
+        let Err(at) = tags.binary_search_by(|t| t.as_str().cmp(tag)) else {
+            return Ok(false);
+        };
+        self.db.execute("INSERT OR IGNORE INTO command_tags (command_id, tag) VALUES (?, ?)", params![id, tag])?;
+        tags.insert(at, tag.to_string());
+        Ok(true)
+    }
+
+    /// Take `tag` off `command`. Returns false if it didn't have it.
+    pub fn remove_tag(&mut self, command: &str, tag: &str) -> Result<bool, Error> {
+        let Some(id) = self.taggable_id(command) else {
+            return Ok(false);
+        };
+        let Some(tags) = self.tags.get_mut(&id) else {
+            return Ok(false);
+        };
+        let Ok(at) = tags.binary_search_by(|t| t.as_str().cmp(tag)) else {
+            return Ok(false);
+        };
+        self.db.execute("DELETE FROM command_tags WHERE command_id = ? AND tag = ?", params![id, tag])?;
+        tags.remove(at);
+        if tags.is_empty() {
+            self.tags.remove(&id);
+        }
+        Ok(true)
+    }
+
+    /// Database id of `command`, unless it can't be tagged
+    fn taggable_id(&self, command: &str) -> Option<i64> {
+        if self.read_only {
+            return None;
+        }
+        let &index = self.positions.get(command)?;
+        Some(self.entries[index].meta.id)
+    }
+
+    /// Fuzzy search restricted to commands tagged `tag`
+    pub fn search_tagged(&mut self, query: &str, tag: &str, limit: usize) -> Vec<SearchResult> {
+        let tags = std::mem::take(&mut self.tags);
+        let results = self.search_filtered(query, limit, |meta| {
+            tags.get(&meta.id).is_some_and(|tags| tags.iter().any(|t| t == tag))
+        });
+        self.tags = tags;
+        results
+    }
+
+    /// The known program (first word of an indexed command) closest to `name`,
+    /// for "did you mean" hints. Only near misses count: one edit for short
+    /// names, two from six characters on. Ties go to the most used program.
+    pub fn closest_program(&self, name: &str) -> Option<String> {
+        let max_distance = if name.chars().count() >= 6 { 2 } else { 1 };
+
+        let mut usage: HashMap<&str, u32> = HashMap::new();
+        for cmd in self.commands() {
+            if let Some(program) = cmd.command.split_whitespace().next()
+                && program != name
+            {
+                *usage.entry(program).or_insert(0) += cmd.frequency;
+            }
+        }
+
+        usage
+            .into_iter()
+            .map(|(program, frequency)| (edit_distance(name, program), frequency, program))
+            .filter(|&(distance, _, _)| distance <= max_distance)
+            .min_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)).then(a.2.cmp(b.2)))
+            .map(|(_, _, program)| program.to_string())
+    }
+
+    /// All commands, most frequent first
+    pub fn commands(&self) -> impl ExactSizeIterator<Item = &IndexedCommand> + DoubleEndedIterator {
+        self.entries.iter().map(|e| &e.meta)
+    }
+
+    /// All commands, collected for tests that look one up
+    #[cfg(test)]
+    pub fn get_all_commands(&self) -> Vec<&IndexedCommand> {
+        self.commands().collect()
     }
 
     /// Get command count
     pub fn len(&self) -> usize {
         self.entries.len()
     }
+
+    /// Whether no commands are indexed
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
 }
 
 #[cfg(test)]
@@ -402,10 +2071,7 @@ mod tests {
         let mut searcher = HistorySearcher::new(temp_db.path().to_path_buf()).unwrap();
 
         // Insert test commands
-        let entry = HistoryEntry {
-            command: "cargo build".to_string(),
-            timestamp: Some(1234567890),
-        };
+        let entry = HistoryEntry::new("cargo build".to_string(), Some(1234567890));
 
         searcher.insert_or_update_command(&entry, "Bash").unwrap();
         searcher.reload_from_db().unwrap();
@@ -422,10 +2088,7 @@ mod tests {
         let mut searcher = HistorySearcher::new(temp_db.path().to_path_buf()).unwrap();
 
         // Insert and record usage
-        let entry = HistoryEntry {
-            command: "cargo test".to_string(),
-            timestamp: Some(1234567890),
-        };
+        let entry = HistoryEntry::new("cargo test".to_string(), Some(1234567890));
 
         searcher.insert_or_update_command(&entry, "Zsh").unwrap();
         searcher.reload_from_db().unwrap();
@@ -440,4 +2103,1018 @@ mod tests {
             .unwrap();
         assert_eq!(freq, 3); // 1 initial + 2 uses
     }
+
+    #[test]
+    fn test_record_usage_keeps_frequency_order() {
+        let temp_db = NamedTempFile::new().unwrap();
+        let mut searcher = HistorySearcher::new(temp_db.path().to_path_buf()).unwrap();
+
+        for command in ["a", "b", "c", "d"] {
+            let entry = HistoryEntry::new(command.to_string(), Some(1));
+            searcher.insert_or_update_command(&entry, "Bash").unwrap();
+        }
+        searcher.reload_from_db().unwrap();
+
+        searcher.record_usage("d").unwrap();
+        searcher.record_usage("c").unwrap();
+        searcher.record_usage("d").unwrap();
+        searcher.record_usage("new").unwrap();
+
+        let entries: Vec<_> = searcher.commands().collect();
+        assert!(entries.windows(2).all(|w| w[0].frequency >= w[1].frequency));
+        assert_eq!(entries[0].command, "d");
+        assert_eq!(entries[1].command, "c");
+
+        // Lookups stay consistent after reordering
+        for (i, entry) in entries.iter().enumerate() {
+            assert_eq!(searcher.positions[&entry.command], i);
+        }
+    }
+
+    #[test]
+    fn test_delete_matching() {
+        let temp_db = NamedTempFile::new().unwrap();
+        let mut searcher = HistorySearcher::new(temp_db.path().to_path_buf()).unwrap();
+        for command in ["ls", "export TOKEN=abc", "curl -H 'TOKEN: abc'", "export TOKEN=abc", "make"] {
+            searcher.record_usage(command).unwrap();
+        }
+
+        let deleted = searcher.delete_matching(|c| c.contains("TOKEN")).unwrap();
+        assert_eq!(deleted, ["export TOKEN=abc", "curl -H 'TOKEN: abc'"]);
+        assert!(searcher.search("TOKEN", 10).is_empty());
+        assert_eq!(searcher.len(), 2);
+        for (i, entry) in searcher.commands().enumerate() {
+            assert_eq!(searcher.positions[&entry.command], i);
+        }
+
+        // Gone from the database, including the per-shell counts
+        let mut reopened = HistorySearcher::new(temp_db.path().to_path_buf()).unwrap();
+        assert_eq!(reopened.len(), 2);
+        assert!(reopened.usage_by_shell(10).unwrap().iter().all(|u| !u.command.contains("TOKEN")));
+        for table in ["command_shell_counts", "command_times"] {
+            let orphans: i64 = reopened
+                .db
+                .query_row(
+                    &format!("SELECT COUNT(*) FROM {} WHERE command_id NOT IN (SELECT id FROM commands)", table),
+                    [],
+                    |row| row.get(0),
+                )
+                .unwrap();
+            assert_eq!(orphans, 0, "{}", table);
+        }
+        assert!(reopened.delete_matching(|c| c.contains("TOKEN")).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_optimize_vacuums() {
+        let temp_db = NamedTempFile::new().unwrap();
+        let mut searcher = HistorySearcher::new(temp_db.path().to_path_buf()).unwrap();
+        searcher
+            .db
+            .execute_batch(
+                "WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 500)
+                 INSERT INTO commands (command, shell_source) SELECT 'echo ' || i || ' ' || hex(zeroblob(100)), 'bash' FROM n",
+            )
+            .unwrap();
+        searcher.reload_from_db().unwrap();
+        searcher.delete_matching(|c| c.starts_with("echo")).unwrap();
+        searcher.record_usage("make").unwrap();
+
+        assert_eq!(searcher.optimize(false).unwrap(), None);
+
+        // Another connection holding a lock: skipped rather than waited for
+        let other = Connection::open(temp_db.path()).unwrap();
+        other.execute_batch("BEGIN IMMEDIATE").unwrap();
+        assert_eq!(searcher.optimize(true).unwrap(), None);
+        other.execute_batch("COMMIT").unwrap();
+
+        assert!(searcher.optimize(true).unwrap().unwrap() > 0);
+        assert_eq!(searcher.search("make", 5).len(), 1);
+
+        let mut read_only = HistorySearcher::open_read_only(temp_db.path().to_path_buf()).unwrap();
+        assert_eq!(read_only.optimize(true).unwrap(), None);
+    }
+
+    #[test]
+    fn test_search_in_range() {
+        let temp_db = NamedTempFile::new().unwrap();
+        let mut searcher = HistorySearcher::new(temp_db.path().to_path_buf()).unwrap();
+
+        for (command, ts) in [("cargo build", Some(100)), ("cargo test", Some(500)), ("cargo run", None)] {
+            let entry = HistoryEntry::new(command.to_string(), ts);
+            searcher.insert_or_update_command(&entry, "Bash").unwrap();
+        }
+        searcher.reload_from_db().unwrap();
+
+        let results = searcher.search_in_range("cargo", 400, 600, 10);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].command, "cargo test");
+
+        // Empty query lists everything in range; NULL last_used is never included
+        let results = searcher.search_in_range("", 0, 1000, 10);
+        assert_eq!(results.len(), 2);
+        assert!(!results.iter().any(|r| r.command == "cargo run"));
+    }
+
+    #[test]
+    fn test_rank_by_modes() {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+        let day = SECONDS_PER_DAY as i64;
+        let mut searcher = HistorySearcher::new_in_memory().unwrap();
+        // (command, frequency, last used)
+        for (command, frequency, last_used) in [
+            ("cargo build", 100, now - 30 * day),
+            ("cargo test", 1, now - 60),
+            ("cargo run", 200, now - 2 * day),
+        ] {
+            let entry = HistoryEntry::new(command.to_string(), Some(last_used));
+            for _ in 0..frequency {
+                searcher.insert_or_update_command(&entry, "Bash").unwrap();
+            }
+        }
+        searcher.reload_from_db().unwrap();
+
+        let mut ranked = |rank_by, query| {
+            searcher.set_rank_by(rank_by);
+            searcher
+                .search(query, 10)
+                .into_iter()
+                .map(|r| r.command)
+                .collect::<Vec<_>>()
+        };
+        for query in ["", "cargo"] {
+            assert_eq!(
+                ranked(RankBy::Frequency, query),
+                vec!["cargo run", "cargo build", "cargo test"]
+            );
+            assert_eq!(
+                ranked(RankBy::Recency, query),
+                vec!["cargo test", "cargo run", "cargo build"]
+            );
+            // Frequent and recent beats either alone
+            assert_eq!(
+                ranked(RankBy::Balanced, query),
+                vec!["cargo run", "cargo test", "cargo build"]
+            );
+        }
+    }
+
+    #[test]
+    fn test_rank_weights() {
+        let mut searcher = HistorySearcher::new_in_memory().unwrap();
+        // A rare exact match and a frequent loose one
+        for (command, frequency) in [("cargo test", 1), ("cargo run --features test", 50)] {
+            let entry = HistoryEntry::new(command.to_string(), Some(1));
+            for _ in 0..frequency {
+                searcher.insert_or_update_command(&entry, "Bash").unwrap();
+            }
+        }
+        searcher.reload_from_db().unwrap();
+
+        let mut top = |fuzzy_weight, frequency_weight| {
+            searcher.set_rank_weights(fuzzy_weight, frequency_weight);
+            searcher.search("cargo test", 2)[0].command.clone()
+        };
+        assert_eq!(top(DEFAULT_FUZZY_WEIGHT, DEFAULT_FREQUENCY_WEIGHT), "cargo run --features test");
+        // Frequency ignored: the better match wins
+        assert_eq!(top(1, 0), "cargo test");
+        // Fuzzy score dominates
+        assert_eq!(top(100, DEFAULT_FREQUENCY_WEIGHT), "cargo test");
+        // Fuzzy score ignored: only usage counts
+        assert_eq!(top(0, 1), "cargo run --features test");
+    }
+
+    #[test]
+    fn test_recency_bonus() {
+        let day = SECONDS_PER_DAY as i64;
+        assert_eq!(recency_bonus(Some(1000), 1000), 1000);
+        assert_eq!(recency_bonus(Some(0), day), 500);
+        assert_eq!(recency_bonus(None, day), 0);
+        // Future timestamps (clock skew) count as just used
+        assert_eq!(recency_bonus(Some(2000), 1000), 1000);
+    }
+
+    #[test]
+    fn test_parse_tag_filter() {
+        assert_eq!(
+            parse_tag_filter("@tag:Deploy  prod"),
+            Some(TagFilter { tag: "deploy".to_string(), query: "prod" })
+        );
+        assert_eq!(parse_tag_filter("@tag:#db").unwrap().query, "");
+        assert!(parse_tag_filter("@tag: make").is_none());
+        assert!(parse_tag_filter("make @tag:db").is_none());
+        assert_eq!(normalize_tag("a:b"), None);
+    }
+
+    #[test]
+    fn test_tags() {
+        let temp_db = NamedTempFile::new().unwrap();
+        let mut searcher = HistorySearcher::new(temp_db.path().to_path_buf()).unwrap();
+        for command in ["make deploy", "make test", "psql prod"] {
+            searcher.record_usage(command).unwrap();
+        }
+
+        assert!(searcher.add_tag("make deploy", "deploy").unwrap());
+        assert!(searcher.add_tag("psql prod", "prod").unwrap());
+        assert!(searcher.add_tag("psql prod", "db").unwrap());
+        assert!(!searcher.add_tag("psql prod", "db").unwrap());
+        assert!(!searcher.add_tag("not in history", "db").unwrap());
+        assert_eq!(searcher.tags("psql prod"), ["db", "prod"]);
+
+        let found = |searcher: &mut HistorySearcher, query: &str, tag: &str| {
+            searcher.search_tagged(query, tag, 10).into_iter().map(|r| r.command).collect::<Vec<_>>()
+        };
+        assert_eq!(found(&mut searcher, "", "deploy"), ["make deploy"]);
+        assert_eq!(found(&mut searcher, "make", "db"), Vec::<String>::new());
+
+        // Tags persist, and go with the command
+        drop(searcher);
+        let mut searcher = HistorySearcher::new(temp_db.path().to_path_buf()).unwrap();
+        assert_eq!(searcher.tags("psql prod"), ["db", "prod"]);
+        assert!(searcher.remove_tag("psql prod", "prod").unwrap());
+        assert!(!searcher.remove_tag("psql prod", "prod").unwrap());
+        assert_eq!(searcher.tags("psql prod"), ["db"]);
+        searcher.delete_matching(|c| c == "psql prod").unwrap();
+        assert_eq!(found(&mut searcher, "", "db"), Vec::<String>::new());
+        let rows: i64 = searcher.db.query_row("SELECT COUNT(*) FROM command_tags", [], |row| row.get(0)).unwrap();
+        assert_eq!(rows, 1);
+    }
+
+    #[test]
+    fn test_parse_time_filter() {
+        let now = 1_000_000;
+        assert_eq!(
+            parse_time_filter("@since:2d cargo build", now),
+            Some(TimeFilter { since: now - 2 * 86_400, query: "cargo build" })
+        );
+        assert_eq!(
+            parse_time_filter("@since:30m", now),
+            Some(TimeFilter { since: now - 1_800, query: "" })
+        );
+        assert_eq!(parse_time_filter("@since:1w git", now).unwrap().since, now - 604_800);
+        assert!(parse_time_filter("@since:2x cargo", now).is_none());
+        assert!(parse_time_filter("@since: cargo", now).is_none());
+        assert!(parse_time_filter("cargo @since:2d", now).is_none());
+    }
+
+    #[test]
+    fn test_read_only_never_writes() {
+        let temp_db = NamedTempFile::new().unwrap();
+        {
+            let searcher = HistorySearcher::new(temp_db.path().to_path_buf()).unwrap();
+            let entry = HistoryEntry::new("cargo test".to_string(), Some(1234567890));
+            searcher.insert_or_update_command(&entry, "Zsh").unwrap();
+        }
+
+        let mut searcher = HistorySearcher::open_read_only(temp_db.path().to_path_buf()).unwrap();
+        assert!(searcher.is_read_only());
+        assert_eq!(searcher.len(), 1);
+
+        searcher.record_usage("cargo test").unwrap();
+        searcher.record_usage("brand new").unwrap();
+        searcher.flush().unwrap();
+        assert_eq!(searcher.sync_from_shell_history(Shell::Bash).unwrap(), 0);
+
+        let reopened = HistorySearcher::new(temp_db.path().to_path_buf()).unwrap();
+        let commands: Vec<_> = reopened.commands().collect();
+        assert_eq!(commands.len(), 1);
+        assert_eq!(commands[0].frequency, 1);
+    }
+
+    #[test]
+    fn test_path_aware_queries() {
+        let temp_db = NamedTempFile::new().unwrap();
+        let mut searcher = HistorySearcher::new(temp_db.path().to_path_buf()).unwrap();
+        for command in ["git diff HEAD -- dep/prod", "./scripts/dep/prod.sh"] {
+            searcher.record_usage(command).unwrap();
+        }
+        searcher.set_rank_weights(1, 0);
+
+        // A path query favours matches starting path segments over words
+        assert_eq!(searcher.search("dep/prod", 2)[0].command, "./scripts/dep/prod.sh");
+        searcher.set_path_aware(false);
+        assert_eq!(searcher.search("dep/prod", 2)[0].command, "git diff HEAD -- dep/prod");
+
+        // Queries without a slash always use the default matcher
+        searcher.set_path_aware(true);
+        let with = searcher.search("dep prod", 2);
+        searcher.set_path_aware(false);
+        let without = searcher.search("dep prod", 2);
+        assert_eq!(
+            with.iter().map(|r| (&r.command, r.score)).collect::<Vec<_>>(),
+            without.iter().map(|r| (&r.command, r.score)).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_match_algorithms() {
+        let mut searcher = HistorySearcher::new_in_memory().unwrap();
+        for command in ["make README", "cat readme.md", "echo café"] {
+            searcher.record_usage(command).unwrap();
+        }
+        let found = |searcher: &mut HistorySearcher, query| {
+            let mut commands: Vec<String> = searcher.search(query, 5).into_iter().map(|r| r.command).collect();
+            commands.sort();
+            commands
+        };
+
+        assert_eq!(found(&mut searcher, "README"), ["cat readme.md", "make README"]);
+        assert_eq!(found(&mut searcher, "cafe"), ["echo café"]);
+
+        // Uppercase in the query makes smartcase case-sensitive
+        searcher.set_algorithm(MatchAlgorithm::Smartcase);
+        assert_eq!(found(&mut searcher, "README"), ["make README"]);
+        assert_eq!(found(&mut searcher, "readme"), ["cat readme.md", "make README"]);
+        assert_eq!(found(&mut searcher, "cafe"), ["echo café"]);
+
+        searcher.set_algorithm(MatchAlgorithm::Strict);
+        assert_eq!(found(&mut searcher, "readme"), ["cat readme.md"]);
+        assert!(found(&mut searcher, "cafe").is_empty());
+
+        // Prefix favours matches at the start of the command
+        searcher.record_usage("git status").unwrap();
+        searcher.record_usage("echo git").unwrap();
+        searcher.set_rank_weights(1, 0);
+        searcher.set_algorithm(MatchAlgorithm::Default);
+        let default = searcher.search("git", 2);
+        searcher.set_algorithm(MatchAlgorithm::Prefix);
+        let prefix = searcher.search("git", 2);
+        assert_eq!(prefix[0].command, "git status");
+        let score = |results: &[SearchResult], command| results.iter().find(|r| r.command == command).unwrap().score;
+        assert!(score(&prefix, "git status") > score(&default, "git status"));
+    }
+
+    #[test]
+    fn test_time_profile_bonus() {
+        let mut profile = TimeProfile::default();
+        assert_eq!(profile.bonus((0, 9)), 0);
+
+        // Monday mornings, five times
+        profile.add((0, 9), 4);
+        profile.add((0, 10), 1);
+        assert_eq!(profile.bonus((0, 9)), TIME_AWARE_BONUS as u32);
+        // An hour either side still counts; the weekday weighs less
+        assert_eq!(profile.bonus((3, 8)), 600);
+        assert_eq!(profile.bonus((0, 21)), 250);
+        // Midnight wraps around
+        profile.add((1, 0), 5);
+        assert!(profile.bonus((1, 23)) > 0);
+
+        // Too few runs for full confidence
+        let mut once = TimeProfile::default();
+        once.add((0, 9), 1);
+        assert_eq!(once.bonus((0, 9)), 200);
+    }
+
+    #[test]
+    fn test_time_aware_ranking() {
+        let mut searcher = HistorySearcher::new_in_memory().unwrap();
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+        let week = 7 * SECONDS_PER_DAY as i64;
+        // Run at this time of day on this weekday in past weeks, and far more
+        // often half a day off
+        let runs = [("./standup.sh", 0, 5), ("./backup.sh", 12 * 60 * 60 + 3 * SECONDS_PER_DAY as i64, 20)];
+        for (command, offset, times) in runs {
+            for i in 1..=times {
+                let entry = HistoryEntry::new(command.to_string(), Some(now - i * week + offset));
+                searcher.insert_or_update_command(&entry, "Zsh").unwrap();
+            }
+        }
+        searcher.reload_from_db().unwrap();
+        let top = |searcher: &mut HistorySearcher, query: &str| searcher.search(query, 1)[0].command.clone();
+
+        assert_eq!(top(&mut searcher, ".sh"), "./backup.sh");
+        assert_eq!(top(&mut searcher, ""), "./backup.sh");
+
+        searcher.set_time_aware(true).unwrap();
+        assert_eq!(top(&mut searcher, ".sh"), "./standup.sh");
+        assert_eq!(top(&mut searcher, ""), "./standup.sh");
+
+        // Runs in mux count too
+        searcher.record_usage("./standup.sh").unwrap();
+        let id = searcher.entries[searcher.positions["./standup.sh"]].meta.id;
+        assert_eq!(searcher.time_profiles[&id].hours.iter().sum::<u32>(), 6);
+
+        searcher.set_time_aware(false).unwrap();
+        assert_eq!(top(&mut searcher, ".sh"), "./backup.sh");
+    }
+
+    #[test]
+    fn test_failure_penalty() {
+        let mut searcher = HistorySearcher::new_in_memory().unwrap();
+        for (command, uses) in [("make deploy", 6), ("make test", 5), ("make lint", 2)] {
+            for _ in 0..uses {
+                searcher.record_usage(command).unwrap();
+            }
+        }
+        let commands = |searcher: &mut HistorySearcher, query: &str| {
+            searcher.search(query, 10).into_iter().map(|r| r.command).collect::<Vec<_>>()
+        };
+        for _ in 0..4 {
+            searcher.record_outcome("make deploy", false).unwrap();
+        }
+        searcher.record_outcome("make test", false).unwrap();
+        assert_eq!(commands(&mut searcher, "make"), ["make deploy", "make test", "make lint"]);
+
+        searcher.set_failure_penalty(50).unwrap();
+        // Four failures in four runs sink it; one failure in one run barely moves it
+        assert_eq!(commands(&mut searcher, "make"), ["make test", "make lint", "make deploy"]);
+        assert_eq!(commands(&mut searcher, "")[0], "make test");
+
+        // Successes win it back, counted as they're recorded
+        for _ in 0..20 {
+            searcher.record_outcome("make deploy", true).unwrap();
+        }
+        assert_eq!(commands(&mut searcher, "make")[0], "make deploy");
+
+        // Nothing is dropped outright
+        assert!((smoothed_failure_rate(0, 1) - 0.25).abs() < 1e-9);
+        searcher.set_failure_penalty(0).unwrap();
+        assert!(searcher.outcomes.is_empty());
+    }
+
+    #[test]
+    fn test_min_fuzzy_length() {
+        let mut searcher = HistorySearcher::new_in_memory().unwrap();
+        for (command, uses) in [("git status", 3), ("cargo test", 2), ("go build", 1)] {
+            for _ in 0..uses {
+                searcher.record_usage(command).unwrap();
+            }
+        }
+        let commands = |results: Vec<SearchResult>| results.into_iter().map(|r| r.command).collect::<Vec<_>>();
+
+        // Default: a single character is fuzzy matched anywhere
+        assert_eq!(commands(searcher.search("g", 10)).len(), 3);
+
+        // Below the threshold: prefix matches by frequency, no fuzzy scan
+        searcher.set_min_fuzzy_length(3);
+        let short = searcher.search("G", 10);
+        assert_eq!(commands(short.clone()), ["git status", "go build"]);
+        assert_eq!(short[0].score, 3);
+        assert_eq!(commands(searcher.search("go", 10)), ["go build"]);
+        // At the threshold the fuzzy scan runs again
+        assert_eq!(commands(searcher.search("gst", 10)), ["git status", "cargo test"]);
+    }
+
+    #[test]
+    fn test_search_filtered_empty_query() {
+        let mut searcher = HistorySearcher::new_in_memory().unwrap();
+        for (command, uses) in [("cargo build", 1), ("cargo test", 3), ("git status", 2)] {
+            for _ in 0..uses {
+                searcher.record_usage(command).unwrap();
+            }
+        }
+
+        let results = searcher.search_filtered("", 10, |meta| meta.command.starts_with("cargo"));
+        let commands: Vec<_> = results.iter().map(|r| r.command.as_str()).collect();
+        assert_eq!(commands, ["cargo test", "cargo build"]);
+        assert_eq!(searcher.search_filtered("", 1, |_| true)[0].command, "cargo test");
+    }
+
+    #[test]
+    fn test_read_only_missing_database() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("missing.db");
+
+        let searcher = HistorySearcher::open_read_only(path.clone()).unwrap();
+        assert_eq!(searcher.len(), 0);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_match_all_terms() {
+        let mut searcher = HistorySearcher::new_in_memory().unwrap();
+        for command in ["docker image prune -a", "docker ps", "git prune"] {
+            let entry = HistoryEntry::new(command.to_string(), Some(1234567890));
+            searcher.insert_or_update_command(&entry, "Zsh").unwrap();
+        }
+        searcher.reload_from_db().unwrap();
+
+        // One pattern: "docker prune" needs the space right after "docker"
+        let results = searcher.search("prune docker", 10);
+        assert!(results.is_empty());
+
+        searcher.set_match_all_terms(true);
+        let results = searcher.search("docker prune", 10);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].command, "docker image prune -a");
+
+        // Order doesn't matter
+        let results = searcher.search("prune docker", 10);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].command, "docker image prune -a");
+
+        // A single term behaves as before
+        assert_eq!(searcher.search("prune", 10).len(), 2);
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("git", "git"), 0);
+        assert_eq!(edit_distance("gti", "git"), 1);
+        assert_eq!(edit_distance("dokcer", "docker"), 1);
+        assert_eq!(edit_distance("carg", "cargo"), 1);
+        assert_eq!(edit_distance("ls", "cat"), 3);
+        assert_eq!(edit_distance("", "ls"), 2);
+    }
+
+    #[test]
+    fn test_closest_program() {
+        let mut searcher = HistorySearcher::new_in_memory().unwrap();
+        for command in ["git status", "git push", "gist create", "kubectl get pods", "gti status"] {
+            searcher.record_usage(command).unwrap();
+        }
+        searcher.record_usage("git status").unwrap();
+
+        // The typo itself is in history but never suggested
+        assert_eq!(searcher.closest_program("gti").as_deref(), Some("git"));
+        assert_eq!(searcher.closest_program("kubctl").as_deref(), Some("kubectl"));
+        assert_eq!(searcher.closest_program("make"), None);
+    }
+
+    #[test]
+    fn test_strip_ansi() {
+        assert!(matches!(strip_ansi("git log\t--oneline"), Cow::Borrowed(_)));
+        assert_eq!(strip_ansi("\x1b[1;32mls\x1b[0m -la"), "ls -la");
+        assert_eq!(strip_ansi("\x1b]0;title\x07make\x1b]2;t\x1b\\ test"), "make test");
+        assert_eq!(strip_ansi("echo a\r\x08b\nc"), "echo ab\nc");
+        assert_eq!(strip_ansi("\x1b[31"), "");
+    }
+
+    #[test]
+    fn test_escape_sequences_are_not_stored() {
+        let mut searcher = HistorySearcher::new_in_memory().unwrap();
+        searcher.record_usage("\x1b[32mcargo build\x1b[0m").unwrap();
+        searcher.record_usage("cargo build").unwrap();
+        searcher.record_outcome("\x1b[32mcargo build\x1b[0m", false).unwrap();
+        searcher.record_usage("\x1b[0m").unwrap();
+
+        let stored: Vec<(String, u32, u32)> = searcher
+            .db
+            .prepare("SELECT command, frequency, fail_count FROM commands")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(stored, vec![("cargo build".to_string(), 2, 1)]);
+
+        let entries = vec![HistoryEntry::new("\x1b[1mmake\x1b[0m".to_string(), Some(1))];
+        assert_eq!(searcher.sync_entries(entries, "test").unwrap(), 1);
+        assert_eq!(searcher.search("make", 5)[0].command, "make");
+
+        searcher.set_strip_ansi(false);
+        searcher.record_usage("\x1b[1mls").unwrap();
+        assert!(searcher.last_used("\x1b[1mls").is_some());
+    }
+
+    #[test]
+    fn test_strip_trailing_comment() {
+        assert_eq!(strip_trailing_comment("make deploy # prod"), "make deploy");
+        assert_eq!(strip_trailing_comment("make deploy\t#prod"), "make deploy");
+        assert_eq!(strip_trailing_comment("ls;# list"), "ls;");
+        assert_eq!(strip_trailing_comment("# just a note"), "");
+        assert_eq!(strip_trailing_comment("make deploy"), "make deploy");
+
+        // `#` inside quotes, escaped or mid-word isn't a comment
+        assert_eq!(strip_trailing_comment("echo '# not a comment'"), "echo '# not a comment'");
+        assert_eq!(strip_trailing_comment("git commit -m \"fix #12\""), "git commit -m \"fix #12\"");
+        assert_eq!(strip_trailing_comment("echo \"a\\\" #b\""), "echo \"a\\\" #b\"");
+        assert_eq!(strip_trailing_comment("echo \\#hash"), "echo \\#hash");
+        assert_eq!(strip_trailing_comment("echo ${#args} a#b"), "echo ${#args} a#b");
+        assert_eq!(strip_trailing_comment("echo 'x' # y"), "echo 'x'");
+
+        // Only a comment on the last line is trailing
+        assert_eq!(strip_trailing_comment("a # one\nb"), "a # one\nb");
+        assert_eq!(strip_trailing_comment("a\nb # two"), "a\nb");
+    }
+
+    #[test]
+    fn test_prompt_prefixes_are_stripped_from_imports() {
+        let mut searcher = HistorySearcher::new_in_memory().unwrap();
+        searcher.set_strip_prompt_prefixes(vec!["$ ".into(), "# ".into(), "> ".into(), "".into()]);
+        searcher.set_strip_comments(true);
+        let entries: Vec<HistoryEntry> = [
+            "$ ls -la",
+            "  # systemctl restart nginx",
+            "> git status",
+            "\x1b[32m$ \x1b[0mcargo build",
+            "$ > out.txt",
+            "echo $HOME",
+            "$ ",
+        ]
+        .iter()
+        .enumerate()
+        .map(|(i, command)| HistoryEntry::new(command.to_string(), Some(i as i64 + 1)))
+        .collect();
+        assert_eq!(searcher.sync_entries(entries, "test").unwrap(), 6);
+
+        let mut stored: Vec<String> = searcher
+            .db
+            .prepare("SELECT command FROM commands")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        stored.sort();
+        assert_eq!(
+            stored,
+            ["> out.txt", "cargo build", "echo $HOME", "git status", "ls -la", "systemctl restart nginx"]
+        );
+
+        // Commands typed in mux are run as written, so they're kept
+        searcher.record_usage("$ ls").unwrap();
+        assert!(searcher.last_used("$ ls").is_some());
+    }
+
+    #[test]
+    fn test_commented_variants_share_an_entry() {
+        let mut searcher = HistorySearcher::new_in_memory().unwrap();
+        searcher.record_usage("make deploy # prod").unwrap();
+        assert_eq!(searcher.search("make", 5)[0].command, "make deploy # prod");
+
+        searcher.set_strip_comments(true);
+        searcher.record_usage("make deploy # prod").unwrap();
+        searcher.record_usage("make deploy").unwrap();
+        searcher.record_usage("echo '#tag' # note").unwrap();
+        let entries = vec![HistoryEntry::new("make deploy  # staging".to_string(), Some(1))];
+        searcher.sync_entries(entries, "test").unwrap();
+
+        let frequency = |command: &str| -> u32 {
+            searcher
+                .db
+                .query_row("SELECT frequency FROM commands WHERE command = ?", [command], |row| row.get(0))
+                .unwrap()
+        };
+        assert_eq!(frequency("make deploy"), 3);
+        assert_eq!(frequency("echo '#tag'"), 1);
+    }
+
+    #[test]
+    fn test_last_used() {
+        let mut searcher = HistorySearcher::new_in_memory().unwrap();
+        searcher.record_usage("git status").unwrap();
+
+        let last_used = searcher.last_used("git status").unwrap();
+        assert!((chrono::Local::now().timestamp() - last_used).abs() < 5);
+        assert_eq!(searcher.last_used("git push"), None);
+    }
+
+    #[test]
+    fn test_flaky_commands() {
+        let mut searcher = HistorySearcher::new_in_memory().unwrap();
+        for command in ["make test", "cargo build", "ls"] {
+            searcher.record_usage(command).unwrap();
+        }
+        for success in [true, false, false] {
+            searcher.record_outcome("make test", success).unwrap();
+        }
+        for success in [true, true, true, false] {
+            searcher.record_outcome("cargo build", success).unwrap();
+        }
+        searcher.record_outcome("ls", true).unwrap();
+        // Unknown commands are ignored
+        searcher.record_outcome("not in history", false).unwrap();
+
+        let flaky = searcher.flaky_commands(1, 10).unwrap();
+        let commands: Vec<_> = flaky.iter().map(|c| c.command.as_str()).collect();
+        assert_eq!(commands, vec!["make test", "cargo build"]);
+        assert!((flaky[0].failure_rate() - 2.0 / 3.0).abs() < 1e-9);
+
+        assert_eq!(searcher.flaky_commands(4, 10).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_record_duration() {
+        let mut searcher = HistorySearcher::new_in_memory().unwrap();
+        searcher.record_usage("cargo build").unwrap();
+        searcher.record_usage("ls").unwrap();
+        assert_eq!(searcher.typical_duration("cargo build"), None);
+
+        searcher.record_duration("cargo build", Duration::from_secs(10)).unwrap();
+        searcher.record_duration("cargo build", Duration::from_secs(20)).unwrap();
+        searcher.record_duration("ls", Duration::from_millis(5)).unwrap();
+        // Unknown commands are ignored
+        searcher.record_duration("not in history", Duration::from_secs(1)).unwrap();
+        assert_eq!(searcher.typical_duration("cargo build"), Some(Duration::from_secs(15)));
+        // Looked up like it was recorded, without color codes
+        assert_eq!(searcher.typical_duration("\x1b[1mcargo build\x1b[0m"), Some(Duration::from_secs(15)));
+
+        // Durations from shell history count too, and survive a reload
+        let entry = |command: &str, duration_ms| HistoryEntry {
+            command: command.to_string(),
+            timestamp: Some(1),
+            duration_ms,
+        };
+        let entries = vec![
+            entry("cargo build", Some(30_000)),
+            entry("make", Some(2_000)),
+            entry("make", None),
+        ];
+        searcher.sync_entries(entries, "Zsh").unwrap();
+        assert_eq!(searcher.typical_duration("cargo build"), Some(Duration::from_secs(20)));
+        assert_eq!(searcher.typical_duration("make"), Some(Duration::from_secs(2)));
+
+        let slowest = searcher.slowest_commands(2).unwrap();
+        assert_eq!(
+            slowest,
+            [
+                CommandDuration { command: "cargo build".to_string(), mean: Duration::from_secs(20), runs: 3 },
+                CommandDuration { command: "make".to_string(), mean: Duration::from_secs(2), runs: 1 },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_outcome_columns_added_to_old_database() {
+        let temp_db = NamedTempFile::new().unwrap();
+        {
+            let db = Connection::open(temp_db.path()).unwrap();
+            db.execute(
+                "CREATE TABLE commands (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    command TEXT NOT NULL UNIQUE,
+                    timestamp INTEGER,
+                    shell_source TEXT NOT NULL,
+                    frequency INTEGER NOT NULL DEFAULT 1,
+                    last_used INTEGER,
+                    created_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now'))
+                )",
+                [],
+            )
+            .unwrap();
+            db.execute(
+                "INSERT INTO commands (command, shell_source) VALUES ('make', 'Bash')",
+                [],
+            )
+            .unwrap();
+        }
+
+        // Read-only, nothing is upgraded and there are no runtimes to show
+        let old = HistorySearcher::open_read_only(temp_db.path().to_path_buf()).unwrap();
+        assert!(old.slowest_commands(10).unwrap().is_empty());
+        drop(old);
+
+        let mut searcher = HistorySearcher::new(temp_db.path().to_path_buf()).unwrap();
+        searcher.record_outcome("make", false).unwrap();
+        assert_eq!(searcher.flaky_commands(1, 10).unwrap()[0].fail_count, 1);
+        searcher.record_duration("make", Duration::from_millis(1500)).unwrap();
+        assert_eq!(searcher.slowest_commands(10).unwrap()[0].mean, Duration::from_millis(1500));
+    }
+
+    #[test]
+    fn test_usage_by_shell() {
+        let mut searcher = HistorySearcher::new_in_memory().unwrap();
+        let entry = |command: &str| HistoryEntry::new(command.to_string(), Some(1));
+        for (command, shell) in [("git status", "Zsh"), ("git status", "Zsh"), ("git status", "Bash"), ("ls", "Bash")] {
+            searcher.insert_or_update_command(&entry(command), shell).unwrap();
+        }
+        searcher.reload_from_db().unwrap();
+        searcher.record_usage("git status").unwrap();
+        searcher.record_usage("make").unwrap();
+
+        let usage = searcher.usage_by_shell(2).unwrap();
+        assert_eq!(usage.len(), 2);
+        assert_eq!(usage[0].command, "git status");
+        assert_eq!(usage[0].frequency, 4);
+        fn shells(usage: &ShellUsage) -> Vec<(&str, u32)> {
+            usage.shells.iter().map(|(s, n)| (s.as_str(), *n)).collect()
+        }
+        assert_eq!(shells(&usage[0]), [("Zsh", 2), ("Bash", 1), ("mux", 1)]);
+        // Ties go to the most recently used
+        assert_eq!(usage[1].command, "make");
+        assert_eq!(shells(&usage[1]), [("mux", 1)]);
+    }
+
+    #[test]
+    fn test_diagnose() {
+        let temp_db = NamedTempFile::new().unwrap();
+        let mut searcher = HistorySearcher::new(temp_db.path().to_path_buf()).unwrap();
+        searcher.record_usage("make").unwrap();
+        searcher.record_usage("ls").unwrap();
+        drop(searcher);
+
+        let diagnosis = HistorySearcher::diagnose(temp_db.path()).unwrap();
+        assert_eq!(diagnosis.problems, Vec::<String>::new());
+        assert_eq!(diagnosis.schema_version, 0);
+        assert_eq!(diagnosis.journal_mode, "delete");
+        assert!(diagnosis.size > 0);
+        assert!(diagnosis.row_counts.contains(&("commands".to_string(), 2)));
+
+        // Rows left behind by a deleted command
+        let db = Connection::open(temp_db.path()).unwrap();
+        db.execute("DELETE FROM commands WHERE command = 'ls'", []).unwrap();
+        drop(db);
+        let diagnosis = HistorySearcher::diagnose(temp_db.path()).unwrap();
+        assert_eq!(diagnosis.problems, ["1 rows of command_shell_counts belong to no command", "1 rows of command_times belong to no command"]);
+    }
+
+    #[test]
+    fn test_move_to_backup() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("history.db");
+        let mut searcher = HistorySearcher::new(db_path.clone()).unwrap();
+        searcher.record_usage("make").unwrap();
+        drop(searcher);
+        // A stale journal of an earlier backup is removed
+        std::fs::write(dir.path().join("history.db.bak-wal"), "stale").unwrap();
+
+        let backup = HistorySearcher::move_to_backup(&db_path).unwrap();
+        assert_eq!(backup, dir.path().join("history.db.bak"));
+        assert!(!db_path.exists());
+        assert!(!dir.path().join("history.db.bak-wal").exists());
+        let mut searcher = HistorySearcher::open_read_only(backup).unwrap();
+        assert_eq!(searcher.search("make", 5)[0].command, "make");
+    }
+
+    #[test]
+    fn test_diagnose_duplicates() {
+        // An old or hand-edited table without the UNIQUE constraint
+        let temp_db = NamedTempFile::new().unwrap();
+        let db = Connection::open(temp_db.path()).unwrap();
+        db.execute_batch(
+            "CREATE TABLE commands (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                command TEXT NOT NULL,
+                shell_source TEXT NOT NULL,
+                frequency INTEGER NOT NULL DEFAULT 1,
+                last_used INTEGER,
+                success_count INTEGER NOT NULL DEFAULT 0,
+                fail_count INTEGER NOT NULL DEFAULT 0
+            );
+            INSERT INTO commands (command, shell_source) VALUES ('make', 'Bash'), ('make', 'Zsh'), ('ls', 'Zsh');",
+        )
+        .unwrap();
+        drop(db);
+
+        let diagnosis = HistorySearcher::diagnose(temp_db.path()).unwrap();
+        assert_eq!(diagnosis.problems, ["1 commands are stored more than once"]);
+    }
+
+    #[test]
+    fn test_shell_counts_backfilled_for_old_database() {
+        let temp_db = NamedTempFile::new().unwrap();
+        {
+            let db = Connection::open(temp_db.path()).unwrap();
+            db.execute(
+                "CREATE TABLE commands (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    command TEXT NOT NULL UNIQUE,
+                    timestamp INTEGER,
+                    shell_source TEXT NOT NULL,
+                    frequency INTEGER NOT NULL DEFAULT 1,
+                    last_used INTEGER,
+                    created_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now'))
+                )",
+                [],
+            )
+            .unwrap();
+            db.execute(
+                "INSERT INTO commands (command, shell_source, frequency) VALUES ('make', 'Fish', 5)",
+                [],
+            )
+            .unwrap();
+        }
+
+        let mut searcher = HistorySearcher::new(temp_db.path().to_path_buf()).unwrap();
+        searcher.record_usage("make").unwrap();
+        let usage = searcher.usage_by_shell(10).unwrap();
+        assert_eq!(usage[0].shells, [("Fish".to_string(), 5), ("mux".to_string(), 1)]);
+
+        // Reopening doesn't credit the runs again
+        drop(searcher);
+        let searcher = HistorySearcher::new(temp_db.path().to_path_buf()).unwrap();
+        assert_eq!(searcher.usage_by_shell(10).unwrap()[0].shells[0], ("Fish".to_string(), 5));
+    }
+
+    #[test]
+    fn test_sync_from_command() {
+        let mut searcher = HistorySearcher::new_in_memory().unwrap();
+        assert_eq!(searcher.sync_from_command("echo 'make deploy'").unwrap(), 1);
+        assert_eq!(searcher.sync_from_command("echo 'make deploy'").unwrap(), 0);
+        assert_eq!(searcher.search("deploy", 10)[0].command, "make deploy");
+
+        assert!(searcher.sync_from_command("exit 1").is_err());
+    }
+
+    #[test]
+    fn test_sync_in_batches() {
+        let temp_db = NamedTempFile::new().unwrap();
+        let mut searcher = HistorySearcher::new(temp_db.path().to_path_buf()).unwrap();
+        searcher.set_sync_batch_size(2);
+        let dir = tempfile::tempdir().unwrap();
+        let history = dir.path().join("history");
+        std::fs::write(&history, "ls\ncd /tmp\nmake\nmake test\ngit status\n").unwrap();
+
+        assert_eq!(searcher.sync_from_plain_file(&history).unwrap(), 5);
+        assert_eq!(searcher.len(), 5);
+        assert_eq!(searcher.sync_from_plain_file(&history).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_sync_resumes_at_line() {
+        let mut searcher = HistorySearcher::new_in_memory().unwrap();
+        let entry = |command: &str, timestamp| HistoryEntry::new(command.to_string(), Some(timestamp));
+        // Timestamps out of file order, as after a clock change
+        let history = vec![entry("make", 200), entry("ls", 100), entry("git status", 300)];
+
+        // A sync interrupted after its first batch of one
+        searcher.insert_or_update_command(&history[0], "Test").unwrap();
+        HistorySearcher::update_sync_state_on(&searcher.db, "Test", 0, 0, 1).unwrap();
+
+        // Picks up at the second line: `make`, though newer than `ls`, isn't counted twice
+        assert_eq!(searcher.sync_entries(history.clone(), "Test").unwrap(), 2);
+        let make = searcher.get_all_commands().into_iter().find(|e| e.command == "make").unwrap();
+        assert_eq!(make.frequency, 1);
+        assert_eq!(searcher.get_sync_state("Test").unwrap().2, 0);
+        assert_eq!(searcher.sync_entries(history, "Test").unwrap(), 0);
+    }
+
+    #[test]
+    fn test_in_memory_searcher() {
+        let mut searcher = HistorySearcher::new_in_memory().unwrap();
+        let entry = HistoryEntry::new("cargo build".to_string(), Some(1234567890));
+        searcher.insert_or_update_command(&entry, "Zsh").unwrap();
+        searcher.reload_from_db().unwrap();
+
+        searcher.record_usage("cargo build").unwrap();
+        searcher.record_usage("git status").unwrap();
+        searcher.flush().unwrap();
+
+        let results = searcher.search("cargo", 10);
+        assert_eq!(results[0].command, "cargo build");
+        assert_eq!(searcher.len(), 2);
+    }
+
+    #[test]
+    fn test_sync_from_plain_file_is_incremental() {
+        let temp_db = NamedTempFile::new().unwrap();
+        let mut searcher = HistorySearcher::new(temp_db.path().to_path_buf()).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let history = dir.path().join("history");
+        std::fs::write(&history, "ls\ncd /tmp\n").unwrap();
+
+        assert_eq!(searcher.sync_from_plain_file(&history).unwrap(), 2);
+        assert_eq!(searcher.sync_from_plain_file(&history).unwrap(), 0);
+
+        std::fs::write(&history, "ls\ncd /tmp\nmake\n").unwrap();
+        assert_eq!(searcher.sync_from_plain_file(&history).unwrap(), 1);
+        assert_eq!(searcher.len(), 3);
+    }
+
+    #[test]
+    fn test_search_matches_unfiltered_ranking() {
+        let temp_db = NamedTempFile::new().unwrap();
+        let mut searcher = HistorySearcher::new(temp_db.path().to_path_buf()).unwrap();
+
+        let commands = [
+            "cargo build --release",
+            "cargo test",
+            "git commit -m 'Ünïcode message'",
+            "git push origin main",
+            "kubectl logs -f pod/web",
+            "CARGO_HOME=/tmp cargo check",
+            "echo café",
+            "ls",
+        ];
+        for (i, command) in commands.iter().enumerate() {
+            let entry = HistoryEntry::new(command.to_string(), Some(i as i64));
+            for _ in 0..=(i % 3) {
+                searcher.insert_or_update_command(&entry, "Bash").unwrap();
+            }
+        }
+        searcher.reload_from_db().unwrap();
+
+        for query in ["c", "cargo", "CARGO", "gt psh", "unicode", "cafe", "é", "zzz", "l"] {
+            // Reference: match everything, stable sort by score
+            let mut matcher = Matcher::new(Config::DEFAULT);
+            let needle = Utf32String::from(query.to_lowercase().as_str());
+            let mut expected: Vec<(u32, String)> = searcher
+                .commands()
+                .filter_map(|e| {
+                    let haystack = Utf32String::from(e.command.as_str());
+                    let score = matcher.fuzzy_match(haystack.slice(..), needle.slice(..))?;
+                    Some((score as u32 + e.frequency * 10, e.command.clone()))
+                })
+                .collect();
+            expected.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+
+            for limit in [1, 3, 100] {
+                let got: Vec<(u32, String)> = searcher
+                    .search(query, limit)
+                    .into_iter()
+                    .map(|r| (r.score, r.command))
+                    .collect();
+                let want: Vec<_> = expected.iter().take(limit).cloned().collect();
+                assert_eq!(got, want, "query {:?} limit {}", query, limit);
+            }
+        }
+    }
 }
@@ -0,0 +1,277 @@
+//! The session record behind `save-session <path>`: every command run since
+//! mux started, with its output, written out as markdown to share.
+//!
+//! Output is kept without ANSI codes and regardless of what the output pane
+//! still shows (`max_lines`, per-task caps, Ctrl+L). The whole record is
+//! held to `MAX_BYTES`: the oldest commands are dropped first, and a single
+//! command bigger than that keeps its first lines.
+//!
+//! Format: a `## ` heading per command (with its label for parallel tasks),
+//! a line with its start time, exit status and runtime, then its output in a
+//! fenced `text` block.
+
+use std::collections::VecDeque;
+use std::path::Path;
+
+use chrono::{DateTime, Local};
+
+use crate::runner::TaskId;
+
+/// Most text the record keeps, commands and output together
+pub const MAX_BYTES: usize = 8 * 1024 * 1024;
+
+/// One task of the session
+struct Entry {
+    id: TaskId,
+    command: String,
+    /// Parallel task label, e.g. "[n=3]"; empty for single commands
+    label: String,
+    started: DateTime<Local>,
+    lines: Vec<String>,
+    /// Lines left out because this command alone filled the record
+    omitted: usize,
+    /// Final status ("completed", "exited with code 1"), once finished
+    status: Option<String>,
+    runtime: Option<String>,
+}
+
+/// Commands of the session in the order they were run, with their output
+pub struct SessionLog {
+    entries: VecDeque<Entry>,
+    bytes: usize,
+    max_bytes: usize,
+    /// Commands dropped from the front to stay within `max_bytes`
+    dropped: usize,
+}
+
+impl Default for SessionLog {
+    fn default() -> Self {
+        Self::new(MAX_BYTES)
+    }
+}
+
+impl SessionLog {
+    pub fn new(max_bytes: usize) -> Self {
+        Self {
+            entries: VecDeque::new(),
+            bytes: 0,
+            max_bytes,
+            dropped: 0,
+        }
+    }
+
+    /// Commands recorded, finished or not
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Record that task `id` runs `command`
+    pub fn start(&mut self, id: TaskId, command: &str) {
+        let command = command.trim().to_string();
+        self.bytes += command.len();
+        self.entries.push_back(Entry {
+            id,
+            command,
+            label: String::new(),
+            started: Local::now(),
+            lines: Vec::new(),
+            omitted: 0,
+            status: None,
+            runtime: None,
+        });
+        self.make_room(0, None);
+    }
+
+    /// Add a line of task `id`'s output, without its ANSI codes
+    pub fn push_line(&mut self, id: TaskId, label: &str, content: &str) {
+        if self.position(id).is_none() {
+            return;
+        }
+        let line = crate::pager::plain_text(content);
+        let fits = self.make_room(line.len(), Some(id)).is_some();
+        let index = self.position(id).expect("kept by make_room");
+        let entry = &mut self.entries[index];
+        if entry.label.is_empty() {
+            entry.label = label.to_string();
+        }
+        if !fits {
+            entry.omitted += 1;
+            return;
+        }
+        self.bytes += line.len();
+        entry.lines.push(line);
+    }
+
+    /// Record how task `id` ended, and after how long if it ran at all
+    pub fn finish(&mut self, id: TaskId, label: &str, status: &str, runtime: Option<String>) {
+        if let Some(index) = self.position(id) {
+            let entry = &mut self.entries[index];
+            if entry.label.is_empty() {
+                entry.label = label.to_string();
+            }
+            entry.status = Some(status.to_string());
+            entry.runtime = runtime;
+        }
+    }
+
+    fn position(&self, id: TaskId) -> Option<usize> {
+        self.entries.iter().rposition(|entry| entry.id == id)
+    }
+
+    /// Drop the oldest commands until `bytes` more fit, keeping the newest
+    /// one and task `keep`'s. None if they still don't fit.
+    fn make_room(&mut self, bytes: usize, keep: Option<TaskId>) -> Option<()> {
+        while self.bytes + bytes > self.max_bytes {
+            let newest = self.entries.len().checked_sub(1)?;
+            let oldest = self.entries.iter().position(|entry| Some(entry.id) != keep)?;
+            if oldest == newest {
+                return None;
+            }
+            let entry = self.entries.remove(oldest)?;
+            self.bytes -= entry.command.len() + entry.lines.iter().map(String::len).sum::<usize>();
+            self.dropped += 1;
+        }
+        Some(())
+    }
+
+    /// The session as markdown, saved at `now`
+    pub fn to_markdown(&self, now: DateTime<Local>) -> String {
+        let mut out = String::from("# mux session\n\n");
+        let commands = match self.entries.len() {
+            1 => "1 command".to_string(),
+            n => format!("{} commands", n),
+        };
+        out.push_str(&format!("Saved {}, {}.\n", now.format("%Y-%m-%d %H:%M:%S"), commands));
+        if self.dropped > 0 {
+            out.push_str(&format!(
+                "\n> {} earlier commands were left out to keep this under {} MiB.\n",
+                self.dropped,
+                self.max_bytes / (1024 * 1024)
+            ));
+        }
+
+        for entry in &self.entries {
+            out.push_str("\n## ");
+            if !entry.label.is_empty() {
+                out.push_str(&entry.label);
+                out.push(' ');
+            }
+            // A multi-line command would end the heading at its first line
+            let command: Vec<&str> = entry.command.lines().map(str::trim).filter(|l| !l.is_empty()).collect();
+            out.push_str(&code_span(&command.join(" ")));
+            out.push_str("\n\n");
+
+            let mut details = vec![code_span(&entry.started.format("%H:%M:%S").to_string())];
+            details.push(entry.status.clone().unwrap_or_else(|| "still running".to_string()));
+            details.extend(entry.runtime.clone());
+            out.push_str(&details.join(" · "));
+            out.push('\n');
+
+            if !entry.lines.is_empty() {
+                let body = entry.lines.join("\n");
+                let fence = "`".repeat(longest_backtick_run(&body).max(2) + 1);
+                out.push_str(&format!("\n{}text\n{}\n{}\n", fence, body, fence));
+            }
+            if entry.omitted > 0 {
+                out.push_str(&format!("\n*… {} more lines left out*\n", entry.omitted));
+            }
+        }
+        out
+    }
+
+    /// Write the session to `path` as markdown, replacing the file
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        std::fs::write(path, self.to_markdown(Local::now()))
+    }
+}
+
+fn longest_backtick_run(text: &str) -> usize {
+    text.split(|c| c != '`').map(str::len).max().unwrap_or(0)
+}
+
+/// `text` as inline code, with enough backticks around it to hold its own
+fn code_span(text: &str) -> String {
+    let ticks = "`".repeat(longest_backtick_run(text) + 1);
+    if text.starts_with('`') || text.ends_with('`') {
+        format!("{} {} {}", ticks, text, ticks)
+    } else {
+        format!("{}{}{}", ticks, text, ticks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_markdown_format() {
+        let mut log = SessionLog::default();
+        log.start(1, "cargo build ");
+        log.push_line(1, "", "\x1b[32m   Compiling\x1b[0m mux");
+        log.push_line(1, "", "```nested```");
+        log.finish(1, "", "completed", Some("1.20s".to_string()));
+        log.start(2, "echo `date`");
+        log.finish(2, "[n=1]", "exited with code 1", Some("0.01s".to_string()));
+        log.start(3, "for i in 1 2; do\n  sleep 30\ndone");
+
+        let now = Local.with_ymd_and_hms(2026, 10, 16, 14, 3, 12).unwrap();
+        let started = |i: usize| log.entries[i].started.format("%H:%M:%S").to_string();
+        assert_eq!(
+            log.to_markdown(now),
+            format!(
+                "# mux session\n\n\
+                 Saved 2026-10-16 14:03:12, 3 commands.\n\
+                 \n## `cargo build`\n\n`{}` · completed · 1.20s\n\
+                 \n````text\n   Compiling mux\n```nested```\n````\n\
+                 \n## [n=1] `` echo `date` ``\n\n`{}` · exited with code 1 · 0.01s\n\
+                 \n## `for i in 1 2; do sleep 30 done`\n\n`{}` · still running\n",
+                started(0),
+                started(1),
+                started(2)
+            )
+        );
+    }
+
+    #[test]
+    fn test_size_cap_drops_oldest_commands() {
+        let mut log = SessionLog::new(40);
+        log.start(1, "first");
+        log.push_line(1, "", "0123456789");
+        log.start(2, "second");
+        log.push_line(2, "", "0123456789");
+        assert_eq!(log.len(), 2);
+
+        // The newest command stays; older ones make way
+        log.push_line(2, "", "0123456789");
+        assert_eq!(log.len(), 1);
+        assert_eq!(log.dropped, 1);
+        assert!(log.to_markdown(Local::now()).contains("> 1 earlier commands were left out"));
+
+        // Alone and full: its later lines are counted, not kept
+        log.push_line(2, "", "0123456789");
+        log.push_line(2, "", "0123456789");
+        assert_eq!(log.entries[0].lines.len(), 3);
+        assert_eq!(log.entries[0].omitted, 1);
+        assert!(log.to_markdown(Local::now()).contains("*… 1 more lines left out*"));
+        assert!(log.bytes <= 40);
+    }
+
+    #[test]
+    fn test_size_cap_keeps_the_command_written_to() {
+        let mut log = SessionLog::new(40);
+        log.start(1, "first");
+        log.start(2, "second");
+        log.start(3, "third");
+        log.push_line(1, "", "012345678901234");
+
+        // Room is made from the commands around it; once only it and the
+        // newest are left, its lines are counted instead
+        log.push_line(1, "", "012345678901234");
+        assert_eq!(log.entries.iter().map(|e| e.id).collect::<Vec<_>>(), [1, 3]);
+        log.push_line(1, "", "012345678901234");
+        assert_eq!(log.entries[0].lines.len(), 2);
+        assert_eq!(log.entries[0].omitted, 1);
+        assert!(log.bytes <= 40);
+    }
+}
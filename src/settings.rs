@@ -0,0 +1,159 @@
+//! Settings overlay for tuning display options while mux runs.
+//!
+//! Alt+, opens a list of live-adjustable options: j/k pick one, h/l (or
+//! ←/→, -/+) change it and the change applies immediately. `w` writes the
+//! current values to the global config file, keeping its comments and layout.
+
+use std::path::Path;
+
+/// An option that can be changed from the overlay
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Setting {
+    BoxPaddingHorizontal,
+    BoxPaddingVertical,
+    MaxLines,
+    LongLines,
+    ShowThroughput,
+}
+
+impl Setting {
+    pub const ALL: [Setting; 5] = [
+        Setting::BoxPaddingHorizontal,
+        Setting::BoxPaddingVertical,
+        Setting::MaxLines,
+        Setting::LongLines,
+        Setting::ShowThroughput,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Setting::BoxPaddingHorizontal => "Box padding, horizontal",
+            Setting::BoxPaddingVertical => "Box padding, vertical",
+            Setting::MaxLines => "Output lines kept",
+            Setting::LongLines => "Long lines",
+            Setting::ShowThroughput => "Show throughput",
+        }
+    }
+
+    /// (table, key) of the option in config.toml
+    pub fn config_key(self) -> (&'static str, &'static str) {
+        match self {
+            Setting::BoxPaddingHorizontal => ("output", "box_padding_horizontal"),
+            Setting::BoxPaddingVertical => ("output", "box_padding_vertical"),
+            Setting::MaxLines => ("output", "max_lines"),
+            Setting::LongLines => ("output", "long_lines"),
+            Setting::ShowThroughput => ("output", "show_throughput"),
+        }
+    }
+}
+
+/// Largest box padding the overlay offers; more only wastes the screen
+pub const MAX_BOX_PADDING: usize = 8;
+/// Step and lower bound for `MaxLines`
+pub const MAX_LINES_STEP: usize = 1000;
+
+/// State of the open overlay
+#[derive(Default)]
+pub struct SettingsView {
+    selected: usize,
+    /// Result of the last save, shown in the footer
+    message: Option<String>,
+}
+
+impl SettingsView {
+    pub fn selected(&self) -> Setting {
+        Setting::ALL[self.selected]
+    }
+
+    pub fn select_next(&mut self) {
+        self.selected = (self.selected + 1) % Setting::ALL.len();
+    }
+
+    pub fn select_prev(&mut self) {
+        self.selected = (self.selected + Setting::ALL.len() - 1) % Setting::ALL.len();
+    }
+
+    pub fn message(&self) -> Option<&str> {
+        self.message.as_deref()
+    }
+
+    pub fn set_message(&mut self, message: String) {
+        self.message = Some(message);
+    }
+}
+
+/// Set `[table] key = value` for each entry in the TOML file at `path`,
+/// creating the file if needed. Other settings, comments and formatting
+/// are kept.
+pub fn write_config_values(
+    path: &Path,
+    values: &[(&str, &str, toml_edit::Value)],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e.into()),
+    };
+    let mut doc: toml_edit::DocumentMut = content.parse()?;
+
+    for (table, key, value) in values {
+        let table = doc
+            .entry(table)
+            .or_insert_with(toml_edit::table)
+            .as_table_mut()
+            .ok_or_else(|| format!("`{}` in {} is not a table", table, path.display()))?;
+        table[*key] = toml_edit::value(value.clone());
+    }
+
+    std::fs::write(path, doc.to_string())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_selection_wraps() {
+        let mut view = SettingsView::default();
+        assert_eq!(view.selected(), Setting::BoxPaddingHorizontal);
+        view.select_prev();
+        assert_eq!(view.selected(), Setting::ShowThroughput);
+        view.select_next();
+        view.select_next();
+        assert_eq!(view.selected(), Setting::BoxPaddingVertical);
+    }
+
+    #[test]
+    fn test_write_config_values_keeps_the_rest() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(
+            &path,
+            "# my config\n[runner]\nmax_concurrent = 4\n\n[output]\nbox_padding_horizontal = 1 # wide\n",
+        )
+        .unwrap();
+
+        write_config_values(
+            &path,
+            &[
+                ("output", "box_padding_horizontal", 3.into()),
+                ("output", "long_lines", "wrap".into()),
+                ("theme", "background", "light".into()),
+            ],
+        )
+        .unwrap();
+
+        let written = std::fs::read_to_string(&path).unwrap();
+        assert!(written.starts_with("# my config\n[runner]\nmax_concurrent = 4\n"));
+        let config: crate::config::Config = toml::from_str(&written).unwrap();
+        assert_eq!(config.runner.max_concurrent, 4);
+        assert_eq!(config.output.box_padding_horizontal, 3);
+        assert_eq!(config.output.long_lines, crate::config::LongLineMode::Wrap);
+
+        // A missing file is created
+        let new_path = dir.path().join("new.toml");
+        write_config_values(&new_path, &[("output", "max_lines", 500.into())]).unwrap();
+        assert_eq!(std::fs::read_to_string(&new_path).unwrap(), "[output]\nmax_lines = 500\n");
+    }
+}
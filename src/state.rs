@@ -0,0 +1,68 @@
+//! UI choices remembered between sessions, stored as TOML in
+//! `$XDG_STATE_HOME/mux/state.toml`. Unlike the config, mux writes this file
+//! itself; settings in it win over the config defaults.
+
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::config::SuggestionDisplay;
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UiState {
+    /// Last suggestion display picked with Alt+V
+    pub suggestion_display: Option<SuggestionDisplay>,
+}
+
+impl UiState {
+    /// Read the saved state. A missing or unreadable file gives the default
+    /// state, so a bad file never keeps mux from starting.
+    pub fn load(path: &Path) -> Self {
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Self::default(),
+            Err(e) => {
+                log::warn!("Failed to read {}: {}", path.display(), e);
+                return Self::default();
+            }
+        };
+        toml::from_str(&content).unwrap_or_else(|e| {
+            log::warn!("Ignoring invalid state file {}: {}", path.display(), e);
+            Self::default()
+        })
+    }
+
+    /// Write the state, replacing the file atomically
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        let tmp = path.with_extension("toml.tmp");
+        std::fs::write(&tmp, toml::to_string(self)?)?;
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.toml");
+        assert_eq!(UiState::load(&path), UiState::default());
+
+        let state = UiState {
+            suggestion_display: Some(SuggestionDisplay::Ghost),
+        };
+        state.save(&path).unwrap();
+        assert_eq!(UiState::load(&path), state);
+    }
+
+    #[test]
+    fn test_invalid_file_is_ignored() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.toml");
+        std::fs::write(&path, "suggestion_display = \"sideways\"").unwrap();
+        assert_eq!(UiState::load(&path), UiState::default());
+    }
+}
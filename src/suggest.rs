@@ -1,8 +1,23 @@
 use log::{debug, info};
+use rayon::prelude::*;
+use std::borrow::Cow;
 use std::collections::{HashMap, HashSet};
 
+use crate::providers::{ProviderContext, SuggestionProvider};
 use crate::searcher::{HistorySearcher, IndexedCommand};
 
+/// An argument combination must be seen at least this often before it's suggested
+const MIN_COMBO_FREQUENCY: u32 = 2;
+
+/// Maximum number of argument combinations offered ahead of single arguments
+const MAX_COMBO_SUGGESTIONS: usize = 2;
+
+/// A `flag value` pair must be seen at least this often before it's suggested
+const MIN_PAIR_FREQUENCY: u32 = 2;
+
+/// Maximum number of `flag value` pairs offered ahead of single arguments
+const MAX_PAIR_SUGGESTIONS: usize = 2;
+
 // --- Argument parsing types ---
 
 /// A parsed command broken into its command prefix and structured arguments
@@ -12,6 +27,8 @@ struct ParsedCommand {
     prefixes: Vec<String>,
     /// Parsed argument entries
     args: Vec<ParsedArg>,
+    /// Everything after the prefix words, re-quoted: "-n prod -o wide"
+    arguments: String,
 }
 
 /// A single parsed argument (flag or key-value option)
@@ -47,6 +64,69 @@ struct InputContext {
     existing_args: HashSet<String>,
 }
 
+/// Split a command line on the shell operators `&&`, `||`, `;` and `|` (outside
+/// quotes) into its sub-commands. Empty segments are dropped.
+fn split_compound(command: &str) -> Vec<&str> {
+    compound_segments(command)
+        .into_iter()
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+/// The sub-command currently being typed: everything after the last operator
+fn current_segment(input: &str) -> &str {
+    compound_segments(input)
+        .pop()
+        .map_or(input, str::trim_start)
+}
+
+/// Raw (untrimmed) segments between shell operators
+fn compound_segments(command: &str) -> Vec<&str> {
+    let mut segments = Vec::new();
+    let mut start = 0;
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    let mut chars = command.char_indices().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match (quote, c) {
+            (Some('\''), '\'') => quote = None,
+            (Some('\''), _) => {}
+            (_, '\\') => escaped = true,
+            (Some('"'), '"') => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(c),
+            (None, ';' | '|' | '&') => {
+                let doubled = chars.peek().is_some_and(|&(_, next)| next == c);
+                // A lone '&' is backgrounding / redirection, not a separator
+                if c == '&' && !doubled {
+                    continue;
+                }
+                segments.push(&command[start..i]);
+                if doubled && c != ';' {
+                    chars.next();
+                    start = i + 2;
+                } else {
+                    start = i + 1;
+                }
+            }
+            _ => {}
+        }
+    }
+    segments.push(&command[start..]);
+    segments
+}
+
+/// Parse every sub-command of a (possibly compound) command line
+fn parse_commands(command: &str) -> Vec<ParsedCommand> {
+    split_compound(command).into_iter().map(parse_command).collect()
+}
+
 /// Parse a complete command string into structured parts (shell-aware tokenization)
 fn parse_command(command: &str) -> ParsedCommand {
     let tokens = match shell_words::split(command) {
@@ -57,102 +137,200 @@ fn parse_command(command: &str) -> ParsedCommand {
         return ParsedCommand {
             prefixes: Vec::new(),
             args: Vec::new(),
+            arguments: String::new(),
         };
     }
 
-    // Find where the command prefix ends (first token starting with '-')
-    let prefix_end = tokens
-        .iter()
-        .position(|t| t.starts_with('-'))
-        .unwrap_or(tokens.len());
+    // Tokens are moved into the result rather than copied: this runs for
+    // every command in history at startup
+    let mut tokens = tokens.into_iter().peekable();
 
-    // Build multi-level prefixes
-    let mut prefixes = Vec::new();
+    // Build multi-level prefixes, up to the first token starting with '-'
+    let mut prefixes: Vec<String> = Vec::new();
     let mut running = String::new();
-    for (i, tok) in tokens[..prefix_end].iter().enumerate() {
-        if i > 0 {
+    while let Some(tok) = tokens.next_if(|t| !t.starts_with('-')) {
+        if !prefixes.is_empty() {
             running.push(' ');
         }
-        running.push_str(tok);
+        running.push_str(&tok);
         prefixes.push(running.clone());
     }
 
     // Parse arguments from the remaining tokens
+    let rest: Vec<String> = tokens.collect();
+    let arguments = shell_words::join(&rest);
+    let mut tokens = rest.into_iter().peekable();
     let mut args = Vec::new();
-    let arg_tokens = &tokens[prefix_end..];
-    let mut i = 0;
-    while i < arg_tokens.len() {
-        let tok = &arg_tokens[i];
-
+    while let Some(mut tok) = tokens.next() {
         if tok == "--" {
             break;
         }
 
         if tok.starts_with('-') {
             if let Some(eq_pos) = tok.find('=') {
+                let value = tok.split_off(eq_pos + 1);
+                tok.truncate(eq_pos);
                 args.push(ParsedArg {
-                    name: tok[..eq_pos].to_string(),
-                    value: Some(tok[eq_pos + 1..].to_string()),
+                    name: tok,
+                    value: Some(value),
                 });
             } else {
-                let value = if i + 1 < arg_tokens.len() && !arg_tokens[i + 1].starts_with('-') {
-                    i += 1;
-                    Some(arg_tokens[i].clone())
-                } else {
-                    None
-                };
-                args.push(ParsedArg {
-                    name: tok.clone(),
-                    value,
-                });
+                let value = tokens.next_if(|t| !t.starts_with('-'));
+                args.push(ParsedArg { name: tok, value });
             }
         }
-        i += 1;
     }
 
-    ParsedCommand { prefixes, args }
+    ParsedCommand {
+        prefixes,
+        args,
+        arguments,
+    }
+}
+
+/// The set of arguments a command was run with, as a sorted list of rendered
+/// args ("--release", "--target x86_64"). None if there are fewer than two.
+fn arg_combo(parsed: &ParsedCommand) -> Option<Vec<String>> {
+    let mut combo: Vec<String> = parsed
+        .args
+        .iter()
+        .map(|arg| match &arg.value {
+            Some(value) => format!("{} {}", arg.name, shell_words::quote(value)),
+            None => arg.name.clone(),
+        })
+        .collect();
+    combo.sort_unstable();
+    combo.dedup();
+    (combo.len() >= 2).then_some(combo)
+}
+
+/// The flag name of a rendered combo arg: "--target x86_64" → "--target"
+fn combo_arg_name(rendered: &str) -> &str {
+    rendered.split_once(' ').map_or(rendered, |(name, _)| name)
+}
+
+/// Whether the partial being typed fuzzily matches a rendered `flag value`
+/// pair: the flag must start with the partial's dashes and first letter, and
+/// the remaining characters must appear in order, so `--tx86` matches
+/// `--target x86_64`.
+fn pair_matches(partial: &str, pair: &str) -> bool {
+    let rest = partial.trim_start_matches('-');
+    let lead_len = partial.len() - rest.len() + rest.chars().next().map_or(0, char::len_utf8);
+    if !pair.starts_with(&partial[..lead_len]) {
+        return false;
+    }
+    let mut remaining = pair[lead_len..].chars();
+    partial[lead_len..].chars().all(|c| remaining.any(|p| p == c))
+}
+
+/// Case-insensitive prefix test, matching how full-command search ignores
+/// case: `--Rel` completes to `--release`, `X86` to `x86_64`
+fn starts_with_ignore_case(text: &str, prefix: &str) -> bool {
+    let mut text = text.chars().flat_map(char::to_lowercase);
+    prefix
+        .chars()
+        .flat_map(char::to_lowercase)
+        .all(|c| text.next() == Some(c))
+}
+
+/// Whether each word of `query` starts the word of `command` at the same
+/// position, ignoring case: `c b --re` abbreviates `cargo build --release`
+fn abbreviates(query: &[&str], command: &str) -> bool {
+    let mut words = command.split_whitespace();
+    query
+        .iter()
+        .all(|prefix| words.next().is_some_and(|word| starts_with_ignore_case(word, prefix)))
 }
 
 /// Split input into completed tokens and partial (the token being typed).
-/// If input has a trailing space, partial is empty (user finished the last token).
-/// Uses shell-aware tokenization for completed tokens, but keeps the raw last
-/// token as partial since the user may be mid-quote.
+/// If input ends with unquoted whitespace, partial is empty (user finished the last token).
+/// Whitespace inside quotes doesn't end a token, so in `--message "fix b` the
+/// partial is `fix b`: an opening quote is dropped so it can be matched
+/// against the (unquoted) values seen in history.
 fn split_input(input: &str) -> (Vec<String>, String) {
-    let raw_tokens: Vec<&str> = input.split_whitespace().collect();
-    if raw_tokens.is_empty() {
-        return (Vec::new(), String::new());
-    }
-    if input.ends_with(' ') {
-        // All tokens are complete; use shell-aware parsing
-        let tokens = match shell_words::split(input) {
-            Ok(t) => t,
-            Err(_) => raw_tokens.iter().map(|s| s.to_string()).collect(),
-        };
-        (tokens, String::new())
-    } else {
-        // The last raw token is the partial being typed (may be mid-quote)
-        let Some(last_token) = raw_tokens.last() else {
-            return (Vec::new(), String::new());
-        };
-        let partial = last_token.to_string();
-        // Parse everything before the last whitespace-delimited token
-        let prefix = input.trim_end().rsplit_once(char::is_whitespace)
-            .map(|(before, _)| before)
-            .unwrap_or("");
-        let completed = if prefix.is_empty() {
-            Vec::new()
-        } else {
-            match shell_words::split(prefix) {
-                Ok(t) => t,
-                Err(_) => prefix.split_whitespace().map(String::from).collect(),
+    let start = partial_token_start(input);
+    let prefix = &input[..start];
+    let completed = match shell_words::split(prefix) {
+        Ok(t) => t,
+        Err(_) => prefix.split_whitespace().map(String::from).collect(),
+    };
+    (completed, unquote_partial(&input[start..]))
+}
+
+/// Byte offset where the token being typed starts, or `input.len()` after
+/// unquoted trailing whitespace
+pub fn partial_token_start(input: &str) -> usize {
+    let mut start = 0;
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+
+    for (i, c) in input.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match (quote, c) {
+            (Some('\''), '\'') => quote = None,
+            (Some('\''), _) => {}
+            (_, '\\') => escaped = true,
+            (Some('"'), '"') => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(c),
+            (None, c) if c.is_whitespace() => start = i + c.len_utf8(),
+            _ => {}
+        }
+    }
+    start
+}
+
+/// The text of a partial token without its quotes. Unquoted tokens are kept
+/// raw; a quoted one is closed first if the user hasn't done so yet.
+fn unquote_partial(raw: &str) -> String {
+    let Some(quote) = raw.chars().next().filter(|c| matches!(c, '\'' | '"')) else {
+        return raw.to_string();
+    };
+    let closed = format!("{}{}", raw, quote);
+    for candidate in [raw, closed.as_str()] {
+        if let Ok(mut tokens) = shell_words::split(candidate)
+            && tokens.len() == 1
+        {
+            return tokens.remove(0);
+        }
+    }
+    raw[quote.len_utf8()..].to_string()
+}
+
+/// Render a value for insertion in place of the partial token `partial`.
+/// A value completing a quoted partial keeps the user's quote style;
+/// otherwise it's quoted only if the shell needs it.
+pub fn quote_value(value: &str, partial: &str) -> String {
+    match partial.chars().next() {
+        Some('"') => {
+            let mut quoted = String::from('"');
+            for c in value.chars() {
+                if matches!(c, '"' | '\\' | '$' | '`') {
+                    quoted.push('\\');
+                }
+                quoted.push(c);
             }
-        };
-        (completed, partial)
+            quoted.push('"');
+            quoted
+        }
+        Some('\'') if !value.contains('\'') => format!("'{}'", value),
+        _ => shell_words::quote(value).into_owned(),
     }
 }
 
-/// Argument-aware suggestion engine for command input
-pub struct SuggestionEngine {
+/// Below this many commands the indexes are built on one thread; spreading
+/// a small history across threads costs more than it saves
+const PARALLEL_BUILD_MIN: usize = 4096;
+
+/// Commands each thread indexes before its maps are merged
+const BUILD_CHUNK: usize = 2048;
+
+/// Argument usage learned from history, keyed by command prefix
+#[derive(Debug, Default, PartialEq)]
+struct ArgIndexes {
     /// command_prefix -> { arg_name -> frequency }
     /// e.g., "cargo build" -> {"--release": 15, "--target": 5}
     arg_index: HashMap<String, HashMap<String, u32>>,
@@ -167,6 +345,212 @@ pub struct SuggestionEngine {
 
     /// Pre-computed set of args that have been seen with values (O(1) lookup)
     value_taking_args: HashSet<String>,
+
+    /// command_prefix -> { sorted arg set -> frequency }
+    /// e.g., "cargo build" -> {["--locked", "--release"]: 6}
+    arg_combo_index: HashMap<String, HashMap<Vec<String>, u32>>,
+
+    /// command_prefix -> { the rest of the command, as run -> frequency }
+    /// e.g., "kubectl" -> {"get pods -n prod -o wide": 4}
+    arg_set_index: HashMap<String, HashMap<String, u32>>,
+}
+
+impl ArgIndexes {
+    /// Index `commands`, in parallel chunks for a large history on a machine
+    /// with more than one core. Gives the same maps as adding each command
+    /// in turn.
+    fn build(commands: &[&IndexedCommand]) -> Self {
+        if commands.len() < PARALLEL_BUILD_MIN || rayon::current_num_threads() < 2 {
+            Self::build_chunk(commands)
+        } else {
+            Self::build_parallel(commands)
+        }
+    }
+
+    /// Index chunks of `commands` on rayon's threads, then merge the maps
+    fn build_parallel(commands: &[&IndexedCommand]) -> Self {
+        commands
+            .par_chunks(BUILD_CHUNK)
+            .map(Self::build_chunk)
+            .reduce(Self::default, Self::merge)
+    }
+
+    fn build_chunk(commands: &[&IndexedCommand]) -> Self {
+        // Only commands with flags get a prefix entry, and many share one; a
+        // quarter of the chunk skips the early rehashes of a large history
+        let prefixes = commands.len() / 4;
+        let mut indexes = Self {
+            arg_index: HashMap::with_capacity(prefixes),
+            arg_value_index: HashMap::with_capacity(prefixes),
+            arg_combo_index: HashMap::with_capacity(prefixes),
+            arg_set_index: HashMap::with_capacity(prefixes),
+            ..Self::default()
+        };
+        for cmd in commands {
+            indexes.add(&cmd.command, cmd.frequency.max(1));
+        }
+        indexes
+    }
+
+    /// Add `weight` uses of `command`. Keys are only copied the first time
+    /// they're seen.
+    fn add(&mut self, command: &str, weight: u32) {
+        for parsed in parse_commands(command) {
+            let combo = arg_combo(&parsed);
+            let deepest = parsed.prefixes.last().map_or("", String::as_str);
+            for prefix in &parsed.prefixes {
+                if let Some(combo) = &combo {
+                    *slot(slot(&mut self.arg_combo_index, prefix.as_str()), combo.as_slice()) += weight;
+                }
+                // Everything after this level, flags or not: for `kubectl`,
+                // `get nodes` as well as `get pods -n prod`
+                let set = match (deepest[prefix.len()..].trim_start(), parsed.arguments.as_str()) {
+                    (words, "") => Cow::Borrowed(words),
+                    ("", arguments) => Cow::Borrowed(arguments),
+                    (words, arguments) => Cow::Owned(format!("{} {}", words, arguments)),
+                };
+                if !set.is_empty() {
+                    *slot(slot(&mut self.arg_set_index, prefix.as_str()), set.as_ref()) += weight;
+                }
+                if parsed.args.is_empty() {
+                    continue;
+                }
+                let args = slot(&mut self.arg_index, prefix.as_str());
+                for arg in &parsed.args {
+                    *slot(args, arg.name.as_str()) += weight;
+                }
+                for arg in &parsed.args {
+                    let Some(value) = &arg.value else {
+                        continue;
+                    };
+                    let values = slot(slot(&mut self.arg_value_index, prefix.as_str()), arg.name.as_str());
+                    *slot(values, value.as_str()) += weight;
+                }
+            }
+            for arg in &parsed.args {
+                let Some(value) = &arg.value else {
+                    continue;
+                };
+                // Counted once per prefix level, like the per-prefix maps
+                let levels = parsed.prefixes.len() as u32;
+                if levels > 0 {
+                    *slot(slot(&mut self.global_arg_values, arg.name.as_str()), value.as_str()) += weight * levels;
+                    if !self.value_taking_args.contains(&arg.name) {
+                        self.value_taking_args.insert(arg.name.clone());
+                    }
+                }
+            }
+        }
+    }
+
+    /// Fold `other`'s counts into these, moving its keys rather than
+    /// copying them
+    fn merge(mut self, mut other: Self) -> Self {
+        if self.arg_index.len() < other.arg_index.len() {
+            std::mem::swap(&mut self, &mut other);
+        }
+        merge_counts(&mut self.arg_index, other.arg_index, |mine, theirs| {
+            merge_counts(mine, theirs, |a, b| *a += b)
+        });
+        merge_counts(&mut self.arg_value_index, other.arg_value_index, |mine, theirs| {
+            merge_counts(mine, theirs, |mine, theirs| merge_counts(mine, theirs, |a, b| *a += b))
+        });
+        merge_counts(&mut self.global_arg_values, other.global_arg_values, |mine, theirs| {
+            merge_counts(mine, theirs, |a, b| *a += b)
+        });
+        self.value_taking_args.extend(other.value_taking_args);
+        merge_counts(&mut self.arg_combo_index, other.arg_combo_index, |mine, theirs| {
+            merge_counts(mine, theirs, |a, b| *a += b)
+        });
+        merge_counts(&mut self.arg_set_index, other.arg_set_index, |mine, theirs| {
+            merge_counts(mine, theirs, |a, b| *a += b)
+        });
+        self
+    }
+}
+
+/// The value for `key` in `map`, inserting a default first; unlike `entry`,
+/// the key is only copied when it's new
+fn slot<'m, K, Q, V>(map: &'m mut HashMap<K, V>, key: &Q) -> &'m mut V
+where
+    K: std::borrow::Borrow<Q> + Eq + std::hash::Hash,
+    Q: ToOwned<Owned = K> + Eq + std::hash::Hash + ?Sized,
+    V: Default,
+{
+    if !map.contains_key(key) {
+        map.insert(key.to_owned(), V::default());
+    }
+    map.get_mut(key).expect("inserted above")
+}
+
+/// Move every entry of `from` into `into`, combining values of keys both
+/// have with `combine`
+fn merge_counts<K, V>(into: &mut HashMap<K, V>, from: HashMap<K, V>, combine: impl Fn(&mut V, V))
+where
+    K: Eq + std::hash::Hash,
+{
+    for (key, value) in from {
+        match into.get_mut(&key) {
+            Some(mine) => combine(mine, value),
+            None => {
+                into.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Argument-aware suggestion engine for command input
+pub struct SuggestionEngine {
+    indexes: ArgIndexes,
+
+    /// Extra suggestion sources, merged with the built-in results by score
+    providers: Vec<Box<dyn SuggestionProvider>>,
+
+    /// Offer frequent `flag value` pairs as single suggestions
+    argument_pairs: bool,
+
+    /// Most suggestions of each kind in one list
+    type_limits: TypeLimits,
+
+    /// Commands used fewer times aren't suggested
+    min_frequency: u32,
+
+    /// Only suggest full commands starting with the query's first word
+    anchor_start: bool,
+
+    /// The query's first word must be the command's own, not fuzzy matched
+    exact_first_token: bool,
+}
+
+/// Caps on how many suggestions of each kind one list holds; 0 leaves a
+/// kind uncapped. Capping full commands also lists argument and value
+/// suggestions after them, where otherwise matching commands replace them.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TypeLimits {
+    /// Full commands from history
+    pub commands: usize,
+    /// Arguments, argument combinations and `flag value` pairs
+    pub args: usize,
+    /// Values for the argument being completed
+    pub values: usize,
+}
+
+impl TypeLimits {
+    /// Drop suggestions past their kind's cap, keeping the order
+    fn apply(&self, suggestions: &mut Vec<Suggestion>) {
+        let mut counts = [0; 3];
+        suggestions.retain(|s| {
+            let (kind, cap) = match s.suggestion_type {
+                SuggestionType::FullCommand => (0, self.commands),
+                SuggestionType::Argument | SuggestionType::ArgumentCombo | SuggestionType::ArgumentPair => {
+                    (1, self.args)
+                }
+                SuggestionType::ArgumentValue => (2, self.values),
+            };
+            counts[kind] += 1;
+            cap == 0 || counts[kind] <= cap
+        });
+    }
 }
 
 /// A suggestion result
@@ -185,105 +569,154 @@ pub enum SuggestionType {
     Argument,
     /// A value for the current argument (e.g., x86_64 for --target)
     ArgumentValue,
+    /// A set of arguments commonly used together (e.g., --locked --release)
+    ArgumentCombo,
+    /// A flag with a value it's often used with (e.g., --target x86_64)
+    ArgumentPair,
+}
+
+/// The whole argument lists a command was run with, for picking one past
+/// invocation instead of building it up flag by flag
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArgumentSets {
+    /// Byte offset in the input where the command starts, after any `&&` or `|`
+    pub start: usize,
+    /// The command the sets were run with, as typed: "kubectl get"
+    pub prefix: String,
+    /// Each rest of the command with how often it was run, most used first
+    pub sets: Vec<(String, u32)>,
 }
 
 impl SuggestionEngine {
     /// Create a new suggestion engine from indexed commands
-    pub fn new(commands: &[IndexedCommand]) -> Self {
+    pub fn new<'a, I>(commands: I) -> Self
+    where
+        I: IntoIterator<Item = &'a IndexedCommand>,
+        I::IntoIter: ExactSizeIterator,
+    {
+        let commands: Vec<&IndexedCommand> = commands.into_iter().collect();
         debug!("Building suggestion engine from {} commands", commands.len());
 
-        let mut arg_index: HashMap<String, HashMap<String, u32>> = HashMap::new();
-        let mut arg_value_index: HashMap<String, HashMap<String, HashMap<String, u32>>> =
-            HashMap::new();
-        let mut global_arg_values: HashMap<String, HashMap<String, u32>> = HashMap::new();
+        let engine = Self {
+            indexes: ArgIndexes::build(&commands),
+            providers: Vec::new(),
+            argument_pairs: false,
+            type_limits: TypeLimits::default(),
+            min_frequency: 1,
+            anchor_start: false,
+            exact_first_token: false,
+        };
 
-        for cmd in commands {
-            let freq_weight = cmd.frequency.max(1);
-            let parsed = parse_command(&cmd.command);
+        info!(
+            "Suggestion engine built: {} command prefixes indexed",
+            engine.indexes.arg_index.len()
+        );
+        engine
+    }
 
-            for prefix in &parsed.prefixes {
-                for arg in &parsed.args {
-                    *arg_index
-                        .entry(prefix.clone())
-                        .or_default()
-                        .entry(arg.name.clone())
-                        .or_insert(0) += freq_weight;
-
-                    if let Some(ref value) = arg.value {
-                        *arg_value_index
-                            .entry(prefix.clone())
-                            .or_default()
-                            .entry(arg.name.clone())
-                            .or_default()
-                            .entry(value.clone())
-                            .or_insert(0) += freq_weight;
+    /// Rebuild the argument indexes from scratch, keeping registered providers
+    pub fn rebuild<'a, I>(&mut self, commands: I)
+    where
+        I: IntoIterator<Item = &'a IndexedCommand>,
+        I::IntoIter: ExactSizeIterator,
+    {
+        let providers = std::mem::take(&mut self.providers);
+        let (argument_pairs, type_limits, min_frequency, anchor_start, exact_first_token) = (
+            self.argument_pairs,
+            self.type_limits,
+            self.min_frequency,
+            self.anchor_start,
+            self.exact_first_token,
+        );
+        *self = Self::new(commands);
+        self.providers = providers;
+        self.argument_pairs = argument_pairs;
+        self.type_limits = type_limits;
+        self.min_frequency = min_frequency;
+        self.anchor_start = anchor_start;
+        self.exact_first_token = exact_first_token;
+    }
 
-                        *global_arg_values
-                            .entry(arg.name.clone())
-                            .or_default()
-                            .entry(value.clone())
-                            .or_insert(0) += freq_weight;
-                    }
-                }
-            }
-        }
+    /// Offer `flag value` pairs seen together in history, such as
+    /// `--target x86_64-unknown-linux-gnu`, alongside single arguments
+    pub fn set_argument_pairs(&mut self, enabled: bool) {
+        self.argument_pairs = enabled;
+    }
 
-        // Pre-compute which args take values for O(1) lookups
-        let value_taking_args: HashSet<String> = global_arg_values.keys().cloned().collect();
+    /// Cap how many suggestions of each kind `suggest` returns
+    pub fn set_type_limits(&mut self, limits: TypeLimits) {
+        self.type_limits = limits;
+    }
 
-        info!(
-            "Suggestion engine built: {} command prefixes indexed",
-            arg_index.len()
-        );
+    /// Only suggest full commands used at least `min_frequency` times; the
+    /// others stay in history and searchable. 1 suggests every command.
+    pub fn set_min_frequency(&mut self, min_frequency: u32) {
+        self.min_frequency = min_frequency;
+    }
 
-        Self {
-            arg_index,
-            arg_value_index,
-            global_arg_values,
-            value_taking_args,
-        }
+    /// Restrict full-command suggestions to commands starting with the
+    /// query's first word (`git` no longer finds `gulp install --retry`),
+    /// still ranked by fuzzy match and usage among those
+    pub fn set_anchor_start(&mut self, anchor_start: bool) {
+        self.anchor_start = anchor_start;
+    }
+
+    /// Lock full-command suggestions to the query's first word: once it's
+    /// followed by a space a command's first word must equal it (`kubectl`
+    /// no longer finds `kubectx`), before that it must start with it. The
+    /// rest of the query is fuzzy matched against the rest of the command.
+    pub fn set_exact_first_token(&mut self, exact_first_token: bool) {
+        self.exact_first_token = exact_first_token;
+    }
+
+    /// Add a suggestion provider. Its results are merged into every `suggest` call.
+    pub fn register_provider(&mut self, provider: Box<dyn SuggestionProvider>) {
+        debug!("Registered suggestion provider: {}", provider.name());
+        self.providers.push(provider);
     }
 
     /// Incrementally index a single command (called when a new command is submitted)
     pub fn index_command(&mut self, command: &str) {
-        let parsed = parse_command(command);
+        self.indexes.add(command, 1);
+    }
 
-        for prefix in &parsed.prefixes {
-            for arg in &parsed.args {
-                *self
-                    .arg_index
-                    .entry(prefix.clone())
-                    .or_default()
-                    .entry(arg.name.clone())
-                    .or_insert(0) += 1;
-
-                if let Some(ref value) = arg.value {
-                    *self
-                        .arg_value_index
-                        .entry(prefix.clone())
-                        .or_default()
-                        .entry(arg.name.clone())
-                        .or_default()
-                        .entry(value.clone())
-                        .or_insert(0) += 1;
-
-                    *self
-                        .global_arg_values
-                        .entry(arg.name.clone())
-                        .or_default()
-                        .entry(value.clone())
-                        .or_insert(0) += 1;
-
-                    // Update value_taking_args set
-                    self.value_taking_args.insert(arg.name.clone());
-                }
-            }
+    /// Incrementally index a batch of commands, each counted `frequency`
+    /// times, e.g. those a sync found while mux is running. Cheaper than a
+    /// `rebuild` when only a few commands are new.
+    pub fn index_commands(&mut self, commands: &[IndexedCommand]) {
+        for cmd in commands {
+            self.indexes.add(&cmd.command, cmd.frequency.max(1));
         }
     }
 
+    /// The argument lists seen after the command being typed, keyed by its
+    /// longest leading words that have any: for `kubectl get` those of
+    /// `kubectl get` if it was run with arguments, else those of `kubectl`.
+    /// None if no prefix of the input was.
+    pub fn argument_sets(&self, input: &str) -> Option<ArgumentSets> {
+        let segment = current_segment(input);
+        let start = input.len() - segment.len();
+        let words = match shell_words::split(segment) {
+            Ok(words) => words,
+            Err(_) => segment.split_whitespace().map(String::from).collect(),
+        };
+        let words: Vec<String> = words.into_iter().take_while(|w| !w.starts_with('-')).collect();
+
+        (1..=words.len()).rev().find_map(|n| {
+            let sets = self.indexes.arg_set_index.get(&words[..n].join(" "))?;
+            let mut sets: Vec<(String, u32)> = sets.iter().map(|(set, &count)| (set.clone(), count)).collect();
+            sets.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+            Some(ArgumentSets {
+                start,
+                prefix: shell_words::join(&words[..n]),
+                sets,
+            })
+        })
+    }
+
     /// Check if an argument has ever been seen with a value in the index (O(1))
     fn arg_takes_value(&self, arg_name: &str) -> bool {
-        self.value_taking_args.contains(arg_name)
+        self.indexes.value_taking_args.contains(arg_name)
     }
 
     /// Analyze completed tokens to determine context and what to suggest next
@@ -342,7 +775,7 @@ impl SuggestionEngine {
                 } else {
                     existing_args.insert(tok.to_string());
                     // If this arg takes values and the next token is a non-dash value, consume it
-                    if self.arg_takes_value(&tok)
+                    if self.arg_takes_value(tok)
                         && i + 1 < completed.len()
                         && !completed[i + 1].starts_with('-')
                     {
@@ -380,52 +813,219 @@ impl SuggestionEngine {
         }
     }
 
-    /// Get suggestions for the current input
+    /// Get suggestions for the current input: built-in results plus those of
+    /// any registered providers, ordered by score
     pub fn suggest(&self, input: &str, searcher: &mut HistorySearcher, limit: usize) -> Vec<Suggestion> {
+        let mut suggestions = self.suggest_builtin(input, searcher, limit);
+
+        let segment = current_segment(input.trim_start());
+        let (completed, partial) = split_input(segment);
+        let ctx = ProviderContext {
+            completed: &completed,
+            partial: &partial,
+        };
+        let extra: Vec<Suggestion> = self
+            .providers
+            .iter()
+            .flat_map(|p| p.suggest(&ctx, limit))
+            .collect();
+        if !extra.is_empty() {
+            for suggestion in extra {
+                if !suggestions.iter().any(|s| s.text == suggestion.text) {
+                    suggestions.push(suggestion);
+                }
+            }
+            suggestions.sort_by(|a, b| b.score.total_cmp(&a.score));
+        }
+        self.type_limits.apply(&mut suggestions);
+        suggestions.truncate(limit);
+        suggestions
+    }
+
+    /// Suggestions from history and the argument indexes
+    fn suggest_builtin(&self, input: &str, searcher: &mut HistorySearcher, limit: usize) -> Vec<Suggestion> {
         let trimmed = input.trim_start();
 
         if trimmed.is_empty() {
-            return Self::commands_from_searcher(searcher, "", limit);
+            return self.commands_from_searcher(searcher, "", limit);
         }
 
-        let (completed, partial) = split_input(trimmed);
+        // Only the sub-command being typed matters for argument context
+        let segment = current_segment(trimmed);
+        let (completed, partial) = split_input(segment);
         let ctx = self.analyze_completed(&completed);
 
         match ctx.next_expected {
+            NextExpected::Command if segment.len() < trimmed.len() => {
+                // First word after an operator: keep the earlier sub-commands
+                self.commands_from_searcher(searcher, trimmed, limit)
+            }
             NextExpected::Command => {
-                Self::commands_from_searcher(searcher, &partial, limit)
+                self.commands_from_searcher(searcher, &partial, limit)
             }
             NextExpected::Subcommand => {
-                let cmd_results = Self::commands_from_searcher(searcher, trimmed, limit);
-                if !cmd_results.is_empty() {
-                    return cmd_results;
+                let mut results = self.commands_from_searcher(searcher, trimmed, limit);
+                if !results.is_empty() && !self.mixes_types() {
+                    return results;
                 }
                 if partial.starts_with('-') {
-                    self.suggest_args(&ctx.prefixes, &partial, &ctx.existing_args, limit)
-                } else {
-                    Vec::new()
+                    results.extend(self.suggest_args_with_combos(&ctx.prefixes, &partial, &ctx.existing_args, limit));
                 }
+                results
             }
             NextExpected::Argument => {
-                let cmd_results = Self::commands_from_searcher(searcher, trimmed, limit);
-                if !cmd_results.is_empty() {
-                    return cmd_results;
+                let mut results = self.commands_from_searcher(searcher, trimmed, limit);
+                if !results.is_empty() && !self.mixes_types() {
+                    return results;
                 }
-                self.suggest_args(&ctx.prefixes, &partial, &ctx.existing_args, limit)
+                results.extend(self.suggest_args_with_combos(&ctx.prefixes, &partial, &ctx.existing_args, limit));
+                results
             }
             NextExpected::Value(ref arg_name) => {
-                let cmd_results = Self::commands_from_searcher(searcher, trimmed, limit);
-                if !cmd_results.is_empty() {
-                    return cmd_results;
+                let mut results = self.commands_from_searcher(searcher, trimmed, limit);
+                if !results.is_empty() && !self.mixes_types() {
+                    return results;
                 }
                 let val_results =
                     self.suggest_arg_values(&ctx.prefixes, arg_name, &partial, limit);
-                if !val_results.is_empty() {
-                    return val_results;
+                if val_results.is_empty() {
+                    results.extend(self.suggest_args(&ctx.prefixes, &partial, &ctx.existing_args, limit));
+                } else {
+                    results.extend(val_results);
+                }
+                results
+            }
+        }
+    }
+
+    /// With full commands capped, argument and value suggestions follow the
+    /// matching commands instead of only showing when none match
+    fn mixes_types(&self) -> bool {
+        self.type_limits.commands > 0
+    }
+
+    /// Suggest frequent argument combinations first, then `flag value` pairs
+    /// (if enabled), then single arguments
+    fn suggest_args_with_combos(
+        &self,
+        prefixes: &[String],
+        partial: &str,
+        exclude: &HashSet<String>,
+        limit: usize,
+    ) -> Vec<Suggestion> {
+        let mut suggestions =
+            self.suggest_arg_combos(prefixes, partial, exclude, MAX_COMBO_SUGGESTIONS.min(limit));
+        if self.argument_pairs {
+            let remaining = MAX_PAIR_SUGGESTIONS.min(limit - suggestions.len());
+            suggestions.extend(self.suggest_arg_pairs(prefixes, partial, exclude, remaining));
+        }
+        let remaining = limit - suggestions.len();
+        suggestions.extend(self.suggest_args(prefixes, partial, exclude, remaining));
+        suggestions
+    }
+
+    /// Suggest argument combinations for the current command prefix.
+    /// Args already typed are dropped from each combo; a combo must still have
+    /// at least two args left, one of which matches the partial being typed.
+    fn suggest_arg_combos(
+        &self,
+        prefixes: &[String],
+        partial: &str,
+        exclude: &HashSet<String>,
+        limit: usize,
+    ) -> Vec<Suggestion> {
+        let mut scored: HashMap<String, f32> = HashMap::new();
+
+        for (i, prefix) in prefixes.iter().enumerate() {
+            let boost = if i == prefixes.len() - 1 { 2.0 } else { 1.0 };
+            let Some(combos) = self.indexes.arg_combo_index.get(prefix) else {
+                continue;
+            };
+            for (combo, freq) in combos {
+                if *freq < MIN_COMBO_FREQUENCY {
+                    continue;
+                }
+                let mut remaining: Vec<&str> = combo
+                    .iter()
+                    .map(String::as_str)
+                    .filter(|arg| !exclude.contains(combo_arg_name(arg)))
+                    .collect();
+                if remaining.len() < 2 {
+                    continue;
+                }
+                // The arg being typed goes first so the combo completes it
+                let Some(lead) = remaining.iter().position(|arg| arg.starts_with(partial)) else {
+                    continue;
+                };
+                remaining[..=lead].rotate_right(1);
+
+                let score = *freq as f32 * boost;
+                let entry = scored.entry(remaining.join(" ")).or_insert(0.0);
+                *entry = entry.max(score);
+            }
+        }
+
+        let mut suggestions: Vec<_> = scored
+            .into_iter()
+            .map(|(text, score)| Suggestion {
+                text,
+                score,
+                suggestion_type: SuggestionType::ArgumentCombo,
+            })
+            .collect();
+
+        suggestions.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| a.text.cmp(&b.text)));
+        suggestions.truncate(limit);
+        suggestions
+    }
+
+    /// Suggest `flag value` pairs for the current command prefix, matched
+    /// fuzzily as a whole. Flags already typed are skipped.
+    fn suggest_arg_pairs(
+        &self,
+        prefixes: &[String],
+        partial: &str,
+        exclude: &HashSet<String>,
+        limit: usize,
+    ) -> Vec<Suggestion> {
+        let mut scored: HashMap<String, f32> = HashMap::new();
+
+        for (i, prefix) in prefixes.iter().enumerate() {
+            let boost = if i == prefixes.len() - 1 { 2.0 } else { 1.0 };
+            let Some(arg_map) = self.indexes.arg_value_index.get(prefix) else {
+                continue;
+            };
+            for (arg_name, values) in arg_map {
+                if exclude.contains(arg_name) {
+                    continue;
+                }
+                for (value, freq) in values {
+                    if *freq < MIN_PAIR_FREQUENCY {
+                        continue;
+                    }
+                    let pair = format!("{} {}", arg_name, shell_words::quote(value));
+                    if !pair_matches(partial, &pair) {
+                        continue;
+                    }
+                    let score = *freq as f32 * boost;
+                    let entry = scored.entry(pair).or_insert(0.0);
+                    *entry = entry.max(score);
                 }
-                self.suggest_args(&ctx.prefixes, &partial, &ctx.existing_args, limit)
             }
         }
+
+        let mut suggestions: Vec<_> = scored
+            .into_iter()
+            .map(|(text, score)| Suggestion {
+                text,
+                score,
+                suggestion_type: SuggestionType::ArgumentPair,
+            })
+            .collect();
+
+        suggestions.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| a.text.cmp(&b.text)));
+        suggestions.truncate(limit);
+        suggestions
     }
 
     /// Suggest arguments for the current command prefix
@@ -440,9 +1040,9 @@ impl SuggestionEngine {
 
         for (i, prefix) in prefixes.iter().enumerate() {
             let boost = if i == prefixes.len() - 1 { 2.0 } else { 1.0 };
-            if let Some(args) = self.arg_index.get(prefix) {
+            if let Some(args) = self.indexes.arg_index.get(prefix) {
                 for (arg_name, freq) in args {
-                    if arg_name.starts_with(partial) && !exclude.contains(arg_name) {
+                    if starts_with_ignore_case(arg_name, partial) && !exclude.contains(arg_name) {
                         let score = *freq as f32 * boost;
                         let entry = scored.entry(arg_name.clone()).or_insert(0.0);
                         *entry = entry.max(score);
@@ -478,26 +1078,26 @@ impl SuggestionEngine {
         // Try command-specific values first
         for (i, prefix) in prefixes.iter().enumerate() {
             let boost = if i == prefixes.len() - 1 { 2.0 } else { 1.5 };
-            if let Some(arg_map) = self.arg_value_index.get(prefix) {
-                if let Some(values) = arg_map.get(arg_name) {
-                    for (value, freq) in values {
-                        if value.starts_with(partial) {
-                            let score = *freq as f32 * boost;
-                            let entry = scored.entry(value.clone()).or_insert(0.0);
-                            *entry = entry.max(score);
-                        }
+            if let Some(arg_map) = self.indexes.arg_value_index.get(prefix)
+                && let Some(values) = arg_map.get(arg_name)
+            {
+                for (value, freq) in values {
+                    if starts_with_ignore_case(value, partial) {
+                        let score = *freq as f32 * boost;
+                        let entry = scored.entry(value.clone()).or_insert(0.0);
+                        *entry = entry.max(score);
                     }
                 }
             }
         }
 
         // Fall back to global values if no command-specific results
-        if scored.is_empty() {
-            if let Some(values) = self.global_arg_values.get(arg_name) {
-                for (value, freq) in values {
-                    if value.starts_with(partial) {
-                        scored.insert(value.clone(), *freq as f32);
-                    }
+        if scored.is_empty()
+            && let Some(values) = self.indexes.global_arg_values.get(arg_name)
+        {
+            for (value, freq) in values {
+                if starts_with_ignore_case(value, partial) {
+                    scored.insert(value.clone(), *freq as f32);
                 }
             }
         }
@@ -518,16 +1118,52 @@ impl SuggestionEngine {
 
     /// Get command suggestions from the history searcher (fuzzy search)
     fn commands_from_searcher(
+        &self,
         searcher: &mut HistorySearcher,
         query: &str,
         limit: usize,
     ) -> Vec<Suggestion> {
-        searcher
-            .search(query, limit)
+        let words: Vec<&str> = query.split_whitespace().collect();
+        let anchor = words.first().filter(|_| self.anchor_start);
+        // With `exact_first_token`, the first word and whether it's finished
+        let first_token = words
+            .first()
+            .filter(|_| self.exact_first_token)
+            .map(|word| (*word, query.trim_start().len() > word.len()));
+        let eligible = |meta: &IndexedCommand| {
+            meta.frequency >= self.min_frequency
+                && anchor.is_none_or(|word| meta.command.starts_with(word))
+                && first_token.is_none_or(|(word, finished)| {
+                    let own = meta.command.split_whitespace().next().unwrap_or("");
+                    if finished { own == word } else { own.starts_with(word) }
+                })
+        };
+        // Every word abbreviated ("c b" for "cargo build") is a stronger hint
+        // than a fuzzy match, so those commands come first, by usage
+        let abbreviated = if words.len() > 1 {
+            searcher.search_filtered("", limit, |meta| eligible(meta) && abbreviates(&words, &meta.command))
+        } else {
+            Vec::new()
+        };
+        let fuzzy = match first_token {
+            Some((_, true)) => {
+                let rest = crate::searcher::after_first_word(query);
+                searcher.search_after_first_word(rest, limit, eligible)
+            }
+            _ => searcher.search_filtered(query, limit, eligible),
+        };
+        let above_fuzzy = fuzzy.first().map_or(0.0, |result| result.score as f32) + 1.0;
+
+        let mut seen = HashSet::new();
+        abbreviated
             .into_iter()
-            .map(|result| Suggestion {
-                text: result.command.clone(),
-                score: result.score as f32,
+            .map(|result| (result.command, above_fuzzy + result.score as f32))
+            .chain(fuzzy.into_iter().map(|result| (result.command, result.score as f32)))
+            .filter(|(command, _)| seen.insert(command.clone()))
+            .take(limit)
+            .map(|(text, score)| Suggestion {
+                text,
+                score,
                 suggestion_type: SuggestionType::FullCommand,
             })
             .collect()
@@ -538,7 +1174,6 @@ impl SuggestionEngine {
 mod tests {
     use super::*;
     use crate::searcher::HistorySearcher;
-    use tempfile::NamedTempFile;
 
     // --- Argument parsing tests ---
 
@@ -598,6 +1233,218 @@ mod tests {
         assert!(parsed.args.is_empty());
     }
 
+    // --- Compound command tests ---
+
+    #[test]
+    fn test_split_compound() {
+        assert_eq!(
+            split_compound("cargo build && cargo test"),
+            vec!["cargo build", "cargo test"]
+        );
+        assert_eq!(split_compound("a || b; c|d"), vec!["a", "b", "c", "d"]);
+        // Operators inside quotes and lone '&' don't split
+        assert_eq!(
+            split_compound("echo 'a && b' \"c|d\" 2>&1"),
+            vec!["echo 'a && b' \"c|d\" 2>&1"]
+        );
+    }
+
+    /// The indexes built the straightforward way, one `entry` chain per count
+    fn reference_indexes(commands: &[IndexedCommand]) -> ArgIndexes {
+        let mut ix = ArgIndexes::default();
+        for cmd in commands {
+            let weight = cmd.frequency.max(1);
+            for parsed in parse_commands(&cmd.command) {
+                let deepest = parsed.prefixes.last().cloned().unwrap_or_default();
+                for prefix in &parsed.prefixes {
+                    let words = deepest[prefix.len()..].trim();
+                    let set = [words, parsed.arguments.as_str()]
+                        .into_iter()
+                        .filter(|part| !part.is_empty())
+                        .collect::<Vec<_>>()
+                        .join(" ");
+                    if !set.is_empty() {
+                        *ix.arg_set_index.entry(prefix.clone()).or_default().entry(set).or_insert(0) += weight;
+                    }
+                }
+                if let Some(combo) = arg_combo(&parsed) {
+                    for prefix in &parsed.prefixes {
+                        *ix.arg_combo_index
+                            .entry(prefix.clone())
+                            .or_default()
+                            .entry(combo.clone())
+                            .or_insert(0) += weight;
+                    }
+                }
+                for prefix in &parsed.prefixes {
+                    for arg in &parsed.args {
+                        *ix.arg_index.entry(prefix.clone()).or_default().entry(arg.name.clone()).or_insert(0) += weight;
+                        if let Some(value) = &arg.value {
+                            *ix.arg_value_index
+                                .entry(prefix.clone())
+                                .or_default()
+                                .entry(arg.name.clone())
+                                .or_default()
+                                .entry(value.clone())
+                                .or_insert(0) += weight;
+                            *ix.global_arg_values
+                                .entry(arg.name.clone())
+                                .or_default()
+                                .entry(value.clone())
+                                .or_insert(0) += weight;
+                            ix.value_taking_args.insert(arg.name.clone());
+                        }
+                    }
+                }
+            }
+        }
+        ix
+    }
+
+    #[test]
+    fn test_parallel_build_matches_sequential() {
+        let tools = ["cargo build", "git log", "kubectl get pods", "ls", "rg"];
+        let args = ["--release", "-n prod", "--target=wasm32", "-v -j 8", "--features 'a b'", "-- -x"];
+        let commands: Vec<IndexedCommand> = (0..PARALLEL_BUILD_MIN * 2 + 7)
+            .map(|i| {
+                let mut command = format!("{} {} {} h{}", tools[i % 5], args[i % 6], args[i / 5 % 6], i % 100);
+                if i % 9 == 0 {
+                    command.push_str(" | grep -i err && make deploy --env staging");
+                }
+                IndexedCommand {
+                    id: i as i64,
+                    command,
+                    frequency: (i % 4) as u32,
+                    last_used: None,
+                }
+            })
+            .collect();
+
+        let expected = reference_indexes(&commands);
+        let refs: Vec<&IndexedCommand> = commands.iter().collect();
+        assert_eq!(ArgIndexes::build_parallel(&refs), expected);
+        assert_eq!(ArgIndexes::build_chunk(&refs), expected);
+        let engine = SuggestionEngine::new(&commands);
+        assert_eq!(engine.indexes, expected);
+        assert_eq!(SuggestionEngine::new(&commands[..100]).indexes, reference_indexes(&commands[..100]));
+
+        // Adding commands one at a time ends up in the same place
+        let mut engine = SuggestionEngine::new(&commands[..PARALLEL_BUILD_MIN + 1]);
+        engine.index_commands(&commands[PARALLEL_BUILD_MIN + 1..]);
+        assert_eq!(engine.indexes, expected);
+    }
+
+    #[test]
+    fn test_index_and_joined_command() {
+        let engine = SuggestionEngine::new(&[IndexedCommand {
+            id: 1,
+            command: "cargo build --release && cargo test --workspace".to_string(),
+            frequency: 1,
+            last_used: None,
+        }]);
+
+        let build = engine.indexes.arg_index.get("cargo build").unwrap();
+        assert!(build.contains_key("--release"));
+        assert!(!build.contains_key("--workspace"));
+        assert!(engine.indexes.arg_index.get("cargo test").unwrap().contains_key("--workspace"));
+        assert!(!engine.indexes.arg_index.keys().any(|k| k.contains("&&")));
+    }
+
+    #[test]
+    fn test_index_piped_command() {
+        let mut engine = SuggestionEngine::new(&[]);
+        engine.index_command("kubectl get pods -n prod | grep -v Running");
+
+        let kubectl = engine.indexes.arg_value_index.get("kubectl get pods").unwrap();
+        assert!(kubectl.get("-n").unwrap().contains_key("prod"));
+        assert!(engine.indexes.arg_index.get("grep").unwrap().contains_key("-v"));
+        assert!(!kubectl.contains_key("-v"));
+    }
+
+    #[test]
+    fn test_suggest_args_in_later_segment() {
+        let engine = SuggestionEngine::new(&create_arg_test_commands());
+        let mut searcher = HistorySearcher::new_in_memory().unwrap();
+
+        let suggestions = engine.suggest("make && cargo build --ta", &mut searcher, 10);
+        assert!(suggestions.iter().any(|s| s.text == "--target"));
+    }
+
+    #[test]
+    fn test_abbreviated_words_match_commands() {
+        let engine = SuggestionEngine::new(&[]);
+        let mut searcher = HistorySearcher::new_in_memory().unwrap();
+        for (command, uses) in [
+            ("cargo build --release", 2),
+            ("cargo bench", 1),
+            ("cargo build", 3),
+            ("docker compose build", 5),
+        ] {
+            for _ in 0..uses {
+                searcher.record_usage(command).unwrap();
+            }
+        }
+
+        let texts = |input: &str, searcher: &mut HistorySearcher| -> Vec<String> {
+            engine.suggest(input, searcher, 10).into_iter().map(|s| s.text).collect()
+        };
+        // Most used first; "docker compose build" only matches fuzzily
+        let suggestions = texts("c b", &mut searcher);
+        assert_eq!(suggestions[..3], ["cargo build", "cargo build --release", "cargo bench"]);
+        assert_eq!(texts("c b --re", &mut searcher)[0], "cargo build --release");
+        assert_eq!(texts("C BU", &mut searcher)[..2], ["cargo build", "cargo build --release"]);
+
+        assert!(abbreviates(&["c", "b", "--re"], "cargo build --release"));
+        assert!(!abbreviates(&["c", "b"], "cargo"));
+        assert!(!abbreviates(&["b", "c"], "cargo build"));
+    }
+
+    #[test]
+    fn test_rebuild_keeps_providers() {
+        let mut engine = SuggestionEngine::new(&create_arg_test_commands());
+        engine.register_provider(Box::new(FixedProvider));
+        let mut searcher = HistorySearcher::new_in_memory().unwrap();
+
+        engine.rebuild(&[]);
+        assert!(engine.suggest("cargo build --re", &mut searcher, 10).is_empty());
+        assert_eq!(engine.suggest("git checkout ", &mut searcher, 10)[0].text, "main");
+    }
+
+    // --- Provider tests ---
+
+    struct FixedProvider;
+
+    impl SuggestionProvider for FixedProvider {
+        fn name(&self) -> &str {
+            "fixed"
+        }
+
+        fn suggest(&self, ctx: &ProviderContext, _limit: usize) -> Vec<Suggestion> {
+            if ctx.completed.first().map(String::as_str) != Some("git") {
+                return Vec::new();
+            }
+            vec![Suggestion {
+                text: "main".to_string(),
+                score: 1000.0,
+                suggestion_type: SuggestionType::ArgumentValue,
+            }]
+        }
+    }
+
+    #[test]
+    fn test_registered_provider_merged_by_score() {
+        let mut engine = SuggestionEngine::new(&create_arg_test_commands());
+        engine.register_provider(Box::new(FixedProvider));
+        let mut searcher = HistorySearcher::new_in_memory().unwrap();
+
+        let suggestions = engine.suggest("git checkout ", &mut searcher, 10);
+        assert_eq!(suggestions[0].text, "main");
+
+        // Not active for other commands
+        let suggestions = engine.suggest("cargo build --re", &mut searcher, 10);
+        assert!(!suggestions.iter().any(|s| s.text == "main"));
+    }
+
     // --- split_input tests ---
 
     #[test]
@@ -621,6 +1468,39 @@ mod tests {
         assert_eq!(partial, "car");
     }
 
+    #[test]
+    fn test_split_input_open_double_quote() {
+        let (completed, partial) = split_input("git commit --message \"fix b");
+        assert_eq!(completed, vec!["git", "commit", "--message"]);
+        assert_eq!(partial, "fix b");
+
+        // Trailing space inside the quote is part of the partial
+        let (_, partial) = split_input("git commit -m \"fix ");
+        assert_eq!(partial, "fix ");
+    }
+
+    #[test]
+    fn test_split_input_single_quote() {
+        let (completed, partial) = split_input("git commit -m 'fix b");
+        assert_eq!(completed, vec!["git", "commit", "-m"]);
+        assert_eq!(partial, "fix b");
+
+        // A closed quote still counts as one completed token
+        let (completed, partial) = split_input("git commit -m 'fix bug' ");
+        assert_eq!(completed, vec!["git", "commit", "-m", "fix bug"]);
+        assert_eq!(partial, "");
+    }
+
+    #[test]
+    fn test_quote_value() {
+        assert_eq!(quote_value("fix bug", "\"fix"), "\"fix bug\"");
+        assert_eq!(quote_value("say \"hi\"", "\""), "\"say \\\"hi\\\"\"");
+        assert_eq!(quote_value("fix bug", "'fix"), "'fix bug'");
+        assert_eq!(quote_value("it's", "'it"), "'it'\\''s'");
+        assert_eq!(quote_value("x86_64", "x"), "x86_64");
+        assert_eq!(quote_value("fix bug", "fix"), "'fix bug'");
+    }
+
     #[test]
     fn test_split_input_empty() {
         let (completed, partial) = split_input("");
@@ -670,11 +1550,11 @@ mod tests {
         let commands = create_arg_test_commands();
         let engine = SuggestionEngine::new(&commands);
 
-        let cargo_build_args = engine.arg_index.get("cargo build").unwrap();
+        let cargo_build_args = engine.indexes.arg_index.get("cargo build").unwrap();
         assert!(cargo_build_args.contains_key("--release"));
         assert!(cargo_build_args.contains_key("--target"));
 
-        let cargo_test_args = engine.arg_index.get("cargo test").unwrap();
+        let cargo_test_args = engine.indexes.arg_index.get("cargo test").unwrap();
         assert!(cargo_test_args.contains_key("--run"));
     }
 
@@ -684,6 +1564,7 @@ mod tests {
         let engine = SuggestionEngine::new(&commands);
 
         let target_values = engine
+            .indexes
             .arg_value_index
             .get("cargo build")
             .unwrap()
@@ -692,7 +1573,7 @@ mod tests {
         assert!(target_values.contains_key("x86_64"));
         assert!(target_values.contains_key("wasm32"));
 
-        let run_values = engine.global_arg_values.get("--run").unwrap();
+        let run_values = engine.indexes.global_arg_values.get("--run").unwrap();
         assert!(run_values.contains_key("sample_run"));
         assert!(run_values.contains_key("integration_test"));
     }
@@ -768,6 +1649,239 @@ mod tests {
         assert_eq!(suggestions[0].text, "x86_64");
     }
 
+    #[test]
+    fn test_suggest_args_and_values_ignore_case() {
+        let commands = create_arg_test_commands();
+        let engine = SuggestionEngine::new(&commands);
+
+        let prefixes = vec!["cargo".to_string(), "cargo build".to_string()];
+        let args = engine.suggest_args(&prefixes, "--Rel", &HashSet::new(), 10);
+        assert_eq!(args.len(), 1);
+        assert_eq!(args[0].text, "--release");
+
+        let values = engine.suggest_arg_values(&prefixes, "--target", "X86", 10);
+        assert_eq!(values.len(), 1);
+        assert_eq!(values[0].text, "x86_64");
+
+        // Global fallback values too
+        let values = engine.suggest_arg_values(&["npm".to_string()], "--run", "Sample", 10);
+        assert_eq!(values.len(), 1);
+        assert_eq!(values[0].text, "sample_run");
+    }
+
+    #[test]
+    fn test_starts_with_ignore_case() {
+        assert!(starts_with_ignore_case("--release", "--REL"));
+        assert!(starts_with_ignore_case("Ärger", "är"));
+        assert!(starts_with_ignore_case("x86_64", ""));
+        assert!(!starts_with_ignore_case("x8", "x86"));
+        assert!(!starts_with_ignore_case("wasm32", "x"));
+    }
+
+    // --- Argument combination tests ---
+
+    fn create_combo_test_commands() -> Vec<IndexedCommand> {
+        vec![
+            IndexedCommand {
+                id: 1,
+                command: "cargo build --release --locked".to_string(),
+                frequency: 6,
+                last_used: Some(1000),
+            },
+            IndexedCommand {
+                id: 2,
+                command: "cargo build --locked --release".to_string(),
+                frequency: 2,
+                last_used: Some(2000),
+            },
+            IndexedCommand {
+                id: 3,
+                command: "cargo build --release --target wasm32".to_string(),
+                frequency: 3,
+                last_used: Some(3000),
+            },
+            IndexedCommand {
+                id: 4,
+                command: "cargo build --verbose --offline".to_string(),
+                frequency: 1,
+                last_used: Some(4000),
+            },
+        ]
+    }
+
+    #[test]
+    fn test_arg_combo_sorted_and_order_independent() {
+        let engine = SuggestionEngine::new(&create_combo_test_commands());
+        let combos = engine.indexes.arg_combo_index.get("cargo build").unwrap();
+        let key = vec!["--locked".to_string(), "--release".to_string()];
+        assert_eq!(combos.get(&key), Some(&8));
+        assert!(combos.contains_key(&vec![
+            "--release".to_string(),
+            "--target wasm32".to_string()
+        ]));
+    }
+
+    #[test]
+    fn test_argument_sets() {
+        let commands: Vec<IndexedCommand> = [
+            ("kubectl get pods -n prod -o wide", 4),
+            ("kubectl get pods -n staging", 2),
+            ("kubectl logs -f 'web 1' --tail 50", 1),
+            ("kubectl get nodes", 9),
+            ("cd deploy && kubectl apply -f app.yaml", 3),
+        ]
+        .iter()
+        .enumerate()
+        .map(|(i, &(command, frequency))| IndexedCommand {
+            id: i as i64,
+            command: command.to_string(),
+            frequency,
+            last_used: None,
+        })
+        .collect();
+        let engine = SuggestionEngine::new(&commands);
+
+        // Whole invocations, most used first, with or without flags
+        let sets = engine.argument_sets("kubectl").unwrap();
+        assert_eq!(sets.start, 0);
+        assert_eq!(sets.prefix, "kubectl");
+        assert_eq!(
+            sets.sets,
+            [
+                ("get nodes".to_string(), 9),
+                ("get pods -n prod -o wide".to_string(), 4),
+                ("apply -f app.yaml".to_string(), 3),
+                ("get pods -n staging".to_string(), 2),
+                ("logs -f 'web 1' --tail 50".to_string(), 1),
+            ]
+        );
+
+        // The longest prefix with sets wins, flags already typed are ignored
+        let sets = engine.argument_sets("kubectl get pods -n").unwrap();
+        assert_eq!(sets.prefix, "kubectl get pods");
+        assert_eq!(sets.sets[0], ("-n prod -o wide".to_string(), 4));
+        let sets = engine.argument_sets("kubectl get po").unwrap();
+        assert_eq!(sets.prefix, "kubectl get");
+        assert_eq!(sets.sets[0], ("nodes".to_string(), 9));
+        // Nothing was run after `kubectl get nodes` itself: the level above's
+        assert_eq!(engine.argument_sets("kubectl get nodes").unwrap().prefix, "kubectl get");
+
+        // Only the command after the last operator counts
+        let sets = engine.argument_sets("make && kubectl apply").unwrap();
+        assert_eq!(sets.start, "make && ".len());
+        assert_eq!(sets.sets, [("-f app.yaml".to_string(), 3)]);
+
+        assert!(engine.argument_sets("").is_none());
+        assert!(engine.argument_sets("cargo").is_none());
+    }
+
+    #[test]
+    fn test_arg_combo_requires_two_args() {
+        assert!(arg_combo(&parse_command("cargo build --release")).is_none());
+        assert!(arg_combo(&parse_command("cargo build --release --release")).is_none());
+    }
+
+    #[test]
+    fn test_suggest_arg_combos() {
+        let engine = SuggestionEngine::new(&create_combo_test_commands());
+        let prefixes = vec!["cargo".to_string(), "cargo build".to_string()];
+        let suggestions = engine.suggest_arg_combos(&prefixes, "", &HashSet::new(), 10);
+
+        assert_eq!(suggestions[0].text, "--locked --release");
+        assert_eq!(suggestions[0].suggestion_type, SuggestionType::ArgumentCombo);
+        assert!(suggestions.iter().any(|s| s.text == "--release --target wasm32"));
+        // Seen only once: not frequent enough
+        assert!(!suggestions.iter().any(|s| s.text.contains("--offline")));
+    }
+
+    #[test]
+    fn test_suggest_arg_combos_partial_leads() {
+        let engine = SuggestionEngine::new(&create_combo_test_commands());
+        let prefixes = vec!["cargo".to_string(), "cargo build".to_string()];
+        let suggestions = engine.suggest_arg_combos(&prefixes, "--r", &HashSet::new(), 10);
+
+        assert!(suggestions.iter().any(|s| s.text == "--release --locked"));
+        assert!(suggestions.iter().all(|s| s.text.starts_with("--r")));
+    }
+
+    #[test]
+    fn test_suggest_arg_combos_dedups_typed_args() {
+        let engine = SuggestionEngine::new(&create_combo_test_commands());
+        let prefixes = vec!["cargo".to_string(), "cargo build".to_string()];
+        let mut exclude = HashSet::new();
+        exclude.insert("--release".to_string());
+
+        // Every combo contains --release, leaving fewer than two args each
+        let suggestions = engine.suggest_arg_combos(&prefixes, "", &exclude, 10);
+        assert!(suggestions.is_empty());
+    }
+
+    #[test]
+    fn test_suggest_combos_before_single_args() {
+        let engine = SuggestionEngine::new(&create_combo_test_commands());
+        let mut searcher = HistorySearcher::new_in_memory().unwrap();
+
+        let suggestions = engine.suggest("cargo build --", &mut searcher, 10);
+        assert_eq!(suggestions[0].suggestion_type, SuggestionType::ArgumentCombo);
+        assert!(suggestions
+            .iter()
+            .any(|s| s.text == "--release" && s.suggestion_type == SuggestionType::Argument));
+    }
+
+    // --- Flag/value pair tests ---
+
+    #[test]
+    fn test_pair_matches() {
+        assert!(pair_matches("", "--target x86_64"));
+        assert!(pair_matches("--", "--target x86_64"));
+        assert!(pair_matches("--tar", "--target x86_64"));
+        assert!(pair_matches("--tx86", "--target x86_64"));
+        assert!(!pair_matches("--x86", "--target x86_64"));
+        assert!(!pair_matches("-t", "--target x86_64"));
+        assert!(!pair_matches("--tarm", "--target x86_64"));
+    }
+
+    #[test]
+    fn test_suggest_arg_pairs() {
+        let mut engine = SuggestionEngine::new(&create_arg_test_commands());
+        let prefixes = vec!["cargo".to_string(), "cargo build".to_string()];
+        let pairs = engine.suggest_arg_pairs(&prefixes, "--tw", &HashSet::new(), 10);
+        assert_eq!(pairs.len(), 1);
+        assert_eq!(pairs[0].text, "--target wasm32");
+        assert_eq!(pairs[0].suggestion_type, SuggestionType::ArgumentPair);
+
+        let mut exclude = HashSet::new();
+        exclude.insert("--target".to_string());
+        let pairs = engine.suggest_arg_pairs(&prefixes, "--", &exclude, 10);
+        assert!(!pairs.iter().any(|s| s.text.starts_with("--target")));
+
+        // Off by default; once on, pairs come before single arguments
+        let mut searcher = HistorySearcher::new_in_memory().unwrap();
+        let suggestions = engine.suggest("cargo build --", &mut searcher, 10);
+        assert!(!suggestions.iter().any(|s| s.suggestion_type == SuggestionType::ArgumentPair));
+        engine.set_argument_pairs(true);
+        engine.rebuild(&create_arg_test_commands());
+        let suggestions = engine.suggest("cargo build --", &mut searcher, 10);
+        assert_eq!(suggestions[0].text, "--target x86_64");
+        assert_eq!(suggestions[1].suggestion_type, SuggestionType::ArgumentPair);
+        assert_eq!(suggestions[2].suggestion_type, SuggestionType::Argument);
+        assert!(suggestions.iter().any(|s| s.text == "--release"));
+    }
+
+    #[test]
+    fn test_suggest_arg_pairs_quotes_values() {
+        let commands = vec![IndexedCommand {
+            id: 1,
+            command: "git commit -m 'wip: save'".to_string(),
+            frequency: 2,
+            last_used: None,
+        }];
+        let engine = SuggestionEngine::new(&commands);
+        let prefixes = vec!["git".to_string(), "git commit".to_string()];
+        let pairs = engine.suggest_arg_pairs(&prefixes, "-mw", &HashSet::new(), 10);
+        assert_eq!(pairs[0].text, "-m 'wip: save'");
+    }
+
     // --- analyze_completed tests ---
 
     /// Helper to convert &str slices to Vec<String> for analyze_completed
@@ -838,8 +1952,7 @@ mod tests {
     #[test]
     fn test_suggest_value_after_value_taking_arg() {
         let engine = SuggestionEngine::new(&create_arg_test_commands());
-        let temp_db = NamedTempFile::new().unwrap();
-        let mut searcher = HistorySearcher::new(temp_db.path().to_path_buf()).unwrap();
+        let mut searcher = HistorySearcher::new_in_memory().unwrap();
 
         // "cargo test --run " → values for --run
         let suggestions = engine.suggest("cargo test --run ", &mut searcher, 10);
@@ -851,8 +1964,7 @@ mod tests {
     #[test]
     fn test_suggest_value_with_partial() {
         let engine = SuggestionEngine::new(&create_arg_test_commands());
-        let temp_db = NamedTempFile::new().unwrap();
-        let mut searcher = HistorySearcher::new(temp_db.path().to_path_buf()).unwrap();
+        let mut searcher = HistorySearcher::new_in_memory().unwrap();
 
         // "cargo test --run sam" → filtered values
         let suggestions = engine.suggest("cargo test --run sam", &mut searcher, 10);
@@ -860,11 +1972,32 @@ mod tests {
         assert!(!suggestions.iter().any(|s| s.text == "integration_test"));
     }
 
+    #[test]
+    fn test_suggest_quoted_value() {
+        let commands = vec![IndexedCommand {
+            id: 1,
+            command: "git commit --message \"fix bug\"".to_string(),
+            frequency: 3,
+            last_used: Some(1000),
+        }];
+        let engine = SuggestionEngine::new(&commands);
+        let mut searcher = HistorySearcher::new_in_memory().unwrap();
+
+        for input in ["git commit --message \"fix ", "git commit --message 'fix b"] {
+            let suggestions = engine.suggest(input, &mut searcher, 10);
+            assert!(
+                suggestions.iter().any(|s| s.text == "fix bug"
+                    && s.suggestion_type == SuggestionType::ArgumentValue),
+                "{}",
+                input
+            );
+        }
+    }
+
     #[test]
     fn test_suggest_arg_mid_typing() {
         let engine = SuggestionEngine::new(&create_arg_test_commands());
-        let temp_db = NamedTempFile::new().unwrap();
-        let mut searcher = HistorySearcher::new(temp_db.path().to_path_buf()).unwrap();
+        let mut searcher = HistorySearcher::new_in_memory().unwrap();
 
         // "cargo build --re" → --release
         let suggestions = engine.suggest("cargo build --re", &mut searcher, 10);
@@ -874,8 +2007,7 @@ mod tests {
     #[test]
     fn test_suggest_args_after_flag() {
         let engine = SuggestionEngine::new(&create_arg_test_commands());
-        let temp_db = NamedTempFile::new().unwrap();
-        let mut searcher = HistorySearcher::new(temp_db.path().to_path_buf()).unwrap();
+        let mut searcher = HistorySearcher::new_in_memory().unwrap();
 
         // "cargo build --release " → more args (--release is a flag, NOT value-taking)
         let suggestions = engine.suggest("cargo build --release ", &mut searcher, 10);
@@ -891,8 +2023,7 @@ mod tests {
     #[test]
     fn test_suggest_args_after_value_consumed() {
         let engine = SuggestionEngine::new(&create_arg_test_commands());
-        let temp_db = NamedTempFile::new().unwrap();
-        let mut searcher = HistorySearcher::new(temp_db.path().to_path_buf()).unwrap();
+        let mut searcher = HistorySearcher::new_in_memory().unwrap();
 
         // "cargo build --target x86_64 " → more args (value consumed)
         let suggestions = engine.suggest("cargo build --target x86_64 ", &mut searcher, 10);
@@ -904,8 +2035,7 @@ mod tests {
     #[test]
     fn test_suggest_subcommand_fallback() {
         let engine = SuggestionEngine::new(&create_arg_test_commands());
-        let temp_db = NamedTempFile::new().unwrap();
-        let mut searcher = HistorySearcher::new(temp_db.path().to_path_buf()).unwrap();
+        let mut searcher = HistorySearcher::new_in_memory().unwrap();
 
         // "cargo " → Subcommand, falls back to searcher
         let suggestions = engine.suggest("cargo ", &mut searcher, 10);
@@ -917,18 +2047,124 @@ mod tests {
     #[test]
     fn test_suggest_empty_input() {
         let engine = SuggestionEngine::new(&create_arg_test_commands());
-        let temp_db = NamedTempFile::new().unwrap();
-        let mut searcher = HistorySearcher::new(temp_db.path().to_path_buf()).unwrap();
+        let mut searcher = HistorySearcher::new_in_memory().unwrap();
 
         let suggestions = engine.suggest("", &mut searcher, 10);
         assert!(suggestions.is_empty()); // empty searcher
     }
 
+    #[test]
+    fn test_min_frequency() {
+        let mut engine = SuggestionEngine::new(&[]);
+        let mut searcher = HistorySearcher::new_in_memory().unwrap();
+        for command in ["git status", "git status", "git stash pop", "g s"] {
+            searcher.record_usage(command).unwrap();
+        }
+        let texts = |engine: &SuggestionEngine, searcher: &mut HistorySearcher, input| {
+            let mut texts: Vec<String> = engine.suggest(input, searcher, 10).into_iter().map(|s| s.text).collect();
+            texts.sort();
+            texts
+        };
+        assert_eq!(texts(&engine, &mut searcher, "git st"), ["git stash pop", "git status"]);
+
+        engine.set_min_frequency(2);
+        assert_eq!(texts(&engine, &mut searcher, "git st"), ["git status"]);
+        // Abbreviations too, and empty input
+        assert_eq!(texts(&engine, &mut searcher, "g s"), ["git status"]);
+        assert_eq!(texts(&engine, &mut searcher, ""), ["git status"]);
+        // Still in history
+        assert_eq!(searcher.search("stash", 5).len(), 1);
+    }
+
+    #[test]
+    fn test_anchor_start() {
+        let mut engine = SuggestionEngine::new(&[]);
+        let mut searcher = HistorySearcher::new_in_memory().unwrap();
+        for command in ["gulp install --retry", "git status", "cargo build"] {
+            searcher.record_usage(command).unwrap();
+        }
+        let texts = |engine: &SuggestionEngine, searcher: &mut HistorySearcher, input| {
+            let mut texts: Vec<String> = engine.suggest(input, searcher, 10).into_iter().map(|s| s.text).collect();
+            texts.sort();
+            texts
+        };
+        assert_eq!(texts(&engine, &mut searcher, "git"), ["git status", "gulp install --retry"]);
+
+        engine.set_anchor_start(true);
+        assert_eq!(texts(&engine, &mut searcher, "git"), ["git status"]);
+        // The rest of the query is still fuzzy, and abbreviations still work
+        assert_eq!(texts(&engine, &mut searcher, "git sts"), ["git status"]);
+        assert_eq!(texts(&engine, &mut searcher, "c b"), ["cargo build"]);
+        assert_eq!(texts(&engine, &mut searcher, "").len(), 3);
+    }
+
+    #[test]
+    fn test_exact_first_token() {
+        let mut engine = SuggestionEngine::new(&[]);
+        let mut searcher = HistorySearcher::new_in_memory().unwrap();
+        for command in ["kubectl get pods", "kubectx local pods-dashboard", "kubectl logs api", "kubectl-ctx get"] {
+            searcher.record_usage(command).unwrap();
+        }
+        let texts = |engine: &SuggestionEngine, searcher: &mut HistorySearcher, input| {
+            let mut texts: Vec<String> = engine.suggest(input, searcher, 10).into_iter().map(|s| s.text).collect();
+            texts.sort();
+            texts
+        };
+        assert!(texts(&engine, &mut searcher, "kubectl pods").contains(&"kubectx local pods-dashboard".to_string()));
+
+        engine.set_exact_first_token(true);
+        assert_eq!(texts(&engine, &mut searcher, "kubectl pods"), ["kubectl get pods"]);
+        // Only the rest of the command is matched: `kctl` isn't in `get pods`
+        assert!(texts(&engine, &mut searcher, "kubectl kctl").is_empty());
+        // A first word still being typed matches as a prefix
+        assert_eq!(
+            texts(&engine, &mut searcher, "kubectl"),
+            ["kubectl get pods", "kubectl logs api", "kubectl-ctx get"]
+        );
+        assert_eq!(texts(&engine, &mut searcher, "kubectl ").len(), 2);
+    }
+
+    #[test]
+    fn test_type_limits() {
+        let mut engine = SuggestionEngine::new(&create_arg_test_commands());
+        let mut searcher = HistorySearcher::new_in_memory().unwrap();
+        for command in create_arg_test_commands() {
+            searcher.record_usage(&command.command).unwrap();
+        }
+
+        // Matching commands replace argument suggestions by default
+        let suggestions = engine.suggest("cargo build -", &mut searcher, 10);
+        assert!(suggestions.iter().all(|s| s.suggestion_type == SuggestionType::FullCommand));
+
+        engine.set_type_limits(TypeLimits {
+            commands: 2,
+            args: 1,
+            values: 0,
+        });
+        let suggestions = engine.suggest("cargo build -", &mut searcher, 10);
+        let types: Vec<&SuggestionType> = suggestions.iter().map(|s| &s.suggestion_type).collect();
+        assert_eq!(types[..2], [&SuggestionType::FullCommand, &SuggestionType::FullCommand]);
+        assert_eq!(types.len(), 3);
+        assert_ne!(types[2], &SuggestionType::FullCommand);
+
+        // Uncapped values fill the rest of the list
+        let suggestions = engine.suggest("cargo build --target ", &mut searcher, 10);
+        let values = suggestions
+            .iter()
+            .filter(|s| s.suggestion_type == SuggestionType::ArgumentValue)
+            .count();
+        assert_eq!(values, 2);
+        assert_eq!(suggestions.len(), 4);
+
+        // Caps survive a rebuild
+        engine.rebuild(&create_arg_test_commands());
+        assert_eq!(engine.suggest("cargo build -", &mut searcher, 10).len(), 3);
+    }
+
     #[test]
     fn test_suggest_first_word() {
         let engine = SuggestionEngine::new(&create_arg_test_commands());
-        let temp_db = NamedTempFile::new().unwrap();
-        let mut searcher = HistorySearcher::new(temp_db.path().to_path_buf()).unwrap();
+        let mut searcher = HistorySearcher::new_in_memory().unwrap();
 
         // "car" → Command, falls back to searcher
         let suggestions = engine.suggest("car", &mut searcher, 10);
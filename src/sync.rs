@@ -1,7 +1,10 @@
 use log::{info, warn};
+use std::collections::HashMap;
 
+use crate::config::{HistoryConfig, SyncConfig};
+use crate::error::Error;
 use crate::history::Shell;
-use crate::searcher::HistorySearcher;
+use crate::searcher::{HistorySearcher, IndexedCommand};
 
 /// Result of syncing shell history into the searcher
 pub struct SyncResult {
@@ -11,11 +14,41 @@ pub struct SyncResult {
     pub warnings: Vec<String>,
 }
 
-/// Sync history from all supported shells (Zsh, Bash, Fish) into the searcher.
+/// Shells read when `[sync] shells` isn't set; cmd.exe only on Windows
+const DEFAULT_SHELLS: &[Shell] = if cfg!(windows) {
+    &[Shell::Zsh, Shell::Bash, Shell::Fish, Shell::Ksh, Shell::Cmd]
+} else {
+    &[Shell::Zsh, Shell::Bash, Shell::Fish, Shell::Ksh]
+};
+
+/// Order in which shell histories are read: the configured shells (or all
+/// supported ones), with the login shell moved to the front
+fn sync_order(configured: Option<&[Shell]>, login_shell: Option<Shell>) -> Vec<Shell> {
+    let mut shells: Vec<Shell> = Vec::new();
+    for &shell in configured.unwrap_or(DEFAULT_SHELLS) {
+        if !shells.contains(&shell) {
+            shells.push(shell);
+        }
+    }
+    if let Some(login_shell) = login_shell
+        && let Some(pos) = shells.iter().position(|&s| s == login_shell)
+    {
+        shells[..=pos].rotate_right(1);
+    }
+    shells
+}
+
+/// Sync history from the shells selected by `[sync] shells` (by default Zsh,
+/// Bash, Fish and Ksh, and Cmd on Windows), the login shell first, and the configured extra
+/// sources (plain history files, a command's output) into the searcher.
 /// Returns the number of new commands indexed and any warnings.
-pub fn sync_shell_history(searcher: &mut HistorySearcher) -> SyncResult {
+pub fn sync_shell_history(
+    searcher: &mut HistorySearcher,
+    config: &HistoryConfig,
+    sync_config: &SyncConfig,
+) -> SyncResult {
     let sync_start = std::time::Instant::now();
-    let shells = [Shell::Zsh, Shell::Bash, Shell::Fish];
+    let shells = sync_order(sync_config.shells.as_deref(), Shell::login_shell());
     let mut total_synced = 0;
     let mut warnings = Vec::new();
 
@@ -39,6 +72,27 @@ pub fn sync_shell_history(searcher: &mut HistorySearcher) -> SyncResult {
         }
     }
 
+    for path in &config.plain_files {
+        let path = crate::paths::expand_path(path);
+        match searcher.sync_from_plain_file(&path) {
+            Ok(count) => total_synced += count,
+            Err(e) => {
+                warn!("Failed to sync {}: {}", path.display(), e);
+                warnings.push(format!("Failed to sync {}: {}", path.display(), e));
+            }
+        }
+    }
+
+    if let Some(command) = &config.command_source {
+        match searcher.sync_from_command(command) {
+            Ok(count) => total_synced += count,
+            Err(e) => {
+                warn!("Failed to sync history command: {}", e);
+                warnings.push(format!("Failed to sync history command: {}", e));
+            }
+        }
+    }
+
     if total_synced > 0 {
         info!(
             "Indexed {} new commands in {:.2?} ({} total)",
@@ -53,3 +107,169 @@ pub fn sync_shell_history(searcher: &mut HistorySearcher) -> SyncResult {
         warnings,
     }
 }
+
+/// Sync again while mux is running, picking up commands run in other shells
+/// since the last sync. Returns the sync result and the commands that gained
+/// uses, each with the number of new uses as its frequency, ready for
+/// `SuggestionEngine::index_commands`. Uses are written through as they're
+/// recorded, so the reloads a sync does lose nothing.
+pub fn resync(
+    searcher: &mut HistorySearcher,
+    config: &HistoryConfig,
+    sync_config: &SyncConfig,
+) -> (SyncResult, Vec<IndexedCommand>) {
+    let before: HashMap<String, u32> = searcher
+        .commands()
+        .map(|cmd| (cmd.command.clone(), cmd.frequency))
+        .collect();
+
+    let result = sync_shell_history(searcher, config, sync_config);
+    if result.total_synced == 0 {
+        return (result, Vec::new());
+    }
+    let new_uses = searcher
+        .commands()
+        .filter_map(|cmd| {
+            let added = cmd.frequency.saturating_sub(before.get(&cmd.command).copied().unwrap_or(0));
+            (added > 0).then(|| IndexedCommand {
+                frequency: added,
+                ..cmd.clone()
+            })
+        })
+        .collect();
+    (result, new_uses)
+}
+
+/// A `resync` done on a second connection to the database, off the UI thread
+pub struct BackgroundSync {
+    result: SyncResult,
+    new_uses: Vec<IndexedCommand>,
+    /// The second connection, loaded after the sync; None if nothing was synced
+    searcher: Option<HistorySearcher>,
+    /// Its `data_version` just before that load
+    data_version: i64,
+}
+
+impl BackgroundSync {
+    /// Bring what the sync indexed into `searcher`, the one the second
+    /// connection was reopened from. If the database changed since the
+    /// second connection loaded it (a use recorded meanwhile), it's loaded
+    /// again. Returns what `resync` would have.
+    pub fn apply(self, searcher: &mut HistorySearcher) -> Result<(SyncResult, Vec<IndexedCommand>), Error> {
+        if let Some(mut synced) = self.searcher {
+            if synced.data_version()? != self.data_version {
+                synced.reload_from_db()?;
+            }
+            searcher.adopt(synced);
+        }
+        Ok((self.result, self.new_uses))
+    }
+}
+
+/// `resync` on `searcher`, just opened by `HistorySearcher::reopen`. Loading
+/// and syncing are slow on a large history, so this is meant for a blocking
+/// thread; `BackgroundSync::apply` then hands the result to the UI's searcher.
+pub fn resync_in_background(
+    mut searcher: HistorySearcher,
+    config: &HistoryConfig,
+    sync_config: &SyncConfig,
+) -> Result<BackgroundSync, Error> {
+    searcher.reload_from_db()?;
+    let (result, new_uses) = resync(&mut searcher, config, sync_config);
+    if result.total_synced == 0 {
+        return Ok(BackgroundSync { result, new_uses, searcher: None, data_version: 0 });
+    }
+    // Noted before loading, so a commit from another connection during the
+    // load shows up in `apply`
+    let data_version = searcher.data_version()?;
+    searcher.reload_from_db()?;
+    Ok(BackgroundSync {
+        result,
+        new_uses,
+        searcher: Some(searcher),
+        data_version,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resync_reports_new_uses() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.txt");
+        std::fs::write(&path, "git status\n").unwrap();
+        let history = HistoryConfig {
+            plain_files: vec![path.clone()],
+            ..HistoryConfig::default()
+        };
+        let sync_config = SyncConfig {
+            shells: Some(Vec::new()),
+            ..SyncConfig::default()
+        };
+        let mut searcher = HistorySearcher::new_in_memory().unwrap();
+        sync_shell_history(&mut searcher, &history, &sync_config);
+        searcher.record_usage("git status").unwrap();
+
+        let (result, new_uses) = resync(&mut searcher, &history, &sync_config);
+        assert_eq!(result.total_synced, 0);
+        assert!(new_uses.is_empty());
+
+        std::fs::write(&path, "git status\ngit status\ncargo build --release\n").unwrap();
+        let (result, mut new_uses) = resync(&mut searcher, &history, &sync_config);
+        assert_eq!(result.total_synced, 2);
+        new_uses.sort_by(|a, b| a.command.cmp(&b.command));
+        let new_uses: Vec<_> = new_uses.iter().map(|c| (c.command.as_str(), c.frequency)).collect();
+        assert_eq!(new_uses, [("cargo build --release", 1), ("git status", 1)]);
+        // The use recorded in this session survived the reload
+        assert_eq!(searcher.commands().find(|c| c.command == "git status").unwrap().frequency, 3);
+    }
+
+    #[test]
+    fn test_resync_in_background() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.txt");
+        std::fs::write(&path, "git status\n").unwrap();
+        let history = HistoryConfig {
+            plain_files: vec![path.clone()],
+            ..HistoryConfig::default()
+        };
+        let sync_config = SyncConfig {
+            shells: Some(Vec::new()),
+            ..SyncConfig::default()
+        };
+        let mut searcher = HistorySearcher::new(dir.path().join("history.db")).unwrap();
+        sync_shell_history(&mut searcher, &history, &sync_config);
+        assert!(HistorySearcher::new_in_memory().unwrap().reopen().unwrap().is_none());
+
+        std::fs::write(&path, "git status\ncargo build --release\n").unwrap();
+        let second = searcher.reopen().unwrap().unwrap();
+        let synced = resync_in_background(second, &history, &sync_config).unwrap();
+        // Recorded while the sync ran: the second connection's load misses it
+        searcher.record_usage("cargo build --release").unwrap();
+        let (result, new_uses) = synced.apply(&mut searcher).unwrap();
+        assert_eq!(result.total_synced, 1);
+        assert_eq!(new_uses.len(), 1);
+        assert_eq!(
+            searcher.commands().find(|c| c.command == "cargo build --release").unwrap().frequency,
+            2
+        );
+    }
+
+    #[test]
+    fn test_sync_order() {
+        assert_eq!(sync_order(None, None), DEFAULT_SHELLS);
+        assert_eq!(
+            sync_order(None, Some(Shell::Fish)),
+            [Shell::Fish, Shell::Zsh, Shell::Bash, Shell::Ksh]
+        );
+        // Only the configured shells, login shell first, each once
+        assert_eq!(
+            sync_order(Some(&[Shell::Bash, Shell::Fish, Shell::Bash]), Some(Shell::Fish)),
+            [Shell::Fish, Shell::Bash]
+        );
+        // A login shell that isn't configured isn't added
+        assert_eq!(sync_order(Some(&[Shell::Fish]), Some(Shell::Zsh)), [Shell::Fish]);
+    }
+}
@@ -0,0 +1,233 @@
+//! `mux --tail <PATH>` and `mux --tail-cmd <COMMAND>`: follow a file or a
+//! command's output in the pager, with its scrolling and search, and without
+//! the input line or suggestions.
+//!
+//! The file is polled. When it shrinks (truncated) or another file takes its
+//! path (rotated), reading starts over from the beginning of what's there.
+//! The command runs in a PTY through the task runner, like commands typed in mux.
+
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crossterm::{
+    event::{Event, EventStream},
+    execute,
+    terminal::EnterAlternateScreen,
+};
+use futures::StreamExt;
+use ratatui::{Terminal, backend::CrosstermBackend};
+
+use crate::config::Config;
+use crate::pager::{Pager, plain_text};
+use crate::runner::{OutputMessage, PtySizes, StreamType, TaskRunner};
+use crate::theme::Palette;
+use crate::tui::TerminalGuard;
+
+/// How often a tailed file is checked for new lines
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// What `--tail`/`--tail-cmd` follows
+pub enum TailSource {
+    File(PathBuf),
+    Command(String),
+}
+
+/// Reads the lines appended to a file since the last poll
+pub struct FileFollower {
+    path: PathBuf,
+    file: File,
+    /// Identity of the open file, to notice when the path is rotated
+    id: Option<(u64, u64)>,
+    /// Bytes read so far
+    offset: u64,
+    /// Bytes after the last newline, completed by a later poll
+    partial: Vec<u8>,
+}
+
+impl FileFollower {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let id = file_id(&file.metadata()?);
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            id,
+            offset: 0,
+            partial: Vec::new(),
+        })
+    }
+
+    /// Complete lines written since the last poll, with a notice line first
+    /// if the file was truncated or rotated. A path that's briefly missing
+    /// mid-rotation reads as no new lines.
+    pub fn poll(&mut self) -> io::Result<Vec<String>> {
+        let mut lines = Vec::new();
+        let metadata = match std::fs::metadata(&self.path) {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(lines),
+            Err(e) => return Err(e),
+        };
+
+        let id = file_id(&metadata);
+        if id != self.id {
+            lines.push(format!("--- {} was replaced, reading the new file ---", self.path.display()));
+            self.file = File::open(&self.path)?;
+            self.restart(id);
+        } else if metadata.len() < self.offset {
+            lines.push(format!("--- {} was truncated ---", self.path.display()));
+            self.restart(id);
+        }
+
+        self.file.seek(SeekFrom::Start(self.offset))?;
+        let mut buf = Vec::new();
+        self.offset += self.file.read_to_end(&mut buf)? as u64;
+        self.partial.extend_from_slice(&buf);
+
+        while let Some(newline) = self.partial.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.partial.drain(..=newline).collect();
+            let line = String::from_utf8_lossy(&line[..newline]);
+            lines.push(line.trim_end_matches('\r').to_string());
+        }
+        Ok(lines)
+    }
+
+    fn restart(&mut self, id: Option<(u64, u64)>) {
+        self.id = id;
+        self.offset = 0;
+        self.partial.clear();
+    }
+}
+
+/// (device, inode) of a file; None where that isn't available
+#[cfg(unix)]
+fn file_id(metadata: &std::fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn file_id(_metadata: &std::fs::Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+/// The pager line for a message from the tailed command
+fn message_line(msg: &OutputMessage) -> String {
+    match msg.stream {
+        StreamType::Output => plain_text(&msg.content),
+        StreamType::Status => format!("--- {} ---", msg.content),
+    }
+}
+
+/// Follow `source` full-screen until the user quits
+pub async fn run_tail(source: TailSource, config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let (mut follower, title) = match &source {
+        TailSource::File(path) => (Some(FileFollower::open(path)?), path.display().to_string()),
+        TailSource::Command(command) => (None, command.clone()),
+    };
+
+    let guard = TerminalGuard::enter()?;
+    // Query the terminal before the event stream starts consuming stdin
+    let palette = Palette::for_background(crate::theme::detect_background(config.theme.background));
+    let mut stdout = io::stdout();
+    execute!(stdout, EnterAlternateScreen)?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(stdout))?;
+
+    let (output_tx, mut output_rx) =
+        tokio::sync::mpsc::channel::<OutputMessage>(config.runner.output_buffer.max(1));
+    let mut runner = TaskRunner::new(output_tx, 1);
+    runner.set_kill_grace(Duration::from_millis(config.runner.kill_grace_ms));
+    if let TailSource::Command(command) = &source {
+        runner.spawn_labeled(command, "");
+    }
+    let mut event_stream = EventStream::new();
+    let mut poll = tokio::time::interval(POLL_INTERVAL);
+    let mut pager = Pager::following(title);
+
+    loop {
+        terminal.draw(|f| crate::tui::render_pager(f, &mut pager, palette))?;
+
+        tokio::select! {
+            Some(event_result) = event_stream.next() => {
+                match event_result {
+                    Ok(Event::Key(key)) if !crate::keymap::handle_pager_key(&mut pager, key) => break,
+                    Ok(Event::Resize(cols, rows)) => runner.resize_all(PtySizes::uniform(cols, rows)),
+                    _ => {}
+                }
+            }
+            Some(msg) = output_rx.recv() => {
+                pager.push_line(message_line(&msg));
+                while let Ok(msg) = output_rx.try_recv() {
+                    pager.push_line(message_line(&msg));
+                }
+            }
+            _ = poll.tick(), if follower.is_some() => {
+                let Some(file) = follower.as_mut() else { continue };
+                match file.poll() {
+                    Ok(lines) => lines.iter().for_each(|line| pager.push_line(plain_text(line))),
+                    Err(e) => {
+                        pager.push_line(format!("--- stopped following: {} ---", e));
+                        follower = None;
+                    }
+                }
+            }
+        }
+    }
+
+    output_rx.close();
+    runner.cancel_all().await;
+    guard.restore()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_follows_appended_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.log");
+        std::fs::write(&path, "one\ntwo\r\nthr").unwrap();
+
+        let mut follower = FileFollower::open(&path).unwrap();
+        assert_eq!(follower.poll().unwrap(), ["one", "two"]);
+        assert!(follower.poll().unwrap().is_empty());
+
+        let mut file = std::fs::OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"ee\nfour\n").unwrap();
+        assert_eq!(follower.poll().unwrap(), ["three", "four"]);
+    }
+
+    #[test]
+    fn test_truncation_and_rotation() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.log");
+        std::fs::write(&path, "old 1\nold 2\n").unwrap();
+        let mut follower = FileFollower::open(&path).unwrap();
+        follower.poll().unwrap();
+
+        // Truncated in place, as by `> app.log`
+        std::fs::write(&path, "new\n").unwrap();
+        let lines = follower.poll().unwrap();
+        assert!(lines[0].contains("was truncated"), "{:?}", lines);
+        assert_eq!(lines[1..], ["new"]);
+
+        // Moved aside, with a fresh file at the path
+        std::fs::rename(&path, dir.path().join("app.log.1")).unwrap();
+        assert!(follower.poll().unwrap().is_empty());
+        std::fs::write(&path, "rotated\n").unwrap();
+        let lines = follower.poll().unwrap();
+        assert!(lines[0].contains("was replaced"), "{:?}", lines);
+        assert_eq!(lines[1..], ["rotated"]);
+    }
+
+    #[test]
+    fn test_message_line() {
+        let output = OutputMessage::output(1, "", "\x1b[32mok\x1b[0m".to_string());
+        assert_eq!(message_line(&output), "ok");
+        assert_eq!(message_line(&OutputMessage::status(1, "", "completed")), "--- completed ---");
+    }
+}
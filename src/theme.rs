@@ -0,0 +1,194 @@
+//! Color palettes for dark and light terminal backgrounds.
+//!
+//! `[theme] background = "auto"` asks the terminal for its background color
+//! (OSC 11) and falls back to `$COLORFGBG`, then to dark.
+
+use ratatui::style::Color;
+use std::time::Duration;
+
+use crate::config::Background;
+
+/// How long to wait for the terminal to answer the background color query
+const OSC_QUERY_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Colors used by the TUI chrome (borders, hints, token highlighting)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Palette {
+    /// Regular text
+    pub text: Color,
+    /// De-emphasized text: typed prefixes, ghost previews, box borders
+    pub dim: Color,
+    /// Output border, argument tokens
+    pub primary: Color,
+    /// Suggestions border
+    pub secondary: Color,
+    /// Input border, value tokens
+    pub success: Color,
+    /// Quit hint, selection marker
+    pub warning: Color,
+    /// Failure counts
+    pub error: Color,
+}
+
+impl Palette {
+    pub fn dark() -> Self {
+        Self {
+            text: Color::White,
+            dim: Color::DarkGray,
+            primary: Color::Cyan,
+            secondary: Color::Magenta,
+            success: Color::Green,
+            warning: Color::Yellow,
+            error: Color::Red,
+        }
+    }
+
+    pub fn light() -> Self {
+        Self {
+            text: Color::Black,
+            dim: Color::Indexed(242),
+            primary: Color::Blue,
+            secondary: Color::Magenta,
+            success: Color::Indexed(28),
+            warning: Color::Indexed(130),
+            error: Color::Red,
+        }
+    }
+
+    /// Palette for a background setting. `Auto` must be resolved with
+    /// [`detect_background`] first; unresolved it means dark.
+    pub fn for_background(background: Background) -> Self {
+        match background {
+            Background::Light => Self::light(),
+            Background::Dark | Background::Auto => Self::dark(),
+        }
+    }
+}
+
+/// Resolve `Auto` to a concrete background. Must run in raw mode, before the
+/// event stream starts reading stdin.
+pub fn detect_background(background: Background) -> Background {
+    if background != Background::Auto {
+        return background;
+    }
+
+    let detected = query_background_color()
+        .map(|(r, g, b)| is_light_rgb(r, g, b))
+        .or_else(|| std::env::var("COLORFGBG").ok().and_then(|v| is_light_colorfgbg(&v)));
+
+    match detected {
+        Some(true) => Background::Light,
+        Some(false) => Background::Dark,
+        None => {
+            log::debug!("Could not detect terminal background, assuming dark");
+            Background::Dark
+        }
+    }
+}
+
+/// Relative luminance check on 0.0–1.0 channels
+fn is_light_rgb(r: f64, g: f64, b: f64) -> bool {
+    0.2126 * r + 0.7152 * g + 0.0722 * b > 0.5
+}
+
+/// `$COLORFGBG` is "fg;bg" (or "fg;default;bg"); the background is the last
+/// field, an ANSI color index where 7 and 9–15 are light.
+fn is_light_colorfgbg(value: &str) -> Option<bool> {
+    let bg: u8 = value.rsplit(';').next()?.parse().ok()?;
+    Some(bg == 7 || (9..=15).contains(&bg))
+}
+
+/// Parse an OSC 11 reply: `ESC ] 11 ; rgb:RRRR/GGGG/BBBB` terminated by BEL
+/// or ST. Each channel has 1–4 hex digits.
+fn parse_osc11_reply(reply: &str) -> Option<(f64, f64, f64)> {
+    let start = reply.find("rgb:")? + 4;
+    let body = reply[start..].trim_end_matches(['\x07', '\x1b', '\\']);
+    let mut channels = body.split('/').map(|c| {
+        let max = 16f64.powi(c.len() as i32) - 1.0;
+        u32::from_str_radix(c, 16).ok().map(|v| v as f64 / max)
+    });
+    let r = channels.next()??;
+    let g = channels.next()??;
+    let b = channels.next()??;
+    Some((r, g, b))
+}
+
+/// Ask the terminal for its background color with OSC 11
+#[cfg(unix)]
+fn query_background_color() -> Option<(f64, f64, f64)> {
+    use std::io::Write;
+    use std::time::Instant;
+
+    let mut stdout = std::io::stdout();
+    stdout.write_all(b"\x1b]11;?\x1b\\").ok()?;
+    stdout.flush().ok()?;
+
+    let deadline = Instant::now() + OSC_QUERY_TIMEOUT;
+    let mut reply = Vec::new();
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let mut fds = libc::pollfd {
+            fd: libc::STDIN_FILENO,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        // SAFETY: `fds` is a valid pollfd for the duration of the call
+        let ready = unsafe { libc::poll(&mut fds, 1, remaining.as_millis() as libc::c_int) };
+        if ready <= 0 {
+            break;
+        }
+
+        let mut buf = [0u8; 64];
+        // SAFETY: reading into a stack buffer of the given length
+        let n = unsafe { libc::read(libc::STDIN_FILENO, buf.as_mut_ptr().cast(), buf.len()) };
+        if n <= 0 {
+            break;
+        }
+        reply.extend_from_slice(&buf[..n as usize]);
+        if reply.ends_with(b"\x07") || reply.ends_with(b"\x1b\\") {
+            break;
+        }
+    }
+
+    parse_osc11_reply(&String::from_utf8_lossy(&reply))
+}
+
+#[cfg(not(unix))]
+fn query_background_color() -> Option<(f64, f64, f64)> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_osc11_reply() {
+        let (r, g, b) = parse_osc11_reply("\x1b]11;rgb:ffff/ffff/ffff\x1b\\").unwrap();
+        assert!(is_light_rgb(r, g, b));
+
+        let (r, g, b) = parse_osc11_reply("\x1b]11;rgb:1e1e/1e1e/2e2e\x07").unwrap();
+        assert!(!is_light_rgb(r, g, b));
+
+        // Short channel form
+        let (r, _, _) = parse_osc11_reply("\x1b]11;rgb:f/0/0\x07").unwrap();
+        assert_eq!(r, 1.0);
+
+        assert!(parse_osc11_reply("garbage").is_none());
+    }
+
+    #[test]
+    fn test_colorfgbg() {
+        assert_eq!(is_light_colorfgbg("15;0"), Some(false));
+        assert_eq!(is_light_colorfgbg("0;15"), Some(true));
+        assert_eq!(is_light_colorfgbg("0;default;7"), Some(true));
+        assert_eq!(is_light_colorfgbg("nonsense"), None);
+    }
+
+    #[test]
+    fn test_explicit_background_not_detected() {
+        assert_eq!(detect_background(Background::Light), Background::Light);
+        assert_eq!(Palette::for_background(Background::Light), Palette::light());
+        assert_eq!(Palette::for_background(Background::Auto), Palette::dark());
+    }
+}
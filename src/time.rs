@@ -0,0 +1,98 @@
+//! Relative time formatting for the UI: "just now", "5m ago", "3d ago", and
+//! rough runtimes: "40s", "3m".
+
+/// Ages below this are shown as "just now"
+const JUST_NOW_SECS: i64 = 10;
+
+const MINUTE: i64 = 60;
+const HOUR: i64 = 60 * MINUTE;
+const DAY: i64 = 24 * HOUR;
+/// From this age on, show the date instead of a day count
+const YEAR: i64 = 365 * DAY;
+
+/// Age of `epoch` (unix seconds) relative to the current time
+pub fn format_relative(epoch: i64) -> String {
+    format_relative_at(epoch, chrono::Local::now().timestamp())
+}
+
+/// Age of `epoch` relative to `now`, both unix seconds. Units are truncated
+/// ("1h ago" until two hours have passed); timestamps in the future, e.g.
+/// from clock skew between machines, count as "just now". Anything older
+/// than a year is shown as a local date.
+pub fn format_relative_at(epoch: i64, now: i64) -> String {
+    let age = now.saturating_sub(epoch);
+    if age < JUST_NOW_SECS {
+        "just now".to_string()
+    } else if age < MINUTE {
+        format!("{}s ago", age)
+    } else if age < HOUR {
+        format!("{}m ago", age / MINUTE)
+    } else if age < DAY {
+        format!("{}h ago", age / HOUR)
+    } else if age < YEAR {
+        format!("{}d ago", age / DAY)
+    } else {
+        chrono::DateTime::from_timestamp(epoch, 0)
+            .map(|t| t.with_timezone(&chrono::Local).format("%Y-%m-%d").to_string())
+            .unwrap_or_else(|| "long ago".to_string())
+    }
+}
+
+/// A runtime rounded down to its largest unit: "40s", "3m", "2h". Under a
+/// second is "0s".
+pub fn format_duration(duration: std::time::Duration) -> String {
+    let secs = i64::try_from(duration.as_secs()).unwrap_or(i64::MAX);
+    if secs < MINUTE {
+        format!("{}s", secs)
+    } else if secs < HOUR {
+        format!("{}m", secs / MINUTE)
+    } else {
+        format!("{}h", secs / HOUR)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: i64 = 1_700_000_000;
+
+    #[test]
+    fn test_seconds() {
+        assert_eq!(format_relative_at(NOW, NOW), "just now");
+        assert_eq!(format_relative_at(NOW - 9, NOW), "just now");
+        assert_eq!(format_relative_at(NOW - 10, NOW), "10s ago");
+        assert_eq!(format_relative_at(NOW - 59, NOW), "59s ago");
+        // Future timestamps don't produce negative ages
+        assert_eq!(format_relative_at(NOW + 300, NOW), "just now");
+    }
+
+    #[test]
+    fn test_minutes_and_hours() {
+        assert_eq!(format_relative_at(NOW - 60, NOW), "1m ago");
+        assert_eq!(format_relative_at(NOW - 119, NOW), "1m ago");
+        assert_eq!(format_relative_at(NOW - HOUR + 1, NOW), "59m ago");
+        assert_eq!(format_relative_at(NOW - HOUR, NOW), "1h ago");
+        assert_eq!(format_relative_at(NOW - DAY + 1, NOW), "23h ago");
+    }
+
+    #[test]
+    fn test_days_and_dates() {
+        assert_eq!(format_relative_at(NOW - DAY, NOW), "1d ago");
+        assert_eq!(format_relative_at(NOW - 45 * DAY, NOW), "45d ago");
+        assert_eq!(format_relative_at(NOW - YEAR + 1, NOW), "364d ago");
+
+        let old = format_relative_at(NOW - 2 * YEAR, NOW);
+        assert_eq!(old.len(), "2021-11-14".len());
+        assert!(old.starts_with("2021-"));
+    }
+
+    #[test]
+    fn test_format_duration() {
+        use std::time::Duration;
+        assert_eq!(format_duration(Duration::from_millis(900)), "0s");
+        assert_eq!(format_duration(Duration::from_secs(59)), "59s");
+        assert_eq!(format_duration(Duration::from_secs(150)), "2m");
+        assert_eq!(format_duration(Duration::from_secs(2 * 3600 + 59 * 60)), "2h");
+    }
+}
@@ -7,20 +7,28 @@ use futures::StreamExt;
 use ratatui::{
     Terminal,
     backend::CrosstermBackend,
-    layout::{Constraint, Direction, Layout},
+    layout::{Constraint, Layout, Rect},
     style::{Color, Modifier, Style},
     text::{Line, Span, Text},
-    widgets::{Block, Borders, List, ListItem, Paragraph},
+    widgets::{Block, Borders, Clear, List, ListItem, Paragraph},
 };
-use std::collections::{HashMap, VecDeque};
-use std::io;
-use std::time::Instant;
-
-use crate::config::Config;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use crate::arg_picker::ArgPicker;
+use crate::config::{BoxStyle, Config, EmptyEnter, EscAction, InputPosition, LongLineMode, ParallelStyle, SuggestionDisplay};
+use crate::command_policy::CommandPolicy;
+use crate::cwd_policy::CwdPolicy;
 use crate::keymap;
+use crate::output_pipe::OutputPipe;
+use crate::pager::Pager;
 use crate::runner::{OutputMessage, TaskRunner};
 use crate::searcher::HistorySearcher;
+use crate::settings::{Setting, SettingsView};
 use crate::suggest::{Suggestion, SuggestionEngine};
+use crate::theme::Palette;
 
 // --- Byte-aware cursor helpers ---
 
@@ -66,940 +74,6525 @@ fn find_next_word_boundary(s: &str, pos: usize) -> usize {
     pos + (after.len() - after_ws.len())
 }
 
-fn extract_first_word(text: &str) -> &str {
-    let after_leading_ws = text.trim_start();
-    let word_end = after_leading_ws
-        .find(char::is_whitespace)
-        .unwrap_or(after_leading_ws.len());
-    let total_bytes = (text.len() - after_leading_ws.len()) + word_end;
-    &text[..total_bytes]
+/// The quote left open at the end of `text`, if any
+fn open_quote(text: &str) -> Option<char> {
+    let mut quote = None;
+    let mut escaped = false;
+    for c in text.chars() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match (quote, c) {
+            (Some('\''), '\'') => quote = None,
+            (Some('\''), _) => {}
+            (_, '\\') => escaped = true,
+            (Some('"'), '"') => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(c),
+            _ => {}
+        }
+    }
+    quote
 }
 
-// Output display settings — configured via Config, stored in App.
-
-/// A single line of output from a running task
-pub struct OutputLine {
-    pub runner_label: String,
-    pub stream: crate::runner::StreamType,
-    pub content: String,
+/// Leading whitespace plus the next shell token of `text`. Whitespace inside
+/// quotes or after a backslash doesn't end the token, so `"fix bug"` comes
+/// out whole. `quote` is the quote already open where `text` starts, e.g.
+/// when the input ends in `-m "fi`.
+fn extract_first_word(text: &str, mut quote: Option<char>) -> &str {
+    let mut escaped = false;
+    let mut in_token = quote.is_some();
+    for (i, c) in text.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match (quote, c) {
+            (Some('\''), '\'') => quote = None,
+            (Some('\''), _) => {}
+            (_, '\\') => escaped = true,
+            (Some('"'), '"') => quote = None,
+            (Some(_), _) => {}
+            (None, c) if c.is_whitespace() => {
+                if in_token {
+                    return &text[..i];
+                }
+                continue;
+            }
+            (None, '\'' | '"') => quote = Some(c),
+            _ => {}
+        }
+        in_token = true;
+    }
+    text
 }
 
-pub struct App {
-    input: String,
-    output: VecDeque<OutputLine>,
-    scroll_offset: usize,
-    auto_scroll: bool,
-    cursor_position: usize,
-    searcher: HistorySearcher,
-    suggestion_engine: SuggestionEngine,
-    suggestions: Vec<Suggestion>,
-    selected_suggestion: usize,
-    last_quit_press: Option<Instant>,
-    /// Track when each task started for runtime display
-    task_start_times: HashMap<crate::runner::TaskId, Instant>,
-    /// Buffered output for parallel tasks (flushed on completion)
-    pending_output: HashMap<crate::runner::TaskId, Vec<OutputLine>>,
-    /// Parallel run progress: (completed, total). Reset on each new parallel submission.
-    parallel_progress: Option<(usize, usize)>,
-    // --- Config values ---
-    max_output_lines: usize,
-    box_pad_h: usize,
-    box_pad_v: usize,
+// --- ANSI-aware width helpers ---
+
+/// Total display width of a styled line
+fn line_width(line: &Line) -> usize {
+    line.spans
+        .iter()
+        .map(|s| unicode_width::UnicodeWidthStr::width(s.content.as_ref()))
+        .sum()
 }
 
-impl App {
-    pub fn new(searcher: HistorySearcher, suggestion_engine: SuggestionEngine, config: &Config) -> Self {
-        Self {
-            input: String::new(),
-            output: VecDeque::new(),
-            scroll_offset: 0,
-            auto_scroll: true,
-            cursor_position: 0,
-            searcher,
-            suggestion_engine,
-            suggestions: Vec::new(),
-            selected_suggestion: 0,
-            last_quit_press: None,
-            task_start_times: HashMap::new(),
-            pending_output: HashMap::new(),
-            parallel_progress: None,
-            max_output_lines: config.output.max_lines,
-            box_pad_h: config.output.box_padding_horizontal,
-            box_pad_v: config.output.box_padding_vertical,
+/// Split a styled line at the given display width.
+/// Returns the head (at most `width` columns) and the remainder, if any.
+/// A span straddling the cut is split in two, each half keeping the span's style;
+/// a wide character that would cross the boundary moves to the remainder.
+fn split_line_at_width(line: Line<'static>, width: usize) -> (Line<'static>, Option<Line<'static>>) {
+    let mut head: Vec<Span<'static>> = Vec::new();
+    let mut tail: Vec<Span<'static>> = Vec::new();
+    let mut used = 0;
+
+    for span in line.spans {
+        if !tail.is_empty() {
+            tail.push(span);
+            continue;
         }
-    }
 
-    // --- Read accessors ---
+        let span_width = unicode_width::UnicodeWidthStr::width(span.content.as_ref());
+        if used + span_width <= width {
+            used += span_width;
+            head.push(span);
+            continue;
+        }
 
-    pub fn input(&self) -> &str {
-        &self.input
+        // This span straddles the cut point
+        let mut cut = span.content.len();
+        for (i, c) in span.content.char_indices() {
+            let w = unicode_width::UnicodeWidthChar::width(c).unwrap_or(0);
+            if used + w > width {
+                cut = i;
+                break;
+            }
+            used += w;
+        }
+        let (left, right) = span.content.split_at(cut);
+        if !left.is_empty() {
+            head.push(Span::styled(left.to_string(), span.style));
+        }
+        tail.push(Span::styled(right.to_string(), span.style));
     }
 
-    pub fn cursor_position(&self) -> usize {
-        self.cursor_position
-    }
+    let rest = if tail.is_empty() {
+        None
+    } else {
+        Some(Line::from(tail).style(line.style))
+    };
+    (Line::from(head).style(line.style), rest)
+}
 
-    pub fn output(&self) -> &VecDeque<OutputLine> {
-        &self.output
-    }
+/// Exit code shells use when the program doesn't exist
+const COMMAND_NOT_FOUND: u32 = 127;
 
-    pub fn scroll_offset(&self) -> usize {
-        self.scroll_offset
-    }
+/// Output title spinner while tasks run, and its fallback for `box_style`s
+/// other than unicode, where braille may not render either
+const SPINNER_FRAMES: [&str; 10] = ["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
+const SPINNER_FRAMES_ASCII: [&str; 4] = ["|", "/", "-", "\\"];
+/// How long each spinner frame is shown
+const SPINNER_INTERVAL: Duration = Duration::from_millis(100);
 
-    pub fn has_suggestions(&self) -> bool {
-        !self.suggestions.is_empty()
-    }
+/// Suggestions that usually take at least this long show their typical runtime
+const SLOW_COMMAND: Duration = Duration::from_secs(10);
 
-    pub fn suggestions(&self) -> &[Suggestion] {
-        &self.suggestions
-    }
+/// Appended to output lines cut at `[output] max_line_length`
+const TRUNCATED_MARKER: &str = "… (truncated)";
 
-    /// Consume the App and return the HistorySearcher for shutdown flush
-    pub fn into_searcher(self) -> HistorySearcher {
-        self.searcher
+/// Cut `content` to at most `max_len` bytes (on a char boundary) and mark it.
+/// Styling is reset before the marker in case the cut fell inside a colored
+/// span. A `max_len` of 0 disables the cap.
+fn cap_line_length(mut content: String, max_len: usize) -> String {
+    if max_len == 0 || content.len() <= max_len {
+        return content;
     }
-
-    // --- Input editing ---
-
-    pub fn insert_char(&mut self, c: char) {
-        self.input.insert(self.cursor_position, c);
-        self.cursor_position += c.len_utf8();
-        self.update_suggestions();
+    let mut end = max_len;
+    while !content.is_char_boundary(end) {
+        end -= 1;
     }
-
-    pub fn delete_char_backward(&mut self) {
-        if self.cursor_position > 0 {
-            let prev = prev_char_pos(&self.input, self.cursor_position);
-            self.input.remove(prev);
-            self.cursor_position = prev;
-            self.update_suggestions();
-        }
+    content.truncate(end);
+    if content.contains('\x1b') {
+        content.push_str("\x1b[0m");
     }
+    content.push(' ');
+    content.push_str(TRUNCATED_MARKER);
+    content
+}
 
-    pub fn delete_char_forward(&mut self) {
-        if self.cursor_position < self.input.len() {
-            self.input.remove(self.cursor_position);
-            self.update_suggestions();
-        }
+/// `text` cut to at most `width` columns, ending in `…` when cut
+fn truncate_to_width(text: &str, width: usize) -> std::borrow::Cow<'_, str> {
+    use unicode_width::UnicodeWidthChar;
+    if unicode_width::UnicodeWidthStr::width(text) <= width {
+        return text.into();
     }
-
-    pub fn delete_word_backward(&mut self) {
-        let word_start = find_prev_word_boundary(&self.input, self.cursor_position);
-        if word_start < self.cursor_position {
-            self.input.drain(word_start..self.cursor_position);
-            self.cursor_position = word_start;
-            self.update_suggestions();
+    let mut out = String::new();
+    let mut used = 0;
+    for c in text.chars() {
+        let w = c.width().unwrap_or(0);
+        if used + w + 1 > width {
+            break;
         }
+        out.push(c);
+        used += w;
     }
-
-    pub fn delete_word_forward(&mut self) {
-        let word_end = find_next_word_boundary(&self.input, self.cursor_position);
-        if word_end > self.cursor_position {
-            self.input.drain(self.cursor_position..word_end);
-            self.update_suggestions();
-        }
+    if width > 0 {
+        out.push('…');
     }
+    out.into()
+}
 
-    pub fn delete_to_line_start(&mut self) {
-        if self.cursor_position > 0 {
-            self.input.drain(..self.cursor_position);
-            self.cursor_position = 0;
-            self.update_suggestions();
+/// Fit a styled line into `width` columns, either truncating it or wrapping it
+/// onto multiple rows. Wrapped rows after the first are indented by `indent`
+/// spaces, leaving at least one column for content.
+fn fit_line(line: Line<'static>, width: usize, mode: LongLineMode, indent: usize) -> Vec<Line<'static>> {
+    if line_width(&line) <= width {
+        return vec![line];
+    }
+    match mode {
+        LongLineMode::Truncate => vec![split_line_at_width(line, width).0],
+        LongLineMode::Wrap => {
+            let indent = indent.min(width.saturating_sub(1));
+            let mut rows = Vec::new();
+            let mut remaining = Some(line);
+            while let Some(current) = remaining {
+                let continued = !rows.is_empty();
+                let room = if continued { width - indent } else { width };
+                let (mut head, rest) = split_line_at_width(current, room);
+                if head.spans.is_empty() {
+                    // Box is narrower than the next character — nothing more fits
+                    break;
+                }
+                if continued && indent > 0 {
+                    head.spans.insert(0, Span::raw(" ".repeat(indent)));
+                }
+                rows.push(head);
+                remaining = rest;
+            }
+            if rows.is_empty() {
+                rows.push(Line::default());
+            }
+            rows
         }
     }
+}
 
-    pub fn delete_to_line_end(&mut self) {
-        if self.cursor_position < self.input.len() {
-            self.input.truncate(self.cursor_position);
-            self.update_suggestions();
+/// Characters for output box borders and command separators.
+/// Every character is one column wide in all styles.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct BoxChars {
+    top_left: &'static str,
+    top_right: &'static str,
+    bottom_left: &'static str,
+    bottom_right: &'static str,
+    horizontal: &'static str,
+    vertical: &'static str,
+    /// Marks a folded box
+    folded: &'static str,
+}
+
+impl BoxChars {
+    fn for_style(style: BoxStyle) -> Self {
+        match style {
+            BoxStyle::Unicode => Self {
+                top_left: "┌",
+                top_right: "┐",
+                bottom_left: "└",
+                bottom_right: "┘",
+                horizontal: "─",
+                vertical: "│",
+                folded: "▸",
+            },
+            BoxStyle::Ascii => Self {
+                top_left: "+",
+                top_right: "+",
+                bottom_left: "+",
+                bottom_right: "+",
+                horizontal: "-",
+                vertical: "|",
+                folded: ">",
+            },
+            // Blanks keep the layout (and the width math) of the other styles
+            BoxStyle::None => Self {
+                top_left: " ",
+                top_right: " ",
+                bottom_left: " ",
+                bottom_right: " ",
+                horizontal: " ",
+                vertical: " ",
+                folded: ">",
+            },
         }
     }
+}
 
-    // --- Cursor movement ---
+/// How output lines are drawn: box characters, colors and long-line handling
+#[derive(Clone, Copy)]
+struct OutputLook {
+    chars: BoxChars,
+    border: Style,
+    separator: Style,
+    box_pad_h: usize,
+    long_lines: LongLineMode,
+    /// Indent of wrapped continuation rows
+    wrap_indent: usize,
+    line_timestamps: bool,
+    /// `[output] show_command`: box headers show the command after the label
+    show_command: bool,
+}
 
-    pub fn move_cursor_left(&mut self) {
-        if self.cursor_position > 0 {
-            self.cursor_position = prev_char_pos(&self.input, self.cursor_position);
-        }
+/// Largest count typed before a scroll key with the output focused
+const MAX_COUNT: usize = 9999;
+
+/// Width of the `HH:MM:SS ` gutter drawn by `[output] line_timestamps`
+const TIMESTAMP_GUTTER: usize = 9;
+
+/// The timestamp gutter for the first screen row of `line`; continuation
+/// rows and lines without a time get a blank one so content stays aligned
+fn timestamp_gutter(line: &OutputLine, first_row: bool) -> Span<'static> {
+    match line.received.filter(|_| first_row) {
+        Some(time) => Span::styled(
+            format!("{} ", time.format("%H:%M:%S")),
+            Style::default().add_modifier(Modifier::DIM),
+        ),
+        None => Span::raw(" ".repeat(TIMESTAMP_GUTTER)),
     }
+}
 
-    pub fn move_cursor_right(&mut self) {
-        if self.cursor_position < self.input.len() {
-            self.cursor_position = next_char_pos(&self.input, self.cursor_position);
+/// Screen rows for one output line drawn `width` columns wide, the first
+/// one reversed if the line is the selected command
+fn render_output_line(line: &OutputLine, width: usize, look: &OutputLook) -> Vec<Line<'static>> {
+    let mut rows = render_line_rows(line, width, look);
+    if line.selected
+        && let Some(first) = rows.first_mut()
+    {
+        for span in &mut first.spans {
+            span.style = span.style.add_modifier(Modifier::REVERSED);
         }
     }
+    rows
+}
 
-    pub fn move_cursor_word_left(&mut self) {
-        self.cursor_position = find_prev_word_boundary(&self.input, self.cursor_position);
+/// Screen rows for one output line drawn `width` columns wide. Box borders
+/// and box content fill the width exactly, so boxes can sit side by side.
+fn render_line_rows(line: &OutputLine, width: usize, look: &OutputLook) -> Vec<Line<'static>> {
+    let OutputLook {
+        chars,
+        border: border_style,
+        separator,
+        box_pad_h,
+        long_lines,
+        wrap_indent,
+        line_timestamps,
+        show_command,
+    } = *look;
+
+    // Box drawing for parallel output blocks
+    // 1 char inner padding on each side: │  content  │
+
+    if line.folded
+        && let Some(label) = line.runner_label.strip_prefix("\x00top:")
+    {
+        let label = box_title(line, label, show_command);
+        let label = label.as_str();
+        // One-line header standing in for the whole box: ▸ [n=1] ───── 12 lines · 1.2s ─
+        let right = format!(" {} {}", line.content, chars.horizontal);
+        let room = width.saturating_sub(unicode_width::UnicodeWidthStr::width(right.as_str()) + 3);
+        let left = if label.is_empty() {
+            format!("{} ", chars.folded)
+        } else {
+            format!("{} {} ", chars.folded, truncate_to_width(label, room))
+        };
+        let fill_len = width
+            .saturating_sub(unicode_width::UnicodeWidthStr::width(left.as_str()))
+            .saturating_sub(unicode_width::UnicodeWidthStr::width(right.as_str()));
+
+        return vec![Line::from(vec![
+            Span::styled(left, border_style),
+            Span::styled(chars.horizontal.repeat(fill_len), border_style),
+            Span::styled(right, border_style),
+        ])];
     }
 
-    pub fn move_cursor_word_right(&mut self) {
-        self.cursor_position = find_next_word_boundary(&self.input, self.cursor_position);
+    if let Some(label) = line.runner_label.strip_prefix("\x00top:") {
+        let label = box_title(line, label, show_command);
+        let label = label.as_str();
+        let right = format!("{}{}", chars.horizontal, chars.top_right);
+        let right_w = unicode_width::UnicodeWidthStr::width(right.as_str());
+        let left = if label.is_empty() {
+            chars.top_left.to_string()
+        } else {
+            let room = width.saturating_sub(right_w + 4);
+            format!("{}{} {} ", chars.top_left, chars.horizontal, truncate_to_width(label, room))
+        };
+        let left_w = unicode_width::UnicodeWidthStr::width(left.as_str());
+        let fill_len = width.saturating_sub(left_w).saturating_sub(right_w);
+        let fill: String = chars.horizontal.repeat(fill_len);
+
+        return vec![Line::from(vec![
+            Span::styled(left, border_style),
+            Span::styled(fill, border_style),
+            Span::styled(right, border_style),
+        ])];
     }
 
-    pub fn move_cursor_home(&mut self) {
-        self.cursor_position = 0;
+    if line.runner_label == "\x00bot" {
+        let left = chars.bottom_left;
+        let left_w = unicode_width::UnicodeWidthStr::width(left);
+
+        let right = if line.content.is_empty() {
+            format!("{}{}", chars.horizontal, chars.bottom_right)
+        } else {
+            format!(" {} {}{}", line.content, chars.horizontal, chars.bottom_right)
+        };
+        let right_w = unicode_width::UnicodeWidthStr::width(right.as_str());
+
+        let fill_len = width.saturating_sub(left_w).saturating_sub(right_w);
+        let fill: String = chars.horizontal.repeat(fill_len);
+
+        return vec![Line::from(vec![
+            Span::styled(left, border_style),
+            Span::styled(fill, border_style),
+            Span::styled(right, border_style),
+        ])];
     }
 
-    pub fn move_cursor_end(&mut self) {
-        self.cursor_position = self.input.len();
+    if let Some(label) = line.runner_label.strip_prefix("\x00row:") {
+        // Table row of a one-line parallel task: [n=1]  │ output
+        let content = output_content_lines(line).into_iter().next().unwrap_or_default();
+        let prefix = format!("{} {} ", label, chars.vertical);
+        let gutter = if line_timestamps { TIMESTAMP_GUTTER } else { 0 };
+        let prefix_w = unicode_width::UnicodeWidthStr::width(prefix.as_str()) + gutter;
+
+        return fit_line(content, width.saturating_sub(prefix_w), long_lines, wrap_indent)
+            .into_iter()
+            .enumerate()
+            .map(|(row, l)| {
+                let mut spans = Vec::new();
+                if line_timestamps {
+                    spans.push(timestamp_gutter(line, row == 0));
+                }
+                if row == 0 {
+                    spans.push(Span::styled(prefix.clone(), border_style));
+                } else {
+                    spans.push(Span::raw(" ".repeat(prefix_w - gutter)));
+                }
+                spans.extend(l.spans);
+                Line::from(spans)
+            })
+            .collect();
     }
 
-    /// Accept the next word from the suggestion preview (Right arrow at end of input)
-    pub fn accept_next_preview_word(&mut self) {
-        if self.cursor_position == self.input.len() {
-            if let Some(preview) = self.get_suggestion_preview() {
-                let next_word = extract_first_word(&preview);
-                if !next_word.is_empty() {
-                    self.input.push_str(next_word);
-                    self.cursor_position = self.input.len();
-                    self.update_suggestions();
+    if line.runner_label == "\x00box" {
+        let content_lines = output_content_lines(line);
+
+        // Inner width: width minus "│" + pad on each side + "│", and the
+        // timestamp gutter just inside the left border
+        let gutter = if line_timestamps { TIMESTAMP_GUTTER } else { 0 };
+        let inner_width = width.saturating_sub(2 + box_pad_h * 2 + gutter);
+        let h_pad = " ".repeat(box_pad_h);
+
+        return content_lines
+            .into_iter()
+            .flat_map(|l| fit_line(l, inner_width, long_lines, wrap_indent))
+            .enumerate()
+            .map(|(row, l)| {
+                let pad = inner_width.saturating_sub(line_width(&l));
+
+                let mut spans = vec![
+                    Span::styled(chars.vertical, border_style),
+                    Span::raw(h_pad.clone()),
+                ];
+                if line_timestamps {
+                    spans.push(timestamp_gutter(line, row == 0));
+                }
+                spans.extend(l.spans);
+                spans.push(Span::raw(" ".repeat(pad)));
+                spans.push(Span::raw(h_pad.clone()));
+                spans.push(Span::styled(chars.vertical, border_style));
+                Line::from(spans)
+            })
+            .collect();
+    }
+
+    // Regular (non-parallel) rendering
+    match line.stream {
+        crate::runner::StreamType::Status => {
+            // Single command separator
+            let right = format!(" {} ", line.content);
+            let fill_len = width
+                .saturating_sub(1)
+                .saturating_sub(unicode_width::UnicodeWidthStr::width(right.as_str()));
+            let fill: String = chars.horizontal.repeat(fill_len);
+
+            vec![Line::from(vec![
+                Span::raw(" "),
+                Span::styled(fill, separator),
+                Span::styled(right, separator),
+            ])]
+        }
+        crate::runner::StreamType::Output => {
+            let gutter = if line_timestamps && line.received.is_some() { TIMESTAMP_GUTTER } else { 0 };
+            let mut lines: Vec<Line<'static>> = output_content_lines(line)
+                .into_iter()
+                .flat_map(|l| fit_line(l, width.saturating_sub(gutter), long_lines, wrap_indent))
+                .collect();
+            if gutter > 0 {
+                for (row, l) in lines.iter_mut().enumerate() {
+                    l.spans.insert(0, timestamp_gutter(line, row == 0));
                 }
             }
+            lines
         }
     }
+}
 
-    // --- Command submission ---
+/// Styled rows of a line of task output. Links are left as markers for
+/// `hyperlink::take_links`.
+fn content_lines(content: &str) -> Vec<Line<'static>> {
+    use ansi_to_tui::IntoText;
+    let content = crate::hyperlink::mark_links(content);
+    match content.as_bytes().into_text() {
+        Ok(text) => text.lines,
+        Err(_) => vec![Line::from(content.into_owned())],
+    }
+}
 
-    /// Submit the current input. Returns true if the app should quit (internal commands).
-    pub fn submit_command(&mut self, runner: &mut TaskRunner) -> bool {
-        if self.input.is_empty() {
-            return false;
+/// `content_lines` of a task's line, with the dim `(×N)` count of lines
+/// collapsed into it after the last row
+fn output_content_lines(line: &OutputLine) -> Vec<Line<'static>> {
+    let mut lines = content_lines(&line.content);
+    if line.repeated > 0 {
+        let count = Span::styled(repeat_count(line), Style::default().add_modifier(Modifier::DIM));
+        match lines.last_mut() {
+            Some(last) => last.spans.push(count),
+            None => lines.push(Line::from(count)),
         }
+    }
+    lines
+}
 
-        let trimmed = self.input.trim();
+/// ` (×N)` after a line that stands for N identical lines, else nothing
+fn repeat_count(line: &OutputLine) -> String {
+    if line.repeated > 0 { format!(" (×{})", line.repeated + 1) } else { String::new() }
+}
 
-        // Internal commands
-        if trimmed == "exit" || trimmed == "quit" {
-            return true;
+/// Blank columns between boxes drawn side by side
+const GRID_GAP: usize = 1;
+
+/// A unit of the output pane: a single line, or a row of consecutive parallel
+/// boxes drawn side by side under `[output] parallel_columns`. Ranges index
+/// into the shown lines.
+#[derive(Debug, PartialEq)]
+enum OutputItem {
+    Line(usize),
+    Grid(Vec<std::ops::Range<usize>>),
+}
+
+impl OutputItem {
+    /// First shown line covered
+    fn start(&self) -> usize {
+        match self {
+            OutputItem::Line(i) => *i,
+            OutputItem::Grid(boxes) => boxes[0].start,
         }
+    }
 
-        if let Err(e) = self.searcher.record_usage(&self.input) {
-            log::warn!("Failed to record command usage: {}", e);
+    /// Number of shown lines covered
+    fn len(&self) -> usize {
+        match self {
+            OutputItem::Line(_) => 1,
+            OutputItem::Grid(boxes) => boxes.last().map_or(0, |b| b.end) - boxes[0].start,
         }
-        self.suggestion_engine.index_command(&self.input);
+    }
+}
+
+/// Whether `line` opens the box of a parallel task (labelled like `[n=3]`);
+/// single commands and `explain` keep the full width
+fn is_parallel_box_top(line: &OutputLine) -> bool {
+    line.runner_label
+        .strip_prefix("\x00top:")
+        .is_some_and(|label| label.starts_with('['))
+}
 
-        // Check for parallel expansion syntax: [name=range] command {name}
-        if let Some(parsed) = crate::parallel::parse_parallel(trimmed) {
-            let expanded = crate::parallel::expand(&parsed);
-            let total = expanded.len();
-            log::info!("Parallel execution: {} tasks", total);
-            self.parallel_progress = Some((0, total));
-            for cmd in expanded {
-                runner.spawn_labeled(&cmd.command, &cmd.label);
+/// Split the shown lines into items, grouping runs of parallel boxes into
+/// rows of `columns`
+fn group_output(shown: &[&OutputLine], columns: usize) -> Vec<OutputItem> {
+    // Line range of the complete parallel box starting at `start`
+    let parallel_box = |start: usize| {
+        if !is_parallel_box_top(shown[start]) {
+            return None;
+        }
+        let bottom = shown[start + 1..]
+            .iter()
+            .position(|l| l.runner_label == "\x00bot" || l.runner_label.starts_with("\x00top:"))?;
+        let end = start + 1 + bottom;
+        (shown[end].runner_label == "\x00bot").then_some(start..end + 1)
+    };
+
+    let mut items = Vec::new();
+    let mut i = 0;
+    while i < shown.len() {
+        let mut boxes = Vec::new();
+        if columns > 1 {
+            while boxes.len() < columns
+                && i < shown.len()
+                && let Some(range) = parallel_box(i)
+            {
+                i = range.end;
+                boxes.push(range);
             }
+        }
+        if boxes.is_empty() {
+            items.push(OutputItem::Line(i));
+            i += 1;
         } else {
-            runner.spawn_labeled(&self.input, "");
+            items.push(OutputItem::Grid(boxes));
         }
-
-        self.input.clear();
-        self.cursor_position = 0;
-        // Reset scroll to bottom so new output is visible
-        self.auto_scroll = true;
-        self.scroll_to_bottom();
-        false
     }
+    items
+}
 
-    /// Receive output from a running task and append to the output buffer.
-    /// All tasks are buffered per-task and flushed as boxed blocks on completion.
-    pub fn push_output(&mut self, msg: OutputMessage) {
-        if msg.stream == crate::runner::StreamType::Status {
-            if msg.content == "started" {
-                self.task_start_times.insert(msg.task_id, Instant::now());
-                return;
-            }
-
-            // Task completed -- compute runtime
-            let runtime = self
-                .task_start_times
-                .remove(&msg.task_id)
-                .map(|start| {
-                    let dur = start.elapsed();
-                    if dur.as_secs() >= 60 {
-                        format!(
-                            "{}m{:.1}s",
-                            dur.as_secs() / 60,
-                            dur.as_secs_f64() % 60.0
-                        )
-                    } else {
-                        format!("{:.2}s", dur.as_secs_f64())
+/// Rows for one item: a line as usual, or its boxes `columns`-to-a-row in
+/// equal widths, shorter boxes padded below
+fn render_item(
+    item: &OutputItem,
+    shown: &[&OutputLine],
+    width: usize,
+    columns: usize,
+    look: &OutputLook,
+) -> Vec<Line<'static>> {
+    let boxes = match item {
+        OutputItem::Line(i) => return render_output_line(shown[*i], width, look),
+        OutputItem::Grid(boxes) => boxes,
+    };
+    let column_width = width.saturating_sub(GRID_GAP * (columns - 1)) / columns;
+    let rendered: Vec<Vec<Line<'static>>> = boxes
+        .iter()
+        .map(|range| {
+            shown[range.clone()]
+                .iter()
+                .flat_map(|line| render_output_line(line, column_width, look))
+                .collect()
+        })
+        .collect();
+    let height = rendered.iter().map(Vec::len).max().unwrap_or(0);
+    let gap = " ".repeat(GRID_GAP);
+
+    (0..height)
+        .map(|row| {
+            let mut spans = Vec::new();
+            for (column, lines) in rendered.iter().enumerate() {
+                if column > 0 {
+                    spans.push(Span::raw(gap.clone()));
+                }
+                match lines.get(row) {
+                    Some(line) => {
+                        // A label wider than the column would push the next box over
+                        let line = split_line_at_width(line.clone(), column_width).0;
+                        let pad = column_width.saturating_sub(line_width(&line));
+                        spans.extend(line.spans);
+                        spans.push(Span::raw(" ".repeat(pad)));
                     }
-                })
-                .unwrap_or_default();
-
-            // Top border: ┌─ [n=1] ─┐ or ┌──────────┐ (no label for single commands)
-            let top_label = if msg.runner_label.is_empty() {
-                String::new()
-            } else {
-                msg.runner_label.clone()
-            };
-            self.append_output(OutputLine {
-                runner_label: format!("\x00top:{}", top_label),
-                stream: crate::runner::StreamType::Status,
-                content: String::new(),
-            });
-
-            // Top padding
-            for _ in 0..self.box_pad_v {
-                self.append_output(OutputLine {
-                    runner_label: "\x00box".to_string(),
-                    stream: crate::runner::StreamType::Output,
-                    content: String::new(),
-                });
-            }
-
-            // Flush buffered content lines
-            if let Some(buffered) = self.pending_output.remove(&msg.task_id) {
-                for mut line in buffered {
-                    line.runner_label = "\x00box".to_string();
-                    self.append_output(line);
+                    None => spans.push(Span::raw(" ".repeat(column_width))),
                 }
             }
+            Line::from(spans)
+        })
+        .collect()
+}
 
-            // Bottom padding
-            for _ in 0..self.box_pad_v {
-                self.append_output(OutputLine {
-                    runner_label: "\x00box".to_string(),
-                    stream: crate::runner::StreamType::Output,
-                    content: String::new(),
-                });
+/// The visible rows when parallel boxes are laid out in columns. A grid
+/// row holds more lines than it has rows, so the view is built from items:
+/// the last `height` rows when following the output, otherwise rows from
+/// the item holding `scroll_offset` on, entering a grid proportionally.
+fn grid_rows(
+    shown: &[&OutputLine],
+    columns: usize,
+    width: usize,
+    height: usize,
+    scroll_offset: usize,
+    follow: bool,
+    look: &OutputLook,
+) -> Vec<Line<'static>> {
+    let items = group_output(shown, columns);
+    let mut rows: Vec<Line<'static>> = Vec::new();
+
+    if follow {
+        let mut tail: Vec<Vec<Line<'static>>> = Vec::new();
+        let mut count = 0;
+        for item in items.iter().rev() {
+            if count >= height {
+                break;
             }
+            let item_rows = render_item(item, shown, width, columns, look);
+            count += item_rows.len();
+            tail.push(item_rows);
+        }
+        rows.extend(tail.into_iter().rev().flatten());
+        let excess = rows.len().saturating_sub(height);
+        rows.drain(..excess);
+        return rows;
+    }
 
-            // Bottom border with runtime
-            self.append_output(OutputLine {
-                runner_label: "\x00bot".to_string(),
-                stream: crate::runner::StreamType::Status,
-                content: runtime,
-            });
-
-            // Update parallel progress if active
-            if let Some((ref mut completed, _)) = self.parallel_progress {
-                *completed += 1;
-            }
-        } else {
-            // Buffer output for this task
-            self.pending_output
-                .entry(msg.task_id)
-                .or_default()
-                .push(OutputLine {
-                    runner_label: msg.runner_label,
-                    stream: msg.stream,
-                    content: msg.content,
-                });
+    let first = items
+        .iter()
+        .position(|item| item.start() + item.len() > scroll_offset)
+        .unwrap_or(items.len());
+    for (n, item) in items[first..].iter().enumerate() {
+        let mut item_rows = render_item(item, shown, width, columns, look);
+        if n == 0 {
+            let into = scroll_offset.saturating_sub(item.start());
+            let skip = into * item_rows.len() / item.len().max(1);
+            item_rows.drain(..skip.min(item_rows.len()));
+        }
+        rows.extend(item_rows);
+        if rows.len() >= height {
+            break;
+        }
+    }
+    rows.truncate(height);
+    rows
+}
+
+/// The title in a box's top border: the task's label, then with `[output]
+/// show_command` the command it ran
+fn box_title(line: &OutputLine, label: &str, show_command: bool) -> String {
+    match line.command.as_deref().map(str::trim).filter(|_| show_command) {
+        Some(command) if label.is_empty() => command.to_string(),
+        Some(command) => format!("{} {}", label, command),
+        None => label.to_string(),
+    }
+}
+
+/// Plain-text rendering of an output line for the pager
+fn pager_line(line: &OutputLine, chars: &BoxChars, show_command: bool) -> String {
+    let BoxChars { top_left, bottom_left, horizontal: h, vertical, .. } = chars;
+    if let Some(label) = line.runner_label.strip_prefix("\x00top:") {
+        let label = box_title(line, label, show_command);
+        return if label.is_empty() { top_left.to_string() } else { format!("{}{} {}", top_left, h, label) };
+    }
+    if let Some(label) = line.runner_label.strip_prefix("\x00row:") {
+        return format!("{} {} {}{}", label, vertical, crate::pager::plain_text(&line.content), repeat_count(line));
+    }
+    match line.runner_label.as_str() {
+        "\x00box" => format!("{} {}{}", vertical, crate::pager::plain_text(&line.content), repeat_count(line)),
+        "\x00bot" if line.content.is_empty() => bottom_left.to_string(),
+        "\x00bot" => format!("{}{} {}", bottom_left, h, line.content),
+        _ => match line.stream {
+            crate::runner::StreamType::Status => format!("{}{} {}", h, h, line.content),
+            crate::runner::StreamType::Output => crate::pager::plain_text(&line.content) + &repeat_count(line),
+        },
+    }
+}
+
+/// Draw the pager over the whole screen
+pub fn render_pager(f: &mut ratatui::Frame, pager: &mut Pager, palette: Palette) {
+    let area = f.area();
+    pager.set_height(area.height.saturating_sub(2) as usize);
+
+    let match_style = Style::default().fg(palette.warning);
+    let lines: Vec<Line> = pager
+        .visible()
+        .iter()
+        .map(|line| {
+            if pager.is_match(line) {
+                Line::styled(line.clone(), match_style)
+            } else {
+                Line::raw(line.clone())
+            }
+        })
+        .collect();
+
+    let (first, last, total) = pager.position();
+    let footer = match (pager.search_input(), pager.message()) {
+        (Some(input), _) => format!(" /{} ", input),
+        (None, Some(message)) => format!(" {} ", message),
+        (None, None) => " j/k: scroll, g/G: top/bottom, /: search, n/N: next/prev, q: close ".to_string(),
+    };
+
+    let pager_widget = Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(format!(" {} {}-{} of {} ", pager.title(), first, last, total))
+            .title_bottom(footer)
+            .border_style(Style::default().fg(palette.primary)),
+    );
+    f.render_widget(pager_widget, area);
+}
+
+/// Name of a long-line mode as written in config.toml
+fn long_line_mode_name(mode: LongLineMode) -> &'static str {
+    match mode {
+        LongLineMode::Truncate => "truncate",
+        LongLineMode::Wrap => "wrap",
+    }
+}
+
+/// Settings overlay, centered over the main layout
+fn render_settings(f: &mut ratatui::Frame, app: &App, view: &SettingsView, palette: Palette) {
+    let area = f.area();
+    let width = 52.min(area.width);
+    let height = (Setting::ALL.len() as u16 + 2).min(area.height);
+    let popup = Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    };
+
+    let items: Vec<ListItem> = Setting::ALL
+        .iter()
+        .map(|&setting| {
+            let selected = setting == view.selected();
+            let style = if selected {
+                Style::default().fg(palette.warning).add_modifier(Modifier::BOLD)
+            } else {
+                Style::default().fg(palette.text)
+            };
+            let value = app.setting_value(setting);
+            let value = if selected { format!("◀ {} ▶", value) } else { format!("  {}  ", value) };
+            ListItem::new(Line::from(vec![
+                Span::styled(if selected { "▌" } else { " " }, Style::default().fg(palette.warning)),
+                Span::styled(format!("{:<26}", setting.label()), style),
+                Span::styled(value, style),
+            ]))
+        })
+        .collect();
+
+    let footer = match view.message() {
+        Some(message) => format!(" {} ", message),
+        None => " j/k: select, h/l: change, w: save, q: close ".to_string(),
+    };
+    let list = List::new(items).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(" Settings ")
+            .title_bottom(footer)
+            .border_style(Style::default().fg(palette.primary)),
+    );
+    f.render_widget(Clear, popup);
+    f.render_widget(list, popup);
+}
+
+/// Argument picker overlay, centered over the main layout
+fn render_arg_picker(f: &mut ratatui::Frame, picker: &ArgPicker, palette: Palette) {
+    let area = f.area();
+    let width = 80.min(area.width);
+    let height = (picker.total() as u16 + 2).clamp(3, (area.height * 2 / 3).max(3)).min(area.height);
+    let popup = Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    };
+
+    // Scroll just enough to keep the highlighted entry in view
+    let rows = height.saturating_sub(2) as usize;
+    let skip = (picker.selected() + 1).saturating_sub(rows);
+    let items: Vec<ListItem> = picker
+        .shown()
+        .enumerate()
+        .skip(skip)
+        .take(rows)
+        .map(|(i, (set, count))| {
+            let selected = i == picker.selected();
+            let style = if selected {
+                Style::default().fg(palette.warning).add_modifier(Modifier::BOLD)
+            } else {
+                Style::default().fg(palette.text)
+            };
+            ListItem::new(Line::from(vec![
+                Span::styled(if selected { "▌" } else { " " }, Style::default().fg(palette.warning)),
+                Span::styled(format!("{:>5}  ", count), Style::default().fg(palette.dim)),
+                Span::styled(set.clone(), style),
+            ]))
+        })
+        .collect();
+
+    let footer = if picker.query().is_empty() {
+        " type to filter, ↑/↓: select, Enter: use, Esc: close ".to_string()
+    } else {
+        format!(" > {} ({} of {}) ", picker.query(), picker.shown().len(), picker.total())
+    };
+    let list = List::new(items).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(format!(" Arguments for `{}` ", picker.prefix()))
+            .title_bottom(footer)
+            .border_style(Style::default().fg(palette.primary)),
+    );
+    f.render_widget(Clear, popup);
+    f.render_widget(list, popup);
+}
+
+/// Lines of the output boxes whose label contains `fragment` (case-insensitive).
+/// Unlabeled output (single commands, warnings) is hidden while filtering.
+fn filter_by_label<'a>(output: &'a VecDeque<OutputLine>, fragment: &str) -> Vec<&'a OutputLine> {
+    let fragment = fragment.to_lowercase();
+    let mut in_match = false;
+    output
+        .iter()
+        .filter(|line| {
+            if let Some(label) = line.runner_label.strip_prefix("\x00top:") {
+                in_match = !label.is_empty() && label.to_lowercase().contains(&fragment);
+                return in_match;
+            }
+            if let Some(label) = line.runner_label.strip_prefix("\x00row:") {
+                return label.to_lowercase().contains(&fragment);
+            }
+            match line.runner_label.as_str() {
+                "\x00box" => in_match,
+                "\x00bot" => std::mem::replace(&mut in_match, false),
+                _ => false,
+            }
+        })
+        .collect()
+}
+
+/// The lines of `output` the pane shows: those of boxes whose label matches
+/// `fragment`, if set, with the content of folded boxes left out
+fn shown_output<'a>(output: &'a VecDeque<OutputLine>, fragment: Option<&str>) -> Vec<&'a OutputLine> {
+    let lines = match fragment {
+        Some(fragment) => filter_by_label(output, fragment),
+        None => output.iter().collect(),
+    };
+    let mut in_folded = false;
+    lines
+        .into_iter()
+        .filter(|line| {
+            if line.runner_label.starts_with("\x00top:") {
+                in_folded = line.folded;
+                return true;
+            }
+            match line.runner_label.as_str() {
+                "\x00box" => !in_folded,
+                "\x00bot" => !std::mem::replace(&mut in_folded, false),
+                _ => true,
+            }
+        })
+        .collect()
+}
+
+/// Index range of the complete box (top border through bottom border) in
+/// `output` that contains `index`
+fn box_range_at(output: &VecDeque<OutputLine>, index: usize) -> Option<std::ops::RangeInclusive<usize>> {
+    let is_top = |line: &OutputLine| line.runner_label.starts_with("\x00top:");
+    let top = (0..=index).rev().find(|&i| {
+        is_top(&output[i]) || (i < index && output[i].runner_label == "\x00bot")
+    })?;
+    if !is_top(&output[top]) {
+        return None;
+    }
+    let bot = (top + 1..output.len()).find(|&i| {
+        output[i].runner_label == "\x00bot" || is_top(&output[i])
+    })?;
+    (output[bot].runner_label == "\x00bot" && bot >= index).then_some(top..=bot)
+}
+
+/// One-line summary of a folded box: "12 lines · 1.2s"
+fn fold_summary(box_lines: &[&OutputLine]) -> String {
+    let count = box_lines.iter().filter(|l| l.runner_label == "\x00box").count();
+    let lines = if count == 1 { "1 line".to_string() } else { format!("{} lines", count) };
+    match box_lines.last().map(|l| l.content.as_str()) {
+        Some(runtime) if !runtime.is_empty() => format!("{} · {}", lines, runtime),
+        _ => lines,
+    }
+}
+
+/// Words of the input offered by Alt+P to become a parallel placeholder
+struct Parameterize {
+    words: Vec<std::ops::Range<usize>>,
+    selected: usize,
+}
+
+/// The command being tagged (Alt+G), and the input to put back afterwards
+struct Tagging {
+    command: String,
+    input: String,
+    cursor_position: usize,
+}
+
+/// What a running task was started from
+struct RunningTask {
+    /// What it runs, kept with its output for re-running: a single
+    /// command as typed, a parallel task after substitution
+    command: String,
+    /// Whether its outcome and runtime are recorded for `command`; a
+    /// parallel batch counts once, as `parallel_command`
+    records_outcome: bool,
+}
+
+/// A `NAME=$(command)` waiting for its command to exit
+struct Capture {
+    name: String,
+    /// Output lines without ANSI codes, up to `max_lines_per_task`
+    lines: Vec<String>,
+    /// Whether lines came after that
+    overflowed: bool,
+}
+
+/// Output removed by Ctrl+L, kept for a single undo until new output arrives
+struct ClearedOutput {
+    lines: VecDeque<OutputLine>,
+    evicted_lines: usize,
+    scroll_offset: usize,
+    auto_scroll: bool,
+}
+
+/// Index range of the last box in `output` (top border through bottom
+/// border), or of the last one whose label contains `fragment`. A box whose
+/// top was evicted by the line cap extends to the start of the buffer.
+fn last_box_range(output: &VecDeque<OutputLine>, fragment: Option<&str>) -> Option<std::ops::RangeInclusive<usize>> {
+    let fragment = fragment.map(str::to_lowercase);
+    let mut bottom = None;
+    for (i, line) in output.iter().enumerate().rev() {
+        if line.runner_label == "\x00bot" && bottom.is_none() {
+            bottom = Some(i);
+        } else if let Some(label) = line.runner_label.strip_prefix("\x00top:")
+            && let Some(end) = bottom.take()
+        {
+            let matches = fragment
+                .as_deref()
+                .is_none_or(|f| !label.is_empty() && label.to_lowercase().contains(f));
+            if matches {
+                return Some(i..=end);
+            }
+        }
+    }
+    bottom.filter(|_| fragment.is_none()).map(|end| 0..=end)
+}
+
+/// Human-readable task runtime: "4.21s" or "2m13.5s"
+fn format_runtime(dur: Duration) -> String {
+    if dur.as_secs() >= 60 {
+        format!("{}m{:.1}s", dur.as_secs() / 60, dur.as_secs_f64() % 60.0)
+    } else {
+        format!("{:.2}s", dur.as_secs_f64())
+    }
+}
+
+/// Human-readable byte size: "512 B", "1.2 KB", "3.4 MB"
+fn format_bytes(bytes: f64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1024.0 {
+        return format!("{} B", bytes as u64);
+    }
+    let mut value = bytes / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+/// Footer text for bytes read and throughput: "1.2 MB, 340.0 KB/s"
+fn format_throughput(stats: &crate::runner::TaskStats) -> String {
+    let secs = stats.elapsed.as_secs_f64();
+    if secs > 0.0 {
+        format!(
+            "{}, {}/s",
+            format_bytes(stats.bytes as f64),
+            format_bytes(stats.bytes as f64 / secs)
+        )
+    } else {
+        format_bytes(stats.bytes as f64)
+    }
+}
+
+/// `time` formatted with a strftime pattern; None if the pattern is invalid
+fn format_timestamp(time: &chrono::DateTime<chrono::Local>, format: &str) -> Option<String> {
+    use std::fmt::Write;
+
+    let mut text = String::new();
+    write!(text, "{}", time.format(format)).ok()?;
+    Some(text)
+}
+
+/// The thread whose terminal is in raw mode, and how to restore it
+type TerminalOwner = (std::thread::ThreadId, fn() -> io::Result<()>);
+
+static TERMINAL_OWNER: std::sync::Mutex<Option<TerminalOwner>> = std::sync::Mutex::new(None);
+
+/// Raw mode and the alternate screen for as long as it's held. Dropping it
+/// restores the terminal, so returning early with an error can't leave the
+/// shell without echo; a panic on the same thread restores it before the
+/// panic message is printed, so the message isn't lost on the alternate
+/// screen either.
+pub struct TerminalGuard {
+    _private: (),
+}
+
+impl TerminalGuard {
+    /// Enable raw mode. The alternate screen is the caller's to enter once
+    /// it has queried the terminal; it's left along with raw mode.
+    pub fn enter() -> io::Result<Self> {
+        let guard = Self::claim(restore_terminal);
+        enable_raw_mode()?;
+        Ok(guard)
+    }
+
+    /// Make the current thread's terminal `restore`d when the guard is
+    /// dropped or the thread panics
+    fn claim(restore: fn() -> io::Result<()>) -> Self {
+        static PANIC_HOOK: std::sync::Once = std::sync::Once::new();
+        PANIC_HOOK.call_once(|| {
+            let previous = std::panic::take_hook();
+            std::panic::set_hook(Box::new(move |info| {
+                let owned = terminal_owner().is_some_and(|(thread, _)| thread == std::thread::current().id());
+                if owned {
+                    let _ = release_terminal();
+                }
+                previous(info);
+            }));
+        });
+        *TERMINAL_OWNER.lock().unwrap_or_else(std::sync::PoisonError::into_inner) =
+            Some((std::thread::current().id(), restore));
+        Self { _private: () }
+    }
+
+    /// Restore the terminal now, reporting what dropping would ignore
+    pub fn restore(self) -> io::Result<()> {
+        release_terminal()
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let _ = release_terminal();
+    }
+}
+
+fn terminal_owner() -> Option<TerminalOwner> {
+    *TERMINAL_OWNER.lock().unwrap_or_else(std::sync::PoisonError::into_inner)
+}
+
+/// Restore the terminal if a guard still holds it; only the first call does
+fn release_terminal() -> io::Result<()> {
+    let owner = TERMINAL_OWNER.lock().unwrap_or_else(std::sync::PoisonError::into_inner).take();
+    match owner {
+        Some((_, restore)) => restore(),
+        None => Ok(()),
+    }
+}
+
+fn restore_terminal() -> io::Result<()> {
+    disable_raw_mode()?;
+    execute!(io::stdout(), LeaveAlternateScreen, crossterm::cursor::Show)
+}
+
+/// Leave the TUI and stop the process with SIGTSTP, as Ctrl+Z does in a
+/// shell; restore the TUI once the shell continues it (`fg`). Tasks keep
+/// running in their PTYs meanwhile, and their output is shown on return.
+#[cfg(unix)]
+fn suspend(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>) -> io::Result<()> {
+    disable_raw_mode()?;
+    execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+    terminal.show_cursor()?;
+
+    // Returns once the process is continued
+    unsafe { libc::raise(libc::SIGTSTP) };
+
+    enable_raw_mode()?;
+    execute!(terminal.backend_mut(), EnterAlternateScreen)?;
+    // The screen may have been used by anything since: redraw all of it
+    terminal.clear()
+}
+
+/// No job control to hand the terminal back to
+#[cfg(not(unix))]
+fn suspend(_terminal: &mut Terminal<CrosstermBackend<io::Stdout>>) -> io::Result<()> {
+    Ok(())
+}
+
+/// Send `msg` down `[logging] output_pipe`, and stop piping with a warning
+/// once the program is gone. It's closed on a thread of its own: one that
+/// stopped reading may take `close`'s grace period to exit.
+fn pipe_output(pipe: &mut Option<OutputPipe>, app: &mut App, msg: &OutputMessage) {
+    if let Some(active) = pipe
+        && let Err(reason) = active.send(msg)
+    {
+        app.add_warning(format!("Output pipe stopped: {}", reason));
+        if let Some(pipe) = pipe.take() {
+            std::thread::spawn(move || pipe.close());
+        }
+    }
+}
+
+/// Whether the idle timeout has elapsed. Never true while tasks are running
+/// or when the timeout is disabled.
+fn idle_expired(
+    last_activity: Instant,
+    now: Instant,
+    timeout: Option<Duration>,
+    tasks_running: bool,
+) -> bool {
+    match timeout {
+        Some(timeout) if !tasks_running => now.saturating_duration_since(last_activity) >= timeout,
+        _ => false,
+    }
+}
+
+/// Live lint for the input line: a short hint if it has an unclosed quote,
+/// unbalanced brackets, or a bracket block that won't expand. Never blocks submit.
+fn input_warning(input: &str) -> Option<&'static str> {
+    if shell_words::split(input).is_err() {
+        return Some("unclosed quote");
+    }
+
+    // Bracket balance outside quotes
+    let mut depth: i32 = 0;
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    for c in input.chars() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match (quote, c) {
+            (Some('\''), '\'') => quote = None,
+            (Some('\''), _) => {}
+            (_, '\\') => escaped = true,
+            (Some('"'), '"') => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(c),
+            (None, '[') => depth += 1,
+            (None, ']') => {
+                depth -= 1;
+                if depth < 0 {
+                    return Some("unbalanced ]");
+                }
+            }
+            _ => {}
+        }
+    }
+    if depth > 0 {
+        return Some("unclosed [");
+    }
+
+    // Looks like a parameter block but doesn't parse (bad range, zip length mismatch)
+    let has_param_block = input
+        .split_whitespace()
+        .any(|tok| tok.starts_with('[') && tok.contains('='));
+    if has_param_block && !matches!(crate::parallel::parse_parallel(input), Ok(Some(_))) {
+        return Some("invalid parallel block");
+    }
+
+    None
+}
+
+/// Longest example command shown in the input's parallel preview
+const PARALLEL_PREVIEW_WIDTH: usize = 60;
+
+/// `→ 64 tasks, e.g. "mysql -h shard-1"` for input with parameter blocks;
+/// None for other input, and for input `input_warning` flags as invalid
+fn parallel_preview(input: &str) -> Option<String> {
+    let parsed = crate::parallel::parse_parallel(input.trim()).ok()??;
+    if parsed.groups.is_empty() {
+        return None;
+    }
+    let count = crate::parallel::count(&parsed);
+    let noun = if count == 1 { "task" } else { "tasks" };
+    let Some(first) = crate::parallel::first(&parsed) else {
+        return Some(format!("→ {} {}", count, noun));
+    };
+    let mut example = first.command;
+    if example.chars().count() > PARALLEL_PREVIEW_WIDTH {
+        example = example.chars().take(PARALLEL_PREVIEW_WIDTH - 1).collect();
+        example.push('…');
+    }
+    Some(format!("→ {} {}, e.g. \"{}\"", count, noun, example))
+}
+
+/// Search queries remembered for Alt+Up/Alt+Down in one session
+const QUERY_HISTORY_LIMIT: usize = 50;
+
+/// Most history entries considered by `parallelize`
+const PARALLELIZE_MAX_MATCHES: usize = 200;
+
+/// Log lines `mux-logs` shows without a count
+const LOG_LINES: usize = 50;
+
+// Output display settings — configured via Config, stored in App.
+
+/// A single line of output from a running task
+#[derive(Default)]
+pub struct OutputLine {
+    pub runner_label: String,
+    pub stream: crate::runner::StreamType,
+    pub content: String,
+    /// Wall-clock time a task's line arrived, for `[output] line_timestamps`
+    pub received: Option<chrono::NaiveTime>,
+    /// Box tops only: the box is folded to this one line, and `content`
+    /// holds its summary (line count and runtime)
+    pub folded: bool,
+    /// The command a finished task ran, on the line standing for it: its
+    /// box top, table row, or the rule closing compact output
+    pub command: Option<String>,
+    /// Picked in the output pane to be edited or run again
+    pub selected: bool,
+    /// Identical lines right after this one, folded into it by `[output]
+    /// collapse_repeats` and shown as a count after `content`
+    pub repeated: usize,
+}
+
+/// What a live sync's blocking thread returns
+type LiveSyncResult = Result<crate::sync::BackgroundSync, crate::error::Error>;
+
+/// A live sync running on a blocking thread
+type LiveSync = tokio::task::JoinHandle<LiveSyncResult>;
+
+pub struct App {
+    input: String,
+    output: VecDeque<OutputLine>,
+    scroll_offset: usize,
+    auto_scroll: bool,
+    cursor_position: usize,
+    searcher: HistorySearcher,
+    suggestion_engine: SuggestionEngine,
+    suggestions: Vec<Suggestion>,
+    selected_suggestion: usize,
+    last_quit_press: Option<Instant>,
+    /// First key of a chord and when it was pressed, while waiting for the
+    /// second (see `keymap::CHORD_TIMEOUT`)
+    pending_chord: Option<(crossterm::event::KeyEvent, Instant)>,
+    /// Digits typed with the output focused, repeating the next scroll key
+    pending_count: Option<usize>,
+    /// Ctrl+Z asked to suspend; handled by the run loop, which owns the terminal
+    suspend_requested: bool,
+    /// Track when each task started for runtime display
+    task_start_times: HashMap<crate::runner::TaskId, Instant>,
+    /// Buffered output for parallel tasks (flushed on completion)
+    pending_output: HashMap<crate::runner::TaskId, Vec<OutputLine>>,
+    /// Lines dropped per task after hitting the per-task cap
+    suppressed_lines: HashMap<crate::runner::TaskId, usize>,
+    /// Parallel run progress: (completed, total). Reset on each new parallel submission.
+    parallel_progress: Option<(usize, usize)>,
+    /// Tasks of the current parallel batch that finished unsuccessfully
+    parallel_failed: usize,
+    /// The current parallel batch is `[fail-fast]`
+    parallel_fail_fast: bool,
+    /// Queued tasks of the current batch cancelled by `[fail-fast]` after a failure
+    parallel_aborted: usize,
+    /// Unfinished tasks as (running, queued), refreshed from the runner each frame
+    task_counts: (usize, usize),
+    /// Tasks of the current parallel batch that haven't completed yet
+    parallel_pending: HashSet<crate::runner::TaskId>,
+    /// When the current parallel batch was submitted
+    parallel_started: Option<Instant>,
+    /// Runner batch of the current parallel submission, for `cancel_parallel_batch`
+    parallel_batch: Option<crate::runner::BatchId>,
+    /// Only show output boxes whose label contains this fragment
+    label_filter: Option<String>,
+    /// Full-screen pager over the output, when open
+    pager: Option<Pager>,
+    /// Settings overlay (Alt+,), when open
+    settings: Option<SettingsView>,
+    /// Picking a past argument list for the command being typed (Alt+R), when open
+    arg_picker: Option<ArgPicker>,
+    /// Every command of the session with its output, for `save-session`
+    session: crate::session::SessionLog,
+    /// Picking a word of the input to parameterize (Alt+P), when active
+    parameterize: Option<Parameterize>,
+    /// Typing tags for a suggested command (Alt+G), when active
+    tagging: Option<Tagging>,
+    /// Keys scroll the output pane instead of editing the input (Alt+O)
+    output_focused: bool,
+    /// Visible rows of the output pane, updated by the renderer
+    output_height: usize,
+    /// Tasks had to wait for the display during the last tick
+    output_throttled: bool,
+    /// Lines dropped from the front of the output to stay within `max_output_lines`
+    evicted_lines: usize,
+    /// What the last Ctrl+L removed, until new output arrives
+    cleared_backup: Option<ClearedOutput>,
+    /// Running tasks' commands, until they finish
+    running: HashMap<crate::runner::TaskId, RunningTask>,
+    /// Submitted command text of the current parallel batch; None once cancelled
+    parallel_command: Option<String>,
+    /// Session variables defined with `set NAME=value`, expanded as `${NAME}`
+    session_vars: HashMap<String, String>,
+    /// Inputs left behind that had suggestions, newest first; see `remember_query`
+    queries: VecDeque<String>,
+    /// The latest input with suggestions, remembered if the input is cleared
+    query_candidate: Option<String>,
+    /// Index into `queries` of the query Alt+Up/Alt+Down put in the input
+    query_recall: Option<usize>,
+    /// The input from before Alt+Up, restored by Alt+Down past the newest query
+    query_draft: String,
+    /// Running `NAME=$(command)` tasks and the output captured so far
+    captures: HashMap<crate::runner::TaskId, Capture>,
+    // --- Config values ---
+    max_output_lines: usize,
+    max_lines_per_task: usize,
+    max_line_length: usize,
+    collapse_repeats: bool,
+    /// `[output] compact`: single commands without a box
+    compact: bool,
+    max_command_length: usize,
+    /// `[runner] allowed_cwd`: where commands may run
+    cwd_policy: CwdPolicy,
+    /// `[runner] allowlist` and `denylist`
+    command_policy: CommandPolicy,
+    /// Where `mux-logs` looks for the log file
+    logging: crate::config::LoggingConfig,
+    /// Completed parallel boxes drawn side by side, this many to a row
+    parallel_columns: usize,
+    /// One-line parallel tasks as table rows instead of boxes
+    parallel_style: ParallelStyle,
+    /// Widest task label of the current parallel batch, to align table rows
+    parallel_label_width: usize,
+    /// `[output] quiet_success`: successful commands get a one-line summary
+    quiet_success: bool,
+    /// `[output] quiet_success_commands`: the same for commands matching these
+    quiet_commands: Vec<regex::Regex>,
+    fast_command_grace: Duration,
+    box_pad_h: usize,
+    box_pad_v: usize,
+    long_lines: LongLineMode,
+    wrap_indent: usize,
+    line_timestamps: bool,
+    show_throughput: bool,
+    /// Put each task's command in its box header
+    show_command: bool,
+    /// Keep OSC 8 links in output clickable
+    hyperlinks: bool,
+    box_chars: BoxChars,
+    spinner_frames: &'static [&'static str],
+    palette: Palette,
+    separator_color: Color,
+    output_color: Color,
+    notify_after: Option<Duration>,
+    notify_bell: bool,
+    notify_desktop: bool,
+    record_own_commands: bool,
+    /// Last command run this session, as typed; handed to the shell on exit
+    last_run: Option<String>,
+    /// Compute suggestions while typing; toggled with Alt+S
+    suggestions_enabled: bool,
+    /// `[suggest] min_frequency`, for restoring after Alt+A
+    min_frequency: u32,
+    /// Suggest commands below `min_frequency` too; toggled with Alt+A
+    include_rare: bool,
+    /// A suggestion was picked with Tab/arrows since the list last changed
+    suggestion_browsed: bool,
+    /// `[suggest] enter_accepts`: Enter accepts a picked suggestion first
+    enter_accepts: bool,
+    /// `[suggest] space_accepts`: Space finishes an unambiguous word first
+    space_accepts: bool,
+    /// Panel, ghost text or both; cycled with Alt+V
+    suggestion_display: SuggestionDisplay,
+    /// Where `suggestion_display` is remembered, unless this session persists nothing
+    ui_state_path: Option<PathBuf>,
+    /// `[tui] date_format` and `datetime_format`, inserted by Alt+T / Alt+Shift+T
+    date_format: String,
+    datetime_format: String,
+    /// `[tui] echo_on_exit`: print the output to the normal screen on exit
+    echo_on_exit: bool,
+    empty_enter: EmptyEnter,
+    /// `[tui] input_position`: input line below or above the output
+    input_position: InputPosition,
+    /// `[keymap] esc`: whether Esc clears the input before quitting
+    esc_action: EscAction,
+    /// `[[pipes]]`: keys that send the selection to an external program
+    pipes: Vec<crate::pipe::Pipe>,
+}
+
+impl App {
+    pub fn new(searcher: HistorySearcher, suggestion_engine: SuggestionEngine, config: &Config) -> Self {
+        let palette = Palette::for_background(config.theme.background);
+        let (pipes, pipe_errors): (Vec<_>, Vec<_>) = config
+            .pipes
+            .iter()
+            .map(crate::pipe::Pipe::from_config)
+            .partition(Result::is_ok);
+        let mut pattern_errors = Vec::new();
+        let quiet_commands = config
+            .output
+            .quiet_success_commands
+            .iter()
+            .filter_map(|pattern| {
+                regex::Regex::new(pattern)
+                    .map_err(|e| {
+                        pattern_errors.push(format!("invalid [output] quiet_success_commands pattern `{}`: {}", pattern, e))
+                    })
+                    .ok()
+            })
+            .collect();
+        let mut app = Self {
+            input: String::new(),
+            output: VecDeque::new(),
+            scroll_offset: 0,
+            auto_scroll: true,
+            cursor_position: 0,
+            searcher,
+            suggestion_engine,
+            suggestions: Vec::new(),
+            selected_suggestion: 0,
+            last_quit_press: None,
+            pending_chord: None,
+            pending_count: None,
+            suspend_requested: false,
+            task_start_times: HashMap::new(),
+            pending_output: HashMap::new(),
+            suppressed_lines: HashMap::new(),
+            parallel_progress: None,
+            parallel_failed: 0,
+            parallel_fail_fast: false,
+            parallel_aborted: 0,
+            task_counts: (0, 0),
+            parallel_pending: HashSet::new(),
+            parallel_started: None,
+            parallel_batch: None,
+            label_filter: None,
+            pager: None,
+            settings: None,
+            arg_picker: None,
+            session: crate::session::SessionLog::default(),
+            parameterize: None,
+            tagging: None,
+            output_focused: false,
+            output_height: 1,
+            output_throttled: false,
+            evicted_lines: 0,
+            cleared_backup: None,
+            running: HashMap::new(),
+            parallel_command: None,
+            session_vars: HashMap::new(),
+            queries: VecDeque::new(),
+            query_candidate: None,
+            query_recall: None,
+            query_draft: String::new(),
+            captures: HashMap::new(),
+            max_output_lines: config.output.max_lines,
+            max_lines_per_task: config.output.max_lines_per_task,
+            collapse_repeats: config.output.collapse_repeats,
+            compact: config.output.compact,
+            max_line_length: config.output.max_line_length,
+            max_command_length: config.runner.max_command_length,
+            cwd_policy: CwdPolicy::new(&config.runner.allowed_cwd),
+            command_policy: CommandPolicy::new(&config.runner.allowlist, &config.runner.denylist),
+            logging: config.logging.clone(),
+            parallel_columns: config.output.parallel_columns,
+            parallel_style: config.output.parallel_style,
+            parallel_label_width: 0,
+            quiet_success: config.output.quiet_success,
+            quiet_commands,
+            fast_command_grace: Duration::from_millis(config.output.fast_command_grace_ms),
+            box_pad_h: config.output.box_padding_horizontal,
+            box_pad_v: config.output.box_padding_vertical,
+            long_lines: config.output.long_lines,
+            wrap_indent: config.output.wrap_indent,
+            line_timestamps: config.output.line_timestamps,
+            show_throughput: config.output.show_throughput,
+            show_command: config.output.show_command,
+            hyperlinks: config.output.hyperlinks,
+            box_chars: BoxChars::for_style(config.output.box_style),
+            spinner_frames: match config.output.box_style {
+                BoxStyle::Unicode => &SPINNER_FRAMES,
+                BoxStyle::Ascii | BoxStyle::None => &SPINNER_FRAMES_ASCII,
+            },
+            palette,
+            separator_color: config.theme.separator_color.map_or(palette.dim, |c| c.0),
+            output_color: config.theme.output_color.map_or(palette.text, |c| c.0),
+            notify_after: config.runner.notify_after_secs.map(Duration::from_secs),
+            notify_bell: config.runner.notify_bell,
+            notify_desktop: config.runner.notify_desktop,
+            record_own_commands: config.history.record_own_commands,
+            last_run: None,
+            suggestions_enabled: config.suggest.enabled,
+            min_frequency: config.suggest.min_frequency,
+            include_rare: false,
+            suggestion_browsed: false,
+            enter_accepts: config.suggest.enter_accepts,
+            space_accepts: config.suggest.space_accepts,
+            suggestion_display: config.suggest.display,
+            ui_state_path: None,
+            date_format: config.tui.date_format.clone(),
+            datetime_format: config.tui.datetime_format.clone(),
+            echo_on_exit: config.tui.echo_on_exit,
+            empty_enter: config.tui.empty_enter,
+            input_position: config.tui.input_position,
+            esc_action: config.keymap.esc,
+            pipes: pipes.into_iter().filter_map(Result::ok).collect(),
+        };
+        for error in pipe_errors.into_iter().filter_map(Result::err) {
+            app.add_warning(error.to_string());
+        }
+        for error in pattern_errors {
+            app.add_warning(error);
+        }
+        app
+    }
+
+    // --- Read accessors ---
+
+    pub fn input(&self) -> &str {
+        &self.input
+    }
+
+    pub fn cursor_position(&self) -> usize {
+        self.cursor_position
+    }
+
+    pub fn label_filter(&self) -> Option<&str> {
+        self.label_filter.as_deref()
+    }
+
+    pub fn scroll_offset(&self) -> usize {
+        self.scroll_offset
+    }
+
+    pub fn has_suggestions(&self) -> bool {
+        !self.suggestions.is_empty()
+    }
+
+    /// Whether the suggestion panel is on screen
+    fn shows_suggestion_panel(&self) -> bool {
+        self.has_suggestions() && self.suggestion_display.shows_panel()
+    }
+
+    /// Ghost text shown after the input, if that display is enabled
+    fn ghost_text(&self) -> Option<String> {
+        self.suggestion_display
+            .shows_ghost()
+            .then(|| self.get_suggestion_preview())
+            .flatten()
+    }
+
+    pub fn suggestions(&self) -> &[Suggestion] {
+        &self.suggestions
+    }
+
+    /// Consume the App and return the HistorySearcher for shutdown flush,
+    /// along with the commands a wrapping shell may want
+    pub fn into_parts(self) -> (HistorySearcher, ExitCommands) {
+        let exit = ExitCommands {
+            last_run: self.last_run,
+            draft: self.input,
+        };
+        (self.searcher, exit)
+    }
+
+    // --- Input editing ---
+
+    pub fn insert_char(&mut self, c: char) {
+        self.input.insert(self.cursor_position, c);
+        self.cursor_position += c.len_utf8();
+        self.update_suggestions();
+    }
+
+    /// Space. With `[suggest] space_accepts`, a word the suggestions agree
+    /// on is finished first: `cargo bu` + Space gives `cargo build `.
+    pub fn insert_space(&mut self) {
+        if self.space_accepts
+            && let Some(rest) = self.unambiguous_word_completion()
+        {
+            self.input.push_str(&rest);
+            self.cursor_position = self.input.len();
+        }
+        self.insert_char(' ');
+    }
+
+    /// The rest of the word being typed at the end of the input, if the top
+    /// suggestion continues the input with it and no other suggestion that
+    /// continues the input finishes the word differently (or not at all).
+    /// Nothing while a suggestion is picked or a quote is open.
+    fn unambiguous_word_completion(&self) -> Option<String> {
+        if self.input.is_empty()
+            || self.cursor_position != self.input.len()
+            || self.input.ends_with(char::is_whitespace)
+            || self.suggestion_browsed
+            || open_quote(&self.input).is_some()
+        {
+            return None;
+        }
+        fn completion<'a>(input: &str, suggestion: &'a Suggestion) -> Option<&'a str> {
+            if suggestion.suggestion_type != crate::suggest::SuggestionType::FullCommand {
+                return None;
+            }
+            let rest = suggestion.text.strip_prefix(input)?;
+            Some(extract_first_word(rest, None))
+        }
+
+        let (top, others) = self.suggestions.split_first()?;
+        let word = completion(&self.input, top)?;
+        if word.is_empty() || word.starts_with(char::is_whitespace) {
+            return None;
+        }
+        others
+            .iter()
+            .filter_map(|suggestion| completion(&self.input, suggestion))
+            .all(|other| other == word)
+            .then(|| word.to_string())
+    }
+
+    /// Insert `text` at the cursor and move the cursor past it
+    pub fn insert_str(&mut self, text: &str) {
+        self.input.insert_str(self.cursor_position, text);
+        self.cursor_position += text.len();
+        self.update_suggestions();
+    }
+
+    /// Insert the current date, or date and time, in the configured format
+    pub fn insert_timestamp(&mut self, with_time: bool) {
+        let (key, format) = if with_time {
+            ("datetime_format", &self.datetime_format)
+        } else {
+            ("date_format", &self.date_format)
+        };
+        match format_timestamp(&chrono::Local::now(), format) {
+            Some(text) => self.insert_str(&text),
+            None => self.add_warning(format!("Invalid [tui] {}: {}", key, format)),
+        }
+    }
+
+    pub fn delete_char_backward(&mut self) {
+        if self.cursor_position > 0 {
+            let prev = prev_char_pos(&self.input, self.cursor_position);
+            self.input.remove(prev);
+            self.cursor_position = prev;
+            self.update_suggestions();
+        }
+    }
+
+    pub fn delete_char_forward(&mut self) {
+        if self.cursor_position < self.input.len() {
+            self.input.remove(self.cursor_position);
+            self.update_suggestions();
+        }
+    }
+
+    pub fn delete_word_backward(&mut self) {
+        let word_start = find_prev_word_boundary(&self.input, self.cursor_position);
+        if word_start < self.cursor_position {
+            self.input.drain(word_start..self.cursor_position);
+            self.cursor_position = word_start;
+            self.update_suggestions();
+        }
+    }
+
+    pub fn delete_word_forward(&mut self) {
+        let word_end = find_next_word_boundary(&self.input, self.cursor_position);
+        if word_end > self.cursor_position {
+            self.input.drain(self.cursor_position..word_end);
+            self.update_suggestions();
+        }
+    }
+
+    pub fn delete_to_line_start(&mut self) {
+        if self.cursor_position > 0 {
+            self.input.drain(..self.cursor_position);
+            self.cursor_position = 0;
+            self.update_suggestions();
+        }
+    }
+
+    pub fn delete_to_line_end(&mut self) {
+        if self.cursor_position < self.input.len() {
+            self.input.truncate(self.cursor_position);
+            self.update_suggestions();
+        }
+    }
+
+    // --- Cursor movement ---
+
+    pub fn move_cursor_left(&mut self) {
+        if self.cursor_position > 0 {
+            self.cursor_position = prev_char_pos(&self.input, self.cursor_position);
+        }
+    }
+
+    pub fn move_cursor_right(&mut self) {
+        if self.cursor_position < self.input.len() {
+            self.cursor_position = next_char_pos(&self.input, self.cursor_position);
+        }
+    }
+
+    pub fn move_cursor_word_left(&mut self) {
+        self.cursor_position = find_prev_word_boundary(&self.input, self.cursor_position);
+    }
+
+    pub fn move_cursor_word_right(&mut self) {
+        self.cursor_position = find_next_word_boundary(&self.input, self.cursor_position);
+    }
+
+    pub fn move_cursor_home(&mut self) {
+        self.cursor_position = 0;
+    }
+
+    pub fn move_cursor_end(&mut self) {
+        self.cursor_position = self.input.len();
+    }
+
+    /// Accept the next word from the suggestion preview (Right arrow at end of input)
+    pub fn accept_next_preview_word(&mut self) {
+        if self.cursor_position == self.input.len()
+            && let Some(preview) = self.ghost_text()
+        {
+            let next_word = extract_first_word(&preview, open_quote(&self.input));
+            if !next_word.is_empty() {
+                self.input.push_str(next_word);
+                self.cursor_position = self.input.len();
+                self.update_suggestions();
+            }
+        }
+    }
+
+    /// Accept the whole suggestion preview (Ctrl+F or End at end of input)
+    pub fn accept_full_preview(&mut self) {
+        if self.cursor_position == self.input.len()
+            && let Some(preview) = self.ghost_text()
+            && !preview.is_empty()
+        {
+            self.input.push_str(&preview);
+            self.cursor_position = self.input.len();
+            self.update_suggestions();
+        }
+    }
+
+    // --- Command submission ---
+
+    /// Submit the current input. Returns true if the app should quit (internal commands).
+    pub fn submit_command(&mut self, runner: &mut TaskRunner) -> bool {
+        if self.input.is_empty() {
+            return match self.empty_enter {
+                EmptyEnter::None => false,
+                EmptyEnter::Separator => {
+                    // A status line without text draws as a plain rule
+                    self.append_output(OutputLine {
+                        runner_label: String::new(),
+                        stream: crate::runner::StreamType::Status,
+                        content: String::new(),
+                        ..Default::default()
+                    });
+                    false
+                }
+                EmptyEnter::RerunLast => self.rerun_last_command(runner),
+            };
+        }
+        // Left in the input so it can be trimmed
+        if self.max_command_length > 0 && self.input.len() > self.max_command_length {
+            self.add_warning(format!(
+                "Command not run: {} is over [runner] max_command_length ({})",
+                format_bytes(self.input.len() as f64),
+                format_bytes(self.max_command_length as f64)
+            ));
+            return false;
+        }
+
+        // A submitted command is recalled with Up and Ctrl+R, not as a query
+        self.query_candidate = None;
+        let command = std::mem::take(&mut self.input);
+        self.cursor_position = 0;
+        self.run_command(&command, runner)
+    }
+
+    /// Enter: submit the input. With `[suggest] enter_accepts`, a suggestion
+    /// picked with Tab or the arrow keys is accepted first, and the next
+    /// Enter submits. Returns true if the app should quit.
+    pub fn submit_or_accept(&mut self, runner: &mut TaskRunner) -> bool {
+        if self.enter_accepts
+            && self.suggestion_browsed
+            && let Some(suggestion) = self.suggestions.get(self.selected_suggestion)
+        {
+            let (typed, new) = self.suggestion_full_preview(suggestion);
+            if typed + &new != self.input {
+                self.accept_suggestion();
+                return false;
+            }
+        }
+        self.submit_command(runner)
+    }
+
+    /// Accept the highlighted suggestion and submit the result in one step.
+    /// Does nothing without a suggestion. Returns true if the app should quit.
+    pub fn accept_and_submit(&mut self, runner: &mut TaskRunner) -> bool {
+        if self.selected_suggestion >= self.suggestions.len() {
+            return false;
+        }
+        self.accept_suggestion();
+        self.submit_command(runner)
+    }
+
+    /// Re-run the most recently used command without touching the input buffer
+    pub fn rerun_last_command(&mut self, runner: &mut TaskRunner) -> bool {
+        let Some(command) = self.searcher.most_recent_command().map(|c| c.command.clone()) else {
+            return false;
+        };
+        self.run_command(&command, runner)
+    }
+
+    /// Shared submit path: handles internal commands, records usage and spawns
+    /// the command (expanding parallel syntax). Returns true if the app should quit.
+    fn run_command(&mut self, command: &str, runner: &mut TaskRunner) -> bool {
+        let trimmed = command.trim();
+
+        // Internal commands
+        if trimmed == "exit" || trimmed == "quit" {
+            return true;
+        }
+        if let Some(var_cmd) = crate::vars::parse_var_command(trimmed) {
+            match var_cmd {
+                crate::vars::VarCommand::Set { name, value } => {
+                    self.session_vars.insert(name.to_string(), value.to_string());
+                }
+                crate::vars::VarCommand::Unset { name } => {
+                    self.session_vars.remove(name);
+                }
+                crate::vars::VarCommand::Capture { name, command: inner } => {
+                    self.capture(name, inner, command, runner);
+                }
+            }
+            return false;
+        }
+        if trimmed == "reindex" {
+            self.reindex();
+            return false;
+        }
+        if let Some(base) = trimmed.strip_prefix("parallelize ") {
+            self.parallelize(base.trim());
+            return false;
+        }
+        if let Some(rest) = trimmed.strip_prefix("explain ") {
+            self.explain(rest.trim());
+            return false;
+        }
+        if let Some(count) = trimmed.strip_prefix("mux-logs")
+            && (count.is_empty() || count.starts_with(' '))
+        {
+            self.show_logs(count.trim());
+            return false;
+        }
+        if let Some(path) = trimmed.strip_prefix("save-session")
+            && (path.is_empty() || path.starts_with(' '))
+        {
+            self.save_session(path.trim());
+            return false;
+        }
+
+        // Expand session variables before parallel placeholders so `${X}` isn't
+        // mistaken for a `{X}` parameter
+        let expanded_input = crate::vars::expand_vars(command, &self.session_vars);
+
+        // Check for parallel expansion syntax: [name=range] command {name}.
+        // Malformed blocks would only fail confusingly in the shell, so the
+        // command goes back into the input for fixing instead of running.
+        let parallel = match crate::parallel::parse_parallel(expanded_input.trim()) {
+            Ok(parallel) => parallel,
+            Err(e) => {
+                self.add_warning(format!("Invalid parallel block: {}", e));
+                self.restore_input(command);
+                return false;
+            }
+        };
+        if let Err(refusal) = self.check_command(&expanded_input, parallel.as_ref()) {
+            self.add_warning(format!("Command not run: {}", refusal));
+            self.restore_input(command);
+            return false;
+        }
+
+        if self.record_own_commands {
+            if let Err(e) = self.searcher.record_usage(command) {
+                log::warn!("Failed to record command usage: {}", e);
+            }
+            self.suggestion_engine.index_command(command);
+        }
+
+        // Outcomes are recorded against the command as typed, like usage
+        let submitted = command.to_string();
+        self.last_run = Some(submitted.clone());
+        let command = expanded_input.as_str();
+
+        match parallel {
+            Some(parsed) if !parsed.groups.is_empty() => self.spawn_parallel(&parsed, submitted, runner),
+            Some(parsed) => {
+                // Only directives such as `[retry=3]`: a single command
+                let id = runner.spawn_in_batch(&parsed.template, "", None, parsed.retries);
+                self.running.insert(id, RunningTask { command: submitted, records_outcome: true });
+                self.session.start(id, &parsed.template);
+            }
+            None => {
+                let id = runner.spawn_labeled(command, "");
+                self.running.insert(id, RunningTask { command: submitted, records_outcome: true });
+                self.session.start(id, command);
+            }
+        }
+
+        // Reset scroll to bottom so new output is visible
+        self.auto_scroll = true;
+        self.scroll_to_bottom();
+        false
+    }
+
+    /// Put a command that wasn't run back into an empty input for fixing
+    fn restore_input(&mut self, command: &str) {
+        if self.input.is_empty() {
+            self.input = command.to_string();
+            self.cursor_position = self.input.len();
+        }
+    }
+
+    /// Check `[runner] allowlist`/`denylist` and `allowed_cwd` for every
+    /// command `expanded` runs; a parallel block is refused as a whole
+    fn check_command(
+        &self,
+        expanded: &str,
+        parallel: Option<&crate::parallel::ParsedParallel>,
+    ) -> Result<(), String> {
+        if self.command_policy.is_unrestricted() && self.cwd_policy.is_unrestricted() {
+            return Ok(());
+        }
+        let cwd = std::env::current_dir().unwrap_or_default();
+        let check = |command: &str| -> Result<(), String> {
+            self.command_policy.check(command).map_err(|refusal| refusal.to_string())?;
+            self.cwd_policy.check(&cwd, command).map_err(|refusal| refusal.to_string())
+        };
+        match parallel {
+            Some(parsed) if !parsed.groups.is_empty() => crate::parallel::expand(parsed)
+                .iter()
+                .try_for_each(|cmd| check(&cmd.command)),
+            Some(parsed) => check(&parsed.template),
+            None => check(expanded),
+        }
+    }
+
+    /// Run `inner` for `NAME=$(inner)`. Its output shows in a box as usual
+    /// and becomes the variable's value once it exits successfully.
+    fn capture(&mut self, name: &str, inner: &str, submitted: &str, runner: &mut TaskRunner) {
+        let inner = crate::vars::expand_vars(inner, &self.session_vars);
+        if let Err(refusal) = self.check_command(&inner, None) {
+            self.add_warning(format!("Command not run: {}", refusal));
+            self.restore_input(submitted);
+            return;
+        }
+        if self.record_own_commands {
+            if let Err(e) = self.searcher.record_usage(submitted) {
+                log::warn!("Failed to record command usage: {}", e);
+            }
+            self.suggestion_engine.index_command(submitted);
+        }
+        self.last_run = Some(submitted.to_string());
+
+        let id = runner.spawn_labeled(&inner, "");
+        self.session.start(id, &inner);
+        self.running.insert(id, RunningTask { command: submitted.to_string(), records_outcome: false });
+        self.captures.insert(
+            id,
+            Capture {
+                name: name.to_string(),
+                lines: Vec::new(),
+                overflowed: false,
+            },
+        );
+        self.auto_scroll = true;
+        self.scroll_to_bottom();
+    }
+
+    /// Set the captured variable, or say why it wasn't
+    fn finish_capture(&mut self, capture: Capture, msg: &OutputMessage) {
+        if msg.stats.is_none_or(|s| s.exit_code != Some(0)) {
+            self.add_warning(format!("{} not set: {}", capture.name, msg.content));
+            return;
+        }
+        if capture.overflowed {
+            self.add_warning(format!(
+                "{} not set: more than {} lines of output",
+                capture.name, self.max_lines_per_task
+            ));
+            return;
+        }
+        let value = capture.lines.join("\n").trim().to_string();
+        let shown = match value.lines().count() {
+            0 | 1 => value.clone(),
+            n => format!("{} … ({} lines)", value.lines().next().unwrap_or_default(), n),
+        };
+        self.add_warning(format!("{} = {}", capture.name, shown));
+        self.session_vars.insert(capture.name, value);
+    }
+
+    /// Start the tasks of a parallel command as a new batch, replacing the
+    /// progress of the previous one
+    fn spawn_parallel(
+        &mut self,
+        parsed: &crate::parallel::ParsedParallel,
+        submitted: String,
+        runner: &mut TaskRunner,
+    ) {
+        let expanded = crate::parallel::expand(parsed);
+        let total = expanded.len();
+        log::info!("Parallel execution: {} tasks", total);
+        self.parallel_progress = Some((0, total));
+        self.parallel_failed = 0;
+        self.parallel_fail_fast = parsed.fail_fast;
+        self.parallel_aborted = 0;
+        self.parallel_started = Some(Instant::now());
+        self.parallel_pending.clear();
+        let batch = runner.new_batch(parsed.fail_fast);
+        self.parallel_batch = Some(batch);
+        self.parallel_command = Some(submitted);
+        self.parallel_label_width = expanded
+            .iter()
+            .map(|cmd| unicode_width::UnicodeWidthStr::width(cmd.label.as_str()))
+            .max()
+            .unwrap_or(0);
+        for cmd in expanded {
+            let id = runner.spawn_in_batch(&cmd.command, &cmd.label, Some(batch), parsed.retries);
+            self.parallel_pending.insert(id);
+            self.session.start(id, &cmd.command);
+            self.running.insert(id, RunningTask { command: cmd.command, records_outcome: false });
+        }
+    }
+
+    /// Start syncing shell history again on a blocking thread, through a
+    /// second connection to the database; `apply_live_sync` takes in the
+    /// result. An in-memory history can't be shared that way, so it's synced
+    /// in place and there's nothing to wait for.
+    fn spawn_live_sync(
+        &mut self,
+        history: &crate::config::HistoryConfig,
+        sync: &crate::config::SyncConfig,
+    ) -> Option<LiveSync> {
+        match self.searcher.reopen() {
+            Ok(Some(searcher)) => {
+                let (history, sync) = (history.clone(), sync.clone());
+                Some(tokio::task::spawn_blocking(move || {
+                    crate::sync::resync_in_background(searcher, &history, &sync)
+                }))
+            }
+            Ok(None) => {
+                let (result, new_uses) = crate::sync::resync(&mut self.searcher, history, sync);
+                self.index_synced(result, &new_uses);
+                None
+            }
+            Err(e) => {
+                log::warn!("Live sync failed: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Take in a live sync that finished on its blocking thread
+    fn apply_live_sync(&mut self, joined: Result<LiveSyncResult, tokio::task::JoinError>) {
+        let applied = match joined {
+            Ok(synced) => synced.and_then(|synced| synced.apply(&mut self.searcher)),
+            Err(e) => {
+                log::warn!("Live sync failed: {}", e);
+                return;
+            }
+        };
+        match applied {
+            Ok((result, new_uses)) => self.index_synced(result, &new_uses),
+            Err(e) => log::warn!("Live sync failed: {}", e),
+        }
+    }
+
+    /// Add the commands a live sync brought in to the suggestion indexes
+    fn index_synced(&mut self, result: crate::sync::SyncResult, new_uses: &[crate::searcher::IndexedCommand]) {
+        for warning in &result.warnings {
+            log::warn!("Live sync: {}", warning);
+        }
+        if !new_uses.is_empty() {
+            log::debug!("Live sync indexed {} new commands", result.total_synced);
+            self.suggestion_engine.index_commands(new_uses);
+        }
+    }
+
+    /// `reindex`: reload the history from the database and rebuild the
+    /// suggestion indexes from it. Nothing is deleted.
+    fn reindex(&mut self) {
+        let result = self
+            .searcher
+            .flush()
+            .and_then(|()| self.searcher.reload_from_db());
+        match result {
+            Ok(()) => {
+                self.suggestion_engine.rebuild(self.searcher.commands());
+                self.update_suggestions();
+                self.add_warning(format!("Reindexed {} commands", self.searcher.len()));
+            }
+            Err(e) => {
+                log::warn!("Reindex failed: {}", e);
+                self.add_warning(format!("Reindex failed: {}", e));
+            }
+        }
+    }
+
+    /// Cancel the unfinished tasks of the current parallel batch. Standalone
+    /// commands and earlier batches keep running.
+    pub fn cancel_parallel_batch(&mut self, runner: &mut TaskRunner) {
+        let Some(batch) = self.parallel_batch else {
+            return;
+        };
+        if self.parallel_pending.is_empty() {
+            return;
+        }
+        // A cancelled batch says nothing about whether the command works
+        self.parallel_command = None;
+        self.parallel_fail_fast = false;
+        let count = runner.cancel_batch(batch);
+        self.add_warning(format!("Cancelling {} parallel tasks", count));
+    }
+
+    /// `parallelize <prefix>`: infer a bracket template from history entries
+    /// starting with `prefix` and load it into the input for editing
+    fn parallelize(&mut self, base: &str) {
+        let matches: Vec<&str> = self
+            .searcher
+            .commands()
+            .map(|c| c.command.as_str())
+            .filter(|c| c.starts_with(base))
+            .take(PARALLELIZE_MAX_MATCHES)
+            .collect();
+
+        match crate::parallel::infer_parallel(&matches) {
+            Some(command) => {
+                self.input = command;
+                self.cursor_position = self.input.len();
+                self.update_suggestions();
+            }
+            None => self.add_warning(format!(
+                "parallelize: no varying history entries start with {:?}",
+                base
+            )),
+        }
+    }
+
+    // --- Parameterize ---
+
+    /// Start picking a word of the input to turn into a `{x}` placeholder,
+    /// beginning with the word at or after the cursor
+    pub fn start_parameterize(&mut self) {
+        let words = crate::parallel::parameterizable_words(&self.input);
+        if words.is_empty() {
+            self.add_warning("parameterize: no words in the input to turn into a placeholder".to_string());
+            return;
+        }
+        let selected = words
+            .iter()
+            .position(|w| w.end >= self.cursor_position)
+            .unwrap_or(words.len() - 1);
+        self.parameterize = Some(Parameterize { words, selected });
+    }
+
+    pub fn is_parameterizing(&self) -> bool {
+        self.parameterize.is_some()
+    }
+
+    /// The word picked for parameterizing, as a byte range of the input
+    fn parameterize_selection(&self) -> Option<std::ops::Range<usize>> {
+        let state = self.parameterize.as_ref()?;
+        Some(state.words[state.selected].clone())
+    }
+
+    /// Pick the next (or previous) word, wrapping around
+    pub fn move_parameterize_selection(&mut self, forward: bool) {
+        if let Some(state) = &mut self.parameterize {
+            let len = state.words.len();
+            state.selected = if forward {
+                (state.selected + 1) % len
+            } else {
+                (state.selected + len - 1) % len
+            };
+        }
+    }
+
+    pub fn cancel_parameterize(&mut self) {
+        self.parameterize = None;
+    }
+
+    /// Rewrite the input with the picked word as a placeholder and a new
+    /// bracket block holding its value, leaving the cursor after the value
+    /// so it can be edited into a range
+    pub fn apply_parameterize(&mut self) {
+        let Some(word) = self.parameterize_selection() else {
+            return;
+        };
+        self.parameterize = None;
+        match crate::parallel::parameterize(&self.input, word) {
+            Some((command, value)) => {
+                self.input = command;
+                self.cursor_position = value.end;
+                self.update_suggestions();
+            }
+            None => self.add_warning(
+                "parameterize: x, y, z and w are all placeholders already".to_string(),
+            ),
+        }
+    }
+
+    // --- Tagging ---
+
+    /// Start typing tags for the highlighted command suggestion. The input
+    /// is set aside and holds the tags until Enter or Esc.
+    pub fn start_tagging(&mut self) {
+        let Some(suggestion) = self
+            .suggestions
+            .get(self.selected_suggestion)
+            .filter(|s| s.suggestion_type == crate::suggest::SuggestionType::FullCommand)
+        else {
+            self.add_warning("tag: highlight a command suggestion to tag it".to_string());
+            return;
+        };
+        if self.searcher.is_read_only() {
+            self.add_warning("tag: the history is read-only".to_string());
+            return;
+        }
+        self.tagging = Some(Tagging {
+            command: suggestion.text.clone(),
+            input: std::mem::take(&mut self.input),
+            cursor_position: self.cursor_position,
+        });
+        self.cursor_position = 0;
+        self.suggestions.clear();
+        self.selected_suggestion = 0;
+    }
+
+    pub fn is_tagging(&self) -> bool {
+        self.tagging.is_some()
+    }
+
+    /// Stop tagging and put the input back as it was
+    pub fn cancel_tagging(&mut self) {
+        if let Some(state) = self.tagging.take() {
+            self.input = state.input;
+            self.cursor_position = state.cursor_position;
+            self.update_suggestions();
+        }
+    }
+
+    /// Apply the typed tags: `name` adds one, `-name` removes it
+    pub fn apply_tagging(&mut self) {
+        let Some(command) = self.tagging.as_ref().map(|state| state.command.clone()) else {
+            return;
+        };
+        let words: Vec<String> = self.input.split_whitespace().map(str::to_string).collect();
+        self.cancel_tagging();
+        for word in words {
+            let (remove, name) = match word.strip_prefix('-') {
+                Some(name) => (true, name),
+                None => (false, word.as_str()),
+            };
+            let Some(tag) = crate::searcher::normalize_tag(name) else {
+                self.add_warning(format!("tag: {:?} isn't a valid tag", name));
+                continue;
+            };
+            let result = if remove {
+                self.searcher.remove_tag(&command, &tag)
+            } else {
+                self.searcher.add_tag(&command, &tag)
+            };
+            if let Err(e) = result {
+                self.add_warning(format!("tag: failed to save {:?}: {}", tag, e));
+            }
+        }
+    }
+
+    /// `explain <command>`: describe how a bracket command expands, in a box
+    /// in the output pane, without running anything
+    fn explain(&mut self, input: &str) {
+        let expanded = crate::vars::expand_vars(input, &self.session_vars);
+        let parsed = match crate::parallel::parse_parallel(&expanded) {
+            Ok(Some(parsed)) => parsed,
+            Ok(None) => {
+                self.add_warning(format!("explain: no parallel blocks in {:?}", input));
+                return;
+            }
+            Err(e) => {
+                self.add_warning(format!("explain: {}", e));
+                return;
+            }
+        };
+
+        self.append_box("explain", crate::parallel::explain(&parsed), "nothing was run".to_string());
+    }
+
+    /// `mux-logs [N]`: show the last N lines of mux's own log file in a box
+    /// in the output pane, so problems can be looked into without leaving
+    /// mux. Prefixed so a `logs` program on the PATH still runs.
+    fn show_logs(&mut self, count: &str) {
+        let count = match count {
+            "" => LOG_LINES,
+            count => match count.parse::<usize>() {
+                Ok(n) if n > 0 => n,
+                _ => {
+                    self.add_warning(format!("mux-logs: expected a number of lines, not {:?}", count));
+                    return;
+                }
+            },
+        };
+        if !self.logging.enabled {
+            self.add_warning("mux-logs: logging is off ([logging] enabled = false)".to_string());
+            return;
+        }
+        let path = crate::logger::log_dir(&self.logging).join(crate::logger::LOG_FILE_NAME);
+        match crate::logger::last_lines(&path, count) {
+            Ok(lines) if lines.is_empty() => {
+                self.add_warning(format!("mux-logs: nothing logged yet in {}", path.display()));
+            }
+            Ok(lines) => self.append_box(crate::logger::LOG_FILE_NAME, lines, path.display().to_string()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                self.add_warning(format!("mux-logs: nothing logged yet, {} doesn't exist", path.display()));
+            }
+            Err(e) => self.add_warning(format!("mux-logs: can't read {}: {}", path.display(), e)),
+        }
+    }
+
+    /// `save-session <path>`: write the commands run so far and their output
+    /// to `path` as markdown
+    fn save_session(&mut self, path: &str) {
+        if path.is_empty() {
+            self.add_warning("save-session: expected a file path, e.g. save-session ~/debug.md".to_string());
+            return;
+        }
+        let path = crate::paths::expand(path);
+        match self.session.save(&path) {
+            Ok(()) => {
+                let count = self.session.len();
+                let commands = if count == 1 { "1 command".to_string() } else { format!("{} commands", count) };
+                self.add_warning(format!("Saved {} to {}", commands, path.display()));
+            }
+            Err(e) => self.add_warning(format!("save-session: can't write {}: {}", path.display(), e)),
+        }
+    }
+
+    /// Show `lines` in a box titled `title` with `footer` in its bottom
+    /// border, for mux's own output rather than a task's
+    fn append_box(&mut self, title: &str, lines: Vec<String>, footer: String) {
+        self.append_output(OutputLine {
+            runner_label: format!("\x00top:{}", title),
+            stream: crate::runner::StreamType::Status,
+            content: String::new(),
+            ..Default::default()
+        });
+        for line in lines {
+            self.append_output(OutputLine {
+                runner_label: "\x00box".to_string(),
+                stream: crate::runner::StreamType::Output,
+                content: line,
+                ..Default::default()
+            });
+        }
+        self.append_output(OutputLine {
+            runner_label: "\x00bot".to_string(),
+            stream: crate::runner::StreamType::Status,
+            content: footer,
+            ..Default::default()
+        });
+        self.auto_scroll = true;
+        self.scroll_to_bottom();
+    }
+
+    /// Receive output from a running task and append to the output buffer.
+    /// All tasks are buffered per-task and flushed as boxed blocks on completion.
+    pub fn push_output(&mut self, msg: OutputMessage) {
+        if msg.stream == crate::runner::StreamType::Status {
+            if msg.content == "started" {
+                self.task_start_times.insert(msg.task_id, Instant::now());
+                return;
+            }
+
+            // Intermediate status (`retry 1/3: ...`): shown inside the task's box
+            if msg.stats.is_none() && msg.content.starts_with(crate::runner::RETRY_STATUS) {
+                self.session.push_line(msg.task_id, &msg.runner_label, &msg.content);
+                self.pending_output.entry(msg.task_id).or_default().push(OutputLine {
+                    runner_label: msg.runner_label,
+                    stream: crate::runner::StreamType::Output,
+                    content: format!("\x1b[2m↻ {}\x1b[0m", msg.content),
+                    received: Some(chrono::Local::now().time()),
+                    ..Default::default()
+                });
+                return;
+            }
+
+            // Task completed -- compute runtime
+            let elapsed = self
+                .task_start_times
+                .remove(&msg.task_id)
+                .map(|start| start.elapsed());
+            self.session
+                .finish(msg.task_id, &msg.runner_label, &msg.content, elapsed.map(format_runtime));
+
+            // Cancelled while queued: nothing ran, so there's no box to draw
+            if msg.content == crate::runner::CANCELLED_STATUS && elapsed.is_none() {
+                self.finish_task(&msg, None);
+                return;
+            }
+
+            let mut runtime = elapsed.map(format_runtime).unwrap_or_default();
+            if let Some(stats) = &msg.stats
+                && stats.attempts > 1
+            {
+                runtime = format!("{} attempts · {}", stats.attempts, runtime);
+            }
+            if self.show_throughput
+                && let Some(stats) = &msg.stats
+            {
+                runtime = format!("{} · {}", format_throughput(stats), runtime);
+            }
+
+            let command = self.running.get(&msg.task_id).map(|task| task.command.clone());
+            if let Some(summary) = self.quiet_summary(&msg, command.as_deref(), &runtime) {
+                self.pending_output.remove(&msg.task_id);
+                self.suppressed_lines.remove(&msg.task_id);
+                self.append_output(OutputLine {
+                    runner_label: String::new(),
+                    stream: crate::runner::StreamType::Output,
+                    content: summary,
+                    command,
+                    ..Default::default()
+                });
+                self.finish_task(&msg, elapsed);
+                return;
+            }
+            if let Some(mut row) = self.table_row(&msg) {
+                row.command = command;
+                self.append_output(row);
+                self.finish_task(&msg, elapsed);
+                return;
+            }
+
+            // `[output] compact`: a single command's lines go straight into
+            // the pane, followed by one separator rule instead of a box
+            let compact = self.compact && msg.runner_label.is_empty();
+            let body_label = if compact { "" } else { "\x00box" };
+            if compact {
+                if self.show_command
+                    && let Some(command) = &command
+                {
+                    runtime = format!("{} · {}", command.trim(), runtime);
+                }
+            } else {
+                // Top border: ┌─ [n=1] ─┐ or ┌──────────┐ (no label for single
+                // commands); the command is drawn after the label with
+                // `[output] show_command`
+                self.append_output(OutputLine {
+                    runner_label: format!("\x00top:{}", msg.runner_label),
+                    stream: crate::runner::StreamType::Status,
+                    content: String::new(),
+                    command: command.clone(),
+                    ..Default::default()
+                });
+
+                // Top padding
+                for _ in 0..self.box_pad_v {
+                    self.append_output(OutputLine {
+                        runner_label: "\x00box".to_string(),
+                        stream: crate::runner::StreamType::Output,
+                        content: String::new(),
+                        ..Default::default()
+                    });
+                }
+            }
+
+            // Flush buffered content lines
+            if let Some(buffered) = self.pending_output.remove(&msg.task_id) {
+                for mut line in buffered {
+                    line.runner_label = body_label.to_string();
+                    self.append_output(line);
+                }
+            }
+
+            // Truncation marker if the task hit its per-task cap
+            if let Some(suppressed) = self.suppressed_lines.remove(&msg.task_id) {
+                self.append_output(OutputLine {
+                    runner_label: body_label.to_string(),
+                    stream: crate::runner::StreamType::Output,
+                    content: format!("\x1b[2m… ({} lines suppressed)\x1b[0m", suppressed),
+                    ..Default::default()
+                });
+            }
+
+            // Bottom padding
+            let pad_v = if compact { 0 } else { self.box_pad_v };
+            for _ in 0..pad_v {
+                self.append_output(OutputLine {
+                    runner_label: "\x00box".to_string(),
+                    stream: crate::runner::StreamType::Output,
+                    content: String::new(),
+                    ..Default::default()
+                });
+            }
+
+            // Bottom border with runtime, or the separator closing compact output
+            self.append_output(OutputLine {
+                runner_label: if compact { String::new() } else { "\x00bot".to_string() },
+                stream: crate::runner::StreamType::Status,
+                content: runtime,
+                command: command.filter(|_| compact),
+                ..Default::default()
+            });
+
+            self.finish_task(&msg, elapsed);
+        } else {
+            self.session.push_line(msg.task_id, &msg.runner_label, &msg.content);
+            if let Some(capture) = self.captures.get_mut(&msg.task_id) {
+                if self.max_lines_per_task > 0 && capture.lines.len() >= self.max_lines_per_task {
+                    capture.overflowed = true;
+                } else {
+                    capture.lines.push(crate::pager::plain_text(&msg.content));
+                }
+            }
+
+            // Buffer output for this task, up to the per-task cap
+            let content = cap_line_length(msg.content, self.max_line_length);
+            let buffered = self.pending_output.entry(msg.task_id).or_default();
+            if self.collapse_repeats
+                && let Some(last) = buffered.last_mut()
+                && last.stream == msg.stream
+                && last.content == content
+            {
+                last.repeated += 1;
+                return;
+            }
+            if self.max_lines_per_task > 0 && buffered.len() >= self.max_lines_per_task {
+                *self.suppressed_lines.entry(msg.task_id).or_insert(0) += 1;
+                return;
+            }
+            buffered.push(OutputLine {
+                runner_label: msg.runner_label,
+                stream: msg.stream,
+                content,
+                received: Some(chrono::Local::now().time()),
+                ..Default::default()
+            });
+        }
+    }
+
+    /// Whether any task has started and not finished yet
+    fn has_running_tasks(&self) -> bool {
+        !self.task_start_times.is_empty()
+    }
+
+    /// Spinner frame for the output title while any task runs, timed from
+    /// the longest-running one
+    fn spinner_frame(&self, now: Instant) -> Option<&'static str> {
+        let started = self.task_start_times.values().min()?;
+        let step = now.saturating_duration_since(*started).as_millis() / SPINNER_INTERVAL.as_millis();
+        Some(self.spinner_frames[(step % self.spinner_frames.len() as u128) as usize])
+    }
+
+    /// PTY sizes for tasks on a `cols`×`rows` terminal: the inside of an
+    /// output box, so full-width output (progress bars, `ls` columns) fits
+    /// it rather than wrapping. Leaves out the pane and box borders, the
+    /// box padding, the timestamp gutter and the input line. Tasks of a
+    /// parallel batch split the width under `parallel_columns`; single
+    /// commands are always full width.
+    pub fn pty_size(&self, cols: u16, rows: u16) -> crate::runner::PtySizes {
+        // Pane borders, the input box, and the box's own borders and padding
+        let inner_height = (rows as usize).saturating_sub(2 + 3 + 2 + self.box_pad_v * 2);
+        let inner_height = inner_height.clamp(1, u16::MAX as usize) as u16;
+        let inner_width = |columns: usize| {
+            let pane_width = (cols as usize).saturating_sub(2);
+            let box_width = pane_width.saturating_sub(GRID_GAP * (columns - 1)) / columns;
+            let gutter = if self.line_timestamps { TIMESTAMP_GUTTER } else { 0 };
+            let inner_width = box_width.saturating_sub(2 + self.box_pad_h * 2 + gutter);
+            inner_width.clamp(1, u16::MAX as usize) as u16
+        };
+        crate::runner::PtySizes {
+            single: (inner_width(1), inner_height),
+            batch: (inner_width(self.parallel_columns.max(1)), inner_height),
+        }
+    }
+
+    /// Record how many tasks are running and how many wait for a slot
+    pub fn set_task_counts(&mut self, (running, queued): (usize, usize)) {
+        self.task_counts = (running, queued);
+    }
+
+    /// `(running, queued)` once tasks are waiting for a free slot
+    /// under `max_concurrent`
+    fn queued_tasks(&self) -> Option<(usize, usize)> {
+        let (running, queued) = self.task_counts;
+        (queued > 0).then_some((running, queued))
+    }
+
+    /// Standalone tasks running for longer than the fast-command grace period:
+    /// (count, longest runtime). Parallel batches have their own progress.
+    fn slow_running_tasks(&self, now: Instant) -> Option<(usize, Duration)> {
+        let slow: Vec<Duration> = self
+            .task_start_times
+            .iter()
+            .filter(|(id, _)| !self.parallel_pending.contains(id))
+            .map(|(_, &start)| now.saturating_duration_since(start))
+            .filter(|&running| running >= self.fast_command_grace)
+            .collect();
+        slow.iter().max().map(|&longest| (slow.len(), longest))
+    }
+
+    /// The line standing in for a task's output under `[output]
+    /// quiet_success`, `✓ cmd (1.2s)`, if it succeeded and is a quiet one
+    fn quiet_summary(&self, msg: &OutputMessage, command: Option<&str>, runtime: &str) -> Option<String> {
+        if msg.stats.is_none_or(|s| s.exit_code != Some(0)) {
+            return None;
+        }
+        let quiet = self.quiet_success || command.is_some_and(|c| self.quiet_commands.iter().any(|r| r.is_match(c)));
+        if !quiet {
+            return None;
+        }
+        let what = match (msg.runner_label.as_str(), command) {
+            ("", Some(command)) => command.to_string(),
+            (label, Some(command)) => format!("{} {}", label, command),
+            (label, None) => label.to_string(),
+        };
+        Some(format!("\x1b[32m✓\x1b[0m {} \x1b[2m({})\x1b[0m", what, runtime))
+    }
+
+    /// Under `[output] parallel_style = "table"`, the row standing in for the
+    /// box of a parallel task that printed exactly one line
+    fn table_row(&mut self, msg: &OutputMessage) -> Option<OutputLine> {
+        if self.parallel_style != ParallelStyle::Table
+            || !msg.runner_label.starts_with('[')
+            || self.suppressed_lines.contains_key(&msg.task_id)
+            || self.pending_output.get(&msg.task_id).is_none_or(|lines| lines.len() != 1)
+        {
+            return None;
+        }
+        let mut row = self.pending_output.remove(&msg.task_id)?.pop()?;
+        let pad = self
+            .parallel_label_width
+            .saturating_sub(unicode_width::UnicodeWidthStr::width(msg.runner_label.as_str()));
+        row.runner_label = format!("\x00row:{}{}", msg.runner_label, " ".repeat(pad));
+        Some(row)
+    }
+
+    /// Progress, failure and notification bookkeeping for a completed task
+    fn finish_task(&mut self, msg: &OutputMessage, elapsed: Option<Duration>) {
+        // Update parallel progress if active
+        if let Some((ref mut completed, _)) = self.parallel_progress {
+            *completed += 1;
+        }
+
+        let success = msg.stats.is_some_and(|s| s.exit_code == Some(0));
+        // A cancelled run says nothing about how long the command takes
+        let timed = msg.content != crate::runner::CANCELLED_STATUS;
+
+        if let Some(capture) = self.captures.remove(&msg.task_id) {
+            self.finish_capture(capture, msg);
+        }
+        let task = self.running.remove(&msg.task_id);
+
+        // Notify on long-running work: once per parallel batch, or per standalone task.
+        // A batch counts as one run of its command, failed if any task failed.
+        if self.parallel_pending.remove(&msg.task_id) {
+            // Queued tasks the runner cancelled after a failure didn't fail themselves
+            let aborted = self.parallel_fail_fast
+                && self.parallel_failed > 0
+                && elapsed.is_none()
+                && msg.content == crate::runner::CANCELLED_STATUS;
+            if aborted {
+                self.parallel_aborted += 1;
+            } else if !success {
+                self.parallel_failed += 1;
+            }
+            if self.parallel_pending.is_empty() {
+                if self.parallel_aborted > 0 {
+                    self.add_warning(format!(
+                        "fail-fast: aborted {} remaining due to failure",
+                        self.parallel_aborted
+                    ));
+                }
+                if let Some(command) = self.parallel_command.take() {
+                    self.record_outcome(&command, self.parallel_failed == 0);
+                    if let Some(started) = self.parallel_started.filter(|_| timed) {
+                        self.record_duration(&command, started.elapsed());
+                    }
+                }
+                if let Some(started) = self.parallel_started.take() {
+                    let total = self.parallel_progress.map_or(0, |(_, t)| t);
+                    self.maybe_notify(started.elapsed(), &format!("{} parallel tasks", total));
+                }
+            }
+        } else {
+            if let Some(command) = task.filter(|task| task.records_outcome).map(|task| task.command) {
+                self.record_outcome(&command, success);
+                if let Some(elapsed) = elapsed.filter(|_| timed) {
+                    self.record_duration(&command, elapsed);
+                }
+                if msg.stats.is_some_and(|s| s.exit_code == Some(COMMAND_NOT_FOUND)) {
+                    self.hint_similar_command(&command);
+                }
+            }
+            if let Some(elapsed) = elapsed {
+                self.maybe_notify(elapsed, "Command");
+            }
+        }
+    }
+
+    /// After a "command not found" exit, suggest the known program closest
+    /// to the one typed, e.g. `did you mean: git status?` for `gti status`
+    fn hint_similar_command(&mut self, command: &str) {
+        let command = command.trim_start();
+        let program = command.split_whitespace().next().unwrap_or("");
+        if let Some(closest) = self.searcher.closest_program(program) {
+            let corrected = format!("{}{}", closest, &command[program.len()..]);
+            self.add_warning(format!("did you mean: {}?", corrected));
+        }
+    }
+
+    fn record_outcome(&mut self, command: &str, success: bool) {
+        if let Err(e) = self.searcher.record_outcome(command, success) {
+            log::warn!("Failed to record command outcome: {}", e);
+        }
+    }
+
+    fn record_duration(&mut self, command: &str, elapsed: Duration) {
+        if let Err(e) = self.searcher.record_duration(command, elapsed) {
+            log::warn!("Failed to record command runtime: {}", e);
+        }
+    }
+
+    /// Ring the bell and/or show a desktop notification if `elapsed` exceeds the threshold
+    fn maybe_notify(&self, elapsed: Duration, what: &str) {
+        let Some(threshold) = self.notify_after else {
+            return;
+        };
+        if elapsed < threshold {
+            return;
+        }
+
+        if self.notify_bell {
+            let mut stdout = io::stdout();
+            let _ = stdout.write_all(b"\x07");
+            let _ = stdout.flush();
+        }
+
+        if self.notify_desktop {
+            let body = format!("{} finished in {}", what, format_runtime(elapsed));
+            // D-Bus round trips can block; keep them off the UI thread
+            std::thread::spawn(move || {
+                if let Err(e) = notify_rust::Notification::new()
+                    .summary("mux")
+                    .body(&body)
+                    .show()
+                {
+                    log::warn!("Failed to show desktop notification: {}", e);
+                }
+            });
         }
     }
 
     /// Append a line to the output buffer with cap enforcement and auto-scroll
     fn append_output(&mut self, line: OutputLine) {
+        self.cleared_backup = None;
         self.output.push_back(line);
+        self.evict_excess_output();
+
+        if self.auto_scroll {
+            self.scroll_to_bottom();
+        }
+    }
+
+    /// Drop the oldest lines beyond `max_output_lines`, keeping the view in place
+    fn evict_excess_output(&mut self) {
+        while self.output.len() > self.max_output_lines {
+            self.output.pop_front();
+            self.evicted_lines += 1;
+            self.scroll_offset = self.scroll_offset.saturating_sub(1);
+        }
+    }
+
+    /// Dim line shown above the output once lines have been dropped from its front
+    fn dropped_lines_marker(&self) -> Option<OutputLine> {
+        (self.evicted_lines > 0).then(|| OutputLine {
+            runner_label: String::new(),
+            stream: crate::runner::StreamType::Output,
+            content: format!("\x1b[2m⋯ {} earlier lines dropped ⋯\x1b[0m", self.evicted_lines),
+            ..Default::default()
+        })
+    }
+
+    /// Set scroll_offset so the bottom of the output is visible.
+    /// Called with the actual panel height during rendering; defaults to a large value
+    /// so the rendering logic clamps it.
+    fn scroll_to_bottom(&mut self) {
+        // Set to a value that rendering will clamp to show the last page
+        self.scroll_offset = usize::MAX;
+    }
+
+    /// Use the input as a label filter for the output pane, or clear the
+    /// filter when the input is empty
+    pub fn toggle_label_filter(&mut self) {
+        let fragment = std::mem::take(&mut self.input);
+        self.cursor_position = 0;
+        let fragment = fragment.trim();
+        self.label_filter = (!fragment.is_empty()).then(|| fragment.to_string());
+        self.update_suggestions();
+        self.scroll_to_bottom();
+        self.auto_scroll = true;
+    }
+
+    /// Open the pager on a snapshot of the current output
+    pub fn open_pager(&mut self) {
+        self.pager = Some(Pager::new(self.transcript()));
+    }
+
+    /// The output as plain text, one string per line, boxes drawn with the
+    /// configured characters
+    pub fn transcript(&self) -> Vec<String> {
+        self.output.iter().map(|l| pager_line(l, &self.box_chars, self.show_command)).collect()
+    }
+
+    pub fn close_pager(&mut self) {
+        self.pager = None;
+    }
+
+    pub fn pager_mut(&mut self) -> Option<&mut Pager> {
+        self.pager.as_mut()
+    }
+
+    // --- Argument picker ---
+
+    /// List the argument lists the command being typed was run with
+    pub fn open_arg_picker(&mut self) {
+        match self.suggestion_engine.argument_sets(&self.input) {
+            Some(sets) => self.arg_picker = Some(ArgPicker::new(sets)),
+            None if self.input.trim().is_empty() => {
+                self.add_warning("args: type a command first".to_string());
+            }
+            None => self.add_warning(format!("args: no arguments recorded for `{}`", self.input.trim())),
+        }
+    }
+
+    pub fn close_arg_picker(&mut self) {
+        self.arg_picker = None;
+    }
+
+    pub fn arg_picker_mut(&mut self) -> Option<&mut ArgPicker> {
+        self.arg_picker.as_mut()
+    }
+
+    /// Put the command with the highlighted argument list in the input
+    pub fn apply_arg_picker(&mut self) {
+        let Some(picker) = self.arg_picker.take() else {
+            return;
+        };
+        if let Some(input) = picker.apply(&self.input) {
+            self.input = input;
+            self.cursor_position = self.input.len();
+            self.update_suggestions();
+        }
+    }
+
+    // --- Settings overlay ---
+
+    pub fn open_settings(&mut self) {
+        self.settings = Some(SettingsView::default());
+    }
+
+    pub fn close_settings(&mut self) {
+        self.settings = None;
+    }
+
+    pub fn settings_mut(&mut self) -> Option<&mut SettingsView> {
+        self.settings.as_mut()
+    }
+
+    /// Current value of a setting, for display
+    fn setting_value(&self, setting: Setting) -> String {
+        match setting {
+            Setting::BoxPaddingHorizontal => self.box_pad_h.to_string(),
+            Setting::BoxPaddingVertical => self.box_pad_v.to_string(),
+            Setting::MaxLines => self.max_output_lines.to_string(),
+            Setting::LongLines => long_line_mode_name(self.long_lines).to_string(),
+            Setting::ShowThroughput => if self.show_throughput { "on" } else { "off" }.to_string(),
+        }
+    }
+
+    /// Current value of a setting as written to config.toml
+    fn setting_config_value(&self, setting: Setting) -> toml_edit::Value {
+        match setting {
+            Setting::BoxPaddingHorizontal => (self.box_pad_h as i64).into(),
+            Setting::BoxPaddingVertical => (self.box_pad_v as i64).into(),
+            Setting::MaxLines => (self.max_output_lines as i64).into(),
+            Setting::LongLines => long_line_mode_name(self.long_lines).into(),
+            Setting::ShowThroughput => self.show_throughput.into(),
+        }
+    }
+
+    /// Step the selected setting up or down; takes effect immediately.
+    /// Padding applies to boxes drawn from now on.
+    pub fn adjust_setting(&mut self, increase: bool) {
+        let Some(setting) = self.settings.as_ref().map(SettingsView::selected) else {
+            return;
+        };
+        let step = |value: usize, step: usize, min: usize, max: usize| {
+            if increase {
+                value.saturating_add(step).min(max)
+            } else {
+                value.saturating_sub(step).max(min)
+            }
+        };
+        match setting {
+            Setting::BoxPaddingHorizontal => {
+                self.box_pad_h = step(self.box_pad_h, 1, 0, crate::settings::MAX_BOX_PADDING);
+            }
+            Setting::BoxPaddingVertical => {
+                self.box_pad_v = step(self.box_pad_v, 1, 0, crate::settings::MAX_BOX_PADDING);
+            }
+            Setting::MaxLines => {
+                let step_size = crate::settings::MAX_LINES_STEP;
+                self.max_output_lines = step(self.max_output_lines, step_size, step_size, usize::MAX);
+                self.evict_excess_output();
+            }
+            Setting::LongLines => {
+                self.long_lines = match self.long_lines {
+                    LongLineMode::Truncate => LongLineMode::Wrap,
+                    LongLineMode::Wrap => LongLineMode::Truncate,
+                };
+            }
+            Setting::ShowThroughput => self.show_throughput = !self.show_throughput,
+        }
+    }
+
+    /// Write the overlay's settings to the global config file
+    pub fn save_settings(&mut self) {
+        let result = crate::paths::get_config_path()
+            .map_err(Box::<dyn std::error::Error>::from)
+            .and_then(|path| {
+                self.save_settings_to(&path)?;
+                Ok(path)
+            });
+        let message = match result {
+            Ok(path) => format!("Saved to {}", path.display()),
+            Err(e) => format!("Save failed: {}", e),
+        };
+        if let Some(view) = &mut self.settings {
+            view.set_message(message);
+        }
+    }
+
+    fn save_settings_to(&self, path: &std::path::Path) -> Result<(), Box<dyn std::error::Error>> {
+        let values: Vec<_> = Setting::ALL
+            .iter()
+            .map(|&setting| {
+                let (table, key) = setting.config_key();
+                (table, key, self.setting_config_value(setting))
+            })
+            .collect();
+        crate::settings::write_config_values(path, &values)
+    }
+
+    /// Remove the most recent box from the output, keeping everything else.
+    /// With a label filter, the last box matching it. Boxes are only added
+    /// once their task completes, so a running task is never cut short.
+    pub fn dismiss_last_box(&mut self) {
+        let Some(range) = last_box_range(&self.output, self.label_filter.as_deref()) else {
+            return;
+        };
+        let (start, removed) = (*range.start(), range.end() - range.start() + 1);
+        self.output.drain(range);
+        if start < self.scroll_offset {
+            self.scroll_offset = self.scroll_offset.saturating_sub(removed).max(start);
+        }
+        if self.auto_scroll {
+            self.scroll_to_bottom();
+        }
+    }
+
+    // --- Folding ---
+
+    /// The complete box at the top of the output view, or the first one
+    /// below it
+    fn box_in_view(&self) -> Option<std::ops::RangeInclusive<usize>> {
+        let shown = shown_output(&self.output, self.label_filter.as_deref());
+        // The dropped-lines marker is shown first, but isn't part of `output`
+        let marker = usize::from(self.evicted_lines > 0);
+        let top = self
+            .scroll_offset
+            .min((shown.len() + marker).saturating_sub(self.output_height))
+            .saturating_sub(marker);
+        let first = shown.get(top)?;
+        let start = self.output.iter().position(|line| std::ptr::eq(line, *first))?;
+        (start..self.output.len()).find_map(|i| box_range_at(&self.output, i))
+    }
+
+    /// Fold or unfold the box spanning `range`, keeping its top in view
+    fn set_folded(&mut self, range: std::ops::RangeInclusive<usize>, folded: bool) {
+        let top = *range.start();
+        if self.output[top].folded == folded {
+            return;
+        }
+        self.output[top].content = if folded {
+            let lines: Vec<&OutputLine> = self.output.range(range).collect();
+            fold_summary(&lines)
+        } else {
+            String::new()
+        };
+        self.output[top].folded = folded;
+    }
+
+    /// Fold the box at the top of the view to a one-line header, or unfold it
+    pub fn toggle_fold_in_view(&mut self) {
+        let Some(range) = self.box_in_view() else {
+            return;
+        };
+        let top = *range.start();
+        let folded = !self.output[top].folded;
+        self.set_folded(range, folded);
+        if self.auto_scroll {
+            self.scroll_to_bottom();
+        } else {
+            // Keep the box's header where the view was
+            let shown = shown_output(&self.output, self.label_filter.as_deref());
+            if let Some(index) = shown.iter().position(|line| std::ptr::eq(*line, &self.output[top])) {
+                self.scroll_offset = index + usize::from(self.evicted_lines > 0);
+            }
+        }
+    }
+
+    /// Fold (or unfold) every complete box in the output
+    pub fn fold_all(&mut self, folded: bool) {
+        let mut i = 0;
+        while i < self.output.len() {
+            match box_range_at(&self.output, i) {
+                Some(range) => {
+                    i = range.end() + 1;
+                    self.set_folded(range, folded);
+                }
+                None => i += 1,
+            }
+        }
+        if self.auto_scroll {
+            self.scroll_to_bottom();
+        }
+    }
+
+    // --- Command selection ---
+
+    /// Index in `output` of the selected command's line
+    fn selected_command_index(&self) -> Option<usize> {
+        self.output.iter().position(|line| line.selected)
+    }
+
+    /// The command selected in the output pane, if any
+    pub fn selected_command(&self) -> Option<&str> {
+        self.output.get(self.selected_command_index()?)?.command.as_deref()
+    }
+
+    /// Select the next (or previous) finished command shown in the output,
+    /// by its box header, table row, compact rule or quiet summary, and
+    /// scroll it into view.
+    /// Without a selection, start from the top of the view.
+    pub fn select_command(&mut self, forward: bool) {
+        let marker = usize::from(self.evicted_lines > 0);
+        let (candidates, current, shown_len) = {
+            let shown = shown_output(&self.output, self.label_filter.as_deref());
+            let candidates: Vec<usize> = (0..shown.len()).filter(|&i| shown[i].command.is_some()).collect();
+            let current = shown.iter().position(|line| line.selected);
+            (candidates, current, shown.len())
+        };
+        if candidates.is_empty() {
+            return;
+        }
+        let view_top = self
+            .scroll_offset
+            .min((shown_len + marker).saturating_sub(self.output_height))
+            .saturating_sub(marker);
+        let target = match (current, forward) {
+            (Some(at), true) => candidates.iter().find(|&&i| i > at),
+            (Some(at), false) => candidates.iter().rev().find(|&&i| i < at),
+            (None, true) => candidates.iter().find(|&&i| i >= view_top),
+            (None, false) => candidates.iter().rev().find(|&&i| i < view_top + self.output_height),
+        };
+        let Some(&target) = target.or(current.as_ref()) else {
+            return;
+        };
+
+        self.clear_command_selection();
+        let shown = shown_output(&self.output, self.label_filter.as_deref());
+        let index = self.output.iter().position(|line| std::ptr::eq(line, shown[target]));
+        if let Some(index) = index {
+            self.output[index].selected = true;
+        }
+        // Keep the view where it is while the line is in it
+        let (top, rows) = (self.scroll_offset.saturating_sub(marker), self.output_height.max(1));
+        if target < top || target >= top + rows || self.auto_scroll {
+            self.scroll_offset = target + marker;
+        }
+        self.auto_scroll = false;
+    }
+
+    pub fn clear_command_selection(&mut self) {
+        if let Some(index) = self.selected_command_index() {
+            self.output[index].selected = false;
+        }
+    }
+
+    /// Put the selected command in the input to edit it, back in the input
+    pub fn edit_selected_command(&mut self) {
+        let Some(command) = self.selected_command().map(str::to_string) else {
+            return;
+        };
+        self.clear_command_selection();
+        self.output_focused = false;
+        self.input = command;
+        self.cursor_position = self.input.len();
+        self.update_suggestions();
+    }
+
+    /// Run the selected command again, leaving the input as it was
+    pub fn rerun_selected_command(&mut self, runner: &mut TaskRunner) {
+        let Some(command) = self.selected_command().map(str::to_string) else {
+            return;
+        };
+        self.clear_command_selection();
+        let draft = std::mem::replace(&mut self.input, command);
+        let cursor = self.cursor_position;
+        self.submit_command(runner);
+        self.input = draft;
+        self.cursor_position = cursor;
+        self.update_suggestions();
+    }
+
+    /// Ctrl+L: clear the output, keeping it for `undo_clear_output`. On an
+    /// already cleared pane, a second Ctrl+L brings the output back.
+    pub fn clear_output(&mut self) {
+        if self.output.is_empty() {
+            self.undo_clear_output();
+            return;
+        }
+        self.cleared_backup = Some(ClearedOutput {
+            lines: std::mem::take(&mut self.output),
+            evicted_lines: std::mem::take(&mut self.evicted_lines),
+            scroll_offset: self.scroll_offset,
+            auto_scroll: self.auto_scroll,
+        });
+        self.scroll_offset = 0;
+        self.auto_scroll = true;
+    }
+
+    /// Ctrl+Z: restore the output removed by the last clear, as long as
+    /// nothing new was printed since
+    pub fn undo_clear_output(&mut self) {
+        let Some(backup) = self.cleared_backup.take() else {
+            return;
+        };
+        self.output = backup.lines;
+        self.evicted_lines = backup.evicted_lines;
+        self.scroll_offset = backup.scroll_offset;
+        self.auto_scroll = backup.auto_scroll;
+    }
+
+    /// Ctrl+Z: bring back the output removed by Ctrl+L if there is any,
+    /// otherwise suspend mux like any job under shell job control
+    pub fn undo_clear_or_suspend(&mut self) {
+        if self.cleared_backup.is_some() {
+            self.undo_clear_output();
+        } else {
+            self.suspend_requested = true;
+        }
+    }
+
+    /// Whether Ctrl+Z asked to suspend since the last call
+    pub fn take_suspend_request(&mut self) -> bool {
+        std::mem::take(&mut self.suspend_requested)
+    }
+
+    /// Add a warning message to the output (for startup errors, etc.)
+    pub fn add_warning(&mut self, message: String) {
+        self.cleared_backup = None;
+        self.output.push_back(OutputLine {
+            runner_label: String::new(),
+            stream: crate::runner::StreamType::Status,
+            content: message,
+            ..Default::default()
+        });
+    }
+
+    // --- History recall ---
+
+    /// Recall the most recent command from history into the input field
+    pub fn recall_last_command(&mut self) {
+        if !self.input.is_empty() {
+            return;
+        }
+        if let Some(cmd) = self.searcher.most_recent_command() {
+            self.input = cmd.command.clone();
+            self.cursor_position = self.input.len();
+            self.update_suggestions();
+        }
+    }
+
+    // --- Output scrolling ---
+
+    pub fn scroll_up(&mut self, lines: usize) {
+        self.scroll_offset = self.scroll_offset.saturating_sub(lines);
+        self.auto_scroll = false;
+    }
+
+    pub fn scroll_down(&mut self, lines: usize) {
+        self.scroll_offset = self.scroll_offset.saturating_add(lines);
+        // auto_scroll is re-enabled by the rendering logic when we're at the bottom
+    }
+
+    pub fn scroll_half_page_up(&mut self) {
+        self.scroll_up((self.output_height / 2).max(1));
+    }
+
+    pub fn scroll_half_page_down(&mut self) {
+        self.scroll_down((self.output_height / 2).max(1));
+    }
+
+    pub fn scroll_page_up(&mut self) {
+        self.scroll_up(self.output_height);
+    }
+
+    pub fn scroll_page_down(&mut self) {
+        self.scroll_down(self.output_height);
+    }
+
+    pub fn jump_to_top(&mut self) {
+        self.scroll_offset = 0;
+        self.auto_scroll = false;
+    }
+
+    pub fn jump_to_bottom(&mut self) {
+        self.scroll_to_bottom();
+        self.auto_scroll = true;
+    }
+
+    pub fn set_output_throttled(&mut self, throttled: bool) {
+        if throttled && !self.output_throttled {
+            log::info!("Output channel full; tasks are waiting for the display");
+        }
+        self.output_throttled = throttled;
+    }
+
+    pub fn is_output_focused(&self) -> bool {
+        self.output_focused
+    }
+
+    /// What a `[[pipes]]` program receives: with the output focused, the
+    /// text of the box in view; otherwise the highlighted suggestion, or the
+    /// input if there's none
+    fn pipe_text(&self) -> Option<String> {
+        if self.output_focused {
+            let range = self.box_in_view()?;
+            let text: String = self
+                .output
+                .range(range)
+                .filter(|line| line.runner_label == "\x00box")
+                .map(|line| crate::pager::plain_text(&line.content) + "\n")
+                .collect();
+            return Some(text);
+        }
+        let command = match self.suggestions.get(self.selected_suggestion) {
+            Some(suggestion) if self.suggestions_enabled => {
+                let (typed, new) = self.suggestion_full_preview(suggestion);
+                typed + &new
+            }
+            _ => self.input.clone(),
+        };
+        Some(command).filter(|command| !command.trim().is_empty())
+    }
+
+    /// Send the selection to the `[[pipes]]` program bound to `key`.
+    /// Returns false if no pipe uses `key`.
+    pub fn run_pipe(&mut self, key: crossterm::event::KeyEvent) -> bool {
+        let Some(pipe) = self.pipes.iter().find(|pipe| pipe.matches(key)) else {
+            return false;
+        };
+        if let Some(text) = self.pipe_text()
+            && let Err(e) = pipe.send(text)
+        {
+            self.add_warning(e.to_string());
+        }
+        true
+    }
+
+    /// Switch keys between editing the input and scrolling the output
+    pub fn toggle_output_focus(&mut self) {
+        self.output_focused = !self.output_focused;
+        if !self.output_focused {
+            self.clear_command_selection();
+        }
+    }
+
+    /// Wait for the second key of a chord starting with `key`
+    pub fn start_chord(&mut self, key: crossterm::event::KeyEvent) {
+        self.pending_chord = Some((key, Instant::now()));
+    }
+
+    /// The first key of the chord in progress, ending it
+    pub fn take_pending_chord(&mut self) -> Option<crossterm::event::KeyEvent> {
+        self.pending_chord.take().map(|(key, _)| key)
+    }
+
+    /// When the chord in progress times out
+    pub fn chord_deadline(&self) -> Option<Instant> {
+        self.pending_chord.map(|(_, started)| started + keymap::CHORD_TIMEOUT)
+    }
+
+    /// Add a digit to the count for the next scroll key (`10j`). A count
+    /// can't start with 0, and stops growing at `MAX_COUNT`.
+    pub fn push_count_digit(&mut self, digit: u32) {
+        if digit == 0 && self.pending_count.is_none() {
+            return;
+        }
+        let count = self.pending_count.unwrap_or(0);
+        self.pending_count = Some((count * 10 + digit as usize).min(MAX_COUNT));
+    }
+
+    /// The count typed for this key, 1 without one, ending it
+    pub fn take_count(&mut self) -> usize {
+        self.pending_count.take().unwrap_or(1)
+    }
+
+    pub fn pending_count(&self) -> Option<usize> {
+        self.pending_count
+    }
+
+    // --- Suggestions ---
+
+    fn update_suggestions(&mut self) {
+        if self.tagging.is_some() {
+            // The input holds tags, not a query
+            return;
+        }
+        if self.input.is_empty()
+            && let Some(query) = self.query_candidate.take()
+        {
+            self.remember_query(query);
+        }
+        if !self.suggestions_enabled {
+            self.suggestions.clear();
+            self.selected_suggestion = 0;
+            return;
+        }
+        let now = chrono::Local::now().timestamp();
+        self.suggestions = if let Some(filter) = crate::searcher::parse_time_filter(&self.input, now) {
+            // `@since:2d query` — recent full commands only
+            self.searcher
+                .search_in_range(filter.query, filter.since, now, 8)
+                .into_iter()
+                .map(|result| Suggestion {
+                    text: result.command,
+                    score: result.score as f32,
+                    suggestion_type: crate::suggest::SuggestionType::FullCommand,
+                })
+                .collect()
+        } else if let Some(filter) = crate::searcher::parse_tag_filter(&self.input) {
+            // `@tag:deploy query` — full commands with that tag only
+            self.searcher
+                .search_tagged(filter.query, &filter.tag, 8)
+                .into_iter()
+                .map(|result| Suggestion {
+                    text: result.command,
+                    score: result.score as f32,
+                    suggestion_type: crate::suggest::SuggestionType::FullCommand,
+                })
+                .collect()
+        } else {
+            self.suggestion_engine
+                .suggest(&self.input, &mut self.searcher, 8)
+        };
+        self.selected_suggestion = 0;
+        self.suggestion_browsed = false;
+        if !self.suggestions.is_empty() && !self.input.trim().is_empty() {
+            self.query_candidate = Some(self.input.clone());
+        }
+    }
+
+    /// Keep `query` for Alt+Up, once: a search is remembered when it's left
+    /// behind, by clearing the input or accepting a command it found
+    fn remember_query(&mut self, query: String) {
+        self.queries.retain(|q| *q != query);
+        self.queries.push_front(query);
+        self.queries.truncate(QUERY_HISTORY_LIMIT);
+        self.query_recall = None;
+    }
+
+    /// Alt+Up (`older`) / Alt+Down: step through this session's queries,
+    /// back to the input as it was before the first step. Editing a recalled
+    /// query and pressing Alt+Up starts again from the newest.
+    pub fn cycle_query(&mut self, older: bool) {
+        let current = self
+            .query_recall
+            .filter(|&i| self.queries.get(i) == Some(&self.input));
+        if current.is_none() {
+            if !older || self.queries.is_empty() {
+                return;
+            }
+            self.query_draft = self.input.clone();
+        }
+        self.query_recall = match (current, older) {
+            (None, _) => Some(0),
+            (Some(i), true) => Some((i + 1).min(self.queries.len() - 1)),
+            (Some(0), false) => None,
+            (Some(i), false) => Some(i - 1),
+        };
+        self.input = match self.query_recall {
+            Some(i) => self.queries[i].clone(),
+            None => self.query_draft.clone(),
+        };
+        self.cursor_position = self.input.len();
+        // Stepping through queries doesn't reorder them; the draft is kept
+        // like any other input
+        self.query_candidate = None;
+        self.update_suggestions();
+        if self.query_recall.is_some() {
+            self.query_candidate = None;
+        }
+    }
+
+    /// Turn suggestions (panel and inline preview) on or off for this session
+    pub fn toggle_suggestions(&mut self) {
+        self.suggestions_enabled = !self.suggestions_enabled;
+        self.update_suggestions();
+    }
+
+    /// Suggest rarely used commands too, or go back to `[suggest] min_frequency`
+    pub fn toggle_rare_commands(&mut self) {
+        if self.min_frequency <= 1 {
+            self.add_warning("Every command is suggested already ([suggest] min_frequency is 1)".to_string());
+            return;
+        }
+        self.include_rare = !self.include_rare;
+        let floor = if self.include_rare { 1 } else { self.min_frequency };
+        self.suggestion_engine.set_min_frequency(floor);
+        self.update_suggestions();
+    }
+
+    /// Switch between panel and ghost text, ghost text only, and panel only,
+    /// and remember the choice for the next session
+    pub fn cycle_suggestion_display(&mut self) {
+        self.suggestion_display = self.suggestion_display.next();
+        if let Some(path) = &self.ui_state_path {
+            let state = crate::state::UiState {
+                suggestion_display: Some(self.suggestion_display),
+            };
+            if let Err(e) = state.save(path) {
+                self.add_warning(format!("Failed to save {}: {}", path.display(), e));
+            }
+        }
+    }
+
+    pub fn accept_suggestion(&mut self) {
+        if self.suggestions.is_empty() || self.selected_suggestion >= self.suggestions.len() {
+            return;
+        }
+
+        let suggestion = &self.suggestions[self.selected_suggestion];
+
+        match suggestion.suggestion_type {
+            crate::suggest::SuggestionType::FullCommand => {
+                let query = std::mem::replace(&mut self.input, suggestion.text.clone());
+                self.cursor_position = self.input.len();
+                self.update_suggestions();
+                // The command found is no query of its own
+                self.query_candidate = None;
+                if !query.trim().is_empty() {
+                    self.remember_query(query);
+                }
+                return;
+            }
+            crate::suggest::SuggestionType::Argument
+            | crate::suggest::SuggestionType::ArgumentValue
+            | crate::suggest::SuggestionType::ArgumentCombo
+            | crate::suggest::SuggestionType::ArgumentPair => {
+                let (start, text) = self.completion_edit(suggestion);
+                self.input.truncate(start);
+                self.input.push_str(&text);
+                self.cursor_position = self.input.len();
+            }
+        }
+
+        self.update_suggestions();
+    }
+
+    /// Copy the highlighted suggestion into the input as an editable draft.
+    /// Unlike `accept_suggestion`, the suggestion list is left as-is so the
+    /// user can keep browsing and yank a different entry.
+    pub fn yank_suggestion(&mut self) {
+        let Some(suggestion) = self.suggestions.get(self.selected_suggestion) else {
+            return;
+        };
+        let (typed, new) = self.suggestion_full_preview(suggestion);
+        self.input = typed + &new;
+        self.cursor_position = self.input.len();
+    }
+
+    pub fn next_suggestion(&mut self) {
+        if !self.suggestions.is_empty() {
+            self.selected_suggestion = (self.selected_suggestion + 1) % self.suggestions.len();
+            self.suggestion_browsed = true;
+        }
+    }
+
+    pub fn prev_suggestion(&mut self) {
+        if !self.suggestions.is_empty() {
+            self.suggestion_browsed = true;
+            if self.selected_suggestion == 0 {
+                self.selected_suggestion = self.suggestions.len() - 1;
+            } else {
+                self.selected_suggestion -= 1;
+            }
+        }
+    }
+
+    pub fn get_suggestion_preview(&self) -> Option<String> {
+        if self.suggestions.is_empty() || self.selected_suggestion >= self.suggestions.len() {
+            return None;
+        }
+
+        let suggestion = &self.suggestions[self.selected_suggestion];
+
+        match suggestion.suggestion_type {
+            crate::suggest::SuggestionType::FullCommand => {
+                if suggestion.text.starts_with(&self.input) {
+                    Some(suggestion.text.get(self.input.len()..)
+                        .unwrap_or("").to_string())
+                } else if self.input.is_empty() {
+                    Some(suggestion.text.clone())
+                } else {
+                    None
+                }
+            }
+            crate::suggest::SuggestionType::Argument
+            | crate::suggest::SuggestionType::ArgumentValue
+            | crate::suggest::SuggestionType::ArgumentCombo
+            | crate::suggest::SuggestionType::ArgumentPair => {
+                let (start, text) = self.completion_edit(suggestion);
+                let current_word = self.input.get(start..).unwrap_or("");
+                if text.starts_with(current_word) {
+                    Some(text.get(current_word.len()..).unwrap_or("").to_string())
+                } else {
+                    Some(format!(" {}", text))
+                }
+            }
+        }
+    }
+
+    /// Compute the full resulting command for a suggestion, split into
+    /// (already_typed_prefix, new_suggestion_suffix) for display purposes.
+    pub fn suggestion_full_preview(&self, suggestion: &Suggestion) -> (String, String) {
+        match suggestion.suggestion_type {
+            crate::suggest::SuggestionType::FullCommand => {
+                // The suggestion IS the full command
+                if suggestion.text.starts_with(&self.input) && !self.input.is_empty() {
+                    (
+                        self.input.clone(),
+                        suggestion.text.get(self.input.len()..)
+                            .unwrap_or("").to_string(),
+                    )
+                } else {
+                    (String::new(), suggestion.text.clone())
+                }
+            }
+            crate::suggest::SuggestionType::Argument
+            | crate::suggest::SuggestionType::ArgumentValue
+            | crate::suggest::SuggestionType::ArgumentCombo
+            | crate::suggest::SuggestionType::ArgumentPair => {
+                let (start, text) = self.completion_edit(suggestion);
+                (self.input.get(..start).unwrap_or("").to_string(), text)
+            }
+        }
+    }
+
+    /// Where an argument suggestion goes in the input: the byte offset of the
+    /// token being typed (quotes included), and the text that replaces it.
+    /// Values are quoted to match the partial, e.g. `"fix` → `"fix bug"`.
+    fn completion_edit(&self, suggestion: &Suggestion) -> (usize, String) {
+        let start = crate::suggest::partial_token_start(&self.input);
+        let text = match suggestion.suggestion_type {
+            crate::suggest::SuggestionType::ArgumentValue => {
+                crate::suggest::quote_value(&suggestion.text, self.input.get(start..).unwrap_or(""))
+            }
+            _ => suggestion.text.clone(),
+        };
+        (start, text)
+    }
+
+    /// Build colorized spans for a full command suggestion.
+    /// Tokens are classified as: typed prefix (dim gray), argument (cyan), value (green),
+    /// or subcommand (bold white).
+    pub fn colorize_command_suggestion<'a>(&self, suggestion: &Suggestion) -> Vec<Span<'a>> {
+        let tokens: Vec<&str> = suggestion.text.split_whitespace().collect();
+        let input_trimmed = self.input.trim_start();
+
+        // Count how many leading tokens match what's already typed
+        let input_tokens: Vec<&str> = input_trimmed.split_whitespace().collect();
+        let typed_count = input_tokens
+            .iter()
+            .zip(tokens.iter())
+            .take_while(|(a, b)| a == b)
+            .count();
+
+        // Find where the prefix ends (first '-' token)
+        let prefix_end = tokens
+            .iter()
+            .position(|t| t.starts_with('-'))
+            .unwrap_or(tokens.len());
+
+        let mut spans = Vec::new();
+
+        for (i, tok) in tokens.iter().enumerate() {
+            if i > 0 {
+                spans.push(Span::raw(" "));
+            }
+
+            let style = if i < typed_count {
+                // Already typed — dim
+                Style::default().fg(self.palette.dim)
+            } else if i < prefix_end {
+                // Subcommand token (not yet typed)
+                Style::default().fg(self.palette.text).add_modifier(Modifier::BOLD)
+            } else if tok.starts_with('-') && *tok != "--" {
+                // Argument token
+                Style::default().fg(self.palette.primary)
+            } else {
+                // Value token
+                Style::default().fg(self.palette.success)
+            };
+
+            spans.push(Span::styled(tok.to_string(), style));
+        }
+
+        spans
+    }
+
+    // --- Quit ---
+
+    /// Handle a quit key press (Ctrl+C, Ctrl+D, Esc by default). Returns true if should quit.
+    pub fn try_quit(&mut self) -> bool {
+        if let Some(last) = self.last_quit_press
+            && last.elapsed() < std::time::Duration::from_secs(1)
+        {
+            return true; // Second press within 1s — quit
+        }
+        self.last_quit_press = Some(std::time::Instant::now());
+        false
+    }
+
+    /// Handle Esc in the input line. With `[keymap] esc = "clear_then_quit"`
+    /// a typed command or a browsed suggestion is cancelled first; otherwise
+    /// it's a quit key. Returns true if should quit.
+    pub fn escape(&mut self) -> bool {
+        if self.esc_action == EscAction::ClearThenQuit && (!self.input.is_empty() || self.suggestion_browsed) {
+            self.input.clear();
+            self.cursor_position = 0;
+            self.last_quit_press = None;
+            self.update_suggestions();
+            return false;
+        }
+        self.try_quit()
+    }
+
+    /// What the input would expand to if submitted, with session variables
+    /// filled in as on submit; see `parallel_preview`
+    fn parallel_preview(&self) -> Option<String> {
+        if !self.input.contains('[') {
+            return None;
+        }
+        parallel_preview(&crate::vars::expand_vars(&self.input, &self.session_vars))
+    }
+
+    /// Whether the "press again to quit" hint should be shown
+    pub fn is_quit_hint_active(&self) -> bool {
+        self.last_quit_press
+            .is_some_and(|t| t.elapsed() < std::time::Duration::from_secs(1))
+    }
+}
+
+/// The output, suggestion panel and input areas of the screen. The output
+/// fills what the other two leave; the panel always sits next to the input,
+/// between it and the output.
+fn screen_areas(area: Rect, suggestion_height: u16, input_height: u16, position: InputPosition) -> [Rect; 3] {
+    let output = Constraint::Min(1);
+    let suggestions = Constraint::Length(suggestion_height);
+    let input = Constraint::Length(input_height);
+    match position {
+        InputPosition::Bottom => {
+            let [output, suggestions, input] = Layout::vertical([output, suggestions, input]).areas(area);
+            [output, suggestions, input]
+        }
+        InputPosition::Top => {
+            let [input, suggestions, output] = Layout::vertical([input, suggestions, output]).areas(area);
+            [output, suggestions, input]
+        }
+    }
+}
+
+/// Commands a wrapping shell may want when mux exits; see `init`
+#[derive(Debug, Default, PartialEq)]
+pub struct ExitCommands {
+    /// Last command run this session, as typed
+    pub last_run: Option<String>,
+    /// Text left in the input line
+    pub draft: String,
+}
+
+pub async fn run_tui(
+    searcher: HistorySearcher,
+    suggestion_engine: SuggestionEngine,
+    startup_warnings: Vec<String>,
+    mut config: Config,
+    ui_state_path: Option<PathBuf>,
+) -> Result<(HistorySearcher, ExitCommands), Box<dyn std::error::Error>> {
+    let guard = TerminalGuard::enter()?;
+    // Query the terminal before the event stream starts consuming stdin
+    config.theme.background = crate::theme::detect_background(config.theme.background);
+    let mut stdout = io::stdout();
+    execute!(stdout, EnterAlternateScreen)?;
+    let backend = CrosstermBackend::new(stdout);
+    let mut terminal = Terminal::new(backend)?;
+
+    let (output_tx, mut output_rx) =
+        tokio::sync::mpsc::channel::<OutputMessage>(config.runner.output_buffer.max(1));
+    let mut runner = TaskRunner::new(output_tx, config.runner.max_concurrent);
+    runner.set_kill_grace(Duration::from_millis(config.runner.kill_grace_ms));
+    let mut event_stream = EventStream::new();
+
+    let mut app = App::new(searcher, suggestion_engine, &config);
+    app.ui_state_path = ui_state_path;
+    for warning in startup_warnings {
+        app.add_warning(warning);
+    }
+    match crate::events::EventSink::open(&config.runner) {
+        Ok(Some(sink)) => runner.set_event_sink(sink),
+        Ok(None) => {}
+        Err(e) => app.add_warning(format!("Task events disabled: {}", e)),
+    }
+    match crate::dotenv::load_configured(&config.runner) {
+        Ok(Some(vars)) => {
+            log::info!("Loaded {} variables for tasks from .env", vars.len());
+            runner.set_env(vars);
+        }
+        Ok(None) => {}
+        Err(e) => app.add_warning(format!(".env not loaded: {}", e)),
+    }
+    let mut output_pipe = match config.logging.output_pipe.as_deref().map(OutputPipe::spawn) {
+        Some(Ok(pipe)) => Some(pipe),
+        Some(Err(e)) => {
+            app.add_warning(format!("Output pipe disabled: {}", e));
+            None
+        }
+        None => None,
+    };
+    if let Ok((cols, rows)) = crossterm::terminal::size() {
+        runner.resize_all(app.pty_size(cols, rows));
+    }
+    let mut should_quit = false;
+    let mut tick = tokio::time::interval(std::time::Duration::from_secs(1));
+    // Redraws the spinner; only polled while tasks run
+    let mut spinner = tokio::time::interval(SPINNER_INTERVAL);
+    spinner.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+    let idle_timeout = (config.tui.idle_timeout_secs > 0)
+        .then(|| Duration::from_secs(config.tui.idle_timeout_secs));
+    let mut last_activity = Instant::now();
+    let sync_interval = (config.sync.interval_secs > 0)
+        .then(|| Duration::from_secs(config.sync.interval_secs));
+    let mut last_sync = Instant::now();
+    let mut live_sync: Option<LiveSync> = None;
+
+    loop {
+        app.set_task_counts(runner.task_counts());
+        terminal.draw(|f| {
+            let show_suggestions = app.shows_suggestion_panel();
+            let box_pad_h = app.box_pad_h;
+            let long_lines = app.long_lines;
+            let separator_color = app.separator_color;
+            let palette = app.palette;
+            let chars = app.box_chars;
+            if let Some(pager) = app.pager.as_mut() {
+                render_pager(f, pager, palette);
+                return;
+            }
+            let area = f.area();
+
+            // Calculate input height: 1 line of content + 2 for borders, grows with wrapping
+            let input_content_width = area.width.saturating_sub(2) as usize; // subtract border columns
+            let input_lines = app
+                .input()
+                .len()
+                .checked_div(input_content_width)
+                .map_or(1, |n| (n + 1) as u16);
+            let input_height = input_lines + 2; // +2 for top/bottom border
+
+            // Suggestions: 5 content lines + 2 borders when visible
+            let suggestion_height: u16 = if show_suggestions { 7 } else { 0 };
+
+            let [output_area, suggestion_area, input_area] =
+                screen_areas(area, suggestion_height, input_height, app.input_position);
+
+            // Output section
+            let output_area_height = output_area.height.saturating_sub(2) as usize; // subtract borders
+            app.output_height = output_area_height.max(1);
+            // Borrow the fields directly so the scroll state below stays assignable
+            let mut shown = shown_output(&app.output, app.label_filter.as_deref());
+            // The marker is the first line, so it's in view when scrolled to the top
+            let dropped_marker = app.dropped_lines_marker();
+            if let Some(marker) = &dropped_marker {
+                shown.insert(0, marker);
+            }
+            let total_lines = shown.len();
+
+            // Clamp scroll_offset: it's the index of the first visible line (top-of-window).
+            // Max value ensures the last page is fully visible.
+            let max_scroll = total_lines.saturating_sub(output_area_height);
+            let scroll_offset = app.scroll_offset().min(max_scroll);
+
+            // Re-enable auto-scroll if we're at the bottom
+            if scroll_offset >= max_scroll {
+                app.auto_scroll = true;
+            }
+            // Update the stored offset to the clamped value
+            app.scroll_offset = scroll_offset;
+
+            let visible_start = scroll_offset;
+            let visible_end = (scroll_offset + output_area_height).min(total_lines);
+
+            let output_width = output_area.width.saturating_sub(2) as usize; // subtract borders
+
+            let look = OutputLook {
+                chars,
+                border: Style::default().fg(palette.dim),
+                separator: Style::default().fg(separator_color),
+                box_pad_h,
+                long_lines,
+                wrap_indent: app.wrap_indent,
+                line_timestamps: app.line_timestamps,
+                show_command: app.show_command,
+            };
+            let mut output_lines: Vec<Line> = if app.parallel_columns > 1 {
+                grid_rows(
+                    &shown,
+                    app.parallel_columns,
+                    output_width,
+                    output_area_height,
+                    scroll_offset,
+                    app.auto_scroll,
+                    &look,
+                )
+            } else {
+                shown
+                    .iter()
+                    .skip(visible_start)
+                    .take(visible_end - visible_start)
+                    .flat_map(|line| render_output_line(line, output_width, &look))
+                    .collect()
+            };
+
+            let output_title = match app.parallel_progress {
+                Some((completed, total)) if completed < total => {
+                    let failed_style = if app.parallel_failed > 0 {
+                        Style::default().fg(palette.error)
+                    } else {
+                        Style::default()
+                    };
+                    Line::from(vec![
+                        Span::raw(format!(" Output ({}/{} done, ", completed, total)),
+                        Span::styled(format!("{} failed", app.parallel_failed), failed_style),
+                        Span::raw(") "),
+                    ])
+                }
+                _ => Line::from(" Output "),
+            };
+            let output_title = match app.spinner_frame(Instant::now()) {
+                Some(frame) => {
+                    let mut title = output_title;
+                    title.spans.insert(0, Span::styled(format!(" {}", frame), Style::default().fg(palette.warning)));
+                    title
+                }
+                None => output_title,
+            };
+            let output_title = match app.label_filter() {
+                Some(fragment) => {
+                    let mut title = output_title;
+                    title.push_span(Span::styled(
+                        format!("[label: {}] ", fragment),
+                        Style::default().fg(palette.warning),
+                    ));
+                    title
+                }
+                None => output_title,
+            };
+            let output_title = match app.queued_tasks() {
+                Some((running, queued)) => {
+                    let mut title = output_title;
+                    title.push_span(Span::styled(
+                        format!("[{} running, {} queued] ", running, queued),
+                        Style::default().fg(palette.dim),
+                    ));
+                    title
+                }
+                None => output_title,
+            };
+            let output_title = match app.slow_running_tasks(Instant::now()) {
+                Some((count, longest)) => {
+                    let mut title = output_title;
+                    title.push_span(Span::styled(
+                        format!("[{} running, {}] ", count, format_runtime(longest)),
+                        Style::default().fg(palette.dim),
+                    ));
+                    title
+                }
+                None => output_title,
+            };
+            let output_title = if app.output_throttled {
+                let mut title = output_title;
+                title.push_span(Span::styled("[output throttled] ", Style::default().fg(palette.dim)));
+                title
+            } else {
+                output_title
+            };
+            let (output_title, output_border_color) = if app.is_output_focused() {
+                let mut title = output_title;
+                if let Some(count) = app.pending_count() {
+                    title.push_span(Span::styled(format!("{} ", count), Style::default().fg(palette.warning)));
+                }
+                title.push_span(Span::styled(
+                    "[scroll: [count] j/k, Ctrl+D/U, gg/G, fold: z/M/R, commands: [/] e r, Esc: back] ",
+                    Style::default().fg(palette.warning),
+                ));
+                (title, palette.warning)
+            } else {
+                (output_title, palette.primary)
+            };
+
+            let links = crate::hyperlink::take_links(&mut output_lines);
+            let output = Paragraph::new(output_lines)
+                .block(
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .title(output_title)
+                        .border_style(Style::default().fg(output_border_color)),
+                )
+                .style(Style::default().fg(app.output_color));
+            f.render_widget(output, output_area);
+            if app.hyperlinks {
+                let inner = output_area.inner(ratatui::layout::Margin::new(1, 1));
+                crate::hyperlink::apply(f.buffer_mut(), inner, &links);
+            }
+
+            // Suggestions section
+            if show_suggestions {
+                let items: Vec<ListItem> = app
+                    .suggestions()
+                    .iter()
+                    .enumerate()
+                    .map(|(i, suggestion)| {
+                        let type_indicator = match suggestion.suggestion_type {
+                            crate::suggest::SuggestionType::FullCommand => "cmd",
+                            crate::suggest::SuggestionType::Argument => "arg",
+                            crate::suggest::SuggestionType::ArgumentValue => "val",
+                            crate::suggest::SuggestionType::ArgumentCombo => "args",
+                            crate::suggest::SuggestionType::ArgumentPair => "pair",
+                        };
+
+                        let is_selected = i == app.selected_suggestion;
+                        let indicator = if is_selected { "▌" } else { " " };
+
+                        let mut spans = vec![
+                            Span::styled(
+                                indicator,
+                                Style::default().fg(palette.warning),
+                            ),
+                            Span::styled(
+                                format!("[{}] ", type_indicator),
+                                Style::default().fg(palette.dim),
+                            ),
+                        ];
+
+                        if suggestion.suggestion_type == crate::suggest::SuggestionType::FullCommand {
+                            spans.extend(app.colorize_command_suggestion(suggestion));
+                            if let Some(last_used) = app.searcher.last_used(&suggestion.text) {
+                                spans.push(Span::styled(
+                                    format!("  {}", crate::time::format_relative(last_used)),
+                                    Style::default().fg(palette.dim),
+                                ));
+                            }
+                            if let Some(typical) = app.searcher.typical_duration(&suggestion.text)
+                                && typical >= SLOW_COMMAND
+                            {
+                                spans.push(Span::styled(
+                                    format!("  ~{}", crate::time::format_duration(typical)),
+                                    Style::default().fg(palette.warning),
+                                ));
+                            }
+                            for tag in app.searcher.tags(&suggestion.text) {
+                                spans.push(Span::raw(" "));
+                                spans.push(Span::styled(
+                                    format!("#{}", tag),
+                                    Style::default().fg(palette.secondary).add_modifier(Modifier::REVERSED),
+                                ));
+                            }
+                        } else {
+                            let (typed, new) = app.suggestion_full_preview(suggestion);
+                            spans.push(Span::styled(typed, Style::default().fg(palette.dim)));
+                            spans.push(Span::styled(new, Style::default().fg(palette.primary)));
+                        }
+
+                        ListItem::new(Line::from(spans))
+                    })
+                    .collect();
+
+                let title = if app.include_rare {
+                    " Suggestions, all commands (Tab/↑↓: navigate, →: next word, Ctrl+Y: accept, Alt+Enter: run, Alt+A: frequent only) "
+                } else {
+                    " Suggestions (Tab/↑↓: navigate, →: next word, Ctrl+Y: accept, Alt+Enter: run) "
+                };
+                let suggestions_list = List::new(items)
+                    .block(
+                        Block::default()
+                            .borders(Borders::ALL)
+                            .title(title)
+                            .border_style(Style::default().fg(palette.secondary)),
+                    )
+                    .style(Style::default().fg(palette.text));
+
+                f.render_widget(suggestions_list, suggestion_area);
+            }
+
+            // Input section
+            let input_text = if let Some(state) = &app.parameterize {
+                // Every word that can be picked is underlined, the picked one highlighted
+                let input = app.input();
+                let mut spans = Vec::new();
+                let mut end = 0;
+                for (i, word) in state.words.iter().enumerate() {
+                    spans.push(Span::raw(input[end..word.start].to_string()));
+                    let style = if i == state.selected {
+                        Style::default().fg(palette.warning).add_modifier(Modifier::REVERSED)
+                    } else {
+                        Style::default().add_modifier(Modifier::UNDERLINED)
+                    };
+                    spans.push(Span::styled(input[word.clone()].to_string(), style));
+                    end = word.end;
+                }
+                spans.push(Span::raw(input[end..].to_string()));
+                Text::from(Line::from(spans))
+            } else if let Some(preview) = app.ghost_text() {
+                let line = Line::from(vec![
+                    Span::styled(app.input().to_string(), Style::default().fg(palette.text)),
+                    Span::styled(
+                        preview,
+                        Style::default()
+                            .fg(palette.dim)
+                            .add_modifier(Modifier::DIM),
+                    ),
+                ]);
+                Text::from(line)
+            } else {
+                Text::from(app.input())
+            };
+
+            let (input_title, input_border_color) = if app.is_quit_hint_active() {
+                (" Press Ctrl+C again to quit ".to_string(), palette.warning)
+            } else if app.is_parameterizing() {
+                (
+                    " Parameterize: ←/→ pick a word, Enter makes it {x}, Esc cancels ".to_string(),
+                    palette.warning,
+                )
+            } else if let Some(state) = &app.tagging {
+                let tags = app.searcher.tags(&state.command);
+                let current = if tags.is_empty() {
+                    String::new()
+                } else {
+                    format!(" [{}]", tags.join(" "))
+                };
+                (
+                    format!(
+                        " Tag `{}`{}: names add, -name removes, Enter saves, Esc cancels ",
+                        state.command, current
+                    ),
+                    palette.warning,
+                )
+            } else if let Some(warning) = input_warning(app.input()) {
+                (format!(" Input ({}) ", warning), palette.warning)
+            } else if app.searcher.is_read_only() {
+                (" Input (read-only history) ".to_string(), palette.success)
+            } else {
+                (" Input ".to_string(), palette.success)
+            };
+
+            // Current time for the input border
+            let now = chrono::Local::now();
+            let time_str = now.format(" %H:%M:%S ").to_string();
+
+            let mut input_block = Block::default()
+                .borders(Borders::ALL)
+                .title(input_title)
+                .border_style(Style::default().fg(input_border_color));
+            if let Some(preview) = app.parallel_preview() {
+                input_block = input_block.title_bottom(
+                    Line::from(format!(" {} ", preview)).style(Style::default().fg(palette.dim)),
+                );
+            }
+            let input = Paragraph::new(input_text)
+                .block(
+                    input_block
+                        .title_bottom(
+                            Line::from(time_str)
+                                .right_aligned()
+                                .style(Style::default().fg(palette.dim))
+                        ),
+                )
+                .style(Style::default().fg(palette.text));
+            f.render_widget(input, input_area);
+
+            // Compute display width (not byte offset) for correct cursor placement
+            let input = app.input();
+            let pos = app
+                .parameterize_selection()
+                .map_or(app.cursor_position(), |word| word.start)
+                .min(input.len());
+            let display_col = input.get(..pos)
+                .map(unicode_width::UnicodeWidthStr::width)
+                .unwrap_or(0) as u16;
+            f.set_cursor_position((
+                input_area.x + display_col + 1,
+                input_area.y + 1,
+            ));
+
+            if let Some(picker) = &app.arg_picker {
+                render_arg_picker(f, picker, palette);
+            }
+            if let Some(view) = &app.settings {
+                render_settings(f, &app, view, palette);
+            }
+        })?;
 
-        while self.output.len() > self.max_output_lines {
-            self.output.pop_front();
-            self.scroll_offset = self.scroll_offset.saturating_sub(1);
+        let chord_deadline = app.chord_deadline();
+        tokio::select! {
+            Some(event_result) = event_stream.next() => {
+                match event_result {
+                    Ok(Event::Key(key)) => {
+                        last_activity = Instant::now();
+                        should_quit = keymap::handle_key_event(&mut app, key, &mut runner);
+                        if app.take_suspend_request() {
+                            suspend(&mut terminal)?;
+                        }
+                    }
+                    Ok(Event::Resize(cols, rows)) => {
+                        runner.resize_all(app.pty_size(cols, rows));
+                        // Repaint every cell: some terminals reflow the old
+                        // screen on resize, and the diff against the last frame
+                        // wouldn't touch cells that only look unchanged
+                        terminal.clear()?;
+                    }
+                    _ => {}
+                }
+            }
+            Some(msg) = output_rx.recv() => {
+                pipe_output(&mut output_pipe, &mut app, &msg);
+                app.push_output(msg);
+                // Drain all remaining messages before re-rendering
+                while let Ok(msg) = output_rx.try_recv() {
+                    pipe_output(&mut output_pipe, &mut app, &msg);
+                    app.push_output(msg);
+                }
+            }
+            joined = async { live_sync.as_mut().expect("guarded by is_some").await }, if live_sync.is_some() => {
+                live_sync = None;
+                app.apply_live_sync(joined);
+            }
+            _ = spinner.tick(), if app.has_running_tasks() => {}
+            _ = tokio::time::sleep_until(chord_deadline.unwrap_or_else(Instant::now).into()), if chord_deadline.is_some() => {
+                keymap::expire_chord(&mut app, Instant::now());
+            }
+            _ = tick.tick() => {
+                // Forces a re-render to update the clock
+                app.set_output_throttled(runner.take_output_stalls() > 0);
+                if live_sync.is_none() && sync_interval.is_some_and(|interval| last_sync.elapsed() >= interval) {
+                    live_sync = app.spawn_live_sync(&config.history, &config.sync);
+                    last_sync = Instant::now();
+                }
+                if idle_expired(last_activity, Instant::now(), idle_timeout, runner.has_active_tasks()) {
+                    log::info!("Idle timeout reached, quitting");
+                    should_quit = true;
+                }
+            }
         }
 
-        if self.auto_scroll {
-            self.scroll_to_bottom();
+        if should_quit {
+            // Readers waiting for room in the channel get an error instead of
+            // blocking while their tasks are stopped
+            output_rx.close();
+            runner.cancel_all().await;
+            break;
         }
     }
 
-    /// Set scroll_offset so the bottom of the output is visible.
-    /// Called with the actual panel height during rendering; defaults to a large value
-    /// so the rendering logic clamps it.
-    fn scroll_to_bottom(&mut self) {
-        // Set to a value that rendering will clamp to show the last page
-        self.scroll_offset = usize::MAX;
+    guard.restore()?;
+    if let Some(pipe) = output_pipe {
+        pipe.close();
+    }
+    // The history is flushed on the way out, which would write the counts
+    // from before a sync still in flight over the ones it stored
+    if let Some(task) = live_sync {
+        app.apply_live_sync(task.await);
     }
 
-    pub fn clear_output(&mut self) {
-        self.output.clear();
-        self.scroll_offset = 0;
-        self.auto_scroll = true;
+    // Back on the normal screen: leave the session in the scrollback
+    if app.echo_on_exit {
+        for line in app.transcript() {
+            println!("{}", line);
+        }
     }
 
-    /// Add a warning message to the output (for startup errors, etc.)
-    pub fn add_warning(&mut self, message: String) {
-        self.output.push_back(OutputLine {
-            runner_label: String::new(),
-            stream: crate::runner::StreamType::Status,
-            content: message,
-        });
+    Ok(app.into_parts())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::suggest::SuggestionType;
+
+    fn test_app() -> (App, tempfile::NamedTempFile) {
+        let temp_db = tempfile::NamedTempFile::new().unwrap();
+        let searcher = HistorySearcher::new(temp_db.path().to_path_buf()).unwrap();
+        let engine = SuggestionEngine::new(searcher.commands());
+        (App::new(searcher, engine, &Config::default()), temp_db)
     }
 
-    // --- History recall ---
+    #[test]
+    fn test_terminal_restored_on_panic() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
 
-    /// Recall the most recent command from history into the input field
-    pub fn recall_last_command(&mut self) {
-        if !self.input.is_empty() {
-            return;
+        static RESTORED: AtomicUsize = AtomicUsize::new(0);
+        fn restore() -> io::Result<()> {
+            RESTORED.fetch_add(1, Ordering::SeqCst);
+            Ok(())
         }
-        if let Some(cmd) = self.searcher.most_recent_command() {
-            self.input = cmd.command.clone();
-            self.cursor_position = self.input.len();
-            self.update_suggestions();
+
+        let result = std::panic::catch_unwind(|| {
+            let _guard = TerminalGuard::claim(restore);
+            let mut terminal = Terminal::new(ratatui::backend::TestBackend::new(20, 5)).unwrap();
+            terminal.draw(|_| panic!("draw failed")).unwrap();
+        });
+        assert!(result.is_err());
+        // Once, by the panic hook; the unwinding drop finds nothing left to do
+        assert_eq!(RESTORED.load(Ordering::SeqCst), 1);
+
+        TerminalGuard::claim(restore).restore().unwrap();
+        assert_eq!(RESTORED.load(Ordering::SeqCst), 2);
+        assert!(terminal_owner().is_none());
+    }
+
+    fn suggestion(text: &str, suggestion_type: SuggestionType) -> Suggestion {
+        Suggestion {
+            text: text.to_string(),
+            score: 1.0,
+            suggestion_type,
         }
     }
 
-    // --- Output scrolling ---
+    #[test]
+    fn test_parallel_preview() {
+        assert_eq!(
+            parallel_preview("[shard=1-64] mysql -h shard-{shard}"),
+            Some("→ 64 tasks, e.g. \"mysql -h shard-1\"".to_string())
+        );
+        assert_eq!(
+            parallel_preview("[a=1-2] [b=x,y,z] cmd {a} {b}"),
+            Some("→ 6 tasks, e.g. \"cmd 1 x\"".to_string())
+        );
+        let long = parallel_preview(&format!("[n=1] echo {} {{n}}", "x".repeat(80))).unwrap();
+        assert!(long.starts_with("→ 1 task, e.g. \"echo xxx"), "{}", long);
+        assert!(long.ends_with("…\""), "{}", long);
+
+        // Plain commands, directives alone and invalid blocks (flagged by input_warning)
+        assert_eq!(parallel_preview("echo [x]"), None);
+        assert_eq!(parallel_preview("[retry=3] make test"), None);
+        assert_eq!(parallel_preview("[n=5-1] echo {n}"), None);
+    }
 
-    pub fn scroll_up(&mut self, lines: usize) {
-        self.scroll_offset = self.scroll_offset.saturating_sub(lines);
-        self.auto_scroll = false;
+    #[test]
+    fn test_input_warning() {
+        assert_eq!(input_warning("echo hello"), None);
+        assert_eq!(input_warning("echo 'it''s'"), None);
+        assert_eq!(input_warning("[n=1-3] echo {n}"), None);
+        assert_eq!(input_warning("[ -f x ] && echo '[' \\]"), None);
+
+        assert_eq!(input_warning("echo \"hello"), Some("unclosed quote"));
+        assert_eq!(input_warning("[n=1-3 echo {n}"), Some("unclosed ["));
+        assert_eq!(input_warning("echo ]"), Some("unbalanced ]"));
+        assert_eq!(
+            input_warning("[a=1-3 b=x,y] cmd {a} {b}"),
+            Some("invalid parallel block")
+        );
     }
 
-    pub fn scroll_down(&mut self, lines: usize) {
-        self.scroll_offset = self.scroll_offset.saturating_add(lines);
-        // auto_scroll is re-enabled by the rendering logic when we're at the bottom
+    #[test]
+    fn test_insert_timestamp() {
+        use chrono::TimeZone;
+
+        let time = chrono::Local.with_ymd_and_hms(2024, 1, 15, 9, 5, 30).unwrap();
+        assert_eq!(format_timestamp(&time, "%Y-%m-%d").as_deref(), Some("2024-01-15"));
+        assert_eq!(format_timestamp(&time, "%Y-%m-%d_%H%M%S").as_deref(), Some("2024-01-15_090530"));
+        assert_eq!(format_timestamp(&time, "%Q"), None);
+
+        // The cursor lands after the inserted text, however many bytes it is
+        let (mut app, _db) = test_app();
+        for c in "tar czf backup-.tgz".chars() {
+            app.insert_char(c);
+        }
+        app.cursor_position = "tar czf backup-".len();
+        app.date_format = "%Y年%m月".to_string();
+        app.insert_timestamp(false);
+        let year = chrono::Local::now().format("%Y").to_string();
+        assert!(app.input().starts_with(&format!("tar czf backup-{}年", year)));
+        assert!(app.input().ends_with("月.tgz"));
+        assert_eq!(&app.input()[app.cursor_position()..], ".tgz");
+
+        app.datetime_format = "%Q".to_string();
+        let before = app.input().to_string();
+        app.insert_timestamp(true);
+        assert_eq!(app.input(), before);
+        assert!(app.output.back().unwrap().content.contains("Invalid [tui] datetime_format"));
     }
 
-    // --- Suggestions ---
+    #[test]
+    fn test_toggle_suggestions() {
+        let (mut app, _db) = test_app();
+        app.searcher.record_usage("cargo build").unwrap();
+        app.input = "carg".to_string();
+        app.update_suggestions();
+        assert!(app.has_suggestions());
+
+        app.toggle_suggestions();
+        assert!(!app.has_suggestions());
+        assert_eq!(app.get_suggestion_preview(), None);
+        app.insert_char('o');
+        assert!(!app.has_suggestions());
+        // History recall doesn't depend on suggestions
+        app.input.clear();
+        app.recall_last_command();
+        assert_eq!(app.input, "cargo build");
+
+        app.toggle_suggestions();
+        assert!(app.has_suggestions());
+    }
 
-    fn update_suggestions(&mut self) {
-        self.suggestions = self
-            .suggestion_engine
-            .suggest(&self.input, &mut self.searcher, 8);
-        self.selected_suggestion = 0;
+    #[test]
+    fn test_toggle_rare_commands() {
+        let temp_db = tempfile::NamedTempFile::new().unwrap();
+        let mut searcher = HistorySearcher::new(temp_db.path().to_path_buf()).unwrap();
+        searcher.record_usage("cargo build").unwrap();
+        let mut config = Config::default();
+        config.suggest.min_frequency = 2;
+        let mut engine = SuggestionEngine::new(searcher.commands());
+        engine.set_min_frequency(config.suggest.min_frequency);
+        let mut app = App::new(searcher, engine, &config);
+
+        app.input = "carg".to_string();
+        app.update_suggestions();
+        assert!(!app.has_suggestions());
+
+        app.toggle_rare_commands();
+        assert!(app.has_suggestions());
+        app.toggle_rare_commands();
+        assert!(!app.has_suggestions());
+
+        // Nothing to toggle without a floor
+        let (mut app, _db) = test_app();
+        app.toggle_rare_commands();
+        assert!(!app.include_rare);
+        assert!(app.output.back().unwrap().content.contains("min_frequency"));
     }
 
-    pub fn accept_suggestion(&mut self) {
-        if self.suggestions.is_empty() || self.selected_suggestion >= self.suggestions.len() {
-            return;
+    #[test]
+    fn test_spinner_frame() {
+        let (mut app, _db) = test_app();
+        assert_eq!(app.spinner_frame(Instant::now()), None);
+
+        app.push_output(OutputMessage::status(1, "", "started"));
+        let start = app.task_start_times[&1];
+        assert_eq!(app.spinner_frame(start), Some(SPINNER_FRAMES[0]));
+        assert_eq!(app.spinner_frame(start + SPINNER_INTERVAL * 3), Some(SPINNER_FRAMES[3]));
+        assert_eq!(app.spinner_frame(start + SPINNER_INTERVAL * 11), Some(SPINNER_FRAMES[1]));
+
+        app.push_output(OutputMessage::status(1, "", "completed"));
+        assert!(!app.has_running_tasks());
+        assert_eq!(app.spinner_frame(Instant::now()), None);
+    }
+
+    #[test]
+    fn test_fast_commands_never_show_as_running() {
+        let (mut app, _db) = test_app();
+        let start = Instant::now();
+        let grace = app.fast_command_grace;
+
+        app.push_output(OutputMessage::status(1, "", "started"));
+        assert_eq!(app.slow_running_tasks(start), None);
+        let (count, longest) = app.slow_running_tasks(start + grace * 5).unwrap();
+        assert_eq!(count, 1);
+        assert!(longest >= grace * 4);
+
+        // Completed: drawn as a single box, no longer running
+        app.push_output(OutputMessage::output(1, "", "hi".to_string()));
+        app.push_output(OutputMessage::completed(1, "", "completed", crate::runner::TaskStats {
+            exit_code: Some(0),
+            lines: 1,
+            bytes: 2,
+            elapsed: Duration::from_millis(1),
+            attempts: 1,
+        }));
+        assert_eq!(app.slow_running_tasks(start + grace * 5), None);
+        assert!(app.output.iter().any(|l| l.content == "hi"));
+    }
+
+    #[test]
+    fn test_max_lines_per_task() {
+        let stats = || crate::runner::TaskStats {
+            exit_code: Some(0),
+            lines: 5,
+            bytes: 10,
+            elapsed: Duration::from_millis(1),
+            attempts: 1,
+        };
+        let (mut app, _db) = test_app();
+        app.max_lines_per_task = 3;
+        for i in 0..5 {
+            app.push_output(OutputMessage::output(1, "", format!("line {}", i)));
+        }
+        assert_eq!(app.pending_output[&1].len(), 3);
+        app.push_output(OutputMessage::completed(1, "", "completed", stats()));
+        let contents: Vec<&str> = app.output.iter().map(|l| l.content.as_str()).collect();
+        assert!(contents.contains(&"line 2"));
+        assert!(!contents.contains(&"line 3"));
+        assert!(contents.iter().any(|c| c.contains("(2 lines suppressed)")));
+
+        // 0: no limit
+        let (mut app, _db) = test_app();
+        app.max_lines_per_task = 0;
+        for i in 0..5 {
+            app.push_output(OutputMessage::output(1, "", format!("line {}", i)));
         }
+        app.push_output(OutputMessage::completed(1, "", "completed", stats()));
+        assert!(app.output.iter().any(|l| l.content == "line 4"));
+        assert!(!app.output.iter().any(|l| l.content.contains("suppressed")));
+    }
 
-        let suggestion = &self.suggestions[self.selected_suggestion];
+    #[test]
+    fn test_retries_are_shown_in_the_box() {
+        let (mut app, _db) = test_app();
+        app.push_output(OutputMessage::status(1, "", "started"));
+        app.push_output(OutputMessage::output(1, "", "timeout".to_string()));
+        app.push_output(OutputMessage::status(1, "", "retry 1/3: exited with code 1"));
+        assert!(app.output.is_empty(), "a retry doesn't finish the task");
+
+        app.push_output(OutputMessage::completed(1, "", "completed", crate::runner::TaskStats {
+            exit_code: Some(0),
+            lines: 1,
+            bytes: 8,
+            elapsed: Duration::from_secs(1),
+            attempts: 2,
+        }));
+        let contents: Vec<&str> = app.output.iter().map(|l| l.content.as_str()).collect();
+        let retry = contents.iter().position(|c| c.contains("↻ retry 1/3")).unwrap();
+        assert!(contents.iter().position(|&c| c == "timeout").unwrap() < retry);
+        assert!(contents.last().unwrap().starts_with("2 attempts · "));
+    }
 
-        match suggestion.suggestion_type {
-            crate::suggest::SuggestionType::FullCommand => {
-                self.input = suggestion.text.clone();
-                self.cursor_position = self.input.len();
+    #[test]
+    fn test_settings_overlay_adjusts_live() {
+        let (mut app, _db) = test_app();
+        for i in 0..3000 {
+            app.add_warning(format!("line {}", i));
+        }
+        // Nothing to adjust while closed
+        app.adjust_setting(true);
+        assert_eq!(app.box_pad_h, Config::default().output.box_padding_horizontal);
+
+        app.open_settings();
+        let pad = app.box_pad_h;
+        app.adjust_setting(true);
+        assert_eq!(app.setting_value(Setting::BoxPaddingHorizontal), (pad + 1).to_string());
+        for _ in 0..20 {
+            app.adjust_setting(false);
+        }
+        assert_eq!(app.box_pad_h, 0);
+
+        // Lowering the line cap trims the buffer right away
+        app.max_output_lines = 2000;
+        for _ in 0..3 {
+            app.settings_mut().unwrap().select_next();
+        }
+        assert_eq!(app.settings.as_ref().unwrap().selected(), Setting::LongLines);
+        app.adjust_setting(true);
+        assert_eq!(app.long_lines, LongLineMode::Wrap);
+        app.settings_mut().unwrap().select_prev();
+        app.adjust_setting(false);
+        assert_eq!(app.max_output_lines, 1000);
+        assert_eq!(app.output.len(), 1000);
+        assert_eq!(app.evicted_lines, 2000);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        app.save_settings_to(&path).unwrap();
+        let saved: Config = toml::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(saved.output.box_padding_horizontal, 0);
+        assert_eq!(saved.output.max_lines, 1000);
+        assert_eq!(saved.output.long_lines, LongLineMode::Wrap);
+
+        app.close_settings();
+        assert!(app.settings_mut().is_none());
+    }
+
+    #[test]
+    fn test_dismiss_last_box() {
+        let (mut app, _db) = test_app();
+        let stats = crate::runner::TaskStats {
+            exit_code: Some(0),
+            lines: 1,
+            bytes: 4,
+            elapsed: Duration::ZERO,
+            attempts: 1,
+        };
+        for (id, label) in [(1, "[host=web-1]"), (2, "[host=db-1]")] {
+            app.push_output(OutputMessage::status(id, label, "started"));
+            app.push_output(OutputMessage::output(id, label, format!("out {}", id)));
+            app.push_output(OutputMessage::completed(id, label, "completed", stats));
+        }
+        app.add_warning("later warning".to_string());
+        // Still running: buffered, not in the output
+        app.push_output(OutputMessage::status(3, "[host=web-2]", "started"));
+        app.push_output(OutputMessage::output(3, "[host=web-2]", "out 3".to_string()));
+
+        let contents = |app: &App| app.output.iter().map(|l| l.content.clone()).collect::<Vec<_>>();
+        app.dismiss_last_box();
+        assert!(!contents(&app).contains(&"out 2".to_string()));
+        assert!(contents(&app).contains(&"out 1".to_string()));
+        assert_eq!(app.output.back().unwrap().content, "later warning");
+        assert!(app.pending_output.contains_key(&3));
+
+        app.dismiss_last_box();
+        assert_eq!(contents(&app), ["later warning"]);
+        app.dismiss_last_box();
+        assert_eq!(contents(&app), ["later warning"]);
+    }
+
+    #[test]
+    fn test_pipe_text() {
+        let (mut app, _db) = test_app();
+        assert_eq!(app.pipe_text(), None);
+
+        app.input = "cargo b".to_string();
+        assert_eq!(app.pipe_text().as_deref(), Some("cargo b"));
+        app.suggestions = vec![Suggestion {
+            text: "cargo build --release".to_string(),
+            score: 1.0,
+            suggestion_type: crate::suggest::SuggestionType::FullCommand,
+        }];
+        assert_eq!(app.pipe_text().as_deref(), Some("cargo build --release"));
+
+        // The output box in view, without its border
+        app.push_output(OutputMessage::status(1, "", "started"));
+        app.push_output(OutputMessage::output(1, "", "\x1b[31merror\x1b[0m: oops".to_string()));
+        app.push_output(OutputMessage::output(1, "", "done".to_string()));
+        app.push_output(OutputMessage::status(1, "", "completed"));
+        app.output_height = 100;
+        app.output_focused = true;
+        assert_eq!(app.pipe_text().as_deref(), Some("error: oops\ndone\n"));
+    }
+
+    #[test]
+    fn test_fold_boxes() {
+        let (mut app, _db) = test_app();
+        let stats = crate::runner::TaskStats {
+            exit_code: Some(0),
+            lines: 2,
+            bytes: 8,
+            elapsed: Duration::ZERO,
+            attempts: 1,
+        };
+        let finish = |app: &mut App, id, label: &str| {
+            app.push_output(OutputMessage::status(id, label, "started"));
+            app.push_output(OutputMessage::output(id, label, "a".to_string()));
+            app.push_output(OutputMessage::output(id, label, "b".to_string()));
+            app.push_output(OutputMessage::completed(id, label, "completed", stats));
+        };
+        app.add_warning("before".to_string());
+        finish(&mut app, 1, "[n=1]");
+        finish(&mut app, 2, "[n=2]");
+        app.output_height = 100;
+        app.auto_scroll = false;
+        app.scroll_offset = 0;
+        let shown = |app: &App| shown_output(&app.output, None).len();
+        assert_eq!(shown(&app), 9);
+
+        // The view starts above the first box, so that's the one folded
+        app.toggle_fold_in_view();
+        assert!(app.output[1].folded);
+        assert!(app.output[1].content.starts_with("2 lines · "));
+        assert_eq!(shown(&app), 6);
+        assert_eq!(app.scroll_offset, 1);
+
+        // Folds survive new output
+        finish(&mut app, 3, "[n=3]");
+        assert!(app.output[1].folded);
+        assert_eq!(shown(&app), 10);
+
+        app.fold_all(true);
+        assert_eq!(shown(&app), 4);
+        app.fold_all(false);
+        assert_eq!(shown(&app), 13);
+        assert!(app.output.iter().all(|l| !l.folded && (l.runner_label != "\x00top:[n=1]" || l.content.is_empty())));
+    }
+
+    #[test]
+    fn test_screen_areas() {
+        let area = Rect::new(0, 0, 80, 30);
+        let [output, suggestions, input] = screen_areas(area, 7, 3, InputPosition::Bottom);
+        assert_eq!((output.y, output.height), (0, 20));
+        assert_eq!((suggestions.y, suggestions.height), (20, 7));
+        assert_eq!((input.y, input.height), (27, 3));
+
+        // On top, the panel still sits between the input and the output
+        let [output, suggestions, input] = screen_areas(area, 7, 3, InputPosition::Top);
+        assert_eq!((input.y, input.height), (0, 3));
+        assert_eq!((suggestions.y, suggestions.height), (3, 7));
+        assert_eq!((output.y, output.height), (10, 20));
+        assert!([output, suggestions, input].iter().all(|a| a.width == 80));
+
+        // Without the panel the output takes its rows
+        let [output, _, input] = screen_areas(area, 0, 3, InputPosition::Top);
+        assert_eq!((output.y, output.height, input.y), (3, 27, 0));
+    }
+
+    #[test]
+    fn test_render_folded_box_header() {
+        let look = OutputLook {
+            chars: BoxChars::for_style(BoxStyle::Ascii),
+            border: Style::default(),
+            separator: Style::default(),
+            box_pad_h: 1,
+            long_lines: LongLineMode::Truncate,
+            wrap_indent: 0,
+            line_timestamps: false,
+            show_command: false,
+        };
+        let top = OutputLine {
+            runner_label: "\x00top:[n=1]".to_string(),
+            stream: crate::runner::StreamType::Status,
+            content: "2 lines · 5ms".to_string(),
+            folded: true,
+            ..Default::default()
+        };
+        let rows: Vec<String> = render_output_line(&top, 30, &look).iter().map(|l| l.to_string()).collect();
+        assert_eq!(rows, ["> [n=1] ------ 2 lines · 5ms -"]);
+    }
+
+    #[test]
+    fn test_resize_rerenders_boxes_and_sizes_ptys() {
+        let look = OutputLook {
+            chars: BoxChars::for_style(BoxStyle::Ascii),
+            border: Style::default(),
+            separator: Style::default(),
+            box_pad_h: 1,
+            long_lines: LongLineMode::Truncate,
+            wrap_indent: 0,
+            line_timestamps: false,
+            show_command: false,
+        };
+        let line = |label: &str, content: &str| OutputLine {
+            runner_label: label.to_string(),
+            stream: crate::runner::StreamType::Output,
+            content: content.to_string(),
+            ..Default::default()
+        };
+        let lines = [line("\x00top:[n=1]", ""), line("\x00box", "hello\nworld"), line("\x00bot", "")];
+        // Stored lines carry no padding, so each width lays them out afresh
+        for width in [40, 12] {
+            for rendered in lines.iter().flat_map(|l| render_output_line(l, width, &look)) {
+                assert_eq!(rendered.width(), width, "{:?}", rendered.to_string());
             }
-            crate::suggest::SuggestionType::Argument
-            | crate::suggest::SuggestionType::ArgumentValue => {
-                if !self.input.ends_with(' ') {
-                    let mut new_input = self.input.trim_end().to_string();
-                    if let Some(last_space_pos) = new_input.rfind(char::is_whitespace) {
-                        new_input.truncate(last_space_pos + 1);
-                        new_input.push_str(&suggestion.text);
-                    } else {
-                        new_input = suggestion.text.clone();
-                    }
-                    self.input = new_input;
-                } else {
-                    self.input.push_str(&suggestion.text);
-                }
-                self.cursor_position = self.input.len();
+        }
+
+        let (mut app, _tmp) = test_app();
+        app.box_pad_h = 1;
+        app.box_pad_v = 0;
+        app.line_timestamps = false;
+        app.parallel_columns = 1;
+        // 100 - pane borders - box borders - padding; 30 - 2 - 3 - 2
+        assert_eq!(app.pty_size(100, 30), crate::runner::PtySizes::uniform(94, 23));
+        // Only parallel tasks share the width
+        app.parallel_columns = 2;
+        let sizes = app.pty_size(100, 30);
+        assert_eq!((sizes.single, sizes.batch), ((94, 23), (44, 23)));
+        assert_eq!(app.pty_size(4, 2), crate::runner::PtySizes::uniform(1, 1));
+    }
+
+    #[test]
+    fn test_last_box_range_with_filter_and_evicted_top() {
+        let line = |label: &str| OutputLine {
+            runner_label: label.to_string(),
+            stream: crate::runner::StreamType::Output,
+            content: String::new(),
+            ..Default::default()
+        };
+        let output: VecDeque<OutputLine> = [
+            "\x00box",
+            "\x00bot",
+            "\x00top:[h=web]",
+            "\x00box",
+            "\x00bot",
+            "\x00top:[h=db]",
+            "\x00bot",
+        ]
+        .into_iter()
+        .map(line)
+        .collect();
+        assert_eq!(last_box_range(&output, None), Some(5..=6));
+        assert_eq!(last_box_range(&output, Some("WEB")), Some(2..=4));
+        assert_eq!(last_box_range(&output, Some("cache")), None);
+
+        let partial: VecDeque<OutputLine> = ["\x00box", "\x00bot"].into_iter().map(line).collect();
+        assert_eq!(last_box_range(&partial, None), Some(0..=1));
+    }
+
+    fn grid_test_output() -> Vec<OutputLine> {
+        let line = |label: &str, content: &str| OutputLine {
+            runner_label: label.to_string(),
+            stream: crate::runner::StreamType::Output,
+            content: content.to_string(),
+            ..Default::default()
+        };
+        vec![
+            line("", "plain"),
+            line("\x00top:[n=1]", ""),
+            line("\x00box", "one"),
+            line("\x00bot", "1ms"),
+            line("\x00top:[n=2]", ""),
+            line("\x00box", "two"),
+            line("\x00box", "two again"),
+            line("\x00bot", "1ms"),
+            line("\x00top:[n=3]", ""),
+            line("\x00bot", ""),
+            line("\x00top:", ""),
+            line("\x00box", "single"),
+            line("\x00bot", ""),
+        ]
+    }
+
+    #[test]
+    fn test_group_output() {
+        let output = grid_test_output();
+        let shown: Vec<&OutputLine> = output.iter().collect();
+        let items = group_output(&shown, 2);
+        assert_eq!(items[0], OutputItem::Line(0));
+        assert_eq!(items[1], OutputItem::Grid(vec![1..4, 4..8]));
+        assert_eq!(items[2], OutputItem::Grid(std::iter::once(8..10).collect()));
+        // The single command's box stays line by line
+        assert_eq!(items[3..], [OutputItem::Line(10), OutputItem::Line(11), OutputItem::Line(12)]);
+        assert_eq!((items[1].start(), items[1].len()), (1, 7));
+
+        assert!(group_output(&shown, 1).iter().all(|item| matches!(item, OutputItem::Line(_))));
+    }
+
+    #[test]
+    fn test_grid_rows_side_by_side() {
+        let output = grid_test_output();
+        let shown: Vec<&OutputLine> = output.iter().collect();
+        let look = OutputLook {
+            chars: BoxChars::for_style(BoxStyle::Ascii),
+            border: Style::default(),
+            separator: Style::default(),
+            box_pad_h: 1,
+            long_lines: LongLineMode::Truncate,
+            wrap_indent: 0,
+            line_timestamps: false,
+            show_command: false,
+        };
+        let text = |rows: Vec<Line>| -> Vec<String> { rows.iter().map(|l| l.to_string()).collect() };
+
+        let rows = text(grid_rows(&shown, 2, 41, 100, 0, false, &look));
+        assert_eq!(rows[0], "plain");
+        assert_eq!(rows[1], "+- [n=1] ----------+ +- [n=2] ----------+");
+        assert_eq!(rows[2], "| one              | | two              |");
+        assert_eq!(rows[3], "+------------ 1ms -+ | two again        |");
+        assert_eq!(rows[4], format!("{} +------------ 1ms -+", " ".repeat(20)));
+        assert!(rows.iter().all(|row| row.chars().count() <= 41));
+
+        // Following the output shows the last rows
+        let tail = text(grid_rows(&shown, 2, 41, 3, 0, true, &look));
+        assert_eq!(tail.len(), 3);
+        assert!(tail[1].starts_with("| single"));
+        // Scrolled into the middle of the first grid row
+        let rows = text(grid_rows(&shown, 2, 41, 2, 3, false, &look));
+        assert!(rows[0].starts_with("| one"));
+    }
+
+    #[test]
+    fn test_parallel_table_style() {
+        let (mut app, _db) = test_app();
+        app.parallel_style = ParallelStyle::Table;
+        app.parallel_label_width = "[host=web-10]".len();
+        let tasks: [(u64, &str, &[&str]); 3] = [
+            (1, "[host=web-1]", &["\x1b[32mok\x1b[0m"]),
+            (2, "[host=db-1]", &["disk full", "read-only"]),
+            (3, "[host=web-10]", &["load 0.42"]),
+        ];
+        for (id, label, lines) in tasks {
+            app.push_output(OutputMessage::status(id, label, "started"));
+            for line in lines {
+                app.push_output(OutputMessage::output(id, label, line.to_string()));
             }
+            app.push_output(OutputMessage::status(id, label, "completed"));
         }
 
-        self.update_suggestions();
+        let labels: Vec<&str> = app.output.iter().map(|l| l.runner_label.as_str()).collect();
+        assert_eq!(labels[0], "\x00row:[host=web-1] ");
+        // More than one line: a box as usual
+        assert_eq!(labels[1], "\x00top:[host=db-1]");
+        assert_eq!(labels.last(), Some(&"\x00row:[host=web-10]"));
+
+        let look = OutputLook {
+            chars: BoxChars::for_style(BoxStyle::Ascii),
+            border: Style::default(),
+            separator: Style::default(),
+            box_pad_h: 1,
+            long_lines: LongLineMode::Truncate,
+            wrap_indent: 0,
+            line_timestamps: false,
+            show_command: false,
+        };
+        let text = |line: &OutputLine| render_output_line(line, 40, &look)[0].to_string();
+        assert_eq!(text(&app.output[0]), "[host=web-1]  | ok");
+        assert_eq!(text(app.output.back().unwrap()), "[host=web-10] | load 0.42");
+        assert_eq!(pager_line(&app.output[0], &look.chars, false), "[host=web-1]  | ok");
+
+        // Rows are kept by a label filter like the boxes they stand for
+        assert_eq!(filter_by_label(&app.output, "web").len(), 2);
     }
 
-    pub fn next_suggestion(&mut self) {
-        if !self.suggestions.is_empty() {
-            self.selected_suggestion = (self.selected_suggestion + 1) % self.suggestions.len();
+    #[test]
+    fn test_show_command_in_box_header() {
+        let (mut app, _db) = test_app();
+        app.show_command = true;
+        app.running.insert(1, RunningTask { command: "mysql -h shard-1".to_string(), records_outcome: false });
+        app.push_output(OutputMessage::status(1, "[n=1]", "started"));
+        app.push_output(OutputMessage::output(1, "[n=1]", "up".to_string()));
+        app.push_output(OutputMessage::status(1, "[n=1]", "completed"));
+        assert_eq!(app.output[0].runner_label, "\x00top:[n=1]");
+        assert_eq!(app.output[0].command.as_deref(), Some("mysql -h shard-1"));
+
+        let look = OutputLook {
+            chars: BoxChars::for_style(BoxStyle::Ascii),
+            border: Style::default(),
+            separator: Style::default(),
+            box_pad_h: 1,
+            long_lines: LongLineMode::Truncate,
+            wrap_indent: 0,
+            line_timestamps: false,
+            show_command: true,
+        };
+        let text = |app: &App, index: usize, width| render_output_line(&app.output[index], width, &look)[0].to_string();
+        assert_eq!(text(&app, 0, 30), "+- [n=1] mysql -h shard-1 ---+");
+        assert_eq!(text(&app, 0, 24), "+- [n=1] mysql -h sh… -+");
+        // The command isn't part of the label: filters only see `[n=1]`
+        assert!(filter_by_label(&app.output, "mysql").is_empty());
+
+        // Single commands show just the command, and aren't parallel boxes
+        // even when the command starts with `[`
+        app.running.insert(2, RunningTask { command: "[ -f Makefile ] && make test".to_string(), records_outcome: true });
+        app.push_output(OutputMessage::status(2, "", "started"));
+        app.push_output(OutputMessage::output(2, "", "ok".to_string()));
+        app.push_output(OutputMessage::status(2, "", "completed"));
+        let top = app.output.iter().position(|l| l.runner_label == "\x00top:").unwrap();
+        assert_eq!(text(&app, top, 36), "+- [ -f Makefile ] && make test ---+");
+        assert!(!is_parallel_box_top(&app.output[top]));
+        assert!(app.transcript().iter().any(|l| l.ends_with(" [ -f Makefile ] && make test")));
+    }
+
+    #[test]
+    fn test_line_timestamps_gutter() {
+        let look = OutputLook {
+            chars: BoxChars::for_style(BoxStyle::Ascii),
+            border: Style::default(),
+            separator: Style::default(),
+            box_pad_h: 1,
+            long_lines: LongLineMode::Wrap,
+            wrap_indent: 0,
+            line_timestamps: true,
+            show_command: false,
+        };
+        let received = chrono::NaiveTime::from_hms_opt(9, 5, 7);
+        let line = |label: &str, content: &str, received| OutputLine {
+            runner_label: label.to_string(),
+            stream: crate::runner::StreamType::Output,
+            content: content.to_string(),
+            received,
+            ..Default::default()
+        };
+        let text = |line: &OutputLine, width| -> Vec<String> {
+            render_output_line(line, width, &look).iter().map(|l| l.to_string()).collect()
+        };
+
+        // Inside the box border; wrapped rows and padding get a blank gutter
+        let boxed = text(&line("\x00box", "abcdefgh", received), 18);
+        assert_eq!(boxed, vec!["| 09:05:07 abcde |", "|          fgh   |"]);
+        let padding = text(&line("\x00box", "", None), 18);
+        assert_eq!(padding, vec![format!("|{}|", " ".repeat(16))]);
+
+        // Unboxed lines only get one when they carry a time
+        assert_eq!(text(&line("", "plain", received), 18), vec!["09:05:07 plain"]);
+        assert_eq!(text(&line("", "warning", None), 18), vec!["warning"]);
+    }
+
+    #[test]
+    fn test_dropped_lines_marker() {
+        let (mut app, _db) = test_app();
+        app.max_output_lines = 3;
+        for i in 0..3 {
+            app.append_output(OutputLine {
+                runner_label: String::new(),
+                stream: crate::runner::StreamType::Output,
+                content: format!("line {}", i),
+                ..Default::default()
+            });
+        }
+        assert!(app.dropped_lines_marker().is_none());
+
+        for i in 3..5 {
+            app.append_output(OutputLine {
+                runner_label: String::new(),
+                stream: crate::runner::StreamType::Output,
+                content: format!("line {}", i),
+                ..Default::default()
+            });
         }
+        assert_eq!(app.output.front().unwrap().content, "line 2");
+        let marker = app.dropped_lines_marker().unwrap();
+        assert!(marker.content.contains("⋯ 2 earlier lines dropped ⋯"));
+
+        app.clear_output();
+        assert!(app.dropped_lines_marker().is_none());
     }
 
-    pub fn prev_suggestion(&mut self) {
-        if !self.suggestions.is_empty() {
-            if self.selected_suggestion == 0 {
-                self.selected_suggestion = self.suggestions.len() - 1;
-            } else {
-                self.selected_suggestion -= 1;
-            }
+    #[test]
+    fn test_undo_clear_output() {
+        let (mut app, _db) = test_app();
+        app.add_warning("first".to_string());
+        app.add_warning("second".to_string());
+        app.scroll_up(1);
+
+        app.clear_output();
+        assert!(app.output.is_empty());
+        app.undo_clear_output();
+        assert_eq!(app.output.len(), 2);
+        assert!(!app.auto_scroll);
+
+        // Ctrl+L on the cleared pane restores too
+        app.clear_output();
+        app.clear_output();
+        assert_eq!(app.output.len(), 2);
+
+        // New output ends the chance to undo
+        app.clear_output();
+        app.push_output(OutputMessage::status(1, "", "started"));
+        app.push_output(OutputMessage::output(1, "", "fresh".to_string()));
+        app.push_output(OutputMessage::status(1, "", "completed"));
+        app.undo_clear_output();
+        assert!(!app.output.iter().any(|l| l.content == "first"));
+    }
+
+    #[test]
+    fn test_ctrl_z_undoes_clear_before_suspending() {
+        let (mut app, _db) = test_app();
+        app.add_warning("kept".to_string());
+
+        app.clear_output();
+        app.undo_clear_or_suspend();
+        assert_eq!(app.output.len(), 1);
+        assert!(!app.take_suspend_request());
+
+        // Nothing to bring back: suspend, once
+        app.undo_clear_or_suspend();
+        assert!(app.take_suspend_request());
+        assert!(!app.take_suspend_request());
+    }
+
+    #[test]
+    fn test_output_focus_scrolling() {
+        let (mut app, _db) = test_app();
+        app.output_height = 10;
+        app.toggle_output_focus();
+        assert!(app.is_output_focused());
+
+        app.jump_to_top();
+        assert_eq!(app.scroll_offset(), 0);
+        assert!(!app.auto_scroll);
+        app.scroll_half_page_down();
+        assert_eq!(app.scroll_offset(), 5);
+        app.scroll_down(1);
+        app.scroll_page_down();
+        assert_eq!(app.scroll_offset(), 16);
+        app.scroll_half_page_up();
+        app.scroll_page_up();
+        assert_eq!(app.scroll_offset(), 1);
+
+        app.jump_to_bottom();
+        assert!(app.auto_scroll);
+        app.toggle_output_focus();
+        assert!(!app.is_output_focused());
+    }
+
+    #[tokio::test]
+    async fn test_output_chords() {
+        use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+        let (mut app, _db) = test_app();
+        let (tx, _rx) = tokio::sync::mpsc::channel(16);
+        let mut runner = TaskRunner::new(tx, 4);
+        let key = |c| KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE);
+        app.output_height = 10;
+        for i in 0..30 {
+            app.add_warning(format!("line {}", i));
         }
+        app.toggle_output_focus();
+        app.scroll_to_bottom();
+        let bottom = app.scroll_offset();
+
+        // `g g` completes at once
+        crate::keymap::handle_key_event(&mut app, key('g'), &mut runner);
+        assert_eq!(app.scroll_offset(), bottom);
+        assert!(app.chord_deadline().is_some());
+        crate::keymap::handle_key_event(&mut app, key('g'), &mut runner);
+        assert_eq!(app.scroll_offset(), 0);
+        assert_eq!(app.chord_deadline(), None);
+
+        // A key that makes no chord: the first one acts alone, then it
+        app.scroll_to_bottom();
+        crate::keymap::handle_key_event(&mut app, key('g'), &mut runner);
+        crate::keymap::handle_key_event(&mut app, key('j'), &mut runner);
+        assert_eq!(app.scroll_offset(), 1);
+
+        // Timed out: the first key alone, once the deadline has passed
+        app.scroll_to_bottom();
+        crate::keymap::handle_key_event(&mut app, key('g'), &mut runner);
+        let deadline = app.chord_deadline().unwrap();
+        crate::keymap::expire_chord(&mut app, deadline - Duration::from_millis(1));
+        assert_eq!(app.scroll_offset(), bottom);
+        crate::keymap::expire_chord(&mut app, deadline);
+        assert_eq!(app.scroll_offset(), 0);
+        assert_eq!(app.chord_deadline(), None);
     }
 
-    pub fn get_suggestion_preview(&self) -> Option<String> {
-        if self.suggestions.is_empty() || self.selected_suggestion >= self.suggestions.len() {
-            return None;
+    #[tokio::test]
+    async fn test_output_count_prefix() {
+        use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+        let (mut app, _db) = test_app();
+        let (tx, _rx) = tokio::sync::mpsc::channel(16);
+        let mut runner = TaskRunner::new(tx, 4);
+        let press = |app: &mut App, runner: &mut TaskRunner, code| {
+            crate::keymap::handle_key_event(app, KeyEvent::new(code, KeyModifiers::NONE), runner);
+        };
+        app.output_height = 10;
+        app.toggle_output_focus();
+        app.jump_to_top();
+
+        // Digits accumulate, and a leading 0 isn't a count
+        for c in ['0', '1', '2'] {
+            press(&mut app, &mut runner, KeyCode::Char(c));
         }
+        assert_eq!(app.pending_count(), Some(12));
+        press(&mut app, &mut runner, KeyCode::Char('j'));
+        assert_eq!(app.scroll_offset(), 12);
+        assert_eq!(app.pending_count(), None);
+
+        press(&mut app, &mut runner, KeyCode::Char('3'));
+        press(&mut app, &mut runner, KeyCode::PageDown);
+        assert_eq!(app.scroll_offset(), 42);
+        press(&mut app, &mut runner, KeyCode::Char('2'));
+        press(&mut app, &mut runner, KeyCode::Char('u'));
+        assert_eq!(app.scroll_offset(), 32);
+
+        // Without a count a key acts once; any other key drops the count
+        press(&mut app, &mut runner, KeyCode::Char('k'));
+        assert_eq!(app.scroll_offset(), 31);
+        press(&mut app, &mut runner, KeyCode::Char('9'));
+        press(&mut app, &mut runner, KeyCode::Char('M'));
+        assert_eq!(app.pending_count(), None);
+        press(&mut app, &mut runner, KeyCode::Char('k'));
+        assert_eq!(app.scroll_offset(), 30);
+
+        for _ in 0..8 {
+            press(&mut app, &mut runner, KeyCode::Char('9'));
+        }
+        assert_eq!(app.pending_count(), Some(MAX_COUNT));
+    }
 
-        let suggestion = &self.suggestions[self.selected_suggestion];
+    #[test]
+    fn test_suggestion_display_modes() {
+        let (mut app, _db) = test_app();
+        let dir = tempfile::tempdir().unwrap();
+        let state_path = dir.path().join("state.toml");
+        app.ui_state_path = Some(state_path.clone());
+        app.searcher.record_usage("cargo build").unwrap();
+        app.input = "carg".to_string();
+        app.cursor_position = app.input.len();
+        app.update_suggestions();
+        assert!(app.shows_suggestion_panel());
+        assert!(app.ghost_text().is_some());
+
+        app.cycle_suggestion_display();
+        assert!(!app.shows_suggestion_panel());
+        assert!(app.ghost_text().is_some());
+        assert_eq!(
+            crate::state::UiState::load(&state_path).suggestion_display,
+            Some(SuggestionDisplay::Ghost)
+        );
+
+        // Panel only: no ghost text, so Right doesn't accept an invisible word
+        app.cycle_suggestion_display();
+        assert!(app.shows_suggestion_panel());
+        assert_eq!(app.ghost_text(), None);
+        app.accept_next_preview_word();
+        assert_eq!(app.input, "carg");
+    }
 
-        match suggestion.suggestion_type {
-            crate::suggest::SuggestionType::FullCommand => {
-                if suggestion.text.starts_with(&self.input) {
-                    Some(suggestion.text.get(self.input.len()..)
-                        .unwrap_or("").to_string())
-                } else if self.input.is_empty() {
-                    Some(suggestion.text.clone())
-                } else {
-                    None
-                }
-            }
-            crate::suggest::SuggestionType::Argument
-            | crate::suggest::SuggestionType::ArgumentValue => {
-                if self.input.ends_with(' ') {
-                    Some(suggestion.text.clone())
-                } else {
-                    let last_word_start = self
-                        .input
-                        .rfind(char::is_whitespace)
-                        .map(|pos| pos + self.input.get(pos..).and_then(|s| s.chars().next()).map_or(1, |c| c.len_utf8()))
-                        .unwrap_or(0);
-                    let current_word = self.input.get(last_word_start..).unwrap_or("");
-                    if suggestion.text.starts_with(current_word) {
-                        Some(suggestion.text.get(current_word.len()..)
-                            .unwrap_or("").to_string())
-                    } else {
-                        Some(format!(" {}", suggestion.text))
-                    }
-                }
-            }
+    #[test]
+    fn test_cycle_queries() {
+        let (mut app, _db) = test_app();
+        for command in ["git push origin main", "cargo build --release", "kubectl get pods"] {
+            app.searcher.record_usage(command).unwrap();
         }
+        app.suggestion_engine = SuggestionEngine::new(app.searcher.commands());
+        let type_input = |app: &mut App, text: &str| text.chars().for_each(|c| app.insert_char(c));
+
+        // Left behind by clearing the input, or by accepting what it found
+        type_input(&mut app, "gt psh");
+        app.delete_to_line_start();
+        type_input(&mut app, "crg rel");
+        app.accept_suggestion();
+        assert_eq!(app.input, "cargo build --release");
+        app.delete_to_line_start();
+        // No suggestions: not a search worth keeping
+        type_input(&mut app, "zzzz");
+        app.delete_to_line_start();
+        assert_eq!(app.queries, ["crg rel", "gt psh"]);
+
+        type_input(&mut app, "kub");
+        app.cycle_query(true);
+        assert_eq!(app.input, "crg rel");
+        assert!(!app.suggestions.is_empty());
+        app.cycle_query(true);
+        assert_eq!(app.input, "gt psh");
+        app.cycle_query(true);
+        assert_eq!(app.input, "gt psh");
+        app.cycle_query(false);
+        app.cycle_query(false);
+        assert_eq!(app.input, "kub");
+        app.cycle_query(false);
+        assert_eq!(app.input, "kub");
+
+        // The draft is remembered when cleared; recalled queries keep their place
+        app.delete_to_line_start();
+        app.cycle_query(true);
+        app.cycle_query(true);
+        assert_eq!(app.input, "crg rel");
+        app.delete_to_line_start();
+        assert_eq!(app.queries, ["kub", "crg rel", "gt psh"]);
     }
 
-    /// Compute the full resulting command for a suggestion, split into
-    /// (already_typed_prefix, new_suggestion_suffix) for display purposes.
-    pub fn suggestion_full_preview(&self, suggestion: &Suggestion) -> (String, String) {
-        match suggestion.suggestion_type {
-            crate::suggest::SuggestionType::FullCommand => {
-                // The suggestion IS the full command
-                if suggestion.text.starts_with(&self.input) && !self.input.is_empty() {
-                    (
-                        self.input.clone(),
-                        suggestion.text.get(self.input.len()..)
-                            .unwrap_or("").to_string(),
-                    )
-                } else {
-                    (String::new(), suggestion.text.clone())
-                }
-            }
-            crate::suggest::SuggestionType::Argument
-            | crate::suggest::SuggestionType::ArgumentValue => {
-                if !self.input.ends_with(' ') {
-                    // Mid-word: the typed prefix is input up to the last space
-                    let trimmed = self.input.trim_end();
-                    if let Some(last_space) = trimmed.rfind(char::is_whitespace) {
-                        let end = last_space + trimmed.get(last_space..).and_then(|s| s.chars().next()).map_or(1, |c| c.len_utf8());
-                        let prefix = trimmed.get(..end).unwrap_or(trimmed);
-                        (prefix.to_string(), suggestion.text.clone())
-                    } else {
-                        (String::new(), suggestion.text.clone())
-                    }
-                } else {
-                    // Trailing space: typed prefix is the full input
-                    (self.input.clone(), suggestion.text.clone())
-                }
-            }
+    #[test]
+    fn test_space_accepts_unambiguous_word() {
+        let (mut app, _db) = test_app();
+        app.space_accepts = true;
+        let type_input = |app: &mut App, input: &str, suggestions: &[&str]| {
+            app.input = input.to_string();
+            app.cursor_position = input.len();
+            app.suggestions = suggestions
+                .iter()
+                .map(|s| suggestion(s, SuggestionType::FullCommand))
+                .collect();
+        };
+
+        type_input(&mut app, "cargo bu", &["cargo build --release", "cargo build", "cargo test"]);
+        app.insert_space();
+        assert_eq!(app.input, "cargo build ");
+
+        // Suggestions disagree on the word, or one is the input as typed
+        type_input(&mut app, "git ch", &["git checkout main", "git cherry-pick abc"]);
+        app.insert_space();
+        assert_eq!(app.input, "git ch ");
+        type_input(&mut app, "ls -l", &["ls -la", "ls -l"]);
+        app.insert_space();
+        assert_eq!(app.input, "ls -l ");
+
+        // The word is already complete
+        type_input(&mut app, "cargo", &["cargo build"]);
+        app.insert_space();
+        assert_eq!(app.input, "cargo ");
+
+        // Inside a quote the space belongs to the argument
+        type_input(&mut app, "git commit -m \"fi", &["git commit -m \"fix bug\""]);
+        app.insert_space();
+        assert_eq!(app.input, "git commit -m \"fi ");
+
+        // Off: Space is just a space
+        app.space_accepts = false;
+        type_input(&mut app, "cargo bu", &["cargo build"]);
+        app.insert_space();
+        assert_eq!(app.input, "cargo bu ");
+    }
+
+    #[test]
+    fn test_extract_first_word() {
+        assert_eq!(extract_first_word(" commit -m", None), " commit");
+        assert_eq!(extract_first_word("\"fix bug\" --amend", None), "\"fix bug\"");
+        assert_eq!(extract_first_word(" 'a b'c d", None), " 'a b'c");
+        assert_eq!(extract_first_word("a\\ b c", None), "a\\ b");
+        assert_eq!(extract_first_word("\"unclosed quote", None), "\"unclosed quote");
+        // Continuing inside a quote opened in the input
+        assert_eq!(extract_first_word("x bug\" --amend", Some('"')), "x bug\"");
+        assert_eq!(extract_first_word(" it's\" now", Some('"')), " it's\"");
+
+        assert_eq!(open_quote("git commit -m \"fi"), Some('"'));
+        assert_eq!(open_quote("echo 'it\"s"), Some('\''));
+        assert_eq!(open_quote("echo \\\"x"), None);
+        assert_eq!(open_quote("echo \"a\" 'b'"), None);
+    }
+
+    #[test]
+    fn test_accept_next_word_keeps_quotes_together() {
+        let (mut app, _db) = test_app();
+        app.searcher.record_usage("git commit -m \"fix bug\" --amend").unwrap();
+
+        app.input = "git commit".to_string();
+        app.cursor_position = app.input.len();
+        app.update_suggestions();
+        app.accept_next_preview_word();
+        assert_eq!(app.input, "git commit -m");
+        app.accept_next_preview_word();
+        assert_eq!(app.input, "git commit -m \"fix bug\"");
+        app.accept_next_preview_word();
+        assert_eq!(app.input, "git commit -m \"fix bug\" --amend");
+
+        // Accepting from inside the quote finishes the quoted argument
+        app.input = "git commit -m \"fi".to_string();
+        app.cursor_position = app.input.len();
+        app.update_suggestions();
+        app.accept_next_preview_word();
+        assert_eq!(app.input, "git commit -m \"fix bug\"");
+    }
+
+    #[test]
+    fn test_accept_full_preview() {
+        let (mut app, _db) = test_app();
+        app.searcher.record_usage("git commit -m \"fix bug\" --amend").unwrap();
+
+        app.input = "git co".to_string();
+        app.cursor_position = 3;
+        app.update_suggestions();
+        // Only at the end of the input
+        app.accept_full_preview();
+        assert_eq!(app.input, "git co");
+
+        app.cursor_position = app.input.len();
+        app.accept_full_preview();
+        assert_eq!(app.input, "git commit -m \"fix bug\" --amend");
+        assert_eq!(app.cursor_position, app.input.len());
+        assert!(app.ghost_text().is_none_or(|rest| rest.is_empty()));
+    }
+
+    #[test]
+    fn test_accept_quoted_value() {
+        let (mut app, _db) = test_app();
+        let value = suggestion("fix bug", SuggestionType::ArgumentValue);
+
+        app.input = "git commit -m \"fix ".to_string();
+        app.suggestions = vec![value.clone()];
+        assert_eq!(app.get_suggestion_preview().as_deref(), Some("bug\""));
+        app.accept_suggestion();
+        assert_eq!(app.input, "git commit -m \"fix bug\"");
+
+        app.input = "git commit -m 'fi".to_string();
+        app.suggestions = vec![value.clone()];
+        app.accept_suggestion();
+        assert_eq!(app.input, "git commit -m 'fix bug'");
+
+        // Unquoted partial: quoted only because the value needs it
+        app.input = "git commit -m ".to_string();
+        app.suggestions = vec![value];
+        app.accept_suggestion();
+        assert_eq!(app.input, "git commit -m 'fix bug'");
+    }
+
+    #[tokio::test]
+    async fn test_allowed_cwd_refuses_commands() {
+        let (mut app, _db) = test_app();
+        let cwd = std::env::current_dir().unwrap();
+        app.cwd_policy = CwdPolicy::new(std::slice::from_ref(&cwd));
+        let (tx, _rx) = tokio::sync::mpsc::channel(16);
+        let mut runner = TaskRunner::new(tx, 4);
+
+        app.input = "cd / && ls".to_string();
+        app.submit_command(&mut runner);
+        assert_eq!(app.input, "cd / && ls");
+        assert_eq!(app.last_run, None);
+        assert!(app.searcher.search("cd / && ls", 5).is_empty());
+        assert_eq!(
+            app.output.back().unwrap().content,
+            "Command not run: / is outside [runner] allowed_cwd"
+        );
+
+        // One task of a parallel block outside refuses the whole block
+        app.input = "[d=src,/] cd {d}".to_string();
+        app.submit_command(&mut runner);
+        assert_eq!(app.input, "[d=src,/] cd {d}");
+        assert_eq!(app.last_run, None);
+
+        app.input = "cd src && true".to_string();
+        app.submit_command(&mut runner);
+        assert!(app.input.is_empty());
+        assert_eq!(app.last_run.as_deref(), Some("cd src && true"));
+    }
+
+    #[tokio::test]
+    async fn test_empty_enter() {
+        let (mut app, _db) = test_app();
+        let (tx, _rx) = tokio::sync::mpsc::channel(16);
+        let mut runner = TaskRunner::new(tx, 4);
+        app.searcher.record_usage("echo hi").unwrap();
+
+        assert!(!app.submit_command(&mut runner));
+        assert!(app.output.is_empty());
+        assert_eq!(app.last_run, None);
+
+        app.empty_enter = EmptyEnter::Separator;
+        app.submit_command(&mut runner);
+        let rule = app.output.back().unwrap();
+        assert_eq!((rule.stream.clone(), rule.content.as_str()), (crate::runner::StreamType::Status, ""));
+        assert_eq!(app.last_run, None);
+
+        app.empty_enter = EmptyEnter::RerunLast;
+        app.submit_command(&mut runner);
+        assert_eq!(app.last_run.as_deref(), Some("echo hi"));
+    }
+
+    #[test]
+    fn test_esc_clears_then_quits() {
+        let (mut app, _db) = test_app();
+        app.searcher.record_usage("git status").unwrap();
+        app.searcher.record_usage("git stash").unwrap();
+        for c in "git".chars() {
+            app.insert_char(c);
         }
+        assert!(!app.escape());
+        assert!(app.escape());
+        assert_eq!(app.input(), "git");
+
+        app.esc_action = EscAction::ClearThenQuit;
+        app.last_quit_press = None;
+        app.next_suggestion();
+        assert!(!app.escape());
+        assert_eq!((app.input(), app.cursor_position), ("", 0));
+        assert!(!app.is_quit_hint_active());
+
+        // A browsed suggestion is cancelled even with nothing typed
+        app.next_suggestion();
+        assert!(!app.escape());
+        assert!(!app.suggestion_browsed);
+
+        assert!(!app.escape());
+        assert!(app.is_quit_hint_active());
+        assert!(app.escape());
     }
 
-    /// Build colorized spans for a full command suggestion.
-    /// Tokens are classified as: typed prefix (dim gray), argument (cyan), value (green),
-    /// or subcommand (bold white).
-    pub fn colorize_command_suggestion<'a>(&self, suggestion: &Suggestion) -> Vec<Span<'a>> {
-        let tokens: Vec<&str> = suggestion.text.split_whitespace().collect();
-        let input_trimmed = self.input.trim_start();
+    #[tokio::test]
+    async fn test_command_policy_refuses_commands() {
+        let (mut app, _db) = test_app();
+        app.command_policy = CommandPolicy::new(&[], &["^rm ".to_string()]);
+        let (tx, _rx) = tokio::sync::mpsc::channel(16);
+        let mut runner = TaskRunner::new(tx, 4);
+
+        app.input = "rm -rf build".to_string();
+        app.submit_command(&mut runner);
+        assert_eq!(app.input, "rm -rf build");
+        assert_eq!(app.last_run, None);
+        assert!(app.searcher.search("rm -rf build", 5).is_empty());
+        assert_eq!(
+            app.output.back().unwrap().content,
+            "Command not run: `rm -rf build` matches [runner] denylist pattern `^rm `"
+        );
+
+        // Each command of a parallel block is checked, after expansion
+        app.input = "[c=ls,rm] {c} -f x".to_string();
+        app.submit_command(&mut runner);
+        assert_eq!(app.last_run, None);
+        assert!(app.output.back().unwrap().content.contains("`rm -f x`"));
+
+        // Allowlist mode; captures are checked too, internal commands aren't
+        app.input.clear();
+        app.command_policy = CommandPolicy::new(&["^echo ".to_string()], &[]);
+        app.input = "X=$(date)".to_string();
+        app.submit_command(&mut runner);
+        assert_eq!(app.last_run, None);
+        assert_eq!(
+            app.output.back().unwrap().content,
+            "Command not run: `date` matches no [runner] allowlist pattern"
+        );
+        app.input = "set Y=1".to_string();
+        app.submit_command(&mut runner);
+        assert_eq!(app.session_vars.get("Y").map(String::as_str), Some("1"));
+        app.input = "echo ok".to_string();
+        app.submit_command(&mut runner);
+        assert_eq!(app.last_run.as_deref(), Some("echo ok"));
+        app.input = "quit".to_string();
+        assert!(app.submit_command(&mut runner));
+    }
 
-        // Count how many leading tokens match what's already typed
-        let input_tokens: Vec<&str> = input_trimmed.split_whitespace().collect();
-        let typed_count = input_tokens
-            .iter()
-            .zip(tokens.iter())
-            .take_while(|(a, b)| a == b)
-            .count();
+    #[tokio::test]
+    async fn test_enter_accepts_picked_suggestion() {
+        let (mut app, _db) = test_app();
+        app.enter_accepts = true;
+        let (tx, _rx) = tokio::sync::mpsc::channel(16);
+        let mut runner = TaskRunner::new(tx, 4);
+        app.input = "echo b".to_string();
+        app.suggestions = vec![
+            suggestion("echo bench", SuggestionType::FullCommand),
+            suggestion("echo build", SuggestionType::FullCommand),
+        ];
+
+        // Nothing picked: Enter runs what was typed
+        app.submit_or_accept(&mut runner);
+        assert!(app.input.is_empty());
+        assert!(!app.searcher.search("echo b", 5).is_empty());
+
+        app.input = "echo b".to_string();
+        app.suggestions = vec![
+            suggestion("echo bench", SuggestionType::FullCommand),
+            suggestion("echo build", SuggestionType::FullCommand),
+        ];
+        app.next_suggestion();
+        app.submit_or_accept(&mut runner);
+        assert_eq!(app.input, "echo build");
+        assert!(!app.suggestion_browsed);
+    }
 
-        // Find where the prefix ends (first '-' token)
-        let prefix_end = tokens
-            .iter()
-            .position(|t| t.starts_with('-'))
-            .unwrap_or(tokens.len());
+    #[test]
+    fn test_overlong_command_not_run() {
+        let (mut app, _db) = test_app();
+        app.max_command_length = 16;
+        let long = format!("echo {}", "x".repeat(20));
+        app.input = long.clone();
+        app.submit_command(&mut TaskRunner::new(tokio::sync::mpsc::channel(1).0, 1));
+
+        assert_eq!(app.input, long);
+        assert!(app.searcher.search("echo", 5).is_empty());
+        assert_eq!(
+            app.output.back().unwrap().content,
+            "Command not run: 25 B is over [runner] max_command_length (16 B)"
+        );
+    }
 
-        let mut spans = Vec::new();
+    #[test]
+    fn test_malformed_parallel_block_not_run() {
+        let (mut app, _db) = test_app();
+        app.input = "[shard=1-3 region=a,b] deploy {shard} {region}".to_string();
+        app.submit_command(&mut TaskRunner::new(tokio::sync::mpsc::channel(1).0, 1));
+
+        assert_eq!(
+            app.output.back().unwrap().content,
+            "Invalid parallel block: zip lengths differ: shard=3, region=2"
+        );
+        // Left in the input for fixing, and not recorded
+        assert_eq!(app.input, "[shard=1-3 region=a,b] deploy {shard} {region}");
+        assert_eq!(app.searcher.len(), 0);
+    }
 
-        for (i, tok) in tokens.iter().enumerate() {
-            if i > 0 {
-                spans.push(Span::raw(" "));
-            }
+    #[test]
+    fn test_command_not_found_hint() {
+        let (mut app, _db) = test_app();
+        app.searcher.record_usage("git status").unwrap();
+        let stats = |exit_code| crate::runner::TaskStats {
+            exit_code,
+            lines: 0,
+            bytes: 0,
+            elapsed: Duration::ZERO,
+            attempts: 1,
+        };
+
+        app.running.insert(1, RunningTask { command: "gti status -s".to_string(), records_outcome: true });
+        app.push_output(OutputMessage::completed(1, "", "command not found (127)", stats(Some(127))));
+        assert_eq!(app.output.back().unwrap().content, "did you mean: git status -s?");
+
+        // Other failures get no hint
+        app.running.insert(2, RunningTask { command: "gti status".to_string(), records_outcome: true });
+        app.push_output(OutputMessage::completed(2, "", "exited with code 1", stats(Some(1))));
+        assert_eq!(app.output.back().unwrap().runner_label, "\x00bot");
+    }
 
-            let style = if i < typed_count {
-                // Already typed — dim
-                Style::default().fg(Color::DarkGray)
-            } else if i < prefix_end {
-                // Subcommand token (not yet typed)
-                Style::default().fg(Color::White).add_modifier(Modifier::BOLD)
-            } else if tok.starts_with('-') && *tok != "--" {
-                // Argument token
-                Style::default().fg(Color::Cyan)
-            } else {
-                // Value token
-                Style::default().fg(Color::Green)
-            };
+    #[test]
+    fn test_parallel_failures_counted() {
+        let (mut app, _db) = test_app();
+        app.parallel_progress = Some((0, 3));
+        app.parallel_pending = [1, 2, 3].into_iter().collect();
+
+        let stats = |exit_code| crate::runner::TaskStats {
+            exit_code,
+            lines: 0,
+            bytes: 0,
+            elapsed: Duration::ZERO,
+            attempts: 1,
+        };
+        app.push_output(OutputMessage::completed(1, "[n=1]", "completed", stats(Some(0))));
+        app.push_output(OutputMessage::completed(2, "[n=2]", "exited with code 2", stats(Some(2))));
+        app.push_output(OutputMessage::completed(3, "[n=3]", "error: spawn failed", stats(None)));
+
+        assert_eq!(app.parallel_progress, Some((3, 3)));
+        assert_eq!(app.parallel_failed, 2);
+    }
 
-            spans.push(Span::styled(tok.to_string(), style));
+    #[test]
+    fn test_filter_by_label() {
+        let (mut app, _db) = test_app();
+        app.add_warning("startup warning".to_string());
+        for (id, label) in [(1, "[host=web-1]"), (2, "[host=db-1]"), (3, "[host=web-2]")] {
+            app.push_output(OutputMessage::output(id, label, format!("out {}", id)));
+            app.push_output(OutputMessage::status(id, label, "completed"));
         }
 
-        spans
+        let shown = filter_by_label(&app.output, "WEB");
+        let contents: Vec<&str> = shown
+            .iter()
+            .filter(|l| l.runner_label == "\x00box")
+            .map(|l| l.content.as_str())
+            .collect();
+        assert_eq!(contents, vec!["out 1", "out 3"]);
+        // Each matching box is kept whole: top, content, bottom
+        assert_eq!(shown.len(), 6);
+
+        assert!(filter_by_label(&app.output, "cache").is_empty());
     }
 
-    // --- Quit ---
-
-    /// Handle a quit key press (Ctrl+C, Ctrl+D, Esc). Returns true if should quit.
-    pub fn try_quit(&mut self) -> bool {
-        if let Some(last) = self.last_quit_press {
-            if last.elapsed() < std::time::Duration::from_secs(1) {
-                return true; // Second press within 1s — quit
+    #[test]
+    fn test_box_chars_single_width() {
+        for style in [BoxStyle::Unicode, BoxStyle::Ascii, BoxStyle::None] {
+            let c = BoxChars::for_style(style);
+            for s in [c.top_left, c.top_right, c.bottom_left, c.bottom_right, c.horizontal, c.vertical] {
+                assert_eq!(unicode_width::UnicodeWidthStr::width(s), 1, "{:?}", style);
             }
         }
-        self.last_quit_press = Some(std::time::Instant::now());
-        false
     }
 
-    /// Whether the "press again to quit" hint should be shown
-    pub fn is_quit_hint_active(&self) -> bool {
-        self.last_quit_press
-            .is_some_and(|t| t.elapsed() < std::time::Duration::from_secs(1))
+    #[test]
+    fn test_pager_line_uses_box_style() {
+        let ascii = BoxChars::for_style(BoxStyle::Ascii);
+        let top = OutputLine {
+            runner_label: "\x00top:[n=1]".to_string(),
+            stream: crate::runner::StreamType::Status,
+            content: String::new(),
+            ..Default::default()
+        };
+        assert_eq!(pager_line(&top, &ascii, false), "+- [n=1]");
+
+        let content = OutputLine {
+            runner_label: "\x00box".to_string(),
+            stream: crate::runner::StreamType::Output,
+            content: "\x1b[32mok\x1b[0m".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(pager_line(&content, &ascii, false), "| ok");
     }
-}
 
-pub async fn run_tui(
-    searcher: HistorySearcher,
-    suggestion_engine: SuggestionEngine,
-    startup_warnings: Vec<String>,
-    config: Config,
-) -> Result<HistorySearcher, Box<dyn std::error::Error>> {
-    enable_raw_mode()?;
-    let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen)?;
-    let backend = CrosstermBackend::new(stdout);
-    let mut terminal = Terminal::new(backend)?;
+    #[test]
+    fn test_transcript_is_plain_text() {
+        let (mut app, _db) = test_app();
+        app.box_chars = BoxChars::for_style(BoxStyle::Ascii);
+        app.push_output(OutputMessage::status(1, "", "started"));
+        app.push_output(OutputMessage::output(1, "", "\x1b[1mbuilt\x1b[0m".to_string()));
+        app.push_output(OutputMessage::status(1, "", "completed"));
+        app.add_warning("history not synced".to_string());
+
+        let transcript = app.transcript();
+        assert!(transcript.contains(&"| built".to_string()), "{:?}", transcript);
+        assert_eq!(transcript.last().unwrap(), "-- history not synced");
+    }
 
-    let (output_tx, mut output_rx) = tokio::sync::mpsc::channel::<OutputMessage>(256);
-    let mut runner = TaskRunner::new(output_tx, config.runner.max_concurrent);
-    let mut event_stream = EventStream::new();
+    #[test]
+    fn test_reindex_picks_up_database_changes() {
+        let (mut app, db) = test_app();
 
-    let mut app = App::new(searcher, suggestion_engine, &config);
-    for warning in startup_warnings {
-        app.add_warning(warning);
-    }
-    let mut should_quit = false;
-    let mut tick = tokio::time::interval(std::time::Duration::from_secs(1));
+        // Written behind the app's back, e.g. by another mux instance
+        let mut other = HistorySearcher::new(db.path().to_path_buf()).unwrap();
+        other.record_usage("cargo build --release").unwrap();
+        other.flush().unwrap();
 
-    loop {
-        terminal.draw(|f| {
-            let show_suggestions = app.has_suggestions();
-            let box_pad_h = app.box_pad_h;
-            let area = f.area();
+        app.input = "reindex".to_string();
+        app.submit_command(&mut TaskRunner::new(tokio::sync::mpsc::channel(1).0, 1));
 
-            // Calculate input height: 1 line of content + 2 for borders, grows with wrapping
-            let input_content_width = area.width.saturating_sub(2) as usize; // subtract border columns
-            let input_lines = if input_content_width > 0 {
-                (app.input().len() / input_content_width + 1).max(1) as u16
-            } else {
-                1
-            };
-            let input_height = input_lines + 2; // +2 for top/bottom border
+        assert_eq!(app.searcher.len(), 1);
+        app.input = "cargo build --re".to_string();
+        app.update_suggestions();
+        assert!(app.suggestions.iter().any(|s| s.text == "cargo build --release"));
+        assert!(app.output.back().unwrap().content.contains("Reindexed 1 commands"));
+    }
 
-            // Suggestions: 5 content lines + 2 borders when visible
-            let suggestion_height: u16 = if show_suggestions { 7 } else { 0 };
+    #[test]
+    fn test_toggle_label_filter() {
+        let (mut app, _db) = test_app();
+        app.input = " web ".to_string();
+        app.toggle_label_filter();
+        assert_eq!(app.label_filter(), Some("web"));
+        assert!(app.input.is_empty());
 
-            let chunks = Layout::default()
-                .direction(Direction::Vertical)
-                .constraints([
-                    Constraint::Min(1),                       // Output: fills remaining
-                    Constraint::Length(suggestion_height),     // Suggestions: 5 lines or 0
-                    Constraint::Length(input_height),          // Input: adapts to content
-                ])
-                .split(area);
+        app.toggle_label_filter();
+        assert_eq!(app.label_filter(), None);
+    }
 
-            // Output section
-            let output_area_height = chunks[0].height.saturating_sub(2) as usize; // subtract borders
-            let total_lines = app.output().len();
+    #[test]
+    fn test_outcomes_recorded_per_submission() {
+        let (mut app, _db) = test_app();
+        let stats = |exit_code| crate::runner::TaskStats {
+            exit_code,
+            lines: 0,
+            bytes: 0,
+            elapsed: Duration::ZERO,
+            attempts: 1,
+        };
+
+        app.searcher.record_usage("make").unwrap();
+        app.running.insert(1, RunningTask { command: "make".to_string(), records_outcome: true });
+        app.push_output(OutputMessage::completed(1, "", "exited with code 2", stats(Some(2))));
+
+        // A batch is one run, failed because one of its tasks failed
+        app.searcher.record_usage("[n=1-2] ping {n}").unwrap();
+        app.parallel_command = Some("[n=1-2] ping {n}".to_string());
+        app.parallel_pending = [2, 3].into_iter().collect();
+        app.push_output(OutputMessage::completed(2, "[n=1]", "completed", stats(Some(0))));
+        app.push_output(OutputMessage::completed(3, "[n=2]", "exited with code 1", stats(Some(1))));
+
+        let flaky = app.searcher.flaky_commands(1, 10).unwrap();
+        assert_eq!(flaky.len(), 2);
+        assert!(flaky.iter().all(|c| c.fail_count == 1 && c.success_count == 0));
+    }
 
-            // Clamp scroll_offset: it's the index of the first visible line (top-of-window).
-            // Max value ensures the last page is fully visible.
-            let max_scroll = total_lines.saturating_sub(output_area_height);
-            let scroll_offset = app.scroll_offset().min(max_scroll);
+    #[test]
+    fn test_cancelled_queued_task_counts_without_box() {
+        let (mut app, _db) = test_app();
+        app.parallel_progress = Some((0, 2));
+        app.parallel_pending = [1, 2].into_iter().collect();
+
+        let stats = crate::runner::TaskStats {
+            exit_code: None,
+            lines: 0,
+            bytes: 0,
+            elapsed: Duration::ZERO,
+            attempts: 1,
+        };
+        app.push_output(OutputMessage::completed(2, "[n=2]", crate::runner::CANCELLED_STATUS, stats));
+
+        assert!(app.output.is_empty());
+        assert_eq!(app.parallel_progress, Some((1, 2)));
+        assert_eq!(app.parallel_failed, 1);
+        assert!(!app.parallel_pending.contains(&2));
+    }
 
-            // Re-enable auto-scroll if we're at the bottom
-            if scroll_offset >= max_scroll {
-                app.auto_scroll = true;
-            }
-            // Update the stored offset to the clamped value
-            app.scroll_offset = scroll_offset;
+    #[tokio::test]
+    async fn test_fail_fast_batch_reports_aborted_tasks() {
+        let (mut app, _db) = test_app();
+        let (tx, mut rx) = tokio::sync::mpsc::channel(64);
+        let mut runner = TaskRunner::new(tx, 1);
+
+        app.input = "[n=1-4] [fail-fast] sh -c 'exit $((4 - {n}))'".to_string();
+        app.submit_command(&mut runner);
+        while !app.parallel_pending.is_empty() {
+            let msg = tokio::time::timeout(Duration::from_secs(10), rx.recv()).await.unwrap().unwrap();
+            app.push_output(msg);
+        }
 
-            let visible_start = scroll_offset;
-            let visible_end = (scroll_offset + output_area_height).min(total_lines);
+        // One at a time: the first fails and the other three never start
+        assert_eq!(app.parallel_failed, 1);
+        assert_eq!(app.parallel_aborted, 3);
+        assert_eq!(app.parallel_progress, Some((4, 4)));
+        assert!(app.output.back().unwrap().content.contains("fail-fast: aborted 3 remaining due to failure"));
+        let boxes = app.output.iter().filter(|l| l.runner_label.starts_with("\x00top:")).count();
+        assert_eq!(boxes, 1);
+    }
 
-            let output_width = chunks[0].width.saturating_sub(2) as usize; // subtract borders
+    #[test]
+    fn test_parallelize_loads_template() {
+        let (mut app, _db) = test_app();
+        for cmd in ["ssh web-1 uptime", "ssh web-2 uptime", "ssh web-3 uptime", "ls"] {
+            app.searcher.record_usage(cmd).unwrap();
+        }
 
-            let output_lines: Vec<Line> = app
-                .output()
-                .iter()
-                .skip(visible_start)
-                .take(visible_end - visible_start)
-                .flat_map(|line| {
-                    let border_style = Style::default().fg(Color::DarkGray);
-
-                    // Box drawing for parallel output blocks
-                    // 1 char inner padding on each side: │  content  │
-
-                    if line.runner_label.starts_with("\x00top:") {
-                        let label = &line.runner_label[5..];
-                        let left = if label.is_empty() {
-                            "┌".to_string()
-                        } else {
-                            format!("┌─ {} ", label)
-                        };
-                        let left_w = unicode_width::UnicodeWidthStr::width(left.as_str());
-                        let right = "─┐";
-                        let right_w = unicode_width::UnicodeWidthStr::width(right);
-                        let fill_len = output_width.saturating_sub(left_w).saturating_sub(right_w);
-                        let fill: String = "─".repeat(fill_len);
-
-                        return vec![Line::from(vec![
-                            Span::styled(left, border_style),
-                            Span::styled(fill, border_style),
-                            Span::styled(right, border_style),
-                        ])];
-                    }
+        app.parallelize("ssh");
+        assert_eq!(app.input, "[x=1-3] ssh web-{x} uptime");
 
-                    if line.runner_label == "\x00bot" {
-                        let left = "└";
-                        let left_w = unicode_width::UnicodeWidthStr::width(left);
+        app.input.clear();
+        let before = app.output.len();
+        app.parallelize("git");
+        assert!(app.input.is_empty());
+        assert_eq!(app.output.len(), before + 1);
+    }
 
-                        let (right, right_w) = if line.content.is_empty() {
-                            ("─┘".to_string(), unicode_width::UnicodeWidthStr::width("─┘"))
-                        } else {
-                            let r = format!(" {} ─┘", line.content);
-                            let w = unicode_width::UnicodeWidthStr::width(r.as_str());
-                            (r, w)
-                        };
+    #[test]
+    fn test_tagging_a_suggestion() {
+        let (mut app, _db) = test_app();
+        app.searcher.record_usage("kubectl apply -f prod.yaml").unwrap();
+        app.searcher.record_usage("kubectl get pods").unwrap();
+        app.input = "kubectl apply".to_string();
+        app.cursor_position = app.input.len();
+        app.update_suggestions();
+        assert_eq!(app.suggestions[0].text, "kubectl apply -f prod.yaml");
+
+        // The input holds the tags while tagging, then comes back
+        app.start_tagging();
+        assert!(app.is_tagging());
+        assert!(app.input.is_empty() && app.suggestions.is_empty());
+        app.input = "Deploy #prod bad:tag".to_string();
+        app.apply_tagging();
+        assert!(!app.is_tagging());
+        assert_eq!(app.input, "kubectl apply");
+        assert_eq!(app.searcher.tags("kubectl apply -f prod.yaml"), ["deploy", "prod"]);
+        assert!(app.output.back().unwrap().content.contains("\"bad:tag\""));
+
+        app.start_tagging();
+        app.input = "-prod".to_string();
+        app.apply_tagging();
+        assert_eq!(app.searcher.tags("kubectl apply -f prod.yaml"), ["deploy"]);
+
+        // Cancelling changes nothing
+        app.start_tagging();
+        app.input = "other".to_string();
+        app.cancel_tagging();
+        assert_eq!(app.input, "kubectl apply");
+        assert_eq!(app.searcher.tags("kubectl apply -f prod.yaml"), ["deploy"]);
+
+        // `@tag:` narrows suggestions to tagged commands
+        app.input = "@tag:deploy kub".to_string();
+        app.update_suggestions();
+        let texts: Vec<&str> = app.suggestions.iter().map(|s| s.text.as_str()).collect();
+        assert_eq!(texts, ["kubectl apply -f prod.yaml"]);
+    }
 
-                        let fill_len = output_width.saturating_sub(left_w).saturating_sub(right_w);
-                        let fill: String = "─".repeat(fill_len);
+    #[tokio::test]
+    async fn test_arg_picker_recalls_a_whole_invocation() {
+        use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+        let (mut app, _db) = test_app();
+        let (tx, _rx) = tokio::sync::mpsc::channel(16);
+        let mut runner = TaskRunner::new(tx, 4);
+        let mut press = |app: &mut App, code, modifiers| {
+            crate::keymap::handle_key_event(app, KeyEvent::new(code, modifiers), &mut runner);
+        };
+        for command in ["kubectl get pods -n prod -o wide", "kubectl logs -f web --tail 50"] {
+            app.suggestion_engine.index_command(command);
+        }
 
-                        return vec![Line::from(vec![
-                            Span::styled(left, border_style),
-                            Span::styled(fill, border_style),
-                            Span::styled(right, border_style),
-                        ])];
-                    }
+        press(&mut app, KeyCode::Char('r'), KeyModifiers::ALT);
+        assert!(app.arg_picker.is_none());
+        assert!(app.output.back().unwrap().content.contains("type a command first"));
 
-                    if line.runner_label == "\x00box" {
-                        use ansi_to_tui::IntoText;
-                        let parsed = line.content.as_bytes().into_text();
-                        let content_lines = match parsed {
-                            Ok(text) => text.lines,
-                            Err(_) => vec![Line::from(line.content.clone())],
-                        };
+        app.input = "kubectl".to_string();
+        app.cursor_position = app.input.len();
+        press(&mut app, KeyCode::Char('r'), KeyModifiers::ALT);
+        assert_eq!(app.arg_picker.as_ref().unwrap().shown().len(), 2);
 
-                        // Inner width: output_width minus "│" + pad on each side + "│"
-                        let inner_width = output_width.saturating_sub(2 + box_pad_h * 2);
-                        let h_pad = " ".repeat(box_pad_h);
-
-                        return content_lines
-                            .into_iter()
-                            .map(|l| {
-                                let content_width: usize = l.spans.iter().map(|s| {
-                                    unicode_width::UnicodeWidthStr::width(s.content.as_ref())
-                                }).sum();
-                                let pad = inner_width.saturating_sub(content_width);
-
-                                let mut spans = vec![
-                                    Span::styled("│", border_style),
-                                    Span::raw(h_pad.clone()),
-                                ];
-                                spans.extend(l.spans);
-                                spans.push(Span::raw(" ".repeat(pad)));
-                                spans.push(Span::raw(h_pad.clone()));
-                                spans.push(Span::styled("│", border_style));
-                                Line::from(spans)
-                            })
-                            .collect();
-                    }
+        // Typing filters the list instead of editing the input
+        for c in "tail".chars() {
+            press(&mut app, KeyCode::Char(c), KeyModifiers::NONE);
+        }
+        assert_eq!(app.input, "kubectl");
+        press(&mut app, KeyCode::Enter, KeyModifiers::NONE);
+        assert!(app.arg_picker.is_none());
+        assert_eq!(app.input, "kubectl logs -f web --tail 50");
+        assert_eq!(app.cursor_position, app.input.len());
+
+        // Esc leaves the input alone
+        press(&mut app, KeyCode::Char('r'), KeyModifiers::ALT);
+        press(&mut app, KeyCode::Down, KeyModifiers::NONE);
+        press(&mut app, KeyCode::Esc, KeyModifiers::NONE);
+        assert!(app.arg_picker.is_none());
+        assert_eq!(app.input, "kubectl logs -f web --tail 50");
+
+        app.input = "cargo".to_string();
+        app.open_arg_picker();
+        assert!(app.arg_picker.is_none());
+        assert!(app.output.back().unwrap().content.contains("no arguments recorded for `cargo`"));
+    }
 
-                    // Regular (non-parallel) rendering
-                    match line.stream {
-                        crate::runner::StreamType::Status => {
-                            // Single command separator
-                            let right = format!(" {} ", line.content);
-                            let fill_len = output_width
-                                .saturating_sub(1)
-                                .saturating_sub(right.len());
-                            let fill: String = "─".repeat(fill_len);
-
-                            vec![Line::from(vec![
-                                Span::raw(" "),
-                                Span::styled(fill, Style::default().fg(Color::DarkGray)),
-                                Span::styled(right, Style::default().fg(Color::DarkGray)),
-                            ])]
-                        }
-                        crate::runner::StreamType::Output => {
-                            use ansi_to_tui::IntoText;
-                            let parsed = line.content.as_bytes().into_text();
-                            match parsed {
-                                Ok(text) => text.lines,
-                                Err(_) => vec![Line::from(line.content.clone())],
-                            }
-                        }
-                    }
-                })
-                .collect();
+    #[test]
+    fn test_parameterize_picks_a_word() {
+        let (mut app, _db) = test_app();
+        app.input = "ping -c 1 db1".to_string();
+        app.cursor_position = 6;
+
+        // Starts at the word under the cursor and wraps around
+        app.start_parameterize();
+        assert_eq!(app.parameterize_selection(), Some(5..7));
+        app.move_parameterize_selection(true);
+        app.move_parameterize_selection(true);
+        assert_eq!(app.parameterize_selection(), Some(10..13));
+        app.move_parameterize_selection(true);
+        assert_eq!(app.parameterize_selection(), Some(0..4));
+        app.move_parameterize_selection(false);
+
+        app.apply_parameterize();
+        assert!(!app.is_parameterizing());
+        assert_eq!(app.input, "[x=db1] ping -c 1 {x}");
+        assert_eq!(app.cursor_position, "[x=db1".len());
+
+        // Cancelling leaves the input alone
+        app.start_parameterize();
+        app.cancel_parameterize();
+        assert_eq!(app.input, "[x=db1] ping -c 1 {x}");
+
+        app.input.clear();
+        let before = app.output.len();
+        app.start_parameterize();
+        assert!(!app.is_parameterizing());
+        assert_eq!(app.output.len(), before + 1);
+    }
 
-            let output_title = if let Some((completed, total)) = app.parallel_progress {
-                if completed < total {
-                    format!(" Output ({}/{} completed) ", completed, total)
-                } else {
-                    " Output ".to_string()
+    #[tokio::test]
+    async fn test_last_line_renders_inside_box() {
+        let (mut app, _db) = test_app();
+        let (tx, mut rx) = tokio::sync::mpsc::channel(64);
+        let mut runner = TaskRunner::new(tx, 4);
+        app.box_pad_v = 0;
+        let look = OutputLook {
+            chars: BoxChars::for_style(BoxStyle::Ascii),
+            border: Style::default(),
+            separator: Style::default(),
+            box_pad_h: 1,
+            long_lines: LongLineMode::Truncate,
+            wrap_indent: 0,
+            line_timestamps: false,
+            show_command: false,
+        };
+
+        for command in ["printf 'no newline'", "printf 'no newline\\n'", "printf 'no newline\\n\\033[0m'"] {
+            app.output.clear();
+            app.input = command.to_string();
+            app.submit_command(&mut runner);
+            while let Some(msg) = tokio::time::timeout(Duration::from_secs(10), rx.recv()).await.unwrap() {
+                let done = msg.stats.is_some();
+                app.push_output(msg);
+                if done {
+                    break;
                 }
-            } else {
-                " Output ".to_string()
-            };
+            }
 
-            let output = Paragraph::new(output_lines)
-                .block(
-                    Block::default()
-                        .borders(Borders::ALL)
-                        .title(output_title)
-                        .border_style(Style::default().fg(Color::Cyan)),
-                )
-                .style(Style::default().fg(Color::White));
-            f.render_widget(output, chunks[0]);
+            let boxed: Vec<&OutputLine> =
+                app.output.iter().skip_while(|l| !l.runner_label.starts_with("\x00top:")).collect();
+            let labels: Vec<&str> = boxed.iter().map(|l| l.runner_label.as_str()).collect();
+            assert_eq!(labels, ["\x00top:", "\x00box", "\x00bot"], "{}", command);
+            let rows: Vec<String> = render_output_line(boxed[1], 20, &look).iter().map(|l| l.to_string()).collect();
+            assert_eq!(rows, ["| no newline       |"], "{}", command);
+        }
+    }
 
-            // Suggestions section
-            if show_suggestions {
-                let items: Vec<ListItem> = app
-                    .suggestions()
-                    .iter()
-                    .enumerate()
-                    .map(|(i, suggestion)| {
-                        let type_indicator = match suggestion.suggestion_type {
-                            crate::suggest::SuggestionType::FullCommand => "cmd",
-                            crate::suggest::SuggestionType::Argument => "arg",
-                            crate::suggest::SuggestionType::ArgumentValue => "val",
-                        };
+    #[tokio::test]
+    async fn test_quiet_success_summarizes_successful_commands() {
+        let (mut app, _db) = test_app();
+        let (tx, mut rx) = tokio::sync::mpsc::channel(64);
+        let mut runner = TaskRunner::new(tx, 4);
+
+        async fn run(
+            app: &mut App,
+            runner: &mut TaskRunner,
+            rx: &mut tokio::sync::mpsc::Receiver<OutputMessage>,
+            input: &str,
+        ) -> Vec<String> {
+            app.output.clear();
+            app.input = input.to_string();
+            app.submit_command(runner);
+            while let Some(msg) = tokio::time::timeout(Duration::from_secs(10), rx.recv()).await.unwrap() {
+                let done = msg.stats.is_some();
+                app.push_output(msg);
+                if done {
+                    break;
+                }
+            }
+            app.output.iter().map(|l| crate::pager::plain_text(&l.content)).collect()
+        }
 
-                        let is_selected = i == app.selected_suggestion;
-                        let indicator = if is_selected { "▌" } else { " " };
+        app.quiet_success = true;
+        let lines = run(&mut app, &mut runner, &mut rx, "echo formatted").await;
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].starts_with("✓ echo formatted ("), "{:?}", lines);
+        assert_eq!(app.output[0].command.as_deref(), Some("echo formatted"));
+
+        // A failure shows everything
+        let lines = run(&mut app, &mut runner, &mut rx, "echo lint error; exit 3").await;
+        assert!(lines.contains(&"lint error".to_string()));
+        assert!(app.output.iter().any(|l| l.runner_label == "\x00bot"));
+
+        // Without the flag, only matching commands are summarized
+        app.quiet_success = false;
+        app.quiet_commands = vec![regex::Regex::new("^echo ").unwrap()];
+        assert_eq!(run(&mut app, &mut runner, &mut rx, "echo quiet").await.len(), 1);
+        let lines = run(&mut app, &mut runner, &mut rx, "printf 'loud\\n'").await;
+        assert!(lines.contains(&"loud".to_string()));
+    }
 
-                        let mut spans = vec![
-                            Span::styled(
-                                indicator,
-                                Style::default().fg(Color::Yellow),
-                            ),
-                            Span::styled(
-                                format!("[{}] ", type_indicator),
-                                Style::default().fg(Color::DarkGray),
-                            ),
-                        ];
+    #[tokio::test]
+    async fn test_finished_commands_record_their_runtime() {
+        let (mut app, _db) = test_app();
+        let (tx, mut rx) = tokio::sync::mpsc::channel(64);
+        let mut runner = TaskRunner::new(tx, 4);
+
+        app.input = "sleep 0.2".to_string();
+        app.submit_command(&mut runner);
+        while let Some(msg) = tokio::time::timeout(Duration::from_secs(10), rx.recv()).await.unwrap() {
+            let done = msg.stats.is_some();
+            app.push_output(msg);
+            if done {
+                break;
+            }
+        }
+        let typical = app.searcher.typical_duration("sleep 0.2").unwrap();
+        assert!(typical >= Duration::from_millis(200), "{:?}", typical);
+        assert!(typical < Duration::from_secs(10), "{:?}", typical);
+    }
 
-                        if suggestion.suggestion_type == crate::suggest::SuggestionType::FullCommand {
-                            spans.extend(app.colorize_command_suggestion(suggestion));
-                        } else {
-                            let (typed, new) = app.suggestion_full_preview(suggestion);
-                            spans.push(Span::styled(typed, Style::default().fg(Color::DarkGray)));
-                            spans.push(Span::styled(new, Style::default().fg(Color::Cyan)));
-                        }
+    #[tokio::test]
+    async fn test_capture_sets_session_var() {
+        let (mut app, _db) = test_app();
+        let (tx, mut rx) = tokio::sync::mpsc::channel(64);
+        let mut runner = TaskRunner::new(tx, 4);
+
+        async fn run(
+            app: &mut App,
+            runner: &mut TaskRunner,
+            rx: &mut tokio::sync::mpsc::Receiver<OutputMessage>,
+            input: &str,
+        ) {
+            app.input = input.to_string();
+            app.submit_command(runner);
+            while let Some(msg) = tokio::time::timeout(Duration::from_secs(10), rx.recv()).await.unwrap() {
+                let done = msg.stats.is_some();
+                app.push_output(msg);
+                if done {
+                    break;
+                }
+            }
+        }
 
-                        ListItem::new(Line::from(spans))
-                    })
-                    .collect();
+        run(&mut app, &mut runner, &mut rx, "GREETING=$(echo '  hello  ')").await;
+        assert_eq!(app.session_vars["GREETING"], "hello");
+        assert_eq!(app.output.back().unwrap().content, "GREETING = hello");
+        assert!(app.output.iter().any(|l| l.runner_label == "\x00box" && l.content.contains("hello")));
+
+        run(&mut app, &mut runner, &mut rx, "LINES=$(printf 'a\\nb\\n${GREETING}\\n')").await;
+        assert_eq!(app.session_vars["LINES"], "a\nb\nhello");
+        assert_eq!(app.output.back().unwrap().content, "LINES = a … (3 lines)");
+
+        // A failed command leaves the variable as it was
+        run(&mut app, &mut runner, &mut rx, "GREETING=$(echo bye; exit 3)").await;
+        assert_eq!(app.session_vars["GREETING"], "hello");
+        assert_eq!(app.output.back().unwrap().content, "GREETING not set: exited with code 3");
+
+        // Nor does output too long to keep
+        app.max_lines_per_task = 2;
+        run(&mut app, &mut runner, &mut rx, "GREETING=$(printf 'a\\nb\\nc\\n')").await;
+        assert_eq!(app.session_vars["GREETING"], "hello");
+        assert_eq!(app.output.back().unwrap().content, "GREETING not set: more than 2 lines of output");
+        assert!(app.captures.is_empty());
+    }
 
-                let suggestions_list = List::new(items)
-                    .block(
-                        Block::default()
-                            .borders(Borders::ALL)
-                            .title(" Suggestions (Tab/↑↓: navigate, →: next word, Ctrl+Y: accept) ")
-                            .border_style(Style::default().fg(Color::Magenta)),
-                    )
-                    .style(Style::default().fg(Color::White));
+    #[test]
+    fn test_explain_runs_nothing() {
+        let (mut app, _db) = test_app();
+        let (tx, _rx) = tokio::sync::mpsc::channel(16);
+        let mut runner = TaskRunner::new(tx, 4);
+
+        app.input = "explain [n=1-3] echo {n}".to_string();
+        app.submit_command(&mut runner);
+        assert!(!runner.has_active_tasks());
+        assert!(app.searcher.search("explain", 5).is_empty());
+        assert_eq!(app.output.front().unwrap().runner_label, "\x00top:explain");
+        assert!(app.output.iter().any(|l| l.content == "1 group: 3 commands"));
+
+        app.output.clear();
+        app.input = "explain echo hi".to_string();
+        app.submit_command(&mut runner);
+        assert_eq!(app.output.len(), 1);
+        assert!(app.output[0].content.starts_with("explain: no parallel blocks"));
+    }
 
-                f.render_widget(suggestions_list, chunks[1]);
+    #[tokio::test]
+    async fn test_save_session() {
+        let (mut app, _db) = test_app();
+        let (tx, mut rx) = tokio::sync::mpsc::channel(64);
+        let mut runner = TaskRunner::new(tx, 4);
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("session.md");
+
+        app.input = "printf '\\033[31mred\\033[0m\\n'; exit 2".to_string();
+        app.submit_command(&mut runner);
+        while let Some(msg) = tokio::time::timeout(Duration::from_secs(10), rx.recv()).await.unwrap() {
+            let done = msg.stats.is_some();
+            app.push_output(msg);
+            if done {
+                break;
             }
+        }
+        // Clearing the pane doesn't clear the session
+        app.clear_output();
+
+        app.input = format!("save-session {}", path.display());
+        app.submit_command(&mut runner);
+        assert_eq!(app.output.back().unwrap().content, format!("Saved 1 command to {}", path.display()));
+        let saved = std::fs::read_to_string(&path).unwrap();
+        assert!(saved.starts_with("# mux session\n"), "{}", saved);
+        assert!(saved.contains("\n## `printf '\\033[31mred\\033[0m\\n'; exit 2`\n"), "{}", saved);
+        assert!(saved.contains(" · exited with code 2 · "), "{}", saved);
+        assert!(saved.contains("\n```text\nred\n```\n"), "{}", saved);
+        // Not a command of its own
+        assert!(app.searcher.search("save-session", 5).is_empty());
+
+        app.input = "save-session".to_string();
+        app.submit_command(&mut runner);
+        assert!(app.output.back().unwrap().content.starts_with("save-session: expected a file path"));
+        app.input = format!("save-session {}", dir.path().join("missing/session.md").display());
+        app.submit_command(&mut runner);
+        assert!(app.output.back().unwrap().content.starts_with("save-session: can't write"));
+    }
 
-            // Input section
-            let input_text = if let Some(preview) = app.get_suggestion_preview() {
-                let line = Line::from(vec![
-                    Span::styled(app.input().to_string(), Style::default().fg(Color::White)),
-                    Span::styled(
-                        preview,
-                        Style::default()
-                            .fg(Color::DarkGray)
-                            .add_modifier(Modifier::DIM),
-                    ),
-                ]);
-                Text::from(line)
-            } else {
-                Text::from(app.input())
-            };
+    #[tokio::test]
+    async fn test_logs_command() {
+        let (mut app, _db) = test_app();
+        let mut runner = TaskRunner::new(tokio::sync::mpsc::channel(16).0, 4);
+        let dir = tempfile::tempdir().unwrap();
+        let log_dir = dir.path().join("logs");
+        app.logging.dir = Some(log_dir.clone());
+
+        // Looking doesn't create the directory
+        app.input = "mux-logs".to_string();
+        app.submit_command(&mut runner);
+        assert!(app.output.back().unwrap().content.starts_with("mux-logs: nothing logged yet"));
+        assert!(!log_dir.exists());
+
+        let lines: String = (1..=60).map(|i| format!("I line {}\n", i)).collect();
+        std::fs::create_dir(&log_dir).unwrap();
+        std::fs::write(log_dir.join("mux.log"), lines).unwrap();
+        app.output.clear();
+        app.input = "mux-logs 3".to_string();
+        app.submit_command(&mut runner);
+        let shown: Vec<&str> = app.output.iter().map(|l| l.content.as_str()).collect();
+        assert_eq!(app.output[0].runner_label, "\x00top:mux.log");
+        assert_eq!(shown[1..4], ["I line 58", "I line 59", "I line 60"]);
+        assert!(!runner.has_active_tasks());
+
+        app.output.clear();
+        app.input = "mux-logs".to_string();
+        app.submit_command(&mut runner);
+        assert_eq!(app.output.len(), LOG_LINES + 2);
+
+        app.input = "mux-logs many".to_string();
+        app.submit_command(&mut runner);
+        assert!(app.output.back().unwrap().content.starts_with("mux-logs: expected a number"));
+
+        app.logging.enabled = false;
+        app.input = "mux-logs".to_string();
+        app.submit_command(&mut runner);
+        assert!(app.output.back().unwrap().content.contains("logging is off"));
+
+        // A program called `logs` is left to the shell
+        app.input = "logs --since 1h".to_string();
+        app.submit_command(&mut runner);
+        assert!(runner.has_active_tasks());
+    }
 
-            let (input_title, input_border_color) = if app.is_quit_hint_active() {
-                (" Press Ctrl+C again to quit ", Color::Yellow)
-            } else {
-                (" Input ", Color::Green)
-            };
+    #[tokio::test]
+    async fn test_live_sync_updates_suggestions() {
+        let (mut app, _db) = test_app();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.txt");
+        std::fs::write(&path, "").unwrap();
+        let history = crate::config::HistoryConfig {
+            plain_files: vec![path.clone()],
+            ..crate::config::HistoryConfig::default()
+        };
+        let sync = crate::config::SyncConfig {
+            shells: Some(Vec::new()),
+            ..crate::config::SyncConfig::default()
+        };
+        let task = app.spawn_live_sync(&history, &sync).unwrap();
+        app.apply_live_sync(task.await);
+
+        std::fs::write(&path, "kubectl get pods --namespace prod\n").unwrap();
+        let task = app.spawn_live_sync(&history, &sync).unwrap();
+        app.apply_live_sync(task.await);
+        // No history entry matches, so the suggestions come from the arg indexes
+        app.input = "kubectl get pods -o wide --namespace ".to_string();
+        app.update_suggestions();
+        assert!(app.suggestions().iter().any(|s| s.text == "prod"));
+    }
 
-            // Current time for the input border
-            let now = chrono::Local::now();
-            let time_str = now.format(" %H:%M:%S ").to_string();
+    #[tokio::test]
+    async fn test_last_run_is_handed_off() {
+        let (mut app, _db) = test_app();
+        let (tx, _rx) = tokio::sync::mpsc::channel(16);
+        let mut runner = TaskRunner::new(tx, 4);
+
+        app.input = "set NAME=mux".to_string();
+        app.submit_command(&mut runner);
+        assert_eq!(app.last_run, None);
+
+        app.input = "echo ${NAME}".to_string();
+        app.submit_command(&mut runner);
+        app.input = "explain [n=1-3] echo {n}".to_string();
+        app.submit_command(&mut runner);
+        runner.cancel_all().await;
+        // As typed, not expanded; internal commands don't count
+        app.input = "echo draft".to_string();
+        let exit = app.into_parts().1;
+        assert_eq!(exit.last_run.as_deref(), Some("echo ${NAME}"));
+        assert_eq!(exit.draft, "echo draft");
+    }
 
-            let input = Paragraph::new(input_text)
-                .block(
-                    Block::default()
-                        .borders(Borders::ALL)
-                        .title(input_title)
-                        .title_bottom(
-                            Line::from(time_str)
-                                .right_aligned()
-                                .style(Style::default().fg(Color::DarkGray))
-                        )
-                        .border_style(Style::default().fg(input_border_color)),
-                )
-                .style(Style::default().fg(Color::White));
-            f.render_widget(input, chunks[2]);
+    #[test]
+    fn test_yank_suggestion_keeps_list_open() {
+        let (mut app, _db) = test_app();
+        app.input = "cargo build --re".to_string();
+        app.suggestions = vec![
+            suggestion("--release", SuggestionType::Argument),
+            suggestion("--locked --release", SuggestionType::ArgumentCombo),
+        ];
+
+        app.yank_suggestion();
+        assert_eq!(app.input, "cargo build --release");
+        assert_eq!(app.cursor_position, app.input.len());
+        assert_eq!(app.suggestions.len(), 2);
+
+        // Browsing on and yanking again replaces the previously yanked arg
+        app.next_suggestion();
+        app.yank_suggestion();
+        assert_eq!(app.input, "cargo build --locked --release");
+    }
 
-            // Compute display width (not byte offset) for correct cursor placement
-            let input = app.input();
-            let pos = app.cursor_position().min(input.len());
-            let display_col = input.get(..pos)
-                .map(unicode_width::UnicodeWidthStr::width)
-                .unwrap_or(0) as u16;
-            f.set_cursor_position((
-                chunks[2].x + display_col + 1,
-                chunks[2].y + 1,
-            ));
-        })?;
+    #[tokio::test]
+    async fn test_accept_and_submit() {
+        let (mut app, _db) = test_app();
+        let (tx, mut rx) = tokio::sync::mpsc::channel(16);
+        let mut runner = TaskRunner::new(tx, 4);
+
+        // No suggestion: nothing happens
+        app.input = "ech".to_string();
+        app.suggestions.clear();
+        assert!(!app.accept_and_submit(&mut runner));
+        assert_eq!(app.input, "ech");
+
+        app.input = "echo hi --fl".to_string();
+        app.suggestions = vec![suggestion("--flag", SuggestionType::Argument)];
+        assert!(!app.accept_and_submit(&mut runner));
+        assert!(app.input.is_empty());
+        assert_eq!(app.searcher.most_recent_command().unwrap().command, "echo hi --flag");
+
+        let started = rx.recv().await.unwrap();
+        assert_eq!(started.content, "started");
+        runner.cancel_all().await;
+    }
 
-        tokio::select! {
-            Some(event_result) = event_stream.next() => {
-                match event_result {
-                    Ok(Event::Key(key)) => {
-                        should_quit = keymap::handle_key_event(&mut app, key, &mut runner);
-                    }
-                    Ok(Event::Resize(cols, rows)) => {
-                        runner.resize_all(cols, rows);
-                    }
-                    _ => {}
-                }
-            }
-            Some(msg) = output_rx.recv() => {
-                app.push_output(msg);
-                // Drain all remaining messages before re-rendering
-                while let Ok(msg) = output_rx.try_recv() {
-                    app.push_output(msg);
-                }
-            }
-            _ = tick.tick() => {
-                // Forces a re-render to update the clock
-            }
+    #[tokio::test]
+    async fn test_own_commands_not_recorded_when_disabled() {
+        let (mut app, _db) = test_app();
+        app.record_own_commands = false;
+        let (tx, _rx) = tokio::sync::mpsc::channel(16);
+        let mut runner = TaskRunner::new(tx, 4);
+
+        app.input = "echo hi --flag".to_string();
+        assert!(!app.submit_command(&mut runner));
+
+        assert_eq!(app.searcher.len(), 0);
+        app.input = "echo hi --fl".to_string();
+        app.update_suggestions();
+        assert!(app.suggestions.is_empty());
+        runner.cancel_all().await;
+    }
+
+    fn styled_line() -> Line<'static> {
+        Line::from(vec![
+            Span::styled("abc", Style::default().fg(Color::Red)),
+            Span::styled("defgh", Style::default().fg(Color::Green)),
+        ])
+    }
+
+    #[test]
+    fn test_format_bytes() {
+        assert_eq!(format_bytes(512.0), "512 B");
+        assert_eq!(format_bytes(1536.0), "1.5 KB");
+        assert_eq!(format_bytes(1.2 * 1024.0 * 1024.0), "1.2 MB");
+    }
+
+    #[test]
+    fn test_format_throughput() {
+        let stats = crate::runner::TaskStats {
+            lines: 10,
+            exit_code: Some(0),
+            bytes: 2 * 1024 * 1024,
+            elapsed: Duration::from_secs(4),
+            attempts: 1,
+        };
+        assert_eq!(format_throughput(&stats), "2.0 MB, 512.0 KB/s");
+    }
+
+    #[test]
+    fn test_idle_expired() {
+        let start = Instant::now();
+        let timeout = Some(Duration::from_secs(60));
+
+        assert!(!idle_expired(start, start + Duration::from_secs(59), timeout, false));
+        assert!(idle_expired(start, start + Duration::from_secs(60), timeout, false));
+        // Never while tasks are running
+        assert!(!idle_expired(start, start + Duration::from_secs(600), timeout, true));
+        // Disabled
+        assert!(!idle_expired(start, start + Duration::from_secs(600), None, false));
+    }
+
+    #[test]
+    fn test_split_line_inside_span() {
+        let (head, rest) = split_line_at_width(styled_line(), 5);
+        assert_eq!(line_width(&head), 5);
+        assert_eq!(head.spans.len(), 2);
+        assert_eq!(head.spans[1].content, "de");
+        assert_eq!(head.spans[1].style.fg, Some(Color::Green));
+
+        let rest = rest.unwrap();
+        assert_eq!(rest.spans[0].content, "fgh");
+        assert_eq!(rest.spans[0].style.fg, Some(Color::Green));
+    }
+
+    #[test]
+    fn test_split_line_on_span_boundary() {
+        let (head, rest) = split_line_at_width(styled_line(), 3);
+        assert_eq!(head.spans.len(), 1);
+        assert_eq!(head.spans[0].content, "abc");
+        assert_eq!(rest.unwrap().spans[0].content, "defgh");
+    }
+
+    #[test]
+    fn test_split_line_wide_char_not_split() {
+        let line = Line::from("a日本");
+        let (head, rest) = split_line_at_width(line, 2);
+        assert_eq!(line_width(&head), 1);
+        assert_eq!(rest.unwrap().spans[0].content, "日本");
+    }
+
+    #[test]
+    fn test_cap_line_length() {
+        assert_eq!(cap_line_length("short".to_string(), 10), "short");
+        assert_eq!(cap_line_length("x".repeat(20), 0), "x".repeat(20));
+        assert_eq!(cap_line_length("abcdefgh".to_string(), 4), "abcd … (truncated)");
+        // Never splits a multi-byte character
+        assert_eq!(cap_line_length("aé€".to_string(), 3), "aé … (truncated)");
+        // Styling is reset before the marker
+        assert_eq!(
+            cap_line_length("\x1b[31mred red red".to_string(), 8),
+            "\x1b[31mred\x1b[0m … (truncated)"
+        );
+    }
+
+    #[test]
+    fn test_collapse_repeats() {
+        let (mut app, _db) = test_app();
+        app.collapse_repeats = true;
+        let contents = |app: &App| {
+            app.pending_output[&1].iter().map(|l| (l.content.clone(), l.repeated)).collect::<Vec<_>>()
+        };
+        let run = |content: &str, repeated| (content.to_string(), repeated);
+        for line in ["retrying", "retrying", "retrying"] {
+            app.push_output(OutputMessage::output(1, "", line.to_string()));
         }
+        // Updated as the lines arrive
+        assert_eq!(contents(&app), [run("retrying", 2)]);
 
-        if should_quit {
-            runner.cancel_all();
-            break;
+        for line in ["done", "retrying", "retrying"] {
+            app.push_output(OutputMessage::output(1, "", line.to_string()));
         }
+        app.push_output(OutputMessage::output(2, "", "retrying".to_string()));
+        assert_eq!(contents(&app), [run("retrying", 2), run("done", 0), run("retrying", 1)]);
+        assert_eq!(app.pending_output[&2][0].repeated, 0);
+
+        // The count is drawn after the content, and fits in the width with it
+        let look = OutputLook {
+            chars: BoxChars::for_style(BoxStyle::Ascii),
+            border: Style::default(),
+            separator: Style::default(),
+            box_pad_h: 1,
+            long_lines: LongLineMode::Truncate,
+            wrap_indent: 0,
+            line_timestamps: false,
+            show_command: false,
+        };
+        let first = &app.pending_output[&1][0];
+        assert_eq!(render_line_rows(first, 20, &look)[0].to_string(), "retrying (×3)");
+        assert!(render_line_rows(first, 10, &look).iter().all(|row| line_width(row) <= 10));
+        assert_eq!(pager_line(first, &look.chars, false), "retrying (×3)");
+
+        // A retry notice in between starts a new run
+        app.push_output(OutputMessage::status(1, "", &format!("{} 1/3: exit 1", crate::runner::RETRY_STATUS)));
+        app.push_output(OutputMessage::output(1, "", "retrying".to_string()));
+        assert_eq!(contents(&app).last().unwrap(), &run("retrying", 0));
+
+        // Off: every line is kept
+        let (mut app, _db) = test_app();
+        for _ in 0..2 {
+            app.push_output(OutputMessage::output(1, "", "x".to_string()));
+        }
+        assert_eq!(app.pending_output[&1].len(), 2);
     }
 
-    disable_raw_mode()?;
-    execute!(
-        terminal.backend_mut(),
-        LeaveAlternateScreen
-    )?;
-    terminal.show_cursor()?;
+    #[tokio::test]
+    async fn test_select_and_rerun_past_commands() {
+        let (mut app, _db) = test_app();
+        let mut runner = TaskRunner::new(tokio::sync::mpsc::channel(16).0, 4);
+        app.output_height = 50;
+        let finish = |app: &mut App, id, label: &str, command: &str| {
+            app.running.insert(id, RunningTask { command: command.to_string(), records_outcome: true });
+            app.push_output(OutputMessage::output(id, label, "ok".to_string()));
+            app.push_output(OutputMessage::status(id, label, "completed"));
+        };
+        finish(&mut app, 1, "", "echo one");
+        app.add_warning("not a command".to_string());
+        finish(&mut app, 2, "", "echo two");
+        finish(&mut app, 3, "[n=1]", "ping db1");
+        assert_eq!(app.output[0].command.as_deref(), Some("echo one"));
+        assert_eq!(app.output.iter().filter(|l| l.command.is_some()).count(), 3);
+
+        // Stepping through the boxes, from the top of the view
+        app.toggle_output_focus();
+        app.jump_to_top();
+        let mut picked = Vec::new();
+        for _ in 0..4 {
+            app.select_command(true);
+            picked.push(app.selected_command().unwrap().to_string());
+        }
+        assert_eq!(picked, ["echo one", "echo two", "ping db1", "ping db1"]);
+        app.select_command(false);
+        assert_eq!(app.selected_command(), Some("echo two"));
+        assert_eq!(app.output.iter().filter(|l| l.selected).count(), 1);
+
+        // The selected header is drawn reversed
+        let look = OutputLook {
+            chars: BoxChars::for_style(BoxStyle::Ascii),
+            border: Style::default(),
+            separator: Style::default(),
+            box_pad_h: 1,
+            long_lines: LongLineMode::Truncate,
+            wrap_indent: 0,
+            line_timestamps: false,
+            show_command: false,
+        };
+        let index = app.selected_command_index().unwrap();
+        let rows = render_output_line(&app.output[index], 20, &look);
+        assert!(rows[0].spans.iter().all(|s| s.style.add_modifier.contains(Modifier::REVERSED)));
+
+        // Running it again leaves the input alone
+        app.input = "draft".to_string();
+        app.cursor_position = 2;
+        app.rerun_selected_command(&mut runner);
+        assert_eq!(app.last_run.as_deref(), Some("echo two"));
+        assert_eq!((app.input.as_str(), app.cursor_position), ("draft", 2));
+        assert_eq!(app.selected_command(), None);
+
+        // Back at the bottom, stepping up starts from the last command.
+        // Editing puts it in the input and goes back to it
+        app.select_command(false);
+        assert_eq!(app.selected_command(), Some("ping db1"));
+        app.edit_selected_command();
+        assert_eq!(app.input, "ping db1");
+        assert!(!app.is_output_focused());
+        runner.cancel_all().await;
+    }
+
+    #[test]
+    fn test_compact_single_commands() {
+        let (mut app, _db) = test_app();
+        app.compact = true;
+        app.box_pad_v = 1;
+        app.push_output(OutputMessage::status(1, "", "started"));
+        app.push_output(OutputMessage::output(1, "", "hello".to_string()));
+        app.push_output(OutputMessage::status(1, "", "completed"));
+        let labels: Vec<&str> = app.output.iter().map(|l| l.runner_label.as_str()).collect();
+        assert_eq!(labels, ["", ""]);
+        assert_eq!(app.output[0].content, "hello");
+        assert_eq!(app.output[1].stream, crate::runner::StreamType::Status);
+
+        // Parallel tasks keep their boxes
+        app.push_output(OutputMessage::output(2, "[n=1]", "hi".to_string()));
+        app.push_output(OutputMessage::status(2, "[n=1]", "completed"));
+        assert_eq!(app.output[2].runner_label, "\x00top:[n=1]");
+        assert_eq!(app.output.back().unwrap().runner_label, "\x00bot");
+
+        // The separator fills the width whatever the runtime text
+        let look = OutputLook {
+            chars: BoxChars::for_style(BoxStyle::Ascii),
+            border: Style::default(),
+            separator: Style::default(),
+            box_pad_h: 1,
+            long_lines: LongLineMode::Truncate,
+            wrap_indent: 0,
+            line_timestamps: false,
+            show_command: false,
+        };
+        let rule = OutputLine {
+            runner_label: String::new(),
+            stream: crate::runner::StreamType::Status,
+            content: "make · 1.2s".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(render_output_line(&rule, 30, &look)[0].width(), 30);
+    }
+
+    #[test]
+    fn test_long_output_line_is_capped() {
+        let (mut app, _db) = test_app();
+        app.max_line_length = 100;
+        app.push_output(OutputMessage::output(1, "", "{".repeat(100_000)));
+        let line = &app.pending_output[&1][0];
+        assert!(line.content.len() < 200);
+        assert!(line.content.ends_with(TRUNCATED_MARKER));
+    }
+
+    #[test]
+    fn test_fit_line_truncate() {
+        let rows = fit_line(styled_line(), 4, LongLineMode::Truncate, 0);
+        assert_eq!(rows.len(), 1);
+        assert_eq!(line_width(&rows[0]), 4);
+    }
+
+    #[test]
+    fn test_fit_line_wrap() {
+        let rows = fit_line(styled_line(), 3, LongLineMode::Wrap, 0);
+        assert_eq!(rows.len(), 3);
+        assert!(rows.iter().all(|r| line_width(r) <= 3));
+        assert_eq!(rows[2].spans[0].content, "gh");
+    }
+
+    #[test]
+    fn test_fit_line_wrap_indent() {
+        let text = |rows: Vec<Line>| rows.iter().map(|l| l.to_string()).collect::<Vec<_>>();
+        assert_eq!(text(fit_line(styled_line(), 4, LongLineMode::Wrap, 2)), ["abcd", "  ef", "  gh"]);
+        // At least one column of content is left
+        assert_eq!(text(fit_line(styled_line(), 3, LongLineMode::Wrap, 8)).len(), 6);
+        assert_eq!(text(fit_line(styled_line(), 4, LongLineMode::Truncate, 2)), ["abcd"]);
+
+        // Inside the borders of a box
+        let look = OutputLook {
+            chars: BoxChars::for_style(BoxStyle::Ascii),
+            border: Style::default(),
+            separator: Style::default(),
+            box_pad_h: 1,
+            long_lines: LongLineMode::Wrap,
+            wrap_indent: 2,
+            line_timestamps: false,
+            show_command: false,
+        };
+        let line = OutputLine {
+            runner_label: "\x00box".to_string(),
+            stream: crate::runner::StreamType::Output,
+            content: "abcdefgh".to_string(),
+            ..Default::default()
+        };
+        let rows: Vec<String> = render_output_line(&line, 9, &look).iter().map(|l| l.to_string()).collect();
+        assert_eq!(rows, ["| abcde |", "|   fgh |"]);
+    }
 
-    Ok(app.into_searcher())
+    #[test]
+    fn test_fit_line_short_line_untouched() {
+        let rows = fit_line(styled_line(), 80, LongLineMode::Truncate, 0);
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].spans.len(), 2);
+    }
 }
@@ -0,0 +1,229 @@
+//! Session variables.
+//!
+//! Syntax:
+//!   set NAME=value     → define (or overwrite) a session variable
+//!   unset NAME         → remove it
+//!   NAME=$(command)    → run the command and set NAME to its output, trimmed;
+//!                        NAME is left alone if the command fails
+//!
+//! Unlike the shell's `$(...)`, a capture gets stderr as well as stdout:
+//! commands run in a terminal, where the two are one stream.
+//!
+//! Before a command runs, `${NAME}` references to session variables are
+//! replaced with their values. Unknown references are left untouched so the
+//! shell can still expand its own environment.
+
+use std::collections::HashMap;
+
+/// An internal variable command parsed from the input
+#[derive(Debug, PartialEq)]
+pub enum VarCommand<'a> {
+    Set { name: &'a str, value: &'a str },
+    Unset { name: &'a str },
+    Capture { name: &'a str, command: &'a str },
+}
+
+/// Variable names follow shell rules: a letter or underscore, then letters,
+/// digits or underscores.
+fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Parse `set NAME=value` / `unset NAME` / `NAME=$(command)`.
+/// Returns None if the input is not a (well-formed) variable command.
+pub fn parse_var_command(input: &str) -> Option<VarCommand<'_>> {
+    let trimmed = input.trim();
+
+    if let Some(rest) = trimmed.strip_prefix("set ") {
+        let (name, value) = rest.trim_start().split_once('=')?;
+        if !is_valid_name(name) {
+            return None;
+        }
+        return Some(VarCommand::Set { name, value });
+    }
+
+    if let Some(rest) = trimmed.strip_prefix("unset ") {
+        let name = rest.trim();
+        if !is_valid_name(name) {
+            return None;
+        }
+        return Some(VarCommand::Unset { name });
+    }
+
+    if let Some((name, value)) = trimmed.split_once('=')
+        && is_valid_name(name)
+        && let Some(command) = substitution(value)
+    {
+        return Some(VarCommand::Capture { name, command });
+    }
+
+    None
+}
+
+/// The command inside `$(...)` when that's all of `value`:
+/// `$(a) $(b)` is two substitutions, not one. Parentheses that are quoted
+/// or escaped don't count.
+fn substitution(value: &str) -> Option<&str> {
+    let inner = value.strip_prefix("$(")?.strip_suffix(')')?;
+    let mut depth = 0usize;
+    let mut quote = None;
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some('\''), _) => {}
+            (_, '\\') => {
+                // A trailing backslash escapes the closing parenthesis
+                chars.next()?;
+            }
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(c),
+            (None, '(') => depth += 1,
+            (None, ')') => depth = depth.checked_sub(1)?,
+            _ => {}
+        }
+    }
+    let command = inner.trim();
+    (depth == 0 && quote.is_none() && !command.is_empty()).then_some(command)
+}
+
+/// Replace `${NAME}` with session variable values.
+/// References to unknown variables are kept verbatim.
+pub fn expand_vars(input: &str, vars: &HashMap<String, String>) -> String {
+    if vars.is_empty() || !input.contains("${") {
+        return input.to_string();
+    }
+
+    let mut out = String::with_capacity(input.len());
+    let mut rest = input;
+
+    while let Some(start) = rest.find("${") {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+
+        match after.find('}') {
+            Some(end) => {
+                let name = &after[..end];
+                match vars.get(name) {
+                    Some(value) => out.push_str(value),
+                    None => out.push_str(&rest[start..start + 2 + end + 1]),
+                }
+                rest = &after[end + 1..];
+            }
+            None => {
+                // Unterminated reference: keep the remainder as-is
+                out.push_str(&rest[start..]);
+                rest = "";
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_set() {
+        assert_eq!(
+            parse_var_command("set ENV=prod"),
+            Some(VarCommand::Set { name: "ENV", value: "prod" })
+        );
+        assert_eq!(
+            parse_var_command("set MSG=hello world"),
+            Some(VarCommand::Set { name: "MSG", value: "hello world" })
+        );
+        assert_eq!(
+            parse_var_command("set EMPTY="),
+            Some(VarCommand::Set { name: "EMPTY", value: "" })
+        );
+    }
+
+    #[test]
+    fn test_parse_unset() {
+        assert_eq!(
+            parse_var_command("unset ENV"),
+            Some(VarCommand::Unset { name: "ENV" })
+        );
+    }
+
+    #[test]
+    fn test_parse_capture() {
+        assert_eq!(
+            parse_var_command("BRANCH=$(git branch --show-current)"),
+            Some(VarCommand::Capture { name: "BRANCH", command: "git branch --show-current" })
+        );
+        assert_eq!(
+            parse_var_command("N=$( ls | wc -l )"),
+            Some(VarCommand::Capture { name: "N", command: "ls | wc -l" })
+        );
+        assert_eq!(
+            parse_var_command("D=$(dirname $(which cargo))"),
+            Some(VarCommand::Capture { name: "D", command: "dirname $(which cargo)" })
+        );
+        assert_eq!(
+            parse_var_command("S=$(echo ')' \"(\" \\))"),
+            Some(VarCommand::Capture { name: "S", command: "echo ')' \"(\" \\)" })
+        );
+        assert!(parse_var_command("A=$(echo a) && echo $(echo b)").is_none());
+        // Quoted, that's one substitution
+        assert_eq!(
+            parse_var_command("A=$(echo 'a) && echo $(echo b')"),
+            Some(VarCommand::Capture { name: "A", command: "echo 'a) && echo $(echo b'" })
+        );
+        assert!(parse_var_command("A=$(echo \"unclosed)").is_none());
+        assert!(parse_var_command("A=$(echo \\)").is_none());
+        assert!(parse_var_command("A=$(echo a) done").is_none());
+        assert!(parse_var_command("A=$()").is_none());
+        assert!(parse_var_command("A=value").is_none());
+        assert!(parse_var_command("1A=$(date)").is_none());
+    }
+
+    #[test]
+    fn test_parse_not_var_command() {
+        assert!(parse_var_command("echo set A=1").is_none());
+        assert!(parse_var_command("set -e").is_none());
+        assert!(parse_var_command("set 1A=x").is_none());
+        assert!(parse_var_command("unset A B").is_none());
+        assert!(parse_var_command("settings").is_none());
+    }
+
+    #[test]
+    fn test_expand_known() {
+        let v = vars(&[("ENV", "prod"), ("REGION", "east")]);
+        assert_eq!(expand_vars("deploy ${ENV} ${REGION}", &v), "deploy prod east");
+    }
+
+    #[test]
+    fn test_expand_unknown_kept() {
+        let v = vars(&[("ENV", "prod")]);
+        assert_eq!(expand_vars("echo ${HOME} ${ENV}", &v), "echo ${HOME} prod");
+        assert_eq!(expand_vars("echo $ENV", &v), "echo $ENV");
+    }
+
+    #[test]
+    fn test_expand_unterminated() {
+        let v = vars(&[("ENV", "prod")]);
+        assert_eq!(expand_vars("echo ${ENV", &v), "echo ${ENV");
+    }
+
+    #[test]
+    fn test_expand_leaves_parallel_placeholders() {
+        let v = vars(&[("ENV", "prod")]);
+        assert_eq!(
+            expand_vars("[n=1-3] deploy ${ENV} {n}", &v),
+            "[n=1-3] deploy prod {n}"
+        );
+    }
+}